@@ -1875,6 +1875,12 @@ impl hal::Device<Backend> for Device {
         info: image::SamplerInfo,
     ) -> Result<Sampler, device::AllocationError> {
         assert!(info.normalized);
+        if info.reduction != image::ReductionMode::WeightedAverage {
+            warn!(
+                "Sampler reduction {:?} is not supported, using a weighted average",
+                info.reduction
+            );
+        }
 
         let op = match info.comparison {
             Some(_) => d3d11::D3D11_FILTER_REDUCTION_TYPE_COMPARISON,
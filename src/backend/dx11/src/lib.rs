@@ -392,6 +392,8 @@ impl hal::Instance for Instance {
                 max_sampler_anisotropy: 16.,
                 optimal_buffer_copy_offset_alignment: 1, // TODO
                 optimal_buffer_copy_pitch_alignment: 1,  // TODO
+                min_buffer_copy_offset_alignment: 1,     // TODO
+                min_buffer_copy_pitch_alignment: 1,      // TODO
                 min_vertex_input_binding_stride_alignment: 1,
                 ..hal::Limits::default() //TODO
             };
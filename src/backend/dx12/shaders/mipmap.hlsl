@@ -0,0 +1,63 @@
+
+Texture2DArray<float4> Source2D : register(t0);
+RWTexture2DArray<float4> Dest2D : register(u0);
+Texture3D<float4> Source3D : register(t0);
+RWTexture3D<float4> Dest3D : register(u0);
+
+cbuffer Level : register(b0) {
+    uint4 src_extent;
+    uint4 dst_extent;
+};
+
+// Coverage of a source texel by the footprint `[start, end)` of a destination texel
+float coverage(uint texel, float start, float end) {
+    return max(0.0, min(texel + 1.0, end) - max(float(texel), start));
+}
+
+// Box filter the footprint of each destination texel, odd source extents
+// are covered by weighting the partially overlapped texels.
+[numthreads(8, 8, 1)]
+void cs_mip_2d(uint3 id: SV_DispatchThreadID) {
+    if (any(id.xy >= dst_extent.xy)) {
+        return;
+    }
+    float2 ratio = float2(src_extent.xy) / float2(dst_extent.xy);
+    float2 start = id.xy * ratio;
+    float2 end = (id.xy + 1) * ratio;
+
+    float4 color = 0.0;
+    float weight = 0.0;
+    for (uint y = uint(start.y); y < uint(ceil(end.y)); y++) {
+        for (uint x = uint(start.x); x < uint(ceil(end.x)); x++) {
+            float w = coverage(x, start.x, end.x) * coverage(y, start.y, end.y);
+            color += Source2D.Load(int4(x, y, id.z, 0)) * w;
+            weight += w;
+        }
+    }
+    Dest2D[id] = color / weight;
+}
+
+[numthreads(4, 4, 4)]
+void cs_mip_3d(uint3 id: SV_DispatchThreadID) {
+    if (any(id >= dst_extent.xyz)) {
+        return;
+    }
+    float3 ratio = float3(src_extent.xyz) / float3(dst_extent.xyz);
+    float3 start = id * ratio;
+    float3 end = (id + 1) * ratio;
+
+    float4 color = 0.0;
+    float weight = 0.0;
+    for (uint z = uint(start.z); z < uint(ceil(end.z)); z++) {
+        for (uint y = uint(start.y); y < uint(ceil(end.y)); y++) {
+            for (uint x = uint(start.x); x < uint(ceil(end.x)); x++) {
+                float w = coverage(x, start.x, end.x)
+                    * coverage(y, start.y, end.y)
+                    * coverage(z, start.z, end.z);
+                color += Source3D.Load(int4(x, y, z, 0)) * w;
+                weight += w;
+            }
+        }
+    }
+    Dest3D[id] = color / weight;
+}
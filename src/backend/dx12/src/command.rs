@@ -14,6 +14,7 @@ use hal::{
 
 use std::borrow::Borrow;
 use std::ops::Range;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::{cmp, iter, mem, ptr};
 
@@ -25,8 +26,10 @@ use winapi::Interface;
 use native::{self, descriptor};
 
 use device::{ViewInfo, IDENTITY_MAPPING};
+use command_list::{GraphicsCommandList8, GraphicsCommandList9};
+use render_pass::{self, GraphicsCommandList4, RenderPassSplit};
 use root_constants::RootConstant;
-use smallvec::SmallVec;
+use smallvec::{Array, SmallVec};
 use {
     conv,
     descriptors_cpu,
@@ -82,6 +85,59 @@ pub struct RenderPassCache {
     #[derivative(Debug = "ignore")]
     target_rect: d3d12::D3D12_RECT,
     attachment_clears: Vec<AttachmentClear>,
+    // Split of the pass across command buffers.
+    split: RenderPassSplit,
+    // Pass was started with `ID3D12GraphicsCommandList4::BeginRenderPass`.
+    native: bool,
+}
+
+impl RenderPassCache {
+    // Check if the render area covers the whole attachment.
+    fn covers(&self, view: &r::ImageView) -> bool {
+        let extent = view.kind.extent().at_level(view.mip_levels.0);
+        self.target_rect.left <= 0
+            && self.target_rect.top <= 0
+            && self.target_rect.right >= extent.width as i32
+            && self.target_rect.bottom >= extent.height as i32
+    }
+
+    // Source and destination views of the `index`-th resolve of a subpass.
+    fn resolve_views(
+        &self,
+        subpass: &r::SubpassDesc,
+        index: usize,
+    ) -> Option<(&r::ImageView, &r::ImageView)> {
+        let (dst_attachment, _) = subpass.resolve_attachments[index];
+        let (src_attachment, _) = subpass.color_attachments[index];
+
+        let resolve_src = &self.framebuffer.attachments[src_attachment];
+        let resolve_dst = &self.framebuffer.attachments[dst_attachment];
+
+        // Resolve attachments must have the same format as their color attachments.
+        // Images are typeless, the view format defines the resolve operation except for
+        // typed swapchain back buffers, see `ImageView::resolve_format`.
+        if resolve_src.dxgi_format != resolve_dst.dxgi_format {
+            error!(
+                "Unable to resolve attachment {} into {} with a different format: {:?} -> {:?}",
+                src_attachment, dst_attachment, resolve_src.dxgi_format, resolve_dst.dxgi_format
+            );
+            return None;
+        }
+
+        Some((resolve_src, resolve_dst))
+    }
+}
+
+// Query result to resolve into the readback buffer of a query pool.
+#[derive(Clone, Copy)]
+struct QueryResolve {
+    heap: native::QueryHeap,
+    readback: native::Resource,
+    availability: native::Resource,
+    ty: d3d12::D3D12_QUERY_TYPE,
+    id: query::Id,
+    result_offset: u64,
+    availability_offset: u64,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -93,7 +149,7 @@ enum OcclusionQuery {
 /// Strongly-typed root signature element
 ///
 /// Could be removed for an unsafer variant to occupy less memory
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 enum RootElement {
     /// Root constant in the signature
     Constant(u32),
@@ -108,6 +164,9 @@ enum RootElement {
 /// Virtual data storage for the current root signature memory.
 struct UserData {
     data: [RootElement; ROOT_SIGNATURE_SIZE],
+    // Generation of the descriptor set referenced by each table.
+    // `None` if the table needs to be rebound regardless of its content.
+    generations: [Option<usize>; ROOT_SIGNATURE_SIZE],
     dirty_mask: u64,
 }
 
@@ -115,6 +174,7 @@ impl std::fmt::Debug for UserData {
     fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         fmt.debug_struct("UserData")
             .field("data", &&self.data[..])
+            .field("generations", &&self.generations[..])
             .field("dirty_mask", &self.dirty_mask)
             .finish()
     }
@@ -124,6 +184,7 @@ impl UserData {
     fn new() -> Self {
         UserData {
             data: [RootElement::Undefined; ROOT_SIGNATURE_SIZE],
+            generations: [None; ROOT_SIGNATURE_SIZE],
             dirty_mask: 0,
         }
     }
@@ -139,30 +200,55 @@ impl UserData {
     }
 
     /// Update descriptor table. Changes are marked as dirty.
-    fn set_srv_cbv_uav_table(&mut self, offset: usize, table_start: u32) {
-        assert!(offset < ROOT_SIGNATURE_SIZE);
-        // A descriptor table occupies one DWORD
-        self.data[offset] = RootElement::TableSrvCbvUav(table_start);
-        self.dirty_mask |= 1u64 << offset;
+    fn set_srv_cbv_uav_table(&mut self, offset: usize, table_start: u32, generation: usize) {
+        self.set_table(offset, RootElement::TableSrvCbvUav(table_start), generation);
     }
 
     /// Update descriptor table. Changes are marked as dirty.
-    fn set_sampler_table(&mut self, offset: usize, table_start: u32) {
+    fn set_sampler_table(&mut self, offset: usize, table_start: u32, generation: usize) {
+        self.set_table(offset, RootElement::TableSampler(table_start), generation);
+    }
+
+    /// Update descriptor table of a set at the given generation.
+    ///
+    /// Rebinding the same table of a set, which hasn't been written to since, is skipped.
+    fn set_table(&mut self, offset: usize, table: RootElement, generation: usize) {
         assert!(offset < ROOT_SIGNATURE_SIZE);
+        if self.data[offset] == table && self.generations[offset] == Some(generation) {
+            return;
+        }
         // A descriptor table occupies one DWORD
-        self.data[offset] = RootElement::TableSampler(table_start);
+        self.data[offset] = table;
+        self.generations[offset] = Some(generation);
         self.dirty_mask |= 1u64 << offset;
     }
 
+    /// Force the next descriptor table updates to be marked as dirty.
+    fn invalidate_tables(&mut self) {
+        self.generations = [None; ROOT_SIGNATURE_SIZE];
+    }
+
     /// Clear dirty flag.
     fn clear_dirty(&mut self, i: usize) {
-        self.dirty_mask &= !(1 << i);
+        self.dirty_mask &= !(1u64 << i);
     }
 
     /// Mark all entries as dirty.
     fn dirty_all(&mut self) {
         self.dirty_mask = !0;
     }
+
+    /// Contiguous runs of dirty entries inside the given range.
+    fn dirty_runs(&self, range: Range<usize>) -> SmallVec<[Range<usize>; 4]> {
+        let mut runs = SmallVec::<[Range<usize>; 4]>::new();
+        for i in range.filter(|&i| (self.dirty_mask >> i) & 1 == 1) {
+            match runs.last_mut() {
+                Some(ref mut run) if run.end == i => run.end += 1,
+                _ => runs.push(i .. i + 1),
+            }
+        }
+        runs
+    }
 }
 
 #[derive(Debug)]
@@ -194,12 +280,25 @@ impl PipelineCache {
         }
     }
 
+    // Check if binding a pipeline with the given root signature requires
+    // setting the root signature. Pipelines created from the same layout share
+    // their root signature and can skip it.
+    fn needs_root_signature(&self, signature: native::RootSignature) -> bool {
+        match self.pipeline {
+            Some((_, current)) => current != signature,
+            None => true,
+        }
+    }
+
+    // Returns the descriptor heaps of the sets. Tables are only marked as dirty if they
+    // changed, if the set has been written to since or if the heaps differ from `current_heaps`.
     fn bind_descriptor_sets<'a, I, J>(
         &mut self,
         layout: &r::PipelineLayout,
         first_set: usize,
         sets: I,
         offsets: J,
+        current_heaps: [native::DescriptorHeap; 2],
     ) -> [native::DescriptorHeap; 2]
     where
         I: IntoIterator,
@@ -222,6 +321,10 @@ impl PipelineCache {
                 return [native::DescriptorHeap::null(); 2];
             };
 
+        if [heap_srv_cbv_uav, heap_sampler] != current_heaps {
+            // Tables are relative to the heap start and need to be rebound on heap changes.
+            self.user_data.invalidate_tables();
+        }
         self.srv_cbv_uav_start = srv_cbv_uav_start;
         self.sampler_start = sampler_start;
 
@@ -242,6 +345,7 @@ impl PipelineCache {
 
         for (set, table) in sets.zip(layout.tables[first_set ..].iter()) {
             let set = set.borrow();
+            let generation = set.generation.load(Ordering::Acquire);
             set.first_gpu_view.map(|gpu| {
                 assert!(table.contains(r::SRV_CBV_UAV));
 
@@ -250,19 +354,20 @@ impl PipelineCache {
                 // create heaps with more descriptors.
                 let table_offset = (gpu.ptr - srv_cbv_uav_start) as u32;
                 self.user_data
-                    .set_srv_cbv_uav_table(root_offset as _, table_offset);
+                    .set_srv_cbv_uav_table(root_offset as _, table_offset, generation);
 
                 table_id += 1;
             });
-            set.first_gpu_sampler.map(|gpu| {
+            set.sampler_table.as_ref().map(|sampler_table| {
                 assert!(table.contains(r::SAMPLERS));
+                let gpu = sampler_table.gpu_start();
 
                 let root_offset = table_id + table_base_offset;
                 // Cast is safe as offset **must** be in u32 range. Unable to
                 // create heaps with more descriptors.
                 let table_offset = (gpu.ptr - sampler_start) as u32;
                 self.user_data
-                    .set_sampler_table(root_offset as _, table_offset);
+                    .set_sampler_table(root_offset as _, table_offset, generation);
 
                 table_id += 1;
             });
@@ -286,6 +391,7 @@ struct Copy {
     footprint_offset: u64,
     footprint: image::Extent,
     row_pitch: u32,
+    format: dxgiformat::DXGI_FORMAT,
     img_subresource: u32,
     img_offset: image::Offset,
     buf_offset: image::Offset,
@@ -296,6 +402,19 @@ struct Copy {
 #[derivative(Debug)]
 pub struct CommandBuffer {
     raw: native::GraphicsCommandList,
+    // `ID3D12GraphicsCommandList1` interface of the raw list, queried on first use.
+    #[derivative(Debug = "ignore")]
+    raw1: Option<native::WeakPtr<d3d12::ID3D12GraphicsCommandList1>>,
+    // `ID3D12GraphicsCommandList4` interface of the raw list, if supported by the runtime.
+    #[derivative(Debug = "ignore")]
+    raw4: Option<native::WeakPtr<GraphicsCommandList4>>,
+    // `ID3D12GraphicsCommandList8` interface of the raw list, queried on first use.
+    // `Some(None)` if the runtime doesn't support it.
+    #[derivative(Debug = "ignore")]
+    raw8: Option<Option<native::WeakPtr<GraphicsCommandList8>>>,
+    // `ID3D12GraphicsCommandList9` interface of the raw list, queried on first use.
+    #[derivative(Debug = "ignore")]
+    raw9: Option<native::WeakPtr<GraphicsCommandList9>>,
     allocator: native::CommandAllocator,
     shared: Arc<Shared>,
 
@@ -309,6 +428,14 @@ pub struct CommandBuffer {
     // Primitive topology of the currently bound graphics pipeline.
     // Caching required for internal graphics pipelines.
     primitive_topology: d3d12::D3D12_PRIMITIVE_TOPOLOGY,
+    // Stencil reference values of the front and back faces.
+    stencil_reference: [pso::StencilValue; 2],
+    // Blend constants set on the command list, persisting across pipeline changes.
+    blend_constants: Option<pso::ColorValue>,
+    // Whether the bound graphics pipeline blends with the blend constants.
+    uses_blend_constants: bool,
+    // Whether the bound graphics pipeline has a dynamic depth bias.
+    dynamic_depth_bias: bool,
     // Cache current compute root signature and pipeline.
     comp_pipeline: PipelineCache,
     // D3D12 only has one slot for both bindpoints. Need to rebind everything if we want to switch
@@ -356,6 +483,29 @@ pub struct CommandBuffer {
     temporary_gpu_heaps: Vec<native::DescriptorHeap>,
     // Resources that need to be alive till the end of the GPU execution.
     retained_resources: Vec<native::Resource>,
+    // Event operations, resolved by the queue on submission.
+    // Signals are issued after executing the command list, waits before, so events
+    // only order work at command buffer granularity: commands recorded before a wait
+    // are also held back, and commands recorded after a set complete before the signal.
+    pub(crate) event_signals: Vec<(native::Fence, u64)>,
+    pub(crate) event_waits: Vec<native::Fence>,
+    // Last known states of buffers transitioned by pipeline barriers in this command buffer.
+    // Used to transition indirect argument buffers for `ExecuteIndirect` if required.
+    buffer_states: FastHashMap<*mut d3d12::ID3D12Resource, d3d12::D3D12_RESOURCE_STATES>,
+    // Resources activated in aliased memory regions by this command buffer.
+    // Used as the `before` resource of subsequent aliasing barriers.
+    active_aliases: Vec<ActiveAlias>,
+    // Resources with a pending split barrier, begun but not ended yet.
+    split_barriers: Vec<*mut d3d12::ID3D12Resource>,
+    // Query resolves deferred until the end of the active native render pass.
+    #[derivative(Debug = "ignore")]
+    deferred_query_resolves: Vec<QueryResolve>,
+    // Queries resolved by this command buffer, signalled as completed on submission.
+    resolved_queries: Vec<(Arc<r::QueryCompletion>, query::Id)>,
+    // Number of currently open debug marker regions.
+    debug_marker_depth: usize,
+    // Secondary command buffers are recorded as bundles.
+    level: com::RawLevel,
     // Parenting command pool create flags.
     //
     // Required for reset behavior.
@@ -379,15 +529,33 @@ impl CommandBuffer {
         allocator: native::CommandAllocator,
         shared: Arc<Shared>,
         pool_create_flags: pool::CommandPoolCreateFlags,
+        level: com::RawLevel,
     ) -> Self {
+        let raw4 = unsafe {
+            let (raw4, hr) = raw.cast::<GraphicsCommandList4>();
+            if winerror::SUCCEEDED(hr) {
+                Some(raw4)
+            } else {
+                None
+            }
+        };
+
         CommandBuffer {
             raw,
+            raw1: None,
+            raw4,
+            raw8: None,
+            raw9: None,
             allocator,
             shared,
             pass_cache: None,
             cur_subpass: !0,
             gr_pipeline: PipelineCache::new(),
             primitive_topology: d3dcommon::D3D_PRIMITIVE_TOPOLOGY_UNDEFINED,
+            stencil_reference: [0; 2],
+            blend_constants: None,
+            uses_blend_constants: false,
+            dynamic_depth_bias: false,
             comp_pipeline: PipelineCache::new(),
             active_bindpoint: BindPoint::Graphics { internal: false },
             active_descriptor_heaps: [native::DescriptorHeap::null(); 2],
@@ -401,11 +569,48 @@ impl CommandBuffer {
             rtv_pools: Vec::new(),
             temporary_gpu_heaps: Vec::new(),
             retained_resources: Vec::new(),
+            event_signals: Vec::new(),
+            event_waits: Vec::new(),
+            buffer_states: FastHashMap::default(),
+            active_aliases: Vec::new(),
+            split_barriers: Vec::new(),
+            deferred_query_resolves: Vec::new(),
+            resolved_queries: Vec::new(),
+            debug_marker_depth: 0,
+            level,
             pool_create_flags,
         }
     }
 
+    // Allocator used exclusively by this command buffer.
+    //
+    // Bundles and command buffers of `RESET_INDIVIDUAL` pools own their allocator, all others
+    // share the allocator of the pool.
+    pub(crate) fn owned_allocator(&self) -> Option<native::CommandAllocator> {
+        if self.level == com::RawLevel::Secondary
+            || self
+                .pool_create_flags
+                .contains(pool::CommandPoolCreateFlags::RESET_INDIVIDUAL)
+        {
+            Some(self.allocator)
+        } else {
+            None
+        }
+    }
+
     pub(crate) unsafe fn destroy(&mut self) {
+        if let Some(raw1) = self.raw1.take() {
+            raw1.destroy();
+        }
+        if let Some(raw4) = self.raw4.take() {
+            raw4.destroy();
+        }
+        if let Some(Some(raw8)) = self.raw8.take() {
+            raw8.destroy();
+        }
+        if let Some(raw9) = self.raw9.take() {
+            raw9.destroy();
+        }
         self.raw.destroy();
         for heap in &self.rtv_pools {
             heap.destroy();
@@ -418,6 +623,302 @@ impl CommandBuffer {
         }
     }
 
+    // Retrieve the `ID3D12GraphicsCommandList1` interface of the command list.
+    //
+    // Only called for commands of features which require it, therefore the cast
+    // is expected to succeed.
+    unsafe fn raw1(&mut self) -> native::WeakPtr<d3d12::ID3D12GraphicsCommandList1> {
+        if let Some(raw1) = self.raw1 {
+            return raw1;
+        }
+        let (raw1, hr) = self.raw.cast::<d3d12::ID3D12GraphicsCommandList1>();
+        assert!(
+            winerror::SUCCEEDED(hr),
+            "ID3D12GraphicsCommandList1 is not supported by the runtime (hr = {:x})",
+            hr
+        );
+        self.raw1 = Some(raw1);
+        raw1
+    }
+
+    // Retrieve the `ID3D12GraphicsCommandList8` interface of the command list,
+    // if supported by the runtime.
+    unsafe fn raw8(&mut self) -> Option<native::WeakPtr<GraphicsCommandList8>> {
+        if let Some(raw8) = self.raw8 {
+            return raw8;
+        }
+        let (raw8, hr) = self.raw.cast::<GraphicsCommandList8>();
+        let raw8 = if winerror::SUCCEEDED(hr) {
+            Some(raw8)
+        } else {
+            None
+        };
+        self.raw8 = Some(raw8);
+        raw8
+    }
+
+    // Retrieve the `ID3D12GraphicsCommandList9` interface of the command list.
+    //
+    // Only called for pipelines with dynamic depth bias, which requires support
+    // for the interface.
+    unsafe fn raw9(&mut self) -> native::WeakPtr<GraphicsCommandList9> {
+        if let Some(raw9) = self.raw9 {
+            return raw9;
+        }
+        let (raw9, hr) = self.raw.cast::<GraphicsCommandList9>();
+        assert!(
+            winerror::SUCCEEDED(hr),
+            "ID3D12GraphicsCommandList9 is not supported by the runtime (hr = {:x})",
+            hr
+        );
+        self.raw9 = Some(raw9);
+        raw9
+    }
+
+    /// Begin a render pass which is split across multiple command buffers.
+    ///
+    /// A pass begun with `RenderPassSplit::SUSPENDING` is continued by beginning the same
+    /// pass with `RenderPassSplit::RESUMING` in the next command buffer of the submission.
+    /// Clears and initial transitions are recorded by the first part of the pass, resolves and
+    /// final transitions by the last part. Clear values are ignored when resuming a pass.
+    ///
+    /// Uses the render pass API of `ID3D12GraphicsCommandList4` if supported by the runtime.
+    /// Split render passes must consist of a single subpass.
+    pub unsafe fn begin_render_pass_split<T>(
+        &mut self,
+        render_pass: &r::RenderPass,
+        framebuffer: &r::Framebuffer,
+        target_rect: pso::Rect,
+        clear_values: T,
+        split: RenderPassSplit,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::ClearValueRaw>,
+    {
+        if self.is_restricted_in_bundle("begin_render_pass_split") {
+            return;
+        }
+        if render_pass.subpasses.len() != 1 {
+            error!(
+                "Unable to split a render pass with {} subpasses",
+                render_pass.subpasses.len()
+            );
+            return;
+        }
+        self.begin_render_pass_impl(render_pass, framebuffer, target_rect, clear_values, split);
+    }
+
+    unsafe fn begin_render_pass_impl<T>(
+        &mut self,
+        render_pass: &r::RenderPass,
+        framebuffer: &r::Framebuffer,
+        target_rect: pso::Rect,
+        clear_values: T,
+        split: RenderPassSplit,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::ClearValueRaw>,
+    {
+        assert_eq!(framebuffer.attachments.len(), render_pass.attachments.len());
+        // Make sure that no subpass works with Present as intermediate layout.
+        // This wouldn't make much sense, and proceeding with this constraint
+        // allows the state transitions generated from subpass dependencies
+        // to ignore the layouts completely.
+        assert!(!render_pass.subpasses.iter().any(|sp| sp
+            .color_attachments
+            .iter()
+            .chain(sp.depth_stencil_attachment.iter())
+            .chain(sp.input_attachments.iter())
+            .any(|aref| aref.1 == image::Layout::Present)));
+
+        let resuming = split.contains(RenderPassSplit::RESUMING);
+        let native = self.raw4.is_some();
+
+        let mut clear_iter = clear_values.into_iter();
+        let attachment_clears = render_pass
+            .attachments
+            .iter()
+            .enumerate()
+            .map(|(i, attachment)| {
+                // Clears are recorded by the first part of split passes.
+                let cv = if attachment.has_clears() && !resuming {
+                    Some(*clear_iter.next().unwrap().borrow())
+                } else {
+                    None
+                };
+
+                AttachmentClear {
+                    subpass_id: render_pass.subpasses.iter().position(|sp| sp.is_using(i)),
+                    value: if attachment.ops.load == pass::AttachmentLoadOp::Clear && !resuming {
+                        assert!(cv.is_some());
+                        cv
+                    } else {
+                        None
+                    },
+                    stencil_value: if attachment.stencil_ops.load == pass::AttachmentLoadOp::Clear
+                        && !resuming
+                    {
+                        Some(cv.unwrap().depth_stencil.stencil)
+                    } else {
+                        None
+                    },
+                }
+            })
+            .collect();
+
+        self.pass_cache = Some(RenderPassCache {
+            render_pass: render_pass.clone(),
+            framebuffer: framebuffer.clone(),
+            target_rect: get_rect(&target_rect),
+            attachment_clears,
+            split,
+            native,
+        });
+        self.cur_subpass = 0;
+        if !resuming {
+            self.insert_subpass_barriers(BarrierPoint::Pre);
+        }
+        if native {
+            self.begin_native_render_pass();
+            self.clear_subpass_attachments();
+        } else {
+            self.bind_targets();
+        }
+    }
+
+    /// Begin a debug marker region, shown in tools like PIX.
+    ///
+    /// `color` is encoded as `0xAARRGGBB`.
+    pub unsafe fn begin_debug_marker(&mut self, name: &str, color: u32) {
+        let data = encode_pix_event(PIX_EVENT_BEGIN_EVENT, name, color);
+        self.raw.BeginEvent(
+            PIX_EVENT_PIX3BLOB_VERSION,
+            data.as_ptr() as *const _,
+            (data.len() * mem::size_of::<u64>()) as _,
+        );
+        self.debug_marker_depth += 1;
+    }
+
+    /// End the last debug marker region begun in this command buffer.
+    pub unsafe fn end_debug_marker(&mut self) {
+        // Regions can't be closed across command lists.
+        if self.debug_marker_depth == 0 {
+            error!("No debug marker region begun in this command buffer");
+            return;
+        }
+        self.raw.EndEvent();
+        self.debug_marker_depth -= 1;
+    }
+
+    /// Insert a single debug marker, shown in tools like PIX.
+    pub unsafe fn insert_debug_marker(&mut self, name: &str, color: u32) {
+        let data = encode_pix_event(PIX_EVENT_SET_MARKER, name, color);
+        self.raw.SetMarker(
+            PIX_EVENT_PIX3BLOB_VERSION,
+            data.as_ptr() as *const _,
+            (data.len() * mem::size_of::<u64>()) as _,
+        );
+    }
+
+    /// Activate `after` in memory shared with `before`, which becomes invalid.
+    ///
+    /// Emits the aliasing barrier and transitions `after` between `states`.
+    /// If `discard` is set, the contents of `after` are discarded, which is required to
+    /// initialize aliased render targets and depth stencil images. In this case the end
+    /// state needs to be a color or depth stencil attachment write state.
+    /// `before` may be `None` if all resources sharing the memory need to be deactivated.
+    pub unsafe fn alias_resources(
+        &mut self,
+        before: Option<&r::Image>,
+        after: &r::Image,
+        states: Range<image::State>,
+        discard: bool,
+    ) {
+        if self.is_restricted_in_bundle("alias_resources") {
+            return;
+        }
+        let after = after.expect_bound();
+        if let Some(ref region) = after.region {
+            activate_alias(&mut self.active_aliases, region, after.resource.as_mut_ptr());
+        }
+        let mut raw_barriers = vec![Self::aliasing_barrier(
+            before.map_or(ptr::null_mut(), |image| image.expect_bound().resource.as_mut_ptr()),
+            after.resource.as_mut_ptr(),
+        )];
+
+        let state_src = conv::map_image_resource_state(states.start.0, states.start.1);
+        let state_dst = conv::map_image_resource_state(states.end.0, states.end.1);
+        if state_src != state_dst {
+            raw_barriers.push(Self::transition_barrier(
+                d3d12::D3D12_RESOURCE_TRANSITION_BARRIER {
+                    pResource: after.resource.as_mut_ptr(),
+                    Subresource: d3d12::D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+                    StateBefore: state_src,
+                    StateAfter: state_dst,
+                },
+            ));
+        }
+        self.raw
+            .ResourceBarrier(raw_barriers.len() as _, raw_barriers.as_ptr());
+
+        if discard {
+            if is_discard_state(state_dst) {
+                self.raw.DiscardResource(after.resource.as_mut_ptr(), ptr::null());
+            } else {
+                error!(
+                    "Unable to discard aliased image in state {:x}, requires an attachment write state",
+                    state_dst
+                );
+            }
+        }
+    }
+
+    /// Begin the transitions of `barriers` early, to overlap them with other work.
+    ///
+    /// The transitions are completed by `end_barrier` with the same barriers. Recorded commands
+    /// in between must not access the transitioned resources. Global memory barriers can't be
+    /// split and are recorded by `end_barrier`.
+    pub unsafe fn begin_barrier<'a, T>(&mut self, barriers: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<memory::Barrier<'a, Backend>>,
+    {
+        if self.is_restricted_in_bundle("begin_barrier") {
+            return;
+        }
+        let raw_barriers =
+            self.record_barriers(barriers, d3d12::D3D12_RESOURCE_BARRIER_FLAG_BEGIN_ONLY);
+        if !raw_barriers.is_empty() {
+            self.raw
+                .ResourceBarrier(raw_barriers.len() as _, raw_barriers.as_ptr());
+        }
+    }
+
+    /// Complete the transitions of `barriers` begun by `begin_barrier`.
+    pub unsafe fn end_barrier<'a, T>(&mut self, barriers: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<memory::Barrier<'a, Backend>>,
+    {
+        if self.is_restricted_in_bundle("end_barrier") {
+            return;
+        }
+        let mut raw_barriers =
+            self.record_barriers(barriers, d3d12::D3D12_RESOURCE_BARRIER_FLAG_END_ONLY);
+        raw_barriers.push(Self::uav_barrier(ptr::null_mut()));
+        self.raw
+            .ResourceBarrier(raw_barriers.len() as _, raw_barriers.as_ptr());
+    }
+
+    // Check if the command can't be recorded, as this command buffer is a bundle.
+    fn is_restricted_in_bundle(&self, command: &str) -> bool {
+        let restricted = !is_allowed_at_level(self.level);
+        if restricted {
+            error!("`{}` is not allowed in secondary command buffers", command);
+        }
+        restricted
+    }
+
     pub(crate) unsafe fn as_raw_list(&self) -> *mut d3d12::ID3D12CommandList {
         self.raw.as_mut_ptr() as *mut _
     }
@@ -429,6 +930,10 @@ impl CommandBuffer {
         self.cur_subpass = !0;
         self.gr_pipeline = PipelineCache::new();
         self.primitive_topology = d3dcommon::D3D_PRIMITIVE_TOPOLOGY_UNDEFINED;
+        self.stencil_reference = [0; 2];
+        self.blend_constants = None;
+        self.uses_blend_constants = false;
+        self.dynamic_depth_bias = false;
         self.comp_pipeline = PipelineCache::new();
         self.active_bindpoint = BindPoint::Graphics { internal: false };
         self.active_descriptor_heaps = [native::DescriptorHeap::null(); 2];
@@ -451,6 +956,14 @@ impl CommandBuffer {
                 resource.destroy();
             }
         }
+        self.event_signals.clear();
+        self.event_waits.clear();
+        self.debug_marker_depth = 0;
+        self.buffer_states.clear();
+        self.active_aliases.clear();
+        self.split_barriers.clear();
+        self.deferred_query_resolves.clear();
+        self.resolved_queries.clear();
     }
 
     // Indicates that the pipeline slot has been overriden with an internal pipeline.
@@ -475,7 +988,7 @@ impl CommandBuffer {
             None => &state.render_pass.post_barriers,
         };
 
-        let transition_barriers = proto_barriers
+        let mut raw_barriers = proto_barriers
             .iter()
             .map(|barrier| {
                 let mut resource_barrier = d3d12::D3D12_RESOURCE_BARRIER {
@@ -498,11 +1011,21 @@ impl CommandBuffer {
             })
             .collect::<Vec<_>>();
 
-        if !transition_barriers.is_empty() {
+        // Storage writes of previous subpasses need to be visible to the next subpass.
+        if let BarrierPoint::Pre = insertion {
+            match state.render_pass.subpasses.get(self.cur_subpass) {
+                Some(subpass) if subpass.uav_barrier => {
+                    raw_barriers.push(Self::uav_barrier(ptr::null_mut()));
+                }
+                _ => {}
+            }
+        }
+
+        if !raw_barriers.is_empty() {
             unsafe {
                 self.raw
                     .clone()
-                    .ResourceBarrier(transition_barriers.len() as _, transition_barriers.as_ptr());
+                    .ResourceBarrier(raw_barriers.len() as _, raw_barriers.as_ptr());
             }
         }
     }
@@ -518,6 +1041,13 @@ impl CommandBuffer {
             .map(|&(id, _)| state.framebuffer.attachments[id].handle_rtv.unwrap())
             .collect::<Vec<_>>();
         let ds_view = match subpass.depth_stencil_attachment {
+            // Read-only depth stencil attachments may be sampled in the same subpass,
+            // which requires a read-only view.
+            Some((id, image::Layout::DepthStencilReadOnlyOptimal)) => state.framebuffer.attachments
+                [id]
+                .handle_dsv_read_only
+                .as_ref()
+                .unwrap() as *const _,
             Some((id, _)) => state.framebuffer.attachments[id]
                 .handle_dsv
                 .as_ref()
@@ -534,7 +1064,163 @@ impl CommandBuffer {
             );
         }
 
-        // performs clears for all the attachments first used in this subpass
+        self.clear_subpass_attachments();
+    }
+
+    // Begin the current subpass with `ID3D12GraphicsCommandList4::BeginRenderPass`.
+    //
+    // Attachments are cleared by the beginning access if the render area covers them,
+    // other clears are recorded explicitly by `clear_subpass_attachments`.
+    unsafe fn begin_native_render_pass(&self) {
+        let state = self.pass_cache.as_ref().unwrap();
+        let subpass = &state.render_pass.subpasses[self.cur_subpass];
+
+        // Attachments read by later subpasses need to be preserved.
+        let is_preserved = |id: pass::AttachmentId| {
+            state.render_pass.subpasses[self.cur_subpass + 1 ..]
+                .iter()
+                .any(|sp| sp.is_using(id))
+        };
+
+        // Resolved layers, which need to stay alive until the pass has begun.
+        let resolves = (0 .. subpass.resolve_attachments.len())
+            .map(|i| {
+                state.resolve_views(subpass, i).map(|(src, dst)| {
+                    let rect = state.target_rect;
+                    let subresources = (0 .. state.framebuffer.layers)
+                        .map(|l| render_pass::ResolveSubresourceParameters {
+                            // Attachments only have a single mip level by specification.
+                            SrcSubresource: src
+                                .calc_subresource(src.mip_levels.0 as _, (src.layers.0 + l) as _),
+                            DstSubresource: dst
+                                .calc_subresource(dst.mip_levels.0 as _, (dst.layers.0 + l) as _),
+                            DstX: rect.left as _,
+                            DstY: rect.top as _,
+                            SrcRect: rect,
+                        })
+                        .collect::<Vec<_>>();
+                    (src, dst, subresources)
+                })
+            })
+            .collect::<SmallVec<[_; 8]>>();
+
+        let render_targets = subpass
+            .color_attachments
+            .iter()
+            .enumerate()
+            .map(|(i, &(id, _))| {
+                let attachment = &state.render_pass.attachments[id];
+                let view = &state.framebuffer.attachments[id];
+                let clear = &state.attachment_clears[id];
+                let clear_value = match clear.value {
+                    Some(cv) if state.covers(view) => {
+                        let mut value: d3d12::D3D12_CLEAR_VALUE = mem::zeroed();
+                        value.Format = view.dxgi_format;
+                        *value.u.Color_mut() = cv.color.float32;
+                        Some(value)
+                    }
+                    _ => None,
+                };
+
+                render_pass::RenderTargetDesc {
+                    cpuDescriptor: view.handle_rtv.unwrap(),
+                    BeginningAccess: render_pass::beginning_access(
+                        clear.subpass_id == Some(self.cur_subpass),
+                        attachment.ops.load,
+                        clear_value,
+                    ),
+                    EndingAccess: match resolves.get(i) {
+                        Some(&Some((src, dst, ref subresources))) => {
+                            let store = attachment.ops.store == pass::AttachmentStoreOp::Store;
+                            render_pass::resolve_access(
+                                src.resource.as_mut_ptr(),
+                                dst.resource.as_mut_ptr(),
+                                dst.resolve_format,
+                                subresources,
+                                is_preserved(id) || store,
+                            )
+                        }
+                        _ => render_pass::ending_access(is_preserved(id), attachment.ops.store),
+                    },
+                }
+            })
+            .collect::<SmallVec<[_; 8]>>();
+
+        let depth_stencil = subpass.depth_stencil_attachment.map(|(id, layout)| {
+            let attachment = &state.render_pass.attachments[id];
+            let view = &state.framebuffer.attachments[id];
+            let clear = &state.attachment_clears[id];
+            let first_use = clear.subpass_id == Some(self.cur_subpass);
+            let aspects = attachment
+                .format
+                .map_or(Aspects::empty(), |format| format.surface_desc().aspects);
+            let clear_value = |depth: f32, stencil: u32| {
+                let mut value: d3d12::D3D12_CLEAR_VALUE = mem::zeroed();
+                value.Format = view.dxgi_format;
+                *value.u.DepthStencil_mut() = d3d12::D3D12_DEPTH_STENCIL_VALUE {
+                    Depth: depth,
+                    Stencil: stencil as _,
+                };
+                value
+            };
+
+            let (depth_begin, depth_end) = if aspects.contains(Aspects::DEPTH) {
+                let value = match clear.value {
+                    Some(cv) if state.covers(view) => Some(clear_value(cv.depth_stencil.depth, 0)),
+                    _ => None,
+                };
+                (
+                    render_pass::beginning_access(first_use, attachment.ops.load, value),
+                    render_pass::ending_access(is_preserved(id), attachment.ops.store),
+                )
+            } else {
+                render_pass::no_access()
+            };
+            let (stencil_begin, stencil_end) = if aspects.contains(Aspects::STENCIL) {
+                let value = match clear.stencil_value {
+                    Some(stencil) if state.covers(view) => Some(clear_value(0.0, stencil)),
+                    _ => None,
+                };
+                (
+                    render_pass::beginning_access(first_use, attachment.stencil_ops.load, value),
+                    render_pass::ending_access(is_preserved(id), attachment.stencil_ops.store),
+                )
+            } else {
+                render_pass::no_access()
+            };
+
+            render_pass::DepthStencilDesc {
+                cpuDescriptor: if layout == image::Layout::DepthStencilReadOnlyOptimal {
+                    view.handle_dsv_read_only.unwrap()
+                } else {
+                    view.handle_dsv.unwrap()
+                },
+                DepthBeginningAccess: depth_begin,
+                StencilBeginningAccess: stencil_begin,
+                DepthEndingAccess: depth_end,
+                StencilEndingAccess: stencil_end,
+            }
+        });
+
+        self.raw4.unwrap().BeginRenderPass(
+            &render_targets,
+            depth_stencil.as_ref(),
+            state.split.flags(),
+        );
+    }
+
+    // End the current native render pass, recording the query resolves deferred
+    // while the pass was active.
+    unsafe fn end_native_render_pass(&mut self) {
+        self.raw4.unwrap().EndRenderPass();
+        for resolve in mem::replace(&mut self.deferred_query_resolves, Vec::new()) {
+            self.record_query_resolve(resolve);
+        }
+    }
+
+    // Perform clears for all the attachments first used in the current subpass.
+    fn clear_subpass_attachments(&self) {
+        let state = self.pass_cache.as_ref().unwrap();
         for (view, clear) in state
             .framebuffer
             .attachments
@@ -544,6 +1230,10 @@ impl CommandBuffer {
             if clear.subpass_id != Some(self.cur_subpass) {
                 continue;
             }
+            // Cleared by the beginning access of the native render pass.
+            if state.native && state.covers(view) {
+                continue;
+            }
 
             if let (Some(handle), Some(cv)) = (view.handle_rtv, clear.value) {
                 self.clear_render_target_view(handle, unsafe { cv.color }, &[state.target_rect]);
@@ -565,12 +1255,16 @@ impl CommandBuffer {
         let framebuffer = &state.framebuffer;
         let subpass = &state.render_pass.subpasses[self.cur_subpass];
 
-        for (i, resolve_attachment) in subpass.resolve_attachments.iter().enumerate() {
-            let (dst_attachment, _) = *resolve_attachment;
-            let (src_attachment, _) = subpass.color_attachments[i];
+        // Native render passes resolve on `EndRenderPass`.
+        if state.native {
+            return;
+        }
 
-            let resolve_src = state.framebuffer.attachments[src_attachment];
-            let resolve_dst = state.framebuffer.attachments[dst_attachment];
+        for i in 0 .. subpass.resolve_attachments.len() {
+            let (resolve_src, resolve_dst) = match state.resolve_views(subpass, i) {
+                Some(views) => views,
+                None => continue,
+            };
 
             // The number of layers of the render area are given on framebuffer creation.
             for l in 0 .. framebuffer.layers {
@@ -591,7 +1285,7 @@ impl CommandBuffer {
                         subresource_dst,
                         resolve_src.resource.as_mut_ptr(),
                         subresource_src,
-                        resolve_dst.dxgi_format,
+                        resolve_dst.resolve_format,
                     );
                 }
             }
@@ -666,17 +1360,29 @@ impl CommandBuffer {
         }
 
         self.active_bindpoint = BindPoint::Graphics { internal: false };
+        // Each viewport is clipped by the scissor of the same index, scissor tests
+        // are always enabled in D3D12.
+        debug_assert_eq!(
+            self.viewport_cache.len(),
+            self.scissor_cache.len(),
+            "Number of viewports and scissors don't match"
+        );
+        if self.uses_blend_constants && self.blend_constants.is_none() {
+            warn!("Blend constants are used without being set, defaulting to (1, 1, 1, 1)");
+            self.raw.set_blend_factor([1.0; 4]);
+            self.blend_constants = Some([1.0; 4]);
+        }
         let cmd_buffer = &mut self.raw;
 
         // Flush root signature data
         Self::flush_user_data(
             &mut self.gr_pipeline,
-            |slot, data| unsafe {
+            |slot, offset, data| unsafe {
                 cmd_buffer.clone().SetGraphicsRoot32BitConstants(
                     slot,
                     data.len() as _,
                     data.as_ptr() as *const _,
-                    0,
+                    offset,
                 )
             },
             |slot, gpu| cmd_buffer.set_graphics_root_descriptor_table(slot, gpu),
@@ -712,12 +1418,12 @@ impl CommandBuffer {
         let cmd_buffer = &mut self.raw;
         Self::flush_user_data(
             &mut self.comp_pipeline,
-            |slot, data| unsafe {
+            |slot, offset, data| unsafe {
                 cmd_buffer.clone().SetComputeRoot32BitConstants(
                     slot,
                     data.len() as _,
                     data.as_ptr() as *const _,
-                    0,
+                    offset,
                 )
             },
             |slot, gpu| cmd_buffer.set_compute_root_descriptor_table(slot, gpu),
@@ -729,7 +1435,7 @@ impl CommandBuffer {
         mut constants_update: F,
         mut table_update: G,
     ) where
-        F: FnMut(u32, &[u32]),
+        F: FnMut(u32, u32, &[u32]),
         G: FnMut(u32, d3d12::D3D12_GPU_DESCRIPTOR_HANDLE),
     {
         let user_data = &mut pipeline.user_data;
@@ -739,24 +1445,26 @@ impl CommandBuffer {
 
         let num_root_constant = pipeline.root_constants.len();
         let mut cur_index = 0;
-        // TODO: opt: Only set dirty root constants?
+        // Only upload the changed words of each root constant, split into contiguous runs.
         for (i, root_constant) in pipeline.root_constants.iter().enumerate() {
             let num_constants = (root_constant.range.end - root_constant.range.start) as usize;
-            let mut data = Vec::new();
-            for c in cur_index .. cur_index + num_constants {
-                data.push(match user_data.data[c] {
-                    RootElement::Constant(v) => v,
-                    _ => {
-                        warn!(
-                            "Unset or mismatching root constant at index {:?} ({:?})",
-                            c, user_data.data[c]
-                        );
-                        0
-                    }
-                });
-                user_data.clear_dirty(c);
+            for run in user_data.dirty_runs(cur_index .. cur_index + num_constants) {
+                let mut data = SmallVec::<[u32; 16]>::new();
+                for c in run.clone() {
+                    data.push(match user_data.data[c] {
+                        RootElement::Constant(v) => v,
+                        _ => {
+                            warn!(
+                                "Unset or mismatching root constant at index {:?} ({:?})",
+                                c, user_data.data[c]
+                            );
+                            0
+                        }
+                    });
+                    user_data.clear_dirty(c);
+                }
+                constants_update(i as _, (run.start - cur_index) as _, &data);
             }
-            constants_update(i as _, &data);
             cur_index += num_constants;
         }
 
@@ -790,394 +1498,134 @@ impl CommandBuffer {
         }
     }
 
-    fn transition_barrier(
-        transition: d3d12::D3D12_RESOURCE_TRANSITION_BARRIER,
-    ) -> d3d12::D3D12_RESOURCE_BARRIER {
-        let mut barrier = d3d12::D3D12_RESOURCE_BARRIER {
-            Type: d3d12::D3D12_RESOURCE_BARRIER_TYPE_TRANSITION,
-            Flags: d3d12::D3D12_RESOURCE_BARRIER_FLAG_NONE,
-            u: unsafe { mem::zeroed() },
+    // Resolve the result of an ended query into the readback buffer of the pool
+    // and mark it as available.
+    //
+    // Query data can't be resolved inside native render passes, the resolve is deferred
+    // until the end of the pass.
+    unsafe fn resolve_query(
+        &mut self,
+        pool: &r::QueryPool,
+        ty: d3d12::D3D12_QUERY_TYPE,
+        id: query::Id,
+    ) {
+        let resolve = QueryResolve {
+            heap: pool.raw,
+            readback: pool.readback,
+            availability: pool.availability,
+            ty,
+            id,
+            result_offset: pool.result_offset(id),
+            availability_offset: pool.availability_offset(id),
         };
-
-        *unsafe { barrier.u.Transition_mut() } = transition;
-        barrier
+        self.resolved_queries.push((Arc::clone(&pool.completion), id));
+        match self.pass_cache {
+            Some(ref state) if state.native => self.deferred_query_resolves.push(resolve),
+            _ => self.record_query_resolve(resolve),
+        }
     }
 
-    fn split_buffer_copy(copies: &mut Vec<Copy>, r: &com::BufferImageCopy, image: &r::ImageBound) {
-        let buffer_width = if r.buffer_width == 0 {
-            r.image_extent.width
-        } else {
-            r.buffer_width
-        };
-        let buffer_height = if r.buffer_height == 0 {
-            r.image_extent.height
-        } else {
-            r.buffer_height
-        };
-        let image_extent_aligned = image::Extent {
-            width: up_align(r.image_extent.width, image.block_dim.0 as _),
-            height: up_align(r.image_extent.height, image.block_dim.1 as _),
-            depth: r.image_extent.depth,
-        };
-        let row_pitch = div(buffer_width, image.block_dim.0 as _) * image.bytes_per_block as u32;
-        let slice_pitch = div(buffer_height, image.block_dim.1 as _) * row_pitch;
-        let is_pitch_aligned = row_pitch % d3d12::D3D12_TEXTURE_DATA_PITCH_ALIGNMENT == 0;
-
-        for layer in r.image_layers.layers.clone() {
-            let img_subresource = image.calc_subresource(r.image_layers.level as _, layer as _, 0);
-            let layer_relative = (layer - r.image_layers.layers.start) as u32;
-            let layer_offset = r.buffer_offset as u64
-                + (layer_relative * slice_pitch * r.image_extent.depth) as u64;
-            let aligned_offset =
-                layer_offset & !(d3d12::D3D12_TEXTURE_DATA_PLACEMENT_ALIGNMENT as u64 - 1);
-            if layer_offset == aligned_offset && is_pitch_aligned {
-                // trivial case: everything is aligned, ready for copying
-                copies.push(Copy {
-                    footprint_offset: aligned_offset,
-                    footprint: image_extent_aligned,
-                    row_pitch,
-                    img_subresource,
-                    img_offset: r.image_offset,
-                    buf_offset: image::Offset::ZERO,
-                    copy_extent: image_extent_aligned,
-                });
-            } else if is_pitch_aligned {
-                // buffer offset is not aligned
-                let row_pitch_texels =
-                    row_pitch / image.bytes_per_block as u32 * image.block_dim.0 as u32;
-                let gap = (layer_offset - aligned_offset) as i32;
-                let buf_offset = image::Offset {
-                    x: (gap % row_pitch as i32) / image.bytes_per_block as i32
-                        * image.block_dim.0 as i32,
-                    y: (gap % slice_pitch as i32) / row_pitch as i32 * image.block_dim.1 as i32,
-                    z: gap / slice_pitch as i32,
-                };
-                let footprint = image::Extent {
-                    width: buf_offset.x as u32 + image_extent_aligned.width,
-                    height: buf_offset.y as u32 + image_extent_aligned.height,
-                    depth: buf_offset.z as u32 + image_extent_aligned.depth,
-                };
-                if r.image_extent.width + buf_offset.x as u32 <= row_pitch_texels {
-                    // we can map it to the aligned one and adjust the offsets accordingly
-                    copies.push(Copy {
-                        footprint_offset: aligned_offset,
-                        footprint,
-                        row_pitch,
-                        img_subresource,
-                        img_offset: r.image_offset,
-                        buf_offset,
-                        copy_extent: image_extent_aligned,
-                    });
-                } else {
-                    // split the copy region into 2 that suffice the previous condition
-                    assert!(buf_offset.x as u32 <= row_pitch_texels);
-                    let half = row_pitch_texels - buf_offset.x as u32;
-                    assert!(half <= r.image_extent.width);
+    // Reserve the fence values signalled after executing this command buffer,
+    // one for each query pool with queries resolved by it.
+    pub(crate) fn submit_query_resolves(&self, signals: &mut Vec<(native::Fence, u64)>) {
+        let mut pools: Vec<&Arc<r::QueryCompletion>> = Vec::new();
+        for &(ref completion, _) in &self.resolved_queries {
+            if pools.iter().any(|pool| Arc::ptr_eq(pool, completion)) {
+                continue;
+            }
+            pools.push(completion);
+            let queries = self
+                .resolved_queries
+                .iter()
+                .filter(|&&(ref other, _)| Arc::ptr_eq(other, completion))
+                .map(|&(_, id)| id);
+            signals.push((completion.fence, completion.submit(queries)));
+        }
+    }
 
-                    copies.push(Copy {
-                        footprint_offset: aligned_offset,
-                        footprint: image::Extent {
-                            width: row_pitch_texels,
-                            ..footprint
-                        },
-                        row_pitch,
-                        img_subresource,
-                        img_offset: r.image_offset,
-                        buf_offset,
-                        copy_extent: image::Extent {
-                            width: half,
-                            ..r.image_extent
-                        },
-                    });
-                    copies.push(Copy {
-                        footprint_offset: aligned_offset,
-                        footprint: image::Extent {
-                            width: image_extent_aligned.width - half,
-                            height: footprint.height + image.block_dim.1 as u32,
-                            depth: footprint.depth,
-                        },
-                        row_pitch,
-                        img_subresource,
-                        img_offset: image::Offset {
-                            x: r.image_offset.x + half as i32,
-                            ..r.image_offset
-                        },
-                        buf_offset: image::Offset {
-                            x: 0,
-                            y: buf_offset.y + image.block_dim.1 as i32,
-                            z: buf_offset.z,
-                        },
-                        copy_extent: image::Extent {
-                            width: image_extent_aligned.width - half,
-                            ..image_extent_aligned
-                        },
-                    });
-                }
-            } else {
-                // worst case: row by row copy
-                for z in 0 .. r.image_extent.depth {
-                    for y in 0 .. image_extent_aligned.height / image.block_dim.1 as u32 {
-                        // an image row starts non-aligned
-                        let row_offset = layer_offset
-                            + z as u64 * slice_pitch as u64
-                            + y as u64 * row_pitch as u64;
-                        let aligned_offset = row_offset
-                            & !(d3d12::D3D12_TEXTURE_DATA_PLACEMENT_ALIGNMENT as u64 - 1);
-                        let next_aligned_offset =
-                            aligned_offset + d3d12::D3D12_TEXTURE_DATA_PLACEMENT_ALIGNMENT as u64;
-                        let cut_row_texels = (next_aligned_offset - row_offset)
-                            / image.bytes_per_block as u64
-                            * image.block_dim.0 as u64;
-                        let cut_width =
-                            cmp::min(image_extent_aligned.width, cut_row_texels as image::Size);
-                        let gap_texels = (row_offset - aligned_offset) as image::Size
-                            / image.bytes_per_block as image::Size
-                            * image.block_dim.0 as image::Size;
-                        // this is a conservative row pitch that should be compatible with both copies
-                        let max_unaligned_pitch =
-                            (r.image_extent.width + gap_texels) * image.bytes_per_block as u32;
-                        let row_pitch = (max_unaligned_pitch
-                            | (d3d12::D3D12_TEXTURE_DATA_PITCH_ALIGNMENT - 1))
-                            + 1;
+    unsafe fn record_query_resolve(&self, resolve: QueryResolve) {
+        self.raw.ResolveQueryData(
+            resolve.heap.as_mut_ptr(),
+            resolve.ty,
+            resolve.id,
+            1,
+            resolve.readback.as_mut_ptr(),
+            resolve.result_offset,
+        );
+        self.raw.CopyBufferRegion(
+            resolve.readback.as_mut_ptr(),
+            resolve.availability_offset,
+            resolve.availability.as_mut_ptr(),
+            0,
+            r::QUERY_AVAILABILITY_SIZE,
+        );
+    }
 
-                        copies.push(Copy {
-                            footprint_offset: aligned_offset,
-                            footprint: image::Extent {
-                                width: cut_width + gap_texels,
-                                height: image.block_dim.1 as _,
-                                depth: 1,
-                            },
-                            row_pitch,
-                            img_subresource,
-                            img_offset: image::Offset {
-                                x: r.image_offset.x,
-                                y: r.image_offset.y + image.block_dim.1 as i32 * y as i32,
-                                z: r.image_offset.z + z as i32,
-                            },
-                            buf_offset: image::Offset {
-                                x: gap_texels as i32,
-                                y: 0,
-                                z: 0,
-                            },
-                            copy_extent: image::Extent {
-                                width: cut_width,
-                                height: image.block_dim.1 as _,
-                                depth: 1,
-                            },
-                        });
+    // Check the tracked state of a buffer against the state required by an operation.
+    //
+    // Only enabled in debug builds, logs an error naming the resource and the states
+    // instead of letting the driver remove the device. Returns `false` if the check failed.
+    fn validate_buffer_state(
+        &self,
+        buffer: &r::BufferBound,
+        expected: d3d12::D3D12_RESOURCE_STATES,
+        operation: &str,
+    ) -> bool {
+        self.validate_resource_state(buffer.resource.as_mut_ptr(), expected, operation)
+    }
 
-                        // and if it crosses a pitch alignment - we copy the rest separately
-                        if cut_width >= image_extent_aligned.width {
-                            continue;
-                        }
-                        let leftover = image_extent_aligned.width - cut_width;
+    // Check the tracked states of the buffers bound as views by a descriptor set.
+    fn validate_descriptor_set_states(&self, set: &r::DescriptorSet, operation: &str) -> bool {
+        if !cfg!(debug_assertions) {
+            return true;
+        }
+        let buffer_states = set.buffer_states.lock().unwrap();
+        buffer_states.values().fold(true, |valid, &(resource, expected)| {
+            self.validate_resource_state(resource, expected, operation) && valid
+        })
+    }
 
-                        copies.push(Copy {
-                            footprint_offset: next_aligned_offset,
-                            footprint: image::Extent {
-                                width: leftover,
-                                height: image.block_dim.1 as _,
-                                depth: 1,
-                            },
-                            row_pitch,
-                            img_subresource,
-                            img_offset: image::Offset {
-                                x: r.image_offset.x + cut_width as i32,
-                                y: r.image_offset.y + y as i32 * image.block_dim.1 as i32,
-                                z: r.image_offset.z + z as i32,
-                            },
-                            buf_offset: image::Offset::ZERO,
-                            copy_extent: image::Extent {
-                                width: leftover,
-                                height: image.block_dim.1 as _,
-                                depth: 1,
-                            },
-                        });
-                    }
-                }
+    fn validate_resource_state(
+        &self,
+        resource: *mut d3d12::ID3D12Resource,
+        expected: d3d12::D3D12_RESOURCE_STATES,
+        operation: &str,
+    ) -> bool {
+        if !cfg!(debug_assertions) {
+            return true;
+        }
+        match self.buffer_states.get(&resource) {
+            Some(&state) if !is_state_compatible(state, expected) => {
+                error!(
+                    "{}: buffer {:?} is in state {:#x}, expected {:#x}",
+                    operation, resource, state, expected
+                );
+                false
             }
+            _ => true,
         }
     }
 
-    fn set_vertex_buffers(&mut self) {
-        let cmd_buffer = &mut self.raw;
-        let vbs_remap = &self.vertex_bindings_remap;
-        let vbs = &self.vertex_buffer_views;
-        let mut last_end_slot = 0;
-        loop {
-            let start_offset = match vbs_remap[last_end_slot ..]
-                .iter()
-                .position(|remap| remap.is_some())
-            {
-                Some(offset) => offset,
-                None => break,
-            };
-
-            let start_slot = last_end_slot + start_offset;
-            let buffers = vbs_remap[start_slot ..]
-                .iter()
-                .take_while(|x| x.is_some())
-                .filter_map(|mapping| {
-                    let mapping = mapping.unwrap();
-                    let view = vbs[mapping.mapped_binding];
-                    // Skip bindings that don't make sense. Since this function is called eagerly,
-                    // we expect it to be called with all the valid inputs prior to drawing.
-                    view.SizeInBytes.checked_sub(mapping.offset).map(|size| {
-                        d3d12::D3D12_VERTEX_BUFFER_VIEW {
-                            BufferLocation: view.BufferLocation + mapping.offset as u64,
-                            SizeInBytes: size,
-                            StrideInBytes: mapping.stride,
-                        }
-                    })
-                })
-                .collect::<SmallVec<[_; MAX_VERTEX_BUFFERS]>>();
-
-            if buffers.is_empty() {
-                last_end_slot = start_slot + 1;
-            } else {
-                let num_views = buffers.len();
-                unsafe {
-                    cmd_buffer.IASetVertexBuffers(
-                        start_slot as _,
-                        num_views as _,
-                        buffers.as_ptr(),
-                    );
-                }
-                last_end_slot = start_slot + num_views;
-            }
-        }
-    }
-}
-
-impl com::RawCommandBuffer<Backend> for CommandBuffer {
-    unsafe fn begin(
-        &mut self,
-        _flags: com::CommandBufferFlags,
-        _info: com::CommandBufferInheritanceInfo<Backend>,
-    ) {
-        // TODO: Implement flags and secondary command buffers (bundles).
-        if self
-            .pool_create_flags
-            .contains(pool::CommandPoolCreateFlags::RESET_INDIVIDUAL)
-        {
-            // Command buffer has reset semantics now and doesn't require to be in `Initial` state.
-            self.allocator.Reset();
-        }
-        self.reset();
-    }
-
-    unsafe fn finish(&mut self) {
-        self.raw.Close();
-    }
-
-    unsafe fn reset(&mut self, _release_resources: bool) {
-        // Ensure that we have a bijective relation between list and allocator.
-        // This allows to modify the allocator here. Using `reset` requires this by specification.
-        assert!(self
-            .pool_create_flags
-            .contains(pool::CommandPoolCreateFlags::RESET_INDIVIDUAL));
-
-        // TODO: `release_resources` should recreate the allocator to give back all memory.
-        self.allocator.Reset();
-        self.reset();
-    }
-
-    unsafe fn begin_render_pass<T>(
-        &mut self,
-        render_pass: &r::RenderPass,
-        framebuffer: &r::Framebuffer,
-        target_rect: pso::Rect,
-        clear_values: T,
-        _first_subpass: com::SubpassContents,
-    ) where
-        T: IntoIterator,
-        T::Item: Borrow<com::ClearValueRaw>,
-    {
-        assert_eq!(framebuffer.attachments.len(), render_pass.attachments.len());
-        // Make sure that no subpass works with Present as intermediate layout.
-        // This wouldn't make much sense, and proceeding with this constraint
-        // allows the state transitions generated from subpass dependencies
-        // to ignore the layouts completely.
-        assert!(!render_pass.subpasses.iter().any(|sp| sp
-            .color_attachments
-            .iter()
-            .chain(sp.depth_stencil_attachment.iter())
-            .chain(sp.input_attachments.iter())
-            .any(|aref| aref.1 == image::Layout::Present)));
-
-        let mut clear_iter = clear_values.into_iter();
-        let attachment_clears = render_pass
-            .attachments
-            .iter()
-            .enumerate()
-            .map(|(i, attachment)| {
-                let cv = if attachment.has_clears() {
-                    Some(*clear_iter.next().unwrap().borrow())
-                } else {
-                    None
-                };
-
-                AttachmentClear {
-                    subpass_id: render_pass.subpasses.iter().position(|sp| sp.is_using(i)),
-                    value: if attachment.ops.load == pass::AttachmentLoadOp::Clear {
-                        assert!(cv.is_some());
-                        cv
-                    } else {
-                        None
-                    },
-                    stencil_value: if attachment.stencil_ops.load == pass::AttachmentLoadOp::Clear {
-                        Some(cv.unwrap().depth_stencil.stencil)
-                    } else {
-                        None
-                    },
-                }
-            })
-            .collect();
-
-        self.pass_cache = Some(RenderPassCache {
-            render_pass: render_pass.clone(),
-            framebuffer: framebuffer.clone(),
-            target_rect: get_rect(&target_rect),
-            attachment_clears,
-        });
-        self.cur_subpass = 0;
-        self.insert_subpass_barriers(BarrierPoint::Pre);
-        self.bind_targets();
-    }
-
-    unsafe fn next_subpass(&mut self, _contents: com::SubpassContents) {
-        self.insert_subpass_barriers(BarrierPoint::Post);
-        self.resolve_attachments();
-
-        self.cur_subpass += 1;
-        self.insert_subpass_barriers(BarrierPoint::Pre);
-        self.bind_targets();
-    }
-
-    unsafe fn end_render_pass(&mut self) {
-        self.insert_subpass_barriers(BarrierPoint::Post);
-        self.resolve_attachments();
-
-        self.cur_subpass = !0;
-        self.insert_subpass_barriers(BarrierPoint::Pre);
-        self.pass_cache = None;
-    }
-
-    unsafe fn pipeline_barrier<'a, T>(
-        &mut self,
-        _stages: Range<pso::PipelineStage>,
-        _dependencies: memory::Dependencies,
-        barriers: T,
-    ) where
-        T: IntoIterator,
-        T::Item: Borrow<memory::Barrier<'a, Backend>>,
-    {
-        let mut raw_barriers = Vec::new();
+    // Translate `barriers` into raw resource barriers, transitions are recorded with `flags`.
+    unsafe fn record_barriers<'a, T>(
+        &mut self,
+        barriers: T,
+        flags: d3d12::D3D12_RESOURCE_BARRIER_FLAGS,
+    ) -> Vec<d3d12::D3D12_RESOURCE_BARRIER>
+    where
+        T: IntoIterator,
+        T::Item: Borrow<memory::Barrier<'a, Backend>>,
+    {
+        let mut raw_barriers = Vec::new();
 
         // transition barriers
         for barrier in barriers {
             match *barrier.borrow() {
                 memory::Barrier::AllBuffers(_) | memory::Barrier::AllImages(_) => {
+                    // UAV barriers can't be split, recorded at the end of a split barrier.
+                    if flags == d3d12::D3D12_RESOURCE_BARRIER_FLAG_BEGIN_ONLY {
+                        continue;
+                    }
                     // Aliasing barrier with NULL resource is the closest we can get to
                     // a global memory barrier in Vulkan.
                     // Was suggested by a Microsoft representative as well as some of the IHVs.
@@ -1206,17 +1654,23 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
                     let state_src = conv::map_buffer_resource_state(states.start);
                     let state_dst = conv::map_buffer_resource_state(states.end);
 
+                    let target = target.expect_bound();
+                    self.buffer_states
+                        .insert(target.resource.as_mut_ptr(), state_dst);
+
                     if state_src == state_dst {
                         continue;
                     }
 
-                    let target = target.expect_bound();
-                    let bar = Self::transition_barrier(d3d12::D3D12_RESOURCE_TRANSITION_BARRIER {
-                        pResource: target.resource.as_mut_ptr(),
-                        Subresource: d3d12::D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
-                        StateBefore: state_src,
-                        StateAfter: state_dst,
-                    });
+                    self.track_split_barrier(target.resource.as_mut_ptr(), flags);
+                    let mut bar =
+                        Self::transition_barrier(d3d12::D3D12_RESOURCE_TRANSITION_BARRIER {
+                            pResource: target.resource.as_mut_ptr(),
+                            Subresource: d3d12::D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+                            StateBefore: state_src,
+                            StateAfter: state_dst,
+                        });
+                    bar.Flags = flags;
 
                     raw_barriers.push(bar);
                 }
@@ -1233,14 +1687,32 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
                         }
                     }
                     let _ = range; //TODO: use subresource range
-                    let state_src = conv::map_image_resource_state(states.start.0, states.start.1);
-                    let state_dst = conv::map_image_resource_state(states.end.0, states.end.1);
+                    let target = target.expect_bound();
+                    let state_src =
+                        image_barrier_state(target.initial_state, states.start.0, states.start.1);
+                    let state_dst =
+                        image_barrier_state(target.initial_state, states.end.0, states.end.1);
+
+                    // Images transitioned from `Undefined` take over their memory region,
+                    // which requires an aliasing barrier if other resources overlap it.
+                    if states.start.1 == image::Layout::Undefined {
+                        if let Some(ref region) = target.region {
+                            let after = target.resource.as_mut_ptr();
+                            if region.is_aliased(target.resource) {
+                                if let Some(before) =
+                                    activate_alias(&mut self.active_aliases, region, after)
+                                {
+                                    raw_barriers.push(Self::aliasing_barrier(before, after));
+                                }
+                            }
+                        }
+                    }
 
                     if state_src == state_dst {
                         continue;
                     }
 
-                    let target = target.expect_bound();
+                    self.track_split_barrier(target.resource.as_mut_ptr(), flags);
                     let mut bar =
                         Self::transition_barrier(d3d12::D3D12_RESOURCE_TRANSITION_BARRIER {
                             pResource: target.resource.as_mut_ptr(),
@@ -1248,20 +1720,26 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
                             StateBefore: state_src,
                             StateAfter: state_dst,
                         });
+                    bar.Flags = flags;
 
-                    if *range == target.to_subresource_range(range.aspects) {
+                    let planes = planar_slices(target.format, range.aspects);
+                    if *range == target.to_subresource_range(range.aspects)
+                        && (!target.format.is_planar() || planes == (0 .. 2))
+                    {
                         // Only one barrier if it affects the whole image.
                         raw_barriers.push(bar);
                     } else {
-                        // Generate barrier for each layer/level combination.
-                        for level in range.levels.clone() {
-                            for layer in range.layers.clone() {
-                                {
-                                    let transition_barrier = &mut *bar.u.Transition_mut();
-                                    transition_barrier.Subresource =
-                                        target.calc_subresource(level as _, layer as _, 0);
+                        // Generate barrier for each plane/layer/level combination.
+                        for plane in planes {
+                            for level in range.levels.clone() {
+                                for layer in range.layers.clone() {
+                                    {
+                                        let transition_barrier = &mut *bar.u.Transition_mut();
+                                        transition_barrier.Subresource =
+                                            target.calc_subresource(level as _, layer as _, plane);
+                                    }
+                                    raw_barriers.push(bar);
                                 }
-                                raw_barriers.push(bar);
                             }
                         }
                     }
@@ -1269,330 +1747,1264 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
             }
         }
 
-        // UAV barriers
-        //
-        // TODO: Currently always add a global UAV barrier.
-        //       WAR only requires an execution barrier but D3D12 seems to need
-        //       a UAV barrier for this according to docs. Can we make this better?
-        {
-            let mut barrier = d3d12::D3D12_RESOURCE_BARRIER {
-                Type: d3d12::D3D12_RESOURCE_BARRIER_TYPE_UAV,
-                Flags: d3d12::D3D12_RESOURCE_BARRIER_FLAG_NONE,
-                u: mem::zeroed(),
-            };
-            *barrier.u.UAV_mut() = d3d12::D3D12_RESOURCE_UAV_BARRIER {
-                pResource: ptr::null_mut(),
-            };
-            raw_barriers.push(barrier);
-        }
-
-        // Alias barriers
-        //
-        // TODO: Optimize, don't always add an alias barrier
-        {
-            let mut barrier = d3d12::D3D12_RESOURCE_BARRIER {
-                Type: d3d12::D3D12_RESOURCE_BARRIER_TYPE_ALIASING,
-                Flags: d3d12::D3D12_RESOURCE_BARRIER_FLAG_NONE,
-                u: mem::zeroed(),
-            };
-            *barrier.u.Aliasing_mut() = d3d12::D3D12_RESOURCE_ALIASING_BARRIER {
-                pResourceBefore: ptr::null_mut(),
-                pResourceAfter: ptr::null_mut(),
-            };
-            raw_barriers.push(barrier);
-        }
-
-        self.raw
-            .ResourceBarrier(raw_barriers.len() as _, raw_barriers.as_ptr());
+        raw_barriers
     }
 
-    unsafe fn clear_image<T>(
+    // Track the resources with pending split barriers.
+    //
+    // Resources are not allowed to be accessed until the split barrier ends.
+    fn track_split_barrier(
         &mut self,
-        image: &r::Image,
-        _: image::Layout,
-        color: com::ClearColorRaw,
-        depth_stencil: com::ClearDepthStencilRaw,
-        subresource_ranges: T,
-    ) where
-        T: IntoIterator,
-        T::Item: Borrow<image::SubresourceRange>,
-    {
-        let image = image.expect_bound();
-        for subresource_range in subresource_ranges {
-            let sub = subresource_range.borrow();
-            assert_eq!(sub.levels, 0 .. 1); //TODO
-            for layer in sub.layers.clone() {
-                if sub.aspects.contains(Aspects::COLOR) {
-                    let rtv = image.clear_cv[layer as usize];
-                    self.clear_render_target_view(rtv, color, &[]);
-                }
-                if sub.aspects.contains(Aspects::DEPTH) {
-                    let dsv = image.clear_dv[layer as usize];
-                    self.clear_depth_stencil_view(dsv, Some(depth_stencil.depth), None, &[]);
-                }
-                if sub.aspects.contains(Aspects::STENCIL) {
-                    let dsv = image.clear_sv[layer as usize];
-                    self.clear_depth_stencil_view(dsv, None, Some(depth_stencil.stencil as _), &[]);
+        resource: *mut d3d12::ID3D12Resource,
+        flags: d3d12::D3D12_RESOURCE_BARRIER_FLAGS,
+    ) {
+        match flags {
+            d3d12::D3D12_RESOURCE_BARRIER_FLAG_BEGIN_ONLY => {
+                self.split_barriers.push(resource);
+            }
+            d3d12::D3D12_RESOURCE_BARRIER_FLAG_END_ONLY => {
+                let pos = self.split_barriers.iter().position(|&r| r == resource);
+                debug_assert!(
+                    pos.is_some(),
+                    "end_barrier: no split barrier begun for resource {:?}",
+                    resource
+                );
+                if let Some(pos) = pos {
+                    self.split_barriers.swap_remove(pos);
                 }
             }
+            _ => self.validate_unsplit(resource, "pipeline_barrier"),
         }
     }
 
-    unsafe fn clear_attachments<T, U>(&mut self, clears: T, rects: U)
-    where
-        T: IntoIterator,
-        T::Item: Borrow<com::AttachmentClear>,
-        U: IntoIterator,
-        U::Item: Borrow<pso::ClearRect>,
-    {
-        let pass_cache = match self.pass_cache {
-            Some(ref cache) => cache,
-            None => panic!("`clear_attachments` can only be called inside a renderpass"),
-        };
-        let sub_pass = &pass_cache.render_pass.subpasses[self.cur_subpass];
-
-        let clear_rects: SmallVec<[pso::ClearRect; 4]> = rects
-            .into_iter()
-            .map(|rect| rect.borrow().clone())
-            .collect();
+    // Check that no split barrier of the resource is pending while accessing it.
+    fn validate_unsplit(&self, resource: *mut d3d12::ID3D12Resource, operation: &str) {
+        debug_assert!(
+            !self.split_barriers.contains(&resource),
+            "{}: resource {:?} is accessed during a split barrier",
+            operation,
+            resource
+        );
+    }
 
-        let device = self.shared.service_pipes.device;
+    // Execute indirect commands, transitioning the argument buffer into the
+    // `INDIRECT_ARGUMENT` state and back if it's known to be in a different state.
+    //
+    // Buffers without recorded transitions are expected to be in the common state,
+    // which is implicitly promoted by D3D12.
+    // Execute indirect commands, with the number of commands read from
+    // `count_buffer` at the given offset, bounded by `count`, if specified.
+    unsafe fn execute_indirect(
+        &mut self,
+        signature: native::CommandSignature,
+        count: u32,
+        buffer: &r::BufferBound,
+        offset: buffer::Offset,
+        count_buffer: Option<(&r::BufferBound, buffer::Offset)>,
+    ) {
+        let resource = buffer.resource.as_mut_ptr();
+        let (count_resource, count_offset) = match count_buffer {
+            Some((count_buffer, count_offset)) => (count_buffer.resource.as_mut_ptr(), count_offset),
+            None => (ptr::null_mut(), 0),
+        };
+        let indirect = d3d12::D3D12_RESOURCE_STATE_INDIRECT_ARGUMENT;
+        let transitions = [resource, count_resource]
+            .iter()
+            .filter(|resource| !resource.is_null())
+            .filter_map(|&resource| match self.buffer_states.get(&resource) {
+                Some(&state)
+                    if state != d3d12::D3D12_RESOURCE_STATE_COMMON && state & indirect == 0 =>
+                {
+                    Some((resource, state))
+                }
+                _ => None,
+            })
+            .collect::<SmallVec<[_; 2]>>();
 
-        for clear in clears {
-            match *clear.borrow() {
-                com::AttachmentClear::Color { index, value } => {
-                    let attachment = {
-                        let rtv_id = sub_pass.color_attachments[index];
-                        pass_cache.framebuffer.attachments[rtv_id.0]
-                    };
+        let barriers = transitions
+            .iter()
+            .map(|&(resource, state)| {
+                Self::transition_barrier(d3d12::D3D12_RESOURCE_TRANSITION_BARRIER {
+                    pResource: resource,
+                    Subresource: d3d12::D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+                    StateBefore: state,
+                    StateAfter: indirect,
+                })
+            })
+            .collect::<SmallVec<[_; 2]>>();
+        if !barriers.is_empty() {
+            self.raw.ResourceBarrier(barriers.len() as _, barriers.as_ptr());
+        }
 
-                    let mut rtv_pool = descriptors_cpu::HeapLinear::new(
-                        device,
-                        descriptor::HeapType::Rtv,
-                        clear_rects.len(),
-                    );
+        self.raw.ExecuteIndirect(
+            signature.as_mut_ptr(),
+            count,
+            resource,
+            offset,
+            count_resource,
+            count_offset,
+        );
 
-                    for clear_rect in &clear_rects {
-                        assert!(attachment.layers.0 + clear_rect.layers.end <= attachment.layers.1);
-                        let rect = [get_rect(&clear_rect.rect)];
+        let barriers = transitions
+            .iter()
+            .map(|&(resource, state)| {
+                Self::transition_barrier(d3d12::D3D12_RESOURCE_TRANSITION_BARRIER {
+                    pResource: resource,
+                    Subresource: d3d12::D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+                    StateBefore: indirect,
+                    StateAfter: state,
+                })
+            })
+            .collect::<SmallVec<[_; 2]>>();
+        if !barriers.is_empty() {
+            self.raw.ResourceBarrier(barriers.len() as _, barriers.as_ptr());
+        }
+    }
 
-                        let view_info = device::ViewInfo {
-                            resource: attachment.resource,
-                            kind: attachment.kind,
-                            caps: image::ViewCapabilities::empty(),
-                            view_kind: image::ViewKind::D2Array,
-                            format: attachment.dxgi_format,
-                            component_mapping: IDENTITY_MAPPING,
-                            range: image::SubresourceRange {
-                                aspects: Aspects::COLOR,
-                                levels: attachment.mip_levels.0 .. attachment.mip_levels.1,
-                                layers: attachment.layers.0 + clear_rect.layers.start
-                                    .. attachment.layers.0 + clear_rect.layers.end,
-                            },
-                        };
-                        let rtv = rtv_pool.alloc_handle();
-                        Device::view_image_as_render_target_impl(device, rtv, view_info).unwrap();
-                        self.clear_render_target_view(rtv, value.into(), &rect);
-                    }
-
-                    rtv_pool.destroy();
-                }
-                com::AttachmentClear::DepthStencil { depth, stencil } => {
-                    let attachment = {
-                        let dsv_id = sub_pass.depth_stencil_attachment.unwrap();
-                        pass_cache.framebuffer.attachments[dsv_id.0]
-                    };
-
-                    let mut dsv_pool = descriptors_cpu::HeapLinear::new(
-                        device,
-                        descriptor::HeapType::Dsv,
-                        clear_rects.len(),
-                    );
-
-                    for clear_rect in &clear_rects {
-                        assert!(attachment.layers.0 + clear_rect.layers.end <= attachment.layers.1);
-                        let rect = [get_rect(&clear_rect.rect)];
-
-                        let view_info = device::ViewInfo {
-                            resource: attachment.resource,
-                            kind: attachment.kind,
-                            caps: image::ViewCapabilities::empty(),
-                            view_kind: image::ViewKind::D2Array,
-                            format: attachment.dxgi_format,
-                            component_mapping: IDENTITY_MAPPING,
-                            range: image::SubresourceRange {
-                                aspects: if depth.is_some() {
-                                    Aspects::DEPTH
-                                } else {
-                                    Aspects::empty()
-                                } | if stencil.is_some() {
-                                    Aspects::STENCIL
-                                } else {
-                                    Aspects::empty()
-                                },
-                                levels: attachment.mip_levels.0 .. attachment.mip_levels.1,
-                                layers: attachment.layers.0 + clear_rect.layers.start
-                                    .. attachment.layers.0 + clear_rect.layers.end,
-                            },
-                        };
-                        let dsv = dsv_pool.alloc_handle();
-                        Device::view_image_as_depth_stencil_impl(device, dsv, view_info).unwrap();
-                        self.clear_depth_stencil_view(dsv, depth, stencil, &rect);
-                    }
-
-                    dsv_pool.destroy();
-                }
-            }
+    /// Draw primitives with indirect parameters, the number of draws is read from `count_buffer`.
+    ///
+    /// At most `max_draw_count` draws are executed. Requires `Features::DRAW_INDIRECT_COUNT`.
+    pub unsafe fn draw_indirect_count(
+        &mut self,
+        buffer: &r::Buffer,
+        offset: buffer::Offset,
+        count_buffer: &r::Buffer,
+        count_buffer_offset: buffer::Offset,
+        max_draw_count: DrawCount,
+        stride: u32,
+    ) {
+        assert_eq!(stride, 16);
+        if !self.is_draw_indirect_count_enabled() {
+            return;
         }
+        let buffer = buffer.expect_bound();
+        let count_buffer = count_buffer.expect_bound();
+        self.set_graphics_bind_point();
+        let signature = self.shared.signatures.draw;
+        self.execute_indirect(
+            signature,
+            max_draw_count,
+            buffer,
+            offset,
+            Some((count_buffer, count_buffer_offset)),
+        );
     }
 
-    unsafe fn resolve_image<T>(
+    /// Draw indexed primitives with indirect parameters, the number of draws is read
+    /// from `count_buffer`.
+    ///
+    /// At most `max_draw_count` draws are executed. Requires `Features::DRAW_INDIRECT_COUNT`.
+    pub unsafe fn draw_indexed_indirect_count(
         &mut self,
-        src: &r::Image,
-        _src_layout: image::Layout,
-        dst: &r::Image,
-        _dst_layout: image::Layout,
-        regions: T,
-    ) where
-        T: IntoIterator,
-        T::Item: Borrow<com::ImageResolve>,
-    {
-        let src = src.expect_bound();
-        let dst = dst.expect_bound();
-        assert_eq!(src.descriptor.Format, dst.descriptor.Format);
-
-        {
-            // Insert barrier for `COPY_DEST` to `RESOLVE_DEST` as we only expose
-            // `TRANSFER_WRITE` which is used for all copy commands.
-            let transition_barrier =
-                Self::transition_barrier(d3d12::D3D12_RESOURCE_TRANSITION_BARRIER {
-                    pResource: dst.resource.as_mut_ptr(),
-                    Subresource: d3d12::D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES, // TODO: only affected ranges
-                    StateBefore: d3d12::D3D12_RESOURCE_STATE_COPY_DEST,
-                    StateAfter: d3d12::D3D12_RESOURCE_STATE_RESOLVE_DEST,
-                });
-            self.raw.ResourceBarrier(1, &transition_barrier);
+        buffer: &r::Buffer,
+        offset: buffer::Offset,
+        count_buffer: &r::Buffer,
+        count_buffer_offset: buffer::Offset,
+        max_draw_count: DrawCount,
+        stride: u32,
+    ) {
+        assert_eq!(stride, 20);
+        if !self.is_draw_indirect_count_enabled() {
+            return;
         }
+        let buffer = buffer.expect_bound();
+        let count_buffer = count_buffer.expect_bound();
+        self.set_graphics_bind_point();
+        let signature = self.shared.signatures.draw_indexed;
+        self.execute_indirect(
+            signature,
+            max_draw_count,
+            buffer,
+            offset,
+            Some((count_buffer, count_buffer_offset)),
+        );
+    }
 
-        for region in regions {
-            let r = region.borrow();
-            for layer in 0 .. r.extent.depth as UINT {
-                self.raw.ResolveSubresource(
-                    src.resource.as_mut_ptr(),
-                    src.calc_subresource(
-                        r.src_subresource.level as UINT,
-                        r.src_subresource.layers.start as UINT + layer,
-                        0,
-                    ),
-                    dst.resource.as_mut_ptr(),
-                    dst.calc_subresource(
-                        r.dst_subresource.level as UINT,
-                        r.dst_subresource.layers.start as UINT + layer,
-                        0,
-                    ),
-                    src.descriptor.Format,
-                );
-            }
+    fn is_draw_indirect_count_enabled(&self) -> bool {
+        let enabled = self
+            .shared
+            .features
+            .contains(hal::Features::DRAW_INDIRECT_COUNT);
+        if !enabled {
+            error!("Indirect draws with count buffer require `Features::DRAW_INDIRECT_COUNT`");
         }
+        enabled
+    }
 
-        {
-            // Insert barrier for back transition from `RESOLVE_DEST` to `COPY_DEST`.
-            let transition_barrier =
-                Self::transition_barrier(d3d12::D3D12_RESOURCE_TRANSITION_BARRIER {
-                    pResource: dst.resource.as_mut_ptr(),
-                    Subresource: d3d12::D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES, // TODO: only affected ranges
-                    StateBefore: d3d12::D3D12_RESOURCE_STATE_RESOLVE_DEST,
-                    StateAfter: d3d12::D3D12_RESOURCE_STATE_COPY_DEST,
-                });
-            self.raw.ResourceBarrier(1, &transition_barrier);
+    /// Generate the mip levels of `range` by successively downsampling each level from
+    /// the previous one, starting at `range.levels.start`.
+    ///
+    /// Supports 2D images, including arrays, and 3D images of float or normalized color
+    /// formats. The image must be created with `SAMPLED` and `STORAGE` usage. All levels
+    /// in `range` are expected in the `TransferDstOptimal` layout and are left in the
+    /// `ShaderReadOnlyOptimal` layout.
+    pub unsafe fn generate_mipmaps(&mut self, image: &r::Image, range: image::SubresourceRange) {
+        if self.is_restricted_in_bundle("generate_mipmaps") {
+            return;
         }
-    }
+        let image = image.expect_bound();
+        let required = image::Usage::SAMPLED | image::Usage::STORAGE;
+        if !image.usage.contains(required) {
+            error!(
+                "Mipmap generation requires an image created with usage {:?}, got {:?}",
+                required, image.usage
+            );
+            return;
+        }
+        if range.aspects != format::Aspects::COLOR {
+            error!("Mipmaps can only be generated for color images");
+            return;
+        }
+        if range.levels.start >= range.levels.end {
+            return;
+        }
+        let dimension = image.descriptor.Dimension;
+        match image.kind {
+            image::Kind::D2(..) | image::Kind::D3(..) => {}
+            image::Kind::D1(..) => {
+                error!("Mipmap generation is not supported for 1D images");
+                return;
+            }
+        }
+        let format = image.default_view_format.unwrap();
+        let layers = match image.kind {
+            image::Kind::D3(..) => 0 .. 1,
+            _ => range.layers.clone(),
+        };
+        let read_state = conv::map_image_resource_state(
+            image::Access::SHADER_READ,
+            image::Layout::ShaderReadOnlyOptimal,
+        );
+        let barrier = |level: image::Level, before, after| {
+            layers
+                .clone()
+                .map(|layer| {
+                    Self::transition_barrier(d3d12::D3D12_RESOURCE_TRANSITION_BARRIER {
+                        pResource: image.resource.as_mut_ptr(),
+                        Subresource: image.calc_subresource(level as _, layer as _, 0),
+                        StateBefore: before,
+                        StateAfter: after,
+                    })
+                })
+                .collect::<SmallVec<[_; 4]>>()
+        };
 
-    unsafe fn blit_image<T>(
-        &mut self,
-        src: &r::Image,
-        _src_layout: image::Layout,
-        dst: &r::Image,
-        _dst_layout: image::Layout,
-        filter: image::Filter,
-        regions: T,
-    ) where
-        T: IntoIterator,
-        T::Item: Borrow<com::ImageBlit>,
-    {
         let device = self.shared.service_pipes.device.clone();
-        let src = src.expect_bound();
-        let dst = dst.expect_bound();
+        let first_level = range.levels.start;
+        let num_passes = range.levels.end.saturating_sub(first_level + 1) as usize;
+        if num_passes > 0 {
+            let pipe = self.shared.service_pipes.get_mipmap(dimension);
+            // Descriptor heap storing the source and destination views of each level
+            let heap = Device::create_descriptor_heap_impl(
+                device,
+                descriptor::HeapType::CbvSrvUav,
+                true,
+                2 * num_passes,
+            );
+            self.temporary_gpu_heaps.push(heap.raw);
+            self.raw.set_descriptor_heaps(&[heap.raw]);
+            self.raw.set_pipeline_state(pipe.pipeline);
+            self.raw.set_compute_root_signature(pipe.signature);
+
+            for pass in 0 .. num_passes {
+                let dst_level = first_level + 1 + pass as image::Level;
+                let src_level = dst_level - 1;
+                let (srv, uav) = mipmap_views(format, dimension, dst_level, &layers);
+                let handles = heap.at(2 * pass as u64, 2);
+                device.CreateShaderResourceView(
+                    image.resource.as_mut_ptr(),
+                    &srv,
+                    handles.cpu,
+                );
+                device.CreateUnorderedAccessView(
+                    image.resource.as_mut_ptr(),
+                    ptr::null_mut(),
+                    &uav,
+                    heap.at(2 * pass as u64 + 1, 1).cpu,
+                );
 
-        // TODO: Resource barriers for src.
-        // TODO: depth or stencil images not supported so far
+                let src_state = if src_level == first_level {
+                    d3d12::D3D12_RESOURCE_STATE_COPY_DEST
+                } else {
+                    d3d12::D3D12_RESOURCE_STATE_UNORDERED_ACCESS
+                };
+                let mut barriers = barrier(src_level, src_state, read_state);
+                barriers.extend(barrier(
+                    dst_level,
+                    d3d12::D3D12_RESOURCE_STATE_COPY_DEST,
+                    d3d12::D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+                ));
+                self.raw.ResourceBarrier(barriers.len() as _, barriers.as_ptr());
 
-        // TODO: only supporting 2D images
-        match (src.kind, dst.kind) {
-            (image::Kind::D2(..), image::Kind::D2(..)) => {}
-            _ => unimplemented!(),
-        }
+                let src_extent = image.kind.level_extent(src_level);
+                let dst_extent = image.kind.level_extent(dst_level);
+                let data = internal::MipmapData {
+                    src_extent: [src_extent.width, src_extent.height, src_extent.depth, 0],
+                    dst_extent: [dst_extent.width, dst_extent.height, dst_extent.depth, 0],
+                };
+                self.raw.set_compute_root_descriptor_table(0, handles.gpu);
+                self.raw.SetComputeRoot32BitConstants(
+                    1,
+                    (mem::size_of::<internal::MipmapData>() / 4) as _,
+                    &data as *const _ as *const _,
+                    0,
+                );
+                self.raw.dispatch(mipmap_group_count(
+                    dimension,
+                    dst_extent,
+                    layers.end - layers.start,
+                ));
+            }
 
-        // Descriptor heap for the current blit, only storing the src image
-        let (srv_heap, _) = device.create_descriptor_heap(
-            1,
-            descriptor::HeapType::CbvSrvUav,
-            descriptor::HeapFlags::SHADER_VISIBLE,
-            0,
-        );
-        let srv_desc = Device::build_image_as_shader_resource_desc(&ViewInfo {
-            resource: src.resource,
-            kind: src.kind,
-            caps: src.view_caps,
-            view_kind: image::ViewKind::D2Array, // TODO
-            format: src.default_view_format.unwrap(),
-            component_mapping: IDENTITY_MAPPING,
-            range: image::SubresourceRange {
-                aspects: format::Aspects::COLOR, // TODO
-                levels: 0 .. src.descriptor.MipLevels as _,
-                layers: 0 .. src.kind.num_layers(),
-            },
-        })
-        .unwrap();
-        device.CreateShaderResourceView(
-            src.resource.as_mut_ptr(),
-            &srv_desc,
-            srv_heap.start_cpu_descriptor(),
-        );
-        self.raw.set_descriptor_heaps(&[srv_heap]);
-        self.temporary_gpu_heaps.push(srv_heap);
+            // Restore the compute root signature, pipeline state and descriptor heaps
+            // are rebound on the next draw or dispatch.
+            if let Some((_, signature)) = self.comp_pipeline.pipeline {
+                self.raw.set_compute_root_signature(signature);
+            }
+            self.comp_pipeline.user_data.dirty_all();
+            self.set_internal_graphics_pipeline();
+        }
 
-        let filter = match filter {
-            image::Filter::Nearest => d3d12::D3D12_FILTER_MIN_MAG_MIP_POINT,
-            image::Filter::Linear => d3d12::D3D12_FILTER_MIN_MAG_LINEAR_MIP_POINT,
+        let last_level = range.levels.end - 1;
+        let last_state = if num_passes > 0 {
+            d3d12::D3D12_RESOURCE_STATE_UNORDERED_ACCESS
+        } else {
+            d3d12::D3D12_RESOURCE_STATE_COPY_DEST
         };
+        let barriers = barrier(last_level, last_state, read_state);
+        self.raw.ResourceBarrier(barriers.len() as _, barriers.as_ptr());
+    }
 
-        struct Instance {
-            rtv: d3d12::D3D12_CPU_DESCRIPTOR_HANDLE,
-            viewport: d3d12::D3D12_VIEWPORT,
-            data: internal::BlitData,
+    fn uav_barrier(resource: *mut d3d12::ID3D12Resource) -> d3d12::D3D12_RESOURCE_BARRIER {
+        let mut barrier = d3d12::D3D12_RESOURCE_BARRIER {
+            Type: d3d12::D3D12_RESOURCE_BARRIER_TYPE_UAV,
+            Flags: d3d12::D3D12_RESOURCE_BARRIER_FLAG_NONE,
+            u: unsafe { mem::zeroed() },
         };
-        let mut instances = FastHashMap::<internal::BlitKey, Vec<Instance>>::default();
-        let mut barriers = Vec::new();
 
-        for region in regions {
-            let r = region.borrow();
+        *unsafe { barrier.u.UAV_mut() } = d3d12::D3D12_RESOURCE_UAV_BARRIER {
+            pResource: resource,
+        };
+        barrier
+    }
 
-            let first_layer = r.dst_subresource.layers.start;
-            let num_layers = r.dst_subresource.layers.end - first_layer;
+    fn aliasing_barrier(
+        before: *mut d3d12::ID3D12Resource,
+        after: *mut d3d12::ID3D12Resource,
+    ) -> d3d12::D3D12_RESOURCE_BARRIER {
+        let mut barrier = d3d12::D3D12_RESOURCE_BARRIER {
+            Type: d3d12::D3D12_RESOURCE_BARRIER_TYPE_ALIASING,
+            Flags: d3d12::D3D12_RESOURCE_BARRIER_FLAG_NONE,
+            u: unsafe { mem::zeroed() },
+        };
 
-            // WORKAROUND: renderdoc crashes if we destroy the pool too early
-            let rtv_pool = Device::create_descriptor_heap_impl(
-                device,
-                descriptor::HeapType::Rtv,
-                false,
-                num_layers as _,
-            );
-            self.rtv_pools.push(rtv_pool.raw.clone());
+        *unsafe { barrier.u.Aliasing_mut() } = d3d12::D3D12_RESOURCE_ALIASING_BARRIER {
+            pResourceBefore: before,
+            pResourceAfter: after,
+        };
+        barrier
+    }
 
-            let key = match r.dst_subresource.aspects {
-                format::Aspects::COLOR => {
-                    let format = dst.default_view_format.unwrap();
+    fn transition_barrier(
+        transition: d3d12::D3D12_RESOURCE_TRANSITION_BARRIER,
+    ) -> d3d12::D3D12_RESOURCE_BARRIER {
+        let mut barrier = d3d12::D3D12_RESOURCE_BARRIER {
+            Type: d3d12::D3D12_RESOURCE_BARRIER_TYPE_TRANSITION,
+            Flags: d3d12::D3D12_RESOURCE_BARRIER_FLAG_NONE,
+            u: unsafe { mem::zeroed() },
+        };
+
+        *unsafe { barrier.u.Transition_mut() } = transition;
+        barrier
+    }
+
+    fn split_buffer_copy(copies: &mut Vec<Copy>, r: &com::BufferImageCopy, image: &r::ImageBound) {
+        let buffer_width = if r.buffer_width == 0 {
+            r.image_extent.width
+        } else {
+            r.buffer_width
+        };
+        let buffer_height = if r.buffer_height == 0 {
+            r.image_extent.height
+        } else {
+            r.buffer_height
+        };
+        let (plane, format, bytes_per_block) = image.copy_layout(r.image_layers.aspects);
+        let image_extent_aligned = image::Extent {
+            width: up_align(r.image_extent.width, image.block_dim.0 as _),
+            height: up_align(r.image_extent.height, image.block_dim.1 as _),
+            depth: r.image_extent.depth,
+        };
+        let row_pitch = div(buffer_width, image.block_dim.0 as _) * bytes_per_block as u32;
+        let slice_pitch = div(buffer_height, image.block_dim.1 as _) * row_pitch;
+        let is_pitch_aligned = row_pitch % d3d12::D3D12_TEXTURE_DATA_PITCH_ALIGNMENT == 0;
+
+        for layer in r.image_layers.layers.clone() {
+            let img_subresource =
+                image.calc_subresource(r.image_layers.level as _, layer as _, plane);
+            let layer_relative = (layer - r.image_layers.layers.start) as u32;
+            let layer_offset = r.buffer_offset as u64
+                + (layer_relative * slice_pitch * r.image_extent.depth) as u64;
+            let aligned_offset =
+                layer_offset & !(d3d12::D3D12_TEXTURE_DATA_PLACEMENT_ALIGNMENT as u64 - 1);
+            if layer_offset == aligned_offset && is_pitch_aligned {
+                // trivial case: everything is aligned, ready for copying
+                copies.push(Copy {
+                    footprint_offset: aligned_offset,
+                    footprint: image_extent_aligned,
+                    row_pitch,
+                    format,
+                    img_subresource,
+                    img_offset: r.image_offset,
+                    buf_offset: image::Offset::ZERO,
+                    copy_extent: image_extent_aligned,
+                });
+            } else if is_pitch_aligned {
+                // buffer offset is not aligned
+                let row_pitch_texels =
+                    row_pitch / bytes_per_block as u32 * image.block_dim.0 as u32;
+                let gap = (layer_offset - aligned_offset) as i32;
+                let buf_offset = image::Offset {
+                    x: (gap % row_pitch as i32) / bytes_per_block as i32
+                        * image.block_dim.0 as i32,
+                    y: (gap % slice_pitch as i32) / row_pitch as i32 * image.block_dim.1 as i32,
+                    z: gap / slice_pitch as i32,
+                };
+                let footprint = image::Extent {
+                    width: buf_offset.x as u32 + image_extent_aligned.width,
+                    height: buf_offset.y as u32 + image_extent_aligned.height,
+                    depth: buf_offset.z as u32 + image_extent_aligned.depth,
+                };
+                if r.image_extent.width + buf_offset.x as u32 <= row_pitch_texels {
+                    // we can map it to the aligned one and adjust the offsets accordingly
+                    copies.push(Copy {
+                        footprint_offset: aligned_offset,
+                        footprint,
+                        row_pitch,
+                        format,
+                        img_subresource,
+                        img_offset: r.image_offset,
+                        buf_offset,
+                        copy_extent: image_extent_aligned,
+                    });
+                } else {
+                    // split the copy region into 2 that suffice the previous condition
+                    assert!(buf_offset.x as u32 <= row_pitch_texels);
+                    let half = row_pitch_texels - buf_offset.x as u32;
+                    assert!(half <= r.image_extent.width);
+
+                    copies.push(Copy {
+                        footprint_offset: aligned_offset,
+                        footprint: image::Extent {
+                            width: row_pitch_texels,
+                            ..footprint
+                        },
+                        row_pitch,
+                        format,
+                        img_subresource,
+                        img_offset: r.image_offset,
+                        buf_offset,
+                        copy_extent: image::Extent {
+                            width: half,
+                            ..r.image_extent
+                        },
+                    });
+                    copies.push(Copy {
+                        footprint_offset: aligned_offset,
+                        footprint: image::Extent {
+                            width: image_extent_aligned.width - half,
+                            height: footprint.height + image.block_dim.1 as u32,
+                            depth: footprint.depth,
+                        },
+                        row_pitch,
+                        format,
+                        img_subresource,
+                        img_offset: image::Offset {
+                            x: r.image_offset.x + half as i32,
+                            ..r.image_offset
+                        },
+                        buf_offset: image::Offset {
+                            x: 0,
+                            y: buf_offset.y + image.block_dim.1 as i32,
+                            z: buf_offset.z,
+                        },
+                        copy_extent: image::Extent {
+                            width: image_extent_aligned.width - half,
+                            ..image_extent_aligned
+                        },
+                    });
+                }
+            } else {
+                // worst case: row by row copy
+                for z in 0 .. r.image_extent.depth {
+                    for y in 0 .. image_extent_aligned.height / image.block_dim.1 as u32 {
+                        // an image row starts non-aligned
+                        let row_offset = layer_offset
+                            + z as u64 * slice_pitch as u64
+                            + y as u64 * row_pitch as u64;
+                        let aligned_offset = row_offset
+                            & !(d3d12::D3D12_TEXTURE_DATA_PLACEMENT_ALIGNMENT as u64 - 1);
+                        let next_aligned_offset =
+                            aligned_offset + d3d12::D3D12_TEXTURE_DATA_PLACEMENT_ALIGNMENT as u64;
+                        let cut_row_texels = (next_aligned_offset - row_offset)
+                            / bytes_per_block as u64
+                            * image.block_dim.0 as u64;
+                        let cut_width =
+                            cmp::min(image_extent_aligned.width, cut_row_texels as image::Size);
+                        let gap_texels = (row_offset - aligned_offset) as image::Size
+                            / bytes_per_block as image::Size
+                            * image.block_dim.0 as image::Size;
+                        // this is a conservative row pitch that should be compatible with both copies
+                        let max_unaligned_pitch =
+                            (r.image_extent.width + gap_texels) * bytes_per_block as u32;
+                        let row_pitch = (max_unaligned_pitch
+                            | (d3d12::D3D12_TEXTURE_DATA_PITCH_ALIGNMENT - 1))
+                            + 1;
+
+                        copies.push(Copy {
+                            footprint_offset: aligned_offset,
+                            footprint: image::Extent {
+                                width: cut_width + gap_texels,
+                                height: image.block_dim.1 as _,
+                                depth: 1,
+                            },
+                            row_pitch,
+                            format,
+                            img_subresource,
+                            img_offset: image::Offset {
+                                x: r.image_offset.x,
+                                y: r.image_offset.y + image.block_dim.1 as i32 * y as i32,
+                                z: r.image_offset.z + z as i32,
+                            },
+                            buf_offset: image::Offset {
+                                x: gap_texels as i32,
+                                y: 0,
+                                z: 0,
+                            },
+                            copy_extent: image::Extent {
+                                width: cut_width,
+                                height: image.block_dim.1 as _,
+                                depth: 1,
+                            },
+                        });
+
+                        // and if it crosses a pitch alignment - we copy the rest separately
+                        if cut_width >= image_extent_aligned.width {
+                            continue;
+                        }
+                        let leftover = image_extent_aligned.width - cut_width;
+
+                        copies.push(Copy {
+                            footprint_offset: next_aligned_offset,
+                            footprint: image::Extent {
+                                width: leftover,
+                                height: image.block_dim.1 as _,
+                                depth: 1,
+                            },
+                            row_pitch,
+                            format,
+                            img_subresource,
+                            img_offset: image::Offset {
+                                x: r.image_offset.x + cut_width as i32,
+                                y: r.image_offset.y + y as i32 * image.block_dim.1 as i32,
+                                z: r.image_offset.z + z as i32,
+                            },
+                            buf_offset: image::Offset::ZERO,
+                            copy_extent: image::Extent {
+                                width: leftover,
+                                height: image.block_dim.1 as _,
+                                depth: 1,
+                            },
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    fn set_vertex_buffers(&mut self) {
+        let cmd_buffer = &mut self.raw;
+        let vbs_remap = &self.vertex_bindings_remap;
+        let vbs = &self.vertex_buffer_views;
+        let mut last_end_slot = 0;
+        loop {
+            let start_offset = match vbs_remap[last_end_slot ..]
+                .iter()
+                .position(|remap| remap.is_some())
+            {
+                Some(offset) => offset,
+                None => break,
+            };
+
+            let start_slot = last_end_slot + start_offset;
+            let buffers = vbs_remap[start_slot ..]
+                .iter()
+                .take_while(|x| x.is_some())
+                .filter_map(|mapping| {
+                    let mapping = mapping.unwrap();
+                    let view = vbs[mapping.mapped_binding];
+                    // Skip bindings that don't make sense. Since this function is called eagerly,
+                    // we expect it to be called with all the valid inputs prior to drawing.
+                    view.SizeInBytes.checked_sub(mapping.offset).map(|size| {
+                        d3d12::D3D12_VERTEX_BUFFER_VIEW {
+                            BufferLocation: view.BufferLocation + mapping.offset as u64,
+                            SizeInBytes: size,
+                            StrideInBytes: mapping.stride,
+                        }
+                    })
+                })
+                .collect::<SmallVec<[_; MAX_VERTEX_BUFFERS]>>();
+
+            if buffers.is_empty() {
+                last_end_slot = start_slot + 1;
+            } else {
+                let num_views = buffers.len();
+                unsafe {
+                    cmd_buffer.IASetVertexBuffers(
+                        start_slot as _,
+                        num_views as _,
+                        buffers.as_ptr(),
+                    );
+                }
+                last_end_slot = start_slot + num_views;
+            }
+        }
+    }
+}
+
+impl com::RawCommandBuffer<Backend> for CommandBuffer {
+    unsafe fn begin(
+        &mut self,
+        _flags: com::CommandBufferFlags,
+        _info: com::CommandBufferInheritanceInfo<Backend>,
+    ) {
+        // TODO: Implement flags.
+        if let Some(allocator) = self.owned_allocator() {
+            // Command buffer has reset semantics now and doesn't require to be in `Initial` state.
+            allocator.Reset();
+        }
+        self.reset();
+    }
+
+    unsafe fn finish(&mut self) {
+        debug_assert!(
+            self.split_barriers.is_empty(),
+            "Split barriers of {} resources have not been ended",
+            self.split_barriers.len()
+        );
+        self.raw.Close();
+    }
+
+    unsafe fn reset(&mut self, _release_resources: bool) {
+        // Ensure that we have a bijective relation between list and allocator.
+        // This allows to modify the allocator here. Using `reset` requires this by specification.
+        // Other command buffers of the pool keep their recorded commands.
+        let allocator = self
+            .owned_allocator()
+            .expect("Command pool needs to be created with `RESET_INDIVIDUAL`");
+
+        // TODO: `release_resources` should recreate the allocator to give back all memory.
+        allocator.Reset();
+        self.reset();
+    }
+
+    unsafe fn begin_render_pass<T>(
+        &mut self,
+        render_pass: &r::RenderPass,
+        framebuffer: &r::Framebuffer,
+        target_rect: pso::Rect,
+        clear_values: T,
+        _first_subpass: com::SubpassContents,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::ClearValueRaw>,
+    {
+        if self.is_restricted_in_bundle("begin_render_pass") {
+            return;
+        }
+        self.begin_render_pass_impl(
+            render_pass,
+            framebuffer,
+            target_rect,
+            clear_values,
+            RenderPassSplit::empty(),
+        );
+    }
+
+    unsafe fn next_subpass(&mut self, _contents: com::SubpassContents) {
+        if self.is_restricted_in_bundle("next_subpass") {
+            return;
+        }
+        let native = self.pass_cache.as_ref().unwrap().native;
+        if native {
+            self.end_native_render_pass();
+        }
+        self.insert_subpass_barriers(BarrierPoint::Post);
+        self.resolve_attachments();
+
+        self.cur_subpass += 1;
+        self.insert_subpass_barriers(BarrierPoint::Pre);
+        if native {
+            self.begin_native_render_pass();
+            self.clear_subpass_attachments();
+        } else {
+            self.bind_targets();
+        }
+    }
+
+    unsafe fn end_render_pass(&mut self) {
+        if self.is_restricted_in_bundle("end_render_pass") {
+            return;
+        }
+        let (split, native) = {
+            let state = self.pass_cache.as_ref().unwrap();
+            (state.split, state.native)
+        };
+        if native {
+            self.end_native_render_pass();
+        }
+        if split.contains(RenderPassSplit::SUSPENDING) {
+            // Resolves and final transitions are recorded by the resuming part.
+            self.cur_subpass = !0;
+            self.pass_cache = None;
+            return;
+        }
+
+        self.insert_subpass_barriers(BarrierPoint::Post);
+        self.resolve_attachments();
+
+        self.cur_subpass = !0;
+        self.insert_subpass_barriers(BarrierPoint::Pre);
+        self.pass_cache = None;
+    }
+
+    unsafe fn pipeline_barrier<'a, T>(
+        &mut self,
+        _stages: Range<pso::PipelineStage>,
+        _dependencies: memory::Dependencies,
+        barriers: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<memory::Barrier<'a, Backend>>,
+    {
+        if self.is_restricted_in_bundle("pipeline_barrier") {
+            return;
+        }
+        let mut raw_barriers =
+            self.record_barriers(barriers, d3d12::D3D12_RESOURCE_BARRIER_FLAG_NONE);
+
+        // UAV barriers
+        //
+        // TODO: Currently always add a global UAV barrier.
+        //       WAR only requires an execution barrier but D3D12 seems to need
+        //       a UAV barrier for this according to docs. Can we make this better?
+        raw_barriers.push(Self::uav_barrier(ptr::null_mut()));
+
+        self.raw
+            .ResourceBarrier(raw_barriers.len() as _, raw_barriers.as_ptr());
+    }
+
+    unsafe fn clear_image<T>(
+        &mut self,
+        image: &r::Image,
+        layout: image::Layout,
+        color: com::ClearColorRaw,
+        depth_stencil: com::ClearDepthStencilRaw,
+        subresource_ranges: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<image::SubresourceRange>,
+    {
+        if self.is_restricted_in_bundle("clear_image") {
+            return;
+        }
+        let image = image.expect_bound();
+        self.validate_unsplit(image.resource.as_mut_ptr(), "clear_image");
+        let device = self.shared.service_pipes.device;
+
+        let subresources = subresource_ranges
+            .into_iter()
+            .flat_map(|range| {
+                let image::SubresourceRange {
+                    aspects,
+                    levels,
+                    layers,
+                } = range.borrow().clone();
+                levels.flat_map(move |level| {
+                    layers.clone().map(move |layer| (aspects, level, layer))
+                })
+            })
+            .collect::<SmallVec<[_; 4]>>();
+        if subresources.is_empty() {
+            return;
+        }
+
+        let is_color = image.format.surface_desc().aspects.contains(Aspects::COLOR);
+        let target_state = match image_clear_state(image.descriptor.Flags, is_color) {
+            Some(state) => state,
+            None if is_color && !image.clear_cv.is_empty() => {
+                d3d12::D3D12_RESOURCE_STATE_RENDER_TARGET
+            }
+            None => {
+                error!(
+                    "Unable to clear image with usage {:?}, requires `TRANSFER_DST` \
+                     with a renderable format or `STORAGE`",
+                    image.usage
+                );
+                return;
+            }
+        };
+
+        // Transition the cleared subresources from the transfer state into the
+        // state required for the clear and back afterwards.
+        let state = conv::map_image_resource_state(image::Access::TRANSFER_WRITE, layout);
+        let mut barriers = SmallVec::<[_; 4]>::new();
+        if state != target_state {
+            for &(aspects, level, layer) in &subresources {
+                let planes = if aspects.contains(Aspects::STENCIL) && !is_color {
+                    let first = if aspects.contains(Aspects::DEPTH) { 0 } else { 1 };
+                    first .. 2
+                } else {
+                    0 .. 1
+                };
+                for plane in planes {
+                    barriers.push(Self::transition_barrier(
+                        d3d12::D3D12_RESOURCE_TRANSITION_BARRIER {
+                            pResource: image.resource.as_mut_ptr(),
+                            Subresource: image.calc_subresource(level as _, layer as _, plane),
+                            StateBefore: state,
+                            StateAfter: target_state,
+                        },
+                    ));
+                }
+            }
+            self.raw.ResourceBarrier(barriers.len() as _, barriers.as_ptr());
+        }
+
+        let view_info = |aspects, format, level, layer| device::ViewInfo {
+            resource: image.resource,
+            kind: image.kind,
+            caps: image::ViewCapabilities::empty(),
+            view_kind: match image.kind {
+                image::Kind::D1(..) => image::ViewKind::D1Array,
+                image::Kind::D2(..) => image::ViewKind::D2Array,
+                image::Kind::D3(..) => image::ViewKind::D3,
+            },
+            format,
+            component_mapping: IDENTITY_MAPPING,
+            range: image::SubresourceRange {
+                aspects,
+                levels: level .. level + 1,
+                layers: layer .. layer + 1,
+            },
+        };
+
+        match target_state {
+            d3d12::D3D12_RESOURCE_STATE_RENDER_TARGET => {
+                let format = image.default_view_format.unwrap();
+                let mut rtv_pool = descriptors_cpu::HeapLinear::new(
+                    device,
+                    descriptor::HeapType::Rtv,
+                    subresources.len(),
+                );
+                for &(_, level, layer) in &subresources {
+                    let rtv = if level == 0 && !image.clear_cv.is_empty() {
+                        image.clear_cv[layer as usize]
+                    } else {
+                        let rtv = rtv_pool.alloc_handle();
+                        let info = view_info(Aspects::COLOR, format, level, layer);
+                        Device::view_image_as_render_target_impl(device, rtv, info).unwrap();
+                        rtv
+                    };
+                    self.clear_render_target_view(rtv, color, &[]);
+                }
+                rtv_pool.destroy();
+            }
+            d3d12::D3D12_RESOURCE_STATE_UNORDERED_ACCESS => {
+                // UAV clears require the view in a shader visible heap as well
+                // as in a CPU only heap.
+                let format = image.default_view_format.unwrap();
+                let mut cpu_pool = descriptors_cpu::HeapLinear::new(
+                    device,
+                    descriptor::HeapType::CbvSrvUav,
+                    subresources.len(),
+                );
+                let gpu_heap = Device::create_descriptor_heap_impl(
+                    device,
+                    descriptor::HeapType::CbvSrvUav,
+                    true,
+                    subresources.len(),
+                );
+                self.raw.set_descriptor_heaps(&[gpu_heap.raw]);
+                self.temporary_gpu_heaps.push(gpu_heap.raw);
+
+                let is_integer = match image.format.base_format().1 {
+                    format::ChannelType::Uint | format::ChannelType::Sint => true,
+                    _ => false,
+                };
+                for (i, &(_, level, layer)) in subresources.iter().enumerate() {
+                    let cpu = cpu_pool.alloc_handle();
+                    let handle = gpu_heap.at(i as _, 1);
+                    for &view in &[cpu, handle.cpu] {
+                        let info = view_info(Aspects::COLOR, format, level, layer);
+                        Device::view_image_as_storage_impl(device, view, info).unwrap();
+                    }
+                    if is_integer {
+                        self.raw.ClearUnorderedAccessViewUint(
+                            handle.gpu,
+                            cpu,
+                            image.resource.as_mut_ptr(),
+                            &color.uint32,
+                            0,
+                            ptr::null(),
+                        );
+                    } else {
+                        self.raw.ClearUnorderedAccessViewFloat(
+                            handle.gpu,
+                            cpu,
+                            image.resource.as_mut_ptr(),
+                            &color.float32,
+                            0,
+                            ptr::null(),
+                        );
+                    }
+                }
+                cpu_pool.destroy();
+                self.bind_descriptor_heaps();
+            }
+            _ => {
+                let format = conv::map_format_dsv(image.surface_type).unwrap();
+                let mut dsv_pool = descriptors_cpu::HeapLinear::new(
+                    device,
+                    descriptor::HeapType::Dsv,
+                    subresources.len(),
+                );
+                for &(aspects, level, layer) in &subresources {
+                    let depth = if aspects.contains(Aspects::DEPTH) {
+                        Some(depth_stencil.depth)
+                    } else {
+                        None
+                    };
+                    let stencil = if aspects.contains(Aspects::STENCIL) {
+                        Some(depth_stencil.stencil)
+                    } else {
+                        None
+                    };
+                    let dsv = match (level, depth, stencil) {
+                        (0, Some(_), None) if !image.clear_dv.is_empty() => {
+                            image.clear_dv[layer as usize]
+                        }
+                        (0, None, Some(_)) if !image.clear_sv.is_empty() => {
+                            image.clear_sv[layer as usize]
+                        }
+                        _ => {
+                            let dsv = dsv_pool.alloc_handle();
+                            let info = view_info(aspects, format, level, layer);
+                            Device::view_image_as_depth_stencil_impl(
+                                device,
+                                dsv,
+                                info,
+                                d3d12::D3D12_DSV_FLAG_NONE,
+                            )
+                            .unwrap();
+                            dsv
+                        }
+                    };
+                    self.clear_depth_stencil_view(dsv, depth, stencil, &[]);
+                }
+                dsv_pool.destroy();
+            }
+        }
+
+        if !barriers.is_empty() {
+            for barrier in &mut barriers {
+                let transition = barrier.u.Transition_mut();
+                mem::swap(&mut transition.StateBefore, &mut transition.StateAfter);
+            }
+            self.raw.ResourceBarrier(barriers.len() as _, barriers.as_ptr());
+        }
+    }
+
+    unsafe fn clear_attachments<T, U>(&mut self, clears: T, rects: U)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<com::AttachmentClear>,
+        U: IntoIterator,
+        U::Item: Borrow<pso::ClearRect>,
+    {
+        if self.is_restricted_in_bundle("clear_attachments") {
+            return;
+        }
+        let pass_cache = match self.pass_cache {
+            Some(ref cache) => cache,
+            None => panic!("`clear_attachments` can only be called inside a renderpass"),
+        };
+        let sub_pass = &pass_cache.render_pass.subpasses[self.cur_subpass];
+
+        let clear_rects: SmallVec<[pso::ClearRect; 4]> = rects
+            .into_iter()
+            .map(|rect| rect.borrow().clone())
+            .collect();
+
+        let device = self.shared.service_pipes.device;
+
+        for clear in clears {
+            match *clear.borrow() {
+                com::AttachmentClear::Color { index, value } => {
+                    let attachment = {
+                        let rtv_id = sub_pass.color_attachments[index];
+                        pass_cache.framebuffer.attachments[rtv_id.0]
+                    };
+
+                    let mut rtv_pool = descriptors_cpu::HeapLinear::new(
+                        device,
+                        descriptor::HeapType::Rtv,
+                        clear_rects.len(),
+                    );
+
+                    for clear_rect in &clear_rects {
+                        assert!(attachment.layers.0 + clear_rect.layers.end <= attachment.layers.1);
+                        let rect = [get_rect(&clear_rect.rect)];
+
+                        let view_info = device::ViewInfo {
+                            resource: attachment.resource,
+                            kind: attachment.kind,
+                            caps: image::ViewCapabilities::empty(),
+                            view_kind: image::ViewKind::D2Array,
+                            format: attachment.dxgi_format,
+                            component_mapping: IDENTITY_MAPPING,
+                            range: image::SubresourceRange {
+                                aspects: Aspects::COLOR,
+                                levels: attachment.mip_levels.0 .. attachment.mip_levels.1,
+                                layers: attachment.layers.0 + clear_rect.layers.start
+                                    .. attachment.layers.0 + clear_rect.layers.end,
+                            },
+                        };
+                        let rtv = rtv_pool.alloc_handle();
+                        Device::view_image_as_render_target_impl(device, rtv, view_info).unwrap();
+                        self.clear_render_target_view(rtv, value.into(), &rect);
+                    }
+
+                    rtv_pool.destroy();
+                }
+                com::AttachmentClear::DepthStencil { depth, stencil } => {
+                    let attachment = {
+                        let dsv_id = sub_pass.depth_stencil_attachment.unwrap();
+                        pass_cache.framebuffer.attachments[dsv_id.0]
+                    };
+
+                    let mut dsv_pool = descriptors_cpu::HeapLinear::new(
+                        device,
+                        descriptor::HeapType::Dsv,
+                        clear_rects.len(),
+                    );
+
+                    for clear_rect in &clear_rects {
+                        assert!(attachment.layers.0 + clear_rect.layers.end <= attachment.layers.1);
+                        let rect = [get_rect(&clear_rect.rect)];
+
+                        let view_info = device::ViewInfo {
+                            resource: attachment.resource,
+                            kind: attachment.kind,
+                            caps: image::ViewCapabilities::empty(),
+                            view_kind: image::ViewKind::D2Array,
+                            format: attachment.dxgi_format,
+                            component_mapping: IDENTITY_MAPPING,
+                            range: image::SubresourceRange {
+                                aspects: if depth.is_some() {
+                                    Aspects::DEPTH
+                                } else {
+                                    Aspects::empty()
+                                } | if stencil.is_some() {
+                                    Aspects::STENCIL
+                                } else {
+                                    Aspects::empty()
+                                },
+                                levels: attachment.mip_levels.0 .. attachment.mip_levels.1,
+                                layers: attachment.layers.0 + clear_rect.layers.start
+                                    .. attachment.layers.0 + clear_rect.layers.end,
+                            },
+                        };
+                        let dsv = dsv_pool.alloc_handle();
+                        Device::view_image_as_depth_stencil_impl(
+                            device,
+                            dsv,
+                            view_info,
+                            d3d12::D3D12_DSV_FLAG_NONE,
+                        )
+                        .unwrap();
+                        self.clear_depth_stencil_view(dsv, depth, stencil, &rect);
+                    }
+
+                    dsv_pool.destroy();
+                }
+            }
+        }
+    }
+
+    unsafe fn resolve_image<T>(
+        &mut self,
+        src: &r::Image,
+        _src_layout: image::Layout,
+        dst: &r::Image,
+        _dst_layout: image::Layout,
+        regions: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::ImageResolve>,
+    {
+        if self.is_restricted_in_bundle("resolve_image") {
+            return;
+        }
+        let src = src.expect_bound();
+        let dst = dst.expect_bound();
+        self.validate_unsplit(src.resource.as_mut_ptr(), "resolve_image");
+        self.validate_unsplit(dst.resource.as_mut_ptr(), "resolve_image");
+        assert_eq!(src.descriptor.Format, dst.descriptor.Format);
+
+        {
+            // Insert barrier for `COPY_DEST` to `RESOLVE_DEST` as we only expose
+            // `TRANSFER_WRITE` which is used for all copy commands.
+            let transition_barrier =
+                Self::transition_barrier(d3d12::D3D12_RESOURCE_TRANSITION_BARRIER {
+                    pResource: dst.resource.as_mut_ptr(),
+                    Subresource: d3d12::D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES, // TODO: only affected ranges
+                    StateBefore: d3d12::D3D12_RESOURCE_STATE_COPY_DEST,
+                    StateAfter: d3d12::D3D12_RESOURCE_STATE_RESOLVE_DEST,
+                });
+            self.raw.ResourceBarrier(1, &transition_barrier);
+        }
+
+        for region in regions {
+            let r = region.borrow();
+            for layer in 0 .. r.extent.depth as UINT {
+                self.raw.ResolveSubresource(
+                    src.resource.as_mut_ptr(),
+                    src.calc_subresource(
+                        r.src_subresource.level as UINT,
+                        r.src_subresource.layers.start as UINT + layer,
+                        0,
+                    ),
+                    dst.resource.as_mut_ptr(),
+                    dst.calc_subresource(
+                        r.dst_subresource.level as UINT,
+                        r.dst_subresource.layers.start as UINT + layer,
+                        0,
+                    ),
+                    src.descriptor.Format,
+                );
+            }
+        }
+
+        {
+            // Insert barrier for back transition from `RESOLVE_DEST` to `COPY_DEST`.
+            let transition_barrier =
+                Self::transition_barrier(d3d12::D3D12_RESOURCE_TRANSITION_BARRIER {
+                    pResource: dst.resource.as_mut_ptr(),
+                    Subresource: d3d12::D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES, // TODO: only affected ranges
+                    StateBefore: d3d12::D3D12_RESOURCE_STATE_RESOLVE_DEST,
+                    StateAfter: d3d12::D3D12_RESOURCE_STATE_COPY_DEST,
+                });
+            self.raw.ResourceBarrier(1, &transition_barrier);
+        }
+    }
+
+    unsafe fn blit_image<T>(
+        &mut self,
+        src: &r::Image,
+        _src_layout: image::Layout,
+        dst: &r::Image,
+        _dst_layout: image::Layout,
+        filter: image::Filter,
+        regions: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::ImageBlit>,
+    {
+        if self.is_restricted_in_bundle("blit_image") {
+            return;
+        }
+        let device = self.shared.service_pipes.device.clone();
+        let src = src.expect_bound();
+        let dst = dst.expect_bound();
+        self.validate_unsplit(src.resource.as_mut_ptr(), "blit_image");
+        self.validate_unsplit(dst.resource.as_mut_ptr(), "blit_image");
+
+        // TODO: depth or stencil images not supported so far
+
+        // TODO: only supporting 2D images
+        match (src.kind, dst.kind) {
+            (image::Kind::D2(..), image::Kind::D2(..)) => {}
+            _ => unimplemented!(),
+        }
+
+        // Regions without scaling or flipping between images of the same format
+        // are copied directly, everything else is drawn by the blit service pipe.
+        let can_copy = src.default_view_format == dst.default_view_format;
+        let mut src_image = d3d12::D3D12_TEXTURE_COPY_LOCATION {
+            pResource: src.resource.as_mut_ptr(),
+            Type: d3d12::D3D12_TEXTURE_COPY_TYPE_SUBRESOURCE_INDEX,
+            u: mem::zeroed(),
+        };
+        let mut dst_image = d3d12::D3D12_TEXTURE_COPY_LOCATION {
+            pResource: dst.resource.as_mut_ptr(),
+            Type: d3d12::D3D12_TEXTURE_COPY_TYPE_SUBRESOURCE_INDEX,
+            u: mem::zeroed(),
+        };
+
+        let filter = blit_filter(filter);
+
+        struct Instance {
+            rtv: d3d12::D3D12_CPU_DESCRIPTOR_HANDLE,
+            viewport: d3d12::D3D12_VIEWPORT,
+            data: internal::BlitData,
+        };
+        let mut instances = FastHashMap::<internal::BlitKey, Vec<Instance>>::default();
+        let mut barriers = Vec::new();
+        // Sampled subresources of the src image
+        let mut src_subresources = Vec::new();
+
+        for region in regions {
+            let r = region.borrow();
+
+            if let Some(src_box) = blit_copy_box(r).filter(|_| can_copy) {
+                for (src_layer, dst_layer) in r
+                    .src_subresource
+                    .layers
+                    .clone()
+                    .zip(r.dst_subresource.layers.clone())
+                {
+                    *src_image.u.SubresourceIndex_mut() =
+                        src.calc_subresource(r.src_subresource.level as _, src_layer as _, 0);
+                    *dst_image.u.SubresourceIndex_mut() =
+                        dst.calc_subresource(r.dst_subresource.level as _, dst_layer as _, 0);
+                    self.raw.CopyTextureRegion(
+                        &dst_image,
+                        r.dst_bounds.start.x as _,
+                        r.dst_bounds.start.y as _,
+                        r.dst_bounds.start.z as _,
+                        &src_image,
+                        &src_box,
+                    );
+                }
+                continue;
+            }
+
+            let first_layer = r.dst_subresource.layers.start;
+            let num_layers = r.dst_subresource.layers.end - first_layer;
+
+            // WORKAROUND: renderdoc crashes if we destroy the pool too early
+            let rtv_pool = Device::create_descriptor_heap_impl(
+                device,
+                descriptor::HeapType::Rtv,
+                false,
+                num_layers as _,
+            );
+            self.rtv_pools.push(rtv_pool.raw.clone());
+
+            let key = match r.dst_subresource.aspects {
+                format::Aspects::COLOR => {
+                    let format = dst.default_view_format.unwrap();
                     // Create RTVs of the dst image for the miplevel of the current region
                     for i in 0 .. num_layers {
                         let mut desc = d3d12::D3D12_RENDER_TARGET_VIEW_DESC {
@@ -1601,928 +3013,4869 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
                             u: mem::zeroed(),
                         };
 
-                        *desc.u.Texture2DArray_mut() = d3d12::D3D12_TEX2D_ARRAY_RTV {
-                            MipSlice: r.dst_subresource.level as _,
-                            FirstArraySlice: (i + first_layer) as u32,
-                            ArraySize: 1,
-                            PlaneSlice: 0, // TODO
-                        };
+                        *desc.u.Texture2DArray_mut() = d3d12::D3D12_TEX2D_ARRAY_RTV {
+                            MipSlice: r.dst_subresource.level as _,
+                            FirstArraySlice: (i + first_layer) as u32,
+                            ArraySize: 1,
+                            PlaneSlice: 0, // TODO
+                        };
+
+                        let view = rtv_pool.at(i as _, 0).cpu;
+                        device.CreateRenderTargetView(dst.resource.as_mut_ptr(), &desc, view);
+                    }
+
+                    (format, filter)
+                }
+                _ => unimplemented!(),
+            };
+
+            // Take flipping into account
+            let viewport = d3d12::D3D12_VIEWPORT {
+                TopLeftX: cmp::min(r.dst_bounds.start.x, r.dst_bounds.end.x) as _,
+                TopLeftY: cmp::min(r.dst_bounds.start.y, r.dst_bounds.end.y) as _,
+                Width: (r.dst_bounds.end.x - r.dst_bounds.start.x).abs() as _,
+                Height: (r.dst_bounds.end.y - r.dst_bounds.start.y).abs() as _,
+                MinDepth: 0.0,
+                MaxDepth: 1.0,
+            };
+
+            let mut list = instances.entry(key).or_insert(Vec::new());
+
+            for i in 0 .. num_layers {
+                let src_layer = r.src_subresource.layers.start + i;
+                let src_level = r.src_subresource.level;
+                // Screen space triangle blitting
+                let data = blit_data(r, src.kind.level_extent(src_level), src_layer);
+                let subresource = src.calc_subresource(src_level as _, src_layer as _, 0);
+                if !src_subresources.contains(&subresource) {
+                    src_subresources.push(subresource);
+                }
+
+                list.push(Instance {
+                    rtv: rtv_pool.at(i as _, 0).cpu,
+                    viewport,
+                    data,
+                });
+
+                barriers.push(Self::transition_barrier(
+                    d3d12::D3D12_RESOURCE_TRANSITION_BARRIER {
+                        pResource: dst.resource.as_mut_ptr(),
+                        Subresource: dst.calc_subresource(
+                            r.dst_subresource.level as _,
+                            (first_layer + i) as _,
+                            0,
+                        ),
+                        StateBefore: d3d12::D3D12_RESOURCE_STATE_COPY_DEST,
+                        StateAfter: d3d12::D3D12_RESOURCE_STATE_RENDER_TARGET,
+                    },
+                ));
+            }
+        }
+
+        if instances.is_empty() {
+            return;
+        }
+
+        let (srv_heap, _) = device.create_descriptor_heap(
+            1,
+            descriptor::HeapType::CbvSrvUav,
+            descriptor::HeapFlags::SHADER_VISIBLE,
+            0,
+        );
+        let srv_desc = Device::build_image_as_shader_resource_desc(&ViewInfo {
+            resource: src.resource,
+            kind: src.kind,
+            caps: src.view_caps,
+            view_kind: image::ViewKind::D2Array, // TODO
+            format: src.default_view_format.unwrap(),
+            component_mapping: IDENTITY_MAPPING,
+            range: image::SubresourceRange {
+                aspects: format::Aspects::COLOR, // TODO
+                levels: 0 .. src.descriptor.MipLevels as _,
+                layers: 0 .. src.kind.num_layers(),
+            },
+        })
+        .unwrap();
+        device.CreateShaderResourceView(
+            src.resource.as_mut_ptr(),
+            &srv_desc,
+            srv_heap.start_cpu_descriptor(),
+        );
+        self.raw.set_descriptor_heaps(&[srv_heap]);
+        self.temporary_gpu_heaps.push(srv_heap);
+
+        barriers.extend(src_subresources.into_iter().map(|subresource| {
+            Self::transition_barrier(d3d12::D3D12_RESOURCE_TRANSITION_BARRIER {
+                pResource: src.resource.as_mut_ptr(),
+                Subresource: subresource,
+                StateBefore: d3d12::D3D12_RESOURCE_STATE_COPY_SOURCE,
+                StateAfter: d3d12::D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+            })
+        }));
+
+        // pre barriers
+        self.raw
+            .ResourceBarrier(barriers.len() as _, barriers.as_ptr());
+        // execute blits
+        self.set_internal_graphics_pipeline();
+        for (key, list) in instances {
+            let blit = self.shared.service_pipes.get_blit_2d_color(key);
+            self.raw
+                .IASetPrimitiveTopology(d3dcommon::D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST);
+            self.raw.set_pipeline_state(blit.pipeline);
+            self.raw.set_graphics_root_signature(blit.signature);
+            self.raw
+                .set_graphics_root_descriptor_table(0, srv_heap.start_gpu_descriptor());
+            for inst in list {
+                let scissor = d3d12::D3D12_RECT {
+                    left: inst.viewport.TopLeftX as _,
+                    top: inst.viewport.TopLeftY as _,
+                    right: (inst.viewport.TopLeftX + inst.viewport.Width) as _,
+                    bottom: (inst.viewport.TopLeftY + inst.viewport.Height) as _,
+                };
+                self.raw.RSSetViewports(1, &inst.viewport);
+                self.raw.RSSetScissorRects(1, &scissor);
+                self.raw.SetGraphicsRoot32BitConstants(
+                    1,
+                    (mem::size_of::<internal::BlitData>() / 4) as _,
+                    &inst.data as *const _ as *const _,
+                    0,
+                );
+                self.raw.OMSetRenderTargets(1, &inst.rtv, TRUE, ptr::null());
+                self.raw.draw(3, 1, 0, 0);
+            }
+        }
+        // post barriers
+        for bar in &mut barriers {
+            let mut transition = bar.u.Transition_mut();
+            mem::swap(&mut transition.StateBefore, &mut transition.StateAfter);
+        }
+        self.raw
+            .ResourceBarrier(barriers.len() as _, barriers.as_ptr());
+
+        // Reset states
+        self.raw
+            .RSSetViewports(self.viewport_cache.len() as _, self.viewport_cache.as_ptr());
+        self.raw
+            .RSSetScissorRects(self.scissor_cache.len() as _, self.scissor_cache.as_ptr());
+        if self.primitive_topology != d3dcommon::D3D_PRIMITIVE_TOPOLOGY_UNDEFINED {
+            self.raw.IASetPrimitiveTopology(self.primitive_topology);
+        }
+    }
+
+    unsafe fn bind_index_buffer(&mut self, ibv: buffer::IndexBufferView<Backend>) {
+        let buffer = ibv.buffer.expect_bound();
+        let format = match ibv.index_type {
+            IndexType::U16 => dxgiformat::DXGI_FORMAT_R16_UINT,
+            IndexType::U32 => dxgiformat::DXGI_FORMAT_R32_UINT,
+        };
+        let location = buffer.resource.gpu_virtual_address();
+        self.raw.set_index_buffer(
+            location + ibv.offset,
+            (buffer.requirements.size - ibv.offset) as u32,
+            format,
+        );
+    }
+
+    unsafe fn bind_vertex_buffers<I, T>(&mut self, first_binding: pso::BufferIndex, buffers: I)
+    where
+        I: IntoIterator<Item = (T, buffer::Offset)>,
+        T: Borrow<r::Buffer>,
+    {
+        assert!(first_binding as usize <= MAX_VERTEX_BUFFERS);
+
+        for (view, (buffer, offset)) in self.vertex_buffer_views[first_binding as _ ..]
+            .iter_mut()
+            .zip(buffers)
+        {
+            let b = buffer.borrow().expect_bound();
+            let base = (*b.resource).GetGPUVirtualAddress();
+            view.BufferLocation = base + offset;
+            view.SizeInBytes = (b.requirements.size - offset) as u32;
+        }
+        self.set_vertex_buffers();
+    }
+
+    unsafe fn set_viewports<T>(&mut self, first_viewport: u32, viewports: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<pso::Viewport>,
+    {
+        if self.is_restricted_in_bundle("set_viewports") {
+            return;
+        }
+        let viewports = viewports.into_iter().map(|viewport| {
+            let viewport = viewport.borrow();
+            d3d12::D3D12_VIEWPORT {
+                TopLeftX: viewport.rect.x as _,
+                TopLeftY: viewport.rect.y as _,
+                Width: viewport.rect.w as _,
+                Height: viewport.rect.h as _,
+                MinDepth: viewport.depth.start,
+                MaxDepth: viewport.depth.end,
+            }
+        });
+        let empty = mem::zeroed();
+        if !update_state_cache(&mut self.viewport_cache, first_viewport as _, viewports, empty) {
+            error!(
+                "Viewports exceed the limit of {} viewports",
+                d3d12::D3D12_VIEWPORT_AND_SCISSORRECT_OBJECT_COUNT_PER_PIPELINE
+            );
+            return;
+        }
+
+        self.raw
+            .RSSetViewports(self.viewport_cache.len() as _, self.viewport_cache.as_ptr());
+    }
+
+    unsafe fn set_scissors<T>(&mut self, first_scissor: u32, scissors: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<pso::Rect>,
+    {
+        if self.is_restricted_in_bundle("set_scissors") {
+            return;
+        }
+        let rects = scissors.into_iter().map(|rect| get_rect(rect.borrow()));
+        let empty = mem::zeroed();
+        if !update_state_cache(&mut self.scissor_cache, first_scissor as _, rects, empty) {
+            error!(
+                "Scissors exceed the limit of {} scissors",
+                d3d12::D3D12_VIEWPORT_AND_SCISSORRECT_OBJECT_COUNT_PER_PIPELINE
+            );
+            return;
+        }
+
+        self.raw
+            .RSSetScissorRects(self.scissor_cache.len() as _, self.scissor_cache.as_ptr())
+    }
+
+    unsafe fn set_blend_constants(&mut self, color: pso::ColorValue) {
+        // Blend constants are part of the command list state, they are kept across
+        // pipeline changes and only affect pipelines blending with constant factors.
+        self.raw.set_blend_factor(color);
+        self.blend_constants = Some(color);
+    }
+
+    unsafe fn set_stencil_reference(&mut self, faces: pso::Face, value: pso::StencilValue) {
+        assert!(!faces.is_empty());
+
+        let [front, back] = update_stencil_reference(&mut self.stencil_reference, faces, value);
+        if front == back {
+            self.raw.set_stencil_reference(front as _);
+        } else if let Some(raw8) = self.raw8() {
+            raw8.OMSetFrontAndBackStencilRef(front as _, back as _);
+        } else {
+            // The values are still tracked, setting the other face afterwards to the same
+            // value results in a valid state.
+            error!(
+                "Different front ({}) and back ({}) stencil reference values are not supported",
+                front, back,
+            );
+        }
+    }
+
+    unsafe fn set_stencil_read_mask(&mut self, _faces: pso::Face, _value: pso::StencilValue) {
+        unimplemented!();
+    }
+
+    unsafe fn set_stencil_write_mask(&mut self, _faces: pso::Face, _value: pso::StencilValue) {
+        unimplemented!();
+    }
+
+    unsafe fn set_depth_bounds(&mut self, bounds: Range<f32>) {
+        // `Features::DEPTH_BOUNDS` is only exposed with `ID3D12GraphicsCommandList1` support.
+        self.raw1().OMSetDepthBounds(bounds.start, bounds.end);
+    }
+
+    unsafe fn set_line_width(&mut self, width: f32) {
+        validate_line_width(width);
+    }
+
+    unsafe fn set_depth_bias(&mut self, depth_bias: pso::DepthBias) {
+        // D3D12 bakes the depth bias into the pipeline state, unless the pipeline was
+        // created with dynamic depth bias (see `PhysicalDevice::supports_dynamic_depth_bias`).
+        if !self.dynamic_depth_bias {
+            error!("Depth bias can only be set for pipelines with dynamic depth bias");
+            return;
+        }
+        self.raw9().RSSetDepthBias(
+            depth_bias.const_factor,
+            depth_bias.clamp,
+            depth_bias.slope_factor,
+        );
+    }
+
+    unsafe fn bind_graphics_pipeline(&mut self, pipeline: &r::GraphicsPipeline) {
+        if self.gr_pipeline.needs_root_signature(pipeline.signature) {
+            self.raw.set_graphics_root_signature(pipeline.signature);
+            self.gr_pipeline.num_parameter_slots = pipeline.num_parameter_slots;
+            self.gr_pipeline.root_constants = pipeline.constants.clone();
+            // All slots need to be rebound internally on signature change.
+            self.gr_pipeline.user_data.dirty_all();
+        }
+        self.raw.set_pipeline_state(pipeline.raw);
+        self.raw.IASetPrimitiveTopology(pipeline.topology);
+        self.primitive_topology = pipeline.topology;
+        self.uses_blend_constants = pipeline.uses_blend_constants;
+        self.dynamic_depth_bias = pipeline.dynamic_depth_bias;
+
+        self.active_bindpoint = BindPoint::Graphics { internal: false };
+        self.gr_pipeline.pipeline = Some((pipeline.raw, pipeline.signature));
+        self.vertex_bindings_remap = pipeline.vertex_bindings;
+
+        self.set_vertex_buffers();
+
+        if let Some(ref vp) = pipeline.baked_states.viewport {
+            self.set_viewports(0, iter::once(vp));
+        }
+        if let Some(ref rect) = pipeline.baked_states.scissor {
+            self.set_scissors(0, iter::once(rect));
+        }
+        if let Some(color) = pipeline.baked_states.blend_color {
+            self.set_blend_constants(color);
+        }
+        if let Some(ref bounds) = pipeline.baked_states.depth_bounds {
+            self.set_depth_bounds(bounds.clone());
+        }
+    }
+
+    unsafe fn bind_graphics_descriptor_sets<'a, I, J>(
+        &mut self,
+        layout: &r::PipelineLayout,
+        first_set: usize,
+        sets: I,
+        offsets: J,
+    ) where
+        I: IntoIterator,
+        I::Item: Borrow<r::DescriptorSet>,
+        J: IntoIterator,
+        J::Item: Borrow<com::DescriptorSetOffset>,
+    {
+        let sets = sets.into_iter().collect::<SmallVec<[_; 8]>>();
+        for set in &sets {
+            self.validate_descriptor_set_states(set.borrow(), "bind_graphics_descriptor_sets");
+        }
+        let heaps = self.gr_pipeline.bind_descriptor_sets(
+            layout,
+            first_set,
+            sets,
+            offsets,
+            self.active_descriptor_heaps,
+        );
+        // Setting descriptor heaps may flush the pipeline on some hardware, only change them
+        // if needed. Internal operations overriding the heaps restore them on their own.
+        if heaps != self.active_descriptor_heaps {
+            self.active_descriptor_heaps = heaps;
+            self.bind_descriptor_heaps();
+        }
+    }
+
+    unsafe fn bind_compute_pipeline(&mut self, pipeline: &r::ComputePipeline) {
+        if self.comp_pipeline.needs_root_signature(pipeline.signature) {
+            self.raw.set_compute_root_signature(pipeline.signature);
+            self.comp_pipeline.num_parameter_slots = pipeline.num_parameter_slots;
+            self.comp_pipeline.root_constants = pipeline.constants.clone();
+            // All slots need to be rebound internally on signature change.
+            self.comp_pipeline.user_data.dirty_all();
+        }
+        self.raw.set_pipeline_state(pipeline.raw);
+
+        self.active_bindpoint = BindPoint::Compute;
+        self.comp_pipeline.pipeline = Some((pipeline.raw, pipeline.signature));
+    }
+
+    unsafe fn bind_compute_descriptor_sets<I, J>(
+        &mut self,
+        layout: &r::PipelineLayout,
+        first_set: usize,
+        sets: I,
+        offsets: J,
+    ) where
+        I: IntoIterator,
+        I::Item: Borrow<r::DescriptorSet>,
+        J: IntoIterator,
+        J::Item: Borrow<com::DescriptorSetOffset>,
+    {
+        let sets = sets.into_iter().collect::<SmallVec<[_; 8]>>();
+        for set in &sets {
+            self.validate_descriptor_set_states(set.borrow(), "bind_compute_descriptor_sets");
+        }
+        let heaps = self.comp_pipeline.bind_descriptor_sets(
+            layout,
+            first_set,
+            sets,
+            offsets,
+            self.active_descriptor_heaps,
+        );
+        // Setting descriptor heaps may flush the pipeline on some hardware, only change them
+        // if needed. Internal operations overriding the heaps restore them on their own.
+        if heaps != self.active_descriptor_heaps {
+            self.active_descriptor_heaps = heaps;
+            self.bind_descriptor_heaps();
+        }
+    }
+
+    unsafe fn dispatch(&mut self, count: WorkGroupCount) {
+        self.set_compute_bind_point();
+        self.raw.dispatch(count);
+    }
+
+    unsafe fn dispatch_indirect(&mut self, buffer: &r::Buffer, offset: buffer::Offset) {
+        let buffer = buffer.expect_bound();
+        if offset % 4 != 0 {
+            error!("Indirect dispatch offset {} must be a multiple of 4", offset);
+            return;
+        }
+        if !buffer.usage.contains(buffer::Usage::INDIRECT) {
+            error!("Indirect dispatch requires a buffer created with usage `INDIRECT`");
+            return;
+        }
+        self.set_compute_bind_point();
+        let signature = self.shared.signatures.dispatch;
+        self.execute_indirect(signature, 1, buffer, offset, None);
+    }
+
+    unsafe fn fill_buffer<R>(&mut self, buffer: &r::Buffer, range: R, data: u32)
+    where
+        R: RangeArg<buffer::Offset>,
+    {
+        if self.is_restricted_in_bundle("fill_buffer") {
+            return;
+        }
+        let buffer = buffer.expect_bound();
+        self.validate_unsplit(buffer.resource.as_mut_ptr(), "fill_buffer");
+        assert!(
+            buffer.clear_uav.is_some(),
+            "Buffer needs to be created with usage `TRANSFER_DST`"
+        );
+        let bytes_per_unit = 4;
+        let start = *range.start().unwrap_or(&0) as i32;
+        let end = *range.end().unwrap_or(&(buffer.requirements.size as u64)) as i32;
+        if start % 4 != 0 || end % 4 != 0 {
+            warn!("Fill buffer bounds have to be multiples of 4");
+        }
+        let _rect = d3d12::D3D12_RECT {
+            left: start / bytes_per_unit,
+            top: 0,
+            right: end / bytes_per_unit,
+            bottom: 1,
+        };
+
+        // Insert barrier for `COPY_DEST` to `UNORDERED_ACCESS` as we use
+        // `TRANSFER_WRITE` for all clear commands.
+        let pre_barrier = Self::transition_barrier(d3d12::D3D12_RESOURCE_TRANSITION_BARRIER {
+            pResource: buffer.resource.as_mut_ptr(),
+            Subresource: d3d12::D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+            StateBefore: d3d12::D3D12_RESOURCE_STATE_COPY_DEST,
+            StateAfter: d3d12::D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+        });
+        self.raw.ResourceBarrier(1, &pre_barrier);
+
+        error!("fill_buffer currently unimplemented");
+        // TODO: GPU handle must be in the current heap. Atm we use a CPU descriptor heap for allocation
+        //       which is not shader visible.
+        /*
+        let handle = buffer.clear_uav.unwrap();
+        unsafe {
+            self.raw.ClearUnorderedAccessViewUint(
+                handle.gpu,
+                handle.cpu,
+                buffer.resource,
+                &[data as UINT; 4],
+                1,
+                &rect as *const _,
+            );
+        }
+        */
+
+        let post_barrier = Self::transition_barrier(d3d12::D3D12_RESOURCE_TRANSITION_BARRIER {
+            pResource: buffer.resource.as_mut_ptr(),
+            Subresource: d3d12::D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+            StateBefore: d3d12::D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+            StateAfter: d3d12::D3D12_RESOURCE_STATE_COPY_DEST,
+        });
+        self.raw.ResourceBarrier(1, &post_barrier);
+    }
+
+    unsafe fn update_buffer(&mut self, _buffer: &r::Buffer, _offset: buffer::Offset, _data: &[u8]) {
+        unimplemented!()
+    }
+
+    unsafe fn copy_buffer<T>(&mut self, src: &r::Buffer, dst: &r::Buffer, regions: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<com::BufferCopy>,
+    {
+        if self.is_restricted_in_bundle("copy_buffer") {
+            return;
+        }
+        let src = src.expect_bound();
+        let dst = dst.expect_bound();
+        self.validate_unsplit(src.resource.as_mut_ptr(), "copy_buffer");
+        self.validate_unsplit(dst.resource.as_mut_ptr(), "copy_buffer");
+        self.validate_buffer_state(src, d3d12::D3D12_RESOURCE_STATE_COPY_SOURCE, "copy_buffer");
+        self.validate_buffer_state(dst, d3d12::D3D12_RESOURCE_STATE_COPY_DEST, "copy_buffer");
+        // copy each region
+        for region in regions {
+            let region = region.borrow();
+            self.raw.CopyBufferRegion(
+                dst.resource.as_mut_ptr(),
+                region.dst as _,
+                src.resource.as_mut_ptr(),
+                region.src as _,
+                region.size as _,
+            );
+        }
+
+        // TODO: Optimization: Copy whole resource if possible
+    }
+
+    unsafe fn copy_image<T>(
+        &mut self,
+        src: &r::Image,
+        _: image::Layout,
+        dst: &r::Image,
+        _: image::Layout,
+        regions: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::ImageCopy>,
+    {
+        if self.is_restricted_in_bundle("copy_image") {
+            return;
+        }
+        let src = src.expect_bound();
+        let dst = dst.expect_bound();
+        self.validate_unsplit(src.resource.as_mut_ptr(), "copy_image");
+        self.validate_unsplit(dst.resource.as_mut_ptr(), "copy_image");
+        let mut src_image = d3d12::D3D12_TEXTURE_COPY_LOCATION {
+            pResource: src.resource.as_mut_ptr(),
+            Type: d3d12::D3D12_TEXTURE_COPY_TYPE_SUBRESOURCE_INDEX,
+            u: mem::zeroed(),
+        };
+        let mut dst_image = d3d12::D3D12_TEXTURE_COPY_LOCATION {
+            pResource: dst.resource.as_mut_ptr(),
+            Type: d3d12::D3D12_TEXTURE_COPY_TYPE_SUBRESOURCE_INDEX,
+            u: mem::zeroed(),
+        };
+
+        let device = self.shared.service_pipes.device.clone();
+        let src_desc = src.surface_type.desc();
+        let dst_desc = dst.surface_type.desc();
+        if src_desc.bits != dst_desc.bits {
+            error!(
+                "Unable to copy between images of incompatible formats: {:?} -> {:?}",
+                src.surface_type, dst.surface_type
+            );
+            return;
+        }
+        //Note: Direct3D 10.1 enables copies between prestructured-typed textures
+        // and block-compressed textures of the same bit widths.
+        // Formats of the same typeless family are copied directly.
+        let do_alias = !conv::is_same_typeless_family(src.surface_type, dst.surface_type)
+            && src_desc.is_compressed() == dst_desc.is_compressed();
+
+        if do_alias {
+            // D3D12 only permits changing the channel type for copies,
+            // similarly to how it allows the views to be created.
+
+            // create an aliased resource to the source
+            let mut alias = native::Resource::null();
+            let desc = d3d12::D3D12_RESOURCE_DESC {
+                Format: dst.descriptor.Format,
+                ..src.descriptor.clone()
+            };
+            let (heap_ptr, offset) = match src.place {
+                r::Place::SwapChain => {
+                    error!("Unable to copy from a swapchain image with format conversion: {:?} -> {:?}",
+                        src.descriptor.Format, dst.descriptor.Format);
+                    return;
+                }
+                r::Place::Committed | r::Place::Reserved => {
+                    error!("Unable to copy from a committed or sparse image with format conversion: {:?} -> {:?}",
+                        src.descriptor.Format, dst.descriptor.Format);
+                    return;
+                }
+                r::Place::Heap { ref raw, offset } => (raw.as_mut_ptr(), offset),
+            };
+            assert_eq!(
+                winerror::S_OK,
+                device.CreatePlacedResource(
+                    heap_ptr,
+                    offset,
+                    &desc,
+                    d3d12::D3D12_RESOURCE_STATE_COMMON,
+                    ptr::null(),
+                    &d3d12::ID3D12Resource::uuidof(),
+                    alias.mut_void(),
+                )
+            );
+            src_image.pResource = alias.as_mut_ptr();
+            self.retained_resources.push(alias);
+
+            // signal the aliasing transition
+            let sub_barrier = d3d12::D3D12_RESOURCE_ALIASING_BARRIER {
+                pResourceBefore: src.resource.as_mut_ptr(),
+                pResourceAfter: src_image.pResource,
+            };
+            let mut barrier = d3d12::D3D12_RESOURCE_BARRIER {
+                Type: d3d12::D3D12_RESOURCE_BARRIER_TYPE_ALIASING,
+                Flags: d3d12::D3D12_RESOURCE_BARRIER_FLAG_NONE,
+                u: mem::zeroed(),
+            };
+            *barrier.u.Aliasing_mut() = sub_barrier;
+            self.raw.ResourceBarrier(1, &barrier as *const _);
+        }
+
+        for region in regions {
+            let r = region.borrow();
+            debug_assert_eq!(
+                r.src_subresource.layers.len(),
+                r.dst_subresource.layers.len()
+            );
+            let src_box = d3d12::D3D12_BOX {
+                left: r.src_offset.x as _,
+                top: r.src_offset.y as _,
+                right: (r.src_offset.x + r.extent.width as i32) as _,
+                bottom: (r.src_offset.y + r.extent.height as i32) as _,
+                front: r.src_offset.z as _,
+                back: (r.src_offset.z + r.extent.depth as i32) as _,
+            };
+
+            for (src_layer, dst_layer) in r
+                .src_subresource
+                .layers
+                .clone()
+                .zip(r.dst_subresource.layers.clone())
+            {
+                *src_image.u.SubresourceIndex_mut() =
+                    src.calc_subresource(r.src_subresource.level as _, src_layer as _, 0);
+                *dst_image.u.SubresourceIndex_mut() =
+                    dst.calc_subresource(r.dst_subresource.level as _, dst_layer as _, 0);
+                self.raw.CopyTextureRegion(
+                    &dst_image,
+                    r.dst_offset.x as _,
+                    r.dst_offset.y as _,
+                    r.dst_offset.z as _,
+                    &src_image,
+                    &src_box,
+                );
+            }
+        }
+
+        if do_alias {
+            // signal the aliasing transition - back to the original
+            let sub_barrier = d3d12::D3D12_RESOURCE_ALIASING_BARRIER {
+                pResourceBefore: src_image.pResource,
+                pResourceAfter: src.resource.as_mut_ptr(),
+            };
+            let mut barrier = d3d12::D3D12_RESOURCE_BARRIER {
+                Type: d3d12::D3D12_RESOURCE_BARRIER_TYPE_ALIASING,
+                Flags: d3d12::D3D12_RESOURCE_BARRIER_FLAG_NONE,
+                u: mem::zeroed(),
+            };
+            *barrier.u.Aliasing_mut() = sub_barrier;
+            self.raw.ResourceBarrier(1, &barrier as *const _);
+        }
+    }
+
+    unsafe fn copy_buffer_to_image<T>(
+        &mut self,
+        buffer: &r::Buffer,
+        image: &r::Image,
+        _: image::Layout,
+        regions: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::BufferImageCopy>,
+    {
+        if self.is_restricted_in_bundle("copy_buffer_to_image") {
+            return;
+        }
+        let buffer = buffer.expect_bound();
+        let image = image.expect_bound();
+        self.validate_unsplit(buffer.resource.as_mut_ptr(), "copy_buffer_to_image");
+        self.validate_unsplit(image.resource.as_mut_ptr(), "copy_buffer_to_image");
+        self.validate_buffer_state(
+            buffer,
+            d3d12::D3D12_RESOURCE_STATE_COPY_SOURCE,
+            "copy_buffer_to_image",
+        );
+        assert!(self.copies.is_empty());
+
+        for region in regions {
+            let r = region.borrow();
+            let alignment = image.buffer_copy_alignment(r.image_layers.aspects);
+            if !is_buffer_copy_aligned(r.buffer_offset, alignment) {
+                error!(
+                    "copy_buffer_to_image: buffer offset {} is not aligned to {} bytes, \
+                     the region is skipped",
+                    r.buffer_offset, alignment
+                );
+                continue;
+            }
+            Self::split_buffer_copy(&mut self.copies, r, image);
+        }
+
+        if self.copies.is_empty() {
+            return;
+        }
+
+        let mut src = d3d12::D3D12_TEXTURE_COPY_LOCATION {
+            pResource: buffer.resource.as_mut_ptr(),
+            Type: d3d12::D3D12_TEXTURE_COPY_TYPE_PLACED_FOOTPRINT,
+            u: mem::zeroed(),
+        };
+        let mut dst = d3d12::D3D12_TEXTURE_COPY_LOCATION {
+            pResource: image.resource.as_mut_ptr(),
+            Type: d3d12::D3D12_TEXTURE_COPY_TYPE_SUBRESOURCE_INDEX,
+            u: mem::zeroed(),
+        };
+
+        for c in self.copies.drain(..) {
+            let src_box = d3d12::D3D12_BOX {
+                left: c.buf_offset.x as u32,
+                top: c.buf_offset.y as u32,
+                right: c.buf_offset.x as u32 + c.copy_extent.width,
+                bottom: c.buf_offset.y as u32 + c.copy_extent.height,
+                front: c.buf_offset.z as u32,
+                back: c.buf_offset.z as u32 + c.copy_extent.depth,
+            };
+            let footprint = d3d12::D3D12_PLACED_SUBRESOURCE_FOOTPRINT {
+                Offset: c.footprint_offset,
+                Footprint: d3d12::D3D12_SUBRESOURCE_FOOTPRINT {
+                    Format: c.format,
+                    Width: c.footprint.width,
+                    Height: c.footprint.height,
+                    Depth: c.footprint.depth,
+                    RowPitch: c.row_pitch,
+                },
+            };
+            *src.u.PlacedFootprint_mut() = footprint;
+            *dst.u.SubresourceIndex_mut() = c.img_subresource;
+            self.raw.CopyTextureRegion(
+                &dst,
+                c.img_offset.x as _,
+                c.img_offset.y as _,
+                c.img_offset.z as _,
+                &src,
+                &src_box,
+            );
+        }
+    }
+
+    unsafe fn copy_image_to_buffer<T>(
+        &mut self,
+        image: &r::Image,
+        _: image::Layout,
+        buffer: &r::Buffer,
+        regions: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::BufferImageCopy>,
+    {
+        if self.is_restricted_in_bundle("copy_image_to_buffer") {
+            return;
+        }
+        let image = image.expect_bound();
+        let buffer = buffer.expect_bound();
+        self.validate_unsplit(image.resource.as_mut_ptr(), "copy_image_to_buffer");
+        self.validate_unsplit(buffer.resource.as_mut_ptr(), "copy_image_to_buffer");
+        self.validate_buffer_state(
+            buffer,
+            d3d12::D3D12_RESOURCE_STATE_COPY_DEST,
+            "copy_image_to_buffer",
+        );
+        assert!(self.copies.is_empty());
+
+        for region in regions {
+            let r = region.borrow();
+            let alignment = image.buffer_copy_alignment(r.image_layers.aspects);
+            if !is_buffer_copy_aligned(r.buffer_offset, alignment) {
+                error!(
+                    "copy_image_to_buffer: buffer offset {} is not aligned to {} bytes, \
+                     the region is skipped",
+                    r.buffer_offset, alignment
+                );
+                continue;
+            }
+            Self::split_buffer_copy(&mut self.copies, r, image);
+        }
+
+        if self.copies.is_empty() {
+            return;
+        }
+
+        let mut src = d3d12::D3D12_TEXTURE_COPY_LOCATION {
+            pResource: image.resource.as_mut_ptr(),
+            Type: d3d12::D3D12_TEXTURE_COPY_TYPE_SUBRESOURCE_INDEX,
+            u: mem::zeroed(),
+        };
+        let mut dst = d3d12::D3D12_TEXTURE_COPY_LOCATION {
+            pResource: buffer.resource.as_mut_ptr(),
+            Type: d3d12::D3D12_TEXTURE_COPY_TYPE_PLACED_FOOTPRINT,
+            u: mem::zeroed(),
+        };
+
+        for c in self.copies.drain(..) {
+            let src_box = d3d12::D3D12_BOX {
+                left: c.img_offset.x as u32,
+                top: c.img_offset.y as u32,
+                right: c.img_offset.x as u32 + c.copy_extent.width,
+                bottom: c.img_offset.y as u32 + c.copy_extent.height,
+                front: c.img_offset.z as u32,
+                back: c.img_offset.z as u32 + c.copy_extent.depth,
+            };
+            let footprint = d3d12::D3D12_PLACED_SUBRESOURCE_FOOTPRINT {
+                Offset: c.footprint_offset,
+                Footprint: d3d12::D3D12_SUBRESOURCE_FOOTPRINT {
+                    Format: c.format,
+                    Width: c.footprint.width,
+                    Height: c.footprint.height,
+                    Depth: c.footprint.depth,
+                    RowPitch: c.row_pitch,
+                },
+            };
+            *dst.u.PlacedFootprint_mut() = footprint;
+            *src.u.SubresourceIndex_mut() = c.img_subresource;
+            self.raw.CopyTextureRegion(
+                &dst,
+                c.buf_offset.x as _,
+                c.buf_offset.y as _,
+                c.buf_offset.z as _,
+                &src,
+                &src_box,
+            );
+        }
+    }
+
+    unsafe fn draw(&mut self, vertices: Range<VertexCount>, instances: Range<InstanceCount>) {
+        self.set_graphics_bind_point();
+        self.raw.draw(
+            vertices.end - vertices.start,
+            instances.end - instances.start,
+            vertices.start,
+            instances.start,
+        );
+    }
+
+    unsafe fn draw_indexed(
+        &mut self,
+        indices: Range<IndexCount>,
+        base_vertex: VertexOffset,
+        instances: Range<InstanceCount>,
+    ) {
+        self.set_graphics_bind_point();
+        self.raw.draw_indexed(
+            indices.end - indices.start,
+            instances.end - instances.start,
+            indices.start,
+            base_vertex,
+            instances.start,
+        );
+    }
+
+    unsafe fn draw_indirect(
+        &mut self,
+        buffer: &r::Buffer,
+        offset: buffer::Offset,
+        draw_count: DrawCount,
+        stride: u32,
+    ) {
+        assert_eq!(stride, 16);
+        let buffer = buffer.expect_bound();
+        self.set_graphics_bind_point();
+        let signature = self.shared.signatures.draw;
+        self.execute_indirect(signature, draw_count, buffer, offset, None);
+    }
+
+    unsafe fn draw_indexed_indirect(
+        &mut self,
+        buffer: &r::Buffer,
+        offset: buffer::Offset,
+        draw_count: DrawCount,
+        stride: u32,
+    ) {
+        assert_eq!(stride, 20);
+        let buffer = buffer.expect_bound();
+        self.set_graphics_bind_point();
+        let signature = self.shared.signatures.draw_indexed;
+        self.execute_indirect(signature, draw_count, buffer, offset, None);
+    }
+
+    unsafe fn set_event(&mut self, event: &r::Event, _: pso::PipelineStage) {
+        if self.is_restricted_in_bundle("set_event") {
+            return;
+        }
+        self.event_signals.push((event.raw, 1));
+    }
+
+    unsafe fn reset_event(&mut self, event: &r::Event, _: pso::PipelineStage) {
+        if self.is_restricted_in_bundle("reset_event") {
+            return;
+        }
+        self.event_signals.push((event.raw, 0));
+    }
+
+    unsafe fn wait_events<'a, I, J>(
+        &mut self,
+        events: I,
+        stages: Range<pso::PipelineStage>,
+        barriers: J,
+    ) where
+        I: IntoIterator,
+        I::Item: Borrow<r::Event>,
+        J: IntoIterator,
+        J::Item: Borrow<memory::Barrier<'a, Backend>>,
+    {
+        if self.is_restricted_in_bundle("wait_events") {
+            return;
+        }
+        for event in events {
+            let raw = event.borrow().raw;
+            // Events set earlier in this command buffer, and not reset since, are satisfied
+            // within the command list: the barrier below orders the commands recorded before
+            // the set against the ones after the wait. Waiting on the queue would deadlock,
+            // as the signal is only issued once the whole list completed.
+            let set_locally = self
+                .event_signals
+                .iter()
+                .rev()
+                .find(|&&(fence, _)| fence.as_mut_ptr() == raw.as_mut_ptr())
+                .map_or(false, |&(_, value)| value == 1);
+            if !set_locally {
+                self.event_waits.push(raw);
+            }
+        }
+        self.pipeline_barrier(stages, memory::Dependencies::empty(), barriers);
+    }
+
+    unsafe fn begin_query(&mut self, query: query::Query<Backend>, flags: query::ControlFlags) {
+        if self.is_restricted_in_bundle("begin_query") {
+            return;
+        }
+        let query_ty = match query.pool.ty {
+            native::query::HeapType::Occlusion => {
+                if flags.contains(query::ControlFlags::PRECISE) {
+                    self.occlusion_query = Some(OcclusionQuery::Precise(query.id));
+                    d3d12::D3D12_QUERY_TYPE_OCCLUSION
+                } else {
+                    // Default to binary occlusion as it might be faster due to early depth/stencil
+                    // tests.
+                    self.occlusion_query = Some(OcclusionQuery::Binary(query.id));
+                    d3d12::D3D12_QUERY_TYPE_BINARY_OCCLUSION
+                }
+            }
+            native::query::HeapType::Timestamp => panic!("Timestap queries are issued via "),
+            native::query::HeapType::PipelineStatistics => {
+                self.pipeline_stats_query = Some(query.id);
+                d3d12::D3D12_QUERY_TYPE_PIPELINE_STATISTICS
+            }
+            _ => unreachable!(),
+        };
+
+        self.raw
+            .BeginQuery(query.pool.raw.as_mut_ptr(), query_ty, query.id);
+    }
 
-                        let view = rtv_pool.at(i as _, 0).cpu;
-                        device.CreateRenderTargetView(dst.resource.as_mut_ptr(), &desc, view);
-                    }
+    unsafe fn end_query(&mut self, query: query::Query<Backend>) {
+        if self.is_restricted_in_bundle("end_query") {
+            return;
+        }
+        let id = query.id;
+        let query_ty = match query.pool.ty {
+            native::query::HeapType::Occlusion
+                if self.occlusion_query == Some(OcclusionQuery::Precise(id)) =>
+            {
+                self.occlusion_query = None;
+                d3d12::D3D12_QUERY_TYPE_OCCLUSION
+            }
+            native::query::HeapType::Occlusion
+                if self.occlusion_query == Some(OcclusionQuery::Binary(id)) =>
+            {
+                self.occlusion_query = None;
+                d3d12::D3D12_QUERY_TYPE_BINARY_OCCLUSION
+            }
+            native::query::HeapType::PipelineStatistics
+                if self.pipeline_stats_query == Some(id) =>
+            {
+                self.pipeline_stats_query = None;
+                d3d12::D3D12_QUERY_TYPE_PIPELINE_STATISTICS
+            }
+            _ => panic!("Missing `begin_query` call for query: {:?}", query),
+        };
 
-                    (format, filter)
-                }
-                _ => unimplemented!(),
-            };
+        self.raw.EndQuery(query.pool.raw.as_mut_ptr(), query_ty, id);
+        self.resolve_query(query.pool, query_ty, id);
+    }
 
-            // Take flipping into account
-            let viewport = d3d12::D3D12_VIEWPORT {
-                TopLeftX: cmp::min(r.dst_bounds.start.x, r.dst_bounds.end.x) as _,
-                TopLeftY: cmp::min(r.dst_bounds.start.y, r.dst_bounds.end.y) as _,
-                Width: (r.dst_bounds.end.x - r.dst_bounds.start.x).abs() as _,
-                Height: (r.dst_bounds.end.y - r.dst_bounds.start.y).abs() as _,
-                MinDepth: 0.0,
-                MaxDepth: 1.0,
-            };
+    unsafe fn reset_query_pool(&mut self, pool: &r::QueryPool, queries: Range<query::Id>) {
+        if self.is_restricted_in_bundle("reset_query_pool") {
+            return;
+        }
+        // vkCmdResetQueryPool sets the queries to `unavailable` but the specification
+        // doesn't state an affect on the `active` state. Every queries at the end of the command
+        // buffer must be made inactive, which can only be done with EndQuery.
+        // Therefore, every `begin_query` must follow a `end_query` state, the resulting values
+        // after calling are undefined.
+        //
+        // Only the availability needs to be cleared, copied from the zeroes following
+        // the first value of the availability buffer.
+        if queries.start < queries.end {
+            self.raw.CopyBufferRegion(
+                pool.readback.as_mut_ptr(),
+                pool.availability_offset(queries.start),
+                pool.availability.as_mut_ptr(),
+                r::QUERY_AVAILABILITY_SIZE,
+                (queries.end - queries.start) as u64 * r::QUERY_AVAILABILITY_SIZE,
+            );
+        }
+    }
 
-            let mut list = instances.entry(key).or_insert(Vec::new());
+    unsafe fn copy_query_pool_results(
+        &mut self,
+        _pool: &r::QueryPool,
+        _queries: Range<query::Id>,
+        _buffer: &r::Buffer,
+        _offset: buffer::Offset,
+        _stride: buffer::Offset,
+        _flags: query::ResultFlags,
+    ) {
+        if self.is_restricted_in_bundle("copy_query_pool_results") {
+            return;
+        }
+        unimplemented!()
+    }
 
-            for i in 0 .. num_layers {
-                let src_layer = r.src_subresource.layers.start + i;
-                // Screen space triangle blitting
-                let data = {
-                    // Image extents, layers are treated as depth
-                    let (sx, dx) = if r.dst_bounds.start.x > r.dst_bounds.end.x {
-                        (
-                            r.src_bounds.end.x,
-                            r.src_bounds.start.x - r.src_bounds.end.x,
-                        )
-                    } else {
-                        (
-                            r.src_bounds.start.x,
-                            r.src_bounds.end.x - r.src_bounds.start.x,
-                        )
-                    };
-                    let (sy, dy) = if r.dst_bounds.start.y > r.dst_bounds.end.y {
-                        (
-                            r.src_bounds.end.y,
-                            r.src_bounds.start.y - r.src_bounds.end.y,
-                        )
-                    } else {
-                        (
-                            r.src_bounds.start.y,
-                            r.src_bounds.end.y - r.src_bounds.start.y,
-                        )
-                    };
-                    let image::Extent { width, height, .. } =
-                        src.kind.level_extent(r.src_subresource.level);
-
-                    internal::BlitData {
-                        src_offset: [sx as f32 / width as f32, sy as f32 / height as f32],
-                        src_extent: [dx as f32 / width as f32, dy as f32 / height as f32],
-                        layer: src_layer as f32,
-                        level: r.src_subresource.level as _,
-                    }
-                };
+    unsafe fn write_timestamp(&mut self, _: pso::PipelineStage, query: query::Query<Backend>) {
+        if self.is_restricted_in_bundle("write_timestamp") {
+            return;
+        }
+        self.raw.EndQuery(
+            query.pool.raw.as_mut_ptr(),
+            d3d12::D3D12_QUERY_TYPE_TIMESTAMP,
+            query.id,
+        );
+        self.resolve_query(query.pool, d3d12::D3D12_QUERY_TYPE_TIMESTAMP, query.id);
+    }
 
-                list.push(Instance {
-                    rtv: rtv_pool.at(i as _, 0).cpu,
-                    viewport,
-                    data,
-                });
+    unsafe fn push_graphics_constants(
+        &mut self,
+        _layout: &r::PipelineLayout,
+        _stages: pso::ShaderStageFlags,
+        offset: u32,
+        constants: &[u32],
+    ) {
+        assert!(offset % 4 == 0);
+        self.gr_pipeline
+            .user_data
+            .set_constants(offset as usize / 4, constants);
+    }
 
-                barriers.push(Self::transition_barrier(
-                    d3d12::D3D12_RESOURCE_TRANSITION_BARRIER {
-                        pResource: dst.resource.as_mut_ptr(),
-                        Subresource: dst.calc_subresource(
-                            r.dst_subresource.level as _,
-                            (first_layer + i) as _,
-                            0,
-                        ),
-                        StateBefore: d3d12::D3D12_RESOURCE_STATE_COPY_DEST,
-                        StateAfter: d3d12::D3D12_RESOURCE_STATE_RENDER_TARGET,
-                    },
-                ));
+    unsafe fn push_compute_constants(
+        &mut self,
+        _layout: &r::PipelineLayout,
+        offset: u32,
+        constants: &[u32],
+    ) {
+        assert!(offset % 4 == 0);
+        self.comp_pipeline
+            .user_data
+            .set_constants(offset as usize / 4, constants);
+    }
+
+    unsafe fn execute_commands<'a, T, I>(&mut self, cmd_buffers: I)
+    where
+        T: 'a + Borrow<CommandBuffer>,
+        I: IntoIterator<Item = &'a T>,
+    {
+        if self.is_restricted_in_bundle("execute_commands") {
+            return;
+        }
+        for cmd_buf in cmd_buffers {
+            let cmd_buf = cmd_buf.borrow();
+            if cmd_buf.level != com::RawLevel::Secondary {
+                error!("Only secondary command buffers can be executed");
+                continue;
             }
+            self.raw.ExecuteBundle(cmd_buf.raw.as_mut_ptr());
         }
 
-        // pre barriers
-        self.raw
-            .ResourceBarrier(barriers.len() as _, barriers.as_ptr());
-        // execute blits
-        self.set_internal_graphics_pipeline();
-        for (key, list) in instances {
-            let blit = self.shared.service_pipes.get_blit_2d_color(key);
-            self.raw
-                .IASetPrimitiveTopology(d3dcommon::D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST);
-            self.raw.set_pipeline_state(blit.pipeline);
-            self.raw.set_graphics_root_signature(blit.signature);
-            self.raw
-                .set_graphics_root_descriptor_table(0, srv_heap.start_gpu_descriptor());
-            for inst in list {
-                let scissor = d3d12::D3D12_RECT {
-                    left: inst.viewport.TopLeftX as _,
-                    top: inst.viewport.TopLeftY as _,
-                    right: (inst.viewport.TopLeftX + inst.viewport.Width) as _,
-                    bottom: (inst.viewport.TopLeftY + inst.viewport.Height) as _,
-                };
-                self.raw.RSSetViewports(1, &inst.viewport);
-                self.raw.RSSetScissorRects(1, &scissor);
-                self.raw.SetGraphicsRoot32BitConstants(
-                    1,
-                    (mem::size_of::<internal::BlitData>() / 4) as _,
-                    &inst.data as *const _ as *const _,
-                    0,
-                );
-                self.raw.OMSetRenderTargets(1, &inst.rtv, TRUE, ptr::null());
-                self.raw.draw(3, 1, 0, 0);
-            }
+        // Pipeline state and primitive topology set in bundles are inherited
+        // by the executing command list, so the cached state is outdated.
+        self.gr_pipeline = PipelineCache::new();
+        self.comp_pipeline = PipelineCache::new();
+        self.primitive_topology = d3dcommon::D3D_PRIMITIVE_TOPOLOGY_UNDEFINED;
+    }
+}
+
+// PIX event encoding, see `pix3.h` of the WinPixEventRuntime.
+const PIX_EVENT_PIX3BLOB_VERSION: UINT = 2;
+const PIX_EVENT_BEGIN_EVENT: u64 = 0x002;
+const PIX_EVENT_SET_MARKER: u64 = 0x008;
+
+// Encode an event without arguments as PIX3 blob: event info, color and a
+// null-terminated UTF-16 string, copied in chunks of 8 bytes.
+fn encode_pix_event(event_type: u64, name: &str, color: u32) -> Vec<u64> {
+    const COPY_CHUNK_SIZE: u64 = 8;
+    let mut data = vec![
+        (event_type & 0x3FF) << 10,
+        color as u64,
+        (COPY_CHUNK_SIZE & 0x1F) << 55,
+    ];
+    let chars = name.encode_utf16().chain(iter::once(0)).collect::<Vec<_>>();
+    data.extend(chars.chunks(4).map(|chunk| {
+        chunk
+            .iter()
+            .enumerate()
+            .fold(0, |word, (i, &c)| word | (c as u64) << (16 * i))
+    }));
+    data
+}
+
+// Source box of a blit region which can be executed as `CopyTextureRegion`, i.e. the
+// region is neither scaled nor flipped.
+fn blit_copy_box(r: &com::ImageBlit) -> Option<d3d12::D3D12_BOX> {
+    let (src, dst) = (&r.src_bounds, &r.dst_bounds);
+    let src_size = [src.end.x - src.start.x, src.end.y - src.start.y, src.end.z - src.start.z];
+    let dst_size = [dst.end.x - dst.start.x, dst.end.y - dst.start.y, dst.end.z - dst.start.z];
+    if src_size != dst_size || src_size.iter().any(|&size| size <= 0) {
+        return None;
+    }
+    Some(d3d12::D3D12_BOX {
+        left: src.start.x as _,
+        top: src.start.y as _,
+        front: src.start.z as _,
+        right: src.end.x as _,
+        bottom: src.end.y as _,
+        back: src.end.z as _,
+    })
+}
+
+// Shader resource view of the level above `level` and unordered access view of `level`
+// for downsampling in the mipmap service pipe.
+fn mipmap_views(
+    format: dxgiformat::DXGI_FORMAT,
+    dimension: d3d12::D3D12_RESOURCE_DIMENSION,
+    level: image::Level,
+    layers: &Range<image::Layer>,
+) -> (
+    d3d12::D3D12_SHADER_RESOURCE_VIEW_DESC,
+    d3d12::D3D12_UNORDERED_ACCESS_VIEW_DESC,
+) {
+    let mut srv = d3d12::D3D12_SHADER_RESOURCE_VIEW_DESC {
+        Format: format,
+        ViewDimension: d3d12::D3D12_SRV_DIMENSION_TEXTURE2DARRAY,
+        Shader4ComponentMapping: IDENTITY_MAPPING,
+        u: unsafe { mem::zeroed() },
+    };
+    let mut uav = d3d12::D3D12_UNORDERED_ACCESS_VIEW_DESC {
+        Format: format,
+        ViewDimension: d3d12::D3D12_UAV_DIMENSION_TEXTURE2DARRAY,
+        u: unsafe { mem::zeroed() },
+    };
+    unsafe {
+        if dimension == d3d12::D3D12_RESOURCE_DIMENSION_TEXTURE3D {
+            srv.ViewDimension = d3d12::D3D12_SRV_DIMENSION_TEXTURE3D;
+            *srv.u.Texture3D_mut() = d3d12::D3D12_TEX3D_SRV {
+                MostDetailedMip: level as u32 - 1,
+                MipLevels: 1,
+                ResourceMinLODClamp: 0.0,
+            };
+            uav.ViewDimension = d3d12::D3D12_UAV_DIMENSION_TEXTURE3D;
+            *uav.u.Texture3D_mut() = d3d12::D3D12_TEX3D_UAV {
+                MipSlice: level as _,
+                FirstWSlice: 0,
+                WSize: !0,
+            };
+        } else {
+            *srv.u.Texture2DArray_mut() = d3d12::D3D12_TEX2D_ARRAY_SRV {
+                MostDetailedMip: level as u32 - 1,
+                MipLevels: 1,
+                FirstArraySlice: layers.start as _,
+                ArraySize: (layers.end - layers.start) as _,
+                PlaneSlice: 0,
+                ResourceMinLODClamp: 0.0,
+            };
+            *uav.u.Texture2DArray_mut() = d3d12::D3D12_TEX2D_ARRAY_UAV {
+                MipSlice: level as _,
+                FirstArraySlice: layers.start as _,
+                ArraySize: (layers.end - layers.start) as _,
+                PlaneSlice: 0,
+            };
         }
-        // post barriers
-        for bar in &mut barriers {
-            let mut transition = bar.u.Transition_mut();
-            mem::swap(&mut transition.StateBefore, &mut transition.StateAfter);
+    }
+    (srv, uav)
+}
+
+// Number of thread groups of the mipmap service pipe covering a destination level,
+// 2D images are processed in 8x8 tiles per layer and 3D images in 4x4x4 blocks.
+fn mipmap_group_count(
+    dimension: d3d12::D3D12_RESOURCE_DIMENSION,
+    extent: image::Extent,
+    num_layers: image::Layer,
+) -> WorkGroupCount {
+    if dimension == d3d12::D3D12_RESOURCE_DIMENSION_TEXTURE3D {
+        [div(extent.width, 4), div(extent.height, 4), div(extent.depth, 4)]
+    } else {
+        [div(extent.width, 8), div(extent.height, 8), num_layers as _]
+    }
+}
+
+// State an image needs to be in to be cleared outside of a render pass. Color images
+// which can't be rendered to are cleared as storage images.
+fn image_clear_state(
+    flags: d3d12::D3D12_RESOURCE_FLAGS,
+    is_color: bool,
+) -> Option<d3d12::D3D12_RESOURCE_STATES> {
+    if !is_color {
+        if flags & d3d12::D3D12_RESOURCE_FLAG_ALLOW_DEPTH_STENCIL != 0 {
+            Some(d3d12::D3D12_RESOURCE_STATE_DEPTH_WRITE)
+        } else {
+            None
         }
-        self.raw
-            .ResourceBarrier(barriers.len() as _, barriers.as_ptr());
+    } else if flags & d3d12::D3D12_RESOURCE_FLAG_ALLOW_RENDER_TARGET != 0 {
+        Some(d3d12::D3D12_RESOURCE_STATE_RENDER_TARGET)
+    } else if flags & d3d12::D3D12_RESOURCE_FLAG_ALLOW_UNORDERED_ACCESS != 0 {
+        Some(d3d12::D3D12_RESOURCE_STATE_UNORDERED_ACCESS)
+    } else {
+        None
+    }
+}
 
-        // Reset states
-        self.raw
-            .RSSetViewports(self.viewport_cache.len() as _, self.viewport_cache.as_ptr());
-        self.raw
-            .RSSetScissorRects(self.scissor_cache.len() as _, self.scissor_cache.as_ptr());
-        if self.primitive_topology != d3dcommon::D3D_PRIMITIVE_TOPOLOGY_UNDEFINED {
-            self.raw.IASetPrimitiveTopology(self.primitive_topology);
+fn blit_filter(filter: image::Filter) -> d3d12::D3D12_FILTER {
+    match filter {
+        image::Filter::Nearest => d3d12::D3D12_FILTER_MIN_MAG_MIP_POINT,
+        image::Filter::Linear => d3d12::D3D12_FILTER_MIN_MAG_LINEAR_MIP_POINT,
+    }
+}
+
+// Normalized source rectangle of a blit region drawn by the blit service pipe,
+// taking flipping of the destination into account.
+fn blit_data(
+    r: &com::ImageBlit,
+    src_extent: image::Extent,
+    layer: image::Layer,
+) -> internal::BlitData {
+    let (sx, dx) = if r.dst_bounds.start.x > r.dst_bounds.end.x {
+        (r.src_bounds.end.x, r.src_bounds.start.x - r.src_bounds.end.x)
+    } else {
+        (r.src_bounds.start.x, r.src_bounds.end.x - r.src_bounds.start.x)
+    };
+    let (sy, dy) = if r.dst_bounds.start.y > r.dst_bounds.end.y {
+        (r.src_bounds.end.y, r.src_bounds.start.y - r.src_bounds.end.y)
+    } else {
+        (r.src_bounds.start.y, r.src_bounds.end.y - r.src_bounds.start.y)
+    };
+    let (width, height) = (src_extent.width as f32, src_extent.height as f32);
+
+    internal::BlitData {
+        src_offset: [sx as f32 / width, sy as f32 / height],
+        src_extent: [dx as f32 / width, dy as f32 / height],
+        layer: layer as f32,
+        level: r.src_subresource.level as _,
+    }
+}
+
+// Render passes, barriers, transfer and query commands are only allowed in direct command lists.
+// Resource activated in a memory region: heap, occupied byte range and resource.
+type ActiveAlias = (
+    *mut d3d12::ID3D12Heap,
+    Range<u64>,
+    *mut d3d12::ID3D12Resource,
+);
+
+// Make `resource` the active resource of its memory region.
+//
+// Returns the `before` resource of the aliasing barrier: the previously active resource
+// if exactly one overlaps the region, otherwise null to deactivate all others.
+// `None` if `resource` is the active resource already.
+fn activate_alias(
+    active: &mut Vec<ActiveAlias>,
+    region: &r::MemoryRegion,
+    resource: *mut d3d12::ID3D12Resource,
+) -> Option<*mut d3d12::ID3D12Resource> {
+    let heap = region.heap.as_mut_ptr();
+    let mut before = Vec::new();
+    active.retain(|&(other_heap, ref range, other)| {
+        let overlaps = other_heap == heap && r::ranges_overlap(range, &region.range);
+        if overlaps {
+            before.push(other);
         }
+        !overlaps
+    });
+    active.push((heap, region.range.clone(), resource));
+    match before[..] {
+        [other] if other == resource => None,
+        [other] => Some(other),
+        _ => Some(ptr::null_mut()),
+    }
+}
+
+fn is_allowed_at_level(level: com::RawLevel) -> bool {
+    level == com::RawLevel::Primary
+}
+
+// `DiscardResource` requires render targets and depth stencil images to be in the
+// respective write state.
+fn is_discard_state(state: d3d12::D3D12_RESOURCE_STATES) -> bool {
+    state == d3d12::D3D12_RESOURCE_STATE_RENDER_TARGET
+        || state == d3d12::D3D12_RESOURCE_STATE_DEPTH_WRITE
+}
+
+// Check if a buffer offset satisfies the required alignment of buffer-image copies.
+// Offsets not matching the optimal D3D12 placement alignment are handled by
+// splitting the copy, but offsets inside a texel block can't be expressed at all.
+fn is_buffer_copy_aligned(offset: buffer::Offset, alignment: buffer::Offset) -> bool {
+    offset % alignment == 0
+}
+
+// Plane slices of a planar image covered by `aspects`, other images only have plane 0.
+fn planar_slices(format: format::Format, aspects: Aspects) -> Range<UINT> {
+    if !format.is_planar() {
+        return 0 .. 1;
+    }
+    let start = if aspects.contains(Aspects::PLANE_0) { 0 } else { 1 };
+    let end = if aspects.contains(Aspects::PLANE_1) { 2 } else { 1 };
+    start .. end
+}
+
+// Map the state of an image in a barrier, images in the undefined layout are
+// assumed to still be in the state they were created in.
+fn image_barrier_state(
+    initial_state: d3d12::D3D12_RESOURCE_STATES,
+    access: image::Access,
+    layout: image::Layout,
+) -> d3d12::D3D12_RESOURCE_STATES {
+    match layout {
+        image::Layout::Undefined => initial_state,
+        _ => conv::map_image_resource_state(access, layout),
+    }
+}
+
+// Update the cached viewports or scissors starting at `first`, gaps are filled with `empty`.
+// Returns `false` without modifying the cache if the number of viewports of a pipeline
+// would be exceeded.
+fn update_state_cache<A, I>(
+    cache: &mut SmallVec<A>,
+    first: usize,
+    values: I,
+    empty: A::Item,
+) -> bool
+where
+    A: Array,
+    A::Item: Copy,
+    I: IntoIterator<Item = A::Item>,
+{
+    let values = values.into_iter().collect::<Vec<_>>();
+    let end = first + values.len();
+    if end > d3d12::D3D12_VIEWPORT_AND_SCISSORRECT_OBJECT_COUNT_PER_PIPELINE as usize {
+        return false;
+    }
+    while cache.len() < end {
+        cache.push(empty);
+    }
+    cache[first .. end].copy_from_slice(&values);
+    true
+}
+
+// Update the stencil reference values of the front and back faces, returning the new values.
+fn update_stencil_reference(
+    references: &mut [pso::StencilValue; 2],
+    faces: pso::Face,
+    value: pso::StencilValue,
+) -> [pso::StencilValue; 2] {
+    if faces.contains(pso::Face::FRONT) {
+        references[0] = value;
+    }
+    if faces.contains(pso::Face::BACK) {
+        references[1] = value;
+    }
+    *references
+}
+
+// Check if a resource in the `actual` state can be used for an operation requiring `expected`.
+// Resources in the common state are implicitly promoted by D3D12.
+fn is_state_compatible(
+    actual: d3d12::D3D12_RESOURCE_STATES,
+    expected: d3d12::D3D12_RESOURCE_STATES,
+) -> bool {
+    actual == d3d12::D3D12_RESOURCE_STATE_COMMON || actual & expected == expected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        activate_alias,
+        blit_copy_box,
+        blit_data,
+        blit_filter,
+        encode_pix_event,
+        image_barrier_state,
+        image_clear_state,
+        is_allowed_at_level,
+        is_buffer_copy_aligned,
+        is_discard_state,
+        is_state_compatible,
+        mipmap_group_count,
+        update_state_cache,
+        update_stencil_reference,
+        CommandBuffer,
+        PipelineCache,
+        PIX_EVENT_BEGIN_EVENT,
+    };
+    use conv;
+    use resource::MemoryRegion;
+    use std::sync::{Arc, Mutex};
+    use std::ptr;
+    use hal::{command as com, format, image, pso};
+    use root_constants::RootConstant;
+    use native;
+    use smallvec::SmallVec;
+    use winapi::um::d3d12::*;
+
+    #[test]
+    fn test_state_compatible() {
+        assert!(is_state_compatible(
+            D3D12_RESOURCE_STATE_COMMON,
+            D3D12_RESOURCE_STATE_COPY_SOURCE
+        ));
+        assert!(is_state_compatible(
+            D3D12_RESOURCE_STATE_GENERIC_READ,
+            D3D12_RESOURCE_STATE_COPY_SOURCE
+        ));
+        assert!(is_state_compatible(
+            D3D12_RESOURCE_STATE_COPY_DEST,
+            D3D12_RESOURCE_STATE_COPY_DEST
+        ));
     }
 
-    unsafe fn bind_index_buffer(&mut self, ibv: buffer::IndexBufferView<Backend>) {
-        let buffer = ibv.buffer.expect_bound();
-        let format = match ibv.index_type {
-            IndexType::U16 => dxgiformat::DXGI_FORMAT_R16_UINT,
-            IndexType::U32 => dxgiformat::DXGI_FORMAT_R32_UINT,
-        };
-        let location = buffer.resource.gpu_virtual_address();
-        self.raw.set_index_buffer(
-            location + ibv.offset,
-            (buffer.requirements.size - ibv.offset) as u32,
-            format,
-        );
+    #[test]
+    fn test_state_incompatible() {
+        // Reading a buffer which is still in the copy destination state.
+        assert!(!is_state_compatible(
+            D3D12_RESOURCE_STATE_COPY_DEST,
+            D3D12_RESOURCE_STATE_COPY_SOURCE
+        ));
+        assert!(!is_state_compatible(
+            D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
+            D3D12_RESOURCE_STATE_COPY_DEST
+        ));
     }
 
-    unsafe fn bind_vertex_buffers<I, T>(&mut self, first_binding: pso::BufferIndex, buffers: I)
-    where
-        I: IntoIterator<Item = (T, buffer::Offset)>,
-        T: Borrow<r::Buffer>,
-    {
-        assert!(first_binding as usize <= MAX_VERTEX_BUFFERS);
+    #[test]
+    fn test_shared_root_signature() {
+        let (signature, other_signature) = unsafe {
+            (
+                native::RootSignature::from_raw(0x10 as *mut _),
+                native::RootSignature::from_raw(0x20 as *mut _),
+            )
+        };
+        let (pipeline_a, pipeline_b) = unsafe {
+            (
+                native::PipelineState::from_raw(0x30 as *mut _),
+                native::PipelineState::from_raw(0x40 as *mut _),
+            )
+        };
 
-        for (view, (buffer, offset)) in self.vertex_buffer_views[first_binding as _ ..]
-            .iter_mut()
-            .zip(buffers)
-        {
-            let b = buffer.borrow().expect_bound();
-            let base = (*b.resource).GetGPUVirtualAddress();
-            view.BufferLocation = base + offset;
-            view.SizeInBytes = (b.requirements.size - offset) as u32;
-        }
-        self.set_vertex_buffers();
+        let mut cache = PipelineCache::new();
+        assert!(cache.needs_root_signature(signature));
+        cache.pipeline = Some((pipeline_a, signature));
+        // Switching between pipelines of the same layout keeps the root signature.
+        assert!(!cache.needs_root_signature(signature));
+        cache.pipeline = Some((pipeline_b, signature));
+        assert!(!cache.needs_root_signature(signature));
+        assert!(cache.needs_root_signature(other_signature));
     }
 
-    unsafe fn set_viewports<T>(&mut self, first_viewport: u32, viewports: T)
-    where
-        T: IntoIterator,
-        T::Item: Borrow<pso::Viewport>,
-    {
-        let viewports = viewports
-            .into_iter()
-            .map(|viewport| {
-                let viewport = viewport.borrow();
-                d3d12::D3D12_VIEWPORT {
-                    TopLeftX: viewport.rect.x as _,
-                    TopLeftY: viewport.rect.y as _,
-                    Width: viewport.rect.w as _,
-                    Height: viewport.rect.h as _,
-                    MinDepth: viewport.depth.start,
-                    MaxDepth: viewport.depth.end,
-                }
-            })
-            .enumerate();
+    #[test]
+    fn test_buffer_copy_alignment() {
+        // Only the texel block size is required, not the 512 byte placement alignment.
+        assert!(is_buffer_copy_aligned(0, 4));
+        assert!(is_buffer_copy_aligned(12, 4));
+        assert!(is_buffer_copy_aligned(32, 16));
+        assert!(!is_buffer_copy_aligned(2, 4));
+        assert!(!is_buffer_copy_aligned(520, 16));
+    }
 
-        for (i, viewport) in viewports {
-            if i + first_viewport as usize >= self.viewport_cache.len() {
-                self.viewport_cache.push(viewport);
-            } else {
-                self.viewport_cache[i + first_viewport as usize] = viewport;
-            }
-        }
+    #[test]
+    fn test_pix_event_encoding() {
+        let data = encode_pix_event(PIX_EVENT_BEGIN_EVENT, "draw", 0xFF00FF00);
+        assert_eq!(data[0], 0x800);
+        assert_eq!(data[1], 0xFF00FF00);
+        assert_eq!(data[2], 8 << 55);
+        assert_eq!(data[3], 0x0077_0061_0072_0064);
+        // Null terminator gets an own chunk.
+        assert_eq!(data[4], 0);
+        assert_eq!(data.len(), 5);
+
+        let data = encode_pix_event(PIX_EVENT_BEGIN_EVENT, "pass", 0);
+        let nested = encode_pix_event(PIX_EVENT_BEGIN_EVENT, "ab", 0);
+        assert_eq!(data.len(), 5);
+        assert_eq!(nested[3], 0x0062_0061);
+        assert_eq!(nested.len(), 4);
+    }
 
-        self.raw
-            .RSSetViewports(self.viewport_cache.len() as _, self.viewport_cache.as_ptr());
+    #[test]
+    fn test_alias_render_targets() {
+        let (before, after) = (0x10 as *mut _, 0x20 as *mut _);
+        let barrier = CommandBuffer::aliasing_barrier(before, after);
+        assert_eq!(barrier.Type, D3D12_RESOURCE_BARRIER_TYPE_ALIASING);
+        let aliasing = unsafe { barrier.u.Aliasing() };
+        assert_eq!(aliasing.pResourceBefore, before);
+        assert_eq!(aliasing.pResourceAfter, after);
+
+        // Newly activated render targets can be discarded once in attachment state.
+        let state = conv::map_image_resource_state(
+            image::Access::COLOR_ATTACHMENT_WRITE,
+            image::Layout::ColorAttachmentOptimal,
+        );
+        assert!(is_discard_state(state));
+        let state = conv::map_image_resource_state(
+            image::Access::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            image::Layout::DepthStencilAttachmentOptimal,
+        );
+        assert!(is_discard_state(state));
+        let state = conv::map_image_resource_state(
+            image::Access::SHADER_READ,
+            image::Layout::ShaderReadOnlyOptimal,
+        );
+        assert!(!is_discard_state(state));
     }
 
-    unsafe fn set_scissors<T>(&mut self, first_scissor: u32, scissors: T)
-    where
-        T: IntoIterator,
-        T::Item: Borrow<pso::Rect>,
-    {
-        let rects = scissors
-            .into_iter()
-            .map(|rect| get_rect(rect.borrow()))
-            .enumerate();
+    #[test]
+    fn test_bundle_restrictions() {
+        assert!(is_allowed_at_level(com::RawLevel::Primary));
+        assert!(!is_allowed_at_level(com::RawLevel::Secondary));
+    }
 
-        for (i, rect) in rects {
-            if i + first_scissor as usize >= self.scissor_cache.len() {
-                self.scissor_cache.push(rect);
-            } else {
-                self.scissor_cache[i + first_scissor as usize] = rect;
-            }
-        }
+    #[test]
+    fn test_swapchain_first_frame_transitions() {
+        let initial = D3D12_RESOURCE_STATE_PRESENT;
+        // First frame: undefined -> color attachment.
+        assert_eq!(
+            image_barrier_state(initial, image::Access::empty(), image::Layout::Undefined),
+            D3D12_RESOURCE_STATE_PRESENT
+        );
+        assert_eq!(
+            image_barrier_state(
+                initial,
+                image::Access::COLOR_ATTACHMENT_WRITE,
+                image::Layout::ColorAttachmentOptimal
+            ),
+            D3D12_RESOURCE_STATE_RENDER_TARGET
+        );
+        // Render -> present.
+        assert_eq!(
+            image_barrier_state(initial, image::Access::empty(), image::Layout::Present),
+            D3D12_RESOURCE_STATE_PRESENT
+        );
+    }
 
-        self.raw
-            .RSSetScissorRects(self.scissor_cache.len() as _, self.scissor_cache.as_ptr())
+    #[test]
+    fn test_blit_routing() {
+        let blit = |src: [i32; 2], dst: [i32; 2]| com::ImageBlit {
+            src_subresource: image::SubresourceLayers {
+                aspects: format::Aspects::COLOR,
+                level: 0,
+                layers: 0 .. 1,
+            },
+            src_bounds: image::Offset::ZERO .. image::Offset {
+                x: src[0],
+                y: src[1],
+                z: 1,
+            },
+            dst_subresource: image::SubresourceLayers {
+                aspects: format::Aspects::COLOR,
+                level: 1,
+                layers: 0 .. 1,
+            },
+            dst_bounds: image::Offset::ZERO .. image::Offset {
+                x: dst[0],
+                y: dst[1],
+                z: 1,
+            },
+        };
+        let extent = image::Extent {
+            width: 8,
+            height: 8,
+            depth: 1,
+        };
+
+        // Unscaled blits are copies.
+        let src_box = blit_copy_box(&blit([8, 8], [8, 8])).unwrap();
+        assert_eq!((src_box.right, src_box.bottom, src_box.back), (8, 8, 1));
+
+        // 2x downscale with linear filtering goes through the service pipe,
+        // sampling the whole source level.
+        let downscale = blit([8, 8], [4, 4]);
+        assert!(blit_copy_box(&downscale).is_none());
+        assert_eq!(
+            blit_filter(image::Filter::Linear),
+            D3D12_FILTER_MIN_MAG_LINEAR_MIP_POINT
+        );
+        let data = blit_data(&downscale, extent, 0);
+        assert_eq!(data.src_offset, [0.0, 0.0]);
+        assert_eq!(data.src_extent, [1.0, 1.0]);
+        assert_eq!(data.level, 0.0);
+
+        // Flipped blits can't be expressed as copies.
+        let mut flipped = blit([8, 8], [8, 8]);
+        flipped.dst_bounds = flipped.dst_bounds.end .. flipped.dst_bounds.start;
+        assert!(blit_copy_box(&flipped).is_none());
+        let data = blit_data(&flipped, extent, 0);
+        assert_eq!(data.src_offset, [1.0, 1.0]);
+        assert_eq!(data.src_extent, [-1.0, -1.0]);
     }
 
-    unsafe fn set_blend_constants(&mut self, color: pso::ColorValue) {
-        self.raw.set_blend_factor(color);
+    #[test]
+    fn test_image_clear_state() {
+        // Color images with render target support.
+        let flags =
+            D3D12_RESOURCE_FLAG_ALLOW_RENDER_TARGET | D3D12_RESOURCE_FLAG_ALLOW_UNORDERED_ACCESS;
+        assert_eq!(
+            image_clear_state(flags, true),
+            Some(D3D12_RESOURCE_STATE_RENDER_TARGET)
+        );
+        // Storage images are cleared through a UAV.
+        assert_eq!(
+            image_clear_state(D3D12_RESOURCE_FLAG_ALLOW_UNORDERED_ACCESS, true),
+            Some(D3D12_RESOURCE_STATE_UNORDERED_ACCESS)
+        );
+        // Depth stencil images.
+        assert_eq!(
+            image_clear_state(D3D12_RESOURCE_FLAG_ALLOW_DEPTH_STENCIL, false),
+            Some(D3D12_RESOURCE_STATE_DEPTH_WRITE)
+        );
+        assert_eq!(image_clear_state(D3D12_RESOURCE_FLAG_NONE, true), None);
+        assert_eq!(
+            image_clear_state(D3D12_RESOURCE_FLAG_ALLOW_UNORDERED_ACCESS, false),
+            None
+        );
     }
 
-    unsafe fn set_stencil_reference(&mut self, faces: pso::Face, value: pso::StencilValue) {
-        assert!(!faces.is_empty());
+    #[test]
+    fn test_mipmap_group_count() {
+        let extent = |width, height, depth| image::Extent {
+            width,
+            height,
+            depth,
+        };
+        // Non power of two levels are covered by partial groups.
+        assert_eq!(
+            mipmap_group_count(D3D12_RESOURCE_DIMENSION_TEXTURE2D, extent(17, 9, 1), 6),
+            [3, 2, 6]
+        );
+        assert_eq!(
+            mipmap_group_count(D3D12_RESOURCE_DIMENSION_TEXTURE2D, extent(1, 1, 1), 1),
+            [1, 1, 1]
+        );
+        assert_eq!(
+            mipmap_group_count(D3D12_RESOURCE_DIMENSION_TEXTURE3D, extent(5, 4, 3), 1),
+            [2, 1, 1]
+        );
+    }
 
-        if !faces.is_all() {
-            warn!(
-                "Stencil ref values set for both faces but only one was requested ({})",
-                faces.bits(),
+    #[test]
+    fn test_partial_root_constant_updates() {
+        let mut cache = PipelineCache::new();
+        cache.root_constants = vec![
+            RootConstant {
+                stages: pso::ShaderStageFlags::VERTEX,
+                range: 0 .. 4,
+            },
+            RootConstant {
+                stages: pso::ShaderStageFlags::FRAGMENT,
+                range: 4 .. 8,
+            },
+        ];
+        cache.num_parameter_slots = 2;
+        // Shader visible state of the two root constants.
+        let mut visible = [[0u32; 4]; 2];
+        let flush = |cache: &mut PipelineCache, visible: &mut [[u32; 4]; 2]| {
+            let mut uploads = Vec::new();
+            CommandBuffer::flush_user_data(
+                cache,
+                |slot, offset, data| {
+                    let start = offset as usize;
+                    visible[slot as usize][start .. start + data.len()].copy_from_slice(data);
+                    uploads.push((slot, offset, data.len()));
+                },
+                |_, _| {},
             );
-        }
+            uploads
+        };
 
-        self.raw.set_stencil_reference(value as _);
-    }
+        // Layout change: everything gets uploaded.
+        cache.user_data.set_constants(0, &[1, 2, 3, 4, 5, 6, 7, 8]);
+        cache.user_data.dirty_all();
+        assert_eq!(flush(&mut cache, &mut visible), vec![(0, 0, 4), (1, 0, 4)]);
+
+        // Overlapping partial updates only flush the touched words.
+        cache.user_data.set_constants(1, &[20, 30]);
+        cache.user_data.set_constants(2, &[31, 40, 50]);
+        cache.user_data.set_constants(7, &[80]);
+        assert_eq!(
+            flush(&mut cache, &mut visible),
+            vec![(0, 1, 3), (1, 0, 1), (1, 3, 1)]
+        );
+        assert_eq!(visible, [[1, 20, 31, 40], [50, 6, 7, 80]]);
 
-    unsafe fn set_stencil_read_mask(&mut self, _faces: pso::Face, _value: pso::StencilValue) {
-        unimplemented!();
-    }
+        // Nothing dirty, nothing to upload.
+        assert!(flush(&mut cache, &mut visible).is_empty());
 
-    unsafe fn set_stencil_write_mask(&mut self, _faces: pso::Face, _value: pso::StencilValue) {
-        unimplemented!();
+        // Graphics and compute root constants are tracked separately.
+        let mut compute = PipelineCache::new();
+        compute.user_data.set_constants(0, &[9]);
+        assert_eq!(cache.user_data.dirty_mask, 0);
+        assert_eq!(compute.user_data.dirty_runs(0 .. 4).into_vec(), vec![0 .. 1]);
     }
 
-    unsafe fn set_depth_bounds(&mut self, bounds: Range<f32>) {
-        let (cmd_list1, hr) = self.raw.cast::<d3d12::ID3D12GraphicsCommandList1>();
-        if winerror::SUCCEEDED(hr) {
-            cmd_list1.OMSetDepthBounds(bounds.start, bounds.end);
-            cmd_list1.destroy();
-        } else {
-            warn!("Depth bounds test is not supported");
+    #[test]
+    fn test_redundant_table_binds() {
+        let mut cache = PipelineCache::new();
+        cache.num_parameter_slots = 2;
+        cache.srv_cbv_uav_start = 0x1000;
+        cache.sampler_start = 0x2000;
+        let flush = |cache: &mut PipelineCache| {
+            let mut tables = Vec::new();
+            CommandBuffer::flush_user_data(
+                cache,
+                |_, _, _| {},
+                |slot, gpu| tables.push((slot, gpu.ptr)),
+            );
+            tables
+        };
+
+        // Binding the same set for every draw only sets the tables once.
+        let mut num_updates = 0;
+        for _ in 0 .. 1000 {
+            cache.user_data.set_srv_cbv_uav_table(0, 0x40, 0);
+            cache.user_data.set_sampler_table(1, 0x20, 0);
+            num_updates += flush(&mut cache).len();
         }
+        assert_eq!(num_updates, 2);
+
+        // Writing to the set bumps its generation.
+        cache.user_data.set_srv_cbv_uav_table(0, 0x40, 1);
+        cache.user_data.set_sampler_table(1, 0x20, 0);
+        assert_eq!(flush(&mut cache), vec![(0, 0x1040)]);
+
+        // Binding another set.
+        cache.user_data.set_sampler_table(1, 0x60, 0);
+        assert_eq!(flush(&mut cache), vec![(1, 0x2060)]);
+
+        // Heap changes require all tables to be rebound.
+        cache.user_data.invalidate_tables();
+        cache.user_data.set_srv_cbv_uav_table(0, 0x40, 1);
+        cache.user_data.set_sampler_table(1, 0x60, 0);
+        assert_eq!(flush(&mut cache), vec![(0, 0x1040), (1, 0x2060)]);
     }
 
-    unsafe fn set_line_width(&mut self, width: f32) {
-        validate_line_width(width);
+    #[test]
+    fn test_activate_alias() {
+        let placements = Arc::new(Mutex::new(Vec::new()));
+        let region = |heap: usize, offset: u64| MemoryRegion {
+            heap: unsafe { native::Heap::from_raw(heap as *mut _) },
+            range: offset .. offset + 0x100,
+            placements: Arc::clone(&placements),
+        };
+        let (a, b, c) = (0x10 as *mut _, 0x20 as *mut _, 0x30 as *mut _);
+        let mut active = Vec::new();
+
+        // Nothing tracked yet, deactivate all resources.
+        assert_eq!(activate_alias(&mut active, &region(1, 0), a), Some(ptr::null_mut()));
+        assert_eq!(activate_alias(&mut active, &region(1, 0x80), b), Some(a));
+        // Already active.
+        assert_eq!(activate_alias(&mut active, &region(1, 0x80), b), None);
+        // Same offset in a different heap doesn't alias.
+        assert_eq!(activate_alias(&mut active, &region(2, 0), a), Some(ptr::null_mut()));
+        assert_eq!(activate_alias(&mut active, &region(2, 0x100), c), Some(ptr::null_mut()));
+        // Overlapping two active resources.
+        assert_eq!(activate_alias(&mut active, &region(2, 0x80), b), Some(ptr::null_mut()));
+        assert_eq!(active.len(), 2);
+    }
+    #[test]
+    fn test_update_state_cache() {
+        let mut cache = SmallVec::<[u32; 16]>::new();
+        assert!(update_state_cache(&mut cache, 0, vec![1, 2, 3, 4], 0));
+        assert_eq!(&cache[..], &[1, 2, 3, 4]);
+        // Partial updates keep the other values.
+        assert!(update_state_cache(&mut cache, 1, vec![5], 0));
+        assert_eq!(&cache[..], &[1, 5, 3, 4]);
+        // Gaps are filled up to the first updated value.
+        assert!(update_state_cache(&mut cache, 6, vec![7], 0));
+        assert_eq!(&cache[..], &[1, 5, 3, 4, 0, 0, 7]);
+        // Exceeding the viewport limit leaves the cache untouched.
+        assert!(!update_state_cache(&mut cache, 15, vec![8, 9], 0));
+        assert_eq!(cache.len(), 7);
     }
 
-    unsafe fn set_depth_bias(&mut self, _depth_bias: pso::DepthBias) {
-        unimplemented!()
+    #[test]
+    fn test_update_stencil_reference() {
+        let mut references = [0; 2];
+        assert_eq!(update_stencil_reference(&mut references, pso::Face::all(), 3), [3, 3]);
+        assert_eq!(update_stencil_reference(&mut references, pso::Face::FRONT, 5), [5, 3]);
+        assert_eq!(update_stencil_reference(&mut references, pso::Face::BACK, 5), [5, 5]);
+        assert_eq!(references, [5, 5]);
     }
+}
 
-    unsafe fn bind_graphics_pipeline(&mut self, pipeline: &r::GraphicsPipeline) {
-        match self.gr_pipeline.pipeline {
-            Some((_, signature)) if signature == pipeline.signature => {
-                // Same root signature, nothing to do
+#[cfg(all(test, feature = "warp"))]
+mod warp_tests {
+    use hal::format::{Aspects, Format};
+    use hal::{pso, Features};
+    use test_utils::Warp;
+
+    #[test]
+    fn test_warp_dispatch_indirect() {
+        use hal::command::{BufferCopy, CommandBufferFlags, RawCommandBuffer, RawLevel};
+        use hal::memory::{Barrier, Dependencies, Properties};
+        use hal::pool::{CommandPoolCreateFlags, RawCommandPool};
+        use hal::pso::{DescriptorPool, PipelineStage};
+        use hal::queue::{RawCommandQueue, Submission};
+        use hal::{buffer, Device};
+        use std::ops::Range;
+        use std::{iter, ptr};
+
+        use resource::Semaphore;
+
+        let (warp, mut queue_group) = Warp::open("test_warp_dispatch_indirect");
+        let device = &warp.device;
+        unsafe {
+            let (args, args_memory) = warp.create_buffer(
+                12,
+                buffer::Usage::STORAGE | buffer::Usage::INDIRECT,
+                Properties::DEVICE_LOCAL,
+            );
+            let (counter, counter_memory) = warp.create_buffer(
+                4,
+                buffer::Usage::STORAGE | buffer::Usage::TRANSFER_SRC,
+                Properties::DEVICE_LOCAL,
+            );
+            let (readback, readback_memory) = warp.create_buffer(
+                4,
+                buffer::Usage::TRANSFER_DST,
+                Properties::CPU_VISIBLE | Properties::CPU_CACHED,
+            );
+
+            let bindings = (0 .. 2)
+                .map(|binding| pso::DescriptorSetLayoutBinding {
+                    binding,
+                    ty: pso::DescriptorType::StorageBuffer,
+                    count: 1,
+                    stage_flags: pso::ShaderStageFlags::COMPUTE,
+                    immutable_samplers: false,
+                })
+                .collect::<Vec<_>>();
+            let set_layout = device
+                .create_descriptor_set_layout(&bindings, &[])
+                .unwrap();
+            let layout = device
+                .create_pipeline_layout(
+                    iter::once(&set_layout),
+                    iter::empty::<(pso::ShaderStageFlags, Range<u32>)>(),
+                )
+                .unwrap();
+            let mut pool = device
+                .create_descriptor_pool(
+                    1,
+                    iter::once(pso::DescriptorRangeDesc {
+                        ty: pso::DescriptorType::StorageBuffer,
+                        count: 2,
+                    }),
+                    pso::DescriptorPoolCreateFlags::empty(),
+                )
+                .unwrap();
+            let set = pool.allocate_set(&set_layout).unwrap();
+            device.write_descriptor_sets(iter::once(pso::DescriptorSetWrite {
+                set: &set,
+                binding: 0,
+                array_offset: 0,
+                descriptors: vec![
+                    pso::Descriptor::Buffer(&args, None .. None),
+                    pso::Descriptor::Buffer(&counter, None .. None),
+                ],
+            }));
+
+            // The first shader computes the group count of the second one.
+            let modules = [
+                &b"RWByteAddressBuffer args : register(u0);
+                [numthreads(1, 1, 1)] void cs_main() { args.Store3(0, uint3(2, 3, 1)); }"[..],
+                &b"RWByteAddressBuffer counter : register(u1);
+                [numthreads(1, 1, 1)] void cs_main() { counter.InterlockedAdd(0, 1); }"[..],
+            ]
+            .iter()
+            .map(|code| {
+                device
+                    .create_shader_module_from_source(pso::Stage::Compute, "cs_main", "main", code)
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+            let pipelines = modules
+                .iter()
+                .map(|module| {
+                    let entry = pso::EntryPoint {
+                        entry: "main",
+                        module,
+                        specialization: pso::Specialization::default(),
+                    };
+                    let desc = pso::ComputePipelineDesc::new(entry, &layout);
+                    device.create_compute_pipeline(&desc, None).unwrap()
+                })
+                .collect::<Vec<_>>();
+
+            let mut command_pool = device
+                .create_command_pool(queue_group.family(), CommandPoolCreateFlags::empty())
+                .unwrap();
+            let mut cmd_buffer = command_pool.allocate_one(RawLevel::Primary);
+            cmd_buffer.begin(CommandBufferFlags::ONE_TIME_SUBMIT, Default::default());
+            cmd_buffer.bind_compute_pipeline(&pipelines[0]);
+            cmd_buffer.bind_compute_descriptor_sets(&layout, 0, iter::once(&set), &[]);
+            cmd_buffer.dispatch([1, 1, 1]);
+            cmd_buffer.pipeline_barrier(
+                PipelineStage::COMPUTE_SHADER .. PipelineStage::DRAW_INDIRECT,
+                Dependencies::empty(),
+                iter::once(Barrier::whole_buffer(
+                    &args,
+                    buffer::Access::SHADER_WRITE .. buffer::Access::INDIRECT_COMMAND_READ,
+                )),
+            );
+            cmd_buffer.bind_compute_pipeline(&pipelines[1]);
+            cmd_buffer.bind_compute_descriptor_sets(&layout, 0, iter::once(&set), &[]);
+            // Misaligned offsets are rejected without recording the dispatch.
+            cmd_buffer.dispatch_indirect(&args, 2);
+            cmd_buffer.dispatch_indirect(&args, 0);
+            cmd_buffer.pipeline_barrier(
+                PipelineStage::COMPUTE_SHADER .. PipelineStage::TRANSFER,
+                Dependencies::empty(),
+                iter::once(Barrier::whole_buffer(
+                    &counter,
+                    buffer::Access::SHADER_WRITE .. buffer::Access::TRANSFER_READ,
+                )),
+            );
+            cmd_buffer.copy_buffer(
+                &counter,
+                &readback,
+                iter::once(BufferCopy {
+                    src: 0,
+                    dst: 0,
+                    size: 4,
+                }),
+            );
+            cmd_buffer.finish();
+
+            let fence = device.create_fence(false).unwrap();
+            queue_group.queues[0].as_raw_mut().submit(
+                Submission {
+                    command_buffers: iter::once(&cmd_buffer),
+                    wait_semaphores: iter::empty::<(&Semaphore, _)>(),
+                    signal_semaphores: iter::empty::<&Semaphore>(),
+                },
+                Some(&fence),
+            );
+            assert_eq!(device.wait_for_fence(&fence, !0), Ok(true));
+
+            let data = device.map_memory(&readback_memory, 0 .. 4).unwrap();
+            assert_eq!(ptr::read(data as *const u32), 6);
+            device.unmap_memory(&readback_memory);
+
+            device.destroy_fence(fence);
+            command_pool.free(iter::once(cmd_buffer));
+            device.destroy_command_pool(command_pool);
+            for pipeline in pipelines {
+                device.destroy_compute_pipeline(pipeline);
             }
-            _ => {
-                self.raw.set_graphics_root_signature(pipeline.signature);
-                self.gr_pipeline.num_parameter_slots = pipeline.num_parameter_slots;
-                self.gr_pipeline.root_constants = pipeline.constants.clone();
-                // All slots need to be rebound internally on signature change.
-                self.gr_pipeline.user_data.dirty_all();
+            for module in modules {
+                device.destroy_shader_module(module);
+            }
+            device.destroy_descriptor_pool(pool);
+            device.destroy_pipeline_layout(layout);
+            device.destroy_descriptor_set_layout(set_layout);
+            for (buffer, memory) in vec![
+                (args, args_memory),
+                (counter, counter_memory),
+                (readback, readback_memory),
+            ] {
+                device.destroy_buffer(buffer);
+                device.free_memory(memory);
             }
         }
-        self.raw.set_pipeline_state(pipeline.raw);
-        self.raw.IASetPrimitiveTopology(pipeline.topology);
-        self.primitive_topology = pipeline.topology;
+    }
 
-        self.active_bindpoint = BindPoint::Graphics { internal: false };
-        self.gr_pipeline.pipeline = Some((pipeline.raw, pipeline.signature));
-        self.vertex_bindings_remap = pipeline.vertex_bindings;
+    #[test]
+    fn test_warp_indirect_argument_transition() {
+        use hal::command::{BufferCopy, CommandBufferFlags, RawCommandBuffer, RawLevel};
+        use hal::memory::{Barrier, Dependencies, Properties};
+        use hal::pool::{CommandPoolCreateFlags, RawCommandPool};
+        use hal::pso::{DescriptorPool, PipelineStage};
+        use hal::queue::{RawCommandQueue, Submission};
+        use hal::{buffer, Device};
+        use std::ops::Range;
+        use std::{iter, ptr};
+
+        use resource::Semaphore;
+
+        let (warp, mut queue_group) = Warp::open("test_warp_indirect_argument_transition");
+        let device = &warp.device;
+        unsafe {
+            let (upload, upload_memory) = warp.create_buffer(
+                12,
+                buffer::Usage::TRANSFER_SRC,
+                Properties::CPU_VISIBLE,
+            );
+            let (args, args_memory) = warp.create_buffer(
+                12,
+                buffer::Usage::TRANSFER_DST | buffer::Usage::INDIRECT,
+                Properties::DEVICE_LOCAL,
+            );
+            let (counter, counter_memory) = warp.create_buffer(
+                4,
+                buffer::Usage::STORAGE | buffer::Usage::TRANSFER_SRC,
+                Properties::DEVICE_LOCAL,
+            );
+            let (readback, readback_memory) = warp.create_buffer(
+                4,
+                buffer::Usage::TRANSFER_DST,
+                Properties::CPU_VISIBLE | Properties::CPU_CACHED,
+            );
 
-        self.set_vertex_buffers();
+            let data = device.map_memory(&upload_memory, 0 .. 12).unwrap();
+            ptr::copy_nonoverlapping([2u32, 3, 1].as_ptr(), data as *mut u32, 3);
+            device.unmap_memory(&upload_memory);
 
-        if let Some(ref vp) = pipeline.baked_states.viewport {
-            self.set_viewports(0, iter::once(vp));
-        }
-        if let Some(ref rect) = pipeline.baked_states.scissor {
-            self.set_scissors(0, iter::once(rect));
-        }
-        if let Some(color) = pipeline.baked_states.blend_color {
-            self.set_blend_constants(color);
+            let binding = pso::DescriptorSetLayoutBinding {
+                binding: 0,
+                ty: pso::DescriptorType::StorageBuffer,
+                count: 1,
+                stage_flags: pso::ShaderStageFlags::COMPUTE,
+                immutable_samplers: false,
+            };
+            let set_layout = device
+                .create_descriptor_set_layout(iter::once(binding), &[])
+                .unwrap();
+            let layout = device
+                .create_pipeline_layout(
+                    iter::once(&set_layout),
+                    iter::empty::<(pso::ShaderStageFlags, Range<u32>)>(),
+                )
+                .unwrap();
+            let mut pool = device
+                .create_descriptor_pool(
+                    1,
+                    iter::once(pso::DescriptorRangeDesc {
+                        ty: pso::DescriptorType::StorageBuffer,
+                        count: 1,
+                    }),
+                    pso::DescriptorPoolCreateFlags::empty(),
+                )
+                .unwrap();
+            let set = pool.allocate_set(&set_layout).unwrap();
+            device.write_descriptor_sets(iter::once(pso::DescriptorSetWrite {
+                set: &set,
+                binding: 0,
+                array_offset: 0,
+                descriptors: iter::once(pso::Descriptor::Buffer(&counter, None .. None)),
+            }));
+
+            let module = device
+                .create_shader_module_from_source(
+                    pso::Stage::Compute,
+                    "cs_main",
+                    "main",
+                    b"RWByteAddressBuffer counter : register(u0);
+                    [numthreads(1, 1, 1)] void cs_main() { counter.InterlockedAdd(0, 1); }",
+                )
+                .unwrap();
+            let pipeline = {
+                let entry = pso::EntryPoint {
+                    entry: "main",
+                    module: &module,
+                    specialization: pso::Specialization::default(),
+                };
+                let desc = pso::ComputePipelineDesc::new(entry, &layout);
+                device.create_compute_pipeline(&desc, None).unwrap()
+            };
+
+            let mut command_pool = device
+                .create_command_pool(queue_group.family(), CommandPoolCreateFlags::empty())
+                .unwrap();
+            let mut cmd_buffer = command_pool.allocate_one(RawLevel::Primary);
+            cmd_buffer.begin(CommandBufferFlags::ONE_TIME_SUBMIT, Default::default());
+            cmd_buffer.pipeline_barrier(
+                PipelineStage::TOP_OF_PIPE .. PipelineStage::TRANSFER,
+                Dependencies::empty(),
+                iter::once(Barrier::whole_buffer(
+                    &args,
+                    buffer::Access::empty() .. buffer::Access::TRANSFER_WRITE,
+                )),
+            );
+            cmd_buffer.copy_buffer(
+                &upload,
+                &args,
+                iter::once(BufferCopy {
+                    src: 0,
+                    dst: 0,
+                    size: 12,
+                }),
+            );
+            // No barrier into `INDIRECT_COMMAND_READ`, the copy destination state is
+            // transitioned around the dispatch.
+            cmd_buffer.bind_compute_pipeline(&pipeline);
+            cmd_buffer.bind_compute_descriptor_sets(&layout, 0, iter::once(&set), &[]);
+            cmd_buffer.dispatch_indirect(&args, 0);
+            cmd_buffer.pipeline_barrier(
+                PipelineStage::COMPUTE_SHADER .. PipelineStage::TRANSFER,
+                Dependencies::empty(),
+                iter::once(Barrier::whole_buffer(
+                    &counter,
+                    buffer::Access::SHADER_WRITE .. buffer::Access::TRANSFER_READ,
+                )),
+            );
+            cmd_buffer.copy_buffer(
+                &counter,
+                &readback,
+                iter::once(BufferCopy {
+                    src: 0,
+                    dst: 0,
+                    size: 4,
+                }),
+            );
+            cmd_buffer.finish();
+
+            let fence = device.create_fence(false).unwrap();
+            queue_group.queues[0].as_raw_mut().submit(
+                Submission {
+                    command_buffers: iter::once(&cmd_buffer),
+                    wait_semaphores: iter::empty::<(&Semaphore, _)>(),
+                    signal_semaphores: iter::empty::<&Semaphore>(),
+                },
+                Some(&fence),
+            );
+            assert_eq!(device.wait_for_fence(&fence, !0), Ok(true));
+
+            let data = device.map_memory(&readback_memory, 0 .. 4).unwrap();
+            assert_eq!(ptr::read(data as *const u32), 6);
+            device.unmap_memory(&readback_memory);
+
+            device.destroy_fence(fence);
+            command_pool.free(iter::once(cmd_buffer));
+            device.destroy_command_pool(command_pool);
+            device.destroy_compute_pipeline(pipeline);
+            device.destroy_shader_module(module);
+            device.destroy_descriptor_pool(pool);
+            device.destroy_pipeline_layout(layout);
+            device.destroy_descriptor_set_layout(set_layout);
+            for (buffer, memory) in vec![
+                (upload, upload_memory),
+                (args, args_memory),
+                (counter, counter_memory),
+                (readback, readback_memory),
+            ] {
+                device.destroy_buffer(buffer);
+                device.free_memory(memory);
+            }
         }
-        if let Some(ref bounds) = pipeline.baked_states.depth_bounds {
-            self.set_depth_bounds(bounds.clone());
+    }
+
+    #[test]
+    fn test_warp_copy_state_validation() {
+        use hal::command::{BufferCopy, CommandBufferFlags, RawCommandBuffer, RawLevel};
+        use hal::memory::{Barrier, Dependencies, Properties};
+        use hal::pool::{CommandPoolCreateFlags, RawCommandPool};
+        use hal::pso::PipelineStage;
+        use hal::{buffer, Device};
+        use std::iter;
+        use winapi::um::d3d12::D3D12_RESOURCE_STATE_COPY_SOURCE;
+
+        let (warp, queue_group) = Warp::open("test_warp_copy_state_validation");
+        let device = &warp.device;
+        unsafe {
+            let (src, src_memory) = warp.create_buffer(
+                16,
+                buffer::Usage::TRANSFER_SRC | buffer::Usage::TRANSFER_DST,
+                Properties::DEVICE_LOCAL,
+            );
+            let (dst, dst_memory) =
+                warp.create_buffer(16, buffer::Usage::TRANSFER_DST, Properties::DEVICE_LOCAL);
+
+            let mut command_pool = device
+                .create_command_pool(queue_group.family(), CommandPoolCreateFlags::empty())
+                .unwrap();
+            let mut cmd_buffer = command_pool.allocate_one(RawLevel::Primary);
+            cmd_buffer.begin(CommandBufferFlags::ONE_TIME_SUBMIT, Default::default());
+            let is_valid = |cmd_buffer: &CommandBuffer| {
+                cmd_buffer.validate_buffer_state(
+                    src.expect_bound(),
+                    D3D12_RESOURCE_STATE_COPY_SOURCE,
+                    "copy_buffer",
+                )
+            };
+
+            // Buffers without recorded transitions are in the common state.
+            assert!(is_valid(&cmd_buffer));
+            // Reading a buffer which is still in the copy destination state.
+            cmd_buffer.pipeline_barrier(
+                PipelineStage::TOP_OF_PIPE .. PipelineStage::TRANSFER,
+                Dependencies::empty(),
+                iter::once(Barrier::whole_buffer(
+                    &src,
+                    buffer::Access::empty() .. buffer::Access::TRANSFER_WRITE,
+                )),
+            );
+            assert_eq!(is_valid(&cmd_buffer), !cfg!(debug_assertions));
+
+            cmd_buffer.pipeline_barrier(
+                PipelineStage::TRANSFER .. PipelineStage::TRANSFER,
+                Dependencies::empty(),
+                iter::once(Barrier::whole_buffer(
+                    &src,
+                    buffer::Access::TRANSFER_WRITE .. buffer::Access::TRANSFER_READ,
+                )),
+            );
+            assert!(is_valid(&cmd_buffer));
+            cmd_buffer.copy_buffer(
+                &src,
+                &dst,
+                iter::once(BufferCopy {
+                    src: 0,
+                    dst: 0,
+                    size: 16,
+                }),
+            );
+            cmd_buffer.finish();
+
+            command_pool.free(iter::once(cmd_buffer));
+            device.destroy_command_pool(command_pool);
+            device.destroy_buffer(src);
+            device.free_memory(src_memory);
+            device.destroy_buffer(dst);
+            device.free_memory(dst_memory);
         }
     }
 
-    unsafe fn bind_graphics_descriptor_sets<'a, I, J>(
-        &mut self,
-        layout: &r::PipelineLayout,
-        first_set: usize,
-        sets: I,
-        offsets: J,
-    ) where
-        I: IntoIterator,
-        I::Item: Borrow<r::DescriptorSet>,
-        J: IntoIterator,
-        J::Item: Borrow<com::DescriptorSetOffset>,
-    {
-        self.active_descriptor_heaps = self
-            .gr_pipeline
-            .bind_descriptor_sets(layout, first_set, sets, offsets);
-        self.bind_descriptor_heaps();
+    #[test]
+    fn test_warp_descriptor_set_state_validation() {
+        use hal::command::{CommandBufferFlags, RawCommandBuffer, RawLevel};
+        use hal::memory::{Barrier, Dependencies, Properties};
+        use hal::pool::{CommandPoolCreateFlags, RawCommandPool};
+        use hal::pso::{DescriptorPool, PipelineStage};
+        use hal::{buffer, Device};
+        use std::iter;
+        use std::ops::Range;
+
+        let (warp, queue_group) = Warp::open("test_warp_descriptor_set_state_validation");
+        let device = &warp.device;
+        unsafe {
+            let (storage, storage_memory) = warp.create_buffer(
+                16,
+                buffer::Usage::STORAGE | buffer::Usage::TRANSFER_DST,
+                Properties::DEVICE_LOCAL,
+            );
+            let binding = pso::DescriptorSetLayoutBinding {
+                binding: 0,
+                ty: pso::DescriptorType::StorageBuffer,
+                count: 1,
+                stage_flags: pso::ShaderStageFlags::COMPUTE,
+                immutable_samplers: false,
+            };
+            let set_layout = device
+                .create_descriptor_set_layout(iter::once(&binding), &[])
+                .unwrap();
+            let layout = device
+                .create_pipeline_layout(
+                    iter::once(&set_layout),
+                    iter::empty::<(pso::ShaderStageFlags, Range<u32>)>(),
+                )
+                .unwrap();
+            let mut pool = device
+                .create_descriptor_pool(
+                    1,
+                    iter::once(pso::DescriptorRangeDesc {
+                        ty: pso::DescriptorType::StorageBuffer,
+                        count: 1,
+                    }),
+                    pso::DescriptorPoolCreateFlags::empty(),
+                )
+                .unwrap();
+            let set = pool.allocate_set(&set_layout).unwrap();
+            device.write_descriptor_sets(iter::once(pso::DescriptorSetWrite {
+                set: &set,
+                binding: 0,
+                array_offset: 0,
+                descriptors: iter::once(pso::Descriptor::Buffer(&storage, None .. None)),
+            }));
+
+            let mut command_pool = device
+                .create_command_pool(queue_group.family(), CommandPoolCreateFlags::empty())
+                .unwrap();
+            let mut cmd_buffer = command_pool.allocate_one(RawLevel::Primary);
+            cmd_buffer.begin(CommandBufferFlags::ONE_TIME_SUBMIT, Default::default());
+            let operation = "bind_compute_descriptor_sets";
+
+            assert!(cmd_buffer.validate_descriptor_set_states(&set, operation));
+            // Bound as UAV while still in the copy destination state.
+            cmd_buffer.pipeline_barrier(
+                PipelineStage::TOP_OF_PIPE .. PipelineStage::TRANSFER,
+                Dependencies::empty(),
+                iter::once(Barrier::whole_buffer(
+                    &storage,
+                    buffer::Access::empty() .. buffer::Access::TRANSFER_WRITE,
+                )),
+            );
+            assert_eq!(
+                cmd_buffer.validate_descriptor_set_states(&set, operation),
+                !cfg!(debug_assertions)
+            );
+            cmd_buffer.bind_compute_descriptor_sets(&layout, 0, iter::once(&set), &[]);
+
+            cmd_buffer.pipeline_barrier(
+                PipelineStage::TRANSFER .. PipelineStage::COMPUTE_SHADER,
+                Dependencies::empty(),
+                iter::once(Barrier::whole_buffer(
+                    &storage,
+                    buffer::Access::TRANSFER_WRITE .. buffer::Access::SHADER_WRITE,
+                )),
+            );
+            assert!(cmd_buffer.validate_descriptor_set_states(&set, operation));
+            cmd_buffer.bind_compute_descriptor_sets(&layout, 0, iter::once(&set), &[]);
+            cmd_buffer.finish();
+
+            command_pool.free(iter::once(cmd_buffer));
+            device.destroy_command_pool(command_pool);
+            device.destroy_descriptor_pool(pool);
+            device.destroy_pipeline_layout(layout);
+            device.destroy_descriptor_set_layout(set_layout);
+            device.destroy_buffer(storage);
+            device.free_memory(storage_memory);
+        }
     }
 
-    unsafe fn bind_compute_pipeline(&mut self, pipeline: &r::ComputePipeline) {
-        match self.comp_pipeline.pipeline {
-            Some((_, signature)) if signature == pipeline.signature => {
-                // Same root signature, nothing to do
-            }
-            _ => {
-                self.raw.set_compute_root_signature(pipeline.signature);
-                self.comp_pipeline.num_parameter_slots = pipeline.num_parameter_slots;
-                self.comp_pipeline.root_constants = pipeline.constants.clone();
-                // All slots need to be rebound internally on signature change.
-                self.comp_pipeline.user_data.dirty_all();
+    #[test]
+    fn test_warp_multiple_viewports() {
+        use hal::command::RawCommandBuffer;
+        use hal::{image, Device, PhysicalDevice};
+        use std::iter;
+
+        use test_utils::{begin_render_pass, clear_color, entry_point, graphics_pipeline_desc};
+        use test_utils::FULLSCREEN_VS;
+
+        let (warp, mut queue_group) = Warp::open("test_warp_multiple_viewports");
+        let device = &warp.device;
+        assert!(warp.adapter.physical_device.features().contains(Features::MULTI_VIEWPORTS));
+        unsafe {
+            let target = warp.create_target(
+                Format::Rgba8Unorm,
+                (4, 4),
+                1,
+                image::Usage::COLOR_ATTACHMENT | image::Usage::TRANSFER_SRC,
+            );
+            let render_pass = warp.create_color_pass(Format::Rgba8Unorm);
+            let framebuffer = warp.create_framebuffer(&render_pass, &[&target.view], (4, 4));
+            let layout = warp.create_pipeline_layout(&[]);
+
+            // A fullscreen triangle is instanced by the geometry shader into each viewport.
+            let modules = warp.create_shader_modules(&[
+                FULLSCREEN_VS,
+                (
+                    pso::Stage::Geometry,
+                    "gs_main",
+                    b"struct GsOut {
+                        float4 pos : SV_Position;
+                        uint viewport : SV_ViewportArrayIndex;
+                    };
+                    [maxvertexcount(3)] [instance(4)]
+                    void gs_main(
+                        triangle float4 pos[3] : SV_Position,
+                        uint instance : SV_GSInstanceID,
+                        inout TriangleStream<GsOut> stream
+                    ) {
+                        for (uint i = 0; i < 3; i++) {
+                            GsOut v;
+                            v.pos = pos[i];
+                            v.viewport = instance;
+                            stream.Append(v);
+                        }
+                    }",
+                ),
+                (
+                    pso::Stage::Fragment,
+                    "ps_main",
+                    b"static const float4 colors[4] = {
+                        float4(1, 0, 0, 1), float4(0, 1, 0, 1),
+                        float4(0, 0, 1, 1), float4(1, 1, 1, 1),
+                    };
+                    float4 ps_main(
+                        float4 pos : SV_Position,
+                        nointerpolation uint viewport : SV_ViewportArrayIndex
+                    ) : SV_Target {
+                        return colors[viewport];
+                    }",
+                ),
+            ]);
+            let mut desc =
+                graphics_pipeline_desc(&modules[0], Some(&modules[2]), &layout, &render_pass, 0);
+            desc.shaders.geometry = Some(entry_point(&modules[1]));
+            let pipeline = device.create_graphics_pipeline(&desc, None).unwrap();
+
+            // One viewport and scissor per quadrant.
+            let rects = [(0, 0), (2, 0), (0, 2), (2, 2)]
+                .iter()
+                .map(|&(x, y)| pso::Rect { x, y, w: 2, h: 2 })
+                .collect::<Vec<_>>();
+            let viewports = rects
+                .iter()
+                .map(|&rect| pso::Viewport {
+                    rect,
+                    depth: 0.0 .. 1.0,
+                })
+                .collect::<Vec<_>>();
+
+            warp.submit(&mut queue_group, |cmd_buffer| {
+                begin_render_pass(
+                    cmd_buffer,
+                    &render_pass,
+                    &framebuffer,
+                    (4, 4),
+                    iter::once(clear_color([0.0; 4])),
+                );
+                cmd_buffer.set_viewports(0, &viewports);
+                cmd_buffer.set_scissors(0, &rects);
+                cmd_buffer.bind_graphics_pipeline(&pipeline);
+                cmd_buffer.draw(0 .. 3, 0 .. 1);
+                cmd_buffer.end_render_pass();
+            });
+
+            let colors = [
+                [0xFF, 0, 0, 0xFF],
+                [0, 0xFF, 0, 0xFF],
+                [0, 0, 0xFF, 0xFF],
+                [0xFF, 0xFF, 0xFF, 0xFF],
+            ];
+            let texels =
+                warp.read_image::<[u8; 4]>(&mut queue_group, &target.image, Aspects::COLOR, (4, 4));
+            for y in 0 .. 4 {
+                for x in 0 .. 4 {
+                    assert_eq!(texels[4 * y + x], colors[(y / 2) * 2 + x / 2]);
+                }
             }
-        }
-        self.raw.set_pipeline_state(pipeline.raw);
 
-        self.active_bindpoint = BindPoint::Compute;
-        self.comp_pipeline.pipeline = Some((pipeline.raw, pipeline.signature));
+            device.destroy_graphics_pipeline(pipeline);
+            warp.destroy_shader_modules(modules);
+            device.destroy_pipeline_layout(layout);
+            device.destroy_framebuffer(framebuffer);
+            device.destroy_render_pass(render_pass);
+            warp.destroy_target(target);
+        }
     }
 
-    unsafe fn bind_compute_descriptor_sets<I, J>(
-        &mut self,
-        layout: &r::PipelineLayout,
-        first_set: usize,
-        sets: I,
-        offsets: J,
-    ) where
-        I: IntoIterator,
-        I::Item: Borrow<r::DescriptorSet>,
-        J: IntoIterator,
-        J::Item: Borrow<com::DescriptorSetOffset>,
-    {
-        self.active_descriptor_heaps = self
-            .comp_pipeline
-            .bind_descriptor_sets(layout, first_set, sets, offsets);
-        self.bind_descriptor_heaps();
-    }
+    #[test]
+    fn test_warp_blend_constants() {
+        use hal::command::RawCommandBuffer;
+        use hal::{image, Device};
+        use std::iter;
+
+        use test_utils::{begin_render_pass, clear_color, graphics_pipeline_desc, FULLSCREEN_VS};
+
+        let (warp, mut queue_group) = Warp::open("test_warp_blend_constants");
+        let device = &warp.device;
+        unsafe {
+            let target = warp.create_target(
+                Format::Rgba8Unorm,
+                (2, 1),
+                1,
+                image::Usage::COLOR_ATTACHMENT | image::Usage::TRANSFER_SRC,
+            );
+            let render_pass = warp.create_color_pass(Format::Rgba8Unorm);
+            let framebuffer = warp.create_framebuffer(&render_pass, &[&target.view], (2, 1));
+            let layout = warp.create_pipeline_layout(&[]);
+
+            // The output is blended with the blend constants only.
+            let modules = warp.create_shader_modules(&[
+                FULLSCREEN_VS,
+                (
+                    pso::Stage::Fragment,
+                    "ps_main",
+                    b"float4 ps_main() : SV_Target { return float4(1.0, 1.0, 1.0, 1.0); }",
+                ),
+            ]);
+            let mut desc =
+                graphics_pipeline_desc(&modules[0], Some(&modules[1]), &layout, &render_pass, 0);
+            let blend = pso::BlendOp::Add {
+                src: pso::Factor::ConstColor,
+                dst: pso::Factor::Zero,
+            };
+            desc.blender.targets[0] = pso::ColorBlendDesc(
+                pso::ColorMask::ALL,
+                pso::BlendState::On {
+                    color: blend,
+                    alpha: blend,
+                },
+            );
+            let pipeline = device.create_graphics_pipeline(&desc, None).unwrap();
+
+            warp.submit(&mut queue_group, |cmd_buffer| {
+                begin_render_pass(
+                    cmd_buffer,
+                    &render_pass,
+                    &framebuffer,
+                    (2, 1),
+                    iter::once(clear_color([0.0; 4])),
+                );
+                // The blend constants are set before binding the pipeline and changed between
+                // the draws, each draw is restricted to one of the texels.
+                cmd_buffer.set_blend_constants([1.0, 0.0, 0.0, 1.0]);
+                cmd_buffer.bind_graphics_pipeline(&pipeline);
+                cmd_buffer.set_scissors(0, iter::once(pso::Rect { x: 0, y: 0, w: 1, h: 1 }));
+                cmd_buffer.draw(0 .. 3, 0 .. 1);
+                cmd_buffer.set_blend_constants([0.0, 0.0, 1.0, 1.0]);
+                cmd_buffer.set_scissors(0, iter::once(pso::Rect { x: 1, y: 0, w: 1, h: 1 }));
+                cmd_buffer.draw(0 .. 3, 0 .. 1);
+                cmd_buffer.end_render_pass();
+            });
 
-    unsafe fn dispatch(&mut self, count: WorkGroupCount) {
-        self.set_compute_bind_point();
-        self.raw.dispatch(count);
-    }
+            let texels =
+                warp.read_image::<[u8; 4]>(&mut queue_group, &target.image, Aspects::COLOR, (2, 1));
+            assert_eq!(texels, vec![[0xFF, 0, 0, 0xFF], [0, 0, 0xFF, 0xFF]]);
 
-    unsafe fn dispatch_indirect(&mut self, buffer: &r::Buffer, offset: buffer::Offset) {
-        let buffer = buffer.expect_bound();
-        self.set_compute_bind_point();
-        self.raw.ExecuteIndirect(
-            self.shared.signatures.dispatch.as_mut_ptr(),
-            1,
-            buffer.resource.as_mut_ptr(),
-            offset,
-            ptr::null_mut(),
-            0,
-        );
+            device.destroy_graphics_pipeline(pipeline);
+            warp.destroy_shader_modules(modules);
+            device.destroy_pipeline_layout(layout);
+            device.destroy_framebuffer(framebuffer);
+            device.destroy_render_pass(render_pass);
+            warp.destroy_target(target);
+        }
     }
 
-    unsafe fn fill_buffer<R>(&mut self, buffer: &r::Buffer, range: R, data: u32)
-    where
-        R: RangeArg<buffer::Offset>,
-    {
-        let buffer = buffer.expect_bound();
-        assert!(
-            buffer.clear_uav.is_some(),
-            "Buffer needs to be created with usage `TRANSFER_DST`"
-        );
-        let bytes_per_unit = 4;
-        let start = *range.start().unwrap_or(&0) as i32;
-        let end = *range.end().unwrap_or(&(buffer.requirements.size as u64)) as i32;
-        if start % 4 != 0 || end % 4 != 0 {
-            warn!("Fill buffer bounds have to be multiples of 4");
-        }
-        let _rect = d3d12::D3D12_RECT {
-            left: start / bytes_per_unit,
-            top: 0,
-            right: end / bytes_per_unit,
-            bottom: 1,
-        };
+    #[test]
+    fn test_warp_stencil_reference() {
+        use hal::command::RawCommandBuffer;
+        use hal::pass::{Attachment, AttachmentLoadOp, AttachmentOps, AttachmentStoreOp};
+        use hal::pass::{SubpassDependency, SubpassDesc};
+        use hal::{image, Device};
+        use std::iter;
 
-        // Insert barrier for `COPY_DEST` to `UNORDERED_ACCESS` as we use
-        // `TRANSFER_WRITE` for all clear commands.
-        let pre_barrier = Self::transition_barrier(d3d12::D3D12_RESOURCE_TRANSITION_BARRIER {
-            pResource: buffer.resource.as_mut_ptr(),
-            Subresource: d3d12::D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
-            StateBefore: d3d12::D3D12_RESOURCE_STATE_COPY_DEST,
-            StateAfter: d3d12::D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
-        });
-        self.raw.ResourceBarrier(1, &pre_barrier);
+        use test_utils::{begin_render_pass, clear_color, clear_depth_stencil};
+        use test_utils::{graphics_pipeline_desc, FULLSCREEN_VS};
 
-        error!("fill_buffer currently unimplemented");
-        // TODO: GPU handle must be in the current heap. Atm we use a CPU descriptor heap for allocation
-        //       which is not shader visible.
-        /*
-        let handle = buffer.clear_uav.unwrap();
+        let (warp, mut queue_group) = Warp::open("test_warp_stencil_reference");
+        let device = &warp.device;
         unsafe {
-            self.raw.ClearUnorderedAccessViewUint(
-                handle.gpu,
-                handle.cpu,
-                buffer.resource,
-                &[data as UINT; 4],
+            let target = warp.create_target(
+                Format::Rgba8Unorm,
+                (2, 1),
                 1,
-                &rect as *const _,
+                image::Usage::COLOR_ATTACHMENT | image::Usage::TRANSFER_SRC,
             );
-        }
-        */
+            let depth = warp.create_target(
+                Format::D24UnormS8Uint,
+                (2, 1),
+                1,
+                image::Usage::DEPTH_STENCIL_ATTACHMENT,
+            );
+            let render_pass = device
+                .create_render_pass(
+                    vec![
+                        Attachment {
+                            format: Some(Format::Rgba8Unorm),
+                            samples: 1,
+                            ops: AttachmentOps::new(
+                                AttachmentLoadOp::Clear,
+                                AttachmentStoreOp::Store,
+                            ),
+                            stencil_ops: AttachmentOps::DONT_CARE,
+                            layouts: image::Layout::Undefined .. image::Layout::TransferSrcOptimal,
+                        },
+                        Attachment {
+                            format: Some(Format::D24UnormS8Uint),
+                            samples: 1,
+                            ops: AttachmentOps::DONT_CARE,
+                            stencil_ops: AttachmentOps::new(
+                                AttachmentLoadOp::Clear,
+                                AttachmentStoreOp::DontCare,
+                            ),
+                            layouts: image::Layout::Undefined
+                                .. image::Layout::DepthStencilAttachmentOptimal,
+                        },
+                    ],
+                    iter::once(SubpassDesc {
+                        colors: &[(0, image::Layout::ColorAttachmentOptimal)],
+                        depth_stencil: Some(&(1, image::Layout::DepthStencilAttachmentOptimal)),
+                        inputs: &[],
+                        resolves: &[],
+                        preserves: &[],
+                    }),
+                    iter::empty::<SubpassDependency>(),
+                )
+                .unwrap();
+            let framebuffer =
+                warp.create_framebuffer(&render_pass, &[&target.view, &depth.view], (2, 1));
+            let layout = warp.create_pipeline_layout(&[]);
 
-        let post_barrier = Self::transition_barrier(d3d12::D3D12_RESOURCE_TRANSITION_BARRIER {
-            pResource: buffer.resource.as_mut_ptr(),
-            Subresource: d3d12::D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
-            StateBefore: d3d12::D3D12_RESOURCE_STATE_UNORDERED_ACCESS,
-            StateAfter: d3d12::D3D12_RESOURCE_STATE_COPY_DEST,
-        });
-        self.raw.ResourceBarrier(1, &post_barrier);
-    }
+            let modules = warp.create_shader_modules(&[
+                FULLSCREEN_VS,
+                (
+                    pso::Stage::Fragment,
+                    "ps_main",
+                    b"float4 ps_main() : SV_Target { return float4(1.0, 1.0, 1.0, 1.0); }",
+                ),
+            ]);
+            let mut desc =
+                graphics_pipeline_desc(&modules[0], Some(&modules[1]), &layout, &render_pass, 0);
+            // The first pipeline only writes the reference value into the stencil mask,
+            // the second one only draws where the mask equals the reference value.
+            let stencil_face = |fun, op_pass| pso::StencilFace {
+                fun,
+                mask_read: pso::State::Static(!0),
+                mask_write: pso::State::Static(!0),
+                op_fail: pso::StencilOp::Keep,
+                op_depth_fail: pso::StencilOp::Keep,
+                op_pass,
+                reference: pso::State::Dynamic,
+            };
+            let stencil_test = |fun, op_pass| pso::StencilTest::On {
+                front: stencil_face(fun, op_pass),
+                back: stencil_face(fun, op_pass),
+            };
+            desc.depth_stencil.stencil =
+                stencil_test(pso::Comparison::Always, pso::StencilOp::Replace);
+            desc.blender.targets[0] =
+                pso::ColorBlendDesc(pso::ColorMask::NONE, pso::BlendState::Off);
+            let mask_pipeline = device.create_graphics_pipeline(&desc, None).unwrap();
+            desc.depth_stencil.stencil = stencil_test(pso::Comparison::Equal, pso::StencilOp::Keep);
+            desc.blender.targets[0] = pso::ColorBlendDesc::EMPTY;
+            let pipeline = device.create_graphics_pipeline(&desc, None).unwrap();
+
+            warp.submit(&mut queue_group, |cmd_buffer| {
+                begin_render_pass(
+                    cmd_buffer,
+                    &render_pass,
+                    &framebuffer,
+                    (2, 1),
+                    vec![clear_color([0.0; 4]), clear_depth_stencil(1.0, 0)],
+                );
+                // Mask the left texel with 3, the right one keeps the cleared value.
+                cmd_buffer.bind_graphics_pipeline(&mask_pipeline);
+                cmd_buffer.set_stencil_reference(pso::Face::all(), 3);
+                cmd_buffer.set_scissors(0, iter::once(pso::Rect { x: 0, y: 0, w: 1, h: 1 }));
+                cmd_buffer.draw(0 .. 3, 0 .. 1);
+                cmd_buffer.bind_graphics_pipeline(&pipeline);
+                cmd_buffer.set_scissors(0, iter::once(pso::Rect { x: 0, y: 0, w: 2, h: 1 }));
+                cmd_buffer.draw(0 .. 3, 0 .. 1);
+                cmd_buffer.end_render_pass();
+            });
 
-    unsafe fn update_buffer(&mut self, _buffer: &r::Buffer, _offset: buffer::Offset, _data: &[u8]) {
-        unimplemented!()
+            let texels =
+                warp.read_image::<[u8; 4]>(&mut queue_group, &target.image, Aspects::COLOR, (2, 1));
+            assert_eq!(texels, vec![[0xFF; 4], [0; 4]]);
+
+            device.destroy_graphics_pipeline(pipeline);
+            device.destroy_graphics_pipeline(mask_pipeline);
+            warp.destroy_shader_modules(modules);
+            device.destroy_pipeline_layout(layout);
+            device.destroy_framebuffer(framebuffer);
+            device.destroy_render_pass(render_pass);
+            warp.destroy_target(target);
+            warp.destroy_target(depth);
+        }
     }
 
-    unsafe fn copy_buffer<T>(&mut self, src: &r::Buffer, dst: &r::Buffer, regions: T)
-    where
-        T: IntoIterator,
-        T::Item: Borrow<com::BufferCopy>,
-    {
-        let src = src.expect_bound();
-        let dst = dst.expect_bound();
-        // copy each region
-        for region in regions {
-            let region = region.borrow();
-            self.raw.CopyBufferRegion(
-                dst.resource.as_mut_ptr(),
-                region.dst as _,
-                src.resource.as_mut_ptr(),
-                region.src as _,
-                region.size as _,
-            );
+    #[test]
+    fn test_warp_dynamic_depth_bias() {
+        use hal::command::RawCommandBuffer;
+        use hal::pass::{Attachment, AttachmentLoadOp, AttachmentOps, AttachmentStoreOp};
+        use hal::pass::{SubpassDependency, SubpassDesc};
+        use hal::{image, Device};
+        use std::iter;
+
+        use test_utils::{begin_render_pass, clear_depth_stencil, graphics_pipeline_desc};
+
+        let (warp, mut queue_group) = Warp::open("test_warp_dynamic_depth_bias");
+        let device = &warp.device;
+        // Older runtimes can only bake the depth bias into the pipeline.
+        if !warp.adapter.physical_device.supports_dynamic_depth_bias() {
+            return;
         }
+        unsafe {
+            let depth = warp.create_target(
+                Format::D16Unorm,
+                (2, 1),
+                1,
+                image::Usage::DEPTH_STENCIL_ATTACHMENT | image::Usage::TRANSFER_SRC,
+            );
+            let render_pass = device
+                .create_render_pass(
+                    iter::once(Attachment {
+                        format: Some(Format::D16Unorm),
+                        samples: 1,
+                        ops: AttachmentOps::new(AttachmentLoadOp::Clear, AttachmentStoreOp::Store),
+                        stencil_ops: AttachmentOps::DONT_CARE,
+                        layouts: image::Layout::Undefined .. image::Layout::TransferSrcOptimal,
+                    }),
+                    iter::once(SubpassDesc {
+                        colors: &[],
+                        depth_stencil: Some(&(0, image::Layout::DepthStencilAttachmentOptimal)),
+                        inputs: &[],
+                        resolves: &[],
+                        preserves: &[],
+                    }),
+                    iter::empty::<SubpassDependency>(),
+                )
+                .unwrap();
+            let framebuffer = warp.create_framebuffer(&render_pass, &[&depth.view], (2, 1));
+            let layout = warp.create_pipeline_layout(&[]);
+
+            // Fullscreen triangle at a depth of 0.5.
+            let modules = warp.create_shader_modules(&[(
+                pso::Stage::Vertex,
+                "vs_main",
+                b"float4 vs_main(uint id : SV_VertexID) : SV_Position {
+                    float2 uv = float2((id << 1) & 2, id & 2);
+                    return float4(uv * float2(2.0, -2.0) + float2(-1.0, 1.0), 0.5, 1.0);
+                }",
+            )]);
+            let mut desc = graphics_pipeline_desc(&modules[0], None, &layout, &render_pass, 0);
+            desc.rasterizer.depth_bias = Some(pso::State::Dynamic);
+            desc.depth_stencil.depth = pso::DepthTest::PASS_WRITE;
+            let pipeline = device.create_graphics_pipeline(&desc, None).unwrap();
+
+            warp.submit(&mut queue_group, |cmd_buffer| {
+                begin_render_pass(
+                    cmd_buffer,
+                    &render_pass,
+                    &framebuffer,
+                    (2, 1),
+                    iter::once(clear_depth_stencil(1.0, 0)),
+                );
+                // The constant bias of 16-bit depth is scaled by 2^-16.
+                cmd_buffer.bind_graphics_pipeline(&pipeline);
+                cmd_buffer.set_depth_bias(pso::DepthBias {
+                    const_factor: 100.0,
+                    clamp: 0.0,
+                    slope_factor: 0.0,
+                });
+                cmd_buffer.draw(0 .. 3, 0 .. 1);
+                cmd_buffer.end_render_pass();
+            });
 
-        // TODO: Optimization: Copy whole resource if possible
+            // 0.5 is stored as 32768, the bias adds about 100 to it.
+            let texels =
+                warp.read_image::<u16>(&mut queue_group, &depth.image, Aspects::DEPTH, (2, 1));
+            for texel in texels {
+                assert!((texel as i32 - 32868).abs() <= 1, "depth {}", texel);
+            }
+
+            device.destroy_graphics_pipeline(pipeline);
+            warp.destroy_shader_modules(modules);
+            device.destroy_pipeline_layout(layout);
+            device.destroy_framebuffer(framebuffer);
+            device.destroy_render_pass(render_pass);
+            warp.destroy_target(depth);
+        }
     }
 
-    unsafe fn copy_image<T>(
-        &mut self,
-        src: &r::Image,
-        _: image::Layout,
-        dst: &r::Image,
-        _: image::Layout,
-        regions: T,
-    ) where
-        T: IntoIterator,
-        T::Item: Borrow<com::ImageCopy>,
-    {
-        let src = src.expect_bound();
-        let dst = dst.expect_bound();
-        let mut src_image = d3d12::D3D12_TEXTURE_COPY_LOCATION {
-            pResource: src.resource.as_mut_ptr(),
-            Type: d3d12::D3D12_TEXTURE_COPY_TYPE_SUBRESOURCE_INDEX,
-            u: mem::zeroed(),
-        };
-        let mut dst_image = d3d12::D3D12_TEXTURE_COPY_LOCATION {
-            pResource: dst.resource.as_mut_ptr(),
-            Type: d3d12::D3D12_TEXTURE_COPY_TYPE_SUBRESOURCE_INDEX,
-            u: mem::zeroed(),
+    #[test]
+    fn test_warp_depth_bounds() {
+        use hal::command::RawCommandBuffer;
+        use hal::pass::{Attachment, AttachmentLoadOp, AttachmentOps, AttachmentStoreOp};
+        use hal::pass::{SubpassDependency, SubpassDesc};
+        use hal::{image, Device};
+        use std::iter;
+
+        use test_utils::{
+            begin_render_pass,
+            clear_color,
+            clear_depth_stencil,
+            graphics_pipeline_desc,
+            FULLSCREEN_VS,
         };
 
-        let device = self.shared.service_pipes.device.clone();
-        let src_desc = src.surface_type.desc();
-        let dst_desc = dst.surface_type.desc();
-        assert_eq!(src_desc.bits, dst_desc.bits);
-        //Note: Direct3D 10.1 enables copies between prestructured-typed textures
-        // and block-compressed textures of the same bit widths.
-        let do_alias = src.surface_type != dst.surface_type
-            && src_desc.is_compressed() == dst_desc.is_compressed();
-
-        if do_alias {
-            // D3D12 only permits changing the channel type for copies,
-            // similarly to how it allows the views to be created.
-
-            // create an aliased resource to the source
-            let mut alias = native::Resource::null();
-            let desc = d3d12::D3D12_RESOURCE_DESC {
-                Format: dst.descriptor.Format,
-                ..src.descriptor.clone()
+        let (warp, mut queue_group) =
+            match Warp::open_with_features("test_warp_depth_bounds", Features::DEPTH_BOUNDS) {
+                Some(warp) => warp,
+                None => return,
             };
-            let (heap_ptr, offset) = match src.place {
-                r::Place::SwapChain => {
-                    error!("Unable to copy from a swapchain image with format conversion: {:?} -> {:?}",
-                        src.descriptor.Format, dst.descriptor.Format);
-                    return;
-                }
-                r::Place::Heap { ref raw, offset } => (raw.as_mut_ptr(), offset),
+        let device = &warp.device;
+        unsafe {
+            let color = warp.create_target(
+                Format::Rgba8Unorm,
+                (2, 1),
+                1,
+                image::Usage::COLOR_ATTACHMENT | image::Usage::TRANSFER_SRC,
+            );
+            let depth = warp.create_target(
+                Format::D32Sfloat,
+                (2, 1),
+                1,
+                image::Usage::DEPTH_STENCIL_ATTACHMENT,
+            );
+            let attachment = |format, store, layout| Attachment {
+                format: Some(format),
+                samples: 1,
+                ops: AttachmentOps::new(AttachmentLoadOp::Clear, store),
+                stencil_ops: AttachmentOps::DONT_CARE,
+                layouts: image::Layout::Undefined .. layout,
             };
-            assert_eq!(
-                winerror::S_OK,
-                device.CreatePlacedResource(
-                    heap_ptr,
-                    offset,
-                    &desc,
-                    d3d12::D3D12_RESOURCE_STATE_COMMON,
-                    ptr::null(),
-                    &d3d12::ID3D12Resource::uuidof(),
-                    alias.mut_void(),
+            let render_pass = device
+                .create_render_pass(
+                    vec![
+                        attachment(
+                            Format::Rgba8Unorm,
+                            AttachmentStoreOp::Store,
+                            image::Layout::TransferSrcOptimal,
+                        ),
+                        attachment(
+                            Format::D32Sfloat,
+                            AttachmentStoreOp::DontCare,
+                            image::Layout::DepthStencilAttachmentOptimal,
+                        ),
+                    ],
+                    iter::once(SubpassDesc {
+                        colors: &[(0, image::Layout::ColorAttachmentOptimal)],
+                        depth_stencil: Some(&(1, image::Layout::DepthStencilAttachmentOptimal)),
+                        inputs: &[],
+                        resolves: &[],
+                        preserves: &[],
+                    }),
+                    iter::empty::<SubpassDependency>(),
                 )
-            );
-            src_image.pResource = alias.as_mut_ptr();
-            self.retained_resources.push(alias);
+                .unwrap();
+            let framebuffer =
+                warp.create_framebuffer(&render_pass, &[&color.view, &depth.view], (2, 1));
+            let layout = warp.create_pipeline_layout(&[]);
+            let modules = warp.create_shader_modules(&[
+                FULLSCREEN_VS,
+                (
+                    pso::Stage::Fragment,
+                    "ps_main",
+                    b"float4 ps_main() : SV_Target { return float4(1.0, 0.0, 0.0, 1.0); }",
+                ),
+            ]);
+            let mut desc =
+                graphics_pipeline_desc(&modules[0], Some(&modules[1]), &layout, &render_pass, 0);
+            desc.depth_stencil.depth = pso::DepthTest::PASS_TEST;
+            desc.depth_stencil.depth_bounds = true;
+            let pipeline = device.create_graphics_pipeline(&desc, None).unwrap();
+
+            // The stored depth of 0.5 is outside of the bounds of the left draw and inside
+            // the bounds of the right draw.
+            warp.submit(&mut queue_group, |cmd_buffer| {
+                begin_render_pass(
+                    cmd_buffer,
+                    &render_pass,
+                    &framebuffer,
+                    (2, 1),
+                    vec![clear_color([0.0; 4]), clear_depth_stencil(0.5, 0)],
+                );
+                cmd_buffer.bind_graphics_pipeline(&pipeline);
+                for &(x, ref bounds) in &[(0, 0.0 .. 0.25), (1, 0.25 .. 0.75)] {
+                    cmd_buffer.set_scissors(0, iter::once(pso::Rect { x, y: 0, w: 1, h: 1 }));
+                    cmd_buffer.set_depth_bounds(bounds.clone());
+                    cmd_buffer.draw(0 .. 3, 0 .. 1);
+                }
+                cmd_buffer.end_render_pass();
+            });
 
-            // signal the aliasing transition
-            let sub_barrier = d3d12::D3D12_RESOURCE_ALIASING_BARRIER {
-                pResourceBefore: src.resource.as_mut_ptr(),
-                pResourceAfter: src_image.pResource,
+            let texels =
+                warp.read_image::<[u8; 4]>(&mut queue_group, &color.image, Aspects::COLOR, (2, 1));
+            assert_eq!(texels, vec![[0, 0, 0, 0], [0xFF, 0, 0, 0xFF]]);
+
+            device.destroy_graphics_pipeline(pipeline);
+            warp.destroy_shader_modules(modules);
+            device.destroy_pipeline_layout(layout);
+            device.destroy_framebuffer(framebuffer);
+            device.destroy_render_pass(render_pass);
+            warp.destroy_target(color);
+            warp.destroy_target(depth);
+        }
+    }
+
+    #[test]
+    fn test_warp_subpass_dependency() {
+        use hal::command::{RawCommandBuffer, SubpassContents};
+        use hal::pass::{Attachment, AttachmentLoadOp, AttachmentOps, AttachmentStoreOp};
+        use hal::pass::{SubpassDependency, SubpassDesc, SubpassRef};
+        use hal::pso::{DescriptorPool, PipelineStage};
+        use hal::{image, Device};
+        use std::iter;
+
+        use test_utils::{begin_render_pass, clear_color, graphics_pipeline_desc, FULLSCREEN_VS};
+
+        let (warp, mut queue_group) = Warp::open("test_warp_subpass_dependency");
+        let device = &warp.device;
+        unsafe {
+            // The first subpass renders into `source`, the second one samples it.
+            let create_target = |usage| {
+                warp.create_target(
+                    Format::Rgba8Unorm,
+                    (2, 1),
+                    1,
+                    image::Usage::COLOR_ATTACHMENT | usage,
+                )
             };
-            let mut barrier = d3d12::D3D12_RESOURCE_BARRIER {
-                Type: d3d12::D3D12_RESOURCE_BARRIER_TYPE_ALIASING,
-                Flags: d3d12::D3D12_RESOURCE_BARRIER_FLAG_NONE,
-                u: mem::zeroed(),
+            let source = create_target(image::Usage::SAMPLED);
+            let target = create_target(image::Usage::TRANSFER_SRC);
+
+            let attachment = |store, layout| Attachment {
+                format: Some(Format::Rgba8Unorm),
+                samples: 1,
+                ops: AttachmentOps::new(AttachmentLoadOp::Clear, store),
+                stencil_ops: AttachmentOps::DONT_CARE,
+                layouts: image::Layout::Undefined .. layout,
             };
-            *barrier.u.Aliasing_mut() = sub_barrier;
-            self.raw.ResourceBarrier(1, &barrier as *const _);
+            let render_pass = device
+                .create_render_pass(
+                    vec![
+                        attachment(
+                            AttachmentStoreOp::DontCare,
+                            image::Layout::ShaderReadOnlyOptimal,
+                        ),
+                        attachment(AttachmentStoreOp::Store, image::Layout::TransferSrcOptimal),
+                    ],
+                    vec![
+                        SubpassDesc {
+                            colors: &[(0, image::Layout::ColorAttachmentOptimal)],
+                            depth_stencil: None,
+                            inputs: &[],
+                            resolves: &[],
+                            preserves: &[],
+                        },
+                        SubpassDesc {
+                            colors: &[(1, image::Layout::ColorAttachmentOptimal)],
+                            depth_stencil: None,
+                            inputs: &[(0, image::Layout::ShaderReadOnlyOptimal)],
+                            resolves: &[],
+                            preserves: &[],
+                        },
+                    ],
+                    iter::once(SubpassDependency {
+                        passes: SubpassRef::Pass(0) .. SubpassRef::Pass(1),
+                        stages: PipelineStage::COLOR_ATTACHMENT_OUTPUT
+                            .. PipelineStage::FRAGMENT_SHADER,
+                        accesses: image::Access::COLOR_ATTACHMENT_WRITE
+                            .. image::Access::SHADER_READ,
+                    }),
+                )
+                .unwrap();
+            let framebuffer =
+                warp.create_framebuffer(&render_pass, &[&source.view, &target.view], (2, 1));
+            let binding = pso::DescriptorSetLayoutBinding {
+                binding: 0,
+                ty: pso::DescriptorType::SampledImage,
+                count: 1,
+                stage_flags: pso::ShaderStageFlags::FRAGMENT,
+                immutable_samplers: false,
+            };
+            let set_layout = device
+                .create_descriptor_set_layout(iter::once(&binding), &[])
+                .unwrap();
+            let layouts = vec![
+                warp.create_pipeline_layout(&[]),
+                warp.create_pipeline_layout(&[&set_layout]),
+            ];
+            let mut pool = device
+                .create_descriptor_pool(
+                    1,
+                    iter::once(pso::DescriptorRangeDesc {
+                        ty: pso::DescriptorType::SampledImage,
+                        count: 1,
+                    }),
+                    pso::DescriptorPoolCreateFlags::empty(),
+                )
+                .unwrap();
+            let set = pool.allocate_set(&set_layout).unwrap();
+            device.write_descriptor_sets(iter::once(pso::DescriptorSetWrite {
+                set: &set,
+                binding: 0,
+                array_offset: 0,
+                descriptors: iter::once(pso::Descriptor::Image(
+                    &source.view,
+                    image::Layout::ShaderReadOnlyOptimal,
+                )),
+            }));
+
+            // The first subpass writes red and green texels, the second one
+            // swaps the channels of the texel at the same position.
+            let modules = warp.create_shader_modules(&[
+                FULLSCREEN_VS,
+                (
+                    pso::Stage::Fragment,
+                    "ps_main",
+                    b"float4 ps_main(float4 pos : SV_Position) : SV_Target {
+                        return pos.x < 1.0 ? float4(1.0, 0.0, 0.0, 1.0)
+                                           : float4(0.0, 1.0, 0.0, 1.0);
+                    }",
+                ),
+                (
+                    pso::Stage::Fragment,
+                    "ps_main",
+                    b"Texture2D<float4> source : register(t0);
+                    float4 ps_main(float4 pos : SV_Position) : SV_Target {
+                        return source.Load(int3(pos.xy, 0)).gbra;
+                    }",
+                ),
+            ]);
+            let pipelines = (0 .. 2)
+                .map(|i| {
+                    let desc = graphics_pipeline_desc(
+                        &modules[0],
+                        Some(&modules[1 + i]),
+                        &layouts[i],
+                        &render_pass,
+                        i,
+                    );
+                    device.create_graphics_pipeline(&desc, None).unwrap()
+                })
+                .collect::<Vec<_>>();
+
+            warp.submit(&mut queue_group, |cmd_buffer| {
+                begin_render_pass(
+                    cmd_buffer,
+                    &render_pass,
+                    &framebuffer,
+                    (2, 1),
+                    iter::repeat(clear_color([0.0; 4])).take(2),
+                );
+                cmd_buffer.bind_graphics_pipeline(&pipelines[0]);
+                cmd_buffer.draw(0 .. 3, 0 .. 1);
+                cmd_buffer.next_subpass(SubpassContents::Inline);
+                cmd_buffer.bind_graphics_pipeline(&pipelines[1]);
+                cmd_buffer.bind_graphics_descriptor_sets(&layouts[1], 0, iter::once(&set), &[]);
+                cmd_buffer.draw(0 .. 3, 0 .. 1);
+                cmd_buffer.end_render_pass();
+            });
+
+            let texels =
+                warp.read_image::<[u8; 4]>(&mut queue_group, &target.image, Aspects::COLOR, (2, 1));
+            assert_eq!(texels, vec![[0, 0, 0xFF, 0xFF], [0xFF, 0, 0, 0xFF]]);
+
+            for pipeline in pipelines {
+                device.destroy_graphics_pipeline(pipeline);
+            }
+            warp.destroy_shader_modules(modules);
+            device.destroy_descriptor_pool(pool);
+            for layout in layouts {
+                device.destroy_pipeline_layout(layout);
+            }
+            device.destroy_descriptor_set_layout(set_layout);
+            device.destroy_framebuffer(framebuffer);
+            device.destroy_render_pass(render_pass);
+            warp.destroy_target(source);
+            warp.destroy_target(target);
         }
+    }
 
-        for region in regions {
-            let r = region.borrow();
-            debug_assert_eq!(
-                r.src_subresource.layers.len(),
-                r.dst_subresource.layers.len()
+    #[test]
+    fn test_warp_reset_individual_command_buffer() {
+        use hal::command::{BufferCopy, CommandBufferFlags, RawCommandBuffer, RawLevel};
+        use hal::memory::Properties;
+        use hal::pool::{CommandPoolCreateFlags, RawCommandPool};
+        use hal::queue::{RawCommandQueue, Submission};
+        use hal::{buffer, Device};
+        use std::{iter, ptr};
+
+        use super::CommandBuffer;
+        use pool::CommandPoolAllocator;
+        use resource::Semaphore;
+
+        let (warp, mut queue_group) = Warp::open("test_warp_reset_individual_command_buffer");
+        let device = &warp.device;
+        unsafe {
+            let (upload, upload_memory) = warp.create_buffer(
+                12,
+                buffer::Usage::TRANSFER_SRC,
+                Properties::CPU_VISIBLE | Properties::COHERENT,
             );
-            let src_box = d3d12::D3D12_BOX {
-                left: r.src_offset.x as _,
-                top: r.src_offset.y as _,
-                right: (r.src_offset.x + r.extent.width as i32) as _,
-                bottom: (r.src_offset.y + r.extent.height as i32) as _,
-                front: r.src_offset.z as _,
-                back: (r.src_offset.z + r.extent.depth as i32) as _,
+            let (readback, readback_memory) = warp.create_buffer(
+                8,
+                buffer::Usage::TRANSFER_DST,
+                Properties::CPU_VISIBLE | Properties::CPU_CACHED,
+            );
+            let data = device.map_memory(&upload_memory, 0 .. 12).unwrap();
+            ptr::copy_nonoverlapping([1u32, 2, 3].as_ptr(), data as *mut u32, 3);
+            device.unmap_memory(&upload_memory);
+
+            let mut command_pool = device
+                .create_command_pool(
+                    queue_group.family(),
+                    CommandPoolCreateFlags::RESET_INDIVIDUAL,
+                )
+                .unwrap();
+            let mut cmd_buffers = command_pool.allocate_vec(2, RawLevel::Primary);
+            // Each command buffer records into a dedicated allocator.
+            let allocators = cmd_buffers
+                .iter()
+                .map(|cmd_buffer| cmd_buffer.owned_allocator().unwrap())
+                .collect::<Vec<_>>();
+            assert!(allocators[0] != allocators[1]);
+
+            let copy_word = |cmd_buffer: &mut CommandBuffer, src: u64, dst: u64| {
+                cmd_buffer.begin(CommandBufferFlags::empty(), Default::default());
+                cmd_buffer.copy_buffer(
+                    &upload,
+                    &readback,
+                    iter::once(BufferCopy { src, dst, size: 4 }),
+                );
+                cmd_buffer.finish();
             };
+            copy_word(&mut cmd_buffers[0], 0, 0);
+            copy_word(&mut cmd_buffers[1], 4, 4);
+
+            // Re-record the first command buffer, the second one stays recorded.
+            cmd_buffers[0].reset(false);
+            copy_word(&mut cmd_buffers[0], 8, 0);
+
+            let fence = device.create_fence(false).unwrap();
+            queue_group.queues[0].as_raw_mut().submit(
+                Submission {
+                    command_buffers: &cmd_buffers,
+                    wait_semaphores: iter::empty::<(&Semaphore, _)>(),
+                    signal_semaphores: iter::empty::<&Semaphore>(),
+                },
+                Some(&fence),
+            );
+            assert_eq!(device.wait_for_fence(&fence, !0), Ok(true));
 
-            for (src_layer, dst_layer) in r
-                .src_subresource
-                .layers
-                .clone()
-                .zip(r.dst_subresource.layers.clone())
-            {
-                *src_image.u.SubresourceIndex_mut() =
-                    src.calc_subresource(r.src_subresource.level as _, src_layer as _, 0);
-                *dst_image.u.SubresourceIndex_mut() =
-                    dst.calc_subresource(r.dst_subresource.level as _, dst_layer as _, 0);
-                self.raw.CopyTextureRegion(
-                    &dst_image,
-                    r.dst_offset.x as _,
-                    r.dst_offset.y as _,
-                    r.dst_offset.z as _,
-                    &src_image,
-                    &src_box,
-                );
+            let data = device.map_memory(&readback_memory, 0 .. 8).unwrap();
+            assert_eq!(ptr::read(data as *const [u32; 2]), [3, 2]);
+            device.unmap_memory(&readback_memory);
+
+            // Freeing a command buffer releases its allocator.
+            command_pool.free(cmd_buffers.drain(.. 1));
+            match command_pool.allocator {
+                CommandPoolAllocator::Individual(ref allocators) => assert_eq!(allocators.len(), 1),
+                CommandPoolAllocator::Shared(_) => panic!("Expected individual allocators"),
+            }
+
+            device.destroy_fence(fence);
+            command_pool.free(cmd_buffers);
+            device.destroy_command_pool(command_pool);
+            for (buffer, memory) in vec![(upload, upload_memory), (readback, readback_memory)] {
+                device.destroy_buffer(buffer);
+                device.free_memory(memory);
             }
         }
+    }
 
-        if do_alias {
-            // signal the aliasing transition - back to the original
-            let sub_barrier = d3d12::D3D12_RESOURCE_ALIASING_BARRIER {
-                pResourceBefore: src_image.pResource,
-                pResourceAfter: src.resource.as_mut_ptr(),
+    #[test]
+    fn test_warp_generate_mipmaps() {
+        use hal::command::{BufferImageCopy, CommandBufferFlags, RawCommandBuffer, RawLevel};
+        use hal::memory::{Barrier, Dependencies, Properties};
+        use hal::pool::{CommandPoolCreateFlags, RawCommandPool};
+        use hal::pso::PipelineStage;
+        use hal::queue::{RawCommandQueue, Submission};
+        use hal::{buffer, image, Device};
+        use std::{iter, slice};
+
+        use resource::Semaphore;
+
+        let (warp, mut queue_group) = Warp::open("test_warp_generate_mipmaps");
+        let device = &warp.device;
+        unsafe {
+            // Rows of 256 bytes keep the buffer copies aligned.
+            const ROW_PITCH: u32 = 256;
+
+            let mut image = device
+                .create_image(
+                    image::Kind::D2(6, 6, 1, 1),
+                    3,
+                    Format::Rgba8Unorm,
+                    image::Tiling::Optimal,
+                    image::Usage::TRANSFER_DST
+                        | image::Usage::TRANSFER_SRC
+                        | image::Usage::SAMPLED
+                        | image::Usage::STORAGE,
+                    image::ViewCapabilities::empty(),
+                )
+                .unwrap();
+            let requirements = device.get_image_requirements(&image);
+            let image_memory = warp.allocate(
+                requirements.type_mask,
+                requirements.size,
+                Properties::DEVICE_LOCAL,
+            );
+            device.bind_image_memory(&image_memory, 0, &mut image).unwrap();
+
+            let mut buffers = Vec::new();
+            for &(usage, properties) in &[
+                (buffer::Usage::TRANSFER_SRC, Properties::CPU_VISIBLE),
+                (
+                    buffer::Usage::TRANSFER_DST,
+                    Properties::CPU_VISIBLE | Properties::CPU_CACHED,
+                ),
+            ] {
+                let mut buffer = device
+                    .create_buffer((ROW_PITCH * 6) as u64, usage)
+                    .unwrap();
+                let requirements = device.get_buffer_requirements(&buffer);
+                let memory = warp.allocate(requirements.type_mask, requirements.size, properties);
+                device.bind_buffer_memory(&memory, 0, &mut buffer).unwrap();
+                buffers.push((buffer, memory));
+            }
+
+            // Level 0 consists of 3x3 blocks of 2x2 texels, the red channel of the
+            // blocks increases in steps of 20, blue stays constant.
+            let data = device
+                .map_memory(&buffers[0].1, 0 .. (ROW_PITCH * 6) as u64)
+                .unwrap();
+            let data = slice::from_raw_parts_mut(data, (ROW_PITCH * 6) as usize);
+            for y in 0 .. 6 {
+                for x in 0 .. 6 {
+                    let red = (20 * (3 * (y / 2) + x / 2)) as u8;
+                    let offset = (y * ROW_PITCH + 4 * x) as usize;
+                    data[offset .. offset + 4].copy_from_slice(&[red, 255 - red, 60, 255]);
+                }
+            }
+            device
+                .flush_mapped_memory_ranges(iter::once((&buffers[0].1, ..)))
+                .unwrap();
+            device.unmap_memory(&buffers[0].1);
+
+            let color_range = |levels| image::SubresourceRange {
+                aspects: Aspects::COLOR,
+                levels,
+                layers: 0 .. 1,
             };
-            let mut barrier = d3d12::D3D12_RESOURCE_BARRIER {
-                Type: d3d12::D3D12_RESOURCE_BARRIER_TYPE_ALIASING,
-                Flags: d3d12::D3D12_RESOURCE_BARRIER_FLAG_NONE,
-                u: mem::zeroed(),
+            let copy = |level| BufferImageCopy {
+                buffer_offset: 0,
+                buffer_width: ROW_PITCH / 4,
+                buffer_height: 6,
+                image_layers: image::SubresourceLayers {
+                    aspects: Aspects::COLOR,
+                    level,
+                    layers: 0 .. 1,
+                },
+                image_offset: image::Offset::ZERO,
+                image_extent: image::Kind::D2(6, 6, 1, 1).level_extent(level),
             };
-            *barrier.u.Aliasing_mut() = sub_barrier;
-            self.raw.ResourceBarrier(1, &barrier as *const _);
+
+            let mut command_pool = device
+                .create_command_pool(queue_group.family(), CommandPoolCreateFlags::empty())
+                .unwrap();
+            let mut cmd_buffer = command_pool.allocate_one(RawLevel::Primary);
+            cmd_buffer.begin(CommandBufferFlags::ONE_TIME_SUBMIT, Default::default());
+            cmd_buffer.pipeline_barrier(
+                PipelineStage::TOP_OF_PIPE .. PipelineStage::TRANSFER,
+                Dependencies::empty(),
+                iter::once(Barrier::Image {
+                    states: (image::Access::empty(), image::Layout::Undefined)
+                        .. (
+                            image::Access::TRANSFER_WRITE,
+                            image::Layout::TransferDstOptimal,
+                        ),
+                    target: &image,
+                    families: None,
+                    range: color_range(0 .. 3),
+                }),
+            );
+            cmd_buffer.copy_buffer_to_image(
+                &buffers[0].0,
+                &image,
+                image::Layout::TransferDstOptimal,
+                iter::once(copy(0)),
+            );
+            cmd_buffer.generate_mipmaps(&image, color_range(0 .. 3));
+            cmd_buffer.pipeline_barrier(
+                PipelineStage::COMPUTE_SHADER .. PipelineStage::TRANSFER,
+                Dependencies::empty(),
+                iter::once(Barrier::Image {
+                    states: (
+                        image::Access::SHADER_READ,
+                        image::Layout::ShaderReadOnlyOptimal,
+                    )
+                        .. (
+                            image::Access::TRANSFER_READ,
+                            image::Layout::TransferSrcOptimal,
+                        ),
+                    target: &image,
+                    families: None,
+                    range: color_range(2 .. 3),
+                }),
+            );
+            cmd_buffer.copy_image_to_buffer(
+                &image,
+                image::Layout::TransferSrcOptimal,
+                &buffers[1].0,
+                iter::once(copy(2)),
+            );
+            cmd_buffer.finish();
+
+            let fence = device.create_fence(false).unwrap();
+            queue_group.queues[0].as_raw_mut().submit(
+                Submission {
+                    command_buffers: iter::once(&cmd_buffer),
+                    wait_semaphores: iter::empty::<(&Semaphore, _)>(),
+                    signal_semaphores: iter::empty::<&Semaphore>(),
+                },
+                Some(&fence),
+            );
+            assert_eq!(device.wait_for_fence(&fence, !0), Ok(true));
+
+            // The 1x1 level averages the 3x3 level, which averages the blocks of level 0.
+            let data = device.map_memory(&buffers[1].1, 0 .. 4).unwrap();
+            let texel = slice::from_raw_parts(data, 4);
+            let expected = [80u8, 175, 60, 255];
+            for (&value, &expected) in texel.iter().zip(&expected) {
+                assert!((value as i32 - expected as i32).abs() <= 1, "{:?}", texel);
+            }
+            device.unmap_memory(&buffers[1].1);
+
+            device.destroy_fence(fence);
+            command_pool.free(iter::once(cmd_buffer));
+            device.destroy_command_pool(command_pool);
+            device.destroy_image(image);
+            device.free_memory(image_memory);
+            for (buffer, memory) in buffers {
+                device.destroy_buffer(buffer);
+                device.free_memory(memory);
+            }
         }
     }
 
-    unsafe fn copy_buffer_to_image<T>(
-        &mut self,
-        buffer: &r::Buffer,
-        image: &r::Image,
-        _: image::Layout,
-        regions: T,
-    ) where
-        T: IntoIterator,
-        T::Item: Borrow<com::BufferImageCopy>,
-    {
-        let buffer = buffer.expect_bound();
-        let image = image.expect_bound();
-        assert!(self.copies.is_empty());
+    #[test]
+    fn test_warp_clear_image() {
+        use hal::command::{
+            BufferImageCopy,
+            ClearColorRaw,
+            ClearDepthStencilRaw,
+            CommandBufferFlags,
+            RawCommandBuffer,
+            RawLevel,
+        };
+        use hal::memory::{Barrier, Dependencies, Properties};
+        use hal::pool::{CommandPoolCreateFlags, RawCommandPool};
+        use hal::pso::PipelineStage;
+        use hal::queue::{RawCommandQueue, Submission};
+        use hal::{buffer, image, Device};
+        use std::{iter, slice};
 
-        for region in regions {
-            let r = region.borrow();
-            Self::split_buffer_copy(&mut self.copies, r, image);
-        }
+        use resource::Semaphore;
 
-        if self.copies.is_empty() {
-            return;
+        let (warp, mut queue_group) = Warp::open("test_warp_clear_image");
+        let device = &warp.device;
+        unsafe {
+            // Color image cleared at level 1 through a transient RTV, a storage image
+            // without render target support cleared through a UAV and a depth image.
+            let transfer = image::Usage::TRANSFER_DST | image::Usage::TRANSFER_SRC;
+            let targets = [
+                (Format::Rgba8Unorm, 2, transfer, Aspects::COLOR, 1),
+                (
+                    Format::R32Uint,
+                    1,
+                    image::Usage::STORAGE | image::Usage::TRANSFER_SRC,
+                    Aspects::COLOR,
+                    0,
+                ),
+                (
+                    Format::D32Sfloat,
+                    1,
+                    transfer | image::Usage::DEPTH_STENCIL_ATTACHMENT,
+                    Aspects::DEPTH,
+                    0,
+                ),
+            ];
+            let mut images = Vec::new();
+            for &(format, levels, usage, _, _) in &targets {
+                let mut image = device
+                    .create_image(
+                        image::Kind::D2(4, 4, 1, 1),
+                        levels,
+                        format,
+                        image::Tiling::Optimal,
+                        usage,
+                        image::ViewCapabilities::empty(),
+                    )
+                    .unwrap();
+                let requirements = device.get_image_requirements(&image);
+                let memory = warp.allocate(
+                    requirements.type_mask,
+                    requirements.size,
+                    Properties::DEVICE_LOCAL,
+                );
+                device.bind_image_memory(&memory, 0, &mut image).unwrap();
+                images.push((image, memory));
+            }
+
+            let mut readback = device
+                .create_buffer(512 * 3, buffer::Usage::TRANSFER_DST)
+                .unwrap();
+            let requirements = device.get_buffer_requirements(&readback);
+            let readback_memory = warp.allocate(
+                requirements.type_mask,
+                requirements.size,
+                Properties::CPU_VISIBLE | Properties::CPU_CACHED,
+            );
+            device
+                .bind_buffer_memory(&readback_memory, 0, &mut readback)
+                .unwrap();
+
+            let mut command_pool = device
+                .create_command_pool(queue_group.family(), CommandPoolCreateFlags::empty())
+                .unwrap();
+            let mut cmd_buffer = command_pool.allocate_one(RawLevel::Primary);
+            cmd_buffer.begin(CommandBufferFlags::ONE_TIME_SUBMIT, Default::default());
+            for (i, (&(_, levels, _, aspects, level), &(ref image, _))) in
+                targets.iter().zip(&images).enumerate()
+            {
+                let range = image::SubresourceRange {
+                    aspects,
+                    levels: 0 .. levels,
+                    layers: 0 .. 1,
+                };
+                cmd_buffer.pipeline_barrier(
+                    PipelineStage::TOP_OF_PIPE .. PipelineStage::TRANSFER,
+                    Dependencies::empty(),
+                    iter::once(Barrier::Image {
+                        states: (image::Access::empty(), image::Layout::Undefined)
+                            .. (
+                                image::Access::TRANSFER_WRITE,
+                                image::Layout::TransferDstOptimal,
+                            ),
+                        target: image,
+                        families: None,
+                        range: range.clone(),
+                    }),
+                );
+                cmd_buffer.clear_image(
+                    image,
+                    image::Layout::TransferDstOptimal,
+                    ClearColorRaw {
+                        float32: if i == 0 {
+                            [1.0, 0.0, 0.0, 1.0]
+                        } else {
+                            [0.0; 4]
+                        },
+                    },
+                    ClearDepthStencilRaw {
+                        depth: 0.25,
+                        stencil: 0,
+                    },
+                    iter::once(image::SubresourceRange {
+                        levels: level .. level + 1,
+                        ..range.clone()
+                    }),
+                );
+                if i == 1 {
+                    // Integer storage images are cleared with the unsigned values.
+                    cmd_buffer.clear_image(
+                        image,
+                        image::Layout::TransferDstOptimal,
+                        ClearColorRaw {
+                            uint32: [7, 0, 0, 0],
+                        },
+                        ClearDepthStencilRaw {
+                            depth: 0.0,
+                            stencil: 0,
+                        },
+                        iter::once(range.clone()),
+                    );
+                }
+                cmd_buffer.pipeline_barrier(
+                    PipelineStage::TRANSFER .. PipelineStage::TRANSFER,
+                    Dependencies::empty(),
+                    iter::once(Barrier::Image {
+                        states: (
+                            image::Access::TRANSFER_WRITE,
+                            image::Layout::TransferDstOptimal,
+                        )
+                            .. (
+                                image::Access::TRANSFER_READ,
+                                image::Layout::TransferSrcOptimal,
+                            ),
+                        target: image,
+                        families: None,
+                        range,
+                    }),
+                );
+                cmd_buffer.copy_image_to_buffer(
+                    image,
+                    image::Layout::TransferSrcOptimal,
+                    &readback,
+                    iter::once(BufferImageCopy {
+                        buffer_offset: 512 * i as u64,
+                        buffer_width: 64,
+                        buffer_height: 1,
+                        image_layers: image::SubresourceLayers {
+                            aspects,
+                            level,
+                            layers: 0 .. 1,
+                        },
+                        image_offset: image::Offset::ZERO,
+                        image_extent: image::Extent {
+                            width: 1,
+                            height: 1,
+                            depth: 1,
+                        },
+                    }),
+                );
+            }
+            cmd_buffer.finish();
+
+            let fence = device.create_fence(false).unwrap();
+            queue_group.queues[0].as_raw_mut().submit(
+                Submission {
+                    command_buffers: iter::once(&cmd_buffer),
+                    wait_semaphores: iter::empty::<(&Semaphore, _)>(),
+                    signal_semaphores: iter::empty::<&Semaphore>(),
+                },
+                Some(&fence),
+            );
+            assert_eq!(device.wait_for_fence(&fence, !0), Ok(true));
+
+            let data = device.map_memory(&readback_memory, 0 .. 512 * 3).unwrap();
+            let data = slice::from_raw_parts(data as *const u32, 128 * 3);
+            assert_eq!(data[0], 0xFF00_00FF);
+            assert_eq!(data[128], 7);
+            assert_eq!(f32::from_bits(data[256]), 0.25);
+            device.unmap_memory(&readback_memory);
+
+            device.destroy_fence(fence);
+            command_pool.free(iter::once(cmd_buffer));
+            device.destroy_command_pool(command_pool);
+            device.destroy_buffer(readback);
+            device.free_memory(readback_memory);
+            for (image, memory) in images {
+                device.destroy_image(image);
+                device.free_memory(memory);
+            }
         }
+    }
 
-        let mut src = d3d12::D3D12_TEXTURE_COPY_LOCATION {
-            pResource: buffer.resource.as_mut_ptr(),
-            Type: d3d12::D3D12_TEXTURE_COPY_TYPE_PLACED_FOOTPRINT,
-            u: mem::zeroed(),
-        };
-        let mut dst = d3d12::D3D12_TEXTURE_COPY_LOCATION {
-            pResource: image.resource.as_mut_ptr(),
-            Type: d3d12::D3D12_TEXTURE_COPY_TYPE_SUBRESOURCE_INDEX,
-            u: mem::zeroed(),
+    #[test]
+    fn test_warp_aliased_images() {
+        use hal::command::{
+            BufferImageCopy,
+            ClearColorRaw,
+            ClearDepthStencilRaw,
+            CommandBufferFlags,
+            RawCommandBuffer,
+            RawLevel,
         };
+        use hal::memory::{Barrier, Dependencies, Properties};
+        use hal::pool::{CommandPoolCreateFlags, RawCommandPool};
+        use hal::pso::PipelineStage;
+        use hal::queue::{RawCommandQueue, Submission};
+        use hal::{buffer, image, Device};
+        use std::{iter, slice};
 
-        for c in self.copies.drain(..) {
-            let src_box = d3d12::D3D12_BOX {
-                left: c.buf_offset.x as u32,
-                top: c.buf_offset.y as u32,
-                right: c.buf_offset.x as u32 + c.copy_extent.width,
-                bottom: c.buf_offset.y as u32 + c.copy_extent.height,
-                front: c.buf_offset.z as u32,
-                back: c.buf_offset.z as u32 + c.copy_extent.depth,
+        use resource::Semaphore;
+
+        let (warp, mut queue_group) = Warp::open("test_warp_aliased_images");
+        let device = &warp.device;
+        unsafe {
+            // Two images placed at the same offset of a single memory object.
+            let mut images = Vec::new();
+            for _ in 0 .. 2 {
+                let image = device
+                    .create_image(
+                        image::Kind::D2(4, 4, 1, 1),
+                        1,
+                        Format::Rgba8Unorm,
+                        image::Tiling::Optimal,
+                        image::Usage::TRANSFER_DST | image::Usage::TRANSFER_SRC,
+                        image::ViewCapabilities::empty(),
+                    )
+                    .unwrap();
+                images.push(image);
+            }
+            let requirements = device.get_image_requirements(&images[0]);
+            let memory = warp.allocate(
+                requirements.type_mask,
+                requirements.size,
+                Properties::DEVICE_LOCAL,
+            );
+            for image in &mut images {
+                device.bind_image_memory(&memory, 0, image).unwrap();
+            }
+
+            let mut readback = device
+                .create_buffer(256, buffer::Usage::TRANSFER_DST)
+                .unwrap();
+            let requirements = device.get_buffer_requirements(&readback);
+            let readback_memory = warp.allocate(
+                requirements.type_mask,
+                requirements.size,
+                Properties::CPU_VISIBLE | Properties::CPU_CACHED,
+            );
+            device
+                .bind_buffer_memory(&readback_memory, 0, &mut readback)
+                .unwrap();
+
+            let range = image::SubresourceRange {
+                aspects: Aspects::COLOR,
+                levels: 0 .. 1,
+                layers: 0 .. 1,
             };
-            let footprint = d3d12::D3D12_PLACED_SUBRESOURCE_FOOTPRINT {
-                Offset: c.footprint_offset,
-                Footprint: d3d12::D3D12_SUBRESOURCE_FOOTPRINT {
-                    Format: image.descriptor.Format,
-                    Width: c.footprint.width,
-                    Height: c.footprint.height,
-                    Depth: c.footprint.depth,
-                    RowPitch: c.row_pitch,
+            let mut command_pool = device
+                .create_command_pool(queue_group.family(), CommandPoolCreateFlags::empty())
+                .unwrap();
+            let mut cmd_buffer = command_pool.allocate_one(RawLevel::Primary);
+            cmd_buffer.begin(CommandBufferFlags::ONE_TIME_SUBMIT, Default::default());
+            // Activating the second image invalidates the contents of the first one.
+            for (image, color) in images.iter().zip(&[[1.0, 0.0, 0.0, 1.0], [0.0, 1.0, 0.0, 1.0]]) {
+                cmd_buffer.pipeline_barrier(
+                    PipelineStage::TOP_OF_PIPE .. PipelineStage::TRANSFER,
+                    Dependencies::empty(),
+                    iter::once(Barrier::Image {
+                        states: (image::Access::empty(), image::Layout::Undefined)
+                            .. (
+                                image::Access::TRANSFER_WRITE,
+                                image::Layout::TransferDstOptimal,
+                            ),
+                        target: image,
+                        families: None,
+                        range: range.clone(),
+                    }),
+                );
+                cmd_buffer.clear_image(
+                    image,
+                    image::Layout::TransferDstOptimal,
+                    ClearColorRaw { float32: *color },
+                    ClearDepthStencilRaw {
+                        depth: 0.0,
+                        stencil: 0,
+                    },
+                    iter::once(range.clone()),
+                );
+            }
+            cmd_buffer.pipeline_barrier(
+                PipelineStage::TRANSFER .. PipelineStage::TRANSFER,
+                Dependencies::empty(),
+                iter::once(Barrier::Image {
+                    states: (
+                        image::Access::TRANSFER_WRITE,
+                        image::Layout::TransferDstOptimal,
+                    )
+                        .. (
+                            image::Access::TRANSFER_READ,
+                            image::Layout::TransferSrcOptimal,
+                        ),
+                    target: &images[1],
+                    families: None,
+                    range: range.clone(),
+                }),
+            );
+            cmd_buffer.copy_image_to_buffer(
+                &images[1],
+                image::Layout::TransferSrcOptimal,
+                &readback,
+                iter::once(BufferImageCopy {
+                    buffer_offset: 0,
+                    buffer_width: 64,
+                    buffer_height: 1,
+                    image_layers: image::SubresourceLayers {
+                        aspects: Aspects::COLOR,
+                        level: 0,
+                        layers: 0 .. 1,
+                    },
+                    image_offset: image::Offset::ZERO,
+                    image_extent: image::Extent {
+                        width: 4,
+                        height: 1,
+                        depth: 1,
+                    },
+                }),
+            );
+            cmd_buffer.finish();
+
+            let fence = device.create_fence(false).unwrap();
+            queue_group.queues[0].as_raw_mut().submit(
+                Submission {
+                    command_buffers: iter::once(&cmd_buffer),
+                    wait_semaphores: iter::empty::<(&Semaphore, _)>(),
+                    signal_semaphores: iter::empty::<&Semaphore>(),
                 },
-            };
-            *src.u.PlacedFootprint_mut() = footprint;
-            *dst.u.SubresourceIndex_mut() = c.img_subresource;
-            self.raw.CopyTextureRegion(
-                &dst,
-                c.img_offset.x as _,
-                c.img_offset.y as _,
-                c.img_offset.z as _,
-                &src,
-                &src_box,
+                Some(&fence),
             );
+            assert_eq!(device.wait_for_fence(&fence, !0), Ok(true));
+
+            let data = device.map_memory(&readback_memory, 0 .. 256).unwrap();
+            let data = slice::from_raw_parts(data as *const u32, 4);
+            assert_eq!(data, &[0xFF00_FF00; 4]);
+            device.unmap_memory(&readback_memory);
+
+            device.destroy_fence(fence);
+            command_pool.free(iter::once(cmd_buffer));
+            device.destroy_command_pool(command_pool);
+            device.destroy_buffer(readback);
+            device.free_memory(readback_memory);
+            for image in images {
+                device.destroy_image(image);
+            }
+            device.free_memory(memory);
         }
     }
 
-    unsafe fn copy_image_to_buffer<T>(
-        &mut self,
-        image: &r::Image,
-        _: image::Layout,
-        buffer: &r::Buffer,
-        regions: T,
-    ) where
-        T: IntoIterator,
-        T::Item: Borrow<com::BufferImageCopy>,
-    {
-        let image = image.expect_bound();
-        let buffer = buffer.expect_bound();
-        assert!(self.copies.is_empty());
+    #[test]
+    fn test_warp_copy_committed_image_formats() {
+        use hal::command::{
+            BufferImageCopy,
+            CommandBufferFlags,
+            ImageCopy,
+            RawCommandBuffer,
+            RawLevel,
+        };
+        use hal::memory::{Barrier, Dependencies, Properties};
+        use hal::pool::{CommandPoolCreateFlags, RawCommandPool};
+        use hal::pso::PipelineStage;
+        use hal::queue::{RawCommandQueue, Submission};
+        use hal::{buffer, image, Device};
+        use std::{iter, ptr, slice};
+
+        use resource::Semaphore;
+
+        let (warp, mut queue_group) = Warp::open("test_warp_copy_committed_image_formats");
+        let device = &warp.device;
+        let data = [0x8040_2010u32, 0xFF00_00FF, 0x0102_0304, 0xDEAD_BEEF];
+        // Copies between committed images, which can't be aliased for format conversions.
+        // UNORM to UINT keeps the bits, BC1 images only share a family with themselves.
+        let copies = [
+            (Format::Rgba8Unorm, Format::Rgba8Uint, 4, 1),
+            (Format::Bc1RgbaUnorm, Format::Bc1RgbaUnorm, 8, 4),
+        ];
+        for &(src_format, dst_format, width, height) in &copies {
+            unsafe {
+                let mut images = Vec::new();
+                for &format in &[src_format, dst_format] {
+                    let mut image = device
+                        .create_image(
+                            image::Kind::D2(width, height, 1, 1),
+                            1,
+                            format,
+                            image::Tiling::Optimal,
+                            image::Usage::TRANSFER_DST | image::Usage::TRANSFER_SRC,
+                            image::ViewCapabilities::empty(),
+                        )
+                        .unwrap();
+                    let requirements = device.get_image_requirements(&image);
+                    let ty = warp.memory_type(requirements.type_mask, Properties::DEVICE_LOCAL);
+                    let memory = device
+                        .allocate_dedicated_memory(ty, requirements.size)
+                        .unwrap();
+                    device.bind_image_memory(&memory, 0, &mut image).unwrap();
+                    images.push((image, memory));
+                }
+                let (upload, upload_memory) = warp.create_buffer(
+                    16,
+                    buffer::Usage::TRANSFER_SRC,
+                    Properties::CPU_VISIBLE,
+                );
+                let mapping = device.map_memory(&upload_memory, 0 .. 16).unwrap();
+                ptr::copy_nonoverlapping(data.as_ptr(), mapping as *mut u32, data.len());
+                device.unmap_memory(&upload_memory);
+                let (readback, readback_memory) = warp.create_buffer(
+                    16,
+                    buffer::Usage::TRANSFER_DST,
+                    Properties::CPU_VISIBLE | Properties::CPU_CACHED,
+                );
 
-        for region in regions {
-            let r = region.borrow();
-            Self::split_buffer_copy(&mut self.copies, r, image);
-        }
+                let layers = image::SubresourceLayers {
+                    aspects: Aspects::COLOR,
+                    level: 0,
+                    layers: 0 .. 1,
+                };
+                let extent = image::Extent {
+                    width,
+                    height,
+                    depth: 1,
+                };
+                let region = BufferImageCopy {
+                    buffer_offset: 0,
+                    buffer_width: width,
+                    buffer_height: height,
+                    image_layers: layers.clone(),
+                    image_offset: image::Offset::ZERO,
+                    image_extent: extent,
+                };
+                let barrier = |image, from, to| Barrier::Image {
+                    states: from .. to,
+                    target: image,
+                    families: None,
+                    range: image::SubresourceRange {
+                        aspects: Aspects::COLOR,
+                        levels: 0 .. 1,
+                        layers: 0 .. 1,
+                    },
+                };
+                let undefined = (image::Access::empty(), image::Layout::Undefined);
+                let dst = (
+                    image::Access::TRANSFER_WRITE,
+                    image::Layout::TransferDstOptimal,
+                );
+                let src = (
+                    image::Access::TRANSFER_READ,
+                    image::Layout::TransferSrcOptimal,
+                );
 
-        if self.copies.is_empty() {
-            return;
+                let mut command_pool = device
+                    .create_command_pool(queue_group.family(), CommandPoolCreateFlags::empty())
+                    .unwrap();
+                let mut cmd_buffer = command_pool.allocate_one(RawLevel::Primary);
+                cmd_buffer.begin(CommandBufferFlags::ONE_TIME_SUBMIT, Default::default());
+                cmd_buffer.pipeline_barrier(
+                    PipelineStage::TOP_OF_PIPE .. PipelineStage::TRANSFER,
+                    Dependencies::empty(),
+                    images
+                        .iter()
+                        .map(|&(ref image, _)| barrier(image, undefined, dst)),
+                );
+                cmd_buffer.copy_buffer_to_image(
+                    &upload,
+                    &images[0].0,
+                    image::Layout::TransferDstOptimal,
+                    iter::once(region.clone()),
+                );
+                cmd_buffer.pipeline_barrier(
+                    PipelineStage::TRANSFER .. PipelineStage::TRANSFER,
+                    Dependencies::empty(),
+                    iter::once(barrier(&images[0].0, dst, src)),
+                );
+                cmd_buffer.copy_image(
+                    &images[0].0,
+                    image::Layout::TransferSrcOptimal,
+                    &images[1].0,
+                    image::Layout::TransferDstOptimal,
+                    iter::once(ImageCopy {
+                        src_subresource: layers.clone(),
+                        src_offset: image::Offset::ZERO,
+                        dst_subresource: layers,
+                        dst_offset: image::Offset::ZERO,
+                        extent,
+                    }),
+                );
+                cmd_buffer.pipeline_barrier(
+                    PipelineStage::TRANSFER .. PipelineStage::TRANSFER,
+                    Dependencies::empty(),
+                    iter::once(barrier(&images[1].0, dst, src)),
+                );
+                cmd_buffer.copy_image_to_buffer(
+                    &images[1].0,
+                    image::Layout::TransferSrcOptimal,
+                    &readback,
+                    iter::once(region),
+                );
+                cmd_buffer.finish();
+
+                let fence = device.create_fence(false).unwrap();
+                queue_group.queues[0].as_raw_mut().submit(
+                    Submission {
+                        command_buffers: iter::once(&cmd_buffer),
+                        wait_semaphores: iter::empty::<(&Semaphore, _)>(),
+                        signal_semaphores: iter::empty::<&Semaphore>(),
+                    },
+                    Some(&fence),
+                );
+                assert_eq!(device.wait_for_fence(&fence, !0), Ok(true));
+
+                let mapping = device.map_memory(&readback_memory, 0 .. 16).unwrap();
+                let copied = slice::from_raw_parts(mapping as *const u32, data.len());
+                assert_eq!(copied, &data, "{:?} -> {:?}", src_format, dst_format);
+                device.unmap_memory(&readback_memory);
+
+                device.destroy_fence(fence);
+                command_pool.free(iter::once(cmd_buffer));
+                device.destroy_command_pool(command_pool);
+                device.destroy_buffer(readback);
+                device.free_memory(readback_memory);
+                device.destroy_buffer(upload);
+                device.free_memory(upload_memory);
+                for (image, memory) in images {
+                    device.destroy_image(image);
+                    device.free_memory(memory);
+                }
+            }
         }
+    }
 
-        let mut src = d3d12::D3D12_TEXTURE_COPY_LOCATION {
-            pResource: image.resource.as_mut_ptr(),
-            Type: d3d12::D3D12_TEXTURE_COPY_TYPE_SUBRESOURCE_INDEX,
-            u: mem::zeroed(),
-        };
-        let mut dst = d3d12::D3D12_TEXTURE_COPY_LOCATION {
-            pResource: buffer.resource.as_mut_ptr(),
-            Type: d3d12::D3D12_TEXTURE_COPY_TYPE_PLACED_FOOTPRINT,
-            u: mem::zeroed(),
+    #[test]
+    fn test_warp_split_barrier() {
+        use hal::command::{
+            BufferImageCopy,
+            ClearColorRaw,
+            ClearDepthStencilRaw,
+            CommandBufferFlags,
+            ImageBlit,
+            RawCommandBuffer,
+            RawLevel,
         };
+        use hal::memory::{Barrier, Dependencies, Properties};
+        use hal::pool::{CommandPoolCreateFlags, RawCommandPool};
+        use hal::pso::PipelineStage;
+        use hal::queue::{RawCommandQueue, Submission};
+        use hal::{buffer, image, Device};
+        use std::ops::Range;
+        use std::{iter, slice};
+
+        use resource::Semaphore;
+
+        let (warp, mut queue_group) = Warp::open("test_warp_split_barrier");
+        let device = &warp.device;
+        unsafe {
+            // `split` is transitioned by a split barrier around a blit drawing `src` into `dst`.
+            let transfer = image::Usage::TRANSFER_DST | image::Usage::TRANSFER_SRC;
+            let targets = [
+                (4, transfer),
+                (4, image::Usage::SAMPLED | image::Usage::TRANSFER_DST),
+                (2, image::Usage::COLOR_ATTACHMENT | transfer),
+            ];
+            let mut images = Vec::new();
+            for &(size, usage) in &targets {
+                let mut image = device
+                    .create_image(
+                        image::Kind::D2(size, size, 1, 1),
+                        1,
+                        Format::Rgba8Unorm,
+                        image::Tiling::Optimal,
+                        usage,
+                        image::ViewCapabilities::empty(),
+                    )
+                    .unwrap();
+                let requirements = device.get_image_requirements(&image);
+                let memory = warp.allocate(
+                    requirements.type_mask,
+                    requirements.size,
+                    Properties::DEVICE_LOCAL,
+                );
+                device.bind_image_memory(&memory, 0, &mut image).unwrap();
+                images.push((image, memory));
+            }
+            let (split, src, dst) = (&images[0].0, &images[1].0, &images[2].0);
+
+            let mut readback = device
+                .create_buffer(512 * 2, buffer::Usage::TRANSFER_DST)
+                .unwrap();
+            let requirements = device.get_buffer_requirements(&readback);
+            let readback_memory = warp.allocate(
+                requirements.type_mask,
+                requirements.size,
+                Properties::CPU_VISIBLE | Properties::CPU_CACHED,
+            );
+            device
+                .bind_buffer_memory(&readback_memory, 0, &mut readback)
+                .unwrap();
+
+            let range = image::SubresourceRange {
+                aspects: Aspects::COLOR,
+                levels: 0 .. 1,
+                layers: 0 .. 1,
+            };
+            let barrier = |target, states: Range<image::State>| Barrier::Image {
+                states,
+                target,
+                families: None,
+                range: range.clone(),
+            };
+            let undefined = (image::Access::empty(), image::Layout::Undefined);
+            let transfer_dst = (
+                image::Access::TRANSFER_WRITE,
+                image::Layout::TransferDstOptimal,
+            );
+            let transfer_src = (
+                image::Access::TRANSFER_READ,
+                image::Layout::TransferSrcOptimal,
+            );
 
-        for c in self.copies.drain(..) {
-            let src_box = d3d12::D3D12_BOX {
-                left: c.img_offset.x as u32,
-                top: c.img_offset.y as u32,
-                right: c.img_offset.x as u32 + c.copy_extent.width,
-                bottom: c.img_offset.y as u32 + c.copy_extent.height,
-                front: c.img_offset.z as u32,
-                back: c.img_offset.z as u32 + c.copy_extent.depth,
+            let mut command_pool = device
+                .create_command_pool(queue_group.family(), CommandPoolCreateFlags::empty())
+                .unwrap();
+            let mut cmd_buffer = command_pool.allocate_one(RawLevel::Primary);
+            cmd_buffer.begin(CommandBufferFlags::ONE_TIME_SUBMIT, Default::default());
+            cmd_buffer.pipeline_barrier(
+                PipelineStage::TOP_OF_PIPE .. PipelineStage::TRANSFER,
+                Dependencies::empty(),
+                vec![
+                    barrier(split, undefined .. transfer_dst),
+                    barrier(src, undefined .. transfer_dst),
+                    barrier(dst, undefined .. transfer_dst),
+                ],
+            );
+            let colors = [[1.0, 0.0, 0.0, 1.0], [0.0, 1.0, 0.0, 1.0]];
+            for (image, color) in [split, src].iter().zip(&colors) {
+                cmd_buffer.clear_image(
+                    image,
+                    image::Layout::TransferDstOptimal,
+                    ClearColorRaw { float32: *color },
+                    ClearDepthStencilRaw {
+                        depth: 0.0,
+                        stencil: 0,
+                    },
+                    iter::once(range.clone()),
+                );
+            }
+            cmd_buffer.pipeline_barrier(
+                PipelineStage::TRANSFER .. PipelineStage::TRANSFER,
+                Dependencies::empty(),
+                iter::once(barrier(src, transfer_dst .. transfer_src)),
+            );
+
+            cmd_buffer.begin_barrier(iter::once(barrier(split, transfer_dst .. transfer_src)));
+            let layers = image::SubresourceLayers {
+                aspects: Aspects::COLOR,
+                level: 0,
+                layers: 0 .. 1,
             };
-            let footprint = d3d12::D3D12_PLACED_SUBRESOURCE_FOOTPRINT {
-                Offset: c.footprint_offset,
-                Footprint: d3d12::D3D12_SUBRESOURCE_FOOTPRINT {
-                    Format: image.descriptor.Format,
-                    Width: c.footprint.width,
-                    Height: c.footprint.height,
-                    Depth: c.footprint.depth,
-                    RowPitch: c.row_pitch,
+            cmd_buffer.blit_image(
+                src,
+                image::Layout::TransferSrcOptimal,
+                dst,
+                image::Layout::TransferDstOptimal,
+                image::Filter::Linear,
+                iter::once(ImageBlit {
+                    src_subresource: layers.clone(),
+                    src_bounds: image::Offset::ZERO .. image::Offset { x: 4, y: 4, z: 1 },
+                    dst_subresource: layers.clone(),
+                    dst_bounds: image::Offset::ZERO .. image::Offset { x: 2, y: 2, z: 1 },
+                }),
+            );
+            cmd_buffer.end_barrier(iter::once(barrier(split, transfer_dst .. transfer_src)));
+
+            cmd_buffer.pipeline_barrier(
+                PipelineStage::TRANSFER .. PipelineStage::TRANSFER,
+                Dependencies::empty(),
+                iter::once(barrier(dst, transfer_dst .. transfer_src)),
+            );
+            for (i, image) in [split, dst].iter().enumerate() {
+                cmd_buffer.copy_image_to_buffer(
+                    image,
+                    image::Layout::TransferSrcOptimal,
+                    &readback,
+                    iter::once(BufferImageCopy {
+                        buffer_offset: 512 * i as u64,
+                        buffer_width: 64,
+                        buffer_height: 1,
+                        image_layers: layers.clone(),
+                        image_offset: image::Offset::ZERO,
+                        image_extent: image::Extent {
+                            width: 1,
+                            height: 1,
+                            depth: 1,
+                        },
+                    }),
+                );
+            }
+            cmd_buffer.finish();
+
+            let fence = device.create_fence(false).unwrap();
+            queue_group.queues[0].as_raw_mut().submit(
+                Submission {
+                    command_buffers: iter::once(&cmd_buffer),
+                    wait_semaphores: iter::empty::<(&Semaphore, _)>(),
+                    signal_semaphores: iter::empty::<&Semaphore>(),
                 },
+                Some(&fence),
+            );
+            assert_eq!(device.wait_for_fence(&fence, !0), Ok(true));
+
+            let data = device.map_memory(&readback_memory, 0 .. 512 * 2).unwrap();
+            let data = slice::from_raw_parts(data as *const u32, 128 * 2);
+            assert_eq!(data[0], 0xFF00_00FF);
+            assert_eq!(data[128], 0xFF00_FF00);
+            device.unmap_memory(&readback_memory);
+
+            device.destroy_fence(fence);
+            command_pool.free(iter::once(cmd_buffer));
+            device.destroy_command_pool(command_pool);
+            device.destroy_buffer(readback);
+            device.free_memory(readback_memory);
+            for (image, memory) in images {
+                device.destroy_image(image);
+                device.free_memory(memory);
+            }
+        }
+    }
+
+    #[test]
+    fn test_warp_events() {
+        use hal::command::{BufferCopy, CommandBufferFlags, RawCommandBuffer, RawLevel};
+        use hal::memory::{Barrier, Properties};
+        use hal::pool::{CommandPoolCreateFlags, RawCommandPool};
+        use hal::pso::PipelineStage;
+        use hal::queue::{RawCommandQueue, Submission};
+        use hal::{buffer, Device, General, QueueGroup};
+        use std::{iter, ptr};
+        use winapi::shared::winerror;
+        use winapi::um::{handleapi, synchapi, winbase};
+
+        use super::CommandBuffer;
+        use native;
+        use resource::{Fence, Semaphore};
+        use Backend;
+
+        let (warp, mut queue_group) = Warp::open("test_warp_events");
+        let device = &warp.device;
+        unsafe {
+            let (upload, upload_memory) = warp.create_buffer(
+                8,
+                buffer::Usage::TRANSFER_SRC,
+                Properties::CPU_VISIBLE | Properties::COHERENT,
+            );
+            let (readback, readback_memory) = warp.create_buffer(
+                8,
+                buffer::Usage::TRANSFER_DST,
+                Properties::CPU_VISIBLE | Properties::CPU_CACHED,
+            );
+            let data = device.map_memory(&upload_memory, 0 .. 8).unwrap();
+            ptr::copy_nonoverlapping([1u32, 2].as_ptr(), data as *mut u32, 2);
+            device.unmap_memory(&upload_memory);
+
+            let host_event = device.create_event().unwrap();
+            let device_event = device.create_event().unwrap();
+            let reset_event = device.create_event().unwrap();
+            let stages = PipelineStage::TOP_OF_PIPE .. PipelineStage::TRANSFER;
+            let no_barriers = || iter::empty::<Barrier<Backend>>();
+
+            let mut command_pool = device
+                .create_command_pool(queue_group.family(), CommandPoolCreateFlags::empty())
+                .unwrap();
+            let mut cmd_buffers = command_pool.allocate_vec(3, RawLevel::Primary);
+            let copy = |src, dst| iter::once(BufferCopy { src, dst, size: 4 });
+
+            // Copy held back until the host sets the event.
+            cmd_buffers[0].begin(CommandBufferFlags::ONE_TIME_SUBMIT, Default::default());
+            cmd_buffers[0].wait_events(iter::once(&host_event), stages.clone(), no_barriers());
+            cmd_buffers[0].copy_buffer(&upload, &readback, copy(0, 0));
+            cmd_buffers[0].finish();
+
+            // Copy followed by setting the event on the device.
+            cmd_buffers[1].begin(CommandBufferFlags::ONE_TIME_SUBMIT, Default::default());
+            cmd_buffers[1].copy_buffer(&upload, &readback, copy(4, 4));
+            cmd_buffers[1].set_event(&device_event, PipelineStage::TRANSFER);
+            cmd_buffers[1].finish();
+
+            // A reset after a local set has to be waited on the queue again.
+            cmd_buffers[2].begin(CommandBufferFlags::ONE_TIME_SUBMIT, Default::default());
+            cmd_buffers[2].set_event(&reset_event, PipelineStage::TRANSFER);
+            cmd_buffers[2].wait_events(iter::once(&reset_event), stages.clone(), no_barriers());
+            assert!(cmd_buffers[2].event_waits.is_empty());
+            cmd_buffers[2].reset_event(&reset_event, PipelineStage::TRANSFER);
+            cmd_buffers[2].wait_events(iter::once(&reset_event), stages.clone(), no_barriers());
+            assert_eq!(cmd_buffers[2].event_waits.len(), 1);
+            cmd_buffers[2].finish();
+
+            let fence = device.create_fence(false).unwrap();
+            let submit = |queue_group: &mut QueueGroup<Backend, General>,
+                          cmd_buffer: &CommandBuffer,
+                          fence: Option<&Fence>| {
+                queue_group.queues[0].as_raw_mut().submit(
+                    Submission {
+                        command_buffers: iter::once(cmd_buffer),
+                        wait_semaphores: iter::empty::<(&Semaphore, _)>(),
+                        signal_semaphores: iter::empty::<&Semaphore>(),
+                    },
+                    fence,
+                );
+            };
+
+            // The queue is blocked on the event, the fence can't be signaled before it's set.
+            submit(&mut queue_group, &cmd_buffers[0], Some(&fence));
+            assert_eq!(device.wait_for_fence(&fence, 0), Ok(false));
+            assert_eq!(device.get_event_status(&host_event), Ok(false));
+            device.set_event(&host_event).unwrap();
+            assert_eq!(device.wait_for_fence(&fence, !0), Ok(true));
+            let data = device.map_memory(&readback_memory, 0 .. 8).unwrap();
+            assert_eq!(ptr::read(data as *const u32), 1);
+            device.unmap_memory(&readback_memory);
+
+            // The event is set once the preceding copy completed.
+            submit(&mut queue_group, &cmd_buffers[1], None);
+            let completion = native::Event::create(false, false);
+            assert_eq!(
+                winerror::S_OK,
+                device_event.raw.set_event_on_completion(completion, 1)
+            );
+            assert_eq!(
+                synchapi::WaitForSingleObject(completion.0, winbase::INFINITE),
+                winbase::WAIT_OBJECT_0
+            );
+            handleapi::CloseHandle(completion.0);
+            assert_eq!(device.get_event_status(&device_event), Ok(true));
+            let data = device.map_memory(&readback_memory, 0 .. 8).unwrap();
+            assert_eq!(ptr::read(data as *const [u32; 2]), [1, 2]);
+            device.unmap_memory(&readback_memory);
+
+            device.reset_fence(&fence).unwrap();
+            submit(&mut queue_group, &cmd_buffers[2], Some(&fence));
+            assert_eq!(device.wait_for_fence(&fence, 0), Ok(false));
+            device.set_event(&reset_event).unwrap();
+            assert_eq!(device.wait_for_fence(&fence, !0), Ok(true));
+            assert_eq!(device.get_event_status(&reset_event), Ok(false));
+
+            device.destroy_fence(fence);
+            command_pool.free(cmd_buffers);
+            device.destroy_command_pool(command_pool);
+            for event in vec![host_event, device_event, reset_event] {
+                device.destroy_event(event);
+            }
+            for (buffer, memory) in vec![(upload, upload_memory), (readback, readback_memory)] {
+                device.destroy_buffer(buffer);
+                device.free_memory(memory);
+            }
+        }
+    }
+
+    #[test]
+    fn test_warp_split_render_pass() {
+        use hal::command::{CommandBufferFlags, RawCommandBuffer, RawLevel};
+        use hal::pool::{CommandPoolCreateFlags, RawCommandPool};
+        use hal::queue::{RawCommandQueue, Submission};
+        use hal::{image, Device};
+        use std::iter;
+
+        use resource::Semaphore;
+        use test_utils::{clear_color, graphics_pipeline_desc, FULLSCREEN_VS};
+        use RenderPassSplit;
+
+        let (warp, mut queue_group) = Warp::open("test_warp_split_render_pass");
+        let device = &warp.device;
+        unsafe {
+            let target = warp.create_target(
+                Format::Rgba8Unorm,
+                (2, 1),
+                1,
+                image::Usage::COLOR_ATTACHMENT | image::Usage::TRANSFER_SRC,
+            );
+            let render_pass = warp.create_color_pass(Format::Rgba8Unorm);
+            let framebuffer = warp.create_framebuffer(&render_pass, &[&target.view], (2, 1));
+            let layout = warp.create_pipeline_layout(&[]);
+
+            // The output is blended with the blend constants only.
+            let modules = warp.create_shader_modules(&[
+                FULLSCREEN_VS,
+                (
+                    pso::Stage::Fragment,
+                    "ps_main",
+                    b"float4 ps_main() : SV_Target { return float4(1.0, 1.0, 1.0, 1.0); }",
+                ),
+            ]);
+            let mut desc =
+                graphics_pipeline_desc(&modules[0], Some(&modules[1]), &layout, &render_pass, 0);
+            let blend = pso::BlendOp::Add {
+                src: pso::Factor::ConstColor,
+                dst: pso::Factor::Zero,
             };
-            *dst.u.PlacedFootprint_mut() = footprint;
-            *src.u.SubresourceIndex_mut() = c.img_subresource;
-            self.raw.CopyTextureRegion(
-                &dst,
-                c.buf_offset.x as _,
-                c.buf_offset.y as _,
-                c.buf_offset.z as _,
-                &src,
-                &src_box,
+            desc.blender.targets[0] = pso::ColorBlendDesc(
+                pso::ColorMask::ALL,
+                pso::BlendState::On {
+                    color: blend,
+                    alpha: blend,
+                },
+            );
+            let pipeline = device.create_graphics_pipeline(&desc, None).unwrap();
+
+            // The first part clears the target and draws the left texel, the second part
+            // draws the right texel and transitions the target for the readback.
+            let mut command_pool = device
+                .create_command_pool(queue_group.family(), CommandPoolCreateFlags::empty())
+                .unwrap();
+            let parts = [
+                (RenderPassSplit::SUSPENDING, [1.0, 0.0, 0.0, 1.0], 0),
+                (RenderPassSplit::RESUMING, [0.0, 0.0, 1.0, 1.0], 1),
+            ];
+            let cmd_buffers = parts
+                .iter()
+                .map(|&(split, color, x)| {
+                    let mut cmd_buffer = command_pool.allocate_one(RawLevel::Primary);
+                    cmd_buffer.begin(CommandBufferFlags::ONE_TIME_SUBMIT, Default::default());
+                    let rect = pso::Rect { x: 0, y: 0, w: 2, h: 1 };
+                    cmd_buffer.set_viewports(
+                        0,
+                        iter::once(pso::Viewport {
+                            rect,
+                            depth: 0.0 .. 1.0,
+                        }),
+                    );
+                    cmd_buffer.set_scissors(0, iter::once(pso::Rect { x, y: 0, w: 1, h: 1 }));
+                    cmd_buffer.begin_render_pass_split(
+                        &render_pass,
+                        &framebuffer,
+                        rect,
+                        iter::once(clear_color([0.0; 4])),
+                        split,
+                    );
+                    cmd_buffer.set_blend_constants(color);
+                    cmd_buffer.bind_graphics_pipeline(&pipeline);
+                    cmd_buffer.draw(0 .. 3, 0 .. 1);
+                    cmd_buffer.end_render_pass();
+                    cmd_buffer.finish();
+                    cmd_buffer
+                })
+                .collect::<Vec<_>>();
+
+            let fence = device.create_fence(false).unwrap();
+            queue_group.queues[0].as_raw_mut().submit(
+                Submission {
+                    command_buffers: cmd_buffers.iter(),
+                    wait_semaphores: iter::empty::<(&Semaphore, _)>(),
+                    signal_semaphores: iter::empty::<&Semaphore>(),
+                },
+                Some(&fence),
             );
+            assert_eq!(device.wait_for_fence(&fence, !0), Ok(true));
+
+            let texels =
+                warp.read_image::<[u8; 4]>(&mut queue_group, &target.image, Aspects::COLOR, (2, 1));
+            assert_eq!(texels, vec![[0xFF, 0, 0, 0xFF], [0, 0, 0xFF, 0xFF]]);
+
+            device.destroy_fence(fence);
+            command_pool.free(cmd_buffers);
+            device.destroy_command_pool(command_pool);
+            device.destroy_graphics_pipeline(pipeline);
+            warp.destroy_shader_modules(modules);
+            device.destroy_pipeline_layout(layout);
+            device.destroy_framebuffer(framebuffer);
+            device.destroy_render_pass(render_pass);
+            warp.destroy_target(target);
         }
     }
 
-    unsafe fn draw(&mut self, vertices: Range<VertexCount>, instances: Range<InstanceCount>) {
-        self.set_graphics_bind_point();
-        self.raw.draw(
-            vertices.end - vertices.start,
-            instances.end - instances.start,
-            vertices.start,
-            instances.start,
-        );
-    }
+    #[test]
+    fn test_warp_native_render_pass() {
+        use hal::command::{ClearColorRaw, ClearDepthStencilRaw, RawCommandBuffer};
+        use hal::memory::Dependencies;
+        use hal::pass::{Attachment, AttachmentLoadOp, AttachmentOps, AttachmentStoreOp};
+        use hal::pass::{SubpassDependency, SubpassDesc};
+        use hal::pso::PipelineStage;
+        use hal::{image, Device};
+        use std::iter;
+
+        use test_utils::{
+            begin_render_pass,
+            clear_color,
+            graphics_pipeline_desc,
+            image_barrier,
+            subresource_range,
+            FULLSCREEN_VS,
+        };
 
-    unsafe fn draw_indexed(
-        &mut self,
-        indices: Range<IndexCount>,
-        base_vertex: VertexOffset,
-        instances: Range<InstanceCount>,
-    ) {
-        self.set_graphics_bind_point();
-        self.raw.draw_indexed(
-            indices.end - indices.start,
-            instances.end - instances.start,
-            indices.start,
-            base_vertex,
-            instances.start,
-        );
-    }
+        let (warp, mut queue_group) = Warp::open("test_warp_native_render_pass");
+        let device = &warp.device;
+        unsafe {
+            // The first attachment is cleared by the pass, the second one preserves the
+            // contents cleared before the pass. Only the left texel is drawn to.
+            let attachment = |load, layout| Attachment {
+                format: Some(Format::Rgba8Unorm),
+                samples: 1,
+                ops: AttachmentOps::new(load, AttachmentStoreOp::Store),
+                stencil_ops: AttachmentOps::DONT_CARE,
+                layouts: layout .. image::Layout::TransferSrcOptimal,
+            };
+            let render_pass = device
+                .create_render_pass(
+                    vec![
+                        attachment(AttachmentLoadOp::Clear, image::Layout::Undefined),
+                        attachment(AttachmentLoadOp::Load, image::Layout::TransferDstOptimal),
+                    ],
+                    iter::once(SubpassDesc {
+                        colors: &[
+                            (0, image::Layout::ColorAttachmentOptimal),
+                            (1, image::Layout::ColorAttachmentOptimal),
+                        ],
+                        depth_stencil: None,
+                        inputs: &[],
+                        resolves: &[],
+                        preserves: &[],
+                    }),
+                    iter::empty::<SubpassDependency>(),
+                )
+                .unwrap();
+            let layout = warp.create_pipeline_layout(&[]);
+            let modules = warp.create_shader_modules(&[
+                FULLSCREEN_VS,
+                (
+                    pso::Stage::Fragment,
+                    "ps_main",
+                    b"struct Output {
+                        float4 first : SV_Target0;
+                        float4 second : SV_Target1;
+                    };
+                    Output ps_main() {
+                        Output output;
+                        output.first = float4(1.0, 0.0, 0.0, 1.0);
+                        output.second = float4(1.0, 0.0, 0.0, 1.0);
+                        return output;
+                    }",
+                ),
+            ]);
+            let desc =
+                graphics_pipeline_desc(&modules[0], Some(&modules[1]), &layout, &render_pass, 0);
+            let pipeline = device.create_graphics_pipeline(&desc, None).unwrap();
+
+            // Render once through `BeginRenderPass` and once through the manual fallback.
+            let mut outputs = Vec::new();
+            for &native in &[true, false] {
+                let usage = image::Usage::COLOR_ATTACHMENT
+                    | image::Usage::TRANSFER_SRC
+                    | image::Usage::TRANSFER_DST;
+                let targets = vec![
+                    warp.create_target(Format::Rgba8Unorm, (2, 1), 1, usage),
+                    warp.create_target(Format::Rgba8Unorm, (2, 1), 1, usage),
+                ];
+                let framebuffer = warp.create_framebuffer(
+                    &render_pass,
+                    &[&targets[0].view, &targets[1].view],
+                    (2, 1),
+                );
 
-    unsafe fn draw_indirect(
-        &mut self,
-        buffer: &r::Buffer,
-        offset: buffer::Offset,
-        draw_count: DrawCount,
-        stride: u32,
-    ) {
-        assert_eq!(stride, 16);
-        let buffer = buffer.expect_bound();
-        self.set_graphics_bind_point();
-        self.raw.ExecuteIndirect(
-            self.shared.signatures.draw.as_mut_ptr(),
-            draw_count,
-            buffer.resource.as_mut_ptr(),
-            offset,
-            ptr::null_mut(),
-            0,
-        );
-    }
+                warp.submit(&mut queue_group, |cmd_buffer| {
+                    if native {
+                        assert!(cmd_buffer.raw4.is_some());
+                    } else if let Some(raw4) = cmd_buffer.raw4.take() {
+                        raw4.destroy();
+                    }
+                    cmd_buffer.pipeline_barrier(
+                        PipelineStage::TOP_OF_PIPE .. PipelineStage::TRANSFER,
+                        Dependencies::empty(),
+                        iter::once(image_barrier(
+                            &targets[1].image,
+                            Aspects::COLOR,
+                            (image::Access::empty(), image::Layout::Undefined)
+                                .. (
+                                    image::Access::TRANSFER_WRITE,
+                                    image::Layout::TransferDstOptimal,
+                                ),
+                        )),
+                    );
+                    cmd_buffer.clear_image(
+                        &targets[1].image,
+                        image::Layout::TransferDstOptimal,
+                        ClearColorRaw {
+                            float32: [0.0, 1.0, 0.0, 1.0],
+                        },
+                        ClearDepthStencilRaw {
+                            depth: 0.0,
+                            stencil: 0,
+                        },
+                        iter::once(subresource_range(Aspects::COLOR)),
+                    );
+                    begin_render_pass(
+                        cmd_buffer,
+                        &render_pass,
+                        &framebuffer,
+                        (2, 1),
+                        iter::once(clear_color([0.0, 0.0, 1.0, 1.0])),
+                    );
+                    cmd_buffer.set_scissors(0, iter::once(pso::Rect { x: 0, y: 0, w: 1, h: 1 }));
+                    cmd_buffer.bind_graphics_pipeline(&pipeline);
+                    cmd_buffer.draw(0 .. 3, 0 .. 1);
+                    cmd_buffer.end_render_pass();
+                });
 
-    unsafe fn draw_indexed_indirect(
-        &mut self,
-        buffer: &r::Buffer,
-        offset: buffer::Offset,
-        draw_count: DrawCount,
-        stride: u32,
-    ) {
-        assert_eq!(stride, 20);
-        let buffer = buffer.expect_bound();
-        self.set_graphics_bind_point();
-        self.raw.ExecuteIndirect(
-            self.shared.signatures.draw_indexed.as_mut_ptr(),
-            draw_count,
-            buffer.resource.as_mut_ptr(),
-            offset,
-            ptr::null_mut(),
-            0,
-        );
-    }
+                outputs.push(
+                    targets
+                        .iter()
+                        .map(|target| {
+                            warp.read_image::<[u8; 4]>(
+                                &mut queue_group,
+                                &target.image,
+                                Aspects::COLOR,
+                                (2, 1),
+                            )
+                        })
+                        .collect::<Vec<_>>(),
+                );
 
-    unsafe fn set_event(&mut self, _: &(), _: pso::PipelineStage) {
-        unimplemented!()
-    }
+                device.destroy_framebuffer(framebuffer);
+                for target in targets {
+                    warp.destroy_target(target);
+                }
+            }
 
-    unsafe fn reset_event(&mut self, _: &(), _: pso::PipelineStage) {
-        unimplemented!()
-    }
+            assert_eq!(
+                outputs[0],
+                vec![
+                    vec![[0xFF, 0, 0, 0xFF], [0, 0, 0xFF, 0xFF]],
+                    vec![[0xFF, 0, 0, 0xFF], [0, 0xFF, 0, 0xFF]],
+                ]
+            );
+            assert_eq!(outputs[0], outputs[1]);
 
-    unsafe fn wait_events<'a, I, J>(&mut self, _: I, _: Range<pso::PipelineStage>, _: J)
-    where
-        I: IntoIterator,
-        I::Item: Borrow<()>,
-        J: IntoIterator,
-        J::Item: Borrow<memory::Barrier<'a, Backend>>,
-    {
-        unimplemented!()
+            device.destroy_graphics_pipeline(pipeline);
+            warp.destroy_shader_modules(modules);
+            device.destroy_pipeline_layout(layout);
+            device.destroy_render_pass(render_pass);
+        }
     }
 
-    unsafe fn begin_query(&mut self, query: query::Query<Backend>, flags: query::ControlFlags) {
-        let query_ty = match query.pool.ty {
-            native::query::HeapType::Occlusion => {
-                if flags.contains(query::ControlFlags::PRECISE) {
-                    self.occlusion_query = Some(OcclusionQuery::Precise(query.id));
-                    d3d12::D3D12_QUERY_TYPE_OCCLUSION
-                } else {
-                    // Default to binary occlusion as it might be faster due to early depth/stencil
-                    // tests.
-                    self.occlusion_query = Some(OcclusionQuery::Binary(query.id));
-                    d3d12::D3D12_QUERY_TYPE_BINARY_OCCLUSION
+    #[test]
+    fn test_warp_native_render_pass_resolve() {
+        use hal::command::RawCommandBuffer;
+        use hal::pass::{Attachment, AttachmentLoadOp, AttachmentOps, AttachmentStoreOp};
+        use hal::pass::{SubpassDependency, SubpassDesc};
+        use hal::{image, Device};
+        use std::iter;
+
+        use test_utils::{begin_render_pass, clear_color, graphics_pipeline_desc, FULLSCREEN_VS};
+
+        let (warp, mut queue_group) = Warp::open("test_warp_native_render_pass_resolve");
+        let device = &warp.device;
+        unsafe {
+            // The multisampled attachment is discarded after being resolved.
+            let attachment = |samples, ops, layout| Attachment {
+                format: Some(Format::Rgba8Unorm),
+                samples,
+                ops,
+                stencil_ops: AttachmentOps::DONT_CARE,
+                layouts: image::Layout::Undefined .. layout,
+            };
+            let render_pass = device
+                .create_render_pass(
+                    &[
+                        attachment(
+                            4,
+                            AttachmentOps::new(
+                                AttachmentLoadOp::Clear,
+                                AttachmentStoreOp::DontCare,
+                            ),
+                            image::Layout::ColorAttachmentOptimal,
+                        ),
+                        attachment(
+                            1,
+                            AttachmentOps::new(
+                                AttachmentLoadOp::DontCare,
+                                AttachmentStoreOp::Store,
+                            ),
+                            image::Layout::TransferSrcOptimal,
+                        ),
+                    ],
+                    iter::once(SubpassDesc {
+                        colors: &[(0, image::Layout::ColorAttachmentOptimal)],
+                        depth_stencil: None,
+                        inputs: &[],
+                        resolves: &[(1, image::Layout::ColorAttachmentOptimal)],
+                        preserves: &[],
+                    }),
+                    iter::empty::<SubpassDependency>(),
+                )
+                .unwrap();
+            let layout = warp.create_pipeline_layout(&[]);
+            let modules = warp.create_shader_modules(&[
+                FULLSCREEN_VS,
+                (
+                    pso::Stage::Fragment,
+                    "ps_main",
+                    b"float4 ps_main(float4 pos : SV_Position) : SV_Target {
+                        return pos.x < 1.0 ? float4(1.0, 0.0, 0.0, 1.0)
+                                           : float4(0.0, 0.0, 1.0, 1.0);
+                    }",
+                ),
+            ]);
+            let mut desc =
+                graphics_pipeline_desc(&modules[0], Some(&modules[1]), &layout, &render_pass, 0);
+            desc.multisampling = Some(pso::Multisampling {
+                rasterization_samples: 4,
+                sample_shading: None,
+                sample_mask: !0,
+                alpha_coverage: false,
+                alpha_to_one: false,
+            });
+            let pipeline = device.create_graphics_pipeline(&desc, None).unwrap();
+
+            // Resolve through the ending access of `BeginRenderPass` and through
+            // `ResolveSubresource` of the manual fallback.
+            let mut outputs = Vec::new();
+            for &native in &[true, false] {
+                let targets = vec![
+                    warp.create_target(
+                        Format::Rgba8Unorm,
+                        (2, 1),
+                        4,
+                        image::Usage::COLOR_ATTACHMENT,
+                    ),
+                    warp.create_target(
+                        Format::Rgba8Unorm,
+                        (2, 1),
+                        1,
+                        image::Usage::COLOR_ATTACHMENT | image::Usage::TRANSFER_SRC,
+                    ),
+                ];
+                let framebuffer = warp.create_framebuffer(
+                    &render_pass,
+                    &[&targets[0].view, &targets[1].view],
+                    (2, 1),
+                );
+
+                warp.submit(&mut queue_group, |cmd_buffer| {
+                    if native {
+                        assert!(cmd_buffer.raw4.is_some());
+                    } else if let Some(raw4) = cmd_buffer.raw4.take() {
+                        raw4.destroy();
+                    }
+                    begin_render_pass(
+                        cmd_buffer,
+                        &render_pass,
+                        &framebuffer,
+                        (2, 1),
+                        iter::once(clear_color([0.0, 0.0, 0.0, 1.0])),
+                    );
+                    cmd_buffer.bind_graphics_pipeline(&pipeline);
+                    cmd_buffer.draw(0 .. 3, 0 .. 1);
+                    cmd_buffer.end_render_pass();
+                });
+
+                outputs.push(warp.read_image::<[u8; 4]>(
+                    &mut queue_group,
+                    &targets[1].image,
+                    Aspects::COLOR,
+                    (2, 1),
+                ));
+
+                device.destroy_framebuffer(framebuffer);
+                for target in targets {
+                    warp.destroy_target(target);
                 }
             }
-            native::query::HeapType::Timestamp => panic!("Timestap queries are issued via "),
-            native::query::HeapType::PipelineStatistics => {
-                self.pipeline_stats_query = Some(query.id);
-                d3d12::D3D12_QUERY_TYPE_PIPELINE_STATISTICS
-            }
-            _ => unreachable!(),
-        };
 
-        self.raw
-            .BeginQuery(query.pool.raw.as_mut_ptr(), query_ty, query.id);
+            assert_eq!(outputs[0], vec![[0xFF, 0, 0, 0xFF], [0, 0, 0xFF, 0xFF]]);
+            assert_eq!(outputs[0], outputs[1]);
+
+            device.destroy_graphics_pipeline(pipeline);
+            warp.destroy_shader_modules(modules);
+            device.destroy_pipeline_layout(layout);
+            device.destroy_render_pass(render_pass);
+        }
     }
 
-    unsafe fn end_query(&mut self, query: query::Query<Backend>) {
-        let id = query.id;
-        let query_ty = match query.pool.ty {
-            native::query::HeapType::Occlusion
-                if self.occlusion_query == Some(OcclusionQuery::Precise(id)) =>
-            {
-                self.occlusion_query = None;
-                d3d12::D3D12_QUERY_TYPE_OCCLUSION
-            }
-            native::query::HeapType::Occlusion
-                if self.occlusion_query == Some(OcclusionQuery::Binary(id)) =>
-            {
-                self.occlusion_query = None;
-                d3d12::D3D12_QUERY_TYPE_BINARY_OCCLUSION
-            }
-            native::query::HeapType::PipelineStatistics
-                if self.pipeline_stats_query == Some(id) =>
-            {
-                self.pipeline_stats_query = None;
-                d3d12::D3D12_QUERY_TYPE_PIPELINE_STATISTICS
+    #[test]
+    fn test_warp_blit_linear_downscale() {
+        use hal::command::{BufferImageCopy, ImageBlit, RawCommandBuffer};
+        use hal::memory::Dependencies;
+        use hal::pso::PipelineStage;
+        use hal::{buffer, image, Device};
+        use std::iter;
+
+        use test_utils::image_barrier;
+
+        let (warp, mut queue_group) = Warp::open("test_warp_blit_linear_downscale");
+        let device = &warp.device;
+        unsafe {
+            let src = warp.create_target(
+                Format::Rgba8Unorm,
+                (4, 2),
+                1,
+                image::Usage::SAMPLED | image::Usage::TRANSFER_DST | image::Usage::TRANSFER_SRC,
+            );
+            let dst = warp.create_target(
+                Format::Rgba8Unorm,
+                (2, 1),
+                1,
+                image::Usage::COLOR_ATTACHMENT
+                    | image::Usage::TRANSFER_DST
+                    | image::Usage::TRANSFER_SRC,
+            );
+
+            // Rows of 64 texels keep the buffer copy aligned, the red channel increases
+            // by 0x20 per texel of each 2x2 block.
+            let mut texels = vec![[0u8, 0, 0xFF, 0xFF]; 64 * 2];
+            for y in 0 .. 2 {
+                for x in 0 .. 4 {
+                    texels[y * 64 + x][0] = (0x80 * (x / 2) + 0x40 * y + 0x20 * (x % 2)) as u8;
+                }
             }
-            _ => panic!("Missing `begin_query` call for query: {:?}", query),
-        };
+            let upload = warp.create_buffer_with_data(&texels, buffer::Usage::TRANSFER_SRC);
 
-        self.raw.EndQuery(query.pool.raw.as_mut_ptr(), query_ty, id);
-    }
+            let color = image::SubresourceLayers {
+                aspects: Aspects::COLOR,
+                level: 0,
+                layers: 0 .. 1,
+            };
+            warp.submit(&mut queue_group, |cmd_buffer| {
+                cmd_buffer.pipeline_barrier(
+                    PipelineStage::TOP_OF_PIPE .. PipelineStage::TRANSFER,
+                    Dependencies::empty(),
+                    vec![
+                        image_barrier(
+                            &src.image,
+                            Aspects::COLOR,
+                            (image::Access::empty(), image::Layout::Undefined)
+                                .. (
+                                    image::Access::TRANSFER_WRITE,
+                                    image::Layout::TransferDstOptimal,
+                                ),
+                        ),
+                        image_barrier(
+                            &dst.image,
+                            Aspects::COLOR,
+                            (image::Access::empty(), image::Layout::Undefined)
+                                .. (
+                                    image::Access::TRANSFER_WRITE,
+                                    image::Layout::TransferDstOptimal,
+                                ),
+                        ),
+                    ],
+                );
+                cmd_buffer.copy_buffer_to_image(
+                    &upload.0,
+                    &src.image,
+                    image::Layout::TransferDstOptimal,
+                    iter::once(BufferImageCopy {
+                        buffer_offset: 0,
+                        buffer_width: 64,
+                        buffer_height: 2,
+                        image_layers: color.clone(),
+                        image_offset: image::Offset::ZERO,
+                        image_extent: image::Extent {
+                            width: 4,
+                            height: 2,
+                            depth: 1,
+                        },
+                    }),
+                );
+                cmd_buffer.pipeline_barrier(
+                    PipelineStage::TRANSFER .. PipelineStage::TRANSFER,
+                    Dependencies::empty(),
+                    iter::once(image_barrier(
+                        &src.image,
+                        Aspects::COLOR,
+                        (
+                            image::Access::TRANSFER_WRITE,
+                            image::Layout::TransferDstOptimal,
+                        )
+                            .. (
+                                image::Access::TRANSFER_READ,
+                                image::Layout::TransferSrcOptimal,
+                            ),
+                    )),
+                );
+                cmd_buffer.blit_image(
+                    &src.image,
+                    image::Layout::TransferSrcOptimal,
+                    &dst.image,
+                    image::Layout::TransferDstOptimal,
+                    image::Filter::Linear,
+                    iter::once(ImageBlit {
+                        src_subresource: color.clone(),
+                        src_bounds: image::Offset::ZERO .. image::Offset { x: 4, y: 2, z: 1 },
+                        dst_subresource: color.clone(),
+                        dst_bounds: image::Offset::ZERO .. image::Offset { x: 2, y: 1, z: 1 },
+                    }),
+                );
+                cmd_buffer.pipeline_barrier(
+                    PipelineStage::TRANSFER .. PipelineStage::TRANSFER,
+                    Dependencies::empty(),
+                    iter::once(image_barrier(
+                        &dst.image,
+                        Aspects::COLOR,
+                        (
+                            image::Access::TRANSFER_WRITE,
+                            image::Layout::TransferDstOptimal,
+                        )
+                            .. (
+                                image::Access::TRANSFER_READ,
+                                image::Layout::TransferSrcOptimal,
+                            ),
+                    )),
+                );
+            });
 
-    unsafe fn reset_query_pool(&mut self, _pool: &r::QueryPool, _queries: Range<query::Id>) {
-        // Nothing to do here
-        // vkCmdResetQueryPool sets the queries to `unavailable` but the specification
-        // doesn't state an affect on the `active` state. Every queries at the end of the command
-        // buffer must be made inactive, which can only be done with EndQuery.
-        // Therefore, every `begin_query` must follow a `end_query` state, the resulting values
-        // after calling are undefined.
-    }
+            // Each texel of the destination averages a 2x2 block of the source.
+            let texels =
+                warp.read_image::<[u8; 4]>(&mut queue_group, &dst.image, Aspects::COLOR, (2, 1));
+            for (texel, &red) in texels.iter().zip(&[0x30, 0xB0]) {
+                assert!((texel[0] as i32 - red).abs() <= 1, "{:?}", texels);
+                assert_eq!(&texel[1 ..], &[0, 0xFF, 0xFF]);
+            }
 
-    unsafe fn copy_query_pool_results(
-        &mut self,
-        _pool: &r::QueryPool,
-        _queries: Range<query::Id>,
-        _buffer: &r::Buffer,
-        _offset: buffer::Offset,
-        _stride: buffer::Offset,
-        _flags: query::ResultFlags,
-    ) {
-        unimplemented!()
+            warp.destroy_buffer(upload);
+            warp.destroy_target(src);
+            warp.destroy_target(dst);
+        }
     }
 
-    unsafe fn write_timestamp(&mut self, _: pso::PipelineStage, query: query::Query<Backend>) {
-        self.raw.EndQuery(
-            query.pool.raw.as_mut_ptr(),
-            d3d12::D3D12_QUERY_TYPE_TIMESTAMP,
-            query.id,
-        );
-    }
+    #[test]
+    fn test_warp_buffer_copy_offset_alignment() {
+        use hal::command::{BufferImageCopy, RawCommandBuffer};
+        use hal::memory::Dependencies;
+        use hal::pso::PipelineStage;
+        use hal::{buffer, image, PhysicalDevice};
+        use std::iter;
 
-    unsafe fn push_graphics_constants(
-        &mut self,
-        _layout: &r::PipelineLayout,
-        _stages: pso::ShaderStageFlags,
-        offset: u32,
-        constants: &[u32],
-    ) {
-        assert!(offset % 4 == 0);
-        self.gr_pipeline
-            .user_data
-            .set_constants(offset as usize / 4, constants);
-    }
+        use test_utils::image_barrier;
 
-    unsafe fn push_compute_constants(
-        &mut self,
-        _layout: &r::PipelineLayout,
-        offset: u32,
-        constants: &[u32],
-    ) {
-        assert!(offset % 4 == 0);
-        self.comp_pipeline
-            .user_data
-            .set_constants(offset as usize / 4, constants);
-    }
+        let (warp, mut queue_group) = Warp::open("test_warp_buffer_copy_offset_alignment");
+        unsafe {
+            let limits = warp.adapter.physical_device.limits();
+            let target = warp.create_target(
+                Format::Rgba8Unorm,
+                (1, 1),
+                1,
+                image::Usage::TRANSFER_DST | image::Usage::TRANSFER_SRC,
+            );
+            assert_eq!(target.image.buffer_copy_alignment(Aspects::COLOR), 4);
+            assert!(limits.min_buffer_copy_offset_alignment <= 4);
+
+            // The texel at `aligned` is copied after the optimal placement alignment, the one
+            // at `misaligned` starts in the middle of a texel.
+            let aligned = limits.optimal_buffer_copy_offset_alignment + 4;
+            let misaligned = aligned + 2;
+            let mut data = vec![0u8; misaligned as usize + 4];
+            data[aligned as usize .. aligned as usize + 4].copy_from_slice(&[1, 2, 3, 4]);
+            data[misaligned as usize .. misaligned as usize + 4].copy_from_slice(&[5, 6, 7, 8]);
+            let upload = warp.create_buffer_with_data(&data, buffer::Usage::TRANSFER_SRC);
+
+            let copy = |buffer_offset| BufferImageCopy {
+                buffer_offset,
+                buffer_width: 0,
+                buffer_height: 0,
+                image_layers: image::SubresourceLayers {
+                    aspects: Aspects::COLOR,
+                    level: 0,
+                    layers: 0 .. 1,
+                },
+                image_offset: image::Offset::ZERO,
+                image_extent: image::Extent {
+                    width: 1,
+                    height: 1,
+                    depth: 1,
+                },
+            };
+            warp.submit(&mut queue_group, |cmd_buffer| {
+                cmd_buffer.pipeline_barrier(
+                    PipelineStage::TOP_OF_PIPE .. PipelineStage::TRANSFER,
+                    Dependencies::empty(),
+                    iter::once(image_barrier(
+                        &target.image,
+                        Aspects::COLOR,
+                        (image::Access::empty(), image::Layout::Undefined)
+                            .. (
+                                image::Access::TRANSFER_WRITE,
+                                image::Layout::TransferDstOptimal,
+                            ),
+                    )),
+                );
+                // The misaligned copy is rejected and leaves the texel of the first one.
+                for &offset in &[aligned, misaligned] {
+                    cmd_buffer.copy_buffer_to_image(
+                        &upload.0,
+                        &target.image,
+                        image::Layout::TransferDstOptimal,
+                        iter::once(copy(offset)),
+                    );
+                }
+                cmd_buffer.pipeline_barrier(
+                    PipelineStage::TRANSFER .. PipelineStage::TRANSFER,
+                    Dependencies::empty(),
+                    iter::once(image_barrier(
+                        &target.image,
+                        Aspects::COLOR,
+                        (
+                            image::Access::TRANSFER_WRITE,
+                            image::Layout::TransferDstOptimal,
+                        )
+                            .. (
+                                image::Access::TRANSFER_READ,
+                                image::Layout::TransferSrcOptimal,
+                            ),
+                    )),
+                );
+            });
 
-    unsafe fn execute_commands<'a, T, I>(&mut self, cmd_buffers: I)
-    where
-        T: 'a + Borrow<CommandBuffer>,
-        I: IntoIterator<Item = &'a T>,
-    {
-        for _cmd_buf in cmd_buffers {
-            error!("TODO: execute_commands");
+            let texels =
+                warp.read_image::<[u8; 4]>(&mut queue_group, &target.image, Aspects::COLOR, (1, 1));
+            assert_eq!(texels, vec![[1, 2, 3, 4]]);
+
+            warp.destroy_buffer(upload);
+            warp.destroy_target(target);
         }
     }
 }
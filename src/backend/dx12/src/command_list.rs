@@ -0,0 +1,108 @@
+//! Dynamic state API of `ID3D12GraphicsCommandList8` and `ID3D12GraphicsCommandList9`,
+//! which isn't exposed by winapi.
+
+#![allow(non_snake_case)]
+
+use winapi::shared::guiddef::GUID;
+use winapi::shared::minwindef::{FLOAT, UINT};
+use winapi::um::d3d12;
+use winapi::Interface;
+
+#[repr(C)]
+#[allow(dead_code)]
+pub(crate) struct GraphicsCommandList8Vtbl {
+    parent: d3d12::ID3D12GraphicsCommandList1Vtbl,
+    // `ID3D12GraphicsCommandList2::WriteBufferImmediate`
+    _write_buffer_immediate: usize,
+    // `ID3D12GraphicsCommandList3::SetProtectedResourceSession`
+    _set_protected_resource_session: usize,
+    // `ID3D12GraphicsCommandList4::BeginRenderPass`
+    _begin_render_pass: usize,
+    // `ID3D12GraphicsCommandList4::EndRenderPass`
+    _end_render_pass: usize,
+    // `ID3D12GraphicsCommandList4::InitializeMetaCommand`
+    _initialize_meta_command: usize,
+    // `ID3D12GraphicsCommandList4::ExecuteMetaCommand`
+    _execute_meta_command: usize,
+    // `ID3D12GraphicsCommandList4::BuildRaytracingAccelerationStructure`
+    _build_raytracing_acceleration_structure: usize,
+    // `ID3D12GraphicsCommandList4::EmitRaytracingAccelerationStructurePostbuildInfo`
+    _emit_raytracing_acceleration_structure_postbuild_info: usize,
+    // `ID3D12GraphicsCommandList4::CopyRaytracingAccelerationStructure`
+    _copy_raytracing_acceleration_structure: usize,
+    // `ID3D12GraphicsCommandList4::SetPipelineState1`
+    _set_pipeline_state1: usize,
+    // `ID3D12GraphicsCommandList4::DispatchRays`
+    _dispatch_rays: usize,
+    // `ID3D12GraphicsCommandList5::RSSetShadingRate`
+    _rs_set_shading_rate: usize,
+    // `ID3D12GraphicsCommandList5::RSSetShadingRateImage`
+    _rs_set_shading_rate_image: usize,
+    // `ID3D12GraphicsCommandList6::DispatchMesh`
+    _dispatch_mesh: usize,
+    // `ID3D12GraphicsCommandList7::Barrier`
+    _barrier: usize,
+    OMSetFrontAndBackStencilRef: unsafe extern "system" fn(
+        This: *mut GraphicsCommandList8,
+        FrontStencilRef: UINT,
+        BackStencilRef: UINT,
+    ),
+}
+
+#[repr(C)]
+pub(crate) struct GraphicsCommandList8 {
+    lpVtbl: *const GraphicsCommandList8Vtbl,
+}
+
+impl Interface for GraphicsCommandList8 {
+    fn uuidof() -> GUID {
+        GUID {
+            Data1: 0xee936ef9,
+            Data2: 0x599d,
+            Data3: 0x4d28,
+            Data4: [0x93, 0x8e, 0x23, 0xc4, 0xad, 0x05, 0xce, 0x51],
+        }
+    }
+}
+
+impl GraphicsCommandList8 {
+    pub(crate) unsafe fn OMSetFrontAndBackStencilRef(&self, front: UINT, back: UINT) {
+        ((*self.lpVtbl).OMSetFrontAndBackStencilRef)(self as *const _ as *mut _, front, back)
+    }
+}
+
+#[repr(C)]
+#[allow(dead_code)]
+pub(crate) struct GraphicsCommandList9Vtbl {
+    parent: GraphicsCommandList8Vtbl,
+    RSSetDepthBias: unsafe extern "system" fn(
+        This: *mut GraphicsCommandList9,
+        DepthBias: FLOAT,
+        DepthBiasClamp: FLOAT,
+        SlopeScaledDepthBias: FLOAT,
+    ),
+    // `ID3D12GraphicsCommandList9::IASetIndexBufferStripCutValue`
+    _ia_set_index_buffer_strip_cut_value: usize,
+}
+
+#[repr(C)]
+pub(crate) struct GraphicsCommandList9 {
+    lpVtbl: *const GraphicsCommandList9Vtbl,
+}
+
+impl Interface for GraphicsCommandList9 {
+    fn uuidof() -> GUID {
+        GUID {
+            Data1: 0x34ed2808,
+            Data2: 0xffe6,
+            Data3: 0x4c2b,
+            Data4: [0xb1, 0x1a, 0xca, 0xbd, 0x2b, 0x0c, 0x59, 0xe1],
+        }
+    }
+}
+
+impl GraphicsCommandList9 {
+    pub(crate) unsafe fn RSSetDepthBias(&self, bias: FLOAT, clamp: FLOAT, slope_scaled: FLOAT) {
+        ((*self.lpVtbl).RSSetDepthBias)(self as *const _ as *mut _, bias, clamp, slope_scaled)
+    }
+}
@@ -1,19 +1,22 @@
-use validate_line_width;
+use {validate_line_width, QueuePriorityClass};
 
 use spirv_cross::spirv;
 use std::mem;
 
 use winapi::shared::basetsd::UINT8;
 use winapi::shared::dxgiformat::*;
+use winapi::shared::dxgitype::*;
 use winapi::shared::minwindef::{FALSE, INT, TRUE, UINT};
 use winapi::um::d3d12::*;
 use winapi::um::d3dcommon::*;
 
-use hal::format::{Format, ImageFeature, SurfaceType, Swizzle};
+use hal::format::{Aspects, Format, ImageFeature, SurfaceType, Swizzle};
 use hal::pso::DescriptorSetLayoutBinding;
-use hal::{buffer, image, pso, Primitive};
+use hal::{buffer, image, pso, Primitive, QueuePriority};
+use window::ColorSpace;
 
-use native::descriptor::{Binding, DescriptorRange, DescriptorRangeType};
+use native::descriptor::{Binding, DescriptorRange, DescriptorRangeType, StaticBorderColor};
+use native::queue::Priority;
 
 
 pub fn map_format(format: Format) -> Option<DXGI_FORMAT> {
@@ -97,6 +100,8 @@ pub fn map_format(format: Format) -> Option<DXGI_FORMAT> {
         Bc6hSfloat => DXGI_FORMAT_BC6H_SF16,
         Bc7Unorm => DXGI_FORMAT_BC7_UNORM,
         Bc7Srgb => DXGI_FORMAT_BC7_UNORM_SRGB,
+        Nv12Unorm => DXGI_FORMAT_NV12,
+        P010Unorm => DXGI_FORMAT_P010,
 
         _ => return None,
     };
@@ -149,14 +154,30 @@ pub fn map_surface_type(st: SurfaceType) -> Option<DXGI_FORMAT> {
         B10_G11_R11 => DXGI_FORMAT_R11G11B10_FLOAT,
         E5_B9_G9_R9 => DXGI_FORMAT_R9G9B9E5_SHAREDEXP,
         D16 => DXGI_FORMAT_R16_TYPELESS,
-        X8D24 => DXGI_FORMAT_D24_UNORM_S8_UINT,
+        X8D24 => DXGI_FORMAT_R24G8_TYPELESS,
         D32 => DXGI_FORMAT_R32_TYPELESS,
-        D24_S8 => DXGI_FORMAT_D24_UNORM_S8_UINT,
-        D32_S8 => DXGI_FORMAT_D32_FLOAT_S8X24_UINT,
+        D24_S8 => DXGI_FORMAT_R24G8_TYPELESS,
+        D32_S8 => DXGI_FORMAT_R32G8X24_TYPELESS,
+        G8_B8R8_420 => DXGI_FORMAT_NV12,
+        G10X6_B10X6R10X6_420 => DXGI_FORMAT_P010,
         _ => return None,
     })
 }
 
+/// Check if two surface types share a typeless parent format, in which case
+/// `CopyTextureRegion` can copy between them directly.
+pub fn is_same_typeless_family(a: SurfaceType, b: SurfaceType) -> bool {
+    // Formats without a typeless parent, like block-compressed ones, are only
+    // compatible with themselves.
+    if a == b {
+        return true;
+    }
+    match (map_surface_type(a), map_surface_type(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    }
+}
+
 pub fn map_format_dsv(surface: SurfaceType) -> Option<DXGI_FORMAT> {
     Some(match surface {
         SurfaceType::D16 => DXGI_FORMAT_D16_UNORM,
@@ -167,6 +188,62 @@ pub fn map_format_dsv(surface: SurfaceType) -> Option<DXGI_FORMAT> {
     })
 }
 
+/// Map the format of a shader resource view to the format of the plane selected by `aspects`.
+///
+/// Depth-stencil and planar video formats can't be used for SRVs, each aspect is
+/// a separate plane which is viewed with its own format.
+pub fn map_format_shader_resource(format: DXGI_FORMAT, aspects: Aspects) -> DXGI_FORMAT {
+    match format {
+        DXGI_FORMAT_NV12 if aspects == Aspects::PLANE_1 => DXGI_FORMAT_R8G8_UNORM,
+        DXGI_FORMAT_NV12 => DXGI_FORMAT_R8_UNORM,
+        DXGI_FORMAT_P010 if aspects == Aspects::PLANE_1 => DXGI_FORMAT_R16G16_UNORM,
+        DXGI_FORMAT_P010 => DXGI_FORMAT_R16_UNORM,
+        DXGI_FORMAT_D16_UNORM => DXGI_FORMAT_R16_UNORM,
+        DXGI_FORMAT_D32_FLOAT => DXGI_FORMAT_R32_FLOAT,
+        DXGI_FORMAT_D24_UNORM_S8_UINT if aspects == Aspects::STENCIL => {
+            DXGI_FORMAT_X24_TYPELESS_G8_UINT
+        }
+        DXGI_FORMAT_D24_UNORM_S8_UINT => DXGI_FORMAT_R24_UNORM_X8_TYPELESS,
+        DXGI_FORMAT_D32_FLOAT_S8X24_UINT if aspects == Aspects::STENCIL => {
+            DXGI_FORMAT_X32_TYPELESS_G8X24_UINT
+        }
+        DXGI_FORMAT_D32_FLOAT_S8X24_UINT => DXGI_FORMAT_R32_FLOAT_X8X24_TYPELESS,
+        format => format,
+    }
+}
+
+/// Map the aspects of a view to the plane slice of a texture.
+///
+/// Depth is stored in plane 0 and stencil in plane 1. Views covering both
+/// depth and stencil read the depth plane. Planar video formats store luma in
+/// plane 0 and the interleaved chroma in plane 1.
+pub fn map_plane_slice(aspects: Aspects) -> UINT {
+    if aspects == Aspects::STENCIL || aspects == Aspects::PLANE_1 {
+        1
+    } else {
+        0
+    }
+}
+
+/// Describe the plane of a planar image selected by `aspects` as a plain color
+/// surface: `(plane slice, format, bytes per texel)`.
+///
+/// The chroma plane stores two components per texel at half the resolution
+/// of the luma plane in both dimensions.
+pub fn map_planar_layout(
+    surface: SurfaceType,
+    aspects: Aspects,
+) -> Option<(UINT, DXGI_FORMAT, u8)> {
+    let chroma = aspects == Aspects::PLANE_1;
+    Some(match (surface, chroma) {
+        (SurfaceType::G8_B8R8_420, false) => (0, DXGI_FORMAT_R8_UNORM, 1),
+        (SurfaceType::G8_B8R8_420, true) => (1, DXGI_FORMAT_R8G8_UNORM, 2),
+        (SurfaceType::G10X6_B10X6R10X6_420, false) => (0, DXGI_FORMAT_R16_UNORM, 2),
+        (SurfaceType::G10X6_B10X6R10X6_420, true) => (1, DXGI_FORMAT_R16G16_UNORM, 4),
+        _ => return None,
+    })
+}
+
 pub fn map_topology_type(primitive: Primitive) -> D3D12_PRIMITIVE_TOPOLOGY_TYPE {
     use hal::Primitive::*;
     match primitive {
@@ -181,6 +258,27 @@ pub fn map_topology_type(primitive: Primitive) -> D3D12_PRIMITIVE_TOPOLOGY_TYPE
     }
 }
 
+/// Check if primitive restart can be used with the topology.
+///
+/// D3D12 only cuts strips, restarting list topologies isn't supported.
+pub fn supports_primitive_restart(primitive: Primitive) -> bool {
+    use hal::Primitive::*;
+    match primitive {
+        LineStrip | LineStripAdjacency | TriangleStrip | TriangleStripAdjacency => true,
+        _ => false,
+    }
+}
+
+pub fn map_primitive_restart(
+    restart: pso::PrimitiveRestart,
+) -> D3D12_INDEX_BUFFER_STRIP_CUT_VALUE {
+    match restart {
+        pso::PrimitiveRestart::Disabled => D3D12_INDEX_BUFFER_STRIP_CUT_VALUE_DISABLED,
+        pso::PrimitiveRestart::U16 => D3D12_INDEX_BUFFER_STRIP_CUT_VALUE_0xFFFF,
+        pso::PrimitiveRestart::U32 => D3D12_INDEX_BUFFER_STRIP_CUT_VALUE_0xFFFFFFFF,
+    }
+}
+
 pub fn map_topology(primitive: Primitive) -> D3D12_PRIMITIVE_TOPOLOGY {
     use hal::Primitive::*;
     match primitive {
@@ -205,7 +303,7 @@ pub fn map_rasterizer(rasterizer: &pso::Rasterizer) -> D3D12_RASTERIZER_DESC {
     use hal::pso::PolygonMode::*;
 
     let bias = match rasterizer.depth_bias {
-        //TODO: support dynamic depth bias
+        // Dynamic depth bias is set on the command list if supported.
         Some(pso::State::Static(db)) => db,
         Some(_) | None => pso::DepthBias::default(),
     };
@@ -240,7 +338,6 @@ pub fn map_rasterizer(rasterizer: &pso::Rasterizer) -> D3D12_RASTERIZER_DESC {
         ForcedSampleCount: 0,         // TODO: currently not supported
         AntialiasedLineEnable: FALSE, // TODO: currently not supported
         ConservativeRaster: if rasterizer.conservative {
-            // TODO: check support
             D3D12_CONSERVATIVE_RASTERIZATION_MODE_ON
         } else {
             D3D12_CONSERVATIVE_RASTERIZATION_MODE_OFF
@@ -286,9 +383,29 @@ fn map_blend_op(operation: pso::BlendOp) -> (D3D12_BLEND_OP, D3D12_BLEND, D3D12_
     }
 }
 
-pub fn map_render_targets(
-    color_targets: &[pso::ColorBlendDesc],
-) -> [D3D12_RENDER_TARGET_BLEND_DESC; 8] {
+fn map_logic_op(op: &pso::LogicOp) -> D3D12_LOGIC_OP {
+    use hal::pso::LogicOp::*;
+    match *op {
+        Clear => D3D12_LOGIC_OP_CLEAR,
+        And => D3D12_LOGIC_OP_AND,
+        AndReverse => D3D12_LOGIC_OP_AND_REVERSE,
+        Copy => D3D12_LOGIC_OP_COPY,
+        AndInverted => D3D12_LOGIC_OP_AND_INVERTED,
+        NoOp => D3D12_LOGIC_OP_NOOP,
+        Xor => D3D12_LOGIC_OP_XOR,
+        Or => D3D12_LOGIC_OP_OR,
+        Nor => D3D12_LOGIC_OP_NOR,
+        Equivalent => D3D12_LOGIC_OP_EQUIV,
+        Invert => D3D12_LOGIC_OP_INVERT,
+        OrReverse => D3D12_LOGIC_OP_OR_REVERSE,
+        CopyInverted => D3D12_LOGIC_OP_COPY_INVERTED,
+        OrInverted => D3D12_LOGIC_OP_OR_INVERTED,
+        Nand => D3D12_LOGIC_OP_NAND,
+        Set => D3D12_LOGIC_OP_SET,
+    }
+}
+
+pub fn map_render_targets(blender: &pso::BlendDesc) -> [D3D12_RENDER_TARGET_BLEND_DESC; 8] {
     let dummy_target = D3D12_RENDER_TARGET_BLEND_DESC {
         BlendEnable: FALSE,
         LogicOpEnable: FALSE,
@@ -303,10 +420,18 @@ pub fn map_render_targets(
     };
     let mut targets = [dummy_target; 8];
 
-    for (target, &pso::ColorBlendDesc(mask, blend)) in targets.iter_mut().zip(color_targets.iter())
+    for (target, &pso::ColorBlendDesc(mask, blend)) in
+        targets.iter_mut().zip(blender.targets.iter())
     {
         target.RenderTargetWriteMask = mask.bits() as UINT8;
-        if let pso::BlendState::On { color, alpha } = blend {
+        if let Some(ref op) = blender.logic_op {
+            // Logic operations and blending are mutually exclusive in D3D12.
+            if blend != pso::BlendState::Off {
+                warn!("Blending is ignored when a logic operation is enabled");
+            }
+            target.LogicOpEnable = TRUE;
+            target.LogicOp = map_logic_op(op);
+        } else if let pso::BlendState::On { color, alpha } = blend {
             let (color_op, color_src, color_dst) = map_blend_op(color);
             let (alpha_op, alpha_src, alpha_dst) = map_blend_op(alpha);
             target.BlendEnable = TRUE;
@@ -419,6 +544,24 @@ pub fn map_wrap(wrap: image::WrapMode) -> D3D12_TEXTURE_ADDRESS_MODE {
     }
 }
 
+/// Map the border color of a sampler to one of the fixed border colors of static samplers.
+///
+/// Dynamic samplers support arbitrary border colors, static samplers are limited to
+/// transparent black, opaque black and opaque white. The border color is irrelevant
+/// if none of the wrap modes uses the border.
+pub fn map_static_border_color(info: &image::SamplerInfo) -> Option<StaticBorderColor> {
+    let (u, v, w) = info.wrap_mode;
+    if ![u, v, w].contains(&image::WrapMode::Border) {
+        return Some(StaticBorderColor::TransparentBlack);
+    }
+    match info.border {
+        image::PackedColor(0x0000_0000) => Some(StaticBorderColor::TransparentBlack),
+        image::PackedColor(0xFF00_0000) => Some(StaticBorderColor::OpaqueBlack),
+        image::PackedColor(0xFFFF_FFFF) => Some(StaticBorderColor::OpaqueWhite),
+        _ => None,
+    }
+}
+
 fn map_filter_type(filter: image::Filter) -> D3D12_FILTER_TYPE {
     match filter {
         image::Filter::Nearest => D3D12_FILTER_TYPE_POINT,
@@ -451,6 +594,27 @@ pub fn map_filter(
         | map_anisotropic(anisotropic)
 }
 
+/// Map the filter of a sampler.
+///
+/// Samplers with a comparison function use the `D3D12_FILTER_COMPARISON_*` variants,
+/// required for `SampleCmp` on `SamplerComparisonState` in shaders. Otherwise the
+/// reduction mode selects between the standard, minimum and maximum variants.
+pub fn map_sampler_filter(info: &image::SamplerInfo) -> D3D12_FILTER {
+    let reduction = match (info.comparison, info.reduction) {
+        (Some(_), _) => D3D12_FILTER_REDUCTION_TYPE_COMPARISON,
+        (None, image::ReductionMode::WeightedAverage) => D3D12_FILTER_REDUCTION_TYPE_STANDARD,
+        (None, image::ReductionMode::Minimum) => D3D12_FILTER_REDUCTION_TYPE_MINIMUM,
+        (None, image::ReductionMode::Maximum) => D3D12_FILTER_REDUCTION_TYPE_MAXIMUM,
+    };
+    map_filter(
+        info.mag_filter,
+        info.min_filter,
+        info.mip_filter,
+        reduction,
+        info.anisotropic,
+    )
+}
+
 pub fn map_buffer_resource_state(access: buffer::Access) -> D3D12_RESOURCE_STATES {
     use self::buffer::Access;
     // Mutable states
@@ -542,10 +706,15 @@ pub fn map_image_resource_state(
     state
 }
 
+/// Map a binding to a descriptor range of a root signature table.
+///
+/// Unbounded ranges cover all registers from the binding onwards, they have to be the
+/// last range of their table.
 pub fn map_descriptor_range(
     bind: &DescriptorSetLayoutBinding,
     register_space: u32,
     sampler: bool,
+    unbounded: bool,
 ) -> DescriptorRange {
     DescriptorRange::new(
         match bind.ty {
@@ -568,7 +737,7 @@ pub fn map_descriptor_range(
                 }
             }
         },
-        bind.count as _,
+        if unbounded { !0 } else { bind.count as _ },
         Binding {
             register: bind.binding as _,
             space: register_space,
@@ -642,3 +811,25 @@ pub fn map_stage(stage: pso::Stage) -> spirv::ExecutionModel {
         pso::Stage::Domain => spirv::ExecutionModel::TessellationEvaluation,
     }
 }
+
+/// Map normalized queue priorities onto the D3D12 priority classes.
+///
+/// Priorities are clamped to `max`. A global realtime queue requires elevated privileges
+/// and may need to fall back to `High` on creation.
+pub fn map_queue_priority(priority: QueuePriority, max: QueuePriorityClass) -> Priority {
+    if max >= QueuePriorityClass::Realtime && priority >= 1.0 {
+        Priority::GlobalRealtime
+    } else if max >= QueuePriorityClass::High && priority > 0.5 {
+        Priority::High
+    } else {
+        Priority::Normal
+    }
+}
+
+pub fn map_color_space(color_space: ColorSpace) -> DXGI_COLOR_SPACE_TYPE {
+    match color_space {
+        ColorSpace::Srgb => DXGI_COLOR_SPACE_RGB_FULL_G22_NONE_P709,
+        ColorSpace::Hdr10 => DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020,
+        ColorSpace::ScRgb => DXGI_COLOR_SPACE_RGB_FULL_G10_NONE_P709,
+    }
+}
@@ -1,3 +1,5 @@
+use hal::backend::FastHashMap;
+use hal::image::SamplerInfo;
 use native;
 use native::descriptor::{CpuDescriptor, HeapFlags, HeapType};
 use std::collections::HashSet;
@@ -95,6 +97,14 @@ impl Heap {
         }
     }
 
+    pub fn free_handle(&mut self, handle: CpuDescriptor) {
+        let slot = (handle.ptr - self.start.ptr) / self.handle_size;
+        assert!(slot < HEAP_SIZE_FIXED);
+        assert_eq!(self.availability & (1 << slot), 0);
+        // Set the slot as free.
+        self.availability ^= 1 << slot;
+    }
+
     pub fn is_full(&self) -> bool {
         self.availability == 0
     }
@@ -143,7 +153,18 @@ impl DescriptorCpuPool {
         handle
     }
 
-    // TODO: free handles
+    pub fn free_handle(&mut self, handle: CpuDescriptor) {
+        let heap_id = self
+            .heaps
+            .iter()
+            .position(|heap| {
+                heap.start.ptr <= handle.ptr
+                    && handle.ptr < heap.start.ptr + heap.handle_size * HEAP_SIZE_FIXED
+            })
+            .expect("Descriptor handle not allocated from this pool");
+        self.heaps[heap_id].free_handle(handle);
+        self.free_list.insert(heap_id);
+    }
 
     pub unsafe fn destroy(&self) {
         for heap in &self.heaps {
@@ -151,3 +172,52 @@ impl DescriptorCpuPool {
         }
     }
 }
+
+// Shared sampler descriptors, deduplicated by their sampler state.
+//
+// Each entry is reference counted by the samplers using the descriptor.
+#[derive(Derivative, Default)]
+#[derivative(Debug)]
+pub struct SamplerCache {
+    #[derivative(Debug = "ignore")]
+    samplers: FastHashMap<SamplerInfo, (CpuDescriptor, usize)>,
+}
+
+impl SamplerCache {
+    // Get the descriptor for the sampler state, creating it with `create` if
+    // there is no sampler with an identical state yet.
+    pub fn get_or_create<F>(&mut self, info: &SamplerInfo, create: F) -> CpuDescriptor
+    where
+        F: FnOnce() -> CpuDescriptor,
+    {
+        if let Some(&mut (handle, ref mut count)) = self.samplers.get_mut(info) {
+            *count += 1;
+            return handle;
+        }
+        let handle = create();
+        self.samplers.insert(info.clone(), (handle, 1));
+        handle
+    }
+
+    // Release a reference to the descriptor of the sampler state.
+    //
+    // Returns the descriptor if it isn't used anymore.
+    pub fn release(&mut self, info: &SamplerInfo) -> Option<CpuDescriptor> {
+        let unused = match self.samplers.get_mut(info) {
+            Some(&mut (_, ref mut count)) => {
+                *count -= 1;
+                *count == 0
+            }
+            None => false,
+        };
+        if unused {
+            self.samplers.remove(info).map(|(handle, _)| handle)
+        } else {
+            None
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.samplers.len()
+    }
+}
@@ -1,13 +1,17 @@
 use std::borrow::Borrow;
-use std::collections::{BTreeMap, VecDeque};
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
 use std::ops::Range;
-use std::{ffi, mem, ptr, slice};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::{ffi, iter, mem, ptr, slice};
 
 use spirv_cross::{hlsl, spirv, ErrorCode as SpirvErrorCode};
 
 use winapi::shared::minwindef::{FALSE, TRUE, UINT};
 use winapi::shared::{dxgi, dxgi1_2, dxgi1_4, dxgiformat, dxgitype, winerror};
-use winapi::um::{d3d12, d3dcompiler, synchapi, winbase, winnt};
+use winapi::um::{d3d12, d3dcommon, d3dcompiler, synchapi, winbase, winnt};
 use winapi::Interface;
 
 use hal::format::Aspects;
@@ -35,7 +39,9 @@ use {
     Backend as B,
     Device,
     MemoryGroup,
+    MAX_SHADER_VISIBLE_SRV_CBV_UAV_HEAP_SIZE,
     MAX_VERTEX_BUFFERS,
+    NON_COHERENT_ATOM_SIZE,
     NUM_HEAP_PROPERTIES,
     QUEUE_FAMILIES,
 };
@@ -53,6 +59,9 @@ const MEM_TYPE_TARGET_SHIFT: u64 = MEM_TYPE_SHIFT * MemoryGroup::TargetOnly as u
 
 pub const IDENTITY_MAPPING: UINT = 0x1688; // D3D12_DEFAULT_SHADER_4_COMPONENT_MAPPING
 
+// Placed images are created in the common state.
+const INITIAL_IMAGE_STATE: d3d12::D3D12_RESOURCE_STATES = d3d12::D3D12_RESOURCE_STATE_COMMON;
+
 /// Emit error during shader module creation. Used if we don't expect an error
 /// but might panic due to an exception in SPIRV-Cross.
 fn gen_unexpected_error(err: SpirvErrorCode) -> d::ShaderError {
@@ -89,6 +98,87 @@ pub(crate) enum CommandSignature {
     Dispatch,
 }
 
+/// Pending descriptor copies into the shader-visible heaps.
+///
+/// Adjacent destination handles are merged into a single range, so updating a
+/// handful of consecutive slots of a set only results in a few ranges.
+#[derive(Default)]
+struct DescriptorUpdates {
+    dst_starts: Vec<native::CpuDescriptor>,
+    dst_counts: Vec<u32>,
+    src: Vec<native::CpuDescriptor>,
+    handle_size: usize,
+}
+
+impl DescriptorUpdates {
+    fn new(handle_size: usize) -> Self {
+        DescriptorUpdates {
+            handle_size,
+            ..Self::default()
+        }
+    }
+
+    fn push(&mut self, dst: native::CpuDescriptor, src: native::CpuDescriptor) {
+        self.src.push(src);
+        if let (Some(start), Some(count)) =
+            (self.dst_starts.last(), self.dst_counts.last_mut())
+        {
+            if start.ptr + *count as usize * self.handle_size == dst.ptr {
+                *count += 1;
+                return;
+            }
+        }
+        self.dst_starts.push(dst);
+        self.dst_counts.push(1);
+    }
+
+    unsafe fn flush(&mut self, device: native::Device, ty: d3d12::D3D12_DESCRIPTOR_HEAP_TYPE) {
+        if self.src.is_empty() {
+            return;
+        }
+        // Source ranges are all single descriptors, which is what a null
+        // `pSrcDescriptorRangeSizes` denotes.
+        device.clone().CopyDescriptors(
+            self.dst_starts.len() as u32,
+            self.dst_starts.as_ptr(),
+            self.dst_counts.as_ptr(),
+            self.src.len() as u32,
+            self.src.as_ptr(),
+            ptr::null(),
+            ty,
+        );
+        self.dst_starts.clear();
+        self.dst_counts.clear();
+        self.src.clear();
+    }
+}
+
+// Target profile of a shader stage, nul-terminated.
+fn shader_profile(stage: pso::Stage, shader_model: hlsl::ShaderModel) -> String {
+    let stage = match stage {
+        pso::Stage::Vertex => "vs",
+        pso::Stage::Hull => "hs",
+        pso::Stage::Domain => "ds",
+        pso::Stage::Geometry => "gs",
+        pso::Stage::Fragment => "ps",
+        pso::Stage::Compute => "cs",
+    };
+
+    let model = match shader_model {
+        hlsl::ShaderModel::V5_0 => "5_0",
+        hlsl::ShaderModel::V5_1 => "5_1",
+        hlsl::ShaderModel::V6_0 => "6_0",
+        _ => unimplemented!(),
+    };
+
+    format!("{}_{}\0", stage, model)
+}
+
+// Check the container header of DXBC bytecode.
+fn is_dxbc(bytecode: &[u8]) -> bool {
+    bytecode.starts_with(b"DXBC")
+}
+
 /// Compile a single shader entry point from a HLSL text shader
 pub(crate) fn compile_shader(
     stage: pso::Stage,
@@ -96,24 +186,6 @@ pub(crate) fn compile_shader(
     entry: &str,
     code: &[u8],
 ) -> Result<native::Blob, d::ShaderError> {
-    let stage_to_str = |stage, shader_model| {
-        let stage = match stage {
-            pso::Stage::Vertex => "vs",
-            pso::Stage::Fragment => "ps",
-            pso::Stage::Compute => "cs",
-            _ => unimplemented!(),
-        };
-
-        let model = match shader_model {
-            hlsl::ShaderModel::V5_0 => "5_0",
-            hlsl::ShaderModel::V5_1 => "5_1",
-            hlsl::ShaderModel::V6_0 => "6_0",
-            _ => unimplemented!(),
-        };
-
-        format!("{}_{}\0", stage, model)
-    };
-
     let mut shader_data = native::Blob::null();
     let mut error = native::Blob::null();
     let entry = ffi::CString::new(entry).unwrap();
@@ -125,7 +197,7 @@ pub(crate) fn compile_shader(
             ptr::null(),
             ptr::null_mut(),
             entry.as_ptr() as *const _,
-            stage_to_str(stage, shader_model).as_ptr() as *const i8,
+            shader_profile(stage, shader_model).as_ptr() as *const i8,
             1,
             0,
             shader_data.mut_void() as *mut *mut _,
@@ -149,6 +221,465 @@ pub(crate) fn compile_shader(
     }
 }
 
+// Number of counters in `D3D12_QUERY_DATA_PIPELINE_STATISTICS`.
+const NUM_PIPELINE_STATISTICS: usize = 11;
+
+/// Select the requested pipeline statistics from the resolved D3D12 counters.
+///
+/// The counters of `D3D12_QUERY_DATA_PIPELINE_STATISTICS` are laid out in the
+/// same order as the `PipelineStatistic` flags.
+fn map_pipeline_statistics<'a>(
+    statistics: query::PipelineStatistic,
+    counters: &'a [u64],
+) -> impl Iterator<Item = u64> + 'a {
+    counters
+        .iter()
+        .enumerate()
+        .filter(move |&(i, _)| statistics.bits() & (1 << i) != 0)
+        .map(|(_, &value)| value)
+}
+
+// Write the `index`-th value of a query result.
+unsafe fn write_query_value(dst: *mut u8, index: usize, value: u64, bits_64: bool) {
+    if bits_64 {
+        ptr::write_unaligned((dst as *mut u64).offset(index as isize), value);
+    } else {
+        ptr::write_unaligned((dst as *mut u32).offset(index as isize), value as u32);
+    }
+}
+
+/// Check the number of mip levels requested for an image.
+///
+/// D3D12 would interpret zero levels as the full mip chain.
+fn is_valid_mip_count(kind: &image::Kind, levels: image::Level) -> bool {
+    levels > 0
+        && levels <= kind.num_levels()
+        && levels as u32 <= d3d12::D3D12_REQ_MIP_LEVELS
+}
+
+/// Check the kind of an image with a planar format.
+///
+/// Planar images are single-level, single-sample 2D images. The chroma plane
+/// has half the resolution of the luma plane, so the extent has to be even.
+fn is_valid_planar_kind(kind: &image::Kind, levels: image::Level) -> bool {
+    match *kind {
+        image::Kind::D2(width, height, _, 1) => {
+            levels == 1 && width % 2 == 0 && height % 2 == 0
+        }
+        _ => false,
+    }
+}
+
+/// Check if the anisotropy of a sampler is allowed by the enabled device features.
+///
+/// Without `SAMPLER_ANISOTROPY` the maximum anisotropy has to be 1.
+fn is_anisotropy_enabled(anisotropic: image::Anisotropic, features: hal::Features) -> bool {
+    match anisotropic {
+        image::Anisotropic::On(max) if max > 1 => {
+            features.contains(hal::Features::SAMPLER_ANISOTROPY)
+        }
+        _ => true,
+    }
+}
+
+/// Check if any color target of a pipeline blends with the blend constants.
+fn uses_blend_constants(blender: &pso::BlendDesc) -> bool {
+    fn is_constant(op: pso::BlendOp) -> bool {
+        use hal::pso::Factor::*;
+        match op {
+            pso::BlendOp::Add { src, dst }
+            | pso::BlendOp::Sub { src, dst }
+            | pso::BlendOp::RevSub { src, dst } => [src, dst].iter().any(|factor| match *factor {
+                ConstColor | OneMinusConstColor | ConstAlpha | OneMinusConstAlpha => true,
+                _ => false,
+            }),
+            pso::BlendOp::Min | pso::BlendOp::Max => false,
+        }
+    }
+
+    blender.targets.iter().any(|target| match target.1 {
+        pso::BlendState::On { color, alpha } => is_constant(color) || is_constant(alpha),
+        pso::BlendState::Off => false,
+    })
+}
+
+/// Check if the depth bias of a pipeline is set on the command list.
+///
+/// Without support, dynamic depth bias falls back to the default bias of the pipeline.
+fn is_depth_bias_dynamic(depth_bias: Option<pso::State<pso::DepthBias>>, supported: bool) -> bool {
+    match depth_bias {
+        Some(pso::State::Dynamic) if supported => true,
+        Some(pso::State::Dynamic) => {
+            warn!("Dynamic depth bias is not supported by the device");
+            false
+        }
+        Some(pso::State::Static(_)) | None => false,
+    }
+}
+
+/// Check if the last binding of a layout has a variable descriptor count.
+///
+/// Unbounded descriptor ranges cover all registers from their binding onwards, so a binding
+/// with variable count has to be the last binding with the highest binding number.
+fn has_variable_count(
+    bindings: &[(pso::DescriptorSetLayoutBinding, r::DescriptorBindingFlags)],
+) -> Result<bool, ()> {
+    let variable_count = r::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT;
+    let position = bindings
+        .iter()
+        .position(|&(_, flags)| flags.contains(variable_count));
+    let index = match position {
+        Some(index) => index,
+        None => return Ok(false),
+    };
+    let binding = &bindings[index].0;
+    if index + 1 != bindings.len()
+        || bindings[.. index].iter().any(|&(ref other, _)| other.binding > binding.binding)
+    {
+        error!(
+            "Binding {} with variable descriptor count has to be the last binding",
+            binding.binding
+        );
+        return Err(());
+    }
+    if binding.immutable_samplers {
+        error!(
+            "Binding {} with variable descriptor count can't have immutable samplers",
+            binding.binding
+        );
+        return Err(());
+    }
+    Ok(true)
+}
+
+/// Check that the update frequency hints of a binding don't contradict its other flags.
+///
+/// Static data requires static descriptors, which have to be initialized before the set is
+/// bound and can't be updated afterwards.
+fn is_valid_binding_flags(
+    binding: &pso::DescriptorSetLayoutBinding,
+    flags: r::DescriptorBindingFlags,
+) -> bool {
+    use r::DescriptorBindingFlags as F;
+    let volatile = F::UPDATE_AFTER_BIND | F::PARTIALLY_BOUND | F::VARIABLE_DESCRIPTOR_COUNT;
+    if flags.contains(F::DATA_STATIC | F::DATA_STATIC_WHILE_SET_AT_EXECUTE) {
+        error!("Binding {} can't have both static data hints", binding.binding);
+        return false;
+    }
+    if flags.contains(F::DATA_STATIC) && flags.intersects(volatile) {
+        error!("Binding {} with static data requires static descriptors", binding.binding);
+        return false;
+    }
+    true
+}
+
+/// Map the update frequency hints of a binding to the flags of its version 1.1 range.
+///
+/// Sets can be written while bound, so descriptors stay volatile unless the data is static.
+/// Without hints the data is volatile as well, which matches version 1.0 root signatures.
+/// Sampler ranges don't have data flags.
+fn descriptor_range_flags(
+    flags: r::DescriptorBindingFlags,
+    sampler: bool,
+) -> d3d12::D3D12_DESCRIPTOR_RANGE_FLAGS {
+    use r::DescriptorBindingFlags as F;
+    if sampler {
+        d3d12::D3D12_DESCRIPTOR_RANGE_FLAG_DESCRIPTORS_VOLATILE
+    } else if flags.contains(F::DATA_STATIC) {
+        d3d12::D3D12_DESCRIPTOR_RANGE_FLAG_DATA_STATIC
+    } else if flags.contains(F::DATA_STATIC_WHILE_SET_AT_EXECUTE) {
+        d3d12::D3D12_DESCRIPTOR_RANGE_FLAG_DESCRIPTORS_VOLATILE
+            | d3d12::D3D12_DESCRIPTOR_RANGE_FLAG_DATA_STATIC_WHILE_SET_AT_EXECUTE
+    } else {
+        d3d12::D3D12_DESCRIPTOR_RANGE_FLAG_DESCRIPTORS_VOLATILE
+            | d3d12::D3D12_DESCRIPTOR_RANGE_FLAG_DATA_VOLATILE
+    }
+}
+
+/// Serialize a version 1.1 root signature, `range_flags` holds the flags of each of `ranges`.
+///
+/// The descriptor tables of `parameters` have to reference `ranges`.
+unsafe fn serialize_root_signature_1_1(
+    parameters: &[descriptor::RootParameter],
+    ranges: &[descriptor::DescriptorRange],
+    range_flags: &[d3d12::D3D12_DESCRIPTOR_RANGE_FLAGS],
+    static_samplers: &[descriptor::StaticSampler],
+    flags: descriptor::RootSignatureFlags,
+) -> native::Blob {
+    // The descriptor types are transparent wrappers of the version 1.0 structures.
+    let raw_ranges = slice::from_raw_parts(
+        ranges.as_ptr() as *const d3d12::D3D12_DESCRIPTOR_RANGE,
+        ranges.len(),
+    );
+    let raw_parameters = slice::from_raw_parts(
+        parameters.as_ptr() as *const d3d12::D3D12_ROOT_PARAMETER,
+        parameters.len(),
+    );
+
+    let ranges1 = raw_ranges
+        .iter()
+        .zip(range_flags)
+        .map(|(range, &flags)| d3d12::D3D12_DESCRIPTOR_RANGE1 {
+            RangeType: range.RangeType,
+            NumDescriptors: range.NumDescriptors,
+            BaseShaderRegister: range.BaseShaderRegister,
+            RegisterSpace: range.RegisterSpace,
+            Flags: flags,
+            OffsetInDescriptorsFromTableStart: range.OffsetInDescriptorsFromTableStart,
+        })
+        .collect::<Vec<_>>();
+    let parameters1 = raw_parameters
+        .iter()
+        .map(|parameter| {
+            let mut parameter1 = d3d12::D3D12_ROOT_PARAMETER1 {
+                ParameterType: parameter.ParameterType,
+                u: mem::zeroed(),
+                ShaderVisibility: parameter.ShaderVisibility,
+            };
+            match parameter.ParameterType {
+                d3d12::D3D12_ROOT_PARAMETER_TYPE_DESCRIPTOR_TABLE => {
+                    let table = parameter.u.DescriptorTable();
+                    let offset = (table.pDescriptorRanges as usize - raw_ranges.as_ptr() as usize)
+                        / mem::size_of::<d3d12::D3D12_DESCRIPTOR_RANGE>();
+                    *parameter1.u.DescriptorTable_mut() = d3d12::D3D12_ROOT_DESCRIPTOR_TABLE1 {
+                        NumDescriptorRanges: table.NumDescriptorRanges,
+                        pDescriptorRanges: ranges1[offset ..].as_ptr(),
+                    };
+                }
+                d3d12::D3D12_ROOT_PARAMETER_TYPE_32BIT_CONSTANTS => {
+                    *parameter1.u.Constants_mut() = *parameter.u.Constants();
+                }
+                _ => {
+                    let root_descriptor = parameter.u.Descriptor();
+                    *parameter1.u.Descriptor_mut() = d3d12::D3D12_ROOT_DESCRIPTOR1 {
+                        ShaderRegister: root_descriptor.ShaderRegister,
+                        RegisterSpace: root_descriptor.RegisterSpace,
+                        Flags: d3d12::D3D12_ROOT_DESCRIPTOR_FLAG_DATA_VOLATILE,
+                    };
+                }
+            }
+            parameter1
+        })
+        .collect::<Vec<_>>();
+
+    let mut desc = d3d12::D3D12_VERSIONED_ROOT_SIGNATURE_DESC {
+        Version: d3d12::D3D_ROOT_SIGNATURE_VERSION_1_1,
+        u: mem::zeroed(),
+    };
+    *desc.u.Desc_1_1_mut() = d3d12::D3D12_ROOT_SIGNATURE_DESC1 {
+        NumParameters: parameters1.len() as _,
+        pParameters: parameters1.as_ptr(),
+        NumStaticSamplers: static_samplers.len() as _,
+        pStaticSamplers: static_samplers.as_ptr() as *const _,
+        Flags: flags.bits(),
+    };
+
+    let mut blob = native::Blob::null();
+    let mut error = native::Blob::null();
+    let hr = d3d12::D3D12SerializeVersionedRootSignature(
+        &desc,
+        blob.mut_void() as *mut *mut _,
+        error.mut_void() as *mut *mut _,
+    );
+    if !error.is_null() {
+        let message = slice::from_raw_parts(
+            error.GetBufferPointer() as *const u8,
+            error.GetBufferSize() as usize,
+        );
+        error!(
+            "Root signature serialization error: {:?}",
+            String::from_utf8_lossy(message)
+        );
+        error.destroy();
+    }
+    blob
+}
+
+/// Formats of the render targets of a subpass, in the order of its color attachments.
+///
+/// Returns `None` if the subpass has more color attachments than simultaneous render targets.
+fn render_target_formats(
+    attachments: &[pass::Attachment],
+    colors: &[pass::AttachmentRef],
+) -> Option<([dxgiformat::DXGI_FORMAT; 8], UINT)> {
+    if colors.len() > d3d12::D3D12_SIMULTANEOUS_RENDER_TARGET_COUNT as usize {
+        return None;
+    }
+    let mut formats = [dxgiformat::DXGI_FORMAT_UNKNOWN; 8];
+    for (format, &(id, _)) in formats.iter_mut().zip(colors) {
+        *format = attachments[id]
+            .format
+            .and_then(conv::map_format)
+            .unwrap_or(dxgiformat::DXGI_FORMAT_UNKNOWN);
+    }
+    Some((formats, colors.len() as _))
+}
+
+/// Resource state of an input attachment, given the state of other uses in the same subpass.
+///
+/// Only read-only depth stencil attachments can be used as input attachment at the same time.
+fn input_attachment_state(
+    current: Option<d3d12::D3D12_RESOURCE_STATES>,
+) -> Option<d3d12::D3D12_RESOURCE_STATES> {
+    match current {
+        None => Some(d3d12::D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE),
+        Some(d3d12::D3D12_RESOURCE_STATE_DEPTH_READ) => Some(
+            d3d12::D3D12_RESOURCE_STATE_DEPTH_READ
+                | d3d12::D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE,
+        ),
+        Some(_) => None,
+    }
+}
+
+/// Check if a dependency between shader accesses requires a UAV barrier.
+///
+/// Transitions of attachments are handled by the attachment states of the subpasses,
+/// but writes to storage resources are only ordered by UAV barriers.
+fn requires_uav_barrier(accesses: &Range<image::Access>) -> bool {
+    let writes = image::Access::SHADER_WRITE | image::Access::MEMORY_WRITE;
+    let accesses_any = writes | image::Access::SHADER_READ | image::Access::MEMORY_READ;
+    (accesses.start.intersects(writes) && accesses.end.intersects(accesses_any))
+        || (accesses.start.intersects(accesses_any) && accesses.end.intersects(writes))
+}
+
+/// Check if the reduction mode of a sampler is allowed by the enabled device features.
+fn is_reduction_enabled(reduction: image::ReductionMode, features: hal::Features) -> bool {
+    reduction == image::ReductionMode::WeightedAverage
+        || features.contains(hal::Features::SAMPLER_REDUCTION)
+}
+
+/// Static sampler of the root signature for an immutable sampler.
+///
+/// The border color has to be supported by static samplers, see `map_static_border_color`.
+fn static_sampler(
+    info: &image::SamplerInfo,
+    binding: native::descriptor::Binding,
+) -> descriptor::StaticSampler {
+    descriptor::StaticSampler::new(
+        descriptor::ShaderVisibility::All,
+        binding,
+        conv::map_sampler_filter(info),
+        [
+            conv::map_wrap(info.wrap_mode.0),
+            conv::map_wrap(info.wrap_mode.1),
+            conv::map_wrap(info.wrap_mode.2),
+        ],
+        info.lod_bias.into(),
+        match info.anisotropic {
+            image::Anisotropic::On(max) => max as _,
+            image::Anisotropic::Off => 0,
+        },
+        conv::map_comparison(info.comparison.unwrap_or(pso::Comparison::Always)),
+        conv::map_static_border_color(info).unwrap(),
+        info.lod_range.start.into() .. info.lod_range.end.into(),
+    )
+}
+
+/// Resource description of a buffer shared with other adapters.
+fn cross_adapter_buffer_desc(size: u64) -> d3d12::D3D12_RESOURCE_DESC {
+    d3d12::D3D12_RESOURCE_DESC {
+        Dimension: d3d12::D3D12_RESOURCE_DIMENSION_BUFFER,
+        Alignment: 0,
+        Width: size,
+        Height: 1,
+        DepthOrArraySize: 1,
+        MipLevels: 1,
+        Format: dxgiformat::DXGI_FORMAT_UNKNOWN,
+        SampleDesc: dxgitype::DXGI_SAMPLE_DESC {
+            Count: 1,
+            Quality: 0,
+        },
+        Layout: d3d12::D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
+        Flags: d3d12::D3D12_RESOURCE_FLAG_ALLOW_CROSS_ADAPTER,
+    }
+}
+
+/// Capacity of the shader-visible descriptor heap replacing an exhausted one.
+///
+/// Returns `None` if `requested` descriptors can't fit into a single heap.
+fn next_descriptor_heap_capacity(current: u64, requested: u64, max_capacity: u64) -> Option<u64> {
+    if requested > max_capacity {
+        None
+    } else {
+        Some(current.max(requested).min(max_capacity))
+    }
+}
+
+/// Heap flags and alignment for memory of the given memory group.
+///
+/// Multisampled resources can only be render targets or depth stencil images and
+/// require a 4MB alignment, other heaps use the default 64KB alignment.
+/// Swapchain images are never placed in heaps, so `ALLOW_DISPLAY` isn't required.
+// The first memory heap of each group corresponds to the default heap, which is can never
+// be mapped.
+// Devices supporting heap tier 1 can only created buffers on mem group 1 (ALLOW_ONLY_BUFFERS).
+// Devices supporting heap tier 2 always expose only mem group 0 and don't have any
+// further restrictions.
+fn is_memory_type_mappable(mem_type: usize) -> bool {
+    let mem_group = mem_type / NUM_HEAP_PROPERTIES;
+    mem_type % NUM_HEAP_PROPERTIES != 0
+        && (mem_group == MemoryGroup::Universal as _ || mem_group == MemoryGroup::BufferOnly as _)
+}
+
+fn heap_flags_and_alignment(mem_group: usize) -> (d3d12::D3D12_HEAP_FLAGS, u64) {
+    let msaa_alignment = d3d12::D3D12_DEFAULT_MSAA_RESOURCE_PLACEMENT_ALIGNMENT as u64;
+    let default_alignment = d3d12::D3D12_DEFAULT_RESOURCE_PLACEMENT_ALIGNMENT as u64;
+    match mem_group {
+        g if g == MemoryGroup::Universal as _ => (
+            d3d12::D3D12_HEAP_FLAG_ALLOW_ALL_BUFFERS_AND_TEXTURES,
+            msaa_alignment,
+        ),
+        g if g == MemoryGroup::BufferOnly as _ => {
+            (d3d12::D3D12_HEAP_FLAG_ALLOW_ONLY_BUFFERS, default_alignment)
+        }
+        g if g == MemoryGroup::ImageOnly as _ => (
+            d3d12::D3D12_HEAP_FLAG_ALLOW_ONLY_NON_RT_DS_TEXTURES,
+            default_alignment,
+        ),
+        g if g == MemoryGroup::TargetOnly as _ => {
+            (d3d12::D3D12_HEAP_FLAG_ALLOW_ONLY_RT_DS_TEXTURES, msaa_alignment)
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Check if a pipeline writes depth to an attachment in the read-only layout.
+fn writes_read_only_depth(layout: image::Layout, depth: &pso::DepthTest) -> bool {
+    match *depth {
+        pso::DepthTest::On { write: true, .. } => {
+            layout == image::Layout::DepthStencilReadOnlyOptimal
+        }
+        _ => false,
+    }
+}
+
+/// Encode a debug name as null-terminated UTF-16 string.
+fn encode_name(name: &str) -> Vec<u16> {
+    name.encode_utf16().chain(iter::once(0)).collect()
+}
+
+fn set_object_name(object: *mut d3d12::ID3D12Object, name: &str) {
+    let name = encode_name(name);
+    let hr = unsafe { (*object).SetName(name.as_ptr()) };
+    if !winerror::SUCCEEDED(hr) {
+        warn!("Failed to set object name: {:x}", hr);
+    }
+}
+
+/// Create a blob holding a copy of `data`.
+fn create_blob(data: &[u8]) -> Option<native::Blob> {
+    let mut blob = native::Blob::null();
+    let hr = unsafe { d3dcompiler::D3DCreateBlob(data.len(), blob.mut_void() as *mut *mut _) };
+    if !winerror::SUCCEEDED(hr) {
+        error!("D3DCreateBlob error {:x}", hr);
+        return None;
+    }
+    unsafe {
+        ptr::copy_nonoverlapping(data.as_ptr(), blob.GetBufferPointer() as *mut u8, data.len());
+    }
+    Some(blob)
+}
+
 #[repr(C)]
 struct GraphicsPipelineStateSubobjectStream {
     root_signature: PipelineStateSubobject<*mut d3d12::ID3D12RootSignature>,
@@ -173,6 +704,42 @@ struct GraphicsPipelineStateSubobjectStream {
     flags: PipelineStateSubobject<d3d12::D3D12_PIPELINE_STATE_FLAGS>,
 }
 
+// `D3D12_PIPELINE_STATE_FLAG_DYNAMIC_DEPTH_BIAS`, not exposed by winapi yet.
+const PIPELINE_STATE_FLAG_DYNAMIC_DEPTH_BIAS: d3d12::D3D12_PIPELINE_STATE_FLAGS = 0x4;
+
+// View instancing types, not exposed by winapi yet.
+pub(crate) const MAX_VIEW_INSTANCE_COUNT: u32 = 4;
+const PIPELINE_STATE_SUBOBJECT_TYPE_VIEW_INSTANCING: d3d12::D3D12_PIPELINE_STATE_SUBOBJECT_TYPE = 22;
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct ViewInstanceLocation {
+    ViewportArrayIndex: UINT,
+    RenderTargetArrayIndex: UINT,
+}
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct ViewInstancingDesc {
+    ViewInstanceCount: UINT,
+    pViewInstanceLocations: *const ViewInstanceLocation,
+    Flags: UINT,
+}
+
+#[repr(C)]
+struct ViewInstancingSubobject {
+    // Subobjects are aligned to pointer size inside the stream.
+    _align: [usize; 0],
+    ty: d3d12::D3D12_PIPELINE_STATE_SUBOBJECT_TYPE,
+    desc: ViewInstancingDesc,
+}
+
+#[repr(C)]
+struct ViewInstancingPipelineStateSubobjectStream {
+    base: GraphicsPipelineStateSubobjectStream,
+    view_instancing: ViewInstancingSubobject,
+}
+
 impl GraphicsPipelineStateSubobjectStream {
     fn new(
         pso_desc: &d3d12::D3D12_GRAPHICS_PIPELINE_STATE_DESC,
@@ -253,6 +820,43 @@ impl Device {
         })
     }
 
+    // Override the default values of specialization constants.
+    //
+    // The components of the compute workgroup size may be specialization constants too,
+    // SPIR-V Cross emits them into `numthreads` with the overridden values.
+    fn specialize_spirv(
+        ast: &mut spirv::Ast<hlsl::Target>,
+        specialization: &pso::Specialization,
+    ) -> Result<(), d::ShaderError> {
+        let spec_constants = ast
+            .get_specialization_constants()
+            .map_err(gen_query_error)?;
+
+        //TODO: move this out into `auxil`
+        for spec_constant in spec_constants {
+            if let Some(constant) = specialization
+                .constants
+                .iter()
+                .find(|c| c.id == spec_constant.constant_id)
+            {
+                let range = constant.range.start as usize .. constant.range.end as usize;
+                let data = match specialization.data.get(range) {
+                    Some(data) if data.len() <= 8 => data,
+                    _ => {
+                        return Err(d::ShaderError::CompilationFailed(format!(
+                            "Invalid data range {:?} of specialization constant {}",
+                            constant.range, constant.id
+                        )))
+                    }
+                };
+                ast.set_scalar_constant(spec_constant.id, specialization_value(data))
+                    .map_err(gen_query_error)?;
+            }
+        }
+
+        Ok(())
+    }
+
     fn patch_spirv_resources(
         ast: &mut spirv::Ast<hlsl::Target>,
         layout: Option<&r::PipelineLayout>,
@@ -395,77 +999,158 @@ impl Device {
         })
     }
 
+    // Hash over all inputs of the SPIR-V to bytecode translation of an entry point.
+    fn shader_cache_key(
+        stage: pso::Stage,
+        raw_data: &[u32],
+        source: &pso::EntryPoint<B>,
+        layout: &r::PipelineLayout,
+    ) -> u64 {
+        let mut hasher = r::CacheKeyHasher::default();
+        raw_data.hash(&mut hasher);
+        Self::translation_key(stage, source, layout).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    // Hash over the inputs of the translation of an entry point of a given module.
+    // The pipeline layout defines the register spaces and root constants.
+    fn translation_key(
+        stage: pso::Stage,
+        source: &pso::EntryPoint<B>,
+        layout: &r::PipelineLayout,
+    ) -> u64 {
+        let mut hasher = r::CacheKeyHasher::default();
+        stage.hash(&mut hasher);
+        source.entry.hash(&mut hasher);
+        for constant in source.specialization.constants.iter() {
+            constant.id.hash(&mut hasher);
+            constant.range.hash(&mut hasher);
+        }
+        source.specialization.data.hash(&mut hasher);
+        for constant in &layout.root_constants {
+            constant.stages.bits().hash(&mut hasher);
+            constant.range.hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
     // Extract entry point from shader module on pipeline creation.
     // Returns compiled shader blob and bool to indicate if the shader should be
-    // destroyed after pipeline creation
+    // destroyed after pipeline creation, translated SPIR-V entry points are owned
+    // by their module.
     fn extract_entry_point(
         stage: pso::Stage,
         source: &pso::EntryPoint<B>,
         layout: &r::PipelineLayout,
+        cache: Option<&r::PipelineCache>,
     ) -> Result<(native::Blob, bool), d::ShaderError> {
         match *source.module {
             r::ShaderModule::Compiled(ref shaders) => {
-                // TODO: do we need to check for specialization constants?
                 // Use precompiled shader, ignore specialization or layout.
+                if !source.specialization.constants.is_empty() {
+                    warn!(
+                        "Specialization constants are ignored for precompiled entry point {}",
+                        source.entry
+                    );
+                }
                 shaders
                     .get(source.entry)
                     .map(|src| (*src, false))
                     .ok_or(d::ShaderError::MissingEntryPoint(source.entry.into()))
             }
-            r::ShaderModule::Spirv(ref raw_data) => {
-                let mut ast = Self::parse_spirv(raw_data)?;
-                let spec_constants = ast
-                    .get_specialization_constants()
-                    .map_err(gen_query_error)?;
-
-                //TODO: move this out into `auxil`
-                for spec_constant in spec_constants {
-                    if let Some(constant) = source
-                        .specialization
-                        .constants
-                        .iter()
-                        .find(|c| c.id == spec_constant.constant_id)
-                    {
-                        // Override specialization constant values
-                        let value = source.specialization.data
-                            [constant.range.start as usize .. constant.range.end as usize]
-                            .iter()
-                            .rev()
-                            .fold(0u64, |u, &b| (u << 8) + b as u64);
-                        ast.set_scalar_constant(spec_constant.id, value)
-                            .map_err(gen_query_error)?;
+            r::ShaderModule::Spirv {
+                raw: ref raw_data,
+                ref translations,
+            } => {
+                let cache_key =
+                    cache.map(|_| Self::shader_cache_key(stage, raw_data, source, layout));
+                let store = |blob: native::Blob| {
+                    if let (Some(cache), Some(key)) = (cache, cache_key) {
+                        let bytecode = unsafe {
+                            slice::from_raw_parts(
+                                blob.GetBufferPointer() as *const u8,
+                                blob.GetBufferSize(),
+                            )
+                        };
+                        cache
+                            .shaders
+                            .lock()
+                            .unwrap()
+                            .entry(key)
+                            .or_insert_with(|| bytecode.to_vec());
                     }
+                };
+
+                // Translated entry points are reused by later pipelines, skipping SPIRV-Cross.
+                let translation_key = Self::translation_key(stage, source, layout);
+                if let Some(&blob) = translations.lock().unwrap().get(&translation_key) {
+                    store(blob);
+                    return Ok((blob, false));
                 }
+                let keep = |blob: native::Blob| {
+                    let mut translations = translations.lock().unwrap();
+                    let kept = *translations.entry(translation_key).or_insert(blob);
+                    if kept.as_mut_ptr() != blob.as_mut_ptr() {
+                        // Translated concurrently by another pipeline creation.
+                        unsafe { blob.destroy() };
+                    }
+                    (kept, false)
+                };
 
-                Self::patch_spirv_resources(&mut ast, Some(layout))?;
-                let shader_model = hlsl::ShaderModel::V5_1;
-                let shader_code = Self::translate_spirv(&mut ast, shader_model, layout, stage)?;
-                debug!("SPIRV-Cross generated shader:\n{}", shader_code);
+                if let (Some(cache), Some(key)) = (cache, cache_key) {
+                    if let Some(bytecode) = cache.shaders.lock().unwrap().get(&key) {
+                        if let Some(blob) = create_blob(bytecode) {
+                            return Ok(keep(blob));
+                        }
+                    }
+                }
 
-                let real_name = ast
-                    .get_cleansed_entry_point_name(source.entry, conv::map_stage(stage))
-                    .map_err(gen_query_error)?;
-                // TODO: opt: don't query *all* entry points.
-                let entry_points = ast.get_entry_points().map_err(gen_query_error)?;
-                entry_points
-                    .iter()
-                    .find(|entry_point| entry_point.name == real_name)
-                    .ok_or(d::ShaderError::MissingEntryPoint(source.entry.into()))
-                    .and_then(|entry_point| {
-                        let stage = conv::map_execution_model(entry_point.execution_model);
-                        let shader = compile_shader(
-                            stage,
-                            shader_model,
-                            &entry_point.name,
-                            shader_code.as_bytes(),
-                        )?;
-                        Ok((shader, true))
-                    })
+                let shader = Self::translate_entry_point(stage, raw_data, source, layout)?;
+                store(shader);
+                Ok(keep(shader))
             }
         }
     }
 
+    fn translate_entry_point(
+        stage: pso::Stage,
+        raw_data: &[u32],
+        source: &pso::EntryPoint<B>,
+        layout: &r::PipelineLayout,
+    ) -> Result<native::Blob, d::ShaderError> {
+        let mut ast = Self::parse_spirv(raw_data)?;
+        Self::specialize_spirv(&mut ast, &source.specialization)?;
+        Self::patch_spirv_resources(&mut ast, Some(layout))?;
+        let shader_model = hlsl::ShaderModel::V5_1;
+        let shader_code = Self::translate_spirv(&mut ast, shader_model, layout, stage)?;
+        debug!("SPIRV-Cross generated shader:\n{}", shader_code);
+
+        let real_name = ast
+            .get_cleansed_entry_point_name(source.entry, conv::map_stage(stage))
+            .map_err(gen_query_error)?;
+        // TODO: opt: don't query *all* entry points.
+        let entry_points = ast.get_entry_points().map_err(gen_query_error)?;
+        entry_points
+            .iter()
+            .find(|entry_point| entry_point.name == real_name)
+            .ok_or(d::ShaderError::MissingEntryPoint(source.entry.into()))
+            .and_then(|entry_point| {
+                let stage = conv::map_execution_model(entry_point.execution_model);
+                compile_shader(
+                    stage,
+                    shader_model,
+                    &entry_point.name,
+                    shader_code.as_bytes(),
+                )
+            })
+    }
+
     /// Create a shader module from HLSL with a single entry point
+    ///
+    /// The source is compiled for shader model 5.1 without going through SPIR-V.
+    /// Resources must be bound to the registers the pipeline layout expects: descriptor
+    /// set `n` maps to register space `n`, or `n + 1` if the layout has push constants,
+    /// which are bound to `b<offset>` in space 0. Specialization constants are ignored.
     pub fn create_shader_module_from_source(
         &self,
         stage: pso::Stage,
@@ -479,6 +1164,28 @@ impl Device {
         Ok(r::ShaderModule::Compiled(shader_map))
     }
 
+    /// Create a shader module from precompiled DXBC bytecode with a single entry point
+    ///
+    /// The bytecode is used as is by pipelines using `entry_point` of the module, register
+    /// bindings need to match the pipeline layout as for `create_shader_module_from_source`.
+    pub fn create_shader_module_from_dxbc(
+        &self,
+        entry_point: &str,
+        bytecode: &[u8],
+    ) -> Result<r::ShaderModule, d::ShaderError> {
+        if !is_dxbc(bytecode) {
+            return Err(d::ShaderError::CompilationFailed(
+                "Invalid DXBC bytecode".into(),
+            ));
+        }
+        let blob = create_blob(bytecode).ok_or_else(|| {
+            d::ShaderError::CompilationFailed("Unable to create bytecode blob".into())
+        })?;
+        let mut shader_map = BTreeMap::new();
+        shader_map.insert(entry_point.into(), blob);
+        Ok(r::ShaderModule::Compiled(shader_map))
+    }
+
     pub(crate) fn create_command_signature(
         device: native::Device,
         ty: CommandSignature,
@@ -527,6 +1234,7 @@ impl Device {
             raw: heap,
             handle_size: descriptor_size as _,
             total_handles: capacity as _,
+            allocated_handles: 0,
             start: r::DualHandle {
                 cpu: cpu_handle,
                 gpu: gpu_handle,
@@ -552,6 +1260,7 @@ impl Device {
         let MipSlice = info.range.levels.start as _;
         let FirstArraySlice = info.range.layers.start as _;
         let ArraySize = (info.range.layers.end - info.range.layers.start) as _;
+        let PlaneSlice = conv::map_plane_slice(info.range.aspects);
         assert_eq!(info.range.levels.start + 1, info.range.levels.end);
         assert!(info.range.layers.end <= info.kind.num_layers());
         let is_msaa = info.kind.num_samples() > 1;
@@ -582,7 +1291,7 @@ impl Device {
                 desc.ViewDimension = d3d12::D3D12_RTV_DIMENSION_TEXTURE2D;
                 *unsafe { desc.u.Texture2D_mut() } = d3d12::D3D12_TEX2D_RTV {
                     MipSlice,
-                    PlaneSlice: 0, //TODO
+                    PlaneSlice,
                 }
             }
             image::ViewKind::D2Array if is_msaa => {
@@ -598,7 +1307,7 @@ impl Device {
                     MipSlice,
                     FirstArraySlice,
                     ArraySize,
-                    PlaneSlice: 0, //TODO
+                    PlaneSlice,
                 }
             }
             image::ViewKind::D3 => {
@@ -631,8 +1340,10 @@ impl Device {
 
     fn view_image_as_render_target(
         &self,
-        info: ViewInfo,
+        mut info: ViewInfo,
     ) -> Result<d3d12::D3D12_CPU_DESCRIPTOR_HANDLE, image::ViewError> {
+        // Planar formats are rendered one plane at a time.
+        info.format = conv::map_format_shader_resource(info.format, info.range.aspects);
         let handle = self.rtv_pool.lock().unwrap().alloc_handle();
         Self::view_image_as_render_target_impl(self.raw, handle, info).map(|_| handle)
     }
@@ -641,13 +1352,14 @@ impl Device {
         device: native::Device,
         handle: d3d12::D3D12_CPU_DESCRIPTOR_HANDLE,
         info: ViewInfo,
+        flags: d3d12::D3D12_DSV_FLAGS,
     ) -> Result<(), image::ViewError> {
         #![allow(non_snake_case)]
 
         let mut desc = d3d12::D3D12_DEPTH_STENCIL_VIEW_DESC {
             Format: info.format,
             ViewDimension: 0,
-            Flags: 0,
+            Flags: flags,
             u: unsafe { mem::zeroed() },
         };
 
@@ -716,7 +1428,23 @@ impl Device {
         info: ViewInfo,
     ) -> Result<d3d12::D3D12_CPU_DESCRIPTOR_HANDLE, image::ViewError> {
         let handle = self.dsv_pool.lock().unwrap().alloc_handle();
-        Self::view_image_as_depth_stencil_impl(self.raw, handle, info).map(|_| handle)
+        Self::view_image_as_depth_stencil_impl(self.raw, handle, info, d3d12::D3D12_DSV_FLAG_NONE)
+            .map(|_| handle)
+    }
+
+    // Read-only depth stencil views allow to use the image as shader resource
+    // at the same time, while depth testing without writing.
+    fn view_image_as_read_only_depth_stencil(
+        &self,
+        info: ViewInfo,
+        aspects: Aspects,
+    ) -> Result<d3d12::D3D12_CPU_DESCRIPTOR_HANDLE, image::ViewError> {
+        let mut flags = d3d12::D3D12_DSV_FLAG_READ_ONLY_DEPTH;
+        if aspects.contains(Aspects::STENCIL) {
+            flags |= d3d12::D3D12_DSV_FLAG_READ_ONLY_STENCIL;
+        }
+        let handle = self.dsv_pool.lock().unwrap().alloc_handle();
+        Self::view_image_as_depth_stencil_impl(self.raw, handle, info, flags).map(|_| handle)
     }
 
     pub(crate) fn build_image_as_shader_resource_desc(
@@ -735,6 +1463,7 @@ impl Device {
         let MipLevels = (info.range.levels.end - info.range.levels.start) as _;
         let FirstArraySlice = info.range.layers.start as _;
         let ArraySize = (info.range.layers.end - info.range.layers.start) as _;
+        let PlaneSlice = conv::map_plane_slice(info.range.aspects);
 
         assert!(info.range.layers.end <= info.kind.num_layers());
         let is_msaa = info.kind.num_samples() > 1;
@@ -773,7 +1502,7 @@ impl Device {
                 *unsafe { desc.u.Texture2D_mut() } = d3d12::D3D12_TEX2D_SRV {
                     MostDetailedMip,
                     MipLevels,
-                    PlaneSlice: 0, //TODO
+                    PlaneSlice,
                     ResourceMinLODClamp: 0.0,
                 }
             }
@@ -791,7 +1520,7 @@ impl Device {
                     MipLevels,
                     FirstArraySlice,
                     ArraySize,
-                    PlaneSlice: 0, //TODO
+                    PlaneSlice,
                     ResourceMinLODClamp: 0.0,
                 }
             }
@@ -842,11 +1571,7 @@ impl Device {
         #![allow(non_snake_case)]
 
         // Depth-stencil formats can't be used for SRVs.
-        info.format = match info.format {
-            dxgiformat::DXGI_FORMAT_D16_UNORM => dxgiformat::DXGI_FORMAT_R16_UNORM,
-            dxgiformat::DXGI_FORMAT_D32_FLOAT => dxgiformat::DXGI_FORMAT_R32_FLOAT,
-            format => format,
-        };
+        info.format = conv::map_format_shader_resource(info.format, info.range.aspects);
 
         let desc = Self::build_image_as_shader_resource_desc(&info)?;
         let handle = self.srv_uav_pool.lock().unwrap().alloc_handle();
@@ -862,6 +1587,15 @@ impl Device {
         &self,
         info: ViewInfo,
     ) -> Result<d3d12::D3D12_CPU_DESCRIPTOR_HANDLE, image::ViewError> {
+        let handle = self.srv_uav_pool.lock().unwrap().alloc_handle();
+        Self::view_image_as_storage_impl(self.raw, handle, info).map(|_| handle)
+    }
+
+    pub(crate) fn view_image_as_storage_impl(
+        device: native::Device,
+        handle: d3d12::D3D12_CPU_DESCRIPTOR_HANDLE,
+        info: ViewInfo,
+    ) -> Result<(), image::ViewError> {
         #![allow(non_snake_case)]
         assert_eq!(info.range.levels.start + 1, info.range.levels.end);
 
@@ -927,9 +1661,8 @@ impl Device {
             }
         }
 
-        let handle = self.srv_uav_pool.lock().unwrap().alloc_handle();
         unsafe {
-            self.raw.CreateUnorderedAccessView(
+            device.CreateUnorderedAccessView(
                 info.resource.as_mut_ptr(),
                 ptr::null_mut(),
                 &desc,
@@ -937,7 +1670,47 @@ impl Device {
             );
         }
 
-        Ok(handle)
+        Ok(())
+    }
+
+    // Block until the submissions resolving queries of the pool have reached `value`.
+    unsafe fn wait_for_query_completion(
+        &self,
+        pool: &r::QueryPool,
+        value: u64,
+    ) -> Result<(), d::OomOrDeviceLost> {
+        let fence = pool.completion.fence;
+        if value == 0 {
+            warn!("Waiting for results of queries which have never been submitted");
+            return Ok(());
+        }
+        let completed = fence.GetCompletedValue();
+        // Fences of a removed device report the maximum value.
+        if completed == u64::max_value() {
+            return Err(d::OomOrDeviceLost::DeviceLost(d::DeviceLost));
+        }
+        if completed >= value {
+            return Ok(());
+        }
+
+        let mut events = self.events.lock().unwrap();
+        if events.is_empty() {
+            events.push(native::Event::create(false, false));
+        }
+        let event = events[0];
+        synchapi::ResetEvent(event.0);
+        let hr = fence.set_event_on_completion(event, value);
+        if !winerror::SUCCEEDED(hr) {
+            error!("error on waiting for query results: {:x}", hr);
+            return Err(d::OomOrDeviceLost::DeviceLost(d::DeviceLost));
+        }
+        match synchapi::WaitForSingleObject(event.0, winbase::INFINITE) {
+            winbase::WAIT_OBJECT_0 => Ok(()),
+            status => {
+                error!("Unexpected wait status 0x{:X}", status);
+                Err(d::OomOrDeviceLost::DeviceLost(d::DeviceLost))
+            }
+        }
     }
 
     pub(crate) fn create_raw_fence(&self, signalled: bool) -> native::Fence {
@@ -952,2248 +1725,7483 @@ impl Device {
         });
         handle
     }
-}
 
-impl d::Device<B> for Device {
-    unsafe fn allocate_memory(
+    // Create a committed buffer in one of the default heaps, used for internal
+    // readback and upload buffers of query pools.
+    pub(crate) fn create_committed_buffer(
         &self,
-        mem_type: hal::MemoryTypeId,
+        heap_type: d3d12::D3D12_HEAP_TYPE,
         size: u64,
-    ) -> Result<r::Memory, d::AllocationError> {
-        let mem_type = mem_type.0;
-        let mem_base_id = mem_type % NUM_HEAP_PROPERTIES;
-        let heap_property = &self.heap_properties[mem_base_id];
-
+        state: d3d12::D3D12_RESOURCE_STATES,
+    ) -> native::Resource {
+        let mut resource = native::Resource::null();
         let properties = d3d12::D3D12_HEAP_PROPERTIES {
-            Type: d3d12::D3D12_HEAP_TYPE_CUSTOM,
-            CPUPageProperty: heap_property.page_property,
-            MemoryPoolPreference: heap_property.memory_pool,
+            Type: heap_type,
+            CPUPageProperty: d3d12::D3D12_CPU_PAGE_PROPERTY_UNKNOWN,
+            MemoryPoolPreference: d3d12::D3D12_MEMORY_POOL_UNKNOWN,
             CreationNodeMask: 0,
             VisibleNodeMask: 0,
         };
-
-        // Exposed memory types are grouped according to their capabilities.
-        // See `MemoryGroup` for more details.
-        let mem_group = mem_type / NUM_HEAP_PROPERTIES;
-
-        let desc = d3d12::D3D12_HEAP_DESC {
-            SizeInBytes: size,
-            Properties: properties,
-            Alignment: d3d12::D3D12_DEFAULT_MSAA_RESOURCE_PLACEMENT_ALIGNMENT as _, // TODO: not always..?
-            Flags: match mem_group {
-                0 => d3d12::D3D12_HEAP_FLAG_ALLOW_ALL_BUFFERS_AND_TEXTURES,
-                1 => d3d12::D3D12_HEAP_FLAG_ALLOW_ONLY_BUFFERS,
-                2 => d3d12::D3D12_HEAP_FLAG_ALLOW_ONLY_NON_RT_DS_TEXTURES,
-                3 => d3d12::D3D12_HEAP_FLAG_ALLOW_ONLY_RT_DS_TEXTURES,
-                _ => unreachable!(),
+        let desc = d3d12::D3D12_RESOURCE_DESC {
+            Dimension: d3d12::D3D12_RESOURCE_DIMENSION_BUFFER,
+            Alignment: 0,
+            Width: size,
+            Height: 1,
+            DepthOrArraySize: 1,
+            MipLevels: 1,
+            Format: dxgiformat::DXGI_FORMAT_UNKNOWN,
+            SampleDesc: dxgitype::DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
             },
+            Layout: d3d12::D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
+            Flags: d3d12::D3D12_RESOURCE_FLAG_NONE,
         };
+        assert_eq!(winerror::S_OK, unsafe {
+            self.raw.CreateCommittedResource(
+                &properties,
+                d3d12::D3D12_HEAP_FLAG_NONE,
+                &desc,
+                state,
+                ptr::null(),
+                &d3d12::ID3D12Resource::uuidof(),
+                resource.mut_void(),
+            )
+        });
+        resource
+    }
 
-        let mut heap = native::Heap::null();
-        let hr = self
-            .raw
-            .clone()
-            .CreateHeap(&desc, &d3d12::ID3D12Heap::uuidof(), heap.mut_void());
-        if hr == winerror::E_OUTOFMEMORY {
-            return Err(d::OutOfMemory::OutOfDeviceMemory.into());
-        }
-        assert_eq!(winerror::S_OK, hr);
-
-        // The first memory heap of each group corresponds to the default heap, which is can never
-        // be mapped.
-        // Devices supporting heap tier 1 can only created buffers on mem group 1 (ALLOW_ONLY_BUFFERS).
-        // Devices supporting heap tier 2 always expose only mem group 0 and don't have any further restrictions.
-        let is_mapable = mem_base_id != 0
-            && (mem_group == MemoryGroup::Universal as _
-                || mem_group == MemoryGroup::BufferOnly as _);
-
-        // Create a buffer resource covering the whole memory slice to be able to map the whole memory.
-        let resource = if is_mapable {
-            let mut resource = native::Resource::null();
-            let desc = d3d12::D3D12_RESOURCE_DESC {
-                Dimension: d3d12::D3D12_RESOURCE_DIMENSION_BUFFER,
-                Alignment: 0,
-                Width: size,
-                Height: 1,
-                DepthOrArraySize: 1,
-                MipLevels: 1,
-                Format: dxgiformat::DXGI_FORMAT_UNKNOWN,
-                SampleDesc: dxgitype::DXGI_SAMPLE_DESC {
-                    Count: 1,
-                    Quality: 0,
-                },
-                Layout: d3d12::D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
-                Flags: d3d12::D3D12_RESOURCE_FLAG_NONE,
-            };
-
-            assert_eq!(
-                winerror::S_OK,
-                self.raw.clone().CreatePlacedResource(
-                    heap.as_mut_ptr(),
-                    0,
-                    &desc,
-                    d3d12::D3D12_RESOURCE_STATE_COMMON,
-                    ptr::null(),
-                    &d3d12::ID3D12Resource::uuidof(),
-                    resource.mut_void(),
-                )
-            );
+    /// Allocate memory dedicated to a single buffer or image.
+    ///
+    /// Binding a resource at offset 0 creates a committed resource with its own implicit heap
+    /// instead of placing it into a shared heap. Committed resources may be faster for large
+    /// render targets and are made resident or evicted individually, but can't alias other
+    /// resources and only a single resource can be bound to the memory.
+    /// Host visible memory types are mapped through a heap and fall back to `allocate_memory`.
+    pub unsafe fn allocate_dedicated_memory(
+        &self,
+        mem_type: hal::MemoryTypeId,
+        size: u64,
+    ) -> Result<r::Memory, d::AllocationError> {
+        use hal::Device as _;
 
-            Some(resource)
-        } else {
-            None
-        };
+        if is_memory_type_mappable(mem_type.0) {
+            warn!("Dedicated allocations of host visible memory are placed into a heap");
+            return self.allocate_memory(mem_type, size);
+        }
 
         Ok(r::Memory {
-            heap,
-            type_id: mem_type,
+            heap: native::Heap::null(),
+            type_id: mem_type.0,
             size,
-            resource,
+            resource: None,
+            placements: Arc::new(Mutex::new(Vec::new())),
+            dedicated: Some(self.memory_heap_properties(mem_type.0)),
         })
     }
 
-    unsafe fn create_command_pool(
-        &self,
-        family: QueueFamilyId,
-        create_flags: CommandPoolCreateFlags,
-    ) -> Result<RawCommandPool, d::OutOfMemory> {
-        let list_type = QUEUE_FAMILIES[family.0].native_type();
-
-        let allocator = if create_flags.contains(CommandPoolCreateFlags::RESET_INDIVIDUAL) {
-            // Allocators are created per individual ID3D12GraphicsCommandList
-            CommandPoolAllocator::Individual(Vec::new())
-        } else {
-            let (command_allocator, hr) = self.raw.create_command_allocator(list_type);
+    fn memory_heap_properties(&self, mem_type: usize) -> d3d12::D3D12_HEAP_PROPERTIES {
+        let heap_property = &self.heap_properties[mem_type % NUM_HEAP_PROPERTIES];
+        d3d12::D3D12_HEAP_PROPERTIES {
+            Type: d3d12::D3D12_HEAP_TYPE_CUSTOM,
+            CPUPageProperty: heap_property.page_property,
+            MemoryPoolPreference: heap_property.memory_pool,
+            CreationNodeMask: 0,
+            VisibleNodeMask: 0,
+        }
+    }
 
-            // TODO: error handling
-            if !winerror::SUCCEEDED(hr) {
-                error!("error on command allocator creation: {:x}", hr);
+    // Create the resource of a buffer or image bound to `memory` at `offset`.
+    //
+    // Dedicated memory gets a committed resource, otherwise the resource is placed into
+    // the memory heap. Both use the same description and therefore resource flags.
+    unsafe fn create_bound_resource(
+        &self,
+        memory: &r::Memory,
+        offset: u64,
+        desc: &d3d12::D3D12_RESOURCE_DESC,
+        state: d3d12::D3D12_RESOURCE_STATES,
+    ) -> Result<native::Resource, d::BindError> {
+        let mut resource = native::Resource::null();
+        let hr = match memory.dedicated {
+            Some(ref properties) => {
+                if offset != 0 {
+                    error!("Dedicated memory can only be bound at offset 0, given {}", offset);
+                    return Err(d::BindError::OutOfBounds);
+                }
+                if !memory.placements.lock().unwrap().is_empty() {
+                    error!("Dedicated memory is already bound to a resource");
+                    return Err(d::BindError::WrongMemory);
+                }
+                self.raw.clone().CreateCommittedResource(
+                    properties,
+                    d3d12::D3D12_HEAP_FLAG_NONE,
+                    desc,
+                    state,
+                    ptr::null(),
+                    &d3d12::ID3D12Resource::uuidof(),
+                    resource.mut_void(),
+                )
             }
-
-            CommandPoolAllocator::Shared(command_allocator)
+            None => self.raw.clone().CreatePlacedResource(
+                memory.heap.as_mut_ptr(),
+                offset,
+                desc,
+                state,
+                ptr::null(),
+                &d3d12::ID3D12Resource::uuidof(),
+                resource.mut_void(),
+            ),
         };
-
-        Ok(RawCommandPool {
-            allocator,
-            device: self.raw,
-            list_type,
-            shared: self.shared.clone(),
-            create_flags,
-        })
-    }
-
-    unsafe fn destroy_command_pool(&self, pool: RawCommandPool) {
-        pool.destroy();
+        assert_eq!(winerror::S_OK, hr);
+        Ok(resource)
     }
 
-    unsafe fn create_render_pass<'a, IA, IS, ID>(
-        &self,
-        attachments: IA,
-        subpasses: IS,
-        dependencies: ID,
-    ) -> Result<r::RenderPass, d::OutOfMemory>
-    where
-        IA: IntoIterator,
-        IA::Item: Borrow<pass::Attachment>,
-        IS: IntoIterator,
-        IS::Item: Borrow<pass::SubpassDesc<'a>>,
-        ID: IntoIterator,
-        ID::Item: Borrow<pass::SubpassDependency>,
-    {
-        #[derive(Copy, Clone, Debug, PartialEq)]
-        pub enum SubState {
-            New(d3d12::D3D12_RESOURCE_STATES),
-            // Color attachment which will be resolved at the end of the subpass
-            Resolve(d3d12::D3D12_RESOURCE_STATES),
-            Preserve,
-            Undefined,
+    /// Make the memory evicted by `evict` resident again, blocking until it's usable.
+    ///
+    /// Together with `PhysicalDevice::memory_budget` this allows applications to implement
+    /// their own residency policy instead of relying on the OS paging memory.
+    pub unsafe fn make_resident(&self, memories: &[&r::Memory]) -> Result<(), d::OutOfMemory> {
+        let pageables = memories
+            .iter()
+            .filter_map(|memory| memory.pageable())
+            .collect::<Vec<_>>();
+        if pageables.is_empty() {
+            return Ok(());
         }
-        struct AttachmentInfo {
-            sub_states: Vec<SubState>,
-            target_state: d3d12::D3D12_RESOURCE_STATES,
-            last_state: d3d12::D3D12_RESOURCE_STATES,
-            barrier_start_index: usize,
+        let hr = self
+            .raw
+            .MakeResident(pageables.len() as _, pageables.as_ptr());
+        if hr == winerror::E_OUTOFMEMORY {
+            return Err(d::OutOfMemory::OutOfDeviceMemory);
         }
+        assert_eq!(winerror::S_OK, hr);
+        Ok(())
+    }
 
-        let attachments = attachments
-            .into_iter()
-            .map(|attachment| attachment.borrow().clone())
-            .collect::<Vec<_>>();
-        let subpasses = subpasses.into_iter().collect::<Vec<_>>();
-        let dependencies = dependencies.into_iter().collect::<Vec<_>>();
-        let mut att_infos = attachments
+    /// Evict memory from video memory.
+    ///
+    /// The memory must not be in use by the GPU, and must not be accessed until made
+    /// resident again by `make_resident`.
+    pub unsafe fn evict(&self, memories: &[&r::Memory]) {
+        let pageables = memories
             .iter()
-            .map(|att| AttachmentInfo {
-                sub_states: vec![SubState::Undefined; subpasses.len()],
-                target_state: if att.format.map_or(false, |f| f.is_depth()) {
-                    d3d12::D3D12_RESOURCE_STATE_DEPTH_WRITE //TODO?
-                } else {
-                    d3d12::D3D12_RESOURCE_STATE_RENDER_TARGET
-                },
-                last_state: conv::map_image_resource_state(
-                    image::Access::empty(),
-                    att.layouts.start,
-                ),
-                barrier_start_index: 0,
-            })
+            .filter_map(|memory| memory.pageable())
             .collect::<Vec<_>>();
-
-        // Fill out subpass known layouts
-        for (sid, sub) in subpasses.iter().enumerate() {
-            let sub = sub.borrow();
-            for (i, &(id, _layout)) in sub.colors.iter().enumerate() {
-                let dst_state = att_infos[id].target_state;
-                let state = match sub.resolves.get(i) {
-                    Some(_) => SubState::Resolve(dst_state),
-                    None => SubState::New(dst_state),
-                };
-                let old = mem::replace(&mut att_infos[id].sub_states[sid], state);
-                debug_assert_eq!(SubState::Undefined, old);
-            }
-            for &(id, _layout) in sub.depth_stencil {
-                let state = SubState::New(att_infos[id].target_state);
-                let old = mem::replace(&mut att_infos[id].sub_states[sid], state);
-                debug_assert_eq!(SubState::Undefined, old);
-            }
-            for &(id, _layout) in sub.inputs {
-                let state = SubState::New(d3d12::D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE);
-                let old = mem::replace(&mut att_infos[id].sub_states[sid], state);
-                debug_assert_eq!(SubState::Undefined, old);
-            }
-            for &(id, _layout) in sub.resolves {
-                let state = SubState::New(d3d12::D3D12_RESOURCE_STATE_RESOLVE_DEST);
-                let old = mem::replace(&mut att_infos[id].sub_states[sid], state);
-                debug_assert_eq!(SubState::Undefined, old);
-            }
-            for &id in sub.preserves {
-                let old = mem::replace(&mut att_infos[id].sub_states[sid], SubState::Preserve);
-                debug_assert_eq!(SubState::Undefined, old);
-            }
-        }
-
-        let mut deps_left = vec![0u16; subpasses.len()];
-        for dep in &dependencies {
-            let dep = dep.borrow();
-            //Note: self-dependencies are ignored
-            if dep.passes.start != dep.passes.end && dep.passes.start != pass::SubpassRef::External
-            {
-                if let pass::SubpassRef::Pass(sid) = dep.passes.end {
-                    deps_left[sid] += 1;
-                }
-            }
+        if pageables.is_empty() {
+            return;
         }
+        assert_eq!(
+            winerror::S_OK,
+            self.raw.Evict(pageables.len() as _, pageables.as_ptr())
+        );
+    }
 
-        let mut rp = r::RenderPass {
-            attachments: attachments.clone(),
-            subpasses: Vec::new(),
-            post_barriers: Vec::new(),
-        };
-
-        while let Some(sid) = deps_left.iter().position(|count| *count == 0) {
-            deps_left[sid] = !0; // mark as done
-            for dep in &dependencies {
-                let dep = dep.borrow();
-                if dep.passes.start != dep.passes.end
-                    && dep.passes.start == pass::SubpassRef::Pass(sid)
-                {
-                    if let pass::SubpassRef::Pass(other) = dep.passes.end {
-                        deps_left[other] -= 1;
-                    }
-                }
-            }
+    // Finish binding an image to its created resource, creating the views for clearing.
+    unsafe fn bind_image_resource(
+        &self,
+        image: &mut r::Image,
+        resource: native::Resource,
+        place: r::Place,
+        region: Option<r::MemoryRegion>,
+    ) {
+        use self::image::Usage;
 
-            // Subpass barriers
-            let mut pre_barriers = Vec::new();
-            let mut post_barriers = Vec::new();
-            for (att_id, ai) in att_infos.iter_mut().enumerate() {
-                // Barrier from previous subpass to current or following subpasses.
-                match ai.sub_states[sid] {
-                    SubState::Preserve => {
-                        ai.barrier_start_index = rp.subpasses.len() + 1;
-                    }
-                    SubState::New(state) if state != ai.last_state => {
-                        let barrier = r::BarrierDesc::new(att_id, ai.last_state .. state);
-                        match rp.subpasses.get_mut(ai.barrier_start_index) {
-                            Some(past_subpass) => {
-                                let split = barrier.split();
-                                past_subpass.pre_barriers.push(split.start);
-                                pre_barriers.push(split.end);
+        let image_unbound = *image.expect_unbound();
+        let num_layers = image_unbound.kind.num_layers();
+        let info = ViewInfo {
+            resource,
+            kind: image_unbound.kind,
+            caps: image::ViewCapabilities::empty(),
+            view_kind: match image_unbound.kind {
+                image::Kind::D1(..) => image::ViewKind::D1Array,
+                image::Kind::D2(..) => image::ViewKind::D2Array,
+                image::Kind::D3(..) => image::ViewKind::D3,
+            },
+            format: image_unbound.desc.Format,
+            component_mapping: IDENTITY_MAPPING,
+            range: image::SubresourceRange {
+                aspects: Aspects::empty(),
+                levels: 0 .. 0,
+                layers: 0 .. 0,
+            },
+        };
+
+        //TODO: the clear_Xv is incomplete. We should support clearing images created without XXX_ATTACHMENT usage.
+        // for this, we need to check the format and force the `RENDER_TARGET` flag behind the user's back
+        // if the format supports being rendered into, allowing us to create clear_Xv
+        let format_properties = self.format_properties.get(image_unbound.format as usize);
+        let props = match image_unbound.tiling {
+            image::Tiling::Optimal => format_properties.optimal_tiling,
+            image::Tiling::Linear => format_properties.linear_tiling,
+        };
+        let can_clear_color = image_unbound
+            .usage
+            .intersects(Usage::TRANSFER_DST | Usage::COLOR_ATTACHMENT)
+            && props.contains(format::ImageFeature::COLOR_ATTACHMENT);
+        let can_clear_depth = image_unbound
+            .usage
+            .intersects(Usage::TRANSFER_DST | Usage::DEPTH_STENCIL_ATTACHMENT)
+            && props.contains(format::ImageFeature::DEPTH_STENCIL_ATTACHMENT);
+        let aspects = image_unbound.format.surface_desc().aspects;
+
+        *image = r::Image::Bound(r::ImageBound {
+            resource: resource,
+            place,
+            region,
+            surface_type: image_unbound.format.base_format().0,
+            format: image_unbound.format,
+            kind: image_unbound.kind,
+            usage: image_unbound.usage,
+            default_view_format: image_unbound.view_format,
+            view_caps: image_unbound.view_caps,
+            descriptor: image_unbound.desc,
+            bytes_per_block: image_unbound.bytes_per_block,
+            block_dim: image_unbound.block_dim,
+            clear_cv: if aspects.contains(Aspects::COLOR) && can_clear_color {
+                let format = image_unbound.view_format.unwrap();
+                (0 .. num_layers)
+                    .map(|layer| {
+                        self.view_image_as_render_target(ViewInfo {
+                            format,
+                            range: image::SubresourceRange {
+                                aspects: Aspects::COLOR,
+                                levels: 0 .. 1, //TODO?
+                                layers: layer .. layer + 1,
+                            },
+                            ..info.clone()
+                        })
+                        .unwrap()
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            },
+            clear_dv: if aspects.contains(Aspects::DEPTH) && can_clear_depth {
+                let format = image_unbound.dsv_format.unwrap();
+                (0 .. num_layers)
+                    .map(|layer| {
+                        self.view_image_as_depth_stencil(ViewInfo {
+                            format,
+                            range: image::SubresourceRange {
+                                aspects: Aspects::DEPTH,
+                                levels: 0 .. 1, //TODO?
+                                layers: layer .. layer + 1,
+                            },
+                            ..info.clone()
+                        })
+                        .unwrap()
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            },
+            clear_sv: if aspects.contains(Aspects::STENCIL) && can_clear_depth {
+                let format = image_unbound.dsv_format.unwrap();
+                (0 .. num_layers)
+                    .map(|layer| {
+                        self.view_image_as_depth_stencil(ViewInfo {
+                            format,
+                            range: image::SubresourceRange {
+                                aspects: Aspects::STENCIL,
+                                levels: 0 .. 1, //TODO?
+                                layers: layer .. layer + 1,
+                            },
+                            ..info.clone()
+                        })
+                        .unwrap()
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            },
+            requirements: image_unbound.requirements,
+            initial_state: INITIAL_IMAGE_STATE,
+        });
+    }
+
+    /// Create a sparse 2D image backed by a reserved resource.
+    ///
+    /// The image has no memory of its own, tiles of 64KB are bound to memory with
+    /// `CommandQueue::bind_sparse_image`. Requires `Features::SPARSE_BINDING`, which is
+    /// supported with `D3D12_TILED_RESOURCES_TIER_1`. Accessing unbound tiles is undefined on
+    /// tier 1, tier 2 and above read zero and discard writes.
+    pub unsafe fn create_sparse_image(
+        &self,
+        kind: image::Kind,
+        mip_levels: image::Level,
+        format: format::Format,
+        usage: image::Usage,
+        view_caps: image::ViewCapabilities,
+    ) -> Result<r::Image, image::CreationError> {
+        use hal::Device as _;
+
+        if !self.features.contains(hal::Features::SPARSE_BINDING) {
+            error!("Sparse images require the `SPARSE_BINDING` feature");
+            return Err(image::CreationError::Kind);
+        }
+        match kind {
+            image::Kind::D2(_, _, _, 1) => {}
+            _ => {
+                error!("Only single sampled 2D sparse images are supported, given {:?}", kind);
+                return Err(image::CreationError::Kind);
+            }
+        }
+        let mut image = self.create_image(
+            kind,
+            mip_levels,
+            format,
+            image::Tiling::Optimal,
+            usage,
+            view_caps,
+        )?;
+        let desc = d3d12::D3D12_RESOURCE_DESC {
+            Alignment: 0,
+            Layout: d3d12::D3D12_TEXTURE_LAYOUT_64KB_UNDEFINED_SWIZZLE,
+            ..image.expect_unbound().desc
+        };
+
+        let mut resource = native::Resource::null();
+        let hr = self.raw.clone().CreateReservedResource(
+            &desc,
+            INITIAL_IMAGE_STATE,
+            ptr::null(),
+            &d3d12::ID3D12Resource::uuidof(),
+            resource.mut_void(),
+        );
+        if hr == winerror::E_OUTOFMEMORY {
+            return Err(d::OutOfMemory::OutOfDeviceMemory.into());
+        }
+        assert_eq!(winerror::S_OK, hr);
+
+        if let r::Image::Unbound(ref mut unbound) = image {
+            unbound.desc = desc;
+        }
+        self.bind_image_resource(&mut image, resource, r::Place::Reserved, None);
+        Ok(image)
+    }
+
+    /// Create a buffer which can be shared with other adapters.
+    ///
+    /// Cross-adapter buffers are supported by all devices, textures additionally
+    /// require `PhysicalDevice::supports_cross_adapter_row_major_textures`.
+    /// The buffer is created in its own shared heap and can't be bound to memory.
+    pub fn create_cross_adapter_buffer(&self, size: u64) -> Result<r::Buffer, d::OutOfMemory> {
+        let mut resource = native::Resource::null();
+        let properties = d3d12::D3D12_HEAP_PROPERTIES {
+            Type: d3d12::D3D12_HEAP_TYPE_DEFAULT,
+            CPUPageProperty: d3d12::D3D12_CPU_PAGE_PROPERTY_UNKNOWN,
+            MemoryPoolPreference: d3d12::D3D12_MEMORY_POOL_UNKNOWN,
+            CreationNodeMask: 0,
+            VisibleNodeMask: 0,
+        };
+        let desc = cross_adapter_buffer_desc(size);
+        let hr = unsafe {
+            self.raw.CreateCommittedResource(
+                &properties,
+                d3d12::D3D12_HEAP_FLAG_SHARED | d3d12::D3D12_HEAP_FLAG_SHARED_CROSS_ADAPTER,
+                &desc,
+                d3d12::D3D12_RESOURCE_STATE_COMMON,
+                ptr::null(),
+                &d3d12::ID3D12Resource::uuidof(),
+                resource.mut_void(),
+            )
+        };
+        if hr == winerror::E_OUTOFMEMORY {
+            return Err(d::OutOfMemory::OutOfDeviceMemory);
+        }
+        assert_eq!(winerror::S_OK, hr);
+
+        Ok(r::Buffer::Bound(r::BufferBound {
+            resource,
+            requirements: memory::Requirements {
+                size,
+                alignment: d3d12::D3D12_DEFAULT_RESOURCE_PLACEMENT_ALIGNMENT as _,
+                type_mask: 0,
+            },
+            usage: buffer::Usage::TRANSFER_SRC | buffer::Usage::TRANSFER_DST,
+            clear_uav: None,
+            region: None,
+        }))
+    }
+
+    // Allocate a slice of `count` descriptors of a shader-visible heap for a descriptor pool.
+    //
+    // If the heap is full, a new heap replaces it for future allocations. Descriptor sets
+    // reference the heap they were allocated from, so existing sets stay valid and the
+    // new heap is bound once sets allocated from it are bound. The replaced heap is
+    // destroyed once the last pool with a slice of it is dropped.
+    fn allocate_descriptor_heap_slice(
+        &self,
+        shared: &Mutex<r::SharedDescriptorHeap>,
+        heap_type: descriptor::HeapType,
+        count: u64,
+        max_capacity: u64,
+    ) -> Result<r::DescriptorHeapSlice, d::OutOfMemory> {
+        let mut shared = shared.lock().unwrap();
+        let shared = &mut *shared;
+        let heap = &mut shared.heap;
+
+        let range = match count {
+            0 => 0 .. 0,
+            _ => match heap.allocate_range(count) {
+                Some(range) => range,
+                None => {
+                    let capacity =
+                        match next_descriptor_heap_capacity(heap.total_handles, count, max_capacity) {
+                            Some(capacity) => capacity,
+                            None => {
+                                error!(
+                                    "Descriptor pool requires {} descriptors, exceeding the maximum of {} in a shader-visible heap",
+                                    count, max_capacity
+                                );
+                                return Err(d::OutOfMemory::OutOfDeviceMemory);
                             }
-                            None => pre_barriers.push(barrier),
+                        };
+                    info!(
+                        "Descriptor heap exhausted, creating a new heap with {} descriptors",
+                        capacity
+                    );
+                    let new_heap = Self::create_descriptor_heap_impl(
+                        self.raw,
+                        heap_type,
+                        true,
+                        capacity as _,
+                    );
+                    *shared = r::SharedDescriptorHeap::new(new_heap);
+                    shared.heap.allocate_range(count).unwrap()
+                }
+            },
+        };
+
+        let heap = &shared.heap;
+        Ok(r::DescriptorHeapSlice {
+            heap: heap.raw.clone(),
+            owner: Arc::clone(shared.owner.as_ref().unwrap()),
+            handle_size: heap.handle_size as _,
+            range_allocator: RangeAllocator::new(range),
+            start: heap.start,
+        })
+    }
+
+    /// Feature level the device was created with.
+    pub fn feature_level(&self) -> d3dcommon::D3D_FEATURE_LEVEL {
+        self.private_caps.feature_level
+    }
+
+    /// Create a persistently mapped upload ring of `size` bytes.
+    pub fn create_upload_ring(&self, size: u64) -> r::UploadRing {
+        let resource = self.create_committed_buffer(
+            d3d12::D3D12_HEAP_TYPE_UPLOAD,
+            size,
+            d3d12::D3D12_RESOURCE_STATE_GENERIC_READ,
+        );
+        let mut mapped = ptr::null_mut();
+        assert_eq!(winerror::S_OK, unsafe {
+            resource.Map(0, &d3d12::D3D12_RANGE { Begin: 0, End: 0 }, &mut mapped)
+        });
+
+        r::UploadRing {
+            buffer: r::Buffer::Bound(r::BufferBound {
+                resource,
+                requirements: memory::Requirements {
+                    size,
+                    alignment: d3d12::D3D12_DEFAULT_RESOURCE_PLACEMENT_ALIGNMENT as _,
+                    type_mask: 0,
+                },
+                usage: buffer::Usage::TRANSFER_SRC,
+                clear_uav: None,
+                region: None,
+            }),
+            mapped: mapped as *mut u8,
+            fence: self.create_raw_fence(false),
+            fence_value: 0,
+            allocator: r::RingAllocator::new(size),
+        }
+    }
+
+    /// Destroy an upload ring, waiting for all submissions using it.
+    pub unsafe fn destroy_upload_ring(&self, ring: r::UploadRing) {
+        if let Some(fence_value) = ring.allocator.last_in_flight() {
+            ring.wait(fence_value);
+        }
+        let resource = ring.buffer.expect_bound().resource;
+        resource.Unmap(0, ptr::null());
+        resource.destroy();
+        ring.fence.destroy();
+    }
+
+    /// Set the debug name of a buffer, reported by debug layers and tools like PIX.
+    ///
+    /// The buffer needs to be bound to memory.
+    pub fn set_buffer_name(&self, buffer: &r::Buffer, name: &str) {
+        match *buffer {
+            r::Buffer::Bound(ref bound) => set_object_name(bound.resource.as_mut_ptr() as _, name),
+            r::Buffer::Unbound(_) => warn!("Unable to name unbound buffer '{}'", name),
+        }
+    }
+
+    /// Set the debug name of an image, reported by debug layers and tools like PIX.
+    ///
+    /// The image needs to be bound to memory.
+    pub fn set_image_name(&self, image: &r::Image, name: &str) {
+        match *image {
+            r::Image::Bound(ref bound) => set_object_name(bound.resource.as_mut_ptr() as _, name),
+            r::Image::Unbound(_) => warn!("Unable to name unbound image '{}'", name),
+        }
+    }
+
+    /// Set the debug name of the heap backing a memory allocation.
+    ///
+    /// Dedicated memory has no heap of its own, name the bound resource instead.
+    pub fn set_memory_name(&self, memory: &r::Memory, name: &str) {
+        if memory.heap.is_null() {
+            warn!("Unable to name dedicated memory '{}'", name);
+            return;
+        }
+        set_object_name(memory.heap.as_mut_ptr() as _, name);
+    }
+
+    /// Set the debug name of the root signature of a pipeline layout.
+    pub fn set_pipeline_layout_name(&self, layout: &r::PipelineLayout, name: &str) {
+        set_object_name(layout.raw.as_mut_ptr() as _, name);
+    }
+
+    /// Set the debug name of a graphics pipeline.
+    pub fn set_graphics_pipeline_name(&self, pipeline: &r::GraphicsPipeline, name: &str) {
+        set_object_name(pipeline.raw.as_mut_ptr() as _, name);
+    }
+
+    /// Set the debug name of a compute pipeline.
+    pub fn set_compute_pipeline_name(&self, pipeline: &r::ComputePipeline, name: &str) {
+        set_object_name(pipeline.raw.as_mut_ptr() as _, name);
+    }
+
+    /// Create a frame pacer allowing up to `max_frames_in_flight` frames executing
+    /// on the GPU at the same time.
+    pub fn create_frame_pacer(&self, max_frames_in_flight: usize) -> r::FramePacer {
+        r::FramePacer {
+            fence: self.create_raw_fence(false),
+            fence_value: 0,
+            frames: r::FrameQueue::new(max_frames_in_flight),
+        }
+    }
+
+    /// Destroy a frame pacer, waiting for all frames in flight.
+    pub unsafe fn destroy_frame_pacer(&self, pacer: r::FramePacer) {
+        if let Some(fence_value) = pacer.frames.last() {
+            pacer.wait(fence_value);
+        }
+        pacer.fence.destroy();
+    }
+
+    /// Create a multisampled color target for a swapchain.
+    ///
+    /// Flip model swapchains can't be multisampled. Instead, render into the returned
+    /// image, which gets resolved into the presented back buffer on `present`.
+    /// The image has to be in `Layout::Present` when presenting, same as the back buffers.
+    ///
+    /// The image is owned by the caller and has to be destroyed with `destroy_image`.
+    /// The swapchain holds its own reference for the resolve, which it releases when
+    /// the swapchain is recreated or destroyed, or a new target is created. The image
+    /// stays valid afterwards but isn't resolved anymore.
+    pub unsafe fn create_swapchain_msaa_target(
+        &self,
+        swapchain: &mut w::Swapchain,
+        samples: image::NumSamples,
+    ) -> Result<r::Image, image::CreationError> {
+        use hal::Device as _;
+
+        if let Some(msaa) = swapchain.msaa.take() {
+            msaa.destroy();
+        }
+
+        let format_id = swapchain.format as usize;
+        if self.format_properties.get_sample_count_mask(format_id) & samples == 0
+            || !self.format_properties.supports_resolve(format_id)
+        {
+            return Err(image::CreationError::Samples(samples));
+        }
+
+        let kind = image::Kind::D2(swapchain.extent.width, swapchain.extent.height, 1, samples);
+        let mut image = self.create_image(
+            kind,
+            1,
+            swapchain.format,
+            image::Tiling::Optimal,
+            image::Usage::COLOR_ATTACHMENT,
+            image::ViewCapabilities::empty(),
+        )?;
+
+        let requirements = self.get_image_requirements(&image);
+        let memory_type = self
+            .memory_types
+            .iter()
+            .enumerate()
+            .position(|(id, ty)| {
+                requirements.type_mask & (1 << id) != 0
+                    && ty.properties.contains(memory::Properties::DEVICE_LOCAL)
+            })
+            .map(hal::MemoryTypeId);
+        // A committed resource owns its memory, which is released together with the
+        // last reference to the image.
+        let memory = match memory_type {
+            Some(memory_type) => self.allocate_dedicated_memory(memory_type, requirements.size),
+            None => Err(d::AllocationError::OutOfMemory(
+                d::OutOfMemory::OutOfDeviceMemory,
+            )),
+        };
+        let memory = match memory {
+            Ok(memory) => memory,
+            Err(err) => {
+                error!("Unable to allocate swapchain MSAA target memory: {:?}", err);
+                self.destroy_image(image);
+                return Err(image::CreationError::OutOfMemory(
+                    d::OutOfMemory::OutOfDeviceMemory,
+                ));
+            }
+        };
+        if let Err(err) = self.bind_image_memory(&memory, 0, &mut image) {
+            error!("Unable to bind swapchain MSAA target memory: {:?}", err);
+            self.destroy_image(image);
+            self.free_memory(memory);
+            return Err(image::CreationError::OutOfMemory(
+                d::OutOfMemory::OutOfDeviceMemory,
+            ));
+        }
+
+        let list_type = native::command_list::CmdListType::Direct;
+        let allocators = swapchain
+            .resources
+            .iter()
+            .map(|_| {
+                let (allocator, hr) = self.raw.create_command_allocator(list_type);
+                assert_eq!(winerror::S_OK, hr);
+                allocator
+            })
+            .collect::<Vec<_>>();
+        let (list, hr) = self.raw.create_graphics_command_list(
+            list_type,
+            allocators[0],
+            native::PipelineState::null(),
+            0,
+        );
+        assert_eq!(winerror::S_OK, hr);
+        // Command lists are created in the recording state.
+        list.close();
+
+        let resource = image.expect_bound().resource;
+        resource.AddRef();
+        swapchain.msaa = Some(w::MsaaTarget {
+            resource,
+            fence_values: Mutex::new(vec![0; allocators.len()]),
+            allocators,
+            list,
+            fence: self.create_raw_fence(false),
+        });
+
+        Ok(image)
+    }
+
+    // Get the back buffer images of a swapchain and create their render target views.
+    unsafe fn create_swapchain_images(
+        &self,
+        swapchain: native::WeakPtr<dxgi1_4::IDXGISwapChain3>,
+        rtv_heap: &r::DescriptorHeap,
+        config: &hal::SwapchainConfig,
+        format: dxgiformat::DXGI_FORMAT,
+    ) -> (Vec<native::Resource>, Vec<r::Image>) {
+        // sRGB formats use a sRGB view over the UNORM back buffers, see `swapchain_buffer_format`.
+        let view_format = conv::map_format(config.format).unwrap();
+        let rtv_desc = d3d12::D3D12_RENDER_TARGET_VIEW_DESC {
+            Format: view_format,
+            ViewDimension: d3d12::D3D12_RTV_DIMENSION_TEXTURE2D,
+            ..mem::zeroed()
+        };
+
+        let mut resources: Vec<native::Resource> = Vec::new();
+        let images = (0 .. config.image_count)
+            .map(|i| {
+                let mut resource = native::Resource::null();
+                swapchain.GetBuffer(
+                    i as _,
+                    &d3d12::ID3D12Resource::uuidof(),
+                    resource.mut_void(),
+                );
+
+                let rtv_handle = rtv_heap.at(i as _, 0).cpu;
+                self.raw
+                    .CreateRenderTargetView(resource.as_mut_ptr(), &rtv_desc, rtv_handle);
+                resources.push(resource);
+
+                let surface_type = config.format.base_format().0;
+                let format_desc = surface_type.desc();
+
+                let bytes_per_block = (format_desc.bits / 8) as _;
+                let block_dim = format_desc.dim;
+                let kind = image::Kind::D2(config.extent.width, config.extent.height, 1, 1);
+
+                r::Image::Bound(r::ImageBound {
+                    resource,
+                    place: r::Place::SwapChain,
+                    region: None,
+                    surface_type,
+                    format: config.format,
+                    kind,
+                    usage: config.image_usage,
+                    default_view_format: Some(view_format),
+                    view_caps: image::ViewCapabilities::empty(),
+                    descriptor: d3d12::D3D12_RESOURCE_DESC {
+                        Dimension: d3d12::D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+                        Alignment: 0,
+                        Width: config.extent.width as _,
+                        Height: config.extent.height as _,
+                        DepthOrArraySize: 1,
+                        MipLevels: 1,
+                        Format: format,
+                        SampleDesc: dxgitype::DXGI_SAMPLE_DESC {
+                            Count: 1,
+                            Quality: 0,
+                        },
+                        Layout: d3d12::D3D12_TEXTURE_LAYOUT_UNKNOWN,
+                        Flags: 0,
+                    },
+                    bytes_per_block,
+                    block_dim,
+                    clear_cv: vec![rtv_handle],
+                    clear_dv: Vec::new(),
+                    clear_sv: Vec::new(),
+                    // Dummy values, image is already bound
+                    requirements: memory::Requirements {
+                        alignment: 1,
+                        size: 1,
+                        type_mask: MEM_TYPE_MASK,
+                    },
+                    // Back buffers are handed out in the present state.
+                    initial_state: d3d12::D3D12_RESOURCE_STATE_PRESENT,
+                })
+            })
+            .collect();
+
+        (resources, images)
+    }
+
+    /// Resize the back buffers of a swapchain, keeping its surface.
+    ///
+    /// All back buffer images and views of `swapchain` must have been destroyed and
+    /// must not be in use by the GPU anymore. The present mode of the swapchain is kept,
+    /// an MSAA target needs to be recreated with `create_swapchain_msaa_target`.
+    ///
+    /// An invalid `config` leaves the swapchain untouched. If resizing the back buffers
+    /// fails, the swapchain is lost and can only be passed to `destroy_swapchain`.
+    pub unsafe fn recreate_swapchain(
+        &self,
+        swapchain: &mut w::Swapchain,
+        config: hal::SwapchainConfig,
+    ) -> Result<Vec<r::Image>, hal::window::CreationError> {
+        validate_swapchain_image_count(config.image_count)?;
+        let format = swapchain_buffer_format(config.format);
+        if (config.present_mode == hal::PresentMode::Immediate) != swapchain.allow_tearing {
+            warn!(
+                "Present mode {:?} can't be changed on swapchain recreation",
+                config.present_mode
+            );
+        }
+
+        // `ResizeBuffers` fails while any back buffer is still referenced.
+        if let Some(msaa) = swapchain.msaa.take() {
+            msaa.destroy();
+        }
+        for resource in swapchain.resources.drain(..) {
+            resource.destroy();
+        }
+
+        let hr = swapchain.inner.ResizeBuffers(
+            config.image_count,
+            config.extent.width,
+            config.extent.height,
+            format,
+            if swapchain.allow_tearing {
+                dxgi::DXGI_SWAP_CHAIN_FLAG_ALLOW_TEARING as _
+            } else {
+                0
+            },
+        );
+        if !winerror::SUCCEEDED(hr) {
+            error!("error on swapchain resize 0x{:x}", hr);
+            swapchain.release();
+            return Err(map_swapchain_error(hr));
+        }
+
+        swapchain.rtv_heap.destroy();
+        let rtv_heap = Device::create_descriptor_heap_impl(
+            self.raw,
+            descriptor::HeapType::Rtv,
+            false,
+            config.image_count as _,
+        );
+        let (resources, images) =
+            self.create_swapchain_images(swapchain.inner, &rtv_heap, &config, format);
+
+        swapchain.rtv_heap = rtv_heap;
+        swapchain.resources = resources;
+        swapchain.extent = config.extent;
+        swapchain.format = config.format;
+        swapchain.buffer_format = format;
+        if !swapchain.color_space.supports_format(config.format) {
+            swapchain.set_color_space(w::ColorSpace::Srgb);
+        }
+
+        Ok(images)
+    }
+
+    /// Create a graphics pipeline with view instancing.
+    ///
+    /// Each of the `view_instance_count` instances renders into the render target
+    /// array slice of its index. A count of 0 disables view instancing.
+    /// Requires `Features::VIEW_INSTANCING` to be enabled for non-zero counts.
+    pub unsafe fn create_graphics_pipeline_with_view_instances<'a>(
+        &self,
+        desc: &pso::GraphicsPipelineDesc<'a, B>,
+        cache: Option<&r::PipelineCache>,
+        view_instance_count: u32,
+    ) -> Result<r::GraphicsPipeline, pso::CreationError> {
+        self.create_graphics_pipeline_impl(desc, cache, view_instance_count)
+    }
+
+    unsafe fn create_graphics_pipeline_impl<'a>(
+        &self,
+        desc: &pso::GraphicsPipelineDesc<'a, B>,
+        cache: Option<&r::PipelineCache>,
+        view_instance_count: u32,
+    ) -> Result<r::GraphicsPipeline, pso::CreationError> {
+        enum ShaderBc {
+            Owned(native::Blob),
+            Borrowed(native::Blob),
+            None,
+        }
+        impl ShaderBc {
+            pub fn shader(&self) -> native::Shader {
+                match *self {
+                    ShaderBc::Owned(ref bc) | ShaderBc::Borrowed(ref bc) => {
+                        native::Shader::from_blob(*bc)
+                    }
+                    ShaderBc::None => native::Shader::null(),
+                }
+            }
+        }
+
+        let build_shader = |stage: pso::Stage, source: Option<&pso::EntryPoint<'a, B>>| {
+            let source = match source {
+                Some(src) => src,
+                None => return Ok(ShaderBc::None),
+            };
+
+            match Self::extract_entry_point(stage, source, desc.layout, cache) {
+                Ok((shader, true)) => Ok(ShaderBc::Owned(shader)),
+                Ok((shader, false)) => Ok(ShaderBc::Borrowed(shader)),
+                Err(err) => Err(pso::CreationError::Shader(err)),
+            }
+        };
+
+        if desc.rasterizer.conservative
+            && !self.features.contains(hal::Features::CONSERVATIVE_RASTERIZATION)
+        {
+            error!("Conservative rasterization is not enabled on this device");
+            return Err(pso::CreationError::Other);
+        }
+        if desc.rasterizer.depth_clamping && !self.features.contains(hal::Features::DEPTH_CLAMP) {
+            error!("Depth clamping is not enabled on this device");
+            return Err(pso::CreationError::Other);
+        }
+        if desc.input_assembler.primitive_restart != pso::PrimitiveRestart::Disabled
+            && !conv::supports_primitive_restart(desc.input_assembler.primitive)
+        {
+            error!(
+                "Primitive restart requires a strip topology, got {:?}",
+                desc.input_assembler.primitive
+            );
+            return Err(pso::CreationError::Other);
+        }
+        if view_instance_count > 0 {
+            if !self.features.contains(hal::Features::VIEW_INSTANCING) {
+                error!("View instancing is not enabled on this device");
+                return Err(pso::CreationError::Other);
+            }
+            if view_instance_count > MAX_VIEW_INSTANCE_COUNT {
+                error!(
+                    "View instance count {} exceeds the maximum of {}",
+                    view_instance_count, MAX_VIEW_INSTANCE_COUNT
+                );
+                return Err(pso::CreationError::Other);
+            }
+        }
+
+        validate_vertex_input(&desc.vertex_buffers, &desc.attributes)?;
+
+        let vs = build_shader(pso::Stage::Vertex, Some(&desc.shaders.vertex))?;
+        let ps = build_shader(pso::Stage::Fragment, desc.shaders.fragment.as_ref())?;
+        let gs = build_shader(pso::Stage::Geometry, desc.shaders.geometry.as_ref())?;
+        let ds = build_shader(pso::Stage::Domain, desc.shaders.domain.as_ref())?;
+        let hs = build_shader(pso::Stage::Hull, desc.shaders.hull.as_ref())?;
+
+        // Rebind vertex buffers, see native.rs for more details.
+        let mut vertex_bindings = [None; MAX_VERTEX_BUFFERS];
+        let mut vertex_strides = [0; MAX_VERTEX_BUFFERS];
+
+        for buffer in &desc.vertex_buffers {
+            vertex_strides[buffer.binding as usize] = buffer.stride;
+        }
+        // Fill in identity mapping where we don't need to adjust anything.
+        for attrib in &desc.attributes {
+            let binding = attrib.binding as usize;
+            let stride = vertex_strides[attrib.binding as usize];
+            if attrib.element.offset < stride {
+                vertex_bindings[binding] = Some(r::VertexBinding {
+                    stride: vertex_strides[attrib.binding as usize],
+                    offset: 0,
+                    mapped_binding: binding,
+                });
+            }
+        }
+
+        // Define input element descriptions
+        let input_element_descs = desc
+            .attributes
+            .iter()
+            .filter_map(|attrib| {
+                let buffer_desc = match desc
+                    .vertex_buffers
+                    .iter()
+                    .find(|buffer_desc| buffer_desc.binding == attrib.binding)
+                {
+                    Some(buffer_desc) => buffer_desc,
+                    None => {
+                        error!(
+                            "Couldn't find associated vertex buffer description {:?}",
+                            attrib.binding
+                        );
+                        return Some(Err(pso::CreationError::Other));
+                    }
+                };
+
+                let (slot_class, step_rate) = match buffer_desc.rate {
+                    VertexInputRate::Vertex => {
+                        (d3d12::D3D12_INPUT_CLASSIFICATION_PER_VERTEX_DATA, 0)
+                    }
+                    VertexInputRate::Instance(divisor) => {
+                        (d3d12::D3D12_INPUT_CLASSIFICATION_PER_INSTANCE_DATA, divisor)
+                    }
+                };
+                let format = attrib.element.format;
+
+                // Check if we need to add a new remapping in-case the offset is
+                // higher than the vertex stride.
+                // In this case we rebase the attribute to zero offset.
+                let binding = attrib.binding as usize;
+                let stride = vertex_strides[binding];
+                let offset = attrib.element.offset;
+                let (input_slot, offset) = if stride <= offset {
+                    // Number of input attributes may not exceed bindings, see limits.
+                    // We will always find at least one free binding.
+                    let mapping = vertex_bindings.iter().position(Option::is_none).unwrap();
+                    vertex_bindings[mapping] = Some(r::VertexBinding {
+                        stride: vertex_strides[binding],
+                        offset: offset,
+                        mapped_binding: binding,
+                    });
+
+                    (mapping, 0)
+                } else {
+                    (binding, offset)
+                };
+
+                Some(Ok(d3d12::D3D12_INPUT_ELEMENT_DESC {
+                    SemanticName: "TEXCOORD\0".as_ptr() as *const _, // Semantic name used by SPIRV-Cross
+                    SemanticIndex: attrib.location,
+                    Format: match conv::map_format(format) {
+                        Some(fm) => fm,
+                        None => {
+                            error!("Unable to find DXGI format for {:?}", format);
+                            return Some(Err(pso::CreationError::Other));
+                        }
+                    },
+                    InputSlot: input_slot as _,
+                    AlignedByteOffset: offset,
+                    InputSlotClass: slot_class,
+                    InstanceDataStepRate: step_rate as _,
+                }))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // TODO: check maximum number of rtvs
+        // Get associated subpass information
+        let pass = {
+            let subpass = &desc.subpass;
+            match subpass.main_pass.subpasses.get(subpass.index) {
+                Some(subpass) => subpass,
+                None => return Err(pso::CreationError::InvalidSubpass(subpass.index)),
+            }
+        };
+
+        if let Some((_, layout)) = pass.depth_stencil_attachment {
+            if writes_read_only_depth(layout, &desc.depth_stencil.depth) {
+                error!("Depth writes are not allowed with a read-only depth stencil attachment");
+                return Err(pso::CreationError::Other);
+            }
+        }
+
+        let dynamic_depth_bias = is_depth_bias_dynamic(
+            desc.rasterizer.depth_bias,
+            self.private_caps.dynamic_depth_bias,
+        );
+
+        // Get color attachment formats from subpass
+        let (rtvs, num_rtvs) = match render_target_formats(
+            &desc.subpass.main_pass.attachments,
+            &pass.color_attachments,
+        ) {
+            Some(formats) => formats,
+            None => {
+                error!(
+                    "Number of color attachments ({}) exceeds the number of render targets",
+                    pass.color_attachments.len(),
+                );
+                return Err(pso::CreationError::Other);
+            }
+        };
+
+        let sample_desc = dxgitype::DXGI_SAMPLE_DESC {
+            Count: match desc.multisampling {
+                Some(ref ms) => ms.rasterization_samples as _,
+                None => 1,
+            },
+            Quality: 0,
+        };
+
+        // Setup pipeline description
+        let pso_desc = d3d12::D3D12_GRAPHICS_PIPELINE_STATE_DESC {
+            pRootSignature: desc.layout.raw.as_mut_ptr(),
+            VS: *vs.shader(),
+            PS: *ps.shader(),
+            GS: *gs.shader(),
+            DS: *ds.shader(),
+            HS: *hs.shader(),
+            StreamOutput: d3d12::D3D12_STREAM_OUTPUT_DESC {
+                pSODeclaration: ptr::null(),
+                NumEntries: 0,
+                pBufferStrides: ptr::null(),
+                NumStrides: 0,
+                RasterizedStream: 0,
+            },
+            BlendState: d3d12::D3D12_BLEND_DESC {
+                AlphaToCoverageEnable: desc.multisampling.as_ref().map_or(FALSE, |ms| {
+                    if ms.alpha_coverage {
+                        TRUE
+                    } else {
+                        FALSE
+                    }
+                }),
+                // Logic operations are only allowed with independent blending disabled.
+                IndependentBlendEnable: if desc.blender.logic_op.is_some() {
+                    FALSE
+                } else {
+                    TRUE
+                },
+                RenderTarget: conv::map_render_targets(&desc.blender),
+            },
+            SampleMask: UINT::max_value(),
+            RasterizerState: conv::map_rasterizer(&desc.rasterizer),
+            DepthStencilState: conv::map_depth_stencil(&desc.depth_stencil),
+            InputLayout: d3d12::D3D12_INPUT_LAYOUT_DESC {
+                pInputElementDescs: if input_element_descs.is_empty() {
+                    ptr::null()
+                } else {
+                    input_element_descs.as_ptr()
+                },
+                NumElements: input_element_descs.len() as u32,
+            },
+            IBStripCutValue: conv::map_primitive_restart(desc.input_assembler.primitive_restart),
+            PrimitiveTopologyType: conv::map_topology_type(desc.input_assembler.primitive),
+            NumRenderTargets: num_rtvs,
+            RTVFormats: rtvs,
+            DSVFormat: pass
+                .depth_stencil_attachment
+                .and_then(|att_ref| {
+                    desc.subpass.main_pass.attachments[att_ref.0]
+                        .format
+                        .and_then(|f| conv::map_format_dsv(f.base_format().0))
+                })
+                .unwrap_or(dxgiformat::DXGI_FORMAT_UNKNOWN),
+            SampleDesc: sample_desc,
+            NodeMask: 0,
+            CachedPSO: d3d12::D3D12_CACHED_PIPELINE_STATE {
+                pCachedBlob: ptr::null(),
+                CachedBlobSizeInBytes: 0,
+            },
+            Flags: if dynamic_depth_bias {
+                PIPELINE_STATE_FLAG_DYNAMIC_DEPTH_BIAS
+            } else {
+                d3d12::D3D12_PIPELINE_STATE_FLAG_NONE
+            },
+        };
+
+        let topology = conv::map_topology(desc.input_assembler.primitive);
+
+        // Create PSO
+        let mut pipeline = native::PipelineState::null();
+        let hr = if view_instance_count > 0 {
+            // View instancing is only available through the subobject stream.
+            let (device2, hr) = self.raw.cast::<d3d12::ID3D12Device2>();
+            if winerror::SUCCEEDED(hr) {
+                let locations = (0 .. view_instance_count)
+                    .map(|i| ViewInstanceLocation {
+                        ViewportArrayIndex: 0,
+                        RenderTargetArrayIndex: i,
+                    })
+                    .collect::<Vec<_>>();
+                let mut pss_stream = ViewInstancingPipelineStateSubobjectStream {
+                    base: GraphicsPipelineStateSubobjectStream::new(
+                        &pso_desc,
+                        desc.depth_stencil.depth_bounds,
+                    ),
+                    view_instancing: ViewInstancingSubobject {
+                        _align: [],
+                        ty: PIPELINE_STATE_SUBOBJECT_TYPE_VIEW_INSTANCING,
+                        desc: ViewInstancingDesc {
+                            ViewInstanceCount: view_instance_count,
+                            pViewInstanceLocations: locations.as_ptr(),
+                            Flags: 0,
+                        },
+                    },
+                };
+                let pss_desc = d3d12::D3D12_PIPELINE_STATE_STREAM_DESC {
+                    SizeInBytes: mem::size_of_val(&pss_stream),
+                    pPipelineStateSubobjectStream: &mut pss_stream as *mut _ as _,
+                };
+                let hr = device2.CreatePipelineState(
+                    &pss_desc,
+                    &d3d12::ID3D12PipelineState::uuidof(),
+                    pipeline.mut_void(),
+                );
+                device2.destroy();
+                hr
+            } else {
+                hr
+            }
+        } else if desc.depth_stencil.depth_bounds {
+            // The DepthBoundsTestEnable option isn't available in the original D3D12_GRAPHICS_PIPELINE_STATE_DESC struct.
+            // Instead, we must use the newer subobject stream method.
+            let (device2, hr) = self.raw.cast::<d3d12::ID3D12Device2>();
+            if winerror::SUCCEEDED(hr) {
+                let mut pss_stream = GraphicsPipelineStateSubobjectStream::new(&pso_desc, true);
+                let pss_desc = d3d12::D3D12_PIPELINE_STATE_STREAM_DESC {
+                    SizeInBytes: mem::size_of_val(&pss_stream),
+                    pPipelineStateSubobjectStream: &mut pss_stream as *mut _ as _,
+                };
+                device2.CreatePipelineState(
+                    &pss_desc,
+                    &d3d12::ID3D12PipelineState::uuidof(),
+                    pipeline.mut_void(),
+                )
+            } else {
+                hr
+            }
+        } else {
+            self.raw.clone().CreateGraphicsPipelineState(
+                &pso_desc,
+                &d3d12::ID3D12PipelineState::uuidof(),
+                pipeline.mut_void(),
+            )
+        };
+
+        let destroy_shader = |shader: ShaderBc| {
+            if let ShaderBc::Owned(bc) = shader {
+                bc.destroy();
+            }
+        };
+
+        destroy_shader(vs);
+        destroy_shader(ps);
+        destroy_shader(gs);
+        destroy_shader(hs);
+        destroy_shader(ds);
+
+        if winerror::SUCCEEDED(hr) {
+            let mut baked_states = desc.baked_states.clone();
+            if !desc.depth_stencil.depth_bounds {
+                baked_states.depth_bounds = None;
+            }
+
+            Ok(r::GraphicsPipeline {
+                raw: pipeline,
+                signature: desc.layout.raw,
+                num_parameter_slots: desc.layout.num_parameter_slots,
+                topology,
+                constants: desc.layout.root_constants.clone(),
+                vertex_bindings,
+                baked_states,
+                uses_blend_constants: uses_blend_constants(&desc.blender),
+                dynamic_depth_bias,
+            })
+        } else {
+            Err(pso::CreationError::Other)
+        }
+    }
+
+    /// Create a descriptor set layout with flags for each of its bindings.
+    ///
+    /// A binding with `VARIABLE_DESCRIPTOR_COUNT` is an unbounded array in the shader, e.g.
+    /// `Texture2D textures[] : register(t0, space1)`, which can be indexed dynamically.
+    /// It has to be the last binding of the layout, `count` descriptors are allocated for it
+    /// in each set. Descriptors are volatile unless a binding has `DATA_STATIC`, so bindings
+    /// with `UPDATE_AFTER_BIND` or `PARTIALLY_BOUND` don't require anything else.
+    ///
+    /// `DATA_STATIC` and `DATA_STATIC_WHILE_SET_AT_EXECUTE` are hints for the descriptor
+    /// range flags of version 1.1 root signatures, they are ignored by devices which only
+    /// support version 1.0.
+    ///
+    /// Fails if the device doesn't support bindless descriptors, see
+    /// `PhysicalDevice::supports_bindless`.
+    pub unsafe fn create_descriptor_set_layout_with_flags<I, J>(
+        &self,
+        bindings: I,
+        immutable_samplers: J,
+    ) -> Result<r::DescriptorSetLayout, d::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<(pso::DescriptorSetLayoutBinding, r::DescriptorBindingFlags)>,
+        J: IntoIterator,
+        J::Item: Borrow<r::Sampler>,
+    {
+        let bindings = bindings
+            .into_iter()
+            .map(|binding| binding.borrow().clone())
+            .collect::<Vec<_>>();
+        let bindless = r::DescriptorBindingFlags::UPDATE_AFTER_BIND
+            | r::DescriptorBindingFlags::PARTIALLY_BOUND
+            | r::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT;
+        let uses_bindless = bindings.iter().any(|&(_, flags)| flags.intersects(bindless));
+        if uses_bindless && !self.private_caps.bindless {
+            error!("Bindless descriptor binding flags require resource binding tier 3");
+            return Err(d::OutOfMemory::OutOfHostMemory);
+        }
+        if !bindings.iter().all(|&(ref binding, flags)| is_valid_binding_flags(binding, flags)) {
+            return Err(d::OutOfMemory::OutOfHostMemory);
+        }
+        let variable_count =
+            has_variable_count(&bindings).map_err(|()| d::OutOfMemory::OutOfHostMemory)?;
+
+        let mut layout = self.create_descriptor_set_layout(
+            bindings.iter().map(|&(ref binding, _)| binding),
+            immutable_samplers,
+        )?;
+        // Bindings with static samplers aren't part of the layout bindings.
+        layout.binding_flags = layout
+            .bindings
+            .iter()
+            .map(|binding| {
+                bindings
+                    .iter()
+                    .find(|&&(ref other, _)| other.binding == binding.binding)
+                    .map_or(r::DescriptorBindingFlags::empty(), |&(_, flags)| flags)
+            })
+            .collect();
+        layout.variable_count = variable_count;
+        Ok(layout)
+    }
+}
+
+impl d::Device<B> for Device {
+    unsafe fn allocate_memory(
+        &self,
+        mem_type: hal::MemoryTypeId,
+        size: u64,
+    ) -> Result<r::Memory, d::AllocationError> {
+        let mem_type = mem_type.0;
+        // Exposed memory types are grouped according to their capabilities.
+        // See `MemoryGroup` for more details.
+        let mem_group = mem_type / NUM_HEAP_PROPERTIES;
+
+        let (flags, alignment) = heap_flags_and_alignment(mem_group);
+        let desc = d3d12::D3D12_HEAP_DESC {
+            SizeInBytes: size,
+            Properties: self.memory_heap_properties(mem_type),
+            Alignment: alignment,
+            Flags: flags,
+        };
+
+        let mut heap = native::Heap::null();
+        let hr = self
+            .raw
+            .clone()
+            .CreateHeap(&desc, &d3d12::ID3D12Heap::uuidof(), heap.mut_void());
+        if hr == winerror::E_OUTOFMEMORY {
+            return Err(d::OutOfMemory::OutOfDeviceMemory.into());
+        }
+        assert_eq!(winerror::S_OK, hr);
+
+        let is_mapable = is_memory_type_mappable(mem_type);
+
+        // Create a buffer resource covering the whole memory slice to be able to map the whole memory.
+        let resource = if is_mapable {
+            let mut resource = native::Resource::null();
+            let desc = d3d12::D3D12_RESOURCE_DESC {
+                Dimension: d3d12::D3D12_RESOURCE_DIMENSION_BUFFER,
+                Alignment: 0,
+                Width: size,
+                Height: 1,
+                DepthOrArraySize: 1,
+                MipLevels: 1,
+                Format: dxgiformat::DXGI_FORMAT_UNKNOWN,
+                SampleDesc: dxgitype::DXGI_SAMPLE_DESC {
+                    Count: 1,
+                    Quality: 0,
+                },
+                Layout: d3d12::D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
+                Flags: d3d12::D3D12_RESOURCE_FLAG_NONE,
+            };
+
+            assert_eq!(
+                winerror::S_OK,
+                self.raw.clone().CreatePlacedResource(
+                    heap.as_mut_ptr(),
+                    0,
+                    &desc,
+                    d3d12::D3D12_RESOURCE_STATE_COMMON,
+                    ptr::null(),
+                    &d3d12::ID3D12Resource::uuidof(),
+                    resource.mut_void(),
+                )
+            );
+
+            Some(resource)
+        } else {
+            None
+        };
+
+        Ok(r::Memory {
+            heap,
+            type_id: mem_type,
+            size,
+            resource,
+            placements: Arc::new(Mutex::new(Vec::new())),
+            dedicated: None,
+        })
+    }
+
+    unsafe fn create_command_pool(
+        &self,
+        family: QueueFamilyId,
+        create_flags: CommandPoolCreateFlags,
+    ) -> Result<RawCommandPool, d::OutOfMemory> {
+        let list_type = QUEUE_FAMILIES[family.0].native_type();
+
+        let allocator = if create_flags.contains(CommandPoolCreateFlags::RESET_INDIVIDUAL) {
+            // Allocators are created per individual ID3D12GraphicsCommandList
+            CommandPoolAllocator::Individual(Vec::new())
+        } else {
+            let (command_allocator, hr) = self.raw.create_command_allocator(list_type);
+
+            // TODO: error handling
+            if !winerror::SUCCEEDED(hr) {
+                error!("error on command allocator creation: {:x}", hr);
+            }
+
+            CommandPoolAllocator::Shared(command_allocator)
+        };
+
+        Ok(RawCommandPool {
+            allocator,
+            device: self.raw,
+            list_type,
+            shared: self.shared.clone(),
+            create_flags,
+            bundle_allocators: Vec::new(),
+        })
+    }
+
+    unsafe fn destroy_command_pool(&self, pool: RawCommandPool) {
+        pool.destroy();
+    }
+
+    unsafe fn create_render_pass<'a, IA, IS, ID>(
+        &self,
+        attachments: IA,
+        subpasses: IS,
+        dependencies: ID,
+    ) -> Result<r::RenderPass, d::OutOfMemory>
+    where
+        IA: IntoIterator,
+        IA::Item: Borrow<pass::Attachment>,
+        IS: IntoIterator,
+        IS::Item: Borrow<pass::SubpassDesc<'a>>,
+        ID: IntoIterator,
+        ID::Item: Borrow<pass::SubpassDependency>,
+    {
+        #[derive(Copy, Clone, Debug, PartialEq)]
+        pub enum SubState {
+            New(d3d12::D3D12_RESOURCE_STATES),
+            // Color attachment which will be resolved at the end of the subpass
+            Resolve(d3d12::D3D12_RESOURCE_STATES),
+            Preserve,
+            Undefined,
+        }
+        struct AttachmentInfo {
+            sub_states: Vec<SubState>,
+            target_state: d3d12::D3D12_RESOURCE_STATES,
+            last_state: d3d12::D3D12_RESOURCE_STATES,
+            barrier_start_index: usize,
+        }
+
+        let attachments = attachments
+            .into_iter()
+            .map(|attachment| attachment.borrow().clone())
+            .collect::<Vec<_>>();
+        let subpasses = subpasses.into_iter().collect::<Vec<_>>();
+        let dependencies = dependencies.into_iter().collect::<Vec<_>>();
+        let mut att_infos = attachments
+            .iter()
+            .map(|att| AttachmentInfo {
+                sub_states: vec![SubState::Undefined; subpasses.len()],
+                target_state: if att.format.map_or(false, |f| f.is_depth()) {
+                    d3d12::D3D12_RESOURCE_STATE_DEPTH_WRITE //TODO?
+                } else {
+                    d3d12::D3D12_RESOURCE_STATE_RENDER_TARGET
+                },
+                last_state: conv::map_image_resource_state(
+                    image::Access::empty(),
+                    att.layouts.start,
+                ),
+                barrier_start_index: 0,
+            })
+            .collect::<Vec<_>>();
+
+        // Fill out subpass known layouts
+        for (sid, sub) in subpasses.iter().enumerate() {
+            let sub = sub.borrow();
+            if sub.colors.len() > d3d12::D3D12_SIMULTANEOUS_RENDER_TARGET_COUNT as usize {
+                error!(
+                    "Subpass {} has {} color attachments, at most {} are supported",
+                    sid,
+                    sub.colors.len(),
+                    d3d12::D3D12_SIMULTANEOUS_RENDER_TARGET_COUNT,
+                );
+                return Err(d::OutOfMemory::OutOfHostMemory);
+            }
+            for (i, &(id, _layout)) in sub.colors.iter().enumerate() {
+                let dst_state = att_infos[id].target_state;
+                let state = match sub.resolves.get(i) {
+                    Some(_) => SubState::Resolve(dst_state),
+                    None => SubState::New(dst_state),
+                };
+                let old = mem::replace(&mut att_infos[id].sub_states[sid], state);
+                debug_assert_eq!(SubState::Undefined, old);
+            }
+            for &(id, layout) in sub.depth_stencil {
+                let state = if layout == image::Layout::DepthStencilReadOnlyOptimal {
+                    SubState::New(d3d12::D3D12_RESOURCE_STATE_DEPTH_READ)
+                } else {
+                    SubState::New(att_infos[id].target_state)
+                };
+                let old = mem::replace(&mut att_infos[id].sub_states[sid], state);
+                debug_assert_eq!(SubState::Undefined, old);
+            }
+            for &(id, _layout) in sub.inputs {
+                // Input attachments are read as SRVs at the position of the current pixel.
+                let current = match att_infos[id].sub_states[sid] {
+                    SubState::New(state) | SubState::Resolve(state) => Some(state),
+                    SubState::Undefined | SubState::Preserve => None,
+                };
+                match input_attachment_state(current) {
+                    Some(state) => att_infos[id].sub_states[sid] = SubState::New(state),
+                    None => {
+                        error!(
+                            "Attachment {} is written and used as input attachment in subpass {}",
+                            id, sid,
+                        );
+                        return Err(d::OutOfMemory::OutOfHostMemory);
+                    }
+                }
+            }
+            // Resolve targets are transitioned from their initial layout, which is `PRESENT`
+            // for swapchain back buffers, and into their final layout after the pass.
+            for &(id, _layout) in sub.resolves {
+                let state = SubState::New(d3d12::D3D12_RESOURCE_STATE_RESOLVE_DEST);
+                let old = mem::replace(&mut att_infos[id].sub_states[sid], state);
+                debug_assert_eq!(SubState::Undefined, old);
+            }
+            for &id in sub.preserves {
+                let old = mem::replace(&mut att_infos[id].sub_states[sid], SubState::Preserve);
+                debug_assert_eq!(SubState::Undefined, old);
+            }
+        }
+
+        let mut deps_left = vec![0u16; subpasses.len()];
+        for dep in &dependencies {
+            let dep = dep.borrow();
+            //Note: self-dependencies are ignored
+            if dep.passes.start != dep.passes.end && dep.passes.start != pass::SubpassRef::External
+            {
+                if let pass::SubpassRef::Pass(sid) = dep.passes.end {
+                    deps_left[sid] += 1;
+                }
+            }
+        }
+
+        let mut rp = r::RenderPass {
+            attachments: attachments.clone(),
+            subpasses: Vec::new(),
+            post_barriers: Vec::new(),
+        };
+
+        while let Some(sid) = deps_left.iter().position(|count| *count == 0) {
+            deps_left[sid] = !0; // mark as done
+            for dep in &dependencies {
+                let dep = dep.borrow();
+                if dep.passes.start != dep.passes.end
+                    && dep.passes.start == pass::SubpassRef::Pass(sid)
+                {
+                    if let pass::SubpassRef::Pass(other) = dep.passes.end {
+                        deps_left[other] -= 1;
+                    }
+                }
+            }
+
+            // Subpass barriers
+            let mut pre_barriers = Vec::new();
+            let mut post_barriers = Vec::new();
+            for (att_id, ai) in att_infos.iter_mut().enumerate() {
+                // Barrier from previous subpass to current or following subpasses.
+                match ai.sub_states[sid] {
+                    SubState::Preserve => {
+                        ai.barrier_start_index = rp.subpasses.len() + 1;
+                    }
+                    SubState::New(state) if state != ai.last_state => {
+                        let barrier = r::BarrierDesc::new(att_id, ai.last_state .. state);
+                        match rp.subpasses.get_mut(ai.barrier_start_index) {
+                            Some(past_subpass) => {
+                                let split = barrier.split();
+                                past_subpass.pre_barriers.push(split.start);
+                                pre_barriers.push(split.end);
+                            }
+                            None => pre_barriers.push(barrier),
+                        }
+                        ai.last_state = state;
+                        ai.barrier_start_index = rp.subpasses.len() + 1;
+                    }
+                    SubState::Resolve(state) => {
+                        // 1. Standard pre barrier to update state from previous pass into desired substate.
+                        if state != ai.last_state {
+                            let barrier = r::BarrierDesc::new(att_id, ai.last_state .. state);
+                            match rp.subpasses.get_mut(ai.barrier_start_index) {
+                                Some(past_subpass) => {
+                                    let split = barrier.split();
+                                    past_subpass.pre_barriers.push(split.start);
+                                    pre_barriers.push(split.end);
+                                }
+                                None => pre_barriers.push(barrier),
+                            }
+                        }
+
+                        // 2. Post Barrier at the end of the subpass into RESOLVE_SOURCE.
+                        let resolve_state = d3d12::D3D12_RESOURCE_STATE_RESOLVE_SOURCE;
+                        let barrier = r::BarrierDesc::new(att_id, state .. resolve_state);
+                        post_barriers.push(barrier);
+
+                        ai.last_state = resolve_state;
+                        ai.barrier_start_index = rp.subpasses.len() + 1;
+                    }
+                    _ => {}
+                };
+            }
+
+            rp.subpasses.push(r::SubpassDesc {
+                color_attachments: subpasses[sid].borrow().colors.iter().cloned().collect(),
+                depth_stencil_attachment: subpasses[sid].borrow().depth_stencil.cloned(),
+                input_attachments: subpasses[sid].borrow().inputs.iter().cloned().collect(),
+                resolve_attachments: subpasses[sid].borrow().resolves.iter().cloned().collect(),
+                pre_barriers,
+                post_barriers,
+                uav_barrier: dependencies.iter().any(|dep| {
+                    let dep = dep.borrow();
+                    dep.passes.end == pass::SubpassRef::Pass(sid)
+                        && dep.passes.start != dep.passes.end
+                        && dep.passes.start != pass::SubpassRef::External
+                        && requires_uav_barrier(&dep.accesses)
+                }),
+            });
+        }
+        // if this fails, our graph has cycles
+        assert_eq!(rp.subpasses.len(), subpasses.len());
+        assert!(deps_left.into_iter().all(|count| count == !0));
+
+        // take care of the post-pass transitions at the end of the renderpass.
+        for (att_id, (ai, att)) in att_infos.iter().zip(attachments.iter()).enumerate() {
+            let state_dst = conv::map_image_resource_state(image::Access::empty(), att.layouts.end);
+            if state_dst == ai.last_state {
+                continue;
+            }
+            let barrier = r::BarrierDesc::new(att_id, ai.last_state .. state_dst);
+            match rp.subpasses.get_mut(ai.barrier_start_index) {
+                Some(past_subpass) => {
+                    let split = barrier.split();
+                    past_subpass.pre_barriers.push(split.start);
+                    rp.post_barriers.push(split.end);
+                }
+                None => rp.post_barriers.push(barrier),
+            }
+        }
+
+        Ok(rp)
+    }
+
+    unsafe fn create_pipeline_layout<IS, IR>(
+        &self,
+        sets: IS,
+        push_constant_ranges: IR,
+    ) -> Result<r::PipelineLayout, d::OutOfMemory>
+    where
+        IS: IntoIterator,
+        IS::Item: Borrow<r::DescriptorSetLayout>,
+        IR: IntoIterator,
+        IR::Item: Borrow<(pso::ShaderStageFlags, Range<u32>)>,
+    {
+        // Pipeline layouts are implemented as RootSignature for D3D12.
+        //
+        // Each descriptor set layout will be one table entry of the root signature.
+        // We have the additional restriction that SRV/CBV/UAV and samplers need to be
+        // separated, so each set layout will actually occupy up to 2 entries!
+        //
+        // Root signature layout:
+        //     Root Constants: Register: Offest/4, Space: 0
+        //       ...
+        //     DescriptorTable0: Space: 2 (+1) (SrvCbvUav)
+        //     DescriptorTable0: Space: 3 (+1) (Sampler)
+        //     DescriptorTable1: Space: 4 (+1) (SrvCbvUav)
+        //     ...
+
+        let sets = sets.into_iter().collect::<Vec<_>>();
+        let root_constants = root_constants::split(push_constant_ranges)
+            .iter()
+            .map(|constant| {
+                assert!(constant.range.start <= constant.range.end);
+                RootConstant {
+                    stages: constant.stages,
+                    range: constant.range.start .. constant.range.end,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // guarantees that no re-allocation is done, and our pointers are valid
+        let mut parameters = Vec::with_capacity(root_constants.len() + sets.len() * 2);
+
+        for root_constant in root_constants.iter() {
+            parameters.push(native::descriptor::RootParameter::constants(
+                native::descriptor::ShaderVisibility::All, // TODO
+                native::descriptor::Binding {
+                    register: root_constant.range.start as _,
+                    space: ROOT_CONSTANT_SPACE,
+                },
+                (root_constant.range.end - root_constant.range.start) as _,
+            ));
+        }
+
+        // Offest of `spaceN` for descriptor tables. Root constants will be in
+        // `space0`.
+        let table_space_offset = if !root_constants.is_empty() { 1 } else { 0 };
+
+        // Collect the whole number of bindings we will create upfront.
+        // It allows us to preallocate enough storage to avoid reallocation,
+        // which could cause invalid pointers.
+        let total = sets
+            .iter()
+            .map(|desc_set| {
+                let mut sum = 0;
+                let bindings = &desc_set.borrow().bindings;
+
+                for binding in bindings {
+                    sum += if binding.ty == pso::DescriptorType::CombinedImageSampler {
+                        2
+                    } else {
+                        1
+                    };
+                }
+
+                sum
+            })
+            .sum();
+        let mut ranges = Vec::with_capacity(total);
+        let mut range_flags = Vec::with_capacity(total);
+        let mut set_tables = Vec::with_capacity(sets.len());
+
+        for (i, set) in sets.iter().enumerate() {
+            let set = set.borrow();
+            let mut table_type = r::SetTableTypes::empty();
+            // Only the last binding of a layout can have a variable count, which keeps
+            // the unbounded ranges at the end of both tables of the set.
+            let is_unbounded = |bind: &pso::DescriptorSetLayoutBinding| {
+                set.variable_count
+                    && set.bindings.last().map(|last| last.binding) == Some(bind.binding)
+            };
+
+            let range_base = ranges.len();
+            for (bind, &flags) in set.bindings.iter().zip(&set.binding_flags) {
+                if bind.ty == pso::DescriptorType::Sampler {
+                    continue;
+                }
+                ranges.push(conv::map_descriptor_range(
+                    bind,
+                    (table_space_offset + i) as u32,
+                    false,
+                    is_unbounded(bind),
+                ));
+                range_flags.push(descriptor_range_flags(flags, false));
+            }
+
+            if ranges.len() > range_base {
+                parameters.push(native::descriptor::RootParameter::descriptor_table(
+                    native::descriptor::ShaderVisibility::All, // TODO
+                    &ranges[range_base ..],
+                ));
+                table_type |= r::SRV_CBV_UAV;
+            }
+
+            let range_base = ranges.len();
+            for (bind, &flags) in set.bindings.iter().zip(&set.binding_flags) {
+                if bind.ty != pso::DescriptorType::Sampler
+                    && bind.ty != pso::DescriptorType::CombinedImageSampler
+                {
+                    continue;
+                }
+                ranges.push(conv::map_descriptor_range(
+                    bind,
+                    (table_space_offset + i) as u32,
+                    true,
+                    is_unbounded(bind),
+                ));
+                range_flags.push(descriptor_range_flags(flags, true));
+            }
+
+            if ranges.len() > range_base {
+                parameters.push(native::descriptor::RootParameter::descriptor_table(
+                    native::descriptor::ShaderVisibility::All, // TODO
+                    &ranges[range_base ..],
+                ));
+                table_type |= r::SAMPLERS;
+            }
+
+            set_tables.push(table_type);
+        }
+
+        // Ensure that we didn't reallocate!
+        debug_assert_eq!(ranges.len(), total);
+
+        let static_samplers = sets
+            .iter()
+            .enumerate()
+            .flat_map(|(i, set)| {
+                set.borrow()
+                    .static_samplers
+                    .iter()
+                    .map(move |&(register, ref info)| {
+                        static_sampler(
+                            info,
+                            native::descriptor::Binding {
+                                register,
+                                space: (table_space_offset + i) as u32,
+                            },
+                        )
+                    })
+            })
+            .collect::<Vec<_>>();
+
+        let signature_flags = native::descriptor::RootSignatureFlags::ALLOW_IA_INPUT_LAYOUT;
+        // Version 1.0 root signatures treat all descriptors and data as volatile.
+        let signature_raw = if self.private_caps.root_signature_1_1 {
+            serialize_root_signature_1_1(
+                &parameters,
+                &ranges,
+                &range_flags,
+                &static_samplers,
+                signature_flags,
+            )
+        } else {
+            // TODO: error handling
+            let ((signature_raw, error), _hr) = native::RootSignature::serialize(
+                native::descriptor::RootSignatureVersion::V1_0,
+                &parameters,
+                &static_samplers,
+                signature_flags,
+            );
+
+            if !error.is_null() {
+                error!(
+                    "Root signature serialization error: {:?}",
+                    error.as_c_str().to_str().unwrap()
+                );
+                error.destroy();
+            }
+            signature_raw
+        };
+
+        // TODO: error handling
+        let (signature, _hr) = self.raw.create_root_signature(signature_raw, 0);
+        signature_raw.destroy();
+
+        Ok(r::PipelineLayout {
+            raw: signature,
+            tables: set_tables,
+            root_constants,
+            num_parameter_slots: parameters.len(),
+        })
+    }
+
+    unsafe fn create_pipeline_cache(
+        &self,
+        data: Option<&[u8]>,
+    ) -> Result<r::PipelineCache, d::OutOfMemory> {
+        let cache = data.and_then(|data| {
+            let cache = r::PipelineCache::from_data(data);
+            if cache.is_none() {
+                warn!("Ignoring incompatible pipeline cache data");
+            }
+            cache
+        });
+        Ok(cache.unwrap_or_default())
+    }
+
+    unsafe fn get_pipeline_cache_data(
+        &self,
+        cache: &r::PipelineCache,
+    ) -> Result<Vec<u8>, d::OutOfMemory> {
+        Ok(cache.to_data())
+    }
+
+    unsafe fn destroy_pipeline_cache(&self, _: r::PipelineCache) {
+        //empty
+    }
+
+    unsafe fn merge_pipeline_caches<I>(
+        &self,
+        target: &r::PipelineCache,
+        sources: I,
+    ) -> Result<(), d::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<r::PipelineCache>,
+    {
+        for source in sources {
+            target.merge(source.borrow());
+        }
+        Ok(())
+    }
+
+    unsafe fn create_graphics_pipeline<'a>(
+        &self,
+        desc: &pso::GraphicsPipelineDesc<'a, B>,
+        cache: Option<&r::PipelineCache>,
+    ) -> Result<r::GraphicsPipeline, pso::CreationError> {
+        self.create_graphics_pipeline_impl(desc, cache, 0)
+    }
+
+    unsafe fn create_compute_pipeline<'a>(
+        &self,
+        desc: &pso::ComputePipelineDesc<'a, B>,
+        cache: Option<&r::PipelineCache>,
+    ) -> Result<r::ComputePipeline, pso::CreationError> {
+        let (cs, cs_destroy) =
+            Self::extract_entry_point(pso::Stage::Compute, &desc.shader, desc.layout, cache)
+                .map_err(|err| pso::CreationError::Shader(err))?;
+
+        let (pipeline, hr) = self.raw.create_compute_pipeline_state(
+            desc.layout.raw,
+            native::Shader::from_blob(cs),
+            0,
+            CachedPSO::null(),
+            PipelineStateFlags::empty(),
+        );
+
+        if cs_destroy {
+            cs.destroy();
+        }
+
+        if winerror::SUCCEEDED(hr) {
+            Ok(r::ComputePipeline {
+                raw: pipeline,
+                signature: desc.layout.raw,
+                num_parameter_slots: desc.layout.num_parameter_slots,
+                constants: desc.layout.root_constants.clone(),
+            })
+        } else {
+            Err(pso::CreationError::Other)
+        }
+    }
+
+    unsafe fn create_framebuffer<I>(
+        &self,
+        _renderpass: &r::RenderPass,
+        attachments: I,
+        extent: image::Extent,
+    ) -> Result<r::Framebuffer, d::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<r::ImageView>,
+    {
+        Ok(r::Framebuffer {
+            attachments: attachments.into_iter().map(|att| *att.borrow()).collect(),
+            layers: extent.depth as _,
+        })
+    }
+
+    unsafe fn create_shader_module(
+        &self,
+        raw_data: &[u32],
+    ) -> Result<r::ShaderModule, d::ShaderError> {
+        Ok(r::ShaderModule::Spirv {
+            raw: raw_data.into(),
+            translations: Mutex::new(HashMap::new()),
+        })
+    }
+
+    unsafe fn create_buffer(
+        &self,
+        mut size: u64,
+        usage: buffer::Usage,
+    ) -> Result<r::Buffer, buffer::CreationError> {
+        if usage.contains(buffer::Usage::UNIFORM) {
+            // Constant buffer view sizes need to be aligned.
+            // Coupled with the offset alignment we can enforce an aligned CBV size
+            // on descriptor updates.
+            size = (size + 255) & !255;
+        }
+        if usage.contains(buffer::Usage::TRANSFER_DST) {
+            // minimum of 1 word for the clear UAV
+            size = size.max(4);
+        }
+
+        let type_mask_shift = if self.private_caps.heterogeneous_resource_heaps {
+            MEM_TYPE_UNIVERSAL_SHIFT
+        } else {
+            MEM_TYPE_BUFFER_SHIFT
+        };
+
+        let requirements = memory::Requirements {
+            size,
+            alignment: d3d12::D3D12_DEFAULT_RESOURCE_PLACEMENT_ALIGNMENT as u64,
+            type_mask: MEM_TYPE_MASK << type_mask_shift,
+        };
+
+        Ok(r::Buffer::Unbound(r::BufferUnbound {
+            requirements,
+            usage,
+        }))
+    }
+
+    unsafe fn get_buffer_requirements(&self, buffer: &r::Buffer) -> Requirements {
+        match buffer {
+            r::Buffer::Unbound(b) => b.requirements,
+            r::Buffer::Bound(b) => b.requirements,
+        }
+    }
+
+    unsafe fn bind_buffer_memory(
+        &self,
+        memory: &r::Memory,
+        offset: u64,
+        buffer: &mut r::Buffer,
+    ) -> Result<(), d::BindError> {
+        let buffer_unbound = *buffer.expect_unbound();
+        if buffer_unbound.requirements.type_mask & (1 << memory.type_id) == 0 {
+            error!(
+                "Bind memory failure: supported mask 0x{:x}, given id {}",
+                buffer_unbound.requirements.type_mask, memory.type_id
+            );
+            return Err(d::BindError::WrongMemory);
+        }
+        if offset + buffer_unbound.requirements.size > memory.size {
+            return Err(d::BindError::OutOfBounds);
+        }
+
+        let desc = d3d12::D3D12_RESOURCE_DESC {
+            Dimension: d3d12::D3D12_RESOURCE_DIMENSION_BUFFER,
+            Alignment: 0,
+            Width: buffer_unbound.requirements.size,
+            Height: 1,
+            DepthOrArraySize: 1,
+            MipLevels: 1,
+            Format: dxgiformat::DXGI_FORMAT_UNKNOWN,
+            SampleDesc: dxgitype::DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            Layout: d3d12::D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
+            Flags: conv::map_buffer_flags(buffer_unbound.usage),
+        };
+        let resource =
+            self.create_bound_resource(memory, offset, &desc, d3d12::D3D12_RESOURCE_STATE_COMMON)?;
+
+        let clear_uav = if buffer_unbound.usage.contains(buffer::Usage::TRANSFER_DST) {
+            let handle = self.srv_uav_pool.lock().unwrap().alloc_handle();
+            let mut view_desc = d3d12::D3D12_UNORDERED_ACCESS_VIEW_DESC {
+                Format: dxgiformat::DXGI_FORMAT_R32_TYPELESS,
+                ViewDimension: d3d12::D3D12_UAV_DIMENSION_BUFFER,
+                u: mem::zeroed(),
+            };
+
+            *view_desc.u.Buffer_mut() = d3d12::D3D12_BUFFER_UAV {
+                FirstElement: 0,
+                NumElements: (buffer_unbound.requirements.size / 4) as _,
+                StructureByteStride: 0,
+                CounterOffsetInBytes: 0,
+                Flags: d3d12::D3D12_BUFFER_UAV_FLAG_RAW,
+            };
+
+            self.raw.CreateUnorderedAccessView(
+                resource.as_mut_ptr(),
+                ptr::null_mut(),
+                &view_desc,
+                handle,
+            );
+            Some(handle)
+        } else {
+            None
+        };
+
+        *buffer = r::Buffer::Bound(r::BufferBound {
+            resource,
+            requirements: buffer_unbound.requirements,
+            usage: buffer_unbound.usage,
+            clear_uav,
+            region: Some(r::MemoryRegion::new(
+                memory,
+                offset,
+                buffer_unbound.requirements.size,
+                resource,
+            )),
+        });
+
+        Ok(())
+    }
+
+    unsafe fn create_buffer_view<R: RangeArg<u64>>(
+        &self,
+        buffer: &r::Buffer,
+        format: Option<format::Format>,
+        range: R,
+    ) -> Result<r::BufferView, buffer::ViewCreationError> {
+        let buffer = buffer.expect_bound();
+        let buffer_features = {
+            let idx = format.map(|fmt| fmt as usize).unwrap_or(0);
+            self.format_properties.get(idx).buffer_features
+        };
+        let (format, format_desc) = match format.and_then(conv::map_format) {
+            Some(fmt) => (fmt, format.unwrap().surface_desc()),
+            None => return Err(buffer::ViewCreationError::UnsupportedFormat { format }),
+        };
+
+        let start = *range.start().unwrap_or(&0);
+        let end = *range.end().unwrap_or(&(buffer.requirements.size as _));
+
+        let bytes_per_texel = (format_desc.bits / 8) as u64;
+        // Check if it adheres to the texel buffer offset limit
+        assert_eq!(start % bytes_per_texel, 0);
+        let first_element = start / bytes_per_texel;
+        let num_elements = (end - start) / bytes_per_texel; // rounds down to next smaller size
+
+        let handle_srv = if buffer_features.contains(format::BufferFeature::UNIFORM_TEXEL) {
+            let mut desc = d3d12::D3D12_SHADER_RESOURCE_VIEW_DESC {
+                Format: format,
+                ViewDimension: d3d12::D3D12_SRV_DIMENSION_BUFFER,
+                Shader4ComponentMapping: IDENTITY_MAPPING,
+                u: mem::zeroed(),
+            };
+
+            *desc.u.Buffer_mut() = d3d12::D3D12_BUFFER_SRV {
+                FirstElement: first_element,
+                NumElements: num_elements as _,
+                StructureByteStride: bytes_per_texel as _,
+                Flags: d3d12::D3D12_BUFFER_SRV_FLAG_NONE,
+            };
+
+            let handle = self.srv_uav_pool.lock().unwrap().alloc_handle();
+            self.raw
+                .clone()
+                .CreateShaderResourceView(buffer.resource.as_mut_ptr(), &desc, handle);
+            handle
+        } else {
+            d3d12::D3D12_CPU_DESCRIPTOR_HANDLE { ptr: 0 }
+        };
+
+        let handle_uav = if buffer_features.intersects(
+            format::BufferFeature::STORAGE_TEXEL | format::BufferFeature::STORAGE_TEXEL_ATOMIC,
+        ) {
+            let mut desc = d3d12::D3D12_UNORDERED_ACCESS_VIEW_DESC {
+                Format: format,
+                ViewDimension: d3d12::D3D12_UAV_DIMENSION_BUFFER,
+                u: mem::zeroed(),
+            };
+
+            *desc.u.Buffer_mut() = d3d12::D3D12_BUFFER_UAV {
+                FirstElement: first_element,
+                NumElements: num_elements as _,
+                StructureByteStride: bytes_per_texel as _,
+                Flags: d3d12::D3D12_BUFFER_UAV_FLAG_NONE,
+                CounterOffsetInBytes: 0,
+            };
+
+            let handle = self.srv_uav_pool.lock().unwrap().alloc_handle();
+            self.raw.clone().CreateUnorderedAccessView(
+                buffer.resource.as_mut_ptr(),
+                ptr::null_mut(),
+                &desc,
+                handle,
+            );
+            handle
+        } else {
+            d3d12::D3D12_CPU_DESCRIPTOR_HANDLE { ptr: 0 }
+        };
+
+        return Ok(r::BufferView {
+            resource: buffer.resource,
+            handle_srv,
+            handle_uav,
+        });
+    }
+
+    unsafe fn create_image(
+        &self,
+        kind: image::Kind,
+        mip_levels: image::Level,
+        format: format::Format,
+        tiling: image::Tiling,
+        usage: image::Usage,
+        view_caps: image::ViewCapabilities,
+    ) -> Result<r::Image, image::CreationError> {
+        if !is_valid_mip_count(&kind, mip_levels) {
+            error!("Invalid number of mip levels {} for {:?}", mip_levels, kind);
+            return Err(image::CreationError::Kind);
+        }
+        if format.is_planar() && !is_valid_planar_kind(&kind, mip_levels) {
+            error!(
+                "Invalid kind {:?} with {} mip levels for planar format {:?}",
+                kind, mip_levels, format
+            );
+            return Err(image::CreationError::Kind);
+        }
+
+        let base_format = format.base_format();
+        let format_desc = base_format.0.desc();
+        let bytes_per_block = (format_desc.bits / 8) as _;
+        let block_dim = format_desc.dim;
+        let extent = kind.extent();
+
+        let format_properties = self.format_properties.get(format as usize);
+        let (layout, features) = match tiling {
+            image::Tiling::Optimal => (
+                d3d12::D3D12_TEXTURE_LAYOUT_UNKNOWN,
+                format_properties.optimal_tiling,
+            ),
+            image::Tiling::Linear => (
+                d3d12::D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
+                format_properties.linear_tiling,
+            ),
+        };
+
+        let desc = d3d12::D3D12_RESOURCE_DESC {
+            Dimension: match kind {
+                image::Kind::D1(..) => d3d12::D3D12_RESOURCE_DIMENSION_TEXTURE1D,
+                image::Kind::D2(..) => d3d12::D3D12_RESOURCE_DIMENSION_TEXTURE2D,
+                image::Kind::D3(..) => d3d12::D3D12_RESOURCE_DIMENSION_TEXTURE3D,
+            },
+            Alignment: 0,
+            Width: extent.width as _,
+            Height: extent.height as _,
+            DepthOrArraySize: if extent.depth > 1 {
+                extent.depth as _
+            } else {
+                kind.num_layers() as _
+            },
+            MipLevels: mip_levels as _,
+            Format: match conv::map_surface_type(base_format.0) {
+                Some(format) => format,
+                None => return Err(image::CreationError::Format(format)),
+            },
+            SampleDesc: dxgitype::DXGI_SAMPLE_DESC {
+                Count: kind.num_samples() as _,
+                Quality: 0,
+            },
+            Layout: layout,
+            Flags: conv::map_image_flags(usage, features),
+        };
+
+        let alloc_info = self.raw.clone().GetResourceAllocationInfo(0, 1, &desc);
+
+        // Image usages which require RT/DS heap due to internal implementation.
+        let target_usage = image::Usage::COLOR_ATTACHMENT
+            | image::Usage::DEPTH_STENCIL_ATTACHMENT
+            | image::Usage::TRANSFER_DST;
+
+        let type_mask_shift = if self.private_caps.heterogeneous_resource_heaps {
+            MEM_TYPE_UNIVERSAL_SHIFT
+        } else if usage.intersects(target_usage) {
+            MEM_TYPE_TARGET_SHIFT
+        } else {
+            MEM_TYPE_IMAGE_SHIFT
+        };
+
+        Ok(r::Image::Unbound(r::ImageUnbound {
+            view_format: conv::map_format(format),
+            dsv_format: conv::map_format_dsv(base_format.0),
+            desc,
+            requirements: memory::Requirements {
+                size: alloc_info.SizeInBytes,
+                alignment: alloc_info.Alignment,
+                type_mask: MEM_TYPE_MASK << type_mask_shift,
+            },
+            format,
+            kind,
+            usage,
+            tiling,
+            view_caps,
+            bytes_per_block,
+            block_dim,
+            num_levels: mip_levels,
+        }))
+    }
+
+    unsafe fn get_image_requirements(&self, image: &r::Image) -> Requirements {
+        match image {
+            r::Image::Bound(i) => i.requirements,
+            r::Image::Unbound(i) => i.requirements,
+        }
+    }
+
+    unsafe fn get_image_subresource_footprint(
+        &self,
+        image: &r::Image,
+        sub: image::Subresource,
+    ) -> image::SubresourceFootprint {
+        let mut num_rows = 0;
+        let mut total_bytes = 0;
+        let desc = match image {
+            r::Image::Bound(i) => i.descriptor,
+            r::Image::Unbound(i) => i.desc,
+        };
+        let footprint = {
+            let mut footprint = mem::zeroed();
+            // Planes of planar images have their own footprint.
+            let plane = conv::map_plane_slice(sub.aspects);
+            self.raw.GetCopyableFootprints(
+                image.get_desc(),
+                image.calc_subresource(sub.level as _, sub.layer as _, plane as _),
+                1,
+                0,
+                &mut footprint,
+                &mut num_rows,
+                ptr::null_mut(), // row size in bytes
+                &mut total_bytes,
+            );
+            footprint
+        };
+
+        let depth_pitch = (footprint.Footprint.RowPitch * num_rows) as buffer::Offset;
+        let array_pitch = footprint.Footprint.Depth as buffer::Offset * depth_pitch;
+        image::SubresourceFootprint {
+            slice: footprint.Offset .. footprint.Offset + total_bytes,
+            row_pitch: footprint.Footprint.RowPitch as _,
+            depth_pitch,
+            array_pitch,
+        }
+    }
+
+    unsafe fn bind_image_memory(
+        &self,
+        memory: &r::Memory,
+        offset: u64,
+        image: &mut r::Image,
+    ) -> Result<(), d::BindError> {
+        let image_unbound = *image.expect_unbound();
+        if image_unbound.requirements.type_mask & (1 << memory.type_id) == 0 {
+            error!(
+                "Bind memory failure: supported mask 0x{:x}, given id {}",
+                image_unbound.requirements.type_mask, memory.type_id
+            );
+            return Err(d::BindError::WrongMemory);
+        }
+        if offset + image_unbound.requirements.size > memory.size {
+            return Err(d::BindError::OutOfBounds);
+        }
+
+        let resource =
+            self.create_bound_resource(memory, offset, &image_unbound.desc, INITIAL_IMAGE_STATE)?;
+        let place = if memory.dedicated.is_some() {
+            r::Place::Committed
+        } else {
+            r::Place::Heap {
+                raw: memory.heap.clone(),
+                offset,
+            }
+        };
+        let region =
+            r::MemoryRegion::new(memory, offset, image_unbound.requirements.size, resource);
+        self.bind_image_resource(image, resource, place, Some(region));
+
+        Ok(())
+    }
+
+    unsafe fn create_image_view(
+        &self,
+        image: &r::Image,
+        view_kind: image::ViewKind,
+        format: format::Format,
+        swizzle: format::Swizzle,
+        range: image::SubresourceRange,
+    ) -> Result<r::ImageView, image::ViewError> {
+        let image = image.expect_bound();
+        if !format.surface_desc().aspects.contains(range.aspects) {
+            error!(
+                "View aspects {:?} are not present in the format {:?}",
+                range.aspects, format
+            );
+            return Err(image::ViewError::BadFormat(format));
+        }
+        if format.is_planar()
+            && range.aspects != format::Aspects::PLANE_0
+            && range.aspects != format::Aspects::PLANE_1
+        {
+            error!("Views of planar images have to select a single plane, got {:?}", range.aspects);
+            return Err(image::ViewError::BadFormat(format));
+        }
+        let is_array = image.kind.num_layers() > 1;
+        let mip_levels = (range.levels.start, range.levels.end);
+        let layers = (range.layers.start, range.layers.end);
+
+        let info = ViewInfo {
+            resource: image.resource,
+            kind: image.kind,
+            caps: image.view_caps,
+            // D3D12 doesn't allow looking at a single slice of an array as a non-array
+            view_kind: if is_array && view_kind == image::ViewKind::D2 {
+                image::ViewKind::D2Array
+            } else if is_array && view_kind == image::ViewKind::D1 {
+                image::ViewKind::D1Array
+            } else {
+                view_kind
+            },
+            format: conv::map_format(format).ok_or(image::ViewError::BadFormat(format))?,
+            component_mapping: conv::map_swizzle(swizzle),
+            range,
+        };
+
+        Ok(r::ImageView {
+            resource: image.resource,
+            handle_srv: if image
+                .usage
+                .intersects(image::Usage::SAMPLED | image::Usage::INPUT_ATTACHMENT)
+            {
+                Some(self.view_image_as_shader_resource(info.clone())?)
+            } else {
+                None
+            },
+            handle_rtv: if image.usage.contains(image::Usage::COLOR_ATTACHMENT) {
+                Some(self.view_image_as_render_target(info.clone())?)
+            } else {
+                None
+            },
+            handle_uav: if image.usage.contains(image::Usage::STORAGE) {
+                Some(self.view_image_as_storage(info.clone())?)
+            } else {
+                None
+            },
+            handle_dsv: if image.usage.contains(image::Usage::DEPTH_STENCIL_ATTACHMENT) {
+                Some(
+                    self.view_image_as_depth_stencil(ViewInfo {
+                        format: conv::map_format_dsv(format.base_format().0)
+                            .ok_or(image::ViewError::BadFormat(format))?,
+                        ..info.clone()
+                    })?,
+                )
+            } else {
+                None
+            },
+            handle_dsv_read_only: if image.usage.contains(image::Usage::DEPTH_STENCIL_ATTACHMENT) {
+                Some(self.view_image_as_read_only_depth_stencil(
+                    ViewInfo {
+                        format: conv::map_format_dsv(format.base_format().0)
+                            .ok_or(image::ViewError::BadFormat(format))?,
+                        ..info
+                    },
+                    format.surface_desc().aspects,
+                )?)
+            } else {
+                None
+            },
+            dxgi_format: image.default_view_format.unwrap(),
+            resolve_format: match image.place {
+                // Back buffers are typed, sRGB views are resolved in the UNORM buffer format.
+                r::Place::SwapChain => image.descriptor.Format,
+                _ => image.default_view_format.unwrap(),
+            },
+            num_levels: image.descriptor.MipLevels as image::Level,
+            mip_levels,
+            layers,
+            kind: info.kind,
+        })
+    }
+
+    unsafe fn create_sampler(
+        &self,
+        info: image::SamplerInfo,
+    ) -> Result<r::Sampler, d::AllocationError> {
+        assert!(info.normalized);
+
+        if !is_anisotropy_enabled(info.anisotropic, self.features) {
+            error!("Anisotropic filtering is not enabled on this device");
+            return Err(d::OutOfMemory::OutOfHostMemory.into());
+        }
+        if !is_reduction_enabled(info.reduction, self.features) {
+            error!("Sampler reduction {:?} is not enabled on this device", info.reduction);
+            return Err(d::OutOfMemory::OutOfHostMemory.into());
+        }
+
+        let handle = self.sampler_cache.lock().unwrap().get_or_create(&info, || {
+            let handle = self.sampler_pool.lock().unwrap().alloc_handle();
+            self.raw.create_sampler(
+                handle,
+                conv::map_sampler_filter(&info),
+                [
+                    conv::map_wrap(info.wrap_mode.0),
+                    conv::map_wrap(info.wrap_mode.1),
+                    conv::map_wrap(info.wrap_mode.2),
+                ],
+                info.lod_bias.into(),
+                match info.anisotropic {
+                    image::Anisotropic::On(max) => max as _,
+                    image::Anisotropic::Off => 0,
+                },
+                conv::map_comparison(info.comparison.unwrap_or(pso::Comparison::Always)),
+                info.border.into(),
+                info.lod_range.start.into() .. info.lod_range.end.into(),
+            );
+            handle
+        });
+
+        Ok(r::Sampler { handle, info })
+    }
+
+    unsafe fn create_descriptor_pool<I>(
+        &self,
+        max_sets: usize,
+        descriptor_pools: I,
+        _flags: pso::DescriptorPoolCreateFlags,
+    ) -> Result<r::DescriptorPool, d::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::DescriptorRangeDesc>,
+    {
+        let mut num_srv_cbv_uav = 0;
+        let mut num_samplers = 0;
+
+        let descriptor_pools = descriptor_pools
+            .into_iter()
+            .map(|desc| *desc.borrow())
+            .collect::<Vec<_>>();
+
+        for desc in &descriptor_pools {
+            match desc.ty {
+                pso::DescriptorType::Sampler => {
+                    num_samplers += desc.count;
+                }
+                pso::DescriptorType::CombinedImageSampler => {
+                    num_samplers += desc.count;
+                    num_srv_cbv_uav += desc.count;
+                }
+                _ => {
+                    num_srv_cbv_uav += desc.count;
+                }
+            }
+        }
+
+        let heap_srv_cbv_uav = self.allocate_descriptor_heap_slice(
+            &self.heap_srv_cbv_uav,
+            descriptor::HeapType::CbvSrvUav,
+            num_srv_cbv_uav as _,
+            MAX_SHADER_VISIBLE_SRV_CBV_UAV_HEAP_SIZE,
+        )?;
+        // Sampler tables are allocated from the shared sampler heap once written.
+        if num_samplers > d3d12::D3D12_MAX_SHADER_VISIBLE_SAMPLER_HEAP_SIZE as usize {
+            error!(
+                "Descriptor pool requires {} samplers, exceeding the maximum of {} in a shader-visible heap",
+                num_samplers,
+                d3d12::D3D12_MAX_SHADER_VISIBLE_SAMPLER_HEAP_SIZE
+            );
+            return Err(d::OutOfMemory::OutOfDeviceMemory);
+        }
+
+        Ok(r::DescriptorPool {
+            heap_srv_cbv_uav,
+            sampler_tables: Arc::clone(&self.sampler_tables),
+            num_samplers: num_samplers as _,
+            allocated_samplers: 0,
+            pools: descriptor_pools,
+            max_size: max_sets as _,
+        })
+    }
+
+    unsafe fn create_descriptor_set_layout<I, J>(
+        &self,
+        bindings: I,
+        immutable_samplers: J,
+    ) -> Result<r::DescriptorSetLayout, d::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::DescriptorSetLayoutBinding>,
+        J: IntoIterator,
+        J::Item: Borrow<r::Sampler>,
+    {
+        let mut immutable_samplers = immutable_samplers.into_iter();
+        let mut layout_bindings = Vec::new();
+        let mut static_samplers = Vec::new();
+
+        // Immutable samplers are implemented as static samplers of the root signature.
+        // Static samplers only support a fixed set of border colors, bindings using
+        // others remain regular sampler bindings.
+        for binding in bindings {
+            let binding = binding.borrow();
+            if !binding.immutable_samplers {
+                layout_bindings.push(binding.clone());
+                continue;
+            }
+            let samplers = immutable_samplers
+                .by_ref()
+                .take(binding.count)
+                .map(|sampler| sampler.borrow().info.clone())
+                .collect::<Vec<_>>();
+            let is_static = binding.ty == pso::DescriptorType::Sampler
+                && samplers
+                    .iter()
+                    .all(|info| conv::map_static_border_color(info).is_some());
+            if is_static {
+                static_samplers.extend(
+                    samplers
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, info)| (binding.binding + i as u32, info)),
+                );
+            } else {
+                warn!(
+                    "Immutable samplers of binding {} have to be written to the sets",
+                    binding.binding
+                );
+                layout_bindings.push(binding.clone());
+            }
+        }
+
+        Ok(r::DescriptorSetLayout {
+            binding_flags: vec![r::DescriptorBindingFlags::empty(); layout_bindings.len()],
+            bindings: layout_bindings,
+            static_samplers,
+            variable_count: false,
+        })
+    }
+
+    unsafe fn write_descriptor_sets<'a, I, J>(&self, write_iter: I)
+    where
+        I: IntoIterator<Item = pso::DescriptorSetWrite<'a, B, J>>,
+        J: IntoIterator,
+        J::Item: Borrow<pso::Descriptor<'a, B>>,
+    {
+        let mut descriptor_update_pools = self.descriptor_update_pools.lock().unwrap();
+        let mut update_pool_index = 0;
+
+        // Descriptor sets own their slots in the shader-visible heaps for their
+        // whole lifetime, so a write only overwrites the affected descriptors in
+        // place, leaving the rest of the set untouched and without reallocating.
+        //
+        // Hazard: the GPU reads these descriptors at execution time, not at record
+        // time. Updating a set which is referenced by a submitted, not yet completed,
+        // command buffer is only valid with Vulkan `UPDATE_AFTER_BIND` semantics and
+        // as long as the slots aren't accessed by that command buffer concurrently.
+        //
+        // Sampler tables are switched to the deduplicated table of the written samplers
+        // once all writes are done.
+        let mut views =
+            DescriptorUpdates::new(self.heap_srv_cbv_uav.lock().unwrap().heap.handle_size as _);
+        let mut sampler_tables: Vec<&r::SamplerTable> = Vec::new();
+        let write_sampler = |set: &r::DescriptorSet,
+                             bind_info: &r::DescriptorBindingInfo,
+                             offset: u64,
+                             sampler: &r::Sampler| {
+            let table = set.sampler_table.as_ref().unwrap();
+            let index = bind_info.sampler_offset.unwrap() + offset;
+            table.write(self.raw, index, Some((sampler.info.clone(), sampler.handle)));
+        };
+        let track_buffer = |set: &r::DescriptorSet,
+                            binding: usize,
+                            offset: u64,
+                            resource: native::Resource,
+                            state: d3d12::D3D12_RESOURCE_STATES| {
+            if cfg!(debug_assertions) {
+                let mut buffer_states = set.buffer_states.lock().unwrap();
+                buffer_states.insert((binding, offset), (resource.as_mut_ptr(), state));
+            }
+        };
+        let shader_resource = d3d12::D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE
+            | d3d12::D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE;
+
+        for write in write_iter {
+            // Invalidate cached bindings of the set in command buffers.
+            write.set.generation.fetch_add(1, Ordering::AcqRel);
+            if let Some(ref table) = write.set.sampler_table {
+                if !sampler_tables.iter().any(|&other| other as *const _ == table as *const _) {
+                    sampler_tables.push(table);
+                }
+            }
+            let mut offset = write.array_offset as u64;
+            let mut target_binding = write.binding as usize;
+            let mut bind_info = &write.set.binding_infos[target_binding];
+            for descriptor in write.descriptors {
+                // spill over the writes onto the next binding
+                while offset >= bind_info.count {
+                    assert_eq!(offset, bind_info.count);
+                    target_binding += 1;
+                    bind_info = &write.set.binding_infos[target_binding];
+                    offset = 0;
+                }
+                match *descriptor.borrow() {
+                    pso::Descriptor::Buffer(buffer, ref range) => {
+                        let buffer = buffer.expect_bound();
+                        if update_pool_index == descriptor_update_pools.len() {
+                            let max_size = 1u64 << 12; //arbitrary
+                            descriptor_update_pools.push(descriptors_cpu::HeapLinear::new(
+                                self.raw,
+                                descriptor::HeapType::CbvSrvUav,
+                                max_size as _,
+                            ));
+                        }
+                        let heap = descriptor_update_pools.last_mut().unwrap();
+                        let handle = heap.alloc_handle();
+                        if heap.is_full() {
+                            // pool is full, move to the next one
+                            update_pool_index += 1;
+                        }
+                        let start = range.start.unwrap_or(0);
+                        let end = range.end.unwrap_or(buffer.requirements.size as _);
+
+                        let state = if bind_info.is_uav {
+                            d3d12::D3D12_RESOURCE_STATE_UNORDERED_ACCESS
+                        } else {
+                            d3d12::D3D12_RESOURCE_STATE_VERTEX_AND_CONSTANT_BUFFER
+                        };
+                        track_buffer(write.set, target_binding, offset, buffer.resource, state);
+
+                        if bind_info.is_uav {
+                            assert_eq!((end - start) % 4, 0);
+                            let mut desc = d3d12::D3D12_UNORDERED_ACCESS_VIEW_DESC {
+                                Format: dxgiformat::DXGI_FORMAT_R32_TYPELESS,
+                                ViewDimension: d3d12::D3D12_UAV_DIMENSION_BUFFER,
+                                u: mem::zeroed(),
+                            };
+                            *desc.u.Buffer_mut() = d3d12::D3D12_BUFFER_UAV {
+                                FirstElement: start as _,
+                                NumElements: ((end - start) / 4) as _,
+                                StructureByteStride: 0,
+                                CounterOffsetInBytes: 0,
+                                Flags: d3d12::D3D12_BUFFER_UAV_FLAG_RAW,
+                            };
+                            self.raw.CreateUnorderedAccessView(
+                                buffer.resource.as_mut_ptr(),
+                                ptr::null_mut(),
+                                &desc,
+                                handle,
+                            );
+                        } else {
+                            // Making the size field of buffer requirements for uniform
+                            // buffers a multiple of 256 and setting the required offset
+                            // alignment to 256 allows us to patch the size here.
+                            // We can always enforce the size to be aligned to 256 for
+                            // CBVs without going out-of-bounds.
+                            let size = ((end - start) + 255) & !255;
+                            let desc = d3d12::D3D12_CONSTANT_BUFFER_VIEW_DESC {
+                                BufferLocation: (*buffer.resource).GetGPUVirtualAddress() + start,
+                                SizeInBytes: size as _,
+                            };
+                            self.raw.CreateConstantBufferView(&desc, handle);
+                        }
+
+                        views.push(bind_info.view_range.as_ref().unwrap().at(offset), handle);
+                    }
+                    pso::Descriptor::Image(image, _layout) => {
+                        let handle = if bind_info.is_uav {
+                            image.handle_uav.unwrap()
+                        } else {
+                            image.handle_srv.unwrap()
+                        };
+                        views.push(bind_info.view_range.as_ref().unwrap().at(offset), handle);
+                    }
+                    pso::Descriptor::CombinedImageSampler(image, _layout, sampler) => {
+                        views.push(
+                            bind_info.view_range.as_ref().unwrap().at(offset),
+                            image.handle_srv.unwrap(),
+                        );
+                        write_sampler(write.set, bind_info, offset, sampler);
+                    }
+                    pso::Descriptor::Sampler(sampler) => {
+                        write_sampler(write.set, bind_info, offset, sampler);
+                    }
+                    pso::Descriptor::UniformTexelBuffer(buffer_view) => {
+                        let resource = buffer_view.resource;
+                        track_buffer(write.set, target_binding, offset, resource, shader_resource);
+                        let handle = buffer_view.handle_srv;
+                        if handle.ptr != 0 {
+                            views.push(bind_info.view_range.as_ref().unwrap().at(offset), handle);
+                        } else {
+                            error!("SRV handle of the uniform texel buffer is zero (not supported by specified format).");
                         }
-                        ai.last_state = state;
-                        ai.barrier_start_index = rp.subpasses.len() + 1;
                     }
-                    SubState::Resolve(state) => {
-                        // 1. Standard pre barrier to update state from previous pass into desired substate.
-                        if state != ai.last_state {
-                            let barrier = r::BarrierDesc::new(att_id, ai.last_state .. state);
-                            match rp.subpasses.get_mut(ai.barrier_start_index) {
-                                Some(past_subpass) => {
-                                    let split = barrier.split();
-                                    past_subpass.pre_barriers.push(split.start);
-                                    pre_barriers.push(split.end);
-                                }
-                                None => pre_barriers.push(barrier),
-                            }
+                    pso::Descriptor::StorageTexelBuffer(buffer_view) => {
+                        let resource = buffer_view.resource;
+                        let state = d3d12::D3D12_RESOURCE_STATE_UNORDERED_ACCESS;
+                        track_buffer(write.set, target_binding, offset, resource, state);
+                        let handle = buffer_view.handle_uav;
+                        if handle.ptr != 0 {
+                            views.push(bind_info.view_range.as_ref().unwrap().at(offset), handle);
+                        } else {
+                            error!("UAV handle of the storage texel buffer is zero (not supported by specified format).");
                         }
-
-                        // 2. Post Barrier at the end of the subpass into RESOLVE_SOURCE.
-                        let resolve_state = d3d12::D3D12_RESOURCE_STATE_RESOLVE_SOURCE;
-                        let barrier = r::BarrierDesc::new(att_id, state .. resolve_state);
-                        post_barriers.push(barrier);
-
-                        ai.last_state = resolve_state;
-                        ai.barrier_start_index = rp.subpasses.len() + 1;
                     }
-                    _ => {}
-                };
+                }
+                offset += 1;
             }
+        }
 
-            rp.subpasses.push(r::SubpassDesc {
-                color_attachments: subpasses[sid].borrow().colors.iter().cloned().collect(),
-                depth_stencil_attachment: subpasses[sid].borrow().depth_stencil.cloned(),
-                input_attachments: subpasses[sid].borrow().inputs.iter().cloned().collect(),
-                resolve_attachments: subpasses[sid].borrow().resolves.iter().cloned().collect(),
-                pre_barriers,
-                post_barriers,
-            });
+        views.flush(self.raw, d3d12::D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV);
+        for table in sampler_tables {
+            table.update(self.raw);
         }
-        // if this fails, our graph has cycles
-        assert_eq!(rp.subpasses.len(), subpasses.len());
-        assert!(deps_left.into_iter().all(|count| count == !0));
 
-        // take care of the post-pass transitions at the end of the renderpass.
-        for (att_id, (ai, att)) in att_infos.iter().zip(attachments.iter()).enumerate() {
-            let state_dst = conv::map_image_resource_state(image::Access::empty(), att.layouts.end);
-            if state_dst == ai.last_state {
-                continue;
+        // reset the temporary CPU-size descriptor pools
+        for buffer_desc_pool in descriptor_update_pools.iter_mut() {
+            buffer_desc_pool.clear();
+        }
+    }
+
+    unsafe fn copy_descriptor_sets<'a, I>(&self, copy_iter: I)
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::DescriptorSetCopy<'a, B>>,
+    {
+        let mut dst_views = Vec::new();
+        let mut src_views = Vec::new();
+        let mut num_views = Vec::new();
+        let mut sampler_tables: Vec<&r::SamplerTable> = Vec::new();
+
+        for copy_wrap in copy_iter {
+            let copy = copy_wrap.borrow();
+            copy.dst_set.generation.fetch_add(1, Ordering::AcqRel);
+            let src_info = &copy.src_set.binding_infos[copy.src_binding as usize];
+            let dst_info = &copy.dst_set.binding_infos[copy.dst_binding as usize];
+            if let (Some(src_range), Some(dst_range)) =
+                (src_info.view_range.as_ref(), dst_info.view_range.as_ref())
+            {
+                assert!(copy.src_array_offset + copy.count <= src_range.count as usize);
+                assert!(copy.dst_array_offset + copy.count <= dst_range.count as usize);
+                src_views.push(src_range.at(copy.src_array_offset as _));
+                dst_views.push(dst_range.at(copy.dst_array_offset as _));
+                num_views.push(copy.count as u32);
+
+                if cfg!(debug_assertions) {
+                    let src_states = copy.src_set.buffer_states.lock().unwrap().clone();
+                    let mut dst_states = copy.dst_set.buffer_states.lock().unwrap();
+                    for i in 0 .. copy.count as u64 {
+                        let src = (copy.src_binding as usize, copy.src_array_offset as u64 + i);
+                        let dst = (copy.dst_binding as usize, copy.dst_array_offset as u64 + i);
+                        match src_states.get(&src) {
+                            Some(&state) => dst_states.insert(dst, state),
+                            None => dst_states.remove(&dst),
+                        };
+                    }
+                }
             }
-            let barrier = r::BarrierDesc::new(att_id, ai.last_state .. state_dst);
-            match rp.subpasses.get_mut(ai.barrier_start_index) {
-                Some(past_subpass) => {
-                    let split = barrier.split();
-                    past_subpass.pre_barriers.push(split.start);
-                    rp.post_barriers.push(split.end);
+            if let (Some(src_offset), Some(dst_offset)) =
+                (src_info.sampler_offset, dst_info.sampler_offset)
+            {
+                assert!(copy.src_array_offset + copy.count <= src_info.count as usize);
+                assert!(copy.dst_array_offset + copy.count <= dst_info.count as usize);
+                let src_table = copy.src_set.sampler_table.as_ref().unwrap();
+                let dst_table = copy.dst_set.sampler_table.as_ref().unwrap();
+                for i in 0 .. copy.count as u64 {
+                    let sampler = src_table.get(src_offset + copy.src_array_offset as u64 + i);
+                    dst_table.write(
+                        self.raw,
+                        dst_offset + copy.dst_array_offset as u64 + i,
+                        sampler,
+                    );
+                }
+                if !sampler_tables.iter().any(|&other| other as *const _ == dst_table as *const _)
+                {
+                    sampler_tables.push(dst_table);
                 }
-                None => rp.post_barriers.push(barrier),
             }
         }
 
-        Ok(rp)
+        if !num_views.is_empty() {
+            self.raw.clone().CopyDescriptors(
+                dst_views.len() as u32,
+                dst_views.as_ptr(),
+                num_views.as_ptr(),
+                src_views.len() as u32,
+                src_views.as_ptr(),
+                num_views.as_ptr(),
+                d3d12::D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV,
+            );
+        }
+        for table in sampler_tables {
+            table.update(self.raw);
+        }
     }
 
-    unsafe fn create_pipeline_layout<IS, IR>(
-        &self,
-        sets: IS,
-        push_constant_ranges: IR,
-    ) -> Result<r::PipelineLayout, d::OutOfMemory>
+    unsafe fn map_memory<R>(&self, memory: &r::Memory, range: R) -> Result<*mut u8, mapping::Error>
     where
-        IS: IntoIterator,
-        IS::Item: Borrow<r::DescriptorSetLayout>,
-        IR: IntoIterator,
-        IR::Item: Borrow<(pso::ShaderStageFlags, Range<u32>)>,
+        R: RangeArg<u64>,
     {
-        // Pipeline layouts are implemented as RootSignature for D3D12.
-        //
-        // Each descriptor set layout will be one table entry of the root signature.
-        // We have the additional restriction that SRV/CBV/UAV and samplers need to be
-        // separated, so each set layout will actually occupy up to 2 entries!
-        //
-        // Root signature layout:
-        //     Root Constants: Register: Offest/4, Space: 0
-        //       ...
-        //     DescriptorTable0: Space: 2 (+1) (SrvCbvUav)
-        //     DescriptorTable0: Space: 3 (+1) (Sampler)
-        //     DescriptorTable1: Space: 4 (+1) (SrvCbvUav)
-        //     ...
+        if let Some(mem) = memory.resource {
+            let start = range.start().unwrap_or(&0);
+            let end = range.end().unwrap_or(&memory.size);
+            assert!(start <= end);
 
-        let sets = sets.into_iter().collect::<Vec<_>>();
-        let root_constants = root_constants::split(push_constant_ranges)
-            .iter()
-            .map(|constant| {
-                assert!(constant.range.start <= constant.range.end);
-                RootConstant {
-                    stages: constant.stages,
-                    range: constant.range.start .. constant.range.end,
-                }
-            })
-            .collect::<Vec<_>>();
+            let mut ptr = ptr::null_mut();
+            assert_eq!(
+                winerror::S_OK,
+                (*mem).Map(0, &d3d12::D3D12_RANGE { Begin: 0, End: 0 }, &mut ptr)
+            );
+            ptr = ptr.offset(*start as _);
+            Ok(ptr as *mut _)
+        } else {
+            panic!("Memory not created with a memory type exposing `CPU_VISIBLE`.")
+        }
+    }
 
-        // guarantees that no re-allocation is done, and our pointers are valid
-        let mut parameters = Vec::with_capacity(root_constants.len() + sets.len() * 2);
+    unsafe fn unmap_memory(&self, memory: &r::Memory) {
+        if let Some(mem) = memory.resource {
+            (*mem).Unmap(0, &d3d12::D3D12_RANGE { Begin: 0, End: 0 });
+        }
+    }
 
-        for root_constant in root_constants.iter() {
-            parameters.push(native::descriptor::RootParameter::constants(
-                native::descriptor::ShaderVisibility::All, // TODO
-                native::descriptor::Binding {
-                    register: root_constant.range.start as _,
-                    space: ROOT_CONSTANT_SPACE,
-                },
-                (root_constant.range.end - root_constant.range.start) as _,
-            ));
+    unsafe fn flush_mapped_memory_ranges<'a, I, R>(&self, ranges: I) -> Result<(), d::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<(&'a r::Memory, R)>,
+        R: RangeArg<u64>,
+    {
+        // All host visible memory types are coherent, writes are visible to the GPU
+        // on submission without an explicit flush.
+        for range in ranges {
+            let &(ref memory, ref range) = range.borrow();
+            validate_mapped_range(memory, range);
         }
 
-        // Offest of `spaceN` for descriptor tables. Root constants will be in
-        // `space0`.
-        let table_space_offset = if !root_constants.is_empty() { 1 } else { 0 };
+        Ok(())
+    }
 
-        // Collect the whole number of bindings we will create upfront.
-        // It allows us to preallocate enough storage to avoid reallocation,
-        // which could cause invalid pointers.
-        let total = sets
-            .iter()
-            .map(|desc_set| {
-                let mut sum = 0;
-                let bindings = &desc_set.borrow().bindings;
+    unsafe fn invalidate_mapped_memory_ranges<'a, I, R>(
+        &self,
+        ranges: I,
+    ) -> Result<(), d::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<(&'a r::Memory, R)>,
+        R: RangeArg<u64>,
+    {
+        // READBACK heaps are cached but coherent, GPU writes are visible once the
+        // host waited for the submission without an explicit invalidation.
+        for range in ranges {
+            let &(ref memory, ref range) = range.borrow();
+            validate_mapped_range(memory, range);
+        }
 
-                for binding in bindings {
-                    sum += if binding.ty == pso::DescriptorType::CombinedImageSampler {
-                        2
-                    } else {
-                        1
-                    };
-                }
+        Ok(())
+    }
 
-                sum
-            })
-            .sum();
-        let mut ranges = Vec::with_capacity(total);
-        let mut set_tables = Vec::with_capacity(sets.len());
+    fn create_semaphore(&self) -> Result<r::Semaphore, d::OutOfMemory> {
+        let fence = self.create_fence(false)?;
+        Ok(r::Semaphore {
+            raw: fence.raw,
+            value: AtomicUsize::new(0),
+        })
+    }
 
-        for (i, set) in sets.iter().enumerate() {
-            let set = set.borrow();
-            let mut table_type = r::SetTableTypes::empty();
+    fn create_fence(&self, signalled: bool) -> Result<r::Fence, d::OutOfMemory> {
+        Ok(r::Fence {
+            raw: self.create_raw_fence(signalled),
+            target: Cell::new(1),
+        })
+    }
 
-            let range_base = ranges.len();
-            ranges.extend(
-                set.bindings
-                    .iter()
-                    .filter(|bind| bind.ty != pso::DescriptorType::Sampler)
-                    .map(|bind| {
-                        conv::map_descriptor_range(bind, (table_space_offset + i) as u32, false)
-                    }),
+    unsafe fn reset_fences<I>(&self, fences: I) -> Result<(), d::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<r::Fence>,
+    {
+        for fence in fences {
+            fence.borrow().reset();
+        }
+        Ok(())
+    }
+
+    unsafe fn wait_for_fences<I>(
+        &self,
+        fences: I,
+        wait: d::WaitFor,
+        timeout_ns: u64,
+    ) -> Result<bool, d::OomOrDeviceLost>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<r::Fence>,
+    {
+        let fences = fences.into_iter().collect::<Vec<_>>();
+        let is_signalled = |fence: &I::Item| fence.borrow().is_signalled().unwrap_or(true);
+        let done = match wait {
+            d::WaitFor::Any => fences.iter().any(is_signalled),
+            d::WaitFor::All => fences.iter().all(is_signalled),
+        };
+        if done || fences.is_empty() {
+            return Ok(true);
+        }
+        if timeout_ns == 0 {
+            return Ok(false);
+        }
+
+        let max_events = winnt::MAXIMUM_WAIT_OBJECTS as usize;
+        if fences.len() > max_events && wait == d::WaitFor::Any {
+            warn!(
+                "Waiting for any of {} fences, only the first {} are considered",
+                fences.len(),
+                max_events
             );
+        }
+
+        // Events are pooled across waits, each wait uses at most `MAXIMUM_WAIT_OBJECTS`.
+        let mut events = self.events.lock().unwrap();
+        for _ in events.len() .. fences.len().min(max_events) {
+            events.push(native::Event::create(false, false));
+        }
 
-            if ranges.len() > range_base {
-                parameters.push(native::descriptor::RootParameter::descriptor_table(
-                    native::descriptor::ShaderVisibility::All, // TODO
-                    &ranges[range_base ..],
-                ));
-                table_type |= r::SRV_CBV_UAV;
+        let timeout_ms = wait_timeout_ms(timeout_ns);
+        for chunk in fences.chunks(max_events) {
+            for (&event, fence) in events.iter().zip(chunk.iter()) {
+                synchapi::ResetEvent(event.0);
+                assert_eq!(
+                    winerror::S_OK,
+                    fence
+                        .borrow()
+                        .raw
+                        .set_event_on_completion(event, fence.borrow().target.get())
+                );
             }
 
-            let range_base = ranges.len();
-            ranges.extend(
-                set.bindings
-                    .iter()
-                    .filter(|bind| {
-                        bind.ty == pso::DescriptorType::Sampler
-                            || bind.ty == pso::DescriptorType::CombinedImageSampler
-                    })
-                    .map(|bind| {
-                        conv::map_descriptor_range(bind, (table_space_offset + i) as u32, true)
-                    }),
+            let all = match wait {
+                d::WaitFor::Any => FALSE,
+                d::WaitFor::All => TRUE,
+            };
+            let hr = synchapi::WaitForMultipleObjects(
+                chunk.len() as u32,
+                events.as_ptr() as *const _,
+                all,
+                timeout_ms,
             );
 
-            if ranges.len() > range_base {
-                parameters.push(native::descriptor::RootParameter::descriptor_table(
-                    native::descriptor::ShaderVisibility::All, // TODO
-                    &ranges[range_base ..],
-                ));
-                table_type |= r::SAMPLERS;
+            const WAIT_OBJECT_LAST: u32 =
+                winbase::WAIT_OBJECT_0 + winnt::MAXIMUM_WAIT_OBJECTS - 1;
+            const WAIT_ABANDONED_LAST: u32 =
+                winbase::WAIT_ABANDONED_0 + winnt::MAXIMUM_WAIT_OBJECTS - 1;
+            match hr {
+                winbase::WAIT_OBJECT_0 ... WAIT_OBJECT_LAST => {}
+                winbase::WAIT_ABANDONED_0 ... WAIT_ABANDONED_LAST => {} //TODO?
+                winerror::WAIT_TIMEOUT => return Ok(false),
+                _ => {
+                    error!("Unexpected wait status 0x{:X}", hr);
+                    return Err(d::OomOrDeviceLost::DeviceLost(d::DeviceLost));
+                }
+            }
+            // Waiting for a single fence is enough.
+            if wait == d::WaitFor::Any {
+                break;
             }
-
-            set_tables.push(table_type);
         }
 
-        // Ensure that we didn't reallocate!
-        debug_assert_eq!(ranges.len(), total);
+        Ok(true)
+    }
 
-        // TODO: error handling
-        let ((signature_raw, error), _hr) = native::RootSignature::serialize(
-            native::descriptor::RootSignatureVersion::V1_0,
-            &parameters,
-            &[],
-            native::descriptor::RootSignatureFlags::ALLOW_IA_INPUT_LAYOUT,
-        );
+    unsafe fn get_fence_status(&self, fence: &r::Fence) -> Result<bool, d::DeviceLost> {
+        fence.is_signalled()
+    }
 
-        if !error.is_null() {
-            error!(
-                "Root signature serialization error: {:?}",
-                error.as_c_str().to_str().unwrap()
-            );
-            error.destroy();
-        }
+    fn create_event(&self) -> Result<r::Event, d::OutOfMemory> {
+        Ok(r::Event {
+            raw: self.create_raw_fence(false),
+        })
+    }
 
-        // TODO: error handling
-        let (signature, _hr) = self.raw.create_root_signature(signature_raw, 0);
-        signature_raw.destroy();
+    unsafe fn get_event_status(&self, event: &r::Event) -> Result<bool, d::OomOrDeviceLost> {
+        match event.raw.GetCompletedValue() {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(d::OomOrDeviceLost::DeviceLost(d::DeviceLost)),
+        }
+    }
 
-        Ok(r::PipelineLayout {
-            raw: signature,
-            tables: set_tables,
-            root_constants,
-            num_parameter_slots: parameters.len(),
-        })
+    unsafe fn set_event(&self, event: &r::Event) -> Result<(), d::OutOfMemory> {
+        // Signaling a fence from the host is thread-safe.
+        assert_eq!(winerror::S_OK, event.raw.signal(1));
+        Ok(())
     }
 
-    unsafe fn create_pipeline_cache(&self, _data: Option<&[u8]>) -> Result<(), d::OutOfMemory> {
+    unsafe fn reset_event(&self, event: &r::Event) -> Result<(), d::OutOfMemory> {
+        assert_eq!(winerror::S_OK, event.raw.signal(0));
         Ok(())
     }
 
-    unsafe fn get_pipeline_cache_data(&self, cache: &()) -> Result<Vec<u8>, d::OutOfMemory> {
-        //empty
-        Ok(Vec::new())
+    unsafe fn free_memory(&self, memory: r::Memory) {
+        if !memory.heap.is_null() {
+            memory.heap.destroy();
+        }
+        if let Some(buffer) = memory.resource {
+            buffer.destroy();
+        }
     }
 
-    unsafe fn destroy_pipeline_cache(&self, _: ()) {
-        //empty
+    unsafe fn create_query_pool(
+        &self,
+        query_ty: query::Type,
+        count: query::Id,
+    ) -> Result<r::QueryPool, query::CreationError> {
+        let (heap_ty, statistics) = match query_ty {
+            query::Type::Occlusion => (
+                native::query::HeapType::Occlusion,
+                query::PipelineStatistic::empty(),
+            ),
+            query::Type::PipelineStatistics(statistics) => {
+                (native::query::HeapType::PipelineStatistics, statistics)
+            }
+            query::Type::Timestamp => (
+                native::query::HeapType::Timestamp,
+                query::PipelineStatistic::empty(),
+            ),
+        };
+
+        let (query_heap, hr) = self.raw.create_query_heap(heap_ty, count, 0);
+        assert_eq!(winerror::S_OK, hr);
+
+        let mut pool = r::QueryPool {
+            raw: query_heap,
+            ty: heap_ty,
+            statistics,
+            count,
+            readback: native::Resource::null(),
+            availability: native::Resource::null(),
+            completion: Arc::new(r::QueryCompletion::new(self.create_raw_fence(false), count)),
+        };
+
+        // Query results and their availability are resolved into the readback buffer
+        // when the queries end.
+        pool.readback = self.create_committed_buffer(
+            d3d12::D3D12_HEAP_TYPE_READBACK,
+            pool.readback_size(),
+            d3d12::D3D12_RESOURCE_STATE_COPY_DEST,
+        );
+
+        // Availability values copied into the readback buffer: `1` followed by `0` for each query.
+        pool.availability = self.create_committed_buffer(
+            d3d12::D3D12_HEAP_TYPE_UPLOAD,
+            (count as u64 + 1) * r::QUERY_AVAILABILITY_SIZE,
+            d3d12::D3D12_RESOURCE_STATE_GENERIC_READ,
+        );
+        let mut mapped = ptr::null_mut();
+        assert_eq!(
+            winerror::S_OK,
+            pool.availability.Map(0, &d3d12::D3D12_RANGE { Begin: 0, End: 0 }, &mut mapped)
+        );
+        let values = slice::from_raw_parts_mut(mapped as *mut u32, count as usize + 1);
+        for value in values.iter_mut() {
+            *value = 0;
+        }
+        values[0] = 1;
+        pool.availability.Unmap(0, ptr::null());
+
+        Ok(pool)
     }
 
-    unsafe fn merge_pipeline_caches<I>(&self, _: &(), _: I) -> Result<(), d::OutOfMemory>
-    where
-        I: IntoIterator,
-        I::Item: Borrow<()>,
-    {
-        //empty
-        Ok(())
+    unsafe fn destroy_query_pool(&self, pool: r::QueryPool) {
+        pool.raw.destroy();
+        pool.readback.destroy();
+        pool.availability.destroy();
     }
 
-    unsafe fn create_graphics_pipeline<'a>(
+    unsafe fn get_query_pool_results(
         &self,
-        desc: &pso::GraphicsPipelineDesc<'a, B>,
-        _cache: Option<&()>,
-    ) -> Result<r::GraphicsPipeline, pso::CreationError> {
-        enum ShaderBc {
-            Owned(native::Blob),
-            Borrowed(native::Blob),
-            None,
-        }
-        impl ShaderBc {
-            pub fn shader(&self) -> native::Shader {
-                match *self {
-                    ShaderBc::Owned(ref bc) | ShaderBc::Borrowed(ref bc) => {
-                        native::Shader::from_blob(*bc)
+        pool: &r::QueryPool,
+        queries: Range<query::Id>,
+        data: &mut [u8],
+        stride: buffer::Offset,
+        flags: query::ResultFlags,
+    ) -> Result<bool, d::OomOrDeviceLost> {
+        let num_queries = (queries.end - queries.start) as usize;
+        let range = d3d12::D3D12_RANGE {
+            Begin: 0,
+            End: pool.readback_size() as _,
+        };
+        let mut values = Vec::new();
+        let mut waited = false;
+
+        loop {
+            let mut mapped = ptr::null_mut();
+            let hr = pool.readback.Map(0, &range, &mut mapped);
+            if !winerror::SUCCEEDED(hr) {
+                error!("error on mapping query results: {:x}", hr);
+                return Err(d::OomOrDeviceLost::DeviceLost(d::DeviceLost));
+            }
+            let base = mapped as *const u8;
+
+            let availability = slice::from_raw_parts(
+                base.offset(pool.availability_offset(queries.start) as isize) as *const u32,
+                num_queries,
+            );
+            let all_available = availability.iter().all(|&available| available != 0);
+            if !all_available && flags.contains(query::ResultFlags::WAIT) && !waited {
+                // Availability is written by the GPU once the query has been resolved,
+                // wait for the last submissions resolving the unavailable queries.
+                let value = availability
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, &available)| available == 0)
+                    .map(|(i, _)| pool.completion.query_value(queries.start + i as query::Id))
+                    .max()
+                    .unwrap_or(0);
+                pool.readback.Unmap(0, &d3d12::D3D12_RANGE { Begin: 0, End: 0 });
+                self.wait_for_query_completion(pool, value)?;
+                waited = true;
+                continue;
+            }
+
+            for (i, &available) in availability.iter().enumerate() {
+                let result = base.offset(pool.result_offset(queries.start + i as query::Id) as isize)
+                    as *const u64;
+                values.clear();
+                match pool.ty {
+                    native::query::HeapType::PipelineStatistics => {
+                        let stats = slice::from_raw_parts(result, NUM_PIPELINE_STATISTICS);
+                        values.extend(map_pipeline_statistics(pool.statistics, stats));
+                    }
+                    _ => values.push(*result),
+                }
+
+                let dst = data[i * stride as usize ..].as_mut_ptr();
+                let bits_64 = flags.contains(query::ResultFlags::BITS_64);
+                if available != 0 || flags.contains(query::ResultFlags::PARTIAL) {
+                    for (j, &value) in values.iter().enumerate() {
+                        write_query_value(dst, j, value, bits_64);
                     }
-                    ShaderBc::None => native::Shader::null(),
+                }
+                if flags.contains(query::ResultFlags::WITH_AVAILABILITY) {
+                    write_query_value(dst, values.len(), (available != 0) as u64, bits_64);
                 }
             }
-        }
 
-        let build_shader = |stage: pso::Stage, source: Option<&pso::EntryPoint<'a, B>>| {
-            let source = match source {
-                Some(src) => src,
-                None => return Ok(ShaderBc::None),
-            };
+            pool.readback.Unmap(0, &d3d12::D3D12_RANGE { Begin: 0, End: 0 });
+            return Ok(all_available);
+        }
+    }
 
-            match Self::extract_entry_point(stage, source, desc.layout) {
-                Ok((shader, true)) => Ok(ShaderBc::Owned(shader)),
-                Ok((shader, false)) => Ok(ShaderBc::Borrowed(shader)),
-                Err(err) => Err(pso::CreationError::Shader(err)),
+    unsafe fn destroy_shader_module(&self, shader_lib: r::ShaderModule) {
+        match shader_lib {
+            r::ShaderModule::Compiled(shaders) => {
+                for (_, blob) in shaders {
+                    blob.destroy();
+                }
             }
-        };
+            r::ShaderModule::Spirv { translations, .. } => {
+                for (_, blob) in translations.into_inner().unwrap() {
+                    blob.destroy();
+                }
+            }
+        }
+    }
 
-        let vs = build_shader(pso::Stage::Vertex, Some(&desc.shaders.vertex))?;
-        let ps = build_shader(pso::Stage::Fragment, desc.shaders.fragment.as_ref())?;
-        let gs = build_shader(pso::Stage::Geometry, desc.shaders.geometry.as_ref())?;
-        let ds = build_shader(pso::Stage::Domain, desc.shaders.domain.as_ref())?;
-        let hs = build_shader(pso::Stage::Hull, desc.shaders.hull.as_ref())?;
+    unsafe fn destroy_render_pass(&self, _rp: r::RenderPass) {
+        // Just drop
+    }
 
-        // Rebind vertex buffers, see native.rs for more details.
-        let mut vertex_bindings = [None; MAX_VERTEX_BUFFERS];
-        let mut vertex_strides = [0; MAX_VERTEX_BUFFERS];
+    unsafe fn destroy_pipeline_layout(&self, layout: r::PipelineLayout) {
+        layout.raw.destroy();
+    }
 
-        for buffer in &desc.vertex_buffers {
-            vertex_strides[buffer.binding as usize] = buffer.stride;
+    unsafe fn destroy_graphics_pipeline(&self, pipeline: r::GraphicsPipeline) {
+        pipeline.raw.destroy();
+    }
+
+    unsafe fn destroy_compute_pipeline(&self, pipeline: r::ComputePipeline) {
+        pipeline.raw.destroy();
+    }
+
+    unsafe fn destroy_framebuffer(&self, _fb: r::Framebuffer) {
+        // Just drop
+    }
+
+    unsafe fn destroy_buffer(&self, buffer: r::Buffer) {
+        match buffer {
+            r::Buffer::Bound(buffer) => {
+                if let Some(ref region) = buffer.region {
+                    region.release(buffer.resource);
+                }
+                buffer.resource.destroy();
+            }
+            r::Buffer::Unbound(_) => {}
         }
-        // Fill in identity mapping where we don't need to adjust anything.
-        for attrib in &desc.attributes {
-            let binding = attrib.binding as usize;
-            let stride = vertex_strides[attrib.binding as usize];
-            if attrib.element.offset < stride {
-                vertex_bindings[binding] = Some(r::VertexBinding {
-                    stride: vertex_strides[attrib.binding as usize],
-                    offset: 0,
-                    mapped_binding: binding,
-                });
+    }
+
+    unsafe fn destroy_buffer_view(&self, _view: r::BufferView) {
+        // empty
+    }
+
+    unsafe fn destroy_image(&self, image: r::Image) {
+        match image {
+            r::Image::Bound(image) => {
+                if let Some(ref region) = image.region {
+                    region.release(image.resource);
+                }
+                image.resource.destroy();
             }
+            r::Image::Unbound(_) => {}
         }
+    }
 
-        // Define input element descriptions
-        let input_element_descs = desc
-            .attributes
-            .iter()
-            .filter_map(|attrib| {
-                let buffer_desc = match desc
-                    .vertex_buffers
-                    .iter()
-                    .find(|buffer_desc| buffer_desc.binding == attrib.binding)
-                {
-                    Some(buffer_desc) => buffer_desc,
-                    None => {
-                        error!(
-                            "Couldn't find associated vertex buffer description {:?}",
-                            attrib.binding
-                        );
-                        return Some(Err(pso::CreationError::Other));
-                    }
-                };
+    unsafe fn destroy_image_view(&self, _view: r::ImageView) {
+        // Just drop
+    }
 
-                let (slot_class, step_rate) = match buffer_desc.rate {
-                    VertexInputRate::Vertex => {
-                        (d3d12::D3D12_INPUT_CLASSIFICATION_PER_VERTEX_DATA, 0)
-                    }
-                    VertexInputRate::Instance(divisor) => {
-                        (d3d12::D3D12_INPUT_CLASSIFICATION_PER_INSTANCE_DATA, divisor)
-                    }
-                };
-                let format = attrib.element.format;
+    unsafe fn destroy_sampler(&self, sampler: r::Sampler) {
+        if let Some(handle) = self.sampler_cache.lock().unwrap().release(&sampler.info) {
+            self.sampler_pool.lock().unwrap().free_handle(handle);
+        }
+    }
 
-                // Check if we need to add a new remapping in-case the offset is
-                // higher than the vertex stride.
-                // In this case we rebase the attribute to zero offset.
-                let binding = attrib.binding as usize;
-                let stride = vertex_strides[binding];
-                let offset = attrib.element.offset;
-                let (input_slot, offset) = if stride <= offset {
-                    // Number of input attributes may not exceed bindings, see limits.
-                    // We will always find at least one free binding.
-                    let mapping = vertex_bindings.iter().position(Option::is_none).unwrap();
-                    vertex_bindings[mapping] = Some(r::VertexBinding {
-                        stride: vertex_strides[binding],
-                        offset: offset,
-                        mapped_binding: binding,
-                    });
+    unsafe fn destroy_descriptor_pool(&self, _pool: r::DescriptorPool) {
+        // Just drop
+        // Allocated descriptor sets don't need to be freed beforehand.
+    }
+
+    unsafe fn destroy_descriptor_set_layout(&self, _layout: r::DescriptorSetLayout) {
+        // Just drop
+    }
 
-                    (mapping, 0)
-                } else {
-                    (binding, offset)
-                };
+    unsafe fn destroy_fence(&self, fence: r::Fence) {
+        fence.raw.destroy();
+    }
 
-                Some(Ok(d3d12::D3D12_INPUT_ELEMENT_DESC {
-                    SemanticName: "TEXCOORD\0".as_ptr() as *const _, // Semantic name used by SPIRV-Cross
-                    SemanticIndex: attrib.location,
-                    Format: match conv::map_format(format) {
-                        Some(fm) => fm,
-                        None => {
-                            error!("Unable to find DXGI format for {:?}", format);
-                            return Some(Err(pso::CreationError::Other));
-                        }
-                    },
-                    InputSlot: input_slot as _,
-                    AlignedByteOffset: offset,
-                    InputSlotClass: slot_class,
-                    InstanceDataStepRate: step_rate as _,
-                }))
-            })
-            .collect::<Result<Vec<_>, _>>()?;
+    unsafe fn destroy_semaphore(&self, semaphore: r::Semaphore) {
+        semaphore.raw.destroy();
+    }
 
-        // TODO: check maximum number of rtvs
-        // Get associated subpass information
-        let pass = {
-            let subpass = &desc.subpass;
-            match subpass.main_pass.subpasses.get(subpass.index) {
-                Some(subpass) => subpass,
-                None => return Err(pso::CreationError::InvalidSubpass(subpass.index)),
+    unsafe fn destroy_event(&self, event: r::Event) {
+        event.raw.destroy();
+    }
+
+    unsafe fn create_swapchain(
+        &self,
+        surface: &mut w::Surface,
+        config: hal::SwapchainConfig,
+        old_swapchain: Option<w::Swapchain>,
+    ) -> Result<(w::Swapchain, Vec<r::Image>), hal::window::CreationError> {
+        let present_queue = match self.present_queue {
+            Some(ref queue) => queue.clone(),
+            None => {
+                error!("Swapchain creation requires a device opened with the `Present` queue family");
+                return Err(hal::window::CreationError::NoPresentQueue);
             }
         };
 
-        // Get color attachment formats from subpass
-        let (rtvs, num_rtvs) = {
-            let mut rtvs = [dxgiformat::DXGI_FORMAT_UNKNOWN; 8];
-            let mut num_rtvs = 0;
-            for (rtv, target) in rtvs.iter_mut().zip(pass.color_attachments.iter()) {
-                let format = desc.subpass.main_pass.attachments[target.0].format;
-                *rtv = format
-                    .and_then(conv::map_format)
-                    .unwrap_or(dxgiformat::DXGI_FORMAT_UNKNOWN);
-                num_rtvs += 1;
-            }
-            (rtvs, num_rtvs)
-        };
+        if let Some(old_swapchain) = old_swapchain {
+            self.destroy_swapchain(old_swapchain);
+        }
+        validate_swapchain_image_count(config.image_count)?;
 
-        let sample_desc = dxgitype::DXGI_SAMPLE_DESC {
-            Count: match desc.multisampling {
-                Some(ref ms) => ms.rasterization_samples as _,
-                None => 1,
-            },
-            Quality: 0,
-        };
+        let mut swap_chain1 = native::WeakPtr::<dxgi1_2::IDXGISwapChain1>::null();
 
-        // Setup pipeline description
-        let pso_desc = d3d12::D3D12_GRAPHICS_PIPELINE_STATE_DESC {
-            pRootSignature: desc.layout.raw.as_mut_ptr(),
-            VS: *vs.shader(),
-            PS: *ps.shader(),
-            GS: *gs.shader(),
-            DS: *ds.shader(),
-            HS: *hs.shader(),
-            StreamOutput: d3d12::D3D12_STREAM_OUTPUT_DESC {
-                pSODeclaration: ptr::null(),
-                NumEntries: 0,
-                pBufferStrides: ptr::null(),
-                NumStrides: 0,
-                RasterizedStream: 0,
-            },
-            BlendState: d3d12::D3D12_BLEND_DESC {
-                AlphaToCoverageEnable: desc.multisampling.as_ref().map_or(FALSE, |ms| {
-                    if ms.alpha_coverage {
-                        TRUE
-                    } else {
-                        FALSE
-                    }
-                }),
-                IndependentBlendEnable: TRUE,
-                RenderTarget: conv::map_render_targets(&desc.blender.targets),
-            },
-            SampleMask: UINT::max_value(),
-            RasterizerState: conv::map_rasterizer(&desc.rasterizer),
-            DepthStencilState: conv::map_depth_stencil(&desc.depth_stencil),
-            InputLayout: d3d12::D3D12_INPUT_LAYOUT_DESC {
-                pInputElementDescs: if input_element_descs.is_empty() {
-                    ptr::null()
-                } else {
-                    input_element_descs.as_ptr()
-                },
-                NumElements: input_element_descs.len() as u32,
-            },
-            IBStripCutValue: d3d12::D3D12_INDEX_BUFFER_STRIP_CUT_VALUE_DISABLED, // TODO
-            PrimitiveTopologyType: conv::map_topology_type(desc.input_assembler.primitive),
-            NumRenderTargets: num_rtvs,
-            RTVFormats: rtvs,
-            DSVFormat: pass
-                .depth_stencil_attachment
-                .and_then(|att_ref| {
-                    desc.subpass.main_pass.attachments[att_ref.0]
-                        .format
-                        .and_then(|f| conv::map_format_dsv(f.base_format().0))
-                })
-                .unwrap_or(dxgiformat::DXGI_FORMAT_UNKNOWN),
-            SampleDesc: sample_desc,
-            NodeMask: 0,
-            CachedPSO: d3d12::D3D12_CACHED_PIPELINE_STATE {
-                pCachedBlob: ptr::null(),
-                CachedBlobSizeInBytes: 0,
-            },
-            Flags: d3d12::D3D12_PIPELINE_STATE_FLAG_NONE,
-        };
+        let format = swapchain_buffer_format(config.format);
 
-        let topology = conv::map_topology(desc.input_assembler.primitive);
+        let rtv_heap = Device::create_descriptor_heap_impl(
+            self.raw,
+            descriptor::HeapType::Rtv,
+            false,
+            config.image_count as _,
+        );
 
-        // Create PSO
-        let mut pipeline = native::PipelineState::null();
-        let hr = if desc.depth_stencil.depth_bounds {
-            // The DepthBoundsTestEnable option isn't available in the original D3D12_GRAPHICS_PIPELINE_STATE_DESC struct.
-            // Instead, we must use the newer subobject stream method.
-            let (device2, hr) = self.raw.cast::<d3d12::ID3D12Device2>();
-            if winerror::SUCCEEDED(hr) {
-                let mut pss_stream = GraphicsPipelineStateSubobjectStream::new(&pso_desc, true);
-                let pss_desc = d3d12::D3D12_PIPELINE_STATE_STREAM_DESC {
-                    SizeInBytes: mem::size_of_val(&pss_stream),
-                    pPipelineStateSubobjectStream: &mut pss_stream as *mut _ as _,
-                };
-                device2.CreatePipelineState(
-                    &pss_desc,
-                    &d3d12::ID3D12PipelineState::uuidof(),
-                    pipeline.mut_void(),
-                )
-            } else {
-                hr
+        let allow_tearing = match config.present_mode {
+            hal::PresentMode::Fifo => false,
+            hal::PresentMode::Immediate if surface.supports_tearing() => true,
+            mode => {
+                warn!("Unsupported present mode {:?}, falling back to Fifo", mode);
+                false
             }
-        } else {
-            self.raw.clone().CreateGraphicsPipelineState(
-                &pso_desc,
-                &d3d12::ID3D12PipelineState::uuidof(),
-                pipeline.mut_void(),
-            )
         };
 
-        let destroy_shader = |shader: ShaderBc| {
-            if let ShaderBc::Owned(bc) = shader {
-                bc.destroy();
-            }
+        // TODO: double-check values
+        let desc = dxgi1_2::DXGI_SWAP_CHAIN_DESC1 {
+            AlphaMode: dxgi1_2::DXGI_ALPHA_MODE_IGNORE,
+            BufferCount: config.image_count,
+            Width: config.extent.width,
+            Height: config.extent.height,
+            Format: format,
+            Flags: if allow_tearing {
+                dxgi::DXGI_SWAP_CHAIN_FLAG_ALLOW_TEARING as _
+            } else {
+                0
+            },
+            BufferUsage: dxgitype::DXGI_USAGE_RENDER_TARGET_OUTPUT,
+            SampleDesc: dxgitype::DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            Scaling: dxgi1_2::DXGI_SCALING_STRETCH,
+            Stereo: FALSE,
+            SwapEffect: dxgi::DXGI_SWAP_EFFECT_FLIP_DISCARD,
         };
 
-        destroy_shader(vs);
-        destroy_shader(ps);
-        destroy_shader(gs);
-        destroy_shader(hs);
-        destroy_shader(ds);
+        // TODO
+        let hr = surface.factory.CreateSwapChainForHwnd(
+            present_queue.as_mut_ptr() as *mut _,
+            surface.wnd_handle,
+            &desc,
+            ptr::null(),
+            ptr::null_mut(),
+            swap_chain1.mut_void() as *mut *mut _,
+        );
 
-        if winerror::SUCCEEDED(hr) {
-            let mut baked_states = desc.baked_states.clone();
-            if !desc.depth_stencil.depth_bounds {
-                baked_states.depth_bounds = None;
-            }
+        if !winerror::SUCCEEDED(hr) {
+            error!("error on swapchain creation 0x{:x}", hr);
+        }
 
-            Ok(r::GraphicsPipeline {
-                raw: pipeline,
-                signature: desc.layout.raw,
-                num_parameter_slots: desc.layout.num_parameter_slots,
-                topology,
-                constants: desc.layout.root_constants.clone(),
-                vertex_bindings,
-                baked_states,
-            })
-        } else {
-            Err(pso::CreationError::Other)
+        let (swap_chain3, hr3) = swap_chain1.cast::<dxgi1_4::IDXGISwapChain3>();
+        if !winerror::SUCCEEDED(hr3) {
+            error!("error on swapchain cast 0x{:x}", hr3);
         }
-    }
 
-    unsafe fn create_compute_pipeline<'a>(
-        &self,
-        desc: &pso::ComputePipelineDesc<'a, B>,
-        _cache: Option<&()>,
-    ) -> Result<r::ComputePipeline, pso::CreationError> {
-        let (cs, cs_destroy) =
-            Self::extract_entry_point(pso::Stage::Compute, &desc.shader, desc.layout)
-                .map_err(|err| pso::CreationError::Shader(err))?;
+        swap_chain1.destroy();
 
-        let (pipeline, hr) = self.raw.create_compute_pipeline_state(
-            desc.layout.raw,
-            native::Shader::from_blob(cs),
-            0,
-            CachedPSO::null(),
-            PipelineStateFlags::empty(),
-        );
+        let (resources, images) =
+            self.create_swapchain_images(swap_chain3, &rtv_heap, &config, format);
 
-        if cs_destroy {
-            cs.destroy();
-        }
+        let swapchain = w::Swapchain {
+            inner: swap_chain3,
+            rtv_heap,
+            resources,
+            extent: config.extent,
+            format: config.format,
+            buffer_format: format,
+            msaa: None,
+            allow_tearing,
+            color_space: w::ColorSpace::Srgb,
+        };
 
-        if winerror::SUCCEEDED(hr) {
-            Ok(r::ComputePipeline {
-                raw: pipeline,
-                signature: desc.layout.raw,
-                num_parameter_slots: desc.layout.num_parameter_slots,
-                constants: desc.layout.root_constants.clone(),
-            })
-        } else {
-            Err(pso::CreationError::Other)
+        Ok((swapchain, images))
+    }
+
+    unsafe fn destroy_swapchain(&self, mut swapchain: w::Swapchain) {
+        if let Some(ref msaa) = swapchain.msaa {
+            msaa.destroy();
+        }
+        for resource in &swapchain.resources {
+            resource.destroy();
         }
+        swapchain.release();
+        swapchain.rtv_heap.destroy();
     }
 
-    unsafe fn create_framebuffer<I>(
-        &self,
-        _renderpass: &r::RenderPass,
-        attachments: I,
-        extent: image::Extent,
-    ) -> Result<r::Framebuffer, d::OutOfMemory>
-    where
-        I: IntoIterator,
-        I::Item: Borrow<r::ImageView>,
-    {
-        Ok(r::Framebuffer {
-            attachments: attachments.into_iter().map(|att| *att.borrow()).collect(),
-            layers: extent.depth as _,
-        })
+    fn wait_idle(&self) -> Result<(), error::HostExecutionError> {
+        for queue in &self.queues {
+            queue.wait_idle()?;
+        }
+        Ok(())
     }
+}
+
+// Format of the swapchain back buffers for a requested surface format.
+//
+// Flip model swapchains don't support sRGB formats, `Bgra8Srgb` and `Rgba8Srgb` back buffers
+// are created with the UNORM format instead. Views of the back buffers, including the ones used
+// internally for clears and blits, use the sRGB format for gamma-correct output.
+fn swapchain_buffer_format(format: format::Format) -> dxgiformat::DXGI_FORMAT {
+    let format = match format {
+        // Apparently, swap chain doesn't like sRGB, but the RTV can still have some:
+        // https://www.gamedev.net/forums/topic/670546-d3d12srgb-buffer-format-for-swap-chain/
+        // [15716] DXGI ERROR: IDXGIFactory::CreateSwapchain: Flip model swapchains
+        //                     (DXGI_SWAP_EFFECT_FLIP_SEQUENTIAL and DXGI_SWAP_EFFECT_FLIP_DISCARD) only support the following Formats:
+        //                     (DXGI_FORMAT_R16G16B16A16_FLOAT, DXGI_FORMAT_B8G8R8A8_UNORM, DXGI_FORMAT_R8G8B8A8_UNORM, DXGI_FORMAT_R10G10B10A2_UNORM),
+        //                     assuming the underlying Device does as well.
+        format::Format::Bgra8Srgb => format::Format::Bgra8Unorm,
+        format::Format::Rgba8Srgb => format::Format::Rgba8Unorm,
+        format => format,
+    };
 
-    unsafe fn create_shader_module(
-        &self,
-        raw_data: &[u32],
-    ) -> Result<r::ShaderModule, d::ShaderError> {
-        Ok(r::ShaderModule::Spirv(raw_data.into()))
+    conv::map_format(format).unwrap() // TODO: error handling
+}
+
+// Check the number of requested swapchain images, DXGI fails with an opaque error otherwise.
+fn validate_swapchain_image_count(
+    count: hal::SwapImageIndex,
+) -> Result<(), hal::window::CreationError> {
+    // `SurfaceCapabilities::image_count` includes the upper bound.
+    if count < w::SWAPCHAIN_IMAGE_COUNT.start || count > w::SWAPCHAIN_IMAGE_COUNT.end {
+        error!(
+            "Swapchain image count {} is not in the supported range {:?}",
+            count,
+            w::SWAPCHAIN_IMAGE_COUNT
+        );
+        return Err(hal::window::CreationError::ImageCount(count));
     }
+    Ok(())
+}
 
-    unsafe fn create_buffer(
-        &self,
-        mut size: u64,
-        usage: buffer::Usage,
-    ) -> Result<r::Buffer, buffer::CreationError> {
-        if usage.contains(buffer::Usage::UNIFORM) {
-            // Constant buffer view sizes need to be aligned.
-            // Coupled with the offset alignment we can enforce an aligned CBV size
-            // on descriptor updates.
-            size = (size + 255) & !255;
+// Map a failed swapchain call, removed devices are reported as lost.
+fn map_swapchain_error(hr: winnt::HRESULT) -> hal::window::CreationError {
+    match hr {
+        winerror::DXGI_ERROR_DEVICE_REMOVED
+        | winerror::DXGI_ERROR_DEVICE_RESET
+        | winerror::DXGI_ERROR_DEVICE_HUNG => {
+            hal::window::CreationError::DeviceLost(d::DeviceLost)
         }
-        if usage.contains(buffer::Usage::TRANSFER_DST) {
-            // minimum of 1 word for the clear UAV
-            size = size.max(4);
+        winerror::E_OUTOFMEMORY => {
+            hal::window::CreationError::OutOfMemory(d::OutOfMemory::OutOfHostMemory)
         }
+        _ => hal::window::CreationError::SurfaceLost(d::SurfaceLost),
+    }
+}
 
-        let type_mask_shift = if self.private_caps.heterogeneous_resource_heaps {
-            MEM_TYPE_UNIVERSAL_SHIFT
-        } else {
-            MEM_TYPE_BUFFER_SHIFT
-        };
+// Value of a scalar specialization constant, stored in little endian.
+fn specialization_value(data: &[u8]) -> u64 {
+    data.iter().rev().fold(0u64, |u, &b| (u << 8) + b as u64)
+}
 
-        let requirements = memory::Requirements {
-            size,
-            alignment: d3d12::D3D12_DEFAULT_RESOURCE_PLACEMENT_ALIGNMENT as u64,
-            type_mask: MEM_TYPE_MASK << type_mask_shift,
-        };
+// Vertex buffer bindings and attributes need to fit into the input slots, including
+// the slots of attributes rebased onto their own binding.
+fn validate_vertex_input(
+    buffers: &[pso::VertexBufferDesc],
+    attributes: &[pso::AttributeDesc],
+) -> Result<(), pso::CreationError> {
+    if let Some(buffer) = buffers
+        .iter()
+        .find(|buffer| buffer.binding as usize >= MAX_VERTEX_BUFFERS)
+    {
+        error!(
+            "Vertex buffer binding {} exceeds the maximum of {} bindings",
+            buffer.binding, MAX_VERTEX_BUFFERS
+        );
+        return Err(pso::CreationError::Other);
+    }
+    if attributes.len() > MAX_VERTEX_BUFFERS {
+        error!(
+            "{} vertex attributes exceed the maximum of {}",
+            attributes.len(),
+            MAX_VERTEX_BUFFERS
+        );
+        return Err(pso::CreationError::Other);
+    }
+    Ok(())
+}
 
-        Ok(r::Buffer::Unbound(r::BufferUnbound {
-            requirements,
-            usage,
-        }))
+// Check if a mapped range is aligned to the non-coherent atom size.
+// The end may also be the end of the memory object.
+fn is_atom_aligned(range: &Range<u64>, size: u64) -> bool {
+    range.start % NON_COHERENT_ATOM_SIZE == 0
+        && (range.end % NON_COHERENT_ATOM_SIZE == 0 || range.end == size)
+}
+
+fn validate_mapped_range<R: RangeArg<u64>>(memory: &r::Memory, range: &R) {
+    assert!(
+        memory.resource.is_some(),
+        "Memory not created with a memory type exposing `CPU_VISIBLE`."
+    );
+    let range = *range.start().unwrap_or(&0) .. *range.end().unwrap_or(&memory.size);
+    assert!(
+        range.start <= range.end && range.end <= memory.size,
+        "Mapped range {:?} exceeds the memory size {}",
+        range,
+        memory.size
+    );
+    // D3D12 itself has no alignment requirements, unaligned ranges are still valid.
+    if !is_atom_aligned(&range, memory.size) {
+        warn!(
+            "Mapped range {:?} is not aligned to `non_coherent_atom_size` ({})",
+            range,
+            NON_COHERENT_ATOM_SIZE
+        );
     }
+}
 
-    unsafe fn get_buffer_requirements(&self, buffer: &r::Buffer) -> Requirements {
-        match buffer {
-            r::Buffer::Unbound(b) => b.requirements,
-            r::Buffer::Bound(b) => b.requirements,
-        }
+// Convert a fence wait timeout to milliseconds.
+//
+// Handles overflow when converting to u32 and always rounds up,
+// the Vulkan specification allows to wait more than specified.
+// Chunked waits on more than `MAXIMUM_WAIT_OBJECTS` fences may exceed the timeout.
+fn wait_timeout_ms(timeout_ns: u64) -> u32 {
+    if timeout_ns > (<u32>::max_value() as u64) * 1_000_000 {
+        <u32>::max_value()
+    } else {
+        ((timeout_ns + 999_999) / 1_000_000) as u32
     }
+}
 
-    unsafe fn bind_buffer_memory(
-        &self,
-        memory: &r::Memory,
-        offset: u64,
-        buffer: &mut r::Buffer,
-    ) -> Result<(), d::BindError> {
-        let buffer_unbound = *buffer.expect_unbound();
-        if buffer_unbound.requirements.type_mask & (1 << memory.type_id) == 0 {
-            error!(
-                "Bind memory failure: supported mask 0x{:x}, given id {}",
-                buffer_unbound.requirements.type_mask, memory.type_id
-            );
-            return Err(d::BindError::WrongMemory);
-        }
-        if offset + buffer_unbound.requirements.size > memory.size {
-            return Err(d::BindError::OutOfBounds);
-        }
+#[test]
+fn test_identity_mapping() {
+    assert_eq!(conv::map_swizzle(format::Swizzle::NO), IDENTITY_MAPPING);
+}
 
-        let mut resource = native::Resource::null();
-        let desc = d3d12::D3D12_RESOURCE_DESC {
-            Dimension: d3d12::D3D12_RESOURCE_DIMENSION_BUFFER,
-            Alignment: 0,
-            Width: buffer_unbound.requirements.size,
-            Height: 1,
-            DepthOrArraySize: 1,
-            MipLevels: 1,
-            Format: dxgiformat::DXGI_FORMAT_UNKNOWN,
-            SampleDesc: dxgitype::DXGI_SAMPLE_DESC {
-                Count: 1,
-                Quality: 0,
-            },
-            Layout: d3d12::D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
-            Flags: conv::map_buffer_flags(buffer_unbound.usage),
-        };
+#[test]
+fn test_descriptor_updates_merge_adjacent() {
+    let handle = |ptr| native::CpuDescriptor { ptr };
+    let mut updates = DescriptorUpdates::new(32);
+    // Partial update of slots 1, 2 and 4 of a set
+    updates.push(handle(1032), handle(0));
+    updates.push(handle(1064), handle(8));
+    updates.push(handle(1128), handle(16));
+
+    assert_eq!(updates.src.len(), 3);
+    assert_eq!(
+        updates.dst_starts.iter().map(|h| h.ptr).collect::<Vec<_>>(),
+        vec![1032, 1128]
+    );
+    assert_eq!(updates.dst_counts, vec![2, 1]);
+}
 
-        assert_eq!(
-            winerror::S_OK,
-            self.raw.clone().CreatePlacedResource(
-                memory.heap.as_mut_ptr(),
-                offset,
-                &desc,
-                d3d12::D3D12_RESOURCE_STATE_COMMON,
-                ptr::null(),
-                &d3d12::ID3D12Resource::uuidof(),
-                resource.mut_void(),
-            )
-        );
+#[test]
+fn test_map_pipeline_statistics() {
+    let counters = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+    let statistics = query::PipelineStatistic::INPUT_ASSEMBLY_VERTICES
+        | query::PipelineStatistic::FRAGMENT_SHADER_INVOCATIONS
+        | query::PipelineStatistic::COMPUTE_SHADER_INVOCATIONS;
+    let values = map_pipeline_statistics(statistics, &counters).collect::<Vec<_>>();
+    assert_eq!(values, vec![1, 8, 11]);
+    assert_eq!(
+        map_pipeline_statistics(query::PipelineStatistic::all(), &counters).count(),
+        NUM_PIPELINE_STATISTICS
+    );
+}
 
-        let clear_uav = if buffer_unbound.usage.contains(buffer::Usage::TRANSFER_DST) {
-            let handle = self.srv_uav_pool.lock().unwrap().alloc_handle();
-            let mut view_desc = d3d12::D3D12_UNORDERED_ACCESS_VIEW_DESC {
-                Format: dxgiformat::DXGI_FORMAT_R32_TYPELESS,
-                ViewDimension: d3d12::D3D12_UAV_DIMENSION_BUFFER,
-                u: mem::zeroed(),
-            };
+#[test]
+fn test_mip_count_validation() {
+    let kind = image::Kind::D2(1024, 1024, 1, 1);
+    assert!(is_valid_mip_count(&kind, 1));
+    assert!(is_valid_mip_count(&kind, 3));
+    assert!(is_valid_mip_count(&kind, 11));
+    assert!(!is_valid_mip_count(&kind, 0));
+    assert!(!is_valid_mip_count(&kind, 12));
+}
 
-            *view_desc.u.Buffer_mut() = d3d12::D3D12_BUFFER_UAV {
-                FirstElement: 0,
-                NumElements: (buffer_unbound.requirements.size / 4) as _,
-                StructureByteStride: 0,
-                CounterOffsetInBytes: 0,
-                Flags: d3d12::D3D12_BUFFER_UAV_FLAG_RAW,
-            };
+#[test]
+fn test_planar_kind_validation() {
+    assert!(is_valid_planar_kind(&image::Kind::D2(64, 32, 1, 1), 1));
+    assert!(is_valid_planar_kind(&image::Kind::D2(64, 32, 4, 1), 1));
+    assert!(!is_valid_planar_kind(&image::Kind::D2(64, 32, 1, 1), 2));
+    assert!(!is_valid_planar_kind(&image::Kind::D2(63, 32, 1, 1), 1));
+    assert!(!is_valid_planar_kind(&image::Kind::D2(64, 31, 1, 1), 1));
+    assert!(!is_valid_planar_kind(&image::Kind::D2(64, 32, 1, 4), 1));
+    assert!(!is_valid_planar_kind(&image::Kind::D1(64, 1), 1));
+    assert!(!is_valid_planar_kind(&image::Kind::D3(64, 32, 2), 1));
+}
 
-            self.raw.CreateUnorderedAccessView(
-                resource.as_mut_ptr(),
-                ptr::null_mut(),
-                &view_desc,
-                handle,
-            );
-            Some(handle)
-        } else {
-            None
-        };
+#[test]
+fn test_static_border_color() {
+    use winapi::um::d3d12::*;
+
+    let mut info = image::SamplerInfo::new(image::Filter::Linear, image::WrapMode::Border);
+    for &(color, expected) in &[
+        ([0.0, 0.0, 0.0, 0.0], Some(D3D12_STATIC_BORDER_COLOR_TRANSPARENT_BLACK)),
+        ([0.0, 0.0, 0.0, 1.0], Some(D3D12_STATIC_BORDER_COLOR_OPAQUE_BLACK)),
+        ([1.0, 1.0, 1.0, 1.0], Some(D3D12_STATIC_BORDER_COLOR_OPAQUE_WHITE)),
+        ([0.0, 1.0, 0.0, 1.0], None),
+    ] {
+        info.border = color.into();
+        assert_eq!(conv::map_static_border_color(&info).map(|c| c as u32), expected);
+    }
+    // Without border addressing any border color can be baked into a static sampler.
+    info.wrap_mode = (image::WrapMode::Clamp, image::WrapMode::Clamp, image::WrapMode::Clamp);
+    assert_eq!(
+        conv::map_static_border_color(&info).map(|c| c as u32),
+        Some(D3D12_STATIC_BORDER_COLOR_TRANSPARENT_BLACK)
+    );
+}
 
-        *buffer = r::Buffer::Bound(r::BufferBound {
-            resource,
-            requirements: buffer_unbound.requirements,
-            clear_uav,
+#[test]
+fn test_uses_blend_constants() {
+    let constant = pso::BlendState::On {
+        color: pso::BlendOp::Add {
+            src: pso::Factor::ConstColor,
+            dst: pso::Factor::OneMinusConstColor,
+        },
+        alpha: pso::BlendOp::ADD,
+    };
+    let mut blender = pso::BlendDesc::default();
+    assert!(!uses_blend_constants(&blender));
+    blender.targets.push(pso::ColorBlendDesc(pso::ColorMask::ALL, pso::BlendState::ALPHA));
+    assert!(!uses_blend_constants(&blender));
+    blender.targets.push(pso::ColorBlendDesc(pso::ColorMask::ALL, constant));
+    assert!(uses_blend_constants(&blender));
+}
+
+#[test]
+fn test_dynamic_depth_bias() {
+    let bias = pso::DepthBias {
+        const_factor: 1.0,
+        clamp: 0.0,
+        slope_factor: 0.0,
+    };
+    assert!(is_depth_bias_dynamic(Some(pso::State::Dynamic), true));
+    assert!(!is_depth_bias_dynamic(Some(pso::State::Dynamic), false));
+    assert!(!is_depth_bias_dynamic(Some(pso::State::Static(bias)), true));
+    assert!(!is_depth_bias_dynamic(None, true));
+}
+
+#[test]
+fn test_variable_count_binding() {
+    let binding = |binding, ty| pso::DescriptorSetLayoutBinding {
+        binding,
+        ty,
+        count: 1000,
+        stage_flags: pso::ShaderStageFlags::FRAGMENT,
+        immutable_samplers: false,
+    };
+    let variable = r::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT
+        | r::DescriptorBindingFlags::PARTIALLY_BOUND;
+    let none = r::DescriptorBindingFlags::empty();
+
+    assert_eq!(has_variable_count(&[]), Ok(false));
+    assert_eq!(
+        has_variable_count(&[
+            (binding(0, pso::DescriptorType::Sampler), none),
+            (binding(1, pso::DescriptorType::SampledImage), variable),
+        ]),
+        Ok(true)
+    );
+    assert_eq!(
+        has_variable_count(&[
+            (binding(0, pso::DescriptorType::SampledImage), variable),
+            (binding(1, pso::DescriptorType::Sampler), none),
+        ]),
+        Err(())
+    );
+    // The unbounded range would overlap the registers of binding 2.
+    assert_eq!(
+        has_variable_count(&[
+            (binding(2, pso::DescriptorType::Sampler), none),
+            (binding(1, pso::DescriptorType::SampledImage), variable),
+        ]),
+        Err(())
+    );
+    let mut immutable = binding(0, pso::DescriptorType::Sampler);
+    immutable.immutable_samplers = true;
+    assert_eq!(has_variable_count(&[(immutable, variable)]), Err(()));
+}
+
+#[test]
+fn test_descriptor_range_flags() {
+    use winapi::um::d3d12::*;
+    use r::DescriptorBindingFlags as F;
+
+    let binding = pso::DescriptorSetLayoutBinding {
+        binding: 0,
+        ty: pso::DescriptorType::SampledImage,
+        count: 1,
+        stage_flags: pso::ShaderStageFlags::FRAGMENT,
+        immutable_samplers: false,
+    };
+    assert!(is_valid_binding_flags(&binding, F::DATA_STATIC));
+    assert!(is_valid_binding_flags(
+        &binding,
+        F::DATA_STATIC_WHILE_SET_AT_EXECUTE | F::UPDATE_AFTER_BIND
+    ));
+    assert!(!is_valid_binding_flags(
+        &binding,
+        F::DATA_STATIC | F::DATA_STATIC_WHILE_SET_AT_EXECUTE
+    ));
+    assert!(!is_valid_binding_flags(&binding, F::DATA_STATIC | F::PARTIALLY_BOUND));
+
+    // Without hints everything is volatile, as in version 1.0 root signatures.
+    assert_eq!(
+        descriptor_range_flags(F::empty(), false),
+        D3D12_DESCRIPTOR_RANGE_FLAG_DESCRIPTORS_VOLATILE | D3D12_DESCRIPTOR_RANGE_FLAG_DATA_VOLATILE
+    );
+    assert_eq!(
+        descriptor_range_flags(F::UPDATE_AFTER_BIND, false),
+        D3D12_DESCRIPTOR_RANGE_FLAG_DESCRIPTORS_VOLATILE | D3D12_DESCRIPTOR_RANGE_FLAG_DATA_VOLATILE
+    );
+    assert_eq!(
+        descriptor_range_flags(F::DATA_STATIC, false),
+        D3D12_DESCRIPTOR_RANGE_FLAG_DATA_STATIC
+    );
+    assert_eq!(
+        descriptor_range_flags(F::DATA_STATIC_WHILE_SET_AT_EXECUTE, false),
+        D3D12_DESCRIPTOR_RANGE_FLAG_DESCRIPTORS_VOLATILE
+            | D3D12_DESCRIPTOR_RANGE_FLAG_DATA_STATIC_WHILE_SET_AT_EXECUTE
+    );
+    // Samplers don't have data.
+    assert_eq!(
+        descriptor_range_flags(F::DATA_STATIC, true),
+        D3D12_DESCRIPTOR_RANGE_FLAG_DESCRIPTORS_VOLATILE
+    );
+}
+
+#[test]
+fn test_render_target_formats() {
+    use hal::pass::{Attachment, AttachmentOps};
+
+    let attachment = |format| Attachment {
+        format: Some(format),
+        samples: 1,
+        ops: AttachmentOps::DONT_CARE,
+        stencil_ops: AttachmentOps::DONT_CARE,
+        layouts: image::Layout::Undefined .. image::Layout::Present,
+    };
+    let attachments = [
+        attachment(format::Format::Rgba8Unorm),
+        attachment(format::Format::R16Sfloat),
+    ];
+    let layout = image::Layout::ColorAttachmentOptimal;
+
+    // Formats follow the order of the color attachments, not of the attachments.
+    let colors = [(1, layout), (0, layout)];
+    let (formats, count) = render_target_formats(&attachments, &colors).unwrap();
+    assert_eq!(count, 2);
+    assert_eq!(
+        &formats[.. 3],
+        &[
+            dxgiformat::DXGI_FORMAT_R16_FLOAT,
+            dxgiformat::DXGI_FORMAT_R8G8B8A8_UNORM,
+            dxgiformat::DXGI_FORMAT_UNKNOWN,
+        ]
+    );
+    assert!(render_target_formats(&attachments, &[(0, layout); 9]).is_none());
+}
+
+#[test]
+fn test_input_attachment_state() {
+    use winapi::um::d3d12::*;
+
+    assert_eq!(input_attachment_state(None), Some(D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE));
+    assert_eq!(
+        input_attachment_state(Some(D3D12_RESOURCE_STATE_DEPTH_READ)),
+        Some(D3D12_RESOURCE_STATE_DEPTH_READ | D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE)
+    );
+    // Attachments can't be written and read in the same subpass.
+    assert_eq!(input_attachment_state(Some(D3D12_RESOURCE_STATE_RENDER_TARGET)), None);
+    assert_eq!(input_attachment_state(Some(D3D12_RESOURCE_STATE_DEPTH_WRITE)), None);
+}
+
+#[test]
+fn test_subpass_uav_barrier() {
+    // Attachment writes and reads are ordered by their transitions.
+    assert!(!requires_uav_barrier(
+        &(image::Access::COLOR_ATTACHMENT_WRITE .. image::Access::INPUT_ATTACHMENT_READ)
+    ));
+    assert!(!requires_uav_barrier(&(image::Access::SHADER_READ .. image::Access::SHADER_READ)));
+    assert!(!requires_uav_barrier(&(image::Access::empty() .. image::Access::SHADER_WRITE)));
+    // Read after write, write after read and write after write.
+    assert!(requires_uav_barrier(&(image::Access::SHADER_WRITE .. image::Access::SHADER_READ)));
+    assert!(requires_uav_barrier(&(image::Access::SHADER_READ .. image::Access::SHADER_WRITE)));
+    assert!(requires_uav_barrier(&(image::Access::MEMORY_WRITE .. image::Access::MEMORY_WRITE)));
+}
+
+#[test]
+fn test_anisotropy_validation() {
+    let features = hal::Features::SAMPLER_ANISOTROPY;
+    assert!(is_anisotropy_enabled(image::Anisotropic::On(16), features));
+    assert!(!is_anisotropy_enabled(image::Anisotropic::On(16), hal::Features::empty()));
+    assert!(is_anisotropy_enabled(image::Anisotropic::On(1), hal::Features::empty()));
+    assert!(is_anisotropy_enabled(image::Anisotropic::Off, hal::Features::empty()));
+}
+
+#[test]
+fn test_sampler_cache_dedup() {
+    let info = image::SamplerInfo::new(image::Filter::Linear, image::WrapMode::Tile);
+    let mut cache = descriptors_cpu::SamplerCache::default();
+    let mut created = 0;
+    for _ in 0 .. 5000 {
+        cache.get_or_create(&info, || {
+            created += 1;
+            native::CpuDescriptor { ptr: created }
         });
+    }
+    assert_eq!(created, 1);
+    assert_eq!(cache.len(), 1);
 
-        Ok(())
+    for _ in 0 .. 4999 {
+        assert!(cache.release(&info).is_none());
     }
+    assert!(cache.release(&info).is_some());
+    assert_eq!(cache.len(), 0);
+}
 
-    unsafe fn create_buffer_view<R: RangeArg<u64>>(
-        &self,
-        buffer: &r::Buffer,
-        format: Option<format::Format>,
-        range: R,
-    ) -> Result<r::BufferView, buffer::ViewCreationError> {
-        let buffer = buffer.expect_bound();
-        let buffer_features = {
-            let idx = format.map(|fmt| fmt as usize).unwrap_or(0);
-            self.format_properties.get(idx).buffer_features
-        };
-        let (format, format_desc) = match format.and_then(conv::map_format) {
-            Some(fmt) => (fmt, format.unwrap().surface_desc()),
-            None => return Err(buffer::ViewCreationError::UnsupportedFormat { format }),
-        };
+#[test]
+fn test_read_only_depth() {
+    let read_only = image::Layout::DepthStencilReadOnlyOptimal;
+    let test = |write| pso::DepthTest::On {
+        fun: pso::Comparison::LessEqual,
+        write,
+    };
+    assert!(writes_read_only_depth(read_only, &test(true)));
+    assert!(!writes_read_only_depth(read_only, &test(false)));
+    assert!(!writes_read_only_depth(read_only, &pso::DepthTest::Off));
+    assert!(!writes_read_only_depth(
+        image::Layout::DepthStencilAttachmentOptimal,
+        &test(true)
+    ));
+
+    // Sampling depth while testing requires both read states.
+    let state = conv::map_image_resource_state(
+        image::Access::DEPTH_STENCIL_ATTACHMENT_READ | image::Access::SHADER_READ,
+        read_only,
+    );
+    assert!(state & d3d12::D3D12_RESOURCE_STATE_DEPTH_READ != 0);
+    assert!(state & d3d12::D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE != 0);
+    assert_eq!(state & d3d12::D3D12_RESOURCE_STATE_DEPTH_WRITE, 0);
+}
 
-        let start = *range.start().unwrap_or(&0);
-        let end = *range.end().unwrap_or(&(buffer.requirements.size as _));
+#[test]
+fn test_encode_name() {
+    assert_eq!(encode_name(""), vec![0]);
+    assert_eq!(encode_name("vb"), vec![0x76, 0x62, 0]);
+    assert_eq!(encode_name("\u{1F600}").len(), 3);
+}
 
-        let bytes_per_texel = (format_desc.bits / 8) as u64;
-        // Check if it adheres to the texel buffer offset limit
-        assert_eq!(start % bytes_per_texel, 0);
-        let first_element = start / bytes_per_texel;
-        let num_elements = (end - start) / bytes_per_texel; // rounds down to next smaller size
+#[test]
+fn test_heap_flags_and_alignment() {
+    use winapi::um::d3d12::*;
 
-        let handle_srv = if buffer_features.contains(format::BufferFeature::UNIFORM_TEXEL) {
-            let mut desc = d3d12::D3D12_SHADER_RESOURCE_VIEW_DESC {
-                Format: format,
-                ViewDimension: d3d12::D3D12_SRV_DIMENSION_BUFFER,
-                Shader4ComponentMapping: IDENTITY_MAPPING,
-                u: mem::zeroed(),
-            };
+    // Tier 1: multisampled targets are placed in the target-only heaps.
+    let (flags, alignment) = heap_flags_and_alignment(MemoryGroup::TargetOnly as _);
+    assert_eq!(flags, D3D12_HEAP_FLAG_ALLOW_ONLY_RT_DS_TEXTURES);
+    assert_eq!(alignment, D3D12_DEFAULT_MSAA_RESOURCE_PLACEMENT_ALIGNMENT as u64);
 
-            *desc.u.Buffer_mut() = d3d12::D3D12_BUFFER_SRV {
-                FirstElement: first_element,
-                NumElements: num_elements as _,
-                StructureByteStride: bytes_per_texel as _,
-                Flags: d3d12::D3D12_BUFFER_SRV_FLAG_NONE,
-            };
+    let (flags, alignment) = heap_flags_and_alignment(MemoryGroup::BufferOnly as _);
+    assert_eq!(flags, D3D12_HEAP_FLAG_ALLOW_ONLY_BUFFERS);
+    assert_eq!(alignment, D3D12_DEFAULT_RESOURCE_PLACEMENT_ALIGNMENT as u64);
 
-            let handle = self.srv_uav_pool.lock().unwrap().alloc_handle();
-            self.raw
-                .clone()
-                .CreateShaderResourceView(buffer.resource.as_mut_ptr(), &desc, handle);
-            handle
-        } else {
-            d3d12::D3D12_CPU_DESCRIPTOR_HANDLE { ptr: 0 }
-        };
+    let (flags, _) = heap_flags_and_alignment(MemoryGroup::ImageOnly as _);
+    assert_eq!(flags, D3D12_HEAP_FLAG_ALLOW_ONLY_NON_RT_DS_TEXTURES);
 
-        let handle_uav = if buffer_features.intersects(
-            format::BufferFeature::STORAGE_TEXEL | format::BufferFeature::STORAGE_TEXEL_ATOMIC,
-        ) {
-            let mut desc = d3d12::D3D12_UNORDERED_ACCESS_VIEW_DESC {
-                Format: format,
-                ViewDimension: d3d12::D3D12_UAV_DIMENSION_BUFFER,
-                u: mem::zeroed(),
-            };
+    // Tier 2
+    let (flags, alignment) = heap_flags_and_alignment(MemoryGroup::Universal as _);
+    assert_eq!(flags, D3D12_HEAP_FLAG_ALLOW_ALL_BUFFERS_AND_TEXTURES);
+    assert_eq!(alignment, D3D12_DEFAULT_MSAA_RESOURCE_PLACEMENT_ALIGNMENT as u64);
+}
 
-            *desc.u.Buffer_mut() = d3d12::D3D12_BUFFER_UAV {
-                FirstElement: first_element,
-                NumElements: num_elements as _,
-                StructureByteStride: bytes_per_texel as _,
-                Flags: d3d12::D3D12_BUFFER_UAV_FLAG_NONE,
-                CounterOffsetInBytes: 0,
-            };
+#[test]
+fn test_cross_adapter_buffer_desc() {
+    let desc = cross_adapter_buffer_desc(1 << 16);
+    assert_eq!(desc.Dimension, d3d12::D3D12_RESOURCE_DIMENSION_BUFFER);
+    assert_eq!(desc.Width, 1 << 16);
+    // Shared buffers have to be row major.
+    assert_eq!(desc.Layout, d3d12::D3D12_TEXTURE_LAYOUT_ROW_MAJOR);
+    assert_ne!(desc.Flags & d3d12::D3D12_RESOURCE_FLAG_ALLOW_CROSS_ADAPTER, 0);
+}
 
-            let handle = self.srv_uav_pool.lock().unwrap().alloc_handle();
-            self.raw.clone().CreateUnorderedAccessView(
-                buffer.resource.as_mut_ptr(),
-                ptr::null_mut(),
-                &desc,
-                handle,
-            );
-            handle
-        } else {
-            d3d12::D3D12_CPU_DESCRIPTOR_HANDLE { ptr: 0 }
-        };
+#[test]
+fn test_descriptor_heap_growth() {
+    let max = 2048;
+    // Exhausted heaps are replaced by heaps of the same capacity.
+    assert_eq!(next_descriptor_heap_capacity(2048, 16, max), Some(2048));
+    // Requests larger than the current heap grow it.
+    assert_eq!(next_descriptor_heap_capacity(64, 1000, max), Some(1000));
+    assert_eq!(next_descriptor_heap_capacity(2048, 2048, max), Some(2048));
+    assert_eq!(next_descriptor_heap_capacity(2048, 2049, max), None);
+}
 
-        return Ok(r::BufferView {
-            handle_srv,
-            handle_uav,
-        });
+#[test]
+fn test_swapchain_errors() {
+    match map_swapchain_error(winerror::DXGI_ERROR_DEVICE_REMOVED) {
+        hal::window::CreationError::DeviceLost(_) => {}
+        err => panic!("Unexpected error {:?}", err),
     }
+    match map_swapchain_error(winerror::E_OUTOFMEMORY) {
+        hal::window::CreationError::OutOfMemory(_) => {}
+        err => panic!("Unexpected error {:?}", err),
+    }
+    assert_eq!(
+        swapchain_buffer_format(format::Format::Bgra8Srgb),
+        dxgiformat::DXGI_FORMAT_B8G8R8A8_UNORM
+    );
+}
 
-    unsafe fn create_image(
-        &self,
-        kind: image::Kind,
-        mip_levels: image::Level,
-        format: format::Format,
-        tiling: image::Tiling,
-        usage: image::Usage,
-        view_caps: image::ViewCapabilities,
-    ) -> Result<r::Image, image::CreationError> {
-        assert!(mip_levels <= kind.num_levels());
+#[test]
+fn test_swapchain_image_count() {
+    for &count in &[2, 3, 16] {
+        assert_eq!(validate_swapchain_image_count(count), Ok(()));
+    }
+    for &count in &[0, 1, 17, 20] {
+        assert_eq!(
+            validate_swapchain_image_count(count),
+            Err(hal::window::CreationError::ImageCount(count))
+        );
+    }
+}
 
-        let base_format = format.base_format();
-        let format_desc = base_format.0.desc();
-        let bytes_per_block = (format_desc.bits / 8) as _;
-        let block_dim = format_desc.dim;
-        let extent = kind.extent();
+#[test]
+fn test_wait_timeout_ms() {
+    assert_eq!(wait_timeout_ms(0), 0);
+    assert_eq!(wait_timeout_ms(1), 1);
+    assert_eq!(wait_timeout_ms(1_000_000), 1);
+    assert_eq!(wait_timeout_ms(1_000_001), 2);
+    // Infinite waits.
+    assert_eq!(wait_timeout_ms(!0), winbase::INFINITE);
+}
 
-        let format_properties = self.format_properties.get(format as usize);
-        let (layout, features) = match tiling {
-            image::Tiling::Optimal => (
-                d3d12::D3D12_TEXTURE_LAYOUT_UNKNOWN,
-                format_properties.optimal_tiling,
-            ),
-            image::Tiling::Linear => (
-                d3d12::D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
-                format_properties.linear_tiling,
-            ),
-        };
+#[test]
+fn test_mapped_range_alignment() {
+    assert!(is_atom_aligned(&(0 .. 128), 1024));
+    assert!(is_atom_aligned(&(64 .. 100), 100));
+    assert!(!is_atom_aligned(&(64 .. 100), 1024));
+    assert!(!is_atom_aligned(&(1 .. 128), 1024));
+}
 
-        let desc = d3d12::D3D12_RESOURCE_DESC {
-            Dimension: match kind {
-                image::Kind::D1(..) => d3d12::D3D12_RESOURCE_DIMENSION_TEXTURE1D,
-                image::Kind::D2(..) => d3d12::D3D12_RESOURCE_DIMENSION_TEXTURE2D,
-                image::Kind::D3(..) => d3d12::D3D12_RESOURCE_DIMENSION_TEXTURE3D,
-            },
-            Alignment: 0,
-            Width: extent.width as _,
-            Height: extent.height as _,
-            DepthOrArraySize: if extent.depth > 1 {
-                extent.depth as _
-            } else {
-                kind.num_layers() as _
-            },
-            MipLevels: mip_levels as _,
-            Format: match conv::map_surface_type(base_format.0) {
-                Some(format) => format,
-                None => return Err(image::CreationError::Format(format)),
-            },
-            SampleDesc: dxgitype::DXGI_SAMPLE_DESC {
-                Count: kind.num_samples() as _,
-                Quality: 0,
+#[test]
+fn test_vertex_input_limits() {
+    let buffers = (0 .. MAX_VERTEX_BUFFERS as u32 + 1)
+        .map(|binding| pso::VertexBufferDesc {
+            binding,
+            stride: 16,
+            rate: VertexInputRate::Vertex,
+        })
+        .collect::<Vec<_>>();
+    let attributes = (0 .. MAX_VERTEX_BUFFERS as u32 + 1)
+        .map(|binding| pso::AttributeDesc {
+            location: binding,
+            binding,
+            element: pso::Element {
+                format: format::Format::Rgba32Sfloat,
+                offset: 0,
             },
-            Layout: layout,
-            Flags: conv::map_image_flags(usage, features),
-        };
+        })
+        .collect::<Vec<_>>();
 
-        let alloc_info = self.raw.clone().GetResourceAllocationInfo(0, 1, &desc);
+    let max = MAX_VERTEX_BUFFERS;
+    assert!(validate_vertex_input(&buffers[.. max], &attributes[.. max]).is_ok());
+    assert!(validate_vertex_input(&buffers, &attributes[.. max]).is_err());
+    assert!(validate_vertex_input(&buffers[.. max], &attributes).is_err());
+}
 
-        // Image usages which require RT/DS heap due to internal implementation.
-        let target_usage = image::Usage::COLOR_ATTACHMENT
-            | image::Usage::DEPTH_STENCIL_ATTACHMENT
-            | image::Usage::TRANSFER_DST;
+// Empty compute shader with `local_size_x_id = 0`, defaulting to 1.
+#[cfg(test)]
+#[cfg_attr(rustfmt, rustfmt_skip)]
+const COMPUTE_SPEC_WORKGROUP_SPIRV: [u32; 65] = [
+    0x07230203, 0x00010000, 0, 10, 0,
+    // OpCapability Shader
+    0x0002_0011, 1,
+    // OpMemoryModel Logical GLSL450
+    0x0003_000E, 0, 1,
+    // OpEntryPoint GLCompute %1 "main"
+    0x0005_000F, 5, 1, 0x6E69_616D, 0,
+    // OpExecutionMode %1 LocalSize 1 1 1
+    0x0006_0010, 1, 17, 1, 1, 1,
+    // OpDecorate %2 SpecId 0
+    0x0004_0047, 2, 1, 0,
+    // OpDecorate %3 BuiltIn WorkgroupSize
+    0x0004_0047, 3, 11, 25,
+    // %4 = OpTypeVoid, %5 = OpTypeFunction %4
+    0x0002_0013, 4,
+    0x0003_0021, 5, 4,
+    // %6 = OpTypeInt 32 0, %7 = OpTypeVector %6 3
+    0x0004_0015, 6, 32, 0,
+    0x0004_0017, 7, 6, 3,
+    // %2 = OpSpecConstant %6 1, %8 = OpConstant %6 1
+    0x0004_0032, 6, 2, 1,
+    0x0004_002B, 6, 8, 1,
+    // %3 = OpSpecConstantComposite %7 %2 %8 %8
+    0x0006_0033, 7, 3, 2, 8, 8,
+    // %1 = OpFunction %4 None %5, OpLabel, OpReturn, OpFunctionEnd
+    0x0005_0036, 4, 1, 0, 5,
+    0x0002_00F8, 9,
+    0x0001_00FD,
+    0x0001_0038,
+];
+
+// Compute shader counting its invocations, the workgroup size is a spec constant.
+//
+// layout(local_size_x_id = 0) in;
+// layout(binding = 0) buffer Counter { uint count; };
+// void main() { atomicAdd(count, 1); }
+#[cfg(test)]
+#[cfg_attr(rustfmt, rustfmt_skip)]
+const COMPUTE_SPEC_WORKGROUP_COUNTER_SPIRV: [u32; 112] = [
+    0x07230203, 0x00010000, 0, 17, 0,
+    // OpCapability Shader
+    0x0002_0011, 1,
+    // OpMemoryModel Logical GLSL450
+    0x0003_000E, 0, 1,
+    // OpEntryPoint GLCompute %1 "main"
+    0x0005_000F, 5, 1, 0x6E69_616D, 0,
+    // OpExecutionMode %1 LocalSize 1 1 1
+    0x0006_0010, 1, 17, 1, 1, 1,
+    // OpDecorate %2 SpecId 0
+    0x0004_0047, 2, 1, 0,
+    // OpDecorate %3 BuiltIn WorkgroupSize
+    0x0004_0047, 3, 11, 25,
+    // OpDecorate %10 BufferBlock, OpMemberDecorate %10 0 Offset 0
+    0x0003_0047, 10, 3,
+    0x0005_0048, 10, 0, 35, 0,
+    // OpDecorate %12 DescriptorSet 0, OpDecorate %12 Binding 0
+    0x0004_0047, 12, 34, 0,
+    0x0004_0047, 12, 33, 0,
+    // %4 = OpTypeVoid, %5 = OpTypeFunction %4
+    0x0002_0013, 4,
+    0x0003_0021, 5, 4,
+    // %6 = OpTypeInt 32 0, %7 = OpTypeVector %6 3
+    0x0004_0015, 6, 32, 0,
+    0x0004_0017, 7, 6, 3,
+    // %10 = OpTypeStruct %6, %11 = OpTypePointer Uniform %10
+    0x0003_001E, 10, 6,
+    0x0004_0020, 11, 2, 10,
+    // %12 = OpVariable %11 Uniform, %13 = OpTypePointer Uniform %6
+    0x0004_003B, 11, 12, 2,
+    0x0004_0020, 13, 2, 6,
+    // %2 = OpSpecConstant %6 1, %8 = OpConstant %6 1, %14 = OpConstant %6 0
+    0x0004_0032, 6, 2, 1,
+    0x0004_002B, 6, 8, 1,
+    0x0004_002B, 6, 14, 0,
+    // %3 = OpSpecConstantComposite %7 %2 %8 %8
+    0x0006_0033, 7, 3, 2, 8, 8,
+    // %1 = OpFunction %4 None %5, OpLabel
+    0x0005_0036, 4, 1, 0, 5,
+    0x0002_00F8, 9,
+    // %15 = OpAccessChain %13 %12 %14
+    0x0005_0041, 13, 15, 12, 14,
+    // %16 = OpAtomicIAdd %6 %15 Device(%8) None(%14) %8
+    0x0007_00EA, 6, 16, 15, 8, 14, 8,
+    // OpReturn, OpFunctionEnd
+    0x0001_00FD,
+    0x0001_0038,
+];
+
+// Fragment shader sampling a depth texture with a depth reference value.
+//
+// layout(binding = 0) uniform texture2D depth;
+// layout(binding = 1) uniform samplerShadow cmp;
+// layout(location = 0) out float result;
+// void main() { result = textureLod(sampler2DShadow(depth, cmp), vec3(0.5), 0.0); }
+#[cfg(test)]
+#[cfg_attr(rustfmt, rustfmt_skip)]
+const FRAGMENT_DEPTH_COMPARE_SPIRV: [u32; 135] = [
+    0x07230203, 0x00010000, 0, 23, 0,
+    // OpCapability Shader
+    0x0002_0011, 1,
+    // OpMemoryModel Logical GLSL450
+    0x0003_000E, 0, 1,
+    // OpEntryPoint Fragment %1 "main" %2
+    0x0006_000F, 4, 1, 0x6E69_616D, 0, 2,
+    // OpExecutionMode %1 OriginUpperLeft
+    0x0003_0010, 1, 7,
+    // OpDecorate %2 Location 0
+    0x0004_0047, 2, 30, 0,
+    // OpDecorate %3 DescriptorSet 0, OpDecorate %3 Binding 0
+    0x0004_0047, 3, 34, 0,
+    0x0004_0047, 3, 33, 0,
+    // OpDecorate %4 DescriptorSet 0, OpDecorate %4 Binding 1
+    0x0004_0047, 4, 34, 0,
+    0x0004_0047, 4, 33, 1,
+    // %5 = OpTypeVoid, %6 = OpTypeFunction %5, %7 = OpTypeFloat 32
+    0x0002_0013, 5,
+    0x0003_0021, 6, 5,
+    0x0003_0016, 7, 32,
+    // %8 = OpTypeImage %7 2D depth=1 arrayed=0 ms=0 sampled=1 Unknown
+    0x0009_0019, 8, 7, 1, 1, 0, 0, 1, 0,
+    // %9 = OpTypePointer UniformConstant %8, %3 = OpVariable %9 UniformConstant
+    0x0004_0020, 9, 0, 8,
+    0x0004_003B, 9, 3, 0,
+    // %10 = OpTypeSampler
+    0x0002_001A, 10,
+    // %11 = OpTypePointer UniformConstant %10, %4 = OpVariable %11 UniformConstant
+    0x0004_0020, 11, 0, 10,
+    0x0004_003B, 11, 4, 0,
+    // %12 = OpTypeSampledImage %8, %13 = OpTypeVector %7 2
+    0x0003_001B, 12, 8,
+    0x0004_0017, 13, 7, 2,
+    // %14 = OpConstant %7 0.5, %15 = OpConstantComposite %13 %14 %14, %16 = OpConstant %7 0
+    0x0004_002B, 7, 14, 0x3F00_0000,
+    0x0005_002C, 13, 15, 14, 14,
+    0x0004_002B, 7, 16, 0,
+    // %17 = OpTypePointer Output %7, %2 = OpVariable %17 Output
+    0x0004_0020, 17, 3, 7,
+    0x0004_003B, 17, 2, 3,
+    // %1 = OpFunction %5 None %6, %18 = OpLabel
+    0x0005_0036, 5, 1, 0, 6,
+    0x0002_00F8, 18,
+    // %19 = OpLoad %8 %3, %20 = OpLoad %10 %4, %21 = OpSampledImage %12 %19 %20
+    0x0004_003D, 8, 19, 3,
+    0x0004_003D, 10, 20, 4,
+    0x0005_0056, 12, 21, 19, 20,
+    // %22 = OpImageSampleDrefExplicitLod %7 %21 %15 %14 Lod %16
+    0x0008_0059, 7, 22, 21, 15, 14, 2, 16,
+    // OpStore %2 %22, OpReturn, OpFunctionEnd
+    0x0003_003E, 2, 22,
+    0x0001_00FD,
+    0x0001_0038,
+];
 
-        let type_mask_shift = if self.private_caps.heterogeneous_resource_heaps {
-            MEM_TYPE_UNIVERSAL_SHIFT
-        } else if usage.intersects(target_usage) {
-            MEM_TYPE_TARGET_SHIFT
-        } else {
-            MEM_TYPE_IMAGE_SHIFT
-        };
+#[test]
+fn test_translate_depth_compare() {
+    let mut options = hlsl::CompilerOptions::default();
+    options.shader_model = hlsl::ShaderModel::V5_1;
+    let mut ast = Device::parse_spirv(&FRAGMENT_DEPTH_COMPARE_SPIRV).unwrap();
+    ast.set_compiler_options(&options).unwrap();
+    let shader_code = ast.compile().unwrap();
+    assert!(shader_code.contains("SamplerComparisonState"));
+    assert!(shader_code.contains("SampleCmp"));
+}
 
-        Ok(r::Image::Unbound(r::ImageUnbound {
-            view_format: conv::map_format(format),
-            dsv_format: conv::map_format_dsv(base_format.0),
-            desc,
-            requirements: memory::Requirements {
-                size: alloc_info.SizeInBytes,
-                alignment: alloc_info.Alignment,
-                type_mask: MEM_TYPE_MASK << type_mask_shift,
-            },
-            format,
-            kind,
-            usage,
-            tiling,
-            view_caps,
-            bytes_per_block,
-            block_dim,
-            num_levels: mip_levels,
-        }))
-    }
+#[test]
+fn test_comparison_sampler_filter() {
+    let mut info = image::SamplerInfo::new(image::Filter::Linear, image::WrapMode::Clamp);
+    assert_eq!(
+        conv::map_sampler_filter(&info),
+        d3d12::D3D12_FILTER_MIN_MAG_MIP_LINEAR
+    );
+    info.comparison = Some(pso::Comparison::LessEqual);
+    assert_eq!(
+        conv::map_sampler_filter(&info),
+        d3d12::D3D12_FILTER_COMPARISON_MIN_MAG_MIP_LINEAR
+    );
+    info.min_filter = image::Filter::Nearest;
+    info.mag_filter = image::Filter::Nearest;
+    info.mip_filter = image::Filter::Nearest;
+    assert_eq!(
+        conv::map_sampler_filter(&info),
+        d3d12::D3D12_FILTER_COMPARISON_MIN_MAG_MIP_POINT
+    );
+}
 
-    unsafe fn get_image_requirements(&self, image: &r::Image) -> Requirements {
-        match image {
-            r::Image::Bound(i) => i.requirements,
-            r::Image::Unbound(i) => i.requirements,
+#[test]
+fn test_reduction_sampler_filter() {
+    let mut info = image::SamplerInfo::new(image::Filter::Linear, image::WrapMode::Clamp);
+    info.reduction = image::ReductionMode::Minimum;
+    assert_eq!(
+        conv::map_sampler_filter(&info),
+        d3d12::D3D12_FILTER_MINIMUM_MIN_MAG_MIP_LINEAR
+    );
+    info.reduction = image::ReductionMode::Maximum;
+    assert_eq!(
+        conv::map_sampler_filter(&info),
+        d3d12::D3D12_FILTER_MAXIMUM_MIN_MAG_MIP_LINEAR
+    );
+
+    let features = hal::Features::SAMPLER_REDUCTION;
+    assert!(is_reduction_enabled(image::ReductionMode::WeightedAverage, hal::Features::empty()));
+    assert!(!is_reduction_enabled(image::ReductionMode::Minimum, hal::Features::empty()));
+    assert!(is_reduction_enabled(image::ReductionMode::Maximum, features));
+}
+
+#[test]
+fn test_specialization_value() {
+    assert_eq!(specialization_value(&[1]), 1);
+    assert_eq!(specialization_value(&[0x78, 0x56, 0x34, 0x12]), 0x1234_5678);
+    assert_eq!(specialization_value(&[]), 0);
+}
+
+#[test]
+fn test_specialize_workgroup_size() {
+    use std::borrow::Cow;
+
+    let specialization = pso::Specialization {
+        constants: Cow::Owned(vec![pso::SpecializationConstant { id: 0, range: 0 .. 4 }]),
+        data: Cow::Owned(vec![8, 0, 0, 0]),
+    };
+
+    let mut options = hlsl::CompilerOptions::default();
+    options.shader_model = hlsl::ShaderModel::V5_1;
+    let mut ast = Device::parse_spirv(&COMPUTE_SPEC_WORKGROUP_SPIRV).unwrap();
+    ast.set_compiler_options(&options).unwrap();
+    Device::specialize_spirv(&mut ast, &specialization).unwrap();
+    let shader_code = ast.compile().unwrap();
+    assert!(shader_code.contains("SPIRV_CROSS_CONSTANT_ID_0 8u"));
+
+    let invalid = pso::Specialization {
+        constants: Cow::Owned(vec![pso::SpecializationConstant { id: 0, range: 0 .. 4 }]),
+        data: Cow::Owned(vec![8]),
+    };
+    let mut ast = Device::parse_spirv(&COMPUTE_SPEC_WORKGROUP_SPIRV).unwrap();
+    assert!(Device::specialize_spirv(&mut ast, &invalid).is_err());
+}
+
+#[test]
+fn test_shader_source_modules() {
+    assert_eq!(
+        shader_profile(pso::Stage::Geometry, hlsl::ShaderModel::V5_1),
+        "gs_5_1\0"
+    );
+    assert_eq!(
+        shader_profile(pso::Stage::Compute, hlsl::ShaderModel::V5_0),
+        "cs_5_0\0"
+    );
+    assert!(is_dxbc(b"DXBC\x01\x02"));
+    assert!(!is_dxbc(&[0x03, 0x02, 0x23, 0x07]));
+}
+
+#[cfg(all(test, feature = "warp"))]
+mod warp_tests {
+    use hal::format::{Aspects, Format, ImageFeature};
+    use hal::{pso, Features};
+    use test_utils::{warp_adapter, Warp};
+    use winapi::um::d3d12::D3D12_RESOURCE_FLAG_ALLOW_RENDER_TARGET;
+    use Instance;
+
+    #[test]
+    fn test_warp_create_buffer() {
+        use hal::{buffer, Device};
+
+        let (warp, _queue_group) = Warp::open("test_warp_create_buffer");
+        let device = &warp.device;
+        unsafe {
+            let buffer = device
+                .create_buffer(256, buffer::Usage::TRANSFER_DST)
+                .unwrap();
+            device.destroy_buffer(buffer);
         }
     }
 
-    unsafe fn get_image_subresource_footprint(
-        &self,
-        image: &r::Image,
-        sub: image::Subresource,
-    ) -> image::SubresourceFootprint {
-        let mut num_rows = 0;
-        let mut total_bytes = 0;
-        let desc = match image {
-            r::Image::Bound(i) => i.descriptor,
-            r::Image::Unbound(i) => i.desc,
-        };
-        let footprint = {
-            let mut footprint = mem::zeroed();
-            self.raw.GetCopyableFootprints(
-                image.get_desc(),
-                image.calc_subresource(sub.level as _, sub.layer as _, 0),
-                1,
-                0,
-                &mut footprint,
-                &mut num_rows,
-                ptr::null_mut(), // row size in bytes
-                &mut total_bytes,
-            );
-            footprint
+    #[test]
+    fn test_warp_anisotropic_sampler() {
+        use hal::image::{Anisotropic, Filter, SamplerInfo, WrapMode};
+        use hal::{Device, PhysicalDevice};
+
+        let instance = Instance::create("test_warp_anisotropic_sampler", 1);
+        let adapter = warp_adapter(&instance);
+        let family = &adapter.queue_families[0];
+        let info = SamplerInfo {
+            anisotropic: Anisotropic::On(16),
+            ..SamplerInfo::new(Filter::Linear, WrapMode::Tile)
         };
-
-        let depth_pitch = (footprint.Footprint.RowPitch * num_rows) as buffer::Offset;
-        let array_pitch = footprint.Footprint.Depth as buffer::Offset * depth_pitch;
-        image::SubresourceFootprint {
-            slice: footprint.Offset .. footprint.Offset + total_bytes,
-            row_pitch: footprint.Footprint.RowPitch as _,
-            depth_pitch,
-            array_pitch,
+        for &(features, valid) in &[
+            (Features::empty(), false),
+            (Features::SAMPLER_ANISOTROPY, true),
+        ] {
+            unsafe {
+                let gpu = adapter
+                    .physical_device
+                    .open(&[(family, &[1.0])], features)
+                    .unwrap();
+                let sampler = gpu.device.create_sampler(info.clone());
+                assert_eq!(sampler.is_ok(), valid);
+                if let Ok(sampler) = sampler {
+                    gpu.device.destroy_sampler(sampler);
+                }
+            }
         }
     }
 
-    unsafe fn bind_image_memory(
-        &self,
-        memory: &r::Memory,
-        offset: u64,
-        image: &mut r::Image,
-    ) -> Result<(), d::BindError> {
-        use self::image::Usage;
+    #[test]
+    fn test_warp_compute_only_device() {
+        use hal::command::{BufferCopy, CommandBufferFlags, RawCommandBuffer, RawLevel};
+        use hal::memory::{Barrier, Dependencies, Properties};
+        use hal::pool::{CommandPoolCreateFlags, RawCommandPool};
+        use hal::pso::{DescriptorPool, PipelineStage};
+        use hal::queue::{RawCommandQueue, Submission};
+        use hal::{buffer, Compute, Device, PhysicalDevice, QueueFamily, QueueType};
+        use std::ops::Range;
+        use std::{iter, ptr};
+
+        use resource::Semaphore;
+
+        let instance = Instance::create("test_warp_compute_only_device", 1);
+        let adapter = warp_adapter(&instance);
+        let (device, mut queue_group) = adapter
+            .open_with::<_, Compute>(1, |family| family.queue_type() == QueueType::Compute)
+            .unwrap();
+        // No present queue is created without the `Present` family.
+        assert!(device.present_queue.is_none());
 
-        let image_unbound = *image.expect_unbound();
-        if image_unbound.requirements.type_mask & (1 << memory.type_id) == 0 {
-            error!(
-                "Bind memory failure: supported mask 0x{:x}, given id {}",
-                image_unbound.requirements.type_mask, memory.type_id
+        unsafe {
+            let memory_types = adapter.physical_device.memory_properties().memory_types;
+            let create_buffer = |size, usage, properties: Properties| {
+                let mut buffer = device.create_buffer(size, usage).unwrap();
+                let requirements = device.get_buffer_requirements(&buffer);
+                let ty = memory_types
+                    .iter()
+                    .enumerate()
+                    .position(|(id, ty)| {
+                        requirements.type_mask & (1 << id) != 0
+                            && ty.properties.contains(properties)
+                    })
+                    .unwrap();
+                let memory = device
+                    .allocate_memory(hal::MemoryTypeId(ty), requirements.size)
+                    .unwrap();
+                device.bind_buffer_memory(&memory, 0, &mut buffer).unwrap();
+                (buffer, memory)
+            };
+            let (data, data_memory) = create_buffer(
+                4,
+                buffer::Usage::STORAGE | buffer::Usage::TRANSFER_SRC,
+                Properties::DEVICE_LOCAL,
             );
-            return Err(d::BindError::WrongMemory);
+            let (readback, readback_memory) = create_buffer(
+                4,
+                buffer::Usage::TRANSFER_DST,
+                Properties::CPU_VISIBLE | Properties::CPU_CACHED,
+            );
+
+            let set_layout = device
+                .create_descriptor_set_layout(
+                    &[pso::DescriptorSetLayoutBinding {
+                        binding: 0,
+                        ty: pso::DescriptorType::StorageBuffer,
+                        count: 1,
+                        stage_flags: pso::ShaderStageFlags::COMPUTE,
+                        immutable_samplers: false,
+                    }],
+                    &[],
+                )
+                .unwrap();
+            let layout = device
+                .create_pipeline_layout(
+                    iter::once(&set_layout),
+                    iter::empty::<(pso::ShaderStageFlags, Range<u32>)>(),
+                )
+                .unwrap();
+            let mut pool = device
+                .create_descriptor_pool(
+                    1,
+                    iter::once(pso::DescriptorRangeDesc {
+                        ty: pso::DescriptorType::StorageBuffer,
+                        count: 1,
+                    }),
+                    pso::DescriptorPoolCreateFlags::empty(),
+                )
+                .unwrap();
+            let set = pool.allocate_set(&set_layout).unwrap();
+            device.write_descriptor_sets(iter::once(pso::DescriptorSetWrite {
+                set: &set,
+                binding: 0,
+                array_offset: 0,
+                descriptors: iter::once(pso::Descriptor::Buffer(&data, None .. None)),
+            }));
+
+            let module = device
+                .create_shader_module_from_source(
+                    pso::Stage::Compute,
+                    "cs_main",
+                    "main",
+                    b"RWByteAddressBuffer data : register(u0);
+                    [numthreads(1, 1, 1)] void cs_main() { data.Store(0, 42); }",
+                )
+                .unwrap();
+            let pipeline = {
+                let entry = pso::EntryPoint {
+                    entry: "main",
+                    module: &module,
+                    specialization: pso::Specialization::default(),
+                };
+                let desc = pso::ComputePipelineDesc::new(entry, &layout);
+                device.create_compute_pipeline(&desc, None).unwrap()
+            };
+
+            let mut command_pool = device
+                .create_command_pool(queue_group.family(), CommandPoolCreateFlags::empty())
+                .unwrap();
+            let mut cmd_buffer = command_pool.allocate_one(RawLevel::Primary);
+            cmd_buffer.begin(CommandBufferFlags::ONE_TIME_SUBMIT, Default::default());
+            cmd_buffer.bind_compute_pipeline(&pipeline);
+            cmd_buffer.bind_compute_descriptor_sets(&layout, 0, iter::once(&set), &[]);
+            cmd_buffer.dispatch([1, 1, 1]);
+            cmd_buffer.pipeline_barrier(
+                PipelineStage::COMPUTE_SHADER .. PipelineStage::TRANSFER,
+                Dependencies::empty(),
+                iter::once(Barrier::whole_buffer(
+                    &data,
+                    buffer::Access::SHADER_WRITE .. buffer::Access::TRANSFER_READ,
+                )),
+            );
+            cmd_buffer.copy_buffer(
+                &data,
+                &readback,
+                iter::once(BufferCopy {
+                    src: 0,
+                    dst: 0,
+                    size: 4,
+                }),
+            );
+            cmd_buffer.finish();
+
+            let fence = device.create_fence(false).unwrap();
+            queue_group.queues[0].as_raw_mut().submit(
+                Submission {
+                    command_buffers: iter::once(&cmd_buffer),
+                    wait_semaphores: iter::empty::<(&Semaphore, _)>(),
+                    signal_semaphores: iter::empty::<&Semaphore>(),
+                },
+                Some(&fence),
+            );
+            assert_eq!(device.wait_for_fence(&fence, !0), Ok(true));
+
+            let mapped = device.map_memory(&readback_memory, 0 .. 4).unwrap();
+            assert_eq!(ptr::read(mapped as *const u32), 42);
+            device.unmap_memory(&readback_memory);
+
+            device.destroy_fence(fence);
+            command_pool.free(iter::once(cmd_buffer));
+            device.destroy_command_pool(command_pool);
+            device.destroy_compute_pipeline(pipeline);
+            device.destroy_shader_module(module);
+            device.destroy_descriptor_pool(pool);
+            device.destroy_pipeline_layout(layout);
+            device.destroy_descriptor_set_layout(set_layout);
+            for (buffer, memory) in vec![(data, data_memory), (readback, readback_memory)] {
+                device.destroy_buffer(buffer);
+                device.free_memory(memory);
+            }
         }
-        if offset + image_unbound.requirements.size > memory.size {
-            return Err(d::BindError::OutOfBounds);
+    }
+
+    #[cfg(feature = "winit")]
+    #[test]
+    fn test_warp_swapchain_without_present_queue() {
+        use hal::format::Format;
+        use hal::window::CreationError;
+        use hal::{Compute, Device, QueueFamily, QueueType, SwapchainConfig};
+
+        let events_loop = winit::EventsLoop::new();
+        let window = winit::WindowBuilder::new()
+            .with_visibility(false)
+            .build(&events_loop)
+            .unwrap();
+        let instance = Instance::create("test_warp_swapchain_without_present_queue", 1);
+        let mut surface = instance.create_surface(&window);
+        let adapter = warp_adapter(&instance);
+        let (device, _queue_group) = adapter
+            .open_with::<_, Compute>(1, |family| family.queue_type() == QueueType::Compute)
+            .unwrap();
+
+        unsafe {
+            let config = SwapchainConfig::new(64, 64, Format::Bgra8Srgb, 2);
+            match device.create_swapchain(&mut surface, config, None) {
+                Err(CreationError::NoPresentQueue) => {}
+                Err(err) => panic!("Unexpected error: {:?}", err),
+                Ok(_) => panic!("Swapchain created without a present queue"),
+            }
         }
+    }
 
-        let mut resource = native::Resource::null();
-        let num_layers = image_unbound.kind.num_layers();
+    #[cfg(feature = "winit")]
+    #[test]
+    fn test_warp_recreate_swapchain() {
+        use hal::format::Format;
+        use hal::window::{CreationError, Extent2D};
+        use hal::{Device, Surface, Swapchain, SwapchainConfig};
+        use std::iter;
+
+        use resource::Semaphore;
+
+        let events_loop = winit::EventsLoop::new();
+        let window = winit::WindowBuilder::new()
+            .with_visibility(false)
+            .build(&events_loop)
+            .unwrap();
+        let instance = Instance::create("test_warp_recreate_swapchain", 1);
+        let mut surface = instance.create_surface(&window);
+        let adapter = warp_adapter(&instance);
+        let (device, mut queue_group) = adapter
+            .open_with::<_, hal::General>(1, |family| surface.supports_queue_family(family))
+            .unwrap();
 
-        assert_eq!(
-            winerror::S_OK,
-            self.raw.clone().CreatePlacedResource(
-                memory.heap.as_mut_ptr(),
-                offset,
-                &image_unbound.desc,
-                d3d12::D3D12_RESOURCE_STATE_COMMON,
-                ptr::null(),
-                &d3d12::ID3D12Resource::uuidof(),
-                resource.mut_void(),
-            )
-        );
+        unsafe {
+            let config = SwapchainConfig::new(64, 64, Format::Bgra8Srgb, 2);
+            // Back buffers are released together with the swapchain.
+            let (mut swapchain, images) =
+                device.create_swapchain(&mut surface, config, None).unwrap();
+            drop(images);
+
+            // An invalid image count keeps the swapchain usable.
+            let config = SwapchainConfig::new(128, 96, Format::Bgra8Srgb, 20);
+            assert_eq!(
+                device.recreate_swapchain(&mut swapchain, config).err(),
+                Some(CreationError::ImageCount(20))
+            );
+            assert_eq!(swapchain.resources.len(), 2);
 
-        let info = ViewInfo {
-            resource,
-            kind: image_unbound.kind,
-            caps: image::ViewCapabilities::empty(),
-            view_kind: match image_unbound.kind {
-                image::Kind::D1(..) => image::ViewKind::D1Array,
-                image::Kind::D2(..) => image::ViewKind::D2Array,
-                image::Kind::D3(..) => image::ViewKind::D3,
-            },
-            format: image_unbound.desc.Format,
-            component_mapping: IDENTITY_MAPPING,
-            range: image::SubresourceRange {
-                aspects: Aspects::empty(),
-                levels: 0 .. 0,
-                layers: 0 .. 0,
-            },
-        };
+            let config = SwapchainConfig::new(128, 96, Format::Bgra8Srgb, 3);
+            let images = device.recreate_swapchain(&mut swapchain, config).unwrap();
+            assert_eq!(images.len(), 3);
+            assert_eq!(
+                swapchain.extent,
+                Extent2D {
+                    width: 128,
+                    height: 96,
+                }
+            );
 
-        //TODO: the clear_Xv is incomplete. We should support clearing images created without XXX_ATTACHMENT usage.
-        // for this, we need to check the format and force the `RENDER_TARGET` flag behind the user's back
-        // if the format supports being rendered into, allowing us to create clear_Xv
-        let format_properties = self.format_properties.get(image_unbound.format as usize);
-        let props = match image_unbound.tiling {
-            image::Tiling::Optimal => format_properties.optimal_tiling,
-            image::Tiling::Linear => format_properties.linear_tiling,
-        };
-        let can_clear_color = image_unbound
-            .usage
-            .intersects(Usage::TRANSFER_DST | Usage::COLOR_ATTACHMENT)
-            && props.contains(format::ImageFeature::COLOR_ATTACHMENT);
-        let can_clear_depth = image_unbound
-            .usage
-            .intersects(Usage::TRANSFER_DST | Usage::DEPTH_STENCIL_ATTACHMENT)
-            && props.contains(format::ImageFeature::DEPTH_STENCIL_ATTACHMENT);
-        let aspects = image_unbound.format.surface_desc().aspects;
+            let (index, _) = swapchain.acquire_image(!0, None, None).unwrap();
+            let no_semaphores = iter::empty::<&Semaphore>();
+            queue_group.queues[0]
+                .present(iter::once((&swapchain, index)), no_semaphores)
+                .unwrap();
+            queue_group.queues[0].wait_idle().unwrap();
 
-        *image = r::Image::Bound(r::ImageBound {
-            resource: resource,
-            place: r::Place::Heap {
-                raw: memory.heap.clone(),
-                offset,
-            },
-            surface_type: image_unbound.format.base_format().0,
-            kind: image_unbound.kind,
-            usage: image_unbound.usage,
-            default_view_format: image_unbound.view_format,
-            view_caps: image_unbound.view_caps,
-            descriptor: image_unbound.desc,
-            bytes_per_block: image_unbound.bytes_per_block,
-            block_dim: image_unbound.block_dim,
-            clear_cv: if aspects.contains(Aspects::COLOR) && can_clear_color {
-                let format = image_unbound.view_format.unwrap();
-                (0 .. num_layers)
-                    .map(|layer| {
-                        self.view_image_as_render_target(ViewInfo {
+            drop(images);
+            device.destroy_swapchain(swapchain);
+        }
+    }
+
+    #[cfg(feature = "winit")]
+    #[test]
+    fn test_warp_swapchain_image_count() {
+        use hal::format::Format;
+        use hal::window::CreationError;
+        use hal::{Device, Surface, SwapchainConfig};
+
+        let events_loop = winit::EventsLoop::new();
+        let window = winit::WindowBuilder::new()
+            .with_visibility(false)
+            .build(&events_loop)
+            .unwrap();
+        let instance = Instance::create("test_warp_swapchain_image_count", 1);
+        let mut surface = instance.create_surface(&window);
+        let adapter = warp_adapter(&instance);
+        let (capabilities, _, _) = surface.compatibility(&adapter.physical_device);
+        assert_eq!(capabilities.image_count, 2 .. 16);
+        let (device, _queue_group) = adapter
+            .open_with::<_, hal::General>(1, |family| surface.supports_queue_family(family))
+            .unwrap();
+
+        unsafe {
+            for &count in &[1, 20] {
+                let config = SwapchainConfig::new(64, 64, Format::Bgra8Unorm, count);
+                assert_eq!(
+                    device.create_swapchain(&mut surface, config, None).err(),
+                    Some(CreationError::ImageCount(count))
+                );
+            }
+        }
+    }
+
+    #[cfg(feature = "winit")]
+    #[test]
+    fn test_warp_bgra_swapchain() {
+        use hal::format::{Format, Swizzle};
+        use hal::image::{SubresourceRange, ViewKind};
+        use hal::{Device, Surface, SwapchainConfig};
+        use winapi::shared::dxgiformat::*;
+
+        let events_loop = winit::EventsLoop::new();
+        let window = winit::WindowBuilder::new()
+            .with_visibility(false)
+            .build(&events_loop)
+            .unwrap();
+        let instance = Instance::create("test_warp_bgra_swapchain", 1);
+        let mut surface = instance.create_surface(&window);
+        let adapter = warp_adapter(&instance);
+        let (device, _queue_group) = adapter
+            .open_with::<_, hal::General>(1, |family| surface.supports_queue_family(family))
+            .unwrap();
+
+        let targets = [
+            (Format::Bgra8Unorm, DXGI_FORMAT_B8G8R8A8_UNORM),
+            // sRGB views over UNORM back buffers.
+            (Format::Bgra8Srgb, DXGI_FORMAT_B8G8R8A8_UNORM_SRGB),
+        ];
+        for &(format, view_format) in &targets {
+            unsafe {
+                let config = SwapchainConfig::new(64, 64, format, 2);
+                let (swapchain, images) =
+                    device.create_swapchain(&mut surface, config, None).unwrap();
+                assert_eq!(swapchain.buffer_format, DXGI_FORMAT_B8G8R8A8_UNORM);
+                for image in &images {
+                    let bound = image.expect_bound();
+                    assert_eq!(bound.descriptor.Format, DXGI_FORMAT_B8G8R8A8_UNORM);
+                    assert_eq!(bound.default_view_format, Some(view_format));
+                    let view = device
+                        .create_image_view(
+                            image,
+                            ViewKind::D2,
                             format,
-                            range: image::SubresourceRange {
+                            Swizzle::NO,
+                            SubresourceRange {
                                 aspects: Aspects::COLOR,
-                                levels: 0 .. 1, //TODO?
-                                layers: layer .. layer + 1,
-                            },
-                            ..info.clone()
-                        })
-                        .unwrap()
-                    })
-                    .collect()
-            } else {
-                Vec::new()
-            },
-            clear_dv: if aspects.contains(Aspects::DEPTH) && can_clear_depth {
-                let format = image_unbound.dsv_format.unwrap();
-                (0 .. num_layers)
-                    .map(|layer| {
-                        self.view_image_as_depth_stencil(ViewInfo {
-                            format,
-                            range: image::SubresourceRange {
-                                aspects: Aspects::DEPTH,
-                                levels: 0 .. 1, //TODO?
-                                layers: layer .. layer + 1,
-                            },
-                            ..info.clone()
-                        })
-                        .unwrap()
-                    })
-                    .collect()
-            } else {
-                Vec::new()
-            },
-            clear_sv: if aspects.contains(Aspects::STENCIL) && can_clear_depth {
-                let format = image_unbound.dsv_format.unwrap();
-                (0 .. num_layers)
-                    .map(|layer| {
-                        self.view_image_as_depth_stencil(ViewInfo {
-                            format,
-                            range: image::SubresourceRange {
-                                aspects: Aspects::STENCIL,
-                                levels: 0 .. 1, //TODO?
-                                layers: layer .. layer + 1,
+                                levels: 0 .. 1,
+                                layers: 0 .. 1,
                             },
-                            ..info.clone()
-                        })
-                        .unwrap()
-                    })
-                    .collect()
-            } else {
-                Vec::new()
-            },
-            requirements: image_unbound.requirements,
-        });
+                        )
+                        .unwrap();
+                    assert!(view.handle_rtv.is_some());
+                    device.destroy_image_view(view);
+                }
+                drop(images);
+                device.destroy_swapchain(swapchain);
+            }
+        }
+    }
 
-        Ok(())
+    #[cfg(feature = "winit")]
+    #[test]
+    fn test_warp_msaa_resolve_swapchain() {
+        use hal::command::RawCommandBuffer;
+        use hal::format::Swizzle;
+        use hal::memory::Dependencies;
+        use hal::pass::{Attachment, AttachmentLoadOp, AttachmentOps, AttachmentStoreOp};
+        use hal::pass::{SubpassDependency, SubpassDesc};
+        use hal::pso::PipelineStage;
+        use hal::{image, Device, SwapchainConfig};
+        use std::iter;
+
+        use test_utils::{begin_render_pass, clear_color, image_barrier, subresource_range};
+
+        let events_loop = winit::EventsLoop::new();
+        let window = winit::WindowBuilder::new()
+            .with_visibility(false)
+            .build(&events_loop)
+            .unwrap();
+        let (warp, mut queue_group) = Warp::open("test_warp_msaa_resolve_swapchain");
+        let device = &warp.device;
+        let mut surface = warp.create_surface(&window);
+
+        unsafe {
+            // sRGB views over UNORM back buffers, resolved in the buffer format.
+            let mut config = SwapchainConfig::new(64, 64, Format::Bgra8Srgb, 2);
+            config.image_usage |= image::Usage::TRANSFER_SRC;
+            let (swapchain, back_buffers) =
+                device.create_swapchain(&mut surface, config, None).unwrap();
+            let back_buffer = &back_buffers[0];
+            let back_buffer_view = device
+                .create_image_view(
+                    back_buffer,
+                    image::ViewKind::D2,
+                    Format::Bgra8Srgb,
+                    Swizzle::NO,
+                    subresource_range(Aspects::COLOR),
+                )
+                .unwrap();
+            assert_eq!(back_buffer_view.resolve_format, swapchain.buffer_format);
+            let msaa = warp.create_target(
+                Format::Bgra8Srgb,
+                (64, 64),
+                4,
+                image::Usage::COLOR_ATTACHMENT,
+            );
+
+            let render_pass = device
+                .create_render_pass(
+                    &[
+                        Attachment {
+                            format: Some(Format::Bgra8Srgb),
+                            samples: 4,
+                            ops: AttachmentOps::new(
+                                AttachmentLoadOp::Clear,
+                                AttachmentStoreOp::DontCare,
+                            ),
+                            stencil_ops: AttachmentOps::DONT_CARE,
+                            layouts: image::Layout::Undefined
+                                .. image::Layout::ColorAttachmentOptimal,
+                        },
+                        // Transitioned from and into the present state of the back buffer.
+                        Attachment {
+                            format: Some(Format::Bgra8Srgb),
+                            samples: 1,
+                            ops: AttachmentOps::new(
+                                AttachmentLoadOp::DontCare,
+                                AttachmentStoreOp::Store,
+                            ),
+                            stencil_ops: AttachmentOps::DONT_CARE,
+                            layouts: image::Layout::Undefined .. image::Layout::Present,
+                        },
+                    ],
+                    iter::once(SubpassDesc {
+                        colors: &[(0, image::Layout::ColorAttachmentOptimal)],
+                        depth_stencil: None,
+                        inputs: &[],
+                        resolves: &[(1, image::Layout::ColorAttachmentOptimal)],
+                        preserves: &[],
+                    }),
+                    iter::empty::<SubpassDependency>(),
+                )
+                .unwrap();
+            let framebuffer = device
+                .create_framebuffer(
+                    &render_pass,
+                    vec![&msaa.view, &back_buffer_view],
+                    image::Extent {
+                        width: 64,
+                        height: 64,
+                        depth: 1,
+                    },
+                )
+                .unwrap();
+
+            warp.submit(&mut queue_group, |cmd_buffer| {
+                begin_render_pass(
+                    cmd_buffer,
+                    &render_pass,
+                    &framebuffer,
+                    (64, 64),
+                    iter::repeat(clear_color([1.0, 0.0, 0.0, 1.0])).take(2),
+                );
+                cmd_buffer.end_render_pass();
+                cmd_buffer.pipeline_barrier(
+                    PipelineStage::COLOR_ATTACHMENT_OUTPUT .. PipelineStage::TRANSFER,
+                    Dependencies::empty(),
+                    iter::once(image_barrier(
+                        back_buffer,
+                        Aspects::COLOR,
+                        (image::Access::empty(), image::Layout::Present)
+                            .. (image::Access::TRANSFER_READ, image::Layout::TransferSrcOptimal),
+                    )),
+                );
+            });
+
+            let texels =
+                warp.read_image::<[u8; 4]>(&mut queue_group, back_buffer, Aspects::COLOR, (1, 1));
+            assert_eq!(texels, vec![[0, 0, 0xFF, 0xFF]]);
+
+            device.destroy_framebuffer(framebuffer);
+            device.destroy_render_pass(render_pass);
+            warp.destroy_target(msaa);
+            device.destroy_image_view(back_buffer_view);
+            drop(back_buffers);
+            device.destroy_swapchain(swapchain);
+        }
     }
 
-    unsafe fn create_image_view(
-        &self,
-        image: &r::Image,
-        view_kind: image::ViewKind,
-        format: format::Format,
-        swizzle: format::Swizzle,
-        range: image::SubresourceRange,
-    ) -> Result<r::ImageView, image::ViewError> {
-        let image = image.expect_bound();
-        let is_array = image.kind.num_layers() > 1;
-        let mip_levels = (range.levels.start, range.levels.end);
-        let layers = (range.layers.start, range.layers.end);
+    #[cfg(feature = "winit")]
+    #[test]
+    fn test_warp_swapchain_msaa_target() {
+        use hal::command::RawCommandBuffer;
+        use hal::format::Swizzle;
+        use hal::pass::{Attachment, AttachmentLoadOp, AttachmentOps, AttachmentStoreOp};
+        use hal::pass::{SubpassDependency, SubpassDesc};
+        use hal::{image, Device, General, QueueGroup, Swapchain, SwapchainConfig};
+        use std::iter;
+
+        use resource::Semaphore;
+        use test_utils::{begin_render_pass, clear_color, subresource_range};
+        use Backend;
+
+        let events_loop = winit::EventsLoop::new();
+        let window = winit::WindowBuilder::new()
+            .with_visibility(false)
+            .build(&events_loop)
+            .unwrap();
+        let (warp, mut queue_group) = Warp::open("test_warp_swapchain_msaa_target");
+        let device = &warp.device;
+        let mut surface = warp.create_surface(&window);
 
-        let info = ViewInfo {
-            resource: image.resource,
-            kind: image.kind,
-            caps: image.view_caps,
-            // D3D12 doesn't allow looking at a single slice of an array as a non-array
-            view_kind: if is_array && view_kind == image::ViewKind::D2 {
-                image::ViewKind::D2Array
-            } else if is_array && view_kind == image::ViewKind::D1 {
-                image::ViewKind::D1Array
-            } else {
-                view_kind
-            },
-            format: conv::map_format(format).ok_or(image::ViewError::BadFormat(format))?,
-            component_mapping: conv::map_swizzle(swizzle),
-            range,
-        };
+        unsafe {
+            let config = SwapchainConfig::new(64, 64, Format::Bgra8Unorm, 2);
+            let (mut swapchain, back_buffers) =
+                device.create_swapchain(&mut surface, config.clone(), None).unwrap();
+            let msaa_image = device
+                .create_swapchain_msaa_target(&mut swapchain, 4)
+                .unwrap();
+            let msaa_view = device
+                .create_image_view(
+                    &msaa_image,
+                    image::ViewKind::D2,
+                    Format::Bgra8Unorm,
+                    Swizzle::NO,
+                    subresource_range(Aspects::COLOR),
+                )
+                .unwrap();
+            // Single-sample copy of the MSAA target, to check its contents.
+            let resolved = warp.create_target(
+                Format::Bgra8Unorm,
+                (64, 64),
+                1,
+                image::Usage::COLOR_ATTACHMENT | image::Usage::TRANSFER_SRC,
+            );
 
-        Ok(r::ImageView {
-            resource: image.resource,
-            handle_srv: if image
-                .usage
-                .intersects(image::Usage::SAMPLED | image::Usage::INPUT_ATTACHMENT)
-            {
-                Some(self.view_image_as_shader_resource(info.clone())?)
-            } else {
-                None
-            },
-            handle_rtv: if image.usage.contains(image::Usage::COLOR_ATTACHMENT) {
-                Some(self.view_image_as_render_target(info.clone())?)
-            } else {
-                None
-            },
-            handle_uav: if image.usage.contains(image::Usage::STORAGE) {
-                Some(self.view_image_as_storage(info.clone())?)
-            } else {
-                None
-            },
-            handle_dsv: if image.usage.contains(image::Usage::DEPTH_STENCIL_ATTACHMENT) {
-                Some(
-                    self.view_image_as_depth_stencil(ViewInfo {
-                        format: conv::map_format_dsv(format.base_format().0)
-                            .ok_or(image::ViewError::BadFormat(format))?,
-                        ..info
-                    })?,
+            let render_pass = device
+                .create_render_pass(
+                    &[
+                        // Left in the present state, which the swapchain resolves from.
+                        Attachment {
+                            format: Some(Format::Bgra8Unorm),
+                            samples: 4,
+                            ops: AttachmentOps::new(
+                                AttachmentLoadOp::Clear,
+                                AttachmentStoreOp::Store,
+                            ),
+                            stencil_ops: AttachmentOps::DONT_CARE,
+                            layouts: image::Layout::Undefined .. image::Layout::Present,
+                        },
+                        Attachment {
+                            format: Some(Format::Bgra8Unorm),
+                            samples: 1,
+                            ops: AttachmentOps::new(
+                                AttachmentLoadOp::DontCare,
+                                AttachmentStoreOp::Store,
+                            ),
+                            stencil_ops: AttachmentOps::DONT_CARE,
+                            layouts: image::Layout::Undefined
+                                .. image::Layout::TransferSrcOptimal,
+                        },
+                    ],
+                    iter::once(SubpassDesc {
+                        colors: &[(0, image::Layout::ColorAttachmentOptimal)],
+                        depth_stencil: None,
+                        inputs: &[],
+                        resolves: &[(1, image::Layout::ColorAttachmentOptimal)],
+                        preserves: &[],
+                    }),
+                    iter::empty::<SubpassDependency>(),
                 )
-            } else {
-                None
-            },
-            dxgi_format: image.default_view_format.unwrap(),
-            num_levels: image.descriptor.MipLevels as image::Level,
-            mip_levels,
-            layers,
-            kind: info.kind,
-        })
+                .unwrap();
+            let framebuffer = warp.create_framebuffer(
+                &render_pass,
+                &[&msaa_view, &resolved.view],
+                (64, 64),
+            );
+            let render = |queue_group: &mut QueueGroup<Backend, General>, color| {
+                warp.submit(queue_group, |cmd_buffer| {
+                    begin_render_pass(
+                        cmd_buffer,
+                        &render_pass,
+                        &framebuffer,
+                        (64, 64),
+                        iter::repeat(clear_color(color)).take(2),
+                    );
+                    cmd_buffer.end_render_pass();
+                });
+                warp.read_image::<[u8; 4]>(queue_group, &resolved.image, Aspects::COLOR, (1, 1))
+            };
+
+            assert_eq!(
+                render(&mut queue_group, [1.0, 0.0, 0.0, 1.0]),
+                vec![[0, 0, 0xFF, 0xFF]]
+            );
+            let (index, _) = swapchain.acquire_image(!0, None, None).unwrap();
+            queue_group.queues[0]
+                .present(iter::once((&swapchain, index)), iter::empty::<&Semaphore>())
+                .unwrap();
+            device.wait_idle().unwrap();
+            {
+                let msaa = swapchain.msaa.as_ref().unwrap();
+                let fence_value = msaa.fence_values.lock().unwrap()[index as usize];
+                assert_ne!(fence_value, 0);
+                assert!(msaa.fence.GetCompletedValue() >= fence_value);
+            }
+
+            // Recreating the swapchain releases its reference to the target,
+            // the image of the caller stays usable.
+            drop(back_buffers);
+            let back_buffers = device.recreate_swapchain(&mut swapchain, config).unwrap();
+            assert!(swapchain.msaa.is_none());
+            assert_eq!(
+                render(&mut queue_group, [0.0, 1.0, 0.0, 1.0]),
+                vec![[0, 0xFF, 0, 0xFF]]
+            );
+
+            device.destroy_framebuffer(framebuffer);
+            device.destroy_render_pass(render_pass);
+            warp.destroy_target(resolved);
+            device.destroy_image_view(msaa_view);
+            device.destroy_image(msaa_image);
+            drop(back_buffers);
+            device.destroy_swapchain(swapchain);
+        }
     }
 
-    unsafe fn create_sampler(
-        &self,
-        info: image::SamplerInfo,
-    ) -> Result<r::Sampler, d::AllocationError> {
-        assert!(info.normalized);
-        let handle = self.sampler_pool.lock().unwrap().alloc_handle();
+    #[test]
+    fn test_warp_fence_wait_timeout() {
+        use hal::device::WaitFor;
+        use hal::Device;
 
-        let op = match info.comparison {
-            Some(_) => d3d12::D3D12_FILTER_REDUCTION_TYPE_COMPARISON,
-            None => d3d12::D3D12_FILTER_REDUCTION_TYPE_STANDARD,
-        };
-        self.raw.create_sampler(
-            handle,
-            conv::map_filter(
-                info.mag_filter,
-                info.min_filter,
-                info.mip_filter,
-                op,
-                info.anisotropic,
-            ),
-            [
-                conv::map_wrap(info.wrap_mode.0),
-                conv::map_wrap(info.wrap_mode.1),
-                conv::map_wrap(info.wrap_mode.2),
-            ],
-            info.lod_bias.into(),
-            match info.anisotropic {
-                image::Anisotropic::On(max) => max as _, // TODO: check support here?
-                image::Anisotropic::Off => 0,
-            },
-            conv::map_comparison(info.comparison.unwrap_or(pso::Comparison::Always)),
-            info.border.into(),
-            info.lod_range.start.into() .. info.lod_range.end.into(),
-        );
+        let (warp, _queue_group) = Warp::open("test_warp_fence_wait_timeout");
+        let device = &warp.device;
+        unsafe {
+            let unsignalled = device.create_fence(false).unwrap();
+            let signalled = device.create_fence(true).unwrap();
+            let fences = [&unsignalled, &signalled];
+            assert_eq!(
+                device.wait_for_fences(fences.iter().cloned(), WaitFor::All, 1_000_000),
+                Ok(false)
+            );
+            assert_eq!(
+                device.wait_for_fences(fences.iter().cloned(), WaitFor::Any, 1_000_000),
+                Ok(true)
+            );
+            assert_eq!(device.wait_for_fence(&unsignalled, 0), Ok(false));
+            device.destroy_fence(unsignalled);
+            device.destroy_fence(signalled);
+        }
+    }
 
-        Ok(r::Sampler { handle })
+    #[test]
+    fn test_warp_fence_reset() {
+        use hal::Device;
+
+        let (warp, _queue_group) = Warp::open("test_warp_fence_reset");
+        let device = &warp.device;
+        unsafe {
+            let fence = device.create_fence(true).unwrap();
+            assert_eq!(device.get_fence_status(&fence), Ok(true));
+            device.reset_fence(&fence).unwrap();
+            assert_eq!(device.get_fence_status(&fence), Ok(false));
+            // Signal the new target from the host, as a submission would.
+            fence.raw.signal(fence.target.get());
+            assert_eq!(device.get_fence_status(&fence), Ok(true));
+            device.destroy_fence(fence);
+        }
     }
 
-    unsafe fn create_descriptor_pool<I>(
-        &self,
-        max_sets: usize,
-        descriptor_pools: I,
-        _flags: pso::DescriptorPoolCreateFlags,
-    ) -> Result<r::DescriptorPool, d::OutOfMemory>
-    where
-        I: IntoIterator,
-        I::Item: Borrow<pso::DescriptorRangeDesc>,
-    {
-        let mut num_srv_cbv_uav = 0;
-        let mut num_samplers = 0;
+    #[test]
+    fn test_warp_occlusion_query_wait() {
+        use hal::command::{CommandBufferFlags, RawCommandBuffer, RawLevel};
+        use hal::pool::{CommandPoolCreateFlags, RawCommandPool};
+        use hal::query::{ControlFlags, Query, ResultFlags, Type};
+        use hal::queue::{RawCommandQueue, Submission};
+        use hal::Device;
+        use std::{iter, slice};
 
-        let descriptor_pools = descriptor_pools
-            .into_iter()
-            .map(|desc| *desc.borrow())
-            .collect::<Vec<_>>();
+        use resource::Semaphore;
 
-        for desc in &descriptor_pools {
-            match desc.ty {
-                pso::DescriptorType::Sampler => {
-                    num_samplers += desc.count;
-                }
-                pso::DescriptorType::CombinedImageSampler => {
-                    num_samplers += desc.count;
-                    num_srv_cbv_uav += desc.count;
-                }
-                _ => {
-                    num_srv_cbv_uav += desc.count;
-                }
+        let (warp, mut queue_group) = Warp::open("test_warp_occlusion_query_wait");
+        let device = &warp.device;
+        unsafe {
+            let pool = device.create_query_pool(Type::Occlusion, 2).unwrap();
+            let flags = ResultFlags::WAIT | ResultFlags::BITS_64 | ResultFlags::WITH_AVAILABILITY;
+            let mut data = [0xFFu64; 4];
+            let results = |data: &mut [u64; 4]| {
+                let bytes = slice::from_raw_parts_mut(data.as_mut_ptr() as *mut u8, 32);
+                device.get_query_pool_results(&pool, 0 .. 2, bytes, 16, flags)
+            };
+
+            // Queries which have never been submitted don't block.
+            assert_eq!(results(&mut data), Ok(false));
+            assert_eq!(data[1], 0);
+
+            let mut command_pool = device
+                .create_command_pool(queue_group.family(), CommandPoolCreateFlags::empty())
+                .unwrap();
+            let mut cmd_buffer = command_pool.allocate_one(RawLevel::Primary);
+            cmd_buffer.begin(CommandBufferFlags::empty(), Default::default());
+            for id in 0 .. 2 {
+                let query = || Query { pool: &pool, id };
+                cmd_buffer.begin_query(query(), ControlFlags::PRECISE);
+                cmd_buffer.end_query(query());
             }
+            cmd_buffer.finish();
+
+            // Results are waited for without a fence of the submission.
+            for _ in 0 .. 2 {
+                queue_group.queues[0].as_raw_mut().submit(
+                    Submission {
+                        command_buffers: iter::once(&cmd_buffer),
+                        wait_semaphores: iter::empty::<(&Semaphore, _)>(),
+                        signal_semaphores: iter::empty::<&Semaphore>(),
+                    },
+                    None,
+                );
+                assert_eq!(results(&mut data), Ok(true));
+                // No samples passed, as nothing has been drawn.
+                assert_eq!(data, [0, 1, 0, 1]);
+            }
+
+            queue_group.queues[0].wait_idle().unwrap();
+            command_pool.free(iter::once(cmd_buffer));
+            device.destroy_command_pool(command_pool);
+            device.destroy_query_pool(pool);
+        }
+    }
+
+    #[test]
+    fn test_warp_occlusion_query_samples() {
+        use hal::command::RawCommandBuffer;
+        use hal::pass::{Attachment, AttachmentLoadOp, AttachmentOps, AttachmentStoreOp};
+        use hal::pass::{SubpassDependency, SubpassDesc};
+        use hal::query::{ControlFlags, PipelineStatistic, Query, ResultFlags, Type};
+        use hal::{image, Device};
+        use std::{iter, slice};
+
+        use test_utils::{begin_render_pass, clear_depth_stencil, graphics_pipeline_desc};
+
+        let (warp, mut queue_group) = Warp::open("test_warp_occlusion_query_samples");
+        let device = &warp.device;
+        unsafe {
+            let depth = warp.create_target(
+                Format::D32Sfloat,
+                (8, 8),
+                1,
+                image::Usage::DEPTH_STENCIL_ATTACHMENT,
+            );
+            let render_pass = device
+                .create_render_pass(
+                    iter::once(Attachment {
+                        format: Some(Format::D32Sfloat),
+                        samples: 1,
+                        ops: AttachmentOps::new(
+                            AttachmentLoadOp::Clear,
+                            AttachmentStoreOp::DontCare,
+                        ),
+                        stencil_ops: AttachmentOps::DONT_CARE,
+                        layouts: image::Layout::Undefined
+                            .. image::Layout::DepthStencilAttachmentOptimal,
+                    }),
+                    iter::once(SubpassDesc {
+                        colors: &[],
+                        depth_stencil: Some(&(0, image::Layout::DepthStencilAttachmentOptimal)),
+                        inputs: &[],
+                        resolves: &[],
+                        preserves: &[],
+                    }),
+                    iter::empty::<SubpassDependency>(),
+                )
+                .unwrap();
+            let framebuffer = warp.create_framebuffer(&render_pass, &[&depth.view], (8, 8));
+            let layout = warp.create_pipeline_layout(&[]);
+
+            // Two fullscreen triangles, the first one in front of the cleared depth
+            // and the second one behind it.
+            let modules = warp.create_shader_modules(&[(
+                pso::Stage::Vertex,
+                "vs_main",
+                b"float4 vs_main(uint id : SV_VertexID) : SV_Position {
+                    uint v = id % 3;
+                    float2 uv = float2((v << 1) & 2, v & 2);
+                    float depth = id < 3 ? 0.25 : 0.75;
+                    return float4(uv * float2(2.0, -2.0) + float2(-1.0, 1.0), depth, 1.0);
+                }",
+            )]);
+            let mut desc = graphics_pipeline_desc(&modules[0], None, &layout, &render_pass, 0);
+            desc.depth_stencil.depth = pso::DepthTest::On {
+                fun: pso::Comparison::Less,
+                write: false,
+            };
+            let pipeline = device.create_graphics_pipeline(&desc, None).unwrap();
+
+            let occlusion = device.create_query_pool(Type::Occlusion, 2).unwrap();
+            let statistics = PipelineStatistic::INPUT_ASSEMBLY_VERTICES
+                | PipelineStatistic::INPUT_ASSEMBLY_PRIMITIVES
+                | PipelineStatistic::VERTEX_SHADER_INVOCATIONS;
+            let pipeline_statistics = device
+                .create_query_pool(Type::PipelineStatistics(statistics), 1)
+                .unwrap();
+
+            warp.submit(&mut queue_group, |cmd_buffer| {
+                begin_render_pass(
+                    cmd_buffer,
+                    &render_pass,
+                    &framebuffer,
+                    (8, 8),
+                    iter::once(clear_depth_stencil(0.5, 0)),
+                );
+                cmd_buffer.bind_graphics_pipeline(&pipeline);
+                let statistics_query = || Query {
+                    pool: &pipeline_statistics,
+                    id: 0,
+                };
+                cmd_buffer.begin_query(statistics_query(), ControlFlags::empty());
+                for id in 0 .. 2 {
+                    let query = || Query {
+                        pool: &occlusion,
+                        id,
+                    };
+                    cmd_buffer.begin_query(query(), ControlFlags::PRECISE);
+                    cmd_buffer.draw(id * 3 .. id * 3 + 3, 0 .. 1);
+                    cmd_buffer.end_query(query());
+                }
+                cmd_buffer.end_query(statistics_query());
+                cmd_buffer.end_render_pass();
+            });
+
+            let flags = ResultFlags::WAIT | ResultFlags::BITS_64;
+            let mut samples = [0xFFu64; 2];
+            let bytes = slice::from_raw_parts_mut(samples.as_mut_ptr() as *mut u8, 16);
+            assert_eq!(
+                device.get_query_pool_results(&occlusion, 0 .. 2, bytes, 8, flags),
+                Ok(true)
+            );
+            // All 64 samples of the visible triangle pass, none of the occluded one.
+            assert_eq!(samples, [64, 0]);
+
+            let mut counters = [0xFFu64; 3];
+            let bytes = slice::from_raw_parts_mut(counters.as_mut_ptr() as *mut u8, 24);
+            assert_eq!(
+                device.get_query_pool_results(&pipeline_statistics, 0 .. 1, bytes, 24, flags),
+                Ok(true)
+            );
+            // Counters are written in the bit order of the enabled statistics.
+            assert_eq!(&counters[.. 2], &[6, 2]);
+            assert_ne!(counters[2], 0);
+
+            device.destroy_query_pool(pipeline_statistics);
+            device.destroy_query_pool(occlusion);
+            device.destroy_graphics_pipeline(pipeline);
+            warp.destroy_shader_modules(modules);
+            device.destroy_pipeline_layout(layout);
+            device.destroy_framebuffer(framebuffer);
+            device.destroy_render_pass(render_pass);
+            warp.destroy_target(depth);
         }
+    }
 
-        let heap_srv_cbv_uav = {
-            let mut heap_srv_cbv_uav = self.heap_srv_cbv_uav.lock().unwrap();
+    #[test]
+    fn test_warp_mapped_readback() {
+        use hal::memory::Properties;
+        use hal::{buffer, Device};
+        use std::slice;
 
-            let range = match num_srv_cbv_uav {
-                0 => 0 .. 0,
-                _ => heap_srv_cbv_uav
-                    .range_allocator
-                    .allocate_range(num_srv_cbv_uav as _)
-                    .unwrap(), // TODO: error/resize
-            };
+        let (warp, _queue_group) = Warp::open("test_warp_mapped_readback");
+        let device = &warp.device;
+        unsafe {
+            let buffer = device
+                .create_buffer(256, buffer::Usage::TRANSFER_DST)
+                .unwrap();
+            let requirements = device.get_buffer_requirements(&buffer);
+            let memory = warp.allocate(
+                requirements.type_mask,
+                requirements.size,
+                Properties::CPU_VISIBLE | Properties::CPU_CACHED,
+            );
 
-            r::DescriptorHeapSlice {
-                heap: heap_srv_cbv_uav.raw.clone(),
-                handle_size: heap_srv_cbv_uav.handle_size as _,
-                range_allocator: RangeAllocator::new(range),
-                start: heap_srv_cbv_uav.start,
-            }
-        };
+            let ptr = device.map_memory(&memory, 0 .. 256).unwrap();
+            slice::from_raw_parts_mut(ptr, 256).copy_from_slice(&[0xAB; 256]);
+            device
+                .flush_mapped_memory_ranges(Some((&memory, 0 .. 256)))
+                .unwrap();
+            device
+                .invalidate_mapped_memory_ranges(Some((&memory, 64 .. 128)))
+                .unwrap();
+            assert!(slice::from_raw_parts(ptr.offset(64), 64)
+                .iter()
+                .all(|&byte| byte == 0xAB));
+            device.unmap_memory(&memory);
+
+            device.destroy_buffer(buffer);
+            device.free_memory(memory);
+        }
+    }
+
+    #[test]
+    fn test_warp_shader_module_translations() {
+        use hal::Device;
+        use std::borrow::Cow;
+        use std::iter;
+        use std::ops::Range;
 
-        let heap_sampler = {
-            let mut heap_sampler = self.heap_sampler.lock().unwrap();
+        use super::COMPUTE_SPEC_WORKGROUP_SPIRV;
+        use resource::{DescriptorSetLayout, ShaderModule};
 
-            let range = match num_samplers {
-                0 => 0 .. 0,
-                _ => heap_sampler
-                    .range_allocator
-                    .allocate_range(num_samplers as _)
-                    .unwrap(), // TODO: error/resize
+        let (warp, _queue_group) = Warp::open("test_warp_shader_module_translations");
+        let device = &warp.device;
+        unsafe {
+            let module = device
+                .create_shader_module(&COMPUTE_SPEC_WORKGROUP_SPIRV)
+                .unwrap();
+            let layout = device
+                .create_pipeline_layout(
+                    iter::empty::<&DescriptorSetLayout>(),
+                    iter::empty::<(pso::ShaderStageFlags, Range<u32>)>(),
+                )
+                .unwrap();
+            let num_translations = |module: &ShaderModule| match *module {
+                ShaderModule::Spirv {
+                    ref translations, ..
+                } => translations.lock().unwrap().len(),
+                ShaderModule::Compiled(_) => 0,
             };
 
-            r::DescriptorHeapSlice {
-                heap: heap_sampler.raw.clone(),
-                handle_size: heap_sampler.handle_size as _,
-                range_allocator: RangeAllocator::new(range),
-                start: heap_sampler.start,
+            for &size in &[1u32, 8] {
+                let constant = pso::SpecializationConstant { id: 0, range: 0 .. 4 };
+                let specialization = pso::Specialization {
+                    constants: Cow::Owned(vec![constant]),
+                    data: Cow::Owned(size.to_le_bytes().to_vec()),
+                };
+                // Only the first pipeline translates the entry point.
+                for _ in 0 .. 100 {
+                    let entry = pso::EntryPoint {
+                        entry: "main",
+                        module: &module,
+                        specialization: specialization.clone(),
+                    };
+                    let desc = pso::ComputePipelineDesc::new(entry, &layout);
+                    let pipeline = device.create_compute_pipeline(&desc, None).unwrap();
+                    device.destroy_compute_pipeline(pipeline);
+                }
             }
-        };
+            assert_eq!(num_translations(&module), 2);
 
-        Ok(r::DescriptorPool {
-            heap_srv_cbv_uav,
-            heap_sampler,
-            pools: descriptor_pools,
-            max_size: max_sets as _,
-        })
+            device.destroy_pipeline_layout(layout);
+            device.destroy_shader_module(module);
+        }
     }
 
-    unsafe fn create_descriptor_set_layout<I, J>(
-        &self,
-        bindings: I,
-        _immutable_samplers: J,
-    ) -> Result<r::DescriptorSetLayout, d::OutOfMemory>
-    where
-        I: IntoIterator,
-        I::Item: Borrow<pso::DescriptorSetLayoutBinding>,
-        J: IntoIterator,
-        J::Item: Borrow<r::Sampler>,
-    {
-        Ok(r::DescriptorSetLayout {
-            bindings: bindings.into_iter().map(|b| b.borrow().clone()).collect(),
-        })
-    }
+    #[test]
+    fn test_warp_spec_constant_workgroup_size() {
+        use hal::command::RawCommandBuffer;
+        use hal::memory::{Barrier, Dependencies, Properties};
+        use hal::pso::{DescriptorPool, PipelineStage};
+        use hal::{buffer, Device};
+        use std::borrow::Cow;
+        use std::iter;
 
-    unsafe fn write_descriptor_sets<'a, I, J>(&self, write_iter: I)
-    where
-        I: IntoIterator<Item = pso::DescriptorSetWrite<'a, B, J>>,
-        J: IntoIterator,
-        J::Item: Borrow<pso::Descriptor<'a, B>>,
-    {
-        let mut descriptor_update_pools = self.descriptor_update_pools.lock().unwrap();
-        let mut update_pool_index = 0;
+        use super::COMPUTE_SPEC_WORKGROUP_COUNTER_SPIRV;
 
-        //TODO: combine destination ranges
-        let mut dst_samplers = Vec::new();
-        let mut dst_views = Vec::new();
-        let mut src_samplers = Vec::new();
-        let mut src_views = Vec::new();
-        let mut num_samplers = Vec::new();
-        let mut num_views = Vec::new();
+        let (warp, mut queue_group) = Warp::open("test_warp_spec_constant_workgroup_size");
+        let device = &warp.device;
+        unsafe {
+            let counter = warp.create_buffer(
+                4,
+                buffer::Usage::STORAGE | buffer::Usage::TRANSFER_SRC | buffer::Usage::TRANSFER_DST,
+                Properties::DEVICE_LOCAL,
+            );
+            let set_layout = device
+                .create_descriptor_set_layout(
+                    &[pso::DescriptorSetLayoutBinding {
+                        binding: 0,
+                        ty: pso::DescriptorType::StorageBuffer,
+                        count: 1,
+                        stage_flags: pso::ShaderStageFlags::COMPUTE,
+                        immutable_samplers: false,
+                    }],
+                    &[],
+                )
+                .unwrap();
+            let layout = warp.create_pipeline_layout(&[&set_layout]);
+            let mut pool = device
+                .create_descriptor_pool(
+                    1,
+                    iter::once(pso::DescriptorRangeDesc {
+                        ty: pso::DescriptorType::StorageBuffer,
+                        count: 1,
+                    }),
+                    pso::DescriptorPoolCreateFlags::empty(),
+                )
+                .unwrap();
+            let set = pool.allocate_set(&set_layout).unwrap();
+            device.write_descriptor_sets(iter::once(pso::DescriptorSetWrite {
+                set: &set,
+                binding: 0,
+                array_offset: 0,
+                descriptors: iter::once(pso::Descriptor::Buffer(&counter.0, None .. None)),
+            }));
+            let module = device
+                .create_shader_module(&COMPUTE_SPEC_WORKGROUP_COUNTER_SPIRV)
+                .unwrap();
+
+            // Two workgroups count twice the specialized workgroup size.
+            for &size in &[1u32, 8, 64] {
+                let entry = pso::EntryPoint {
+                    entry: "main",
+                    module: &module,
+                    specialization: pso::Specialization {
+                        constants: Cow::Owned(vec![pso::SpecializationConstant {
+                            id: 0,
+                            range: 0 .. 4,
+                        }]),
+                        data: Cow::Owned(size.to_le_bytes().to_vec()),
+                    },
+                };
+                let desc = pso::ComputePipelineDesc::new(entry, &layout);
+                let pipeline = device.create_compute_pipeline(&desc, None).unwrap();
+
+                warp.submit(&mut queue_group, |cmd_buffer| {
+                    cmd_buffer.fill_buffer(&counter.0, .., 0);
+                    cmd_buffer.pipeline_barrier(
+                        PipelineStage::TRANSFER .. PipelineStage::COMPUTE_SHADER,
+                        Dependencies::empty(),
+                        iter::once(Barrier::whole_buffer(
+                            &counter.0,
+                            buffer::Access::TRANSFER_WRITE
+                                .. buffer::Access::SHADER_READ | buffer::Access::SHADER_WRITE,
+                        )),
+                    );
+                    cmd_buffer.bind_compute_pipeline(&pipeline);
+                    cmd_buffer.bind_compute_descriptor_sets(&layout, 0, iter::once(&set), &[]);
+                    cmd_buffer.dispatch([2, 1, 1]);
+                    cmd_buffer.pipeline_barrier(
+                        PipelineStage::COMPUTE_SHADER .. PipelineStage::TRANSFER,
+                        Dependencies::empty(),
+                        iter::once(Barrier::whole_buffer(
+                            &counter.0,
+                            buffer::Access::SHADER_WRITE .. buffer::Access::TRANSFER_READ,
+                        )),
+                    );
+                });
+                let count = warp.read_buffer::<u32>(&mut queue_group, &counter.0, 1);
+                assert_eq!(count, vec![2 * size]);
 
-        for write in write_iter {
-            let mut offset = write.array_offset as u64;
-            let mut target_binding = write.binding as usize;
-            let mut bind_info = &write.set.binding_infos[target_binding];
-            for descriptor in write.descriptors {
-                // spill over the writes onto the next binding
-                while offset >= bind_info.count {
-                    assert_eq!(offset, bind_info.count);
-                    target_binding += 1;
-                    bind_info = &write.set.binding_infos[target_binding];
-                    offset = 0;
-                }
-                match *descriptor.borrow() {
-                    pso::Descriptor::Buffer(buffer, ref range) => {
-                        let buffer = buffer.expect_bound();
-                        if update_pool_index == descriptor_update_pools.len() {
-                            let max_size = 1u64 << 12; //arbitrary
-                            descriptor_update_pools.push(descriptors_cpu::HeapLinear::new(
-                                self.raw,
-                                descriptor::HeapType::CbvSrvUav,
-                                max_size as _,
-                            ));
-                        }
-                        let heap = descriptor_update_pools.last_mut().unwrap();
-                        let handle = heap.alloc_handle();
-                        if heap.is_full() {
-                            // pool is full, move to the next one
-                            update_pool_index += 1;
-                        }
-                        let start = range.start.unwrap_or(0);
-                        let end = range.end.unwrap_or(buffer.requirements.size as _);
+                device.destroy_compute_pipeline(pipeline);
+            }
 
-                        if bind_info.is_uav {
-                            assert_eq!((end - start) % 4, 0);
-                            let mut desc = d3d12::D3D12_UNORDERED_ACCESS_VIEW_DESC {
-                                Format: dxgiformat::DXGI_FORMAT_R32_TYPELESS,
-                                ViewDimension: d3d12::D3D12_UAV_DIMENSION_BUFFER,
-                                u: mem::zeroed(),
-                            };
-                            *desc.u.Buffer_mut() = d3d12::D3D12_BUFFER_UAV {
-                                FirstElement: start as _,
-                                NumElements: ((end - start) / 4) as _,
-                                StructureByteStride: 0,
-                                CounterOffsetInBytes: 0,
-                                Flags: d3d12::D3D12_BUFFER_UAV_FLAG_RAW,
-                            };
-                            self.raw.CreateUnorderedAccessView(
-                                buffer.resource.as_mut_ptr(),
-                                ptr::null_mut(),
-                                &desc,
-                                handle,
-                            );
-                        } else {
-                            // Making the size field of buffer requirements for uniform
-                            // buffers a multiple of 256 and setting the required offset
-                            // alignment to 256 allows us to patch the size here.
-                            // We can always enforce the size to be aligned to 256 for
-                            // CBVs without going out-of-bounds.
-                            let size = ((end - start) + 255) & !255;
-                            let desc = d3d12::D3D12_CONSTANT_BUFFER_VIEW_DESC {
-                                BufferLocation: (*buffer.resource).GetGPUVirtualAddress() + start,
-                                SizeInBytes: size as _,
-                            };
-                            self.raw.CreateConstantBufferView(&desc, handle);
-                        }
+            device.destroy_shader_module(module);
+            device.destroy_descriptor_pool(pool);
+            device.destroy_pipeline_layout(layout);
+            device.destroy_descriptor_set_layout(set_layout);
+            warp.destroy_buffer(counter);
+        }
+    }
 
-                        src_views.push(handle);
-                        dst_views.push(bind_info.view_range.as_ref().unwrap().at(offset));
-                        num_views.push(1);
-                    }
-                    pso::Descriptor::Image(image, _layout) => {
-                        let handle = if bind_info.is_uav {
-                            image.handle_uav.unwrap()
-                        } else {
-                            image.handle_srv.unwrap()
-                        };
-                        src_views.push(handle);
-                        dst_views.push(bind_info.view_range.as_ref().unwrap().at(offset));
-                        num_views.push(1);
-                    }
-                    pso::Descriptor::CombinedImageSampler(image, _layout, sampler) => {
-                        src_views.push(image.handle_srv.unwrap());
-                        dst_views.push(bind_info.view_range.as_ref().unwrap().at(offset));
-                        num_views.push(1);
-                        src_samplers.push(sampler.handle);
-                        dst_samplers.push(bind_info.sampler_range.as_ref().unwrap().at(offset));
-                        num_samplers.push(1);
-                    }
-                    pso::Descriptor::Sampler(sampler) => {
-                        src_samplers.push(sampler.handle);
-                        dst_samplers.push(bind_info.sampler_range.as_ref().unwrap().at(offset));
-                        num_samplers.push(1);
-                    }
-                    pso::Descriptor::UniformTexelBuffer(buffer_view) => {
-                        let handle = buffer_view.handle_srv;
-                        if handle.ptr != 0 {
-                            src_views.push(handle);
-                            dst_views.push(bind_info.view_range.as_ref().unwrap().at(offset));
-                            num_views.push(1);
-                        } else {
-                            error!("SRV handle of the uniform texel buffer is zero (not supported by specified format).");
-                        }
-                    }
-                    pso::Descriptor::StorageTexelBuffer(buffer_view) => {
-                        let handle = buffer_view.handle_uav;
-                        if handle.ptr != 0 {
-                            src_views.push(handle);
-                            dst_views.push(bind_info.view_range.as_ref().unwrap().at(offset));
-                            num_views.push(1);
-                        } else {
-                            error!("UAV handle of the storage texel buffer is zero (not supported by specified format).");
-                        }
-                    }
+    #[test]
+    fn test_warp_shader_module_from_dxbc() {
+        use hal::Device;
+        use std::iter;
+        use std::ops::Range;
+        use std::slice;
+
+        use resource::{DescriptorSetLayout, ShaderModule};
+
+        let (warp, _queue_group) = Warp::open("test_warp_shader_module_from_dxbc");
+        let device = &warp.device;
+        unsafe {
+            let hlsl = device
+                .create_shader_module_from_source(
+                    pso::Stage::Compute,
+                    "cs_main",
+                    "main",
+                    b"[numthreads(8, 1, 1)] void cs_main() {}",
+                )
+                .unwrap();
+            let bytecode = match hlsl {
+                ShaderModule::Compiled(ref shaders) => {
+                    let blob = shaders["main"];
+                    let size = blob.GetBufferSize();
+                    slice::from_raw_parts(blob.GetBufferPointer() as *const u8, size).to_vec()
                 }
-                offset += 1;
+                ShaderModule::Spirv { .. } => unreachable!(),
+            };
+            let dxbc = device.create_shader_module_from_dxbc("main", &bytecode).unwrap();
+            assert!(device.create_shader_module_from_dxbc("main", &[0; 16]).is_err());
+
+            let layout = device
+                .create_pipeline_layout(
+                    iter::empty::<&DescriptorSetLayout>(),
+                    iter::empty::<(pso::ShaderStageFlags, Range<u32>)>(),
+                )
+                .unwrap();
+            for &module in &[&hlsl, &dxbc] {
+                let entry = pso::EntryPoint {
+                    entry: "main",
+                    module,
+                    specialization: pso::Specialization::default(),
+                };
+                let desc = pso::ComputePipelineDesc::new(entry, &layout);
+                let pipeline = device.create_compute_pipeline(&desc, None).unwrap();
+                device.destroy_compute_pipeline(pipeline);
             }
-        }
 
-        if !num_views.is_empty() {
-            self.raw.clone().CopyDescriptors(
-                dst_views.len() as u32,
-                dst_views.as_ptr(),
-                num_views.as_ptr(),
-                src_views.len() as u32,
-                src_views.as_ptr(),
-                num_views.as_ptr(),
-                d3d12::D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV,
-            );
+            device.destroy_pipeline_layout(layout);
+            device.destroy_shader_module(hlsl);
+            device.destroy_shader_module(dxbc);
         }
-        if !num_samplers.is_empty() {
-            self.raw.clone().CopyDescriptors(
-                dst_samplers.len() as u32,
-                dst_samplers.as_ptr(),
-                num_samplers.as_ptr(),
-                src_samplers.len() as u32,
-                src_samplers.as_ptr(),
-                num_samplers.as_ptr(),
-                d3d12::D3D12_DESCRIPTOR_HEAP_TYPE_SAMPLER,
+    }
+
+    #[test]
+    fn test_warp_sampler_border_colors() {
+        use hal::command::RawCommandBuffer;
+        use hal::memory::{Barrier, Dependencies, Properties};
+        use hal::pso::{DescriptorPool, PipelineStage};
+        use hal::{buffer, image, Device};
+        use std::iter;
+
+        use test_utils::{entry_point, image_barrier};
+
+        let (warp, mut queue_group) = Warp::open("test_warp_sampler_border_colors");
+        let device = &warp.device;
+        unsafe {
+            let result = warp.create_buffer(
+                32,
+                buffer::Usage::STORAGE | buffer::Usage::TRANSFER_SRC,
+                Properties::DEVICE_LOCAL,
             );
-        }
+            let texture = warp.create_target(Format::Rgba8Unorm, (1, 1), 1, image::Usage::SAMPLED);
 
-        // reset the temporary CPU-size descriptor pools
-        for buffer_desc_pool in descriptor_update_pools.iter_mut() {
-            buffer_desc_pool.clear();
+            // A custom border color is only supported by dynamic samplers, the immutable
+            // sampler uses a fixed border color and is baked into the root signature.
+            let sampler_info = |border: [f32; 4]| image::SamplerInfo {
+                border: border.into(),
+                ..image::SamplerInfo::new(image::Filter::Nearest, image::WrapMode::Border)
+            };
+            let custom = device.create_sampler(sampler_info([0.0, 1.0, 0.0, 1.0])).unwrap();
+            let fixed = device.create_sampler(sampler_info([1.0, 1.0, 1.0, 1.0])).unwrap();
+
+            let binding = |binding, ty, immutable_samplers| pso::DescriptorSetLayoutBinding {
+                binding,
+                ty,
+                count: 1,
+                stage_flags: pso::ShaderStageFlags::COMPUTE,
+                immutable_samplers,
+            };
+            let set_layout = device
+                .create_descriptor_set_layout(
+                    &[
+                        binding(0, pso::DescriptorType::SampledImage, false),
+                        binding(1, pso::DescriptorType::Sampler, false),
+                        binding(2, pso::DescriptorType::Sampler, true),
+                        binding(3, pso::DescriptorType::StorageBuffer, false),
+                    ],
+                    iter::once(&fixed),
+                )
+                .unwrap();
+            assert_eq!(set_layout.static_samplers.len(), 1);
+            let layout = warp.create_pipeline_layout(&[&set_layout]);
+            let mut pool = device
+                .create_descriptor_pool(
+                    1,
+                    &[
+                        pso::DescriptorRangeDesc {
+                            ty: pso::DescriptorType::SampledImage,
+                            count: 1,
+                        },
+                        pso::DescriptorRangeDesc {
+                            ty: pso::DescriptorType::Sampler,
+                            count: 1,
+                        },
+                        pso::DescriptorRangeDesc {
+                            ty: pso::DescriptorType::StorageBuffer,
+                            count: 1,
+                        },
+                    ],
+                    pso::DescriptorPoolCreateFlags::empty(),
+                )
+                .unwrap();
+            let set = pool.allocate_set(&set_layout).unwrap();
+            device.write_descriptor_sets(vec![
+                pso::DescriptorSetWrite {
+                    set: &set,
+                    binding: 0,
+                    array_offset: 0,
+                    descriptors: vec![pso::Descriptor::Image(
+                        &texture.view,
+                        image::Layout::ShaderReadOnlyOptimal,
+                    )],
+                },
+                pso::DescriptorSetWrite {
+                    set: &set,
+                    binding: 1,
+                    array_offset: 0,
+                    descriptors: vec![pso::Descriptor::Sampler(&custom)],
+                },
+                pso::DescriptorSetWrite {
+                    set: &set,
+                    binding: 3,
+                    array_offset: 0,
+                    descriptors: vec![pso::Descriptor::Buffer(&result.0, None .. None)],
+                },
+            ]);
+
+            let modules = warp.create_shader_modules(&[(
+                pso::Stage::Compute,
+                "cs_main",
+                b"Texture2D tex : register(t0);
+                SamplerState custom : register(s1);
+                SamplerState fixed : register(s2);
+                RWByteAddressBuffer result : register(u3);
+                [numthreads(1, 1, 1)] void cs_main() {
+                    float2 uv = float2(-1.0, 2.0);
+                    result.Store4(0, asuint(tex.SampleLevel(custom, uv, 0)));
+                    result.Store4(16, asuint(tex.SampleLevel(fixed, uv, 0)));
+                }",
+            )]);
+            let pipeline = device
+                .create_compute_pipeline(
+                    &pso::ComputePipelineDesc::new(entry_point(&modules[0]), &layout),
+                    None,
+                )
+                .unwrap();
+
+            warp.submit(&mut queue_group, |cmd_buffer| {
+                cmd_buffer.pipeline_barrier(
+                    PipelineStage::TOP_OF_PIPE .. PipelineStage::COMPUTE_SHADER,
+                    Dependencies::empty(),
+                    iter::once(image_barrier(
+                        &texture.image,
+                        Aspects::COLOR,
+                        (image::Access::empty(), image::Layout::Undefined)
+                            .. (image::Access::SHADER_READ, image::Layout::ShaderReadOnlyOptimal),
+                    )),
+                );
+                cmd_buffer.bind_compute_pipeline(&pipeline);
+                cmd_buffer.bind_compute_descriptor_sets(&layout, 0, iter::once(&set), &[]);
+                cmd_buffer.dispatch([1, 1, 1]);
+                cmd_buffer.pipeline_barrier(
+                    PipelineStage::COMPUTE_SHADER .. PipelineStage::TRANSFER,
+                    Dependencies::empty(),
+                    iter::once(Barrier::whole_buffer(
+                        &result.0,
+                        buffer::Access::SHADER_WRITE .. buffer::Access::TRANSFER_READ,
+                    )),
+                );
+            });
+
+            let colors = warp.read_buffer::<f32>(&mut queue_group, &result.0, 8);
+            assert_eq!(&colors[.. 4], &[0.0, 1.0, 0.0, 1.0]);
+            assert_eq!(&colors[4 ..], &[1.0, 1.0, 1.0, 1.0]);
+
+            device.destroy_compute_pipeline(pipeline);
+            warp.destroy_shader_modules(modules);
+            device.destroy_descriptor_pool(pool);
+            device.destroy_pipeline_layout(layout);
+            device.destroy_descriptor_set_layout(set_layout);
+            device.destroy_sampler(custom);
+            device.destroy_sampler(fixed);
+            warp.destroy_target(texture);
+            warp.destroy_buffer(result);
         }
     }
 
-    unsafe fn copy_descriptor_sets<'a, I>(&self, copy_iter: I)
-    where
-        I: IntoIterator,
-        I::Item: Borrow<pso::DescriptorSetCopy<'a, B>>,
-    {
-        let mut dst_samplers = Vec::new();
-        let mut dst_views = Vec::new();
-        let mut src_samplers = Vec::new();
-        let mut src_views = Vec::new();
-        let mut num_samplers = Vec::new();
-        let mut num_views = Vec::new();
+    #[test]
+    fn test_warp_comparison_sampler() {
+        use hal::command::{BufferImageCopy, RawCommandBuffer};
+        use hal::memory::{Barrier, Dependencies, Properties};
+        use hal::pso::{DescriptorPool, PipelineStage};
+        use hal::{buffer, image, Device};
+        use std::iter;
 
-        for copy_wrap in copy_iter {
-            let copy = copy_wrap.borrow();
-            let src_info = &copy.src_set.binding_infos[copy.src_binding as usize];
-            let dst_info = &copy.dst_set.binding_infos[copy.dst_binding as usize];
-            if let (Some(src_range), Some(dst_range)) =
-                (src_info.view_range.as_ref(), dst_info.view_range.as_ref())
-            {
-                assert!(copy.src_array_offset + copy.count <= src_range.count as usize);
-                assert!(copy.dst_array_offset + copy.count <= dst_range.count as usize);
-                src_views.push(src_range.at(copy.src_array_offset as _));
-                dst_views.push(dst_range.at(copy.dst_array_offset as _));
-                num_views.push(copy.count as u32);
-            }
-            if let (Some(src_range), Some(dst_range)) = (
-                src_info.sampler_range.as_ref(),
-                dst_info.sampler_range.as_ref(),
-            ) {
-                assert!(copy.src_array_offset + copy.count <= src_range.count as usize);
-                assert!(copy.dst_array_offset + copy.count <= dst_range.count as usize);
-                src_samplers.push(src_range.at(copy.src_array_offset as _));
-                dst_samplers.push(dst_range.at(copy.dst_array_offset as _));
-                num_samplers.push(copy.count as u32);
-            }
+        use test_utils::{entry_point, image_barrier};
+
+        let (warp, mut queue_group) = Warp::open("test_warp_comparison_sampler");
+        let device = &warp.device;
+        unsafe {
+            // Two texels, the edge between them is sampled with a reference of 0.5.
+            let upload =
+                warp.create_buffer_with_data(&[0.25f32, 0.75], buffer::Usage::TRANSFER_SRC);
+            let result = warp.create_buffer(
+                4,
+                buffer::Usage::STORAGE | buffer::Usage::TRANSFER_SRC,
+                Properties::DEVICE_LOCAL,
+            );
+            let depth = warp.create_target(
+                Format::D32Sfloat,
+                (2, 1),
+                1,
+                image::Usage::SAMPLED | image::Usage::TRANSFER_DST,
+            );
+            let sampler = device
+                .create_sampler(image::SamplerInfo {
+                    comparison: Some(pso::Comparison::LessEqual),
+                    ..image::SamplerInfo::new(image::Filter::Linear, image::WrapMode::Clamp)
+                })
+                .unwrap();
+
+            let bindings = [
+                (0, pso::DescriptorType::SampledImage),
+                (1, pso::DescriptorType::Sampler),
+                (2, pso::DescriptorType::StorageBuffer),
+            ]
+            .iter()
+            .map(|&(binding, ty)| pso::DescriptorSetLayoutBinding {
+                binding,
+                ty,
+                count: 1,
+                stage_flags: pso::ShaderStageFlags::COMPUTE,
+                immutable_samplers: false,
+            })
+            .collect::<Vec<_>>();
+            let set_layout = device
+                .create_descriptor_set_layout(&bindings, &[])
+                .unwrap();
+            let layout = warp.create_pipeline_layout(&[&set_layout]);
+            let mut pool = device
+                .create_descriptor_pool(
+                    1,
+                    bindings.iter().map(|binding| pso::DescriptorRangeDesc {
+                        ty: binding.ty,
+                        count: 1,
+                    }),
+                    pso::DescriptorPoolCreateFlags::empty(),
+                )
+                .unwrap();
+            let set = pool.allocate_set(&set_layout).unwrap();
+            device.write_descriptor_sets(iter::once(pso::DescriptorSetWrite {
+                set: &set,
+                binding: 0,
+                array_offset: 0,
+                descriptors: vec![
+                    pso::Descriptor::Image(&depth.view, image::Layout::ShaderReadOnlyOptimal),
+                    pso::Descriptor::Sampler(&sampler),
+                    pso::Descriptor::Buffer(&result.0, None .. None),
+                ],
+            }));
+
+            let modules = warp.create_shader_modules(&[(
+                pso::Stage::Compute,
+                "cs_main",
+                b"Texture2D<float> depth : register(t0);
+                SamplerComparisonState cmp : register(s1);
+                RWByteAddressBuffer result : register(u2);
+                [numthreads(1, 1, 1)] void cs_main() {
+                    float lit = depth.SampleCmpLevelZero(cmp, float2(0.5, 0.5), 0.5);
+                    result.Store(0, asuint(lit));
+                }",
+            )]);
+            let pipeline = device
+                .create_compute_pipeline(
+                    &pso::ComputePipelineDesc::new(entry_point(&modules[0]), &layout),
+                    None,
+                )
+                .unwrap();
+
+            warp.submit(&mut queue_group, |cmd_buffer| {
+                cmd_buffer.pipeline_barrier(
+                    PipelineStage::TOP_OF_PIPE .. PipelineStage::TRANSFER,
+                    Dependencies::empty(),
+                    iter::once(image_barrier(
+                        &depth.image,
+                        Aspects::DEPTH,
+                        (image::Access::empty(), image::Layout::Undefined)
+                            .. (image::Access::TRANSFER_WRITE, image::Layout::TransferDstOptimal),
+                    )),
+                );
+                cmd_buffer.copy_buffer_to_image(
+                    &upload.0,
+                    &depth.image,
+                    image::Layout::TransferDstOptimal,
+                    iter::once(BufferImageCopy {
+                        buffer_offset: 0,
+                        buffer_width: 2,
+                        buffer_height: 1,
+                        image_layers: image::SubresourceLayers {
+                            aspects: Aspects::DEPTH,
+                            level: 0,
+                            layers: 0 .. 1,
+                        },
+                        image_offset: image::Offset::ZERO,
+                        image_extent: image::Extent {
+                            width: 2,
+                            height: 1,
+                            depth: 1,
+                        },
+                    }),
+                );
+                cmd_buffer.pipeline_barrier(
+                    PipelineStage::TRANSFER .. PipelineStage::COMPUTE_SHADER,
+                    Dependencies::empty(),
+                    iter::once(image_barrier(
+                        &depth.image,
+                        Aspects::DEPTH,
+                        (image::Access::TRANSFER_WRITE, image::Layout::TransferDstOptimal)
+                            .. (image::Access::SHADER_READ, image::Layout::ShaderReadOnlyOptimal),
+                    )),
+                );
+                cmd_buffer.bind_compute_pipeline(&pipeline);
+                cmd_buffer.bind_compute_descriptor_sets(&layout, 0, iter::once(&set), &[]);
+                cmd_buffer.dispatch([1, 1, 1]);
+                cmd_buffer.pipeline_barrier(
+                    PipelineStage::COMPUTE_SHADER .. PipelineStage::TRANSFER,
+                    Dependencies::empty(),
+                    iter::once(Barrier::whole_buffer(
+                        &result.0,
+                        buffer::Access::SHADER_WRITE .. buffer::Access::TRANSFER_READ,
+                    )),
+                );
+            });
+
+            // Only the far texel passes the comparison, filtering both results gives 0.5.
+            assert_eq!(warp.read_buffer::<f32>(&mut queue_group, &result.0, 1), vec![0.5]);
+
+            device.destroy_compute_pipeline(pipeline);
+            warp.destroy_shader_modules(modules);
+            device.destroy_descriptor_pool(pool);
+            device.destroy_pipeline_layout(layout);
+            device.destroy_descriptor_set_layout(set_layout);
+            device.destroy_sampler(sampler);
+            warp.destroy_target(depth);
+            warp.destroy_buffer(upload);
+            warp.destroy_buffer(result);
         }
+    }
 
-        if !num_views.is_empty() {
-            self.raw.clone().CopyDescriptors(
-                dst_views.len() as u32,
-                dst_views.as_ptr(),
-                num_views.as_ptr(),
-                src_views.len() as u32,
-                src_views.as_ptr(),
-                num_views.as_ptr(),
-                d3d12::D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV,
+    #[test]
+    fn test_warp_min_reduction_sampler() {
+        use hal::command::{BufferImageCopy, RawCommandBuffer};
+        use hal::memory::{Barrier, Dependencies, Properties};
+        use hal::pso::{DescriptorPool, PipelineStage};
+        use hal::{buffer, image, Device};
+        use std::iter;
+
+        use test_utils::{entry_point, image_barrier};
+
+        const FEATURES: Features = Features::SAMPLER_REDUCTION;
+        let (warp, mut queue_group) =
+            match Warp::open_with_features("test_warp_min_reduction_sampler", FEATURES) {
+                Some(warp) => warp,
+                None => return,
+            };
+        let device = &warp.device;
+        unsafe {
+            // 2x2 texels with rows of 256 bytes, sampled in the center of the footprint.
+            let mut texels = vec![0.0f32; 128];
+            texels[.. 2].copy_from_slice(&[0.75, 0.25]);
+            texels[64 .. 66].copy_from_slice(&[0.5, 1.0]);
+            let upload = warp.create_buffer_with_data(&texels, buffer::Usage::TRANSFER_SRC);
+            let result = warp.create_buffer(
+                4,
+                buffer::Usage::STORAGE | buffer::Usage::TRANSFER_SRC,
+                Properties::DEVICE_LOCAL,
             );
-        }
-        if !num_samplers.is_empty() {
-            self.raw.clone().CopyDescriptors(
-                dst_samplers.len() as u32,
-                dst_samplers.as_ptr(),
-                num_samplers.as_ptr(),
-                src_samplers.len() as u32,
-                src_samplers.as_ptr(),
-                num_samplers.as_ptr(),
-                d3d12::D3D12_DESCRIPTOR_HEAP_TYPE_SAMPLER,
+            let texture = warp.create_target(
+                Format::R32Sfloat,
+                (2, 2),
+                1,
+                image::Usage::SAMPLED | image::Usage::TRANSFER_DST,
             );
+            let sampler = device
+                .create_sampler(image::SamplerInfo {
+                    reduction: image::ReductionMode::Minimum,
+                    ..image::SamplerInfo::new(image::Filter::Linear, image::WrapMode::Clamp)
+                })
+                .unwrap();
+
+            let bindings = [
+                (0, pso::DescriptorType::SampledImage),
+                (1, pso::DescriptorType::Sampler),
+                (2, pso::DescriptorType::StorageBuffer),
+            ]
+            .iter()
+            .map(|&(binding, ty)| pso::DescriptorSetLayoutBinding {
+                binding,
+                ty,
+                count: 1,
+                stage_flags: pso::ShaderStageFlags::COMPUTE,
+                immutable_samplers: false,
+            })
+            .collect::<Vec<_>>();
+            let set_layout = device
+                .create_descriptor_set_layout(&bindings, &[])
+                .unwrap();
+            let layout = warp.create_pipeline_layout(&[&set_layout]);
+            let mut pool = device
+                .create_descriptor_pool(
+                    1,
+                    bindings.iter().map(|binding| pso::DescriptorRangeDesc {
+                        ty: binding.ty,
+                        count: 1,
+                    }),
+                    pso::DescriptorPoolCreateFlags::empty(),
+                )
+                .unwrap();
+            let set = pool.allocate_set(&set_layout).unwrap();
+            device.write_descriptor_sets(iter::once(pso::DescriptorSetWrite {
+                set: &set,
+                binding: 0,
+                array_offset: 0,
+                descriptors: vec![
+                    pso::Descriptor::Image(&texture.view, image::Layout::ShaderReadOnlyOptimal),
+                    pso::Descriptor::Sampler(&sampler),
+                    pso::Descriptor::Buffer(&result.0, None .. None),
+                ],
+            }));
+
+            let modules = warp.create_shader_modules(&[(
+                pso::Stage::Compute,
+                "cs_main",
+                b"Texture2D<float> tex : register(t0);
+                SamplerState reduce : register(s1);
+                RWByteAddressBuffer result : register(u2);
+                [numthreads(1, 1, 1)] void cs_main() {
+                    result.Store(0, asuint(tex.SampleLevel(reduce, float2(0.5, 0.5), 0)));
+                }",
+            )]);
+            let pipeline = device
+                .create_compute_pipeline(
+                    &pso::ComputePipelineDesc::new(entry_point(&modules[0]), &layout),
+                    None,
+                )
+                .unwrap();
+
+            warp.submit(&mut queue_group, |cmd_buffer| {
+                cmd_buffer.pipeline_barrier(
+                    PipelineStage::TOP_OF_PIPE .. PipelineStage::TRANSFER,
+                    Dependencies::empty(),
+                    iter::once(image_barrier(
+                        &texture.image,
+                        Aspects::COLOR,
+                        (image::Access::empty(), image::Layout::Undefined)
+                            .. (image::Access::TRANSFER_WRITE, image::Layout::TransferDstOptimal),
+                    )),
+                );
+                cmd_buffer.copy_buffer_to_image(
+                    &upload.0,
+                    &texture.image,
+                    image::Layout::TransferDstOptimal,
+                    iter::once(BufferImageCopy {
+                        buffer_offset: 0,
+                        buffer_width: 64,
+                        buffer_height: 2,
+                        image_layers: image::SubresourceLayers {
+                            aspects: Aspects::COLOR,
+                            level: 0,
+                            layers: 0 .. 1,
+                        },
+                        image_offset: image::Offset::ZERO,
+                        image_extent: image::Extent {
+                            width: 2,
+                            height: 2,
+                            depth: 1,
+                        },
+                    }),
+                );
+                cmd_buffer.pipeline_barrier(
+                    PipelineStage::TRANSFER .. PipelineStage::COMPUTE_SHADER,
+                    Dependencies::empty(),
+                    iter::once(image_barrier(
+                        &texture.image,
+                        Aspects::COLOR,
+                        (image::Access::TRANSFER_WRITE, image::Layout::TransferDstOptimal)
+                            .. (image::Access::SHADER_READ, image::Layout::ShaderReadOnlyOptimal),
+                    )),
+                );
+                cmd_buffer.bind_compute_pipeline(&pipeline);
+                cmd_buffer.bind_compute_descriptor_sets(&layout, 0, iter::once(&set), &[]);
+                cmd_buffer.dispatch([1, 1, 1]);
+                cmd_buffer.pipeline_barrier(
+                    PipelineStage::COMPUTE_SHADER .. PipelineStage::TRANSFER,
+                    Dependencies::empty(),
+                    iter::once(Barrier::whole_buffer(
+                        &result.0,
+                        buffer::Access::SHADER_WRITE .. buffer::Access::TRANSFER_READ,
+                    )),
+                );
+            });
+
+            // All four texels have a non-zero weight, the minimum of them is returned.
+            assert_eq!(warp.read_buffer::<f32>(&mut queue_group, &result.0, 1), vec![0.25]);
+
+            device.destroy_compute_pipeline(pipeline);
+            warp.destroy_shader_modules(modules);
+            device.destroy_descriptor_pool(pool);
+            device.destroy_pipeline_layout(layout);
+            device.destroy_descriptor_set_layout(set_layout);
+            device.destroy_sampler(sampler);
+            warp.destroy_target(texture);
+            warp.destroy_buffer(upload);
+            warp.destroy_buffer(result);
         }
     }
 
-    unsafe fn map_memory<R>(&self, memory: &r::Memory, range: R) -> Result<*mut u8, mapping::Error>
-    where
-        R: RangeArg<u64>,
-    {
-        if let Some(mem) = memory.resource {
-            let start = range.start().unwrap_or(&0);
-            let end = range.end().unwrap_or(&memory.size);
-            assert!(start <= end);
+    #[test]
+    fn test_warp_conservative_rasterization() {
+        use hal::command::RawCommandBuffer;
+        use hal::{image, Device};
+        use std::iter;
+
+        use test_utils::{begin_render_pass, clear_color, graphics_pipeline_desc};
+
+        // A small triangle in the corner of the left texel, missing its center.
+        let sources = [
+            (
+                pso::Stage::Vertex,
+                "vs_main",
+                &b"float4 vs_main(uint id : SV_VertexID) : SV_Position {
+                    static const float2 positions[3] = {
+                        float2(-0.9, 0.9), float2(-0.8, 0.9), float2(-0.9, 0.8)
+                    };
+                    return float4(positions[id], 0.0, 1.0);
+                }"[..],
+            ),
+            (
+                pso::Stage::Fragment,
+                "ps_main",
+                &b"float4 ps_main() : SV_Target { return float4(1.0, 0.0, 0.0, 1.0); }"[..],
+            ),
+        ];
+
+        // Enabling conservative rasterization requires the feature.
+        {
+            let (warp, _queue_group) = Warp::open("test_warp_conservative_rasterization");
+            let device = &warp.device;
+            unsafe {
+                let render_pass = warp.create_color_pass(Format::Rgba8Unorm);
+                let layout = warp.create_pipeline_layout(&[]);
+                let modules = warp.create_shader_modules(&sources);
+                let mut desc = graphics_pipeline_desc(
+                    &modules[0],
+                    Some(&modules[1]),
+                    &layout,
+                    &render_pass,
+                    0,
+                );
+                desc.rasterizer.conservative = true;
+                assert_eq!(
+                    device.create_graphics_pipeline(&desc, None).err(),
+                    Some(pso::CreationError::Other)
+                );
 
-            let mut ptr = ptr::null_mut();
-            assert_eq!(
-                winerror::S_OK,
-                (*mem).Map(0, &d3d12::D3D12_RANGE { Begin: 0, End: 0 }, &mut ptr)
-            );
-            ptr = ptr.offset(*start as _);
-            Ok(ptr as *mut _)
-        } else {
-            panic!("Memory not created with a memory type exposing `CPU_VISIBLE`.")
+                warp.destroy_shader_modules(modules);
+                device.destroy_pipeline_layout(layout);
+                device.destroy_render_pass(render_pass);
+            }
         }
-    }
 
-    unsafe fn unmap_memory(&self, memory: &r::Memory) {
-        if let Some(mem) = memory.resource {
-            (*mem).Unmap(0, &d3d12::D3D12_RANGE { Begin: 0, End: 0 });
+        let (warp, mut queue_group) = match Warp::open_with_features(
+            "test_warp_conservative_rasterization",
+            Features::CONSERVATIVE_RASTERIZATION,
+        ) {
+            Some(warp) => warp,
+            None => return,
+        };
+        let device = &warp.device;
+        unsafe {
+            let target = warp.create_target(
+                Format::Rgba8Unorm,
+                (2, 1),
+                1,
+                image::Usage::COLOR_ATTACHMENT | image::Usage::TRANSFER_SRC,
+            );
+            let render_pass = warp.create_color_pass(Format::Rgba8Unorm);
+            let framebuffer = warp.create_framebuffer(&render_pass, &[&target.view], (2, 1));
+            let layout = warp.create_pipeline_layout(&[]);
+            let modules = warp.create_shader_modules(&sources);
+            let mut desc =
+                graphics_pipeline_desc(&modules[0], Some(&modules[1]), &layout, &render_pass, 0);
+            desc.rasterizer.conservative = true;
+            let pipeline = device.create_graphics_pipeline(&desc, None).unwrap();
+
+            warp.submit(&mut queue_group, |cmd_buffer| {
+                begin_render_pass(
+                    cmd_buffer,
+                    &render_pass,
+                    &framebuffer,
+                    (2, 1),
+                    iter::once(clear_color([0.0; 4])),
+                );
+                cmd_buffer.bind_graphics_pipeline(&pipeline);
+                cmd_buffer.draw(0 .. 3, 0 .. 1);
+                cmd_buffer.end_render_pass();
+            });
+
+            // The partially covered texel is rasterized.
+            let texels =
+                warp.read_image::<[u8; 4]>(&mut queue_group, &target.image, Aspects::COLOR, (2, 1));
+            assert_eq!(texels, vec![[0xFF, 0, 0, 0xFF], [0, 0, 0, 0]]);
+
+            device.destroy_graphics_pipeline(pipeline);
+            warp.destroy_shader_modules(modules);
+            device.destroy_pipeline_layout(layout);
+            device.destroy_framebuffer(framebuffer);
+            device.destroy_render_pass(render_pass);
+            warp.destroy_target(target);
         }
     }
 
-    unsafe fn flush_mapped_memory_ranges<'a, I, R>(&self, ranges: I) -> Result<(), d::OutOfMemory>
-    where
-        I: IntoIterator,
-        I::Item: Borrow<(&'a r::Memory, R)>,
-        R: RangeArg<u64>,
-    {
-        for range in ranges {
-            let &(ref memory, ref range) = range.borrow();
-            if let Some(mem) = memory.resource {
-                // map and immediately unmap, hoping that dx12 drivers internally cache
-                // currently mapped buffers.
-                assert_eq!(
-                    winerror::S_OK,
-                    (*mem).Map(0, &d3d12::D3D12_RANGE { Begin: 0, End: 0 }, ptr::null_mut())
-                );
+    #[test]
+    fn test_warp_primitive_restart() {
+        use hal::command::RawCommandBuffer;
+        use hal::{buffer, image, Device, IndexType, Primitive};
+        use std::iter;
 
-                let start = *range.start().unwrap_or(&0);
-                let end = *range.end().unwrap_or(&memory.size); // TODO: only need to be end of current mapping
+        use test_utils::{begin_render_pass, clear_color, graphics_pipeline_desc};
 
-                (*mem).Unmap(
-                    0,
-                    &d3d12::D3D12_RANGE {
-                        Begin: start as _,
-                        End: end as _,
-                    },
+        let (warp, mut queue_group) = Warp::open("test_warp_primitive_restart");
+        let device = &warp.device;
+        unsafe {
+            let target = warp.create_target(
+                Format::Rgba8Unorm,
+                (4, 2),
+                1,
+                image::Usage::COLOR_ATTACHMENT | image::Usage::TRANSFER_SRC,
+            );
+            // Two strips of two triangles, separated by a cut index.
+            let indices = [0u16, 1, 2, 3, 0xFFFF, 4, 5, 6, 7];
+            let index_buffer = warp.create_buffer_with_data(&indices, buffer::Usage::INDEX);
+            let render_pass = warp.create_color_pass(Format::Rgba8Unorm);
+            let framebuffer = warp.create_framebuffer(&render_pass, &[&target.view], (4, 2));
+            let layout = warp.create_pipeline_layout(&[]);
+
+            // The strips cover the first and last column of the target, a triangle
+            // connecting both strips would cover the columns in between.
+            let modules = warp.create_shader_modules(&[
+                (
+                    pso::Stage::Vertex,
+                    "vs_main",
+                    b"static const float2 positions[8] = {
+                        float2(-1.0, 1.0), float2(-0.5, 1.0),
+                        float2(-1.0, -1.0), float2(-0.5, -1.0),
+                        float2(0.5, 1.0), float2(1.0, 1.0),
+                        float2(0.5, -1.0), float2(1.0, -1.0),
+                    };
+                    float4 vs_main(uint id : SV_VertexID) : SV_Position {
+                        return float4(positions[id], 0.0, 1.0);
+                    }",
+                ),
+                (
+                    pso::Stage::Fragment,
+                    "ps_main",
+                    b"float4 ps_main() : SV_Target { return float4(1.0, 1.0, 1.0, 1.0); }",
+                ),
+            ]);
+            let mut desc =
+                graphics_pipeline_desc(&modules[0], Some(&modules[1]), &layout, &render_pass, 0);
+            desc.input_assembler.primitive = Primitive::TriangleStrip;
+            desc.input_assembler.primitive_restart = pso::PrimitiveRestart::U16;
+            let pipeline = device.create_graphics_pipeline(&desc, None).unwrap();
+
+            // Lists can't be restarted.
+            desc.input_assembler.primitive = Primitive::TriangleList;
+            assert!(device.create_graphics_pipeline(&desc, None).is_err());
+
+            warp.submit(&mut queue_group, |cmd_buffer| {
+                begin_render_pass(
+                    cmd_buffer,
+                    &render_pass,
+                    &framebuffer,
+                    (4, 2),
+                    iter::once(clear_color([0.0; 4])),
                 );
+                cmd_buffer.bind_graphics_pipeline(&pipeline);
+                cmd_buffer.bind_index_buffer(buffer::IndexBufferView {
+                    buffer: &index_buffer.0,
+                    offset: 0,
+                    index_type: IndexType::U16,
+                });
+                cmd_buffer.draw_indexed(0 .. indices.len() as u32, 0, 0 .. 1);
+                cmd_buffer.end_render_pass();
+            });
+
+            let texels =
+                warp.read_image::<[u8; 4]>(&mut queue_group, &target.image, Aspects::COLOR, (4, 2));
+            for (i, texel) in texels.iter().enumerate() {
+                let covered = i % 4 == 0 || i % 4 == 3;
+                assert_eq!(*texel, if covered { [0xFF; 4] } else { [0; 4] });
             }
-        }
 
-        Ok(())
+            device.destroy_graphics_pipeline(pipeline);
+            warp.destroy_shader_modules(modules);
+            device.destroy_pipeline_layout(layout);
+            device.destroy_framebuffer(framebuffer);
+            device.destroy_render_pass(render_pass);
+            warp.destroy_buffer(index_buffer);
+            warp.destroy_target(target);
+        }
     }
 
-    unsafe fn invalidate_mapped_memory_ranges<'a, I, R>(
-        &self,
-        ranges: I,
-    ) -> Result<(), d::OutOfMemory>
-    where
-        I: IntoIterator,
-        I::Item: Borrow<(&'a r::Memory, R)>,
-        R: RangeArg<u64>,
-    {
-        for range in ranges {
-            let &(ref memory, ref range) = range.borrow();
-            if let Some(mem) = memory.resource {
-                let start = *range.start().unwrap_or(&0);
-                let end = *range.end().unwrap_or(&memory.size); // TODO: only need to be end of current mapping
+    #[test]
+    fn test_warp_input_attachments() {
+        use hal::command::{RawCommandBuffer, SubpassContents};
+        use hal::pass::{Attachment, AttachmentLoadOp, AttachmentOps, AttachmentStoreOp};
+        use hal::pass::{SubpassDependency, SubpassDesc, SubpassRef};
+        use hal::pso::{DescriptorPool, PipelineStage};
+        use hal::{image, Device};
+        use std::iter;
 
-                // map and immediately unmap, hoping that dx12 drivers internally cache
-                // currently mapped buffers.
-                assert_eq!(
-                    winerror::S_OK,
-                    (*mem).Map(
-                        0,
-                        &d3d12::D3D12_RANGE {
-                            Begin: start as _,
-                            End: end as _,
+        use test_utils::{begin_render_pass, clear_color, graphics_pipeline_desc, FULLSCREEN_VS};
+
+        let (warp, mut queue_group) = Warp::open("test_warp_input_attachments");
+        let device = &warp.device;
+        unsafe {
+            // Deferred lighting, the first subpass renders the albedo and normals which are
+            // read as input attachments by the second one.
+            let input = image::Usage::COLOR_ATTACHMENT | image::Usage::INPUT_ATTACHMENT;
+            let albedo = warp.create_target(Format::Rgba8Unorm, (2, 1), 1, input);
+            let normal = warp.create_target(Format::Rgba16Sfloat, (2, 1), 1, input);
+            let target = warp.create_target(
+                Format::Rgba8Unorm,
+                (2, 1),
+                1,
+                image::Usage::COLOR_ATTACHMENT | image::Usage::TRANSFER_SRC,
+            );
+
+            let attachment = |format, store, layout| Attachment {
+                format: Some(format),
+                samples: 1,
+                ops: AttachmentOps::new(AttachmentLoadOp::Clear, store),
+                stencil_ops: AttachmentOps::DONT_CARE,
+                layouts: image::Layout::Undefined .. layout,
+            };
+            let render_pass = device
+                .create_render_pass(
+                    vec![
+                        attachment(
+                            Format::Rgba8Unorm,
+                            AttachmentStoreOp::DontCare,
+                            image::Layout::ShaderReadOnlyOptimal,
+                        ),
+                        attachment(
+                            Format::Rgba16Sfloat,
+                            AttachmentStoreOp::DontCare,
+                            image::Layout::ShaderReadOnlyOptimal,
+                        ),
+                        attachment(
+                            Format::Rgba8Unorm,
+                            AttachmentStoreOp::Store,
+                            image::Layout::TransferSrcOptimal,
+                        ),
+                    ],
+                    vec![
+                        SubpassDesc {
+                            colors: &[
+                                (0, image::Layout::ColorAttachmentOptimal),
+                                (1, image::Layout::ColorAttachmentOptimal),
+                            ],
+                            depth_stencil: None,
+                            inputs: &[],
+                            resolves: &[],
+                            preserves: &[],
                         },
-                        ptr::null_mut(),
-                    )
+                        SubpassDesc {
+                            colors: &[(2, image::Layout::ColorAttachmentOptimal)],
+                            depth_stencil: None,
+                            inputs: &[
+                                (0, image::Layout::ShaderReadOnlyOptimal),
+                                (1, image::Layout::ShaderReadOnlyOptimal),
+                            ],
+                            resolves: &[],
+                            preserves: &[],
+                        },
+                    ],
+                    iter::once(SubpassDependency {
+                        passes: SubpassRef::Pass(0) .. SubpassRef::Pass(1),
+                        stages: PipelineStage::COLOR_ATTACHMENT_OUTPUT
+                            .. PipelineStage::FRAGMENT_SHADER,
+                        accesses: image::Access::COLOR_ATTACHMENT_WRITE
+                            .. image::Access::INPUT_ATTACHMENT_READ,
+                    }),
+                )
+                .unwrap();
+            let framebuffer = warp.create_framebuffer(
+                &render_pass,
+                &[&albedo.view, &normal.view, &target.view],
+                (2, 1),
+            );
+            let bindings = (0 .. 2)
+                .map(|binding| pso::DescriptorSetLayoutBinding {
+                    binding,
+                    ty: pso::DescriptorType::InputAttachment,
+                    count: 1,
+                    stage_flags: pso::ShaderStageFlags::FRAGMENT,
+                    immutable_samplers: false,
+                })
+                .collect::<Vec<_>>();
+            let set_layout = device
+                .create_descriptor_set_layout(&bindings, &[])
+                .unwrap();
+            let layouts = vec![
+                warp.create_pipeline_layout(&[]),
+                warp.create_pipeline_layout(&[&set_layout]),
+            ];
+            let mut pool = device
+                .create_descriptor_pool(
+                    1,
+                    iter::once(pso::DescriptorRangeDesc {
+                        ty: pso::DescriptorType::InputAttachment,
+                        count: 2,
+                    }),
+                    pso::DescriptorPoolCreateFlags::empty(),
+                )
+                .unwrap();
+            let set = pool.allocate_set(&set_layout).unwrap();
+            device.write_descriptor_sets(iter::once(pso::DescriptorSetWrite {
+                set: &set,
+                binding: 0,
+                array_offset: 0,
+                descriptors: vec![
+                    pso::Descriptor::Image(&albedo.view, image::Layout::ShaderReadOnlyOptimal),
+                    pso::Descriptor::Image(&normal.view, image::Layout::ShaderReadOnlyOptimal),
+                ],
+            }));
+
+            // The left texel faces the light, the right one is perpendicular to it.
+            let modules = warp.create_shader_modules(&[
+                FULLSCREEN_VS,
+                (
+                    pso::Stage::Fragment,
+                    "ps_main",
+                    b"struct GBuffer {
+                        float4 albedo : SV_Target0;
+                        float4 normal : SV_Target1;
+                    };
+                    GBuffer ps_main(float4 pos : SV_Position) {
+                        bool left = pos.x < 1.0;
+                        GBuffer gbuffer;
+                        gbuffer.albedo = left ? float4(1.0, 0.0, 0.0, 1.0)
+                                              : float4(0.0, 1.0, 0.0, 1.0);
+                        gbuffer.normal = left ? float4(0.0, 0.0, 1.0, 0.0)
+                                              : float4(1.0, 0.0, 0.0, 0.0);
+                        return gbuffer;
+                    }",
+                ),
+                (
+                    pso::Stage::Fragment,
+                    "ps_main",
+                    b"Texture2D<float4> albedo : register(t0);
+                    Texture2D<float4> normal : register(t1);
+                    float4 ps_main(float4 pos : SV_Position) : SV_Target {
+                        int3 texel = int3(pos.xy, 0);
+                        float3 light = float3(0.0, 0.0, 1.0);
+                        float lit = saturate(dot(normal.Load(texel).xyz, light));
+                        return float4(albedo.Load(texel).rgb * lit, 1.0);
+                    }",
+                ),
+            ]);
+            let pipelines = (0 .. 2)
+                .map(|i| {
+                    let desc = graphics_pipeline_desc(
+                        &modules[0],
+                        Some(&modules[1 + i]),
+                        &layouts[i],
+                        &render_pass,
+                        i,
+                    );
+                    device.create_graphics_pipeline(&desc, None).unwrap()
+                })
+                .collect::<Vec<_>>();
+
+            warp.submit(&mut queue_group, |cmd_buffer| {
+                begin_render_pass(
+                    cmd_buffer,
+                    &render_pass,
+                    &framebuffer,
+                    (2, 1),
+                    iter::repeat(clear_color([0.0; 4])).take(3),
                 );
+                cmd_buffer.bind_graphics_pipeline(&pipelines[0]);
+                cmd_buffer.draw(0 .. 3, 0 .. 1);
+                cmd_buffer.next_subpass(SubpassContents::Inline);
+                cmd_buffer.bind_graphics_pipeline(&pipelines[1]);
+                cmd_buffer.bind_graphics_descriptor_sets(&layouts[1], 0, iter::once(&set), &[]);
+                cmd_buffer.draw(0 .. 3, 0 .. 1);
+                cmd_buffer.end_render_pass();
+            });
+
+            let texels =
+                warp.read_image::<[u8; 4]>(&mut queue_group, &target.image, Aspects::COLOR, (2, 1));
+            assert_eq!(texels, vec![[0xFF, 0, 0, 0xFF], [0, 0, 0, 0xFF]]);
 
-                (*mem).Unmap(0, &d3d12::D3D12_RANGE { Begin: 0, End: 0 });
+            for pipeline in pipelines {
+                device.destroy_graphics_pipeline(pipeline);
+            }
+            warp.destroy_shader_modules(modules);
+            device.destroy_descriptor_pool(pool);
+            for layout in layouts {
+                device.destroy_pipeline_layout(layout);
+            }
+            device.destroy_descriptor_set_layout(set_layout);
+            device.destroy_framebuffer(framebuffer);
+            device.destroy_render_pass(render_pass);
+            for target in vec![albedo, normal, target] {
+                warp.destroy_target(target);
             }
         }
-
-        Ok(())
-    }
-
-    fn create_semaphore(&self) -> Result<r::Semaphore, d::OutOfMemory> {
-        let fence = self.create_fence(false)?;
-        Ok(r::Semaphore { raw: fence.raw })
-    }
-
-    fn create_fence(&self, signalled: bool) -> Result<r::Fence, d::OutOfMemory> {
-        Ok(r::Fence {
-            raw: self.create_raw_fence(signalled),
-        })
     }
 
-    unsafe fn reset_fence(&self, fence: &r::Fence) -> Result<(), d::OutOfMemory> {
-        assert_eq!(winerror::S_OK, fence.raw.signal(0));
-        Ok(())
-    }
+    #[test]
+    fn test_warp_partial_descriptor_set_update() {
+        use hal::command::RawCommandBuffer;
+        use hal::pso::DescriptorPool;
+        use hal::{buffer, image, Device};
+        use std::iter;
 
-    unsafe fn wait_for_fences<I>(
-        &self,
-        fences: I,
-        wait: d::WaitFor,
-        timeout_ns: u64,
-    ) -> Result<bool, d::OomOrDeviceLost>
-    where
-        I: IntoIterator,
-        I::Item: Borrow<r::Fence>,
-    {
-        let fences = fences.into_iter().collect::<Vec<_>>();
-        let mut events = self.events.lock().unwrap();
-        for _ in events.len() .. fences.len() {
-            events.push(native::Event::create(false, false));
-        }
+        use resource::Buffer;
+        use test_utils::{begin_render_pass, clear_color, graphics_pipeline_desc, FULLSCREEN_VS};
 
-        for (&event, fence) in events.iter().zip(fences.iter()) {
-            synchapi::ResetEvent(event.0);
-            assert_eq!(
-                winerror::S_OK,
-                fence.borrow().raw.set_event_on_completion(event, 1)
+        let (warp, mut queue_group) = Warp::open("test_warp_partial_descriptor_set_update");
+        let device = &warp.device;
+        unsafe {
+            let target = warp.create_target(
+                Format::Rgba8Unorm,
+                (1, 1),
+                1,
+                image::Usage::COLOR_ATTACHMENT | image::Usage::TRANSFER_SRC,
             );
+            let render_pass = warp.create_color_pass(Format::Rgba8Unorm);
+            let framebuffer = warp.create_framebuffer(&render_pass, &[&target.view], (1, 1));
+            let uniforms = [[1.0f32, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0]]
+                .iter()
+                .map(|color| warp.create_buffer_with_data(color, buffer::Usage::UNIFORM))
+                .collect::<Vec<_>>();
+
+            let bindings = (0 .. 2)
+                .map(|binding| pso::DescriptorSetLayoutBinding {
+                    binding,
+                    ty: pso::DescriptorType::UniformBuffer,
+                    count: 1,
+                    stage_flags: pso::ShaderStageFlags::FRAGMENT,
+                    immutable_samplers: false,
+                })
+                .collect::<Vec<_>>();
+            let set_layout = device
+                .create_descriptor_set_layout(&bindings, &[])
+                .unwrap();
+            let layout = warp.create_pipeline_layout(&[&set_layout]);
+            let mut pool = device
+                .create_descriptor_pool(
+                    1,
+                    iter::once(pso::DescriptorRangeDesc {
+                        ty: pso::DescriptorType::UniformBuffer,
+                        count: 2,
+                    }),
+                    pso::DescriptorPoolCreateFlags::empty(),
+                )
+                .unwrap();
+            let set = pool.allocate_set(&set_layout).unwrap();
+            let write = |binding, buffer: &Buffer| pso::DescriptorSetWrite {
+                set: &set,
+                binding,
+                array_offset: 0,
+                descriptors: iter::once(pso::Descriptor::Buffer(buffer, None .. None)),
+            };
+            device.write_descriptor_sets(vec![write(0, &uniforms[0].0), write(1, &uniforms[1].0)]);
+
+            let modules = warp.create_shader_modules(&[
+                FULLSCREEN_VS,
+                (
+                    pso::Stage::Fragment,
+                    "ps_main",
+                    b"cbuffer First : register(b0) { float4 first; };
+                    cbuffer Second : register(b1) { float4 second; };
+                    float4 ps_main() : SV_Target {
+                        return float4(first.rgb + second.rgb, 1.0);
+                    }",
+                ),
+            ]);
+            let desc =
+                graphics_pipeline_desc(&modules[0], Some(&modules[1]), &layout, &render_pass, 0);
+            let pipeline = device.create_graphics_pipeline(&desc, None).unwrap();
+
+            let draw = |queue_group: &mut _| {
+                warp.submit(queue_group, |cmd_buffer| {
+                    begin_render_pass(
+                        cmd_buffer,
+                        &render_pass,
+                        &framebuffer,
+                        (1, 1),
+                        iter::once(clear_color([0.0; 4])),
+                    );
+                    cmd_buffer.bind_graphics_pipeline(&pipeline);
+                    cmd_buffer.bind_graphics_descriptor_sets(&layout, 0, iter::once(&set), &[]);
+                    cmd_buffer.draw(0 .. 3, 0 .. 1);
+                    cmd_buffer.end_render_pass();
+                });
+                warp.read_image::<[u8; 4]>(queue_group, &target.image, Aspects::COLOR, (1, 1))
+            };
+
+            // Only the second slot is rewritten between the draws, the first one keeps
+            // its descriptor.
+            assert_eq!(draw(&mut queue_group), vec![[0xFF, 0xFF, 0, 0xFF]]);
+            device.write_descriptor_sets(iter::once(write(1, &uniforms[2].0)));
+            assert_eq!(draw(&mut queue_group), vec![[0xFF, 0, 0xFF, 0xFF]]);
+
+            device.destroy_graphics_pipeline(pipeline);
+            warp.destroy_shader_modules(modules);
+            device.destroy_descriptor_pool(pool);
+            device.destroy_pipeline_layout(layout);
+            device.destroy_descriptor_set_layout(set_layout);
+            for uniform in uniforms {
+                warp.destroy_buffer(uniform);
+            }
+            device.destroy_framebuffer(framebuffer);
+            device.destroy_render_pass(render_pass);
+            warp.destroy_target(target);
         }
+    }
 
-        let all = match wait {
-            d::WaitFor::Any => FALSE,
-            d::WaitFor::All => TRUE,
-        };
+    #[test]
+    fn test_warp_multiple_render_target_formats() {
+        use hal::command::RawCommandBuffer;
+        use hal::pass::{Attachment, AttachmentLoadOp, AttachmentOps, AttachmentStoreOp};
+        use hal::pass::{SubpassDependency, SubpassDesc};
+        use hal::{image, Device};
+        use std::iter;
 
-        let hr = {
-            // This block handles overflow when converting to u32 and always rounds up
-            // The Vulkan specification allows to wait more than specified
-            let timeout_ms = {
-                if timeout_ns > (<u32>::max_value() as u64) * 1_000_000 {
-                    <u32>::max_value()
-                } else {
-                    ((timeout_ns + 999_999) / 1_000_000) as u32
-                }
-            };
+        use test_utils::{begin_render_pass, clear_color, graphics_pipeline_desc, FULLSCREEN_VS};
 
-            synchapi::WaitForMultipleObjects(
-                fences.len() as u32,
-                events.as_ptr() as *const _,
-                all,
-                timeout_ms,
-            )
-        };
+        let (warp, mut queue_group) = Warp::open("test_warp_multiple_render_target_formats");
+        let device = &warp.device;
+        unsafe {
+            let formats = [Format::Rgba8Unorm, Format::R16Sfloat];
+            let targets = formats
+                .iter()
+                .map(|&format| {
+                    warp.create_target(
+                        format,
+                        (2, 1),
+                        1,
+                        image::Usage::COLOR_ATTACHMENT | image::Usage::TRANSFER_SRC,
+                    )
+                })
+                .collect::<Vec<_>>();
+            let render_pass = device
+                .create_render_pass(
+                    formats.iter().map(|&format| Attachment {
+                        format: Some(format),
+                        samples: 1,
+                        ops: AttachmentOps::new(AttachmentLoadOp::Clear, AttachmentStoreOp::Store),
+                        stencil_ops: AttachmentOps::DONT_CARE,
+                        layouts: image::Layout::Undefined .. image::Layout::TransferSrcOptimal,
+                    }),
+                    iter::once(SubpassDesc {
+                        colors: &[
+                            (0, image::Layout::ColorAttachmentOptimal),
+                            (1, image::Layout::ColorAttachmentOptimal),
+                        ],
+                        depth_stencil: None,
+                        inputs: &[],
+                        resolves: &[],
+                        preserves: &[],
+                    }),
+                    iter::empty::<SubpassDependency>(),
+                )
+                .unwrap();
+            let framebuffer = warp.create_framebuffer(
+                &render_pass,
+                &[&targets[0].view, &targets[1].view],
+                (2, 1),
+            );
+            let layout = warp.create_pipeline_layout(&[]);
+
+            // Each target receives a different value per texel.
+            let modules = warp.create_shader_modules(&[
+                FULLSCREEN_VS,
+                (
+                    pso::Stage::Fragment,
+                    "ps_main",
+                    b"struct Targets {
+                        float4 color : SV_Target0;
+                        float value : SV_Target1;
+                    };
+                    Targets ps_main(float4 pos : SV_Position) {
+                        bool left = pos.x < 1.0;
+                        Targets targets;
+                        targets.color = left ? float4(1.0, 0.0, 0.0, 1.0)
+                                             : float4(0.0, 0.0, 1.0, 1.0);
+                        targets.value = left ? 0.5 : 0.25;
+                        return targets;
+                    }",
+                ),
+            ]);
+            let desc =
+                graphics_pipeline_desc(&modules[0], Some(&modules[1]), &layout, &render_pass, 0);
+            let pipeline = device.create_graphics_pipeline(&desc, None).unwrap();
+
+            warp.submit(&mut queue_group, |cmd_buffer| {
+                begin_render_pass(
+                    cmd_buffer,
+                    &render_pass,
+                    &framebuffer,
+                    (2, 1),
+                    iter::repeat(clear_color([0.0; 4])).take(2),
+                );
+                cmd_buffer.bind_graphics_pipeline(&pipeline);
+                cmd_buffer.draw(0 .. 3, 0 .. 1);
+                cmd_buffer.end_render_pass();
+            });
 
-        const WAIT_OBJECT_LAST: u32 = winbase::WAIT_OBJECT_0 + winnt::MAXIMUM_WAIT_OBJECTS;
-        const WAIT_ABANDONED_LAST: u32 = winbase::WAIT_ABANDONED_0 + winnt::MAXIMUM_WAIT_OBJECTS;
-        match hr {
-            winbase::WAIT_OBJECT_0 ... WAIT_OBJECT_LAST => Ok(true),
-            winbase::WAIT_ABANDONED_0 ... WAIT_ABANDONED_LAST => Ok(true), //TODO?
-            winerror::WAIT_TIMEOUT => Ok(false),
-            _ => panic!("Unexpected wait status 0x{:X}", hr),
+            let colors = warp.read_image::<[u8; 4]>(
+                &mut queue_group,
+                &targets[0].image,
+                Aspects::COLOR,
+                (2, 1),
+            );
+            assert_eq!(colors, vec![[0xFF, 0, 0, 0xFF], [0, 0, 0xFF, 0xFF]]);
+            // Half precision floats 0.5 and 0.25.
+            let values =
+                warp.read_image::<u16>(&mut queue_group, &targets[1].image, Aspects::COLOR, (2, 1));
+            assert_eq!(values, vec![0x3800, 0x3400]);
+
+            device.destroy_graphics_pipeline(pipeline);
+            warp.destroy_shader_modules(modules);
+            device.destroy_pipeline_layout(layout);
+            device.destroy_framebuffer(framebuffer);
+            device.destroy_render_pass(render_pass);
+            for target in targets {
+                warp.destroy_target(target);
+            }
         }
     }
 
-    unsafe fn get_fence_status(&self, fence: &r::Fence) -> Result<bool, d::DeviceLost> {
-        match fence.raw.GetCompletedValue() {
-            0 => Ok(false),
-            1 => Ok(true),
-            _ => Err(d::DeviceLost),
-        }
-    }
+    #[test]
+    fn test_warp_msaa_resolve() {
+        use hal::command::RawCommandBuffer;
+        use hal::pass::{Attachment, AttachmentLoadOp, AttachmentOps, AttachmentStoreOp};
+        use hal::pass::{SubpassDependency, SubpassDesc};
+        use hal::{image, Device};
+        use std::iter;
 
-    fn create_event(&self) -> Result<(), d::OutOfMemory> {
-        unimplemented!()
-    }
+        use test_utils::{begin_render_pass, clear_color, graphics_pipeline_desc};
 
-    unsafe fn get_event_status(&self, event: &()) -> Result<bool, d::OomOrDeviceLost> {
-        unimplemented!()
-    }
+        let (warp, mut queue_group) = Warp::open("test_warp_msaa_resolve");
+        let device = &warp.device;
+        unsafe {
+            let targets = vec![
+                warp.create_target(Format::Rgba8Unorm, (1, 1), 4, image::Usage::COLOR_ATTACHMENT),
+                warp.create_target(
+                    Format::Rgba8Unorm,
+                    (1, 1),
+                    1,
+                    image::Usage::COLOR_ATTACHMENT | image::Usage::TRANSFER_SRC,
+                ),
+            ];
+            let attachment = |samples, ops, layout| Attachment {
+                format: Some(Format::Rgba8Unorm),
+                samples,
+                ops,
+                stencil_ops: AttachmentOps::DONT_CARE,
+                layouts: image::Layout::Undefined .. layout,
+            };
+            let render_pass = device
+                .create_render_pass(
+                    &[
+                        attachment(
+                            4,
+                            AttachmentOps::new(
+                                AttachmentLoadOp::Clear,
+                                AttachmentStoreOp::DontCare,
+                            ),
+                            image::Layout::ColorAttachmentOptimal,
+                        ),
+                        attachment(
+                            1,
+                            AttachmentOps::new(
+                                AttachmentLoadOp::DontCare,
+                                AttachmentStoreOp::Store,
+                            ),
+                            image::Layout::TransferSrcOptimal,
+                        ),
+                    ],
+                    iter::once(SubpassDesc {
+                        colors: &[(0, image::Layout::ColorAttachmentOptimal)],
+                        depth_stencil: None,
+                        inputs: &[],
+                        resolves: &[(1, image::Layout::ColorAttachmentOptimal)],
+                        preserves: &[],
+                    }),
+                    iter::empty::<SubpassDependency>(),
+                )
+                .unwrap();
+            let framebuffer = device
+                .create_framebuffer(
+                    &render_pass,
+                    targets.iter().map(|target| &target.view),
+                    image::Extent {
+                        width: 1,
+                        height: 1,
+                        depth: 1,
+                    },
+                )
+                .unwrap();
+            let layout = warp.create_pipeline_layout(&[]);
+
+            // The triangle covers the upper right half of the pixel, its diagonal edge
+            // splits the samples.
+            let modules = warp.create_shader_modules(&[
+                (
+                    pso::Stage::Vertex,
+                    "vs_main",
+                    b"float4 vs_main(uint id : SV_VertexID) : SV_Position {
+                        static const float2 positions[3] = {
+                            float2(-1.0, 1.0), float2(1.0, 1.0), float2(1.0, -1.0)
+                        };
+                        return float4(positions[id], 0.0, 1.0);
+                    }",
+                ),
+                (
+                    pso::Stage::Fragment,
+                    "ps_main",
+                    b"float4 ps_main() : SV_Target { return float4(1.0, 0.0, 0.0, 1.0); }",
+                ),
+            ]);
+            let mut desc =
+                graphics_pipeline_desc(&modules[0], Some(&modules[1]), &layout, &render_pass, 0);
+            desc.multisampling = Some(pso::Multisampling {
+                rasterization_samples: 4,
+                sample_shading: None,
+                sample_mask: !0,
+                alpha_coverage: false,
+                alpha_to_one: false,
+            });
+            let pipeline = device.create_graphics_pipeline(&desc, None).unwrap();
+
+            warp.submit(&mut queue_group, |cmd_buffer| {
+                begin_render_pass(
+                    cmd_buffer,
+                    &render_pass,
+                    &framebuffer,
+                    (1, 1),
+                    iter::repeat(clear_color([0.0, 0.0, 0.0, 1.0])).take(2),
+                );
+                cmd_buffer.bind_graphics_pipeline(&pipeline);
+                cmd_buffer.draw(0 .. 3, 0 .. 1);
+                cmd_buffer.end_render_pass();
+            });
 
-    unsafe fn set_event(&self, event: &()) -> Result<(), d::OutOfMemory> {
-        unimplemented!()
+            // Average of the covered red and the cleared black samples.
+            let color = warp.read_image::<[u8; 4]>(
+                &mut queue_group,
+                &targets[1].image,
+                Aspects::COLOR,
+                (1, 1),
+            )[0];
+            assert!(color[0] > 0 && color[0] < 0xFF, "color {:?}", color);
+            assert_eq!(&color[1 ..], &[0, 0, 0xFF]);
+
+            device.destroy_graphics_pipeline(pipeline);
+            warp.destroy_shader_modules(modules);
+            device.destroy_pipeline_layout(layout);
+            device.destroy_framebuffer(framebuffer);
+            device.destroy_render_pass(render_pass);
+            for target in targets {
+                warp.destroy_target(target);
+            }
+        }
     }
 
-    unsafe fn reset_event(&self, event: &()) -> Result<(), d::OutOfMemory> {
-        unimplemented!()
-    }
+    #[test]
+    fn test_warp_depth_stencil_formats() {
+        use hal::format::Swizzle;
+        use hal::memory::Properties;
+        use hal::{image, Device, PhysicalDevice};
+        use winapi::shared::dxgiformat::*;
+
+        let (warp, _queue_group) = Warp::open("test_warp_depth_stencil_formats");
+        let device = &warp.device;
+        let formats = [
+            (Format::D16Unorm, DXGI_FORMAT_R16_TYPELESS),
+            (Format::X8D24Unorm, DXGI_FORMAT_R24G8_TYPELESS),
+            (Format::D24UnormS8Uint, DXGI_FORMAT_R24G8_TYPELESS),
+            (Format::D32Sfloat, DXGI_FORMAT_R32_TYPELESS),
+            (Format::D32SfloatS8Uint, DXGI_FORMAT_R32G8X24_TYPELESS),
+        ];
+        let usage = image::Usage::DEPTH_STENCIL_ATTACHMENT | image::Usage::SAMPLED;
+        for &(format, _) in &formats {
+            let props = warp.adapter.physical_device.format_properties(Some(format));
+            assert!(
+                props
+                    .optimal_tiling
+                    .contains(ImageFeature::DEPTH_STENCIL_ATTACHMENT),
+                "{:?}",
+                format
+            );
+            assert!(!props.optimal_tiling.contains(ImageFeature::COLOR_ATTACHMENT));
+            assert!(warp
+                .adapter
+                .physical_device
+                .image_format_properties(
+                    format,
+                    2,
+                    image::Tiling::Optimal,
+                    usage,
+                    image::ViewCapabilities::empty(),
+                )
+                .is_some());
+        }
 
-    unsafe fn free_memory(&self, memory: r::Memory) {
-        memory.heap.destroy();
-        if let Some(buffer) = memory.resource {
-            buffer.destroy();
+        unsafe {
+            for &(format, typeless) in &formats {
+                let mut image = device
+                    .create_image(
+                        image::Kind::D2(16, 16, 1, 1),
+                        1,
+                        format,
+                        image::Tiling::Optimal,
+                        usage,
+                        image::ViewCapabilities::empty(),
+                    )
+                    .unwrap();
+                let requirements = device.get_image_requirements(&image);
+                let memory = warp.allocate(
+                    requirements.type_mask,
+                    requirements.size,
+                    Properties::DEVICE_LOCAL,
+                );
+                device.bind_image_memory(&memory, 0, &mut image).unwrap();
+                assert_eq!(image.expect_bound().descriptor.Format, typeless);
+                assert_eq!(image.expect_bound().resource.GetDesc().Format, typeless);
+
+                // The typeless parent allows both depth-stencil and shader resource views.
+                let view = |aspects| {
+                    device
+                        .create_image_view(
+                            &image,
+                            image::ViewKind::D2,
+                            format,
+                            Swizzle::NO,
+                            image::SubresourceRange {
+                                aspects,
+                                levels: 0 .. 1,
+                                layers: 0 .. 1,
+                            },
+                        )
+                        .unwrap()
+                };
+                for &aspects in &[format.surface_desc().aspects, Aspects::DEPTH] {
+                    let view = view(aspects);
+                    assert!(view.handle_dsv.is_some());
+                    assert!(view.handle_srv.is_some());
+                    device.destroy_image_view(view);
+                }
+                device.destroy_image(image);
+                device.free_memory(memory);
+            }
         }
     }
 
-    unsafe fn create_query_pool(
-        &self,
-        query_ty: query::Type,
-        count: query::Id,
-    ) -> Result<r::QueryPool, query::CreationError> {
-        let heap_ty = match query_ty {
-            query::Type::Occlusion => native::query::HeapType::Occlusion,
-            query::Type::PipelineStatistics(_) => native::query::HeapType::PipelineStatistics,
-            query::Type::Timestamp => native::query::HeapType::Timestamp,
-        };
+    #[test]
+    fn test_warp_bindless_textures() {
+        use hal::command::{BufferImageCopy, RawCommandBuffer};
+        use hal::format::Swizzle;
+        use hal::memory::{Barrier, Dependencies, Properties};
+        use hal::pso::{DescriptorPool, PipelineStage};
+        use hal::{buffer, image, Device};
+        use std::iter;
 
-        let (query_heap, hr) = self.raw.create_query_heap(heap_ty, count, 0);
-        assert_eq!(winerror::S_OK, hr);
+        use test_utils::entry_point;
+        use DescriptorBindingFlags;
 
-        Ok(r::QueryPool {
-            raw: query_heap,
-            ty: heap_ty,
-        })
-    }
+        const NUM_TEXTURES: u32 = 1000;
+
+        let (warp, mut queue_group) = Warp::open("test_warp_bindless_textures");
+        let device = &warp.device;
+        assert!(warp.adapter.physical_device.supports_bindless());
+        unsafe {
+            // Texture offsets in buffers have to be aligned to 512 bytes.
+            let mut texels = vec![0u32; NUM_TEXTURES as usize * 128];
+            for i in 0 .. NUM_TEXTURES {
+                texels[i as usize * 128] = i * 3 + 1;
+            }
+            let upload = warp.create_buffer_with_data(&texels, buffer::Usage::TRANSFER_SRC);
+            let result = warp.create_buffer(
+                16,
+                buffer::Usage::STORAGE | buffer::Usage::TRANSFER_SRC,
+                Properties::DEVICE_LOCAL,
+            );
+
+            // Each layer of the image is a separate texture of the bindless array.
+            let mut image = device
+                .create_image(
+                    image::Kind::D2(1, 1, NUM_TEXTURES as _, 1),
+                    1,
+                    Format::R32Uint,
+                    image::Tiling::Optimal,
+                    image::Usage::SAMPLED | image::Usage::TRANSFER_DST,
+                    image::ViewCapabilities::empty(),
+                )
+                .unwrap();
+            let requirements = device.get_image_requirements(&image);
+            let image_memory = warp.allocate(
+                requirements.type_mask,
+                requirements.size,
+                Properties::DEVICE_LOCAL,
+            );
+            device.bind_image_memory(&image_memory, 0, &mut image).unwrap();
+            let views = (0 .. NUM_TEXTURES as image::Layer)
+                .map(|layer| {
+                    device
+                        .create_image_view(
+                            &image,
+                            image::ViewKind::D2Array,
+                            Format::R32Uint,
+                            Swizzle::NO,
+                            image::SubresourceRange {
+                                aspects: Aspects::COLOR,
+                                levels: 0 .. 1,
+                                layers: layer .. layer + 1,
+                            },
+                        )
+                        .unwrap()
+                })
+                .collect::<Vec<_>>();
+
+            let bindings = [
+                (
+                    pso::DescriptorSetLayoutBinding {
+                        binding: 0,
+                        ty: pso::DescriptorType::StorageBuffer,
+                        count: 1,
+                        stage_flags: pso::ShaderStageFlags::COMPUTE,
+                        immutable_samplers: false,
+                    },
+                    DescriptorBindingFlags::empty(),
+                ),
+                (
+                    pso::DescriptorSetLayoutBinding {
+                        binding: 1,
+                        ty: pso::DescriptorType::SampledImage,
+                        count: NUM_TEXTURES as _,
+                        stage_flags: pso::ShaderStageFlags::COMPUTE,
+                        immutable_samplers: false,
+                    },
+                    DescriptorBindingFlags::UPDATE_AFTER_BIND
+                        | DescriptorBindingFlags::PARTIALLY_BOUND
+                        | DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT,
+                ),
+            ];
+            // The unbounded binding has to be the last one.
+            assert!(device
+                .create_descriptor_set_layout_with_flags(bindings.iter().rev(), &[])
+                .is_err());
+            let set_layout = device
+                .create_descriptor_set_layout_with_flags(&bindings, &[])
+                .unwrap();
+            let layout = warp.create_pipeline_layout(&[&set_layout]);
+            let mut pool = device
+                .create_descriptor_pool(
+                    1,
+                    &[
+                        pso::DescriptorRangeDesc {
+                            ty: pso::DescriptorType::StorageBuffer,
+                            count: 1,
+                        },
+                        pso::DescriptorRangeDesc {
+                            ty: pso::DescriptorType::SampledImage,
+                            count: NUM_TEXTURES as _,
+                        },
+                    ],
+                    pso::DescriptorPoolCreateFlags::empty(),
+                )
+                .unwrap();
+            let set = pool.allocate_set(&set_layout).unwrap();
+            device.write_descriptor_sets(vec![
+                pso::DescriptorSetWrite {
+                    set: &set,
+                    binding: 0,
+                    array_offset: 0,
+                    descriptors: vec![pso::Descriptor::Buffer(&result.0, None .. None)],
+                },
+                pso::DescriptorSetWrite {
+                    set: &set,
+                    binding: 1,
+                    array_offset: 0,
+                    descriptors: views
+                        .iter()
+                        .map(|view| {
+                            pso::Descriptor::Image(view, image::Layout::ShaderReadOnlyOptimal)
+                        })
+                        .collect(),
+                },
+            ]);
+
+            let modules = warp.create_shader_modules(&[(
+                pso::Stage::Compute,
+                "cs_main",
+                b"RWByteAddressBuffer result : register(u0);
+                Texture2DArray<uint> textures[] : register(t1);
+                [numthreads(4, 1, 1)] void cs_main(uint3 id : SV_DispatchThreadID) {
+                    uint index = (id.x * 331 + 7) % 1000;
+                    Texture2DArray<uint> texture = textures[NonUniformResourceIndex(index)];
+                    result.Store(id.x * 4, texture.Load(int4(0, 0, 0, 0)));
+                }",
+            )]);
+            let pipeline = device
+                .create_compute_pipeline(
+                    &pso::ComputePipelineDesc::new(entry_point(&modules[0]), &layout),
+                    None,
+                )
+                .unwrap();
 
-    unsafe fn destroy_query_pool(&self, pool: r::QueryPool) {
-        pool.raw.destroy();
-    }
+            let range = image::SubresourceRange {
+                aspects: Aspects::COLOR,
+                levels: 0 .. 1,
+                layers: 0 .. NUM_TEXTURES as _,
+            };
+            warp.submit(&mut queue_group, |cmd_buffer| {
+                cmd_buffer.pipeline_barrier(
+                    PipelineStage::TOP_OF_PIPE .. PipelineStage::TRANSFER,
+                    Dependencies::empty(),
+                    iter::once(Barrier::Image {
+                        states: (image::Access::empty(), image::Layout::Undefined)
+                            .. (image::Access::TRANSFER_WRITE, image::Layout::TransferDstOptimal),
+                        target: &image,
+                        families: None,
+                        range: range.clone(),
+                    }),
+                );
+                cmd_buffer.copy_buffer_to_image(
+                    &upload.0,
+                    &image,
+                    image::Layout::TransferDstOptimal,
+                    (0 .. NUM_TEXTURES as image::Layer).map(|layer| BufferImageCopy {
+                        buffer_offset: layer as u64 * 512,
+                        buffer_width: 64,
+                        buffer_height: 1,
+                        image_layers: image::SubresourceLayers {
+                            aspects: Aspects::COLOR,
+                            level: 0,
+                            layers: layer .. layer + 1,
+                        },
+                        image_offset: image::Offset::ZERO,
+                        image_extent: image::Extent {
+                            width: 1,
+                            height: 1,
+                            depth: 1,
+                        },
+                    }),
+                );
+                cmd_buffer.pipeline_barrier(
+                    PipelineStage::TRANSFER .. PipelineStage::COMPUTE_SHADER,
+                    Dependencies::empty(),
+                    iter::once(Barrier::Image {
+                        states: (image::Access::TRANSFER_WRITE, image::Layout::TransferDstOptimal)
+                            .. (image::Access::SHADER_READ, image::Layout::ShaderReadOnlyOptimal),
+                        target: &image,
+                        families: None,
+                        range,
+                    }),
+                );
+                cmd_buffer.bind_compute_pipeline(&pipeline);
+                cmd_buffer.bind_compute_descriptor_sets(&layout, 0, iter::once(&set), &[]);
+                cmd_buffer.dispatch([1, 1, 1]);
+                cmd_buffer.pipeline_barrier(
+                    PipelineStage::COMPUTE_SHADER .. PipelineStage::TRANSFER,
+                    Dependencies::empty(),
+                    iter::once(Barrier::whole_buffer(
+                        &result.0,
+                        buffer::Access::SHADER_WRITE .. buffer::Access::TRANSFER_READ,
+                    )),
+                );
+            });
 
-    unsafe fn get_query_pool_results(
-        &self,
-        _pool: &r::QueryPool,
-        _queries: Range<query::Id>,
-        _data: &mut [u8],
-        _stride: buffer::Offset,
-        _flags: query::ResultFlags,
-    ) -> Result<bool, d::OomOrDeviceLost> {
-        unimplemented!()
-    }
+            let texels = warp.read_buffer::<u32>(&mut queue_group, &result.0, 4);
+            for (thread, &texel) in texels.iter().enumerate() {
+                let index = (thread as u32 * 331 + 7) % NUM_TEXTURES;
+                assert_eq!(texel, index * 3 + 1);
+            }
 
-    unsafe fn destroy_shader_module(&self, shader_lib: r::ShaderModule) {
-        if let r::ShaderModule::Compiled(shaders) = shader_lib {
-            for (_, blob) in shaders {
-                blob.destroy();
+            device.destroy_compute_pipeline(pipeline);
+            warp.destroy_shader_modules(modules);
+            device.destroy_descriptor_pool(pool);
+            device.destroy_pipeline_layout(layout);
+            device.destroy_descriptor_set_layout(set_layout);
+            for view in views {
+                device.destroy_image_view(view);
             }
+            device.destroy_image(image);
+            device.free_memory(image_memory);
+            warp.destroy_buffer(upload);
+            warp.destroy_buffer(result);
         }
     }
 
-    unsafe fn destroy_render_pass(&self, _rp: r::RenderPass) {
-        // Just drop
-    }
-
-    unsafe fn destroy_pipeline_layout(&self, layout: r::PipelineLayout) {
-        layout.raw.destroy();
-    }
-
-    unsafe fn destroy_graphics_pipeline(&self, pipeline: r::GraphicsPipeline) {
-        pipeline.raw.destroy();
-    }
-
-    unsafe fn destroy_compute_pipeline(&self, pipeline: r::ComputePipeline) {
-        pipeline.raw.destroy();
-    }
+    #[test]
+    fn test_warp_root_signature_1_1() {
+        use hal::Device;
+        use std::iter;
+        use std::ops::Range;
 
-    unsafe fn destroy_framebuffer(&self, _fb: r::Framebuffer) {
-        // Just drop
-    }
+        use DescriptorBindingFlags;
 
-    unsafe fn destroy_buffer(&self, buffer: r::Buffer) {
-        match buffer {
-            r::Buffer::Bound(buffer) => {
-                buffer.resource.destroy();
-            }
-            r::Buffer::Unbound(_) => {}
-        }
-    }
+        use resource::DescriptorSetLayout;
 
-    unsafe fn destroy_buffer_view(&self, _view: r::BufferView) {
-        // empty
-    }
+        let (mut warp, _queue_group) = Warp::open("test_warp_root_signature_1_1");
+        let device = &mut warp.device;
+        assert!(warp.adapter.physical_device.private_caps.root_signature_1_1);
+        unsafe {
+            let binding = |binding, ty| pso::DescriptorSetLayoutBinding {
+                binding,
+                ty,
+                count: 2,
+                stage_flags: pso::ShaderStageFlags::ALL,
+                immutable_samplers: false,
+            };
+            let bindings = [
+                (
+                    binding(0, pso::DescriptorType::SampledImage),
+                    DescriptorBindingFlags::DATA_STATIC,
+                ),
+                (
+                    binding(1, pso::DescriptorType::UniformBuffer),
+                    DescriptorBindingFlags::DATA_STATIC_WHILE_SET_AT_EXECUTE,
+                ),
+                (
+                    binding(2, pso::DescriptorType::CombinedImageSampler),
+                    DescriptorBindingFlags::DATA_STATIC,
+                ),
+                (
+                    binding(3, pso::DescriptorType::StorageBuffer),
+                    DescriptorBindingFlags::empty(),
+                ),
+            ];
+            // Static data can't be referenced by descriptors updated after binding.
+            assert!(device
+                .create_descriptor_set_layout_with_flags(
+                    iter::once((
+                        binding(0, pso::DescriptorType::SampledImage),
+                        DescriptorBindingFlags::DATA_STATIC
+                            | DescriptorBindingFlags::UPDATE_AFTER_BIND,
+                    )),
+                    &[],
+                )
+                .is_err());
+            let set_layout = device
+                .create_descriptor_set_layout_with_flags(&bindings, &[])
+                .unwrap();
+            assert_eq!(
+                set_layout.binding_flags,
+                bindings.iter().map(|&(_, flags)| flags).collect::<Vec<_>>()
+            );
 
-    unsafe fn destroy_image(&self, image: r::Image) {
-        match image {
-            r::Image::Bound(image) => {
-                image.resource.destroy();
+            // Serialized as version 1.1 and as version 1.0 without support.
+            for &root_signature_1_1 in &[true, false] {
+                device.private_caps.root_signature_1_1 = root_signature_1_1;
+                let layout = device
+                    .create_pipeline_layout(
+                        iter::once(&set_layout),
+                        iter::once((pso::ShaderStageFlags::ALL, 0 .. 16)),
+                    )
+                    .unwrap();
+                assert!(!layout.raw.is_null());
+                assert_eq!(layout.tables.len(), 1);
+                assert_eq!(layout.num_parameter_slots, 3);
+                device.destroy_pipeline_layout(layout);
             }
-            r::Image::Unbound(_) => {}
+            let layout = device
+                .create_pipeline_layout(
+                    iter::empty::<&DescriptorSetLayout>(),
+                    iter::empty::<(pso::ShaderStageFlags, Range<u32>)>(),
+                )
+                .unwrap();
+            assert!(!layout.raw.is_null());
+            device.destroy_pipeline_layout(layout);
+            device.destroy_descriptor_set_layout(set_layout);
         }
     }
 
-    unsafe fn destroy_image_view(&self, _view: r::ImageView) {
-        // Just drop
-    }
+    #[test]
+    fn test_warp_placed_and_committed_resources() {
+        use hal::device::BindError;
+        use hal::memory::Properties;
+        use hal::{buffer, image, Device};
 
-    unsafe fn destroy_sampler(&self, _sampler: r::Sampler) {
-        // Just drop
-    }
+        use resource::{Image, Place};
 
-    unsafe fn destroy_descriptor_pool(&self, _pool: r::DescriptorPool) {
-        // Just drop
-        // Allocated descriptor sets don't need to be freed beforehand.
-    }
+        let (warp, _queue_group) = Warp::open("test_warp_placed_and_committed_resources");
+        let device = &warp.device;
+        unsafe {
+            let create_image = || {
+                device
+                    .create_image(
+                        image::Kind::D2(64, 64, 1, 1),
+                        1,
+                        Format::Rgba8Unorm,
+                        image::Tiling::Optimal,
+                        image::Usage::COLOR_ATTACHMENT | image::Usage::SAMPLED,
+                        image::ViewCapabilities::empty(),
+                    )
+                    .unwrap()
+            };
 
-    unsafe fn destroy_descriptor_set_layout(&self, _layout: r::DescriptorSetLayout) {
-        // Just drop
-    }
+            let mut placed = create_image();
+            let mut committed = create_image();
+            let requirements = device.get_image_requirements(&placed);
+            let ty = warp.memory_type(requirements.type_mask, Properties::DEVICE_LOCAL);
+            let memory = device
+                .allocate_memory(ty, requirements.size * 2)
+                .unwrap();
+            let dedicated = device
+                .allocate_dedicated_memory(ty, requirements.size)
+                .unwrap();
+            device
+                .bind_image_memory(&memory, requirements.size, &mut placed)
+                .unwrap();
+            device
+                .bind_image_memory(&dedicated, 0, &mut committed)
+                .unwrap();
+
+            let flags = |image: &Image| {
+                let bound = image.expect_bound();
+                (bound.resource.GetDesc().Flags, bound.descriptor.Flags)
+            };
+            match placed.expect_bound().place {
+                Place::Heap { offset, .. } => assert_eq!(offset, requirements.size),
+                _ => panic!("Expected a placed image"),
+            }
+            match committed.expect_bound().place {
+                Place::Committed => {}
+                _ => panic!("Expected a committed image"),
+            }
+            assert_eq!(flags(&placed), flags(&committed));
+            assert_ne!(
+                flags(&committed).0 & D3D12_RESOURCE_FLAG_ALLOW_RENDER_TARGET,
+                0
+            );
 
-    unsafe fn destroy_fence(&self, fence: r::Fence) {
-        fence.raw.destroy();
+            // Dedicated memory only holds a single resource at offset 0.
+            let mut image = create_image();
+            assert_eq!(
+                device.bind_image_memory(&dedicated, 0, &mut image),
+                Err(BindError::WrongMemory)
+            );
+            let mut buffer = device
+                .create_buffer(256, buffer::Usage::STORAGE)
+                .unwrap();
+            let buffer_requirements = device.get_buffer_requirements(&buffer);
+            let buffer_memory = device
+                .allocate_dedicated_memory(
+                    warp.memory_type(buffer_requirements.type_mask, Properties::DEVICE_LOCAL),
+                    buffer_requirements.size * 2,
+                )
+                .unwrap();
+            assert_eq!(
+                device.bind_buffer_memory(&buffer_memory, buffer_requirements.size, &mut buffer),
+                Err(BindError::OutOfBounds)
+            );
+            device
+                .bind_buffer_memory(&buffer_memory, 0, &mut buffer)
+                .unwrap();
+
+            device.destroy_buffer(buffer);
+            device.free_memory(buffer_memory);
+            device.destroy_image(image);
+            device.destroy_image(placed);
+            device.destroy_image(committed);
+            device.free_memory(memory);
+            device.free_memory(dedicated);
+        }
     }
 
-    unsafe fn destroy_semaphore(&self, semaphore: r::Semaphore) {
-        semaphore.raw.destroy();
-    }
+    #[test]
+    fn test_warp_memory_residency() {
+        use hal::{buffer, Device};
 
-    unsafe fn destroy_event(&self, event: ()) {
-        unimplemented!()
+        let (warp, _queue_group) = Warp::open("test_warp_memory_residency");
+        let device = &warp.device;
+        unsafe {
+            let mut buffer = device
+                .create_buffer(1 << 16, buffer::Usage::STORAGE)
+                .unwrap();
+            let requirements = device.get_buffer_requirements(&buffer);
+            let ty = hal::MemoryTypeId(requirements.type_mask.trailing_zeros() as _);
+            let memory = device.allocate_memory(ty, requirements.size).unwrap();
+            let dedicated = device
+                .allocate_dedicated_memory(ty, requirements.size)
+                .unwrap();
+            // Unbound dedicated memory has nothing to page.
+            assert_eq!(dedicated.pageable(), None);
+            device
+                .bind_buffer_memory(&dedicated, 0, &mut buffer)
+                .unwrap();
+            assert!(dedicated.pageable().is_some());
+
+            device.evict(&[&memory, &dedicated]);
+            assert_eq!(device.make_resident(&[&memory, &dedicated]), Ok(()));
+
+            device.destroy_buffer(buffer);
+            device.free_memory(memory);
+            device.free_memory(dedicated);
+        }
     }
 
-    unsafe fn create_swapchain(
-        &self,
-        surface: &mut w::Surface,
-        config: hal::SwapchainConfig,
-        old_swapchain: Option<w::Swapchain>,
-    ) -> Result<(w::Swapchain, Vec<r::Image>), hal::window::CreationError> {
-        if let Some(old_swapchain) = old_swapchain {
-            self.destroy_swapchain(old_swapchain);
-        }
+    #[test]
+    fn test_warp_shared_sampler_tables() {
+        use hal::pso::DescriptorPool;
+        use hal::{image, Device};
+        use std::iter;
 
-        let mut swap_chain1 = native::WeakPtr::<dxgi1_2::IDXGISwapChain1>::null();
+        let (warp, _queue_group) = Warp::open("test_warp_shared_sampler_tables");
+        let device = &warp.device;
+        unsafe {
+            let sampler_info = |filter| image::SamplerInfo::new(filter, image::WrapMode::Clamp);
+            let nearest = device.create_sampler(sampler_info(image::Filter::Nearest)).unwrap();
+            let linear = device.create_sampler(sampler_info(image::Filter::Linear)).unwrap();
+
+            let set_layout = device
+                .create_descriptor_set_layout(
+                    &[pso::DescriptorSetLayoutBinding {
+                        binding: 0,
+                        ty: pso::DescriptorType::Sampler,
+                        count: 2,
+                        stage_flags: pso::ShaderStageFlags::FRAGMENT,
+                        immutable_samplers: false,
+                    }],
+                    &[],
+                )
+                .unwrap();
+            let mut pool = device
+                .create_descriptor_pool(
+                    64,
+                    &[pso::DescriptorRangeDesc {
+                        ty: pso::DescriptorType::Sampler,
+                        count: 128,
+                    }],
+                    pso::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET,
+                )
+                .unwrap();
+            let sets = (0 .. 64)
+                .map(|_| pool.allocate_set(&set_layout).unwrap())
+                .collect::<Vec<_>>();
+            device.write_descriptor_sets(sets.iter().map(|set| pso::DescriptorSetWrite {
+                set,
+                binding: 0,
+                array_offset: 0,
+                descriptors: vec![
+                    pso::Descriptor::Sampler(&nearest),
+                    pso::Descriptor::Sampler(&linear),
+                ],
+            }));
+
+            // All sets share a single table of the shader-visible heap.
+            {
+                let tables = device.sampler_tables.lock().unwrap();
+                assert_eq!(tables.num_shared(), 1);
+                assert_eq!(tables.heap.allocated_handles, 2);
+            }
 
-        let format = match config.format {
-            // Apparently, swap chain doesn't like sRGB, but the RTV can still have some:
-            // https://www.gamedev.net/forums/topic/670546-d3d12srgb-buffer-format-for-swap-chain/
-            // [15716] DXGI ERROR: IDXGIFactory::CreateSwapchain: Flip model swapchains
-            //                     (DXGI_SWAP_EFFECT_FLIP_SEQUENTIAL and DXGI_SWAP_EFFECT_FLIP_DISCARD) only support the following Formats:
-            //                     (DXGI_FORMAT_R16G16B16A16_FLOAT, DXGI_FORMAT_B8G8R8A8_UNORM, DXGI_FORMAT_R8G8B8A8_UNORM, DXGI_FORMAT_R10G10B10A2_UNORM),
-            //                     assuming the underlying Device does as well.
-            format::Format::Bgra8Srgb => format::Format::Bgra8Unorm,
-            format::Format::Rgba8Srgb => format::Format::Rgba8Unorm,
-            format => format,
-        };
+            // Rewriting one set moves it to its own table.
+            device.write_descriptor_sets(iter::once(pso::DescriptorSetWrite {
+                set: &sets[0],
+                binding: 0,
+                array_offset: 1,
+                descriptors: iter::once(pso::Descriptor::Sampler(&nearest)),
+            }));
+            assert_eq!(device.sampler_tables.lock().unwrap().num_shared(), 2);
 
-        let format = conv::map_format(format).unwrap(); // TODO: error handling
+            pool.free_sets(sets);
+            {
+                let tables = device.sampler_tables.lock().unwrap();
+                assert_eq!(tables.num_shared(), 0);
+                assert_eq!(tables.heap.allocated_handles, 0);
+            }
 
-        let rtv_desc = d3d12::D3D12_RENDER_TARGET_VIEW_DESC {
-            Format: conv::map_format(config.format).unwrap(),
-            ViewDimension: d3d12::D3D12_RTV_DIMENSION_TEXTURE2D,
-            ..mem::zeroed()
-        };
-        let rtv_heap = Device::create_descriptor_heap_impl(
-            self.raw,
-            descriptor::HeapType::Rtv,
-            false,
-            config.image_count as _,
-        );
+            // The CPU descriptor of a destroyed sampler is reused by the next sampler.
+            let handle = linear.handle;
+            device.destroy_sampler(linear);
+            let tile = device
+                .create_sampler(image::SamplerInfo::new(
+                    image::Filter::Linear,
+                    image::WrapMode::Tile,
+                ))
+                .unwrap();
+            assert_eq!(tile.handle.ptr, handle.ptr);
+
+            device.destroy_sampler(tile);
+            device.destroy_sampler(nearest);
+            device.destroy_descriptor_pool(pool);
+            device.destroy_descriptor_set_layout(set_layout);
+        }
+    }
 
-        // TODO: double-check values
-        let desc = dxgi1_2::DXGI_SWAP_CHAIN_DESC1 {
-            AlphaMode: dxgi1_2::DXGI_ALPHA_MODE_IGNORE,
-            BufferCount: config.image_count,
-            Width: config.extent.width,
-            Height: config.extent.height,
-            Format: format,
-            Flags: 0,
-            BufferUsage: dxgitype::DXGI_USAGE_RENDER_TARGET_OUTPUT,
-            SampleDesc: dxgitype::DXGI_SAMPLE_DESC {
-                Count: 1,
-                Quality: 0,
-            },
-            Scaling: dxgi1_2::DXGI_SCALING_STRETCH,
-            Stereo: FALSE,
-            SwapEffect: dxgi::DXGI_SWAP_EFFECT_FLIP_DISCARD,
-        };
+    #[test]
+    fn test_warp_frame_pacer() {
+        use std::sync::mpsc;
+        use std::thread;
+        use std::time::Duration;
+        use winapi::shared::winerror;
 
-        // TODO
-        let hr = surface.factory.CreateSwapChainForHwnd(
-            self.present_queue.as_mut_ptr() as *mut _,
-            surface.wnd_handle,
-            &desc,
-            ptr::null(),
-            ptr::null_mut(),
-            swap_chain1.mut_void() as *mut *mut _,
-        );
+        let (warp, queue_group) = Warp::open("test_warp_frame_pacer");
+        let device = &warp.device;
+        let queue = queue_group.queues[0].as_raw();
+        unsafe {
+            // Hold back the queue until the gate is signaled from the host.
+            let gate = device.create_raw_fence(false);
+            let mut pacer = device.create_frame_pacer(2);
+            for _ in 0 .. 2 {
+                pacer.begin_frame();
+                assert_eq!(winerror::S_OK, queue.raw.Wait(gate.as_mut_ptr(), 1));
+                pacer.end_frame(queue);
+            }
 
-        if !winerror::SUCCEEDED(hr) {
-            error!("error on swapchain creation 0x{:x}", hr);
-        }
+            // The third frame waits for the first one to complete.
+            let (sender, receiver) = mpsc::channel();
+            let frame = thread::spawn(move || {
+                pacer.begin_frame();
+                sender.send(()).unwrap();
+                pacer
+            });
+            assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+            assert_eq!(winerror::S_OK, gate.signal(1));
+            receiver.recv_timeout(Duration::from_secs(1)).unwrap();
 
-        let (swap_chain3, hr3) = swap_chain1.cast::<dxgi1_4::IDXGISwapChain3>();
-        if !winerror::SUCCEEDED(hr3) {
-            error!("error on swapchain cast 0x{:x}", hr3);
+            let pacer = frame.join().unwrap();
+            device.destroy_frame_pacer(pacer);
+            gate.destroy();
         }
+    }
 
-        swap_chain1.destroy();
-
-        // Get backbuffer images
-        let mut resources: Vec<native::Resource> = Vec::new();
-        let images = (0 .. config.image_count)
-            .map(|i| {
-                let mut resource = native::Resource::null();
-                swap_chain3.GetBuffer(
-                    i as _,
-                    &d3d12::ID3D12Resource::uuidof(),
-                    resource.mut_void(),
-                );
+    #[test]
+    fn test_warp_retired_descriptor_heap() {
+        use hal::Device;
+        use std::iter;
+        use std::sync::Arc;
 
-                let rtv_handle = rtv_heap.at(i as _, 0).cpu;
-                self.raw
-                    .CreateRenderTargetView(resource.as_mut_ptr(), &rtv_desc, rtv_handle);
-                resources.push(resource);
+        use MAX_SHADER_VISIBLE_SRV_CBV_UAV_HEAP_SIZE;
 
-                let surface_type = config.format.base_format().0;
-                let format_desc = surface_type.desc();
+        let (warp, _queue_group) = Warp::open("test_warp_retired_descriptor_heap");
+        let device = &warp.device;
+        unsafe {
+            let create_pool = |count| {
+                device
+                    .create_descriptor_pool(
+                        1,
+                        iter::once(pso::DescriptorRangeDesc {
+                            ty: pso::DescriptorType::SampledImage,
+                            count,
+                        }),
+                        pso::DescriptorPoolCreateFlags::empty(),
+                    )
+                    .unwrap()
+            };
+            // The first pool exhausts the heap, the second one replaces it.
+            let full = create_pool(MAX_SHADER_VISIBLE_SRV_CBV_UAV_HEAP_SIZE as _);
+            let retired = Arc::downgrade(&full.heap_srv_cbv_uav.owner);
+            let pool = create_pool(16);
+            assert!(!Arc::ptr_eq(&full.heap_srv_cbv_uav.owner, &pool.heap_srv_cbv_uav.owner));
+            assert!(retired.upgrade().is_some());
+
+            // Dropping the last pool allocated from the replaced heap destroys it.
+            device.destroy_descriptor_pool(full);
+            assert!(retired.upgrade().is_none());
+            device.destroy_descriptor_pool(pool);
+        }
+    }
 
-                let bytes_per_block = (format_desc.bits / 8) as _;
-                let block_dim = format_desc.dim;
-                let kind = image::Kind::D2(config.extent.width, config.extent.height, 1, 1);
+    #[test]
+    fn test_warp_planar_image() {
+        use hal::command::{BufferImageCopy, RawCommandBuffer};
+        use hal::format::Swizzle;
+        use hal::memory::{Dependencies, Properties};
+        use hal::pso::PipelineStage;
+        use hal::{buffer, image, Device, PhysicalDevice};
+        use std::iter;
+
+        use test_utils::image_barrier;
+
+        let (warp, mut queue_group) = Warp::open("test_warp_planar_image");
+        let device = &warp.device;
+        let properties = warp
+            .adapter
+            .physical_device
+            .image_format_properties(
+                Format::Nv12Unorm,
+                2,
+                image::Tiling::Optimal,
+                image::Usage::SAMPLED,
+                image::ViewCapabilities::empty(),
+            )
+            .unwrap();
+        assert_eq!(properties.max_levels, 1);
+        assert_eq!(properties.sample_count_mask, 0x1);
+        assert!(warp
+            .adapter
+            .physical_device
+            .image_format_properties(
+                Format::Nv12Unorm,
+                2,
+                image::Tiling::Linear,
+                image::Usage::SAMPLED,
+                image::ViewCapabilities::empty(),
+            )
+            .is_none());
 
-                r::Image::Bound(r::ImageBound {
-                    resource,
-                    place: r::Place::SwapChain,
-                    surface_type,
+        let usage = image::Usage::SAMPLED | image::Usage::TRANSFER_SRC | image::Usage::TRANSFER_DST;
+        unsafe {
+            let create_image = |kind, levels| {
+                device.create_image(
                     kind,
-                    usage: config.image_usage,
-                    default_view_format: Some(format),
-                    view_caps: image::ViewCapabilities::empty(),
-                    descriptor: d3d12::D3D12_RESOURCE_DESC {
-                        Dimension: d3d12::D3D12_RESOURCE_DIMENSION_TEXTURE2D,
-                        Alignment: 0,
-                        Width: config.extent.width as _,
-                        Height: config.extent.height as _,
-                        DepthOrArraySize: 1,
-                        MipLevels: 1,
-                        Format: format,
-                        SampleDesc: desc.SampleDesc.clone(),
-                        Layout: d3d12::D3D12_TEXTURE_LAYOUT_UNKNOWN,
-                        Flags: 0,
+                    levels,
+                    Format::Nv12Unorm,
+                    image::Tiling::Optimal,
+                    usage,
+                    image::ViewCapabilities::empty(),
+                )
+            };
+            // The chroma plane has half the resolution of the luma plane.
+            assert!(create_image(image::Kind::D2(3, 2, 1, 1), 1).is_err());
+            assert!(create_image(image::Kind::D2(4, 4, 1, 1), 2).is_err());
+
+            let mut image = create_image(image::Kind::D2(4, 2, 1, 1), 1).unwrap();
+            let requirements = device.get_image_requirements(&image);
+            let memory = warp.allocate(
+                requirements.type_mask,
+                requirements.size,
+                Properties::DEVICE_LOCAL,
+            );
+            device.bind_image_memory(&memory, 0, &mut image).unwrap();
+
+            let footprint = |aspects| {
+                device.get_image_subresource_footprint(
+                    &image,
+                    image::Subresource {
+                        aspects,
+                        level: 0,
+                        layer: 0,
                     },
-                    bytes_per_block,
-                    block_dim,
-                    clear_cv: vec![rtv_handle],
-                    clear_dv: Vec::new(),
-                    clear_sv: Vec::new(),
-                    // Dummy values, image is already bound
-                    requirements: memory::Requirements {
-                        alignment: 1,
-                        size: 1,
-                        type_mask: MEM_TYPE_MASK,
+                )
+            };
+            let luma = footprint(Aspects::PLANE_0);
+            let chroma = footprint(Aspects::PLANE_1);
+            assert!(luma.row_pitch >= 4);
+            assert!(chroma.row_pitch >= 4);
+            assert!(chroma.slice.start >= luma.slice.end);
+
+            let view = |aspects| {
+                device.create_image_view(
+                    &image,
+                    image::ViewKind::D2,
+                    Format::Nv12Unorm,
+                    Swizzle::NO,
+                    image::SubresourceRange {
+                        aspects,
+                        levels: 0 .. 1,
+                        layers: 0 .. 1,
                     },
-                })
-            })
-            .collect();
-
-        let swapchain = w::Swapchain {
-            inner: swap_chain3,
-            next_frame: 0,
-            frame_queue: VecDeque::new(),
-            rtv_heap,
-            resources,
-        };
+                )
+            };
+            assert!(view(Aspects::PLANE_0 | Aspects::PLANE_1).is_err());
+            for &aspects in &[Aspects::PLANE_0, Aspects::PLANE_1] {
+                let view = view(aspects).unwrap();
+                assert!(view.handle_srv.is_some());
+                device.destroy_image_view(view);
+            }
 
-        Ok((swapchain, images))
-    }
+            // Luma rows are 256 bytes apart, the chroma plane starts at byte 512.
+            let mut texels = vec![0u8; 768];
+            texels[.. 4].copy_from_slice(&[1, 2, 3, 4]);
+            texels[256 .. 260].copy_from_slice(&[5, 6, 7, 8]);
+            texels[512 .. 516].copy_from_slice(&[10, 20, 30, 40]);
+            let upload = warp.create_buffer_with_data(&texels, buffer::Usage::TRANSFER_SRC);
+            let plane_copy = |aspects, buffer_offset, buffer_width, (width, height)| {
+                BufferImageCopy {
+                    buffer_offset,
+                    buffer_width,
+                    buffer_height: height,
+                    image_layers: image::SubresourceLayers {
+                        aspects,
+                        level: 0,
+                        layers: 0 .. 1,
+                    },
+                    image_offset: image::Offset::ZERO,
+                    image_extent: image::Extent {
+                        width,
+                        height,
+                        depth: 1,
+                    },
+                }
+            };
+            warp.submit(&mut queue_group, |cmd_buffer| {
+                cmd_buffer.pipeline_barrier(
+                    PipelineStage::TOP_OF_PIPE .. PipelineStage::TRANSFER,
+                    Dependencies::empty(),
+                    iter::once(image_barrier(
+                        &image,
+                        Aspects::PLANE_0 | Aspects::PLANE_1,
+                        (image::Access::empty(), image::Layout::Undefined)
+                            .. (image::Access::TRANSFER_WRITE, image::Layout::TransferDstOptimal),
+                    )),
+                );
+                cmd_buffer.copy_buffer_to_image(
+                    &upload.0,
+                    &image,
+                    image::Layout::TransferDstOptimal,
+                    vec![
+                        plane_copy(Aspects::PLANE_0, 0, 256, (4, 2)),
+                        plane_copy(Aspects::PLANE_1, 512, 128, (2, 1)),
+                    ],
+                );
+                cmd_buffer.pipeline_barrier(
+                    PipelineStage::TRANSFER .. PipelineStage::TRANSFER,
+                    Dependencies::empty(),
+                    iter::once(image_barrier(
+                        &image,
+                        Aspects::PLANE_0 | Aspects::PLANE_1,
+                        (image::Access::TRANSFER_WRITE, image::Layout::TransferDstOptimal)
+                            .. (image::Access::TRANSFER_READ, image::Layout::TransferSrcOptimal),
+                    )),
+                );
+            });
 
-    unsafe fn destroy_swapchain(&self, swapchain: w::Swapchain) {
-        for resource in &swapchain.resources {
-            resource.destroy();
-        }
-        swapchain.inner.destroy();
-        swapchain.rtv_heap.destroy();
-    }
+            let luma = warp.read_image::<u8>(&mut queue_group, &image, Aspects::PLANE_0, (4, 2));
+            assert_eq!(luma, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+            let chroma =
+                warp.read_image::<[u8; 2]>(&mut queue_group, &image, Aspects::PLANE_1, (2, 1));
+            assert_eq!(chroma, vec![[10, 20], [30, 40]]);
 
-    fn wait_idle(&self) -> Result<(), error::HostExecutionError> {
-        for queue in &self.queues {
-            queue.wait_idle()?;
+            warp.destroy_buffer(upload);
+            device.destroy_image(image);
+            device.free_memory(memory);
         }
-        Ok(())
     }
 }
-
-
-#[test]
-fn test_identity_mapping() {
-    assert_eq!(conv::map_swizzle(format::Swizzle::NO), IDENTITY_MAPPING);
-}
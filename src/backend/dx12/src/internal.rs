@@ -37,10 +37,23 @@ pub struct BlitData {
 pub type BlitKey = (dxgiformat::DXGI_FORMAT, d3d12::D3D12_FILTER);
 type BlitMap = FastHashMap<BlitKey, BlitPipe>;
 
+// Extents of the source and destination level of a mipmap generation pass
+#[repr(C)]
+#[derive(Debug)]
+pub struct MipmapData {
+    pub src_extent: [u32; 4],
+    pub dst_extent: [u32; 4],
+}
+
+// Resource dimension of the image, either `TEXTURE2D` or `TEXTURE3D`
+pub type MipmapKey = d3d12::D3D12_RESOURCE_DIMENSION;
+type MipmapMap = FastHashMap<MipmapKey, BlitPipe>;
+
 #[derive(Debug)]
 pub(crate) struct ServicePipes {
     pub(crate) device: native::Device,
     blits_2d_color: Mutex<BlitMap>,
+    mipmaps: Mutex<MipmapMap>,
 }
 
 impl ServicePipes {
@@ -48,6 +61,7 @@ impl ServicePipes {
         ServicePipes {
             device,
             blits_2d_color: Mutex::new(FastHashMap::default()),
+            mipmaps: Mutex::new(FastHashMap::default()),
         }
     }
 
@@ -56,6 +70,103 @@ impl ServicePipes {
         for (_, pipe) in &*blits {
             pipe.destroy();
         }
+        let mipmaps = self.mipmaps.lock().unwrap();
+        for (_, pipe) in &*mipmaps {
+            pipe.destroy();
+        }
+    }
+
+    pub fn get_mipmap(&self, key: MipmapKey) -> BlitPipe {
+        let mut mipmaps = self.mipmaps.lock().unwrap();
+        mipmaps
+            .entry(key)
+            .or_insert_with(|| self.create_mipmap(key))
+            .clone()
+    }
+
+    fn create_mipmap(&self, dimension: MipmapKey) -> BlitPipe {
+        // Source level and destination level of the image, stored next to each other.
+        let descriptor_ranges = [
+            descriptor::DescriptorRange::new(
+                descriptor::DescriptorRangeType::SRV,
+                1,
+                native::descriptor::Binding {
+                    register: 0,
+                    space: 0,
+                },
+                0,
+            ),
+            descriptor::DescriptorRange::new(
+                descriptor::DescriptorRangeType::UAV,
+                1,
+                native::descriptor::Binding {
+                    register: 0,
+                    space: 0,
+                },
+                1,
+            ),
+        ];
+
+        let root_parameters = [
+            descriptor::RootParameter::descriptor_table(
+                descriptor::ShaderVisibility::All,
+                &descriptor_ranges,
+            ),
+            descriptor::RootParameter::constants(
+                descriptor::ShaderVisibility::All,
+                native::descriptor::Binding {
+                    register: 0,
+                    space: 0,
+                },
+                (mem::size_of::<MipmapData>() / 4) as _,
+            ),
+        ];
+
+        let ((signature_raw, error), _hr) = native::RootSignature::serialize(
+            descriptor::RootSignatureVersion::V1_0,
+            &root_parameters,
+            &[],
+            descriptor::RootSignatureFlags::empty(),
+        );
+
+        if !error.is_null() {
+            error!("D3D12SerializeRootSignature error: {:?}", unsafe {
+                error.as_c_str().to_str().unwrap()
+            });
+            unsafe { error.destroy() };
+        }
+
+        let (signature, _hr) = self.device.create_root_signature(signature_raw, 0);
+        unsafe { signature_raw.destroy() };
+
+        let entry: &[u8] = if dimension == d3d12::D3D12_RESOURCE_DIMENSION_TEXTURE3D {
+            b"cs_mip_3d\0"
+        } else {
+            b"cs_mip_2d\0"
+        };
+        let shader_src = include_bytes!("../shaders/mipmap.hlsl");
+        // TODO: check results
+        let ((cs, _), _hr_cs) = pso::Shader::compile(
+            shader_src,
+            unsafe { CStr::from_bytes_with_nul_unchecked(b"cs_5_0\0") },
+            unsafe { CStr::from_bytes_with_nul_unchecked(entry) },
+            pso::ShaderCompileFlags::empty(),
+        );
+
+        let (pipeline, hr) = self.device.create_compute_pipeline_state(
+            signature,
+            pso::Shader::from_blob(cs),
+            0,
+            pso::CachedPSO::null(),
+            pso::PipelineStateFlags::empty(),
+        );
+        unsafe { cs.destroy() };
+        assert_eq!(hr, winerror::S_OK);
+
+        BlitPipe {
+            pipeline,
+            signature,
+        }
     }
 
     pub fn get_blit_2d_color(&self, key: BlitKey) -> BlitPipe {
@@ -14,24 +14,34 @@ extern crate winapi;
 extern crate winit;
 
 mod command;
+mod command_list;
 mod conv;
 mod descriptors_cpu;
 mod device;
 mod internal;
 mod pool;
+mod render_pass;
 mod resource;
 mod root_constants;
+#[cfg(all(test, feature = "warp"))]
+mod test_utils;
 mod window;
 
+pub use render_pass::RenderPassSplit;
+pub use resource::DescriptorBindingFlags;
+pub use window::{ColorSpace, FullscreenError};
+
 use descriptors_cpu::DescriptorCpuPool;
 use hal::adapter::DeviceType;
 use hal::pso::PipelineStage;
 use hal::queue::{QueueFamilyId, Queues};
 use hal::{error, format as f, image, memory, Features, Limits, QueueType, SwapImageIndex};
 
-use winapi::shared::minwindef::TRUE;
+use winapi::shared::minwindef::{BOOL, FALSE, TRUE, UINT};
 use winapi::shared::{dxgi, dxgi1_2, dxgi1_3, dxgi1_4, dxgi1_6, winerror};
-use winapi::um::{d3d12, d3d12sdklayers, dxgidebug, handleapi, synchapi, winbase};
+use winapi::um::{
+    d3d12, d3d12sdklayers, d3dcommon, dxgidebug, handleapi, synchapi, winbase, winnt,
+};
 use winapi::Interface;
 
 use std::borrow::Borrow;
@@ -54,6 +64,20 @@ const MAX_VERTEX_BUFFERS: usize = 16;
 
 const NUM_HEAP_PROPERTIES: usize = 3;
 
+// D3D12 has no alignment requirements for mapped ranges and all host visible heaps are
+// coherent, CPU caches are snooped on READBACK heaps. Ranges are aligned to the cache line
+// size to be on the safe side for write-combined UPLOAD heaps.
+const NON_COHERENT_ATOM_SIZE: u64 = 64;
+
+// Required alignments of buffer offsets and row pitches in buffer-image copies. Copies are
+// split to match the D3D12 placement and pitch alignments, which are only reported as the
+// optimal alignments, offsets only need to be a multiple of the texel block size on top.
+const MIN_BUFFER_COPY_OFFSET_ALIGNMENT: u64 = 1;
+const MIN_BUFFER_COPY_PITCH_ALIGNMENT: u64 = 1;
+
+// Maximum number of CBV/SRV/UAV descriptors in a shader-visible heap for Tier 1.
+const MAX_SHADER_VISIBLE_SRV_CBV_UAV_HEAP_SIZE: u64 = 1_000_000;
+
 // Memory types are grouped according to the supported resources.
 // Grouping is done to circumvent the limitations of heap tier 1 devices.
 // Devices with Tier 1 will expose `BuffersOnl`, `ImageOnly` and `TargetOnly`.
@@ -153,7 +177,8 @@ impl hal::QueueFamily for QueueFamily {
             QueueFamily::Normal(QueueType::General) => 1,
             QueueFamily::Normal(QueueType::Compute) => 2,
             QueueFamily::Normal(QueueType::Transfer) => 3,
-            _ => unreachable!(),
+            // Not exposed, but keeps the mapping total should it be added.
+            QueueFamily::Normal(QueueType::Graphics) => 4,
         })
     }
 }
@@ -184,6 +209,9 @@ static QUEUE_FAMILIES: [QueueFamily; 4] = [
 pub struct PhysicalDevice {
     #[derivative(Debug = "ignore")]
     adapter: native::WeakPtr<dxgi1_2::IDXGIAdapter2>,
+    // Used for querying the video memory budget.
+    #[derivative(Debug = "ignore")]
+    adapter3: native::WeakPtr<dxgi1_4::IDXGIAdapter3>,
     features: Features,
     limits: Limits,
     #[derivative(Debug = "ignore")]
@@ -191,48 +219,222 @@ pub struct PhysicalDevice {
     private_caps: Capabilities,
     heap_properties: &'static [HeapProperties; NUM_HEAP_PROPERTIES],
     memory_properties: hal::MemoryProperties,
-    // Indicates that there is currently an active logical device.
-    // Opening the same adapter multiple times will return the same D3D12Device again.
-    is_open: Arc<Mutex<bool>>,
+    // Locally unique identifier of the adapter, cached at enumeration.
+    luid: [u8; 8],
+    driver_version: Option<u64>,
+    // Number of currently active logical devices.
+    // Opening the same adapter multiple times will return the same D3D12Device again,
+    // which is reference counted. Each logical device owns its queues and descriptor heaps.
+    open_count: Arc<Mutex<usize>>,
+    max_queue_priority: QueuePriorityClass,
 }
 
 unsafe impl Send for PhysicalDevice {}
 unsafe impl Sync for PhysicalDevice {}
 
+impl PhysicalDevice {
+    /// Check if row-major textures can be shared with other adapters.
+    ///
+    /// Buffers can always be shared, see `Device::create_cross_adapter_buffer`.
+    pub fn supports_cross_adapter_row_major_textures(&self) -> bool {
+        self.private_caps.cross_adapter_row_major_texture
+    }
+
+    /// Query the current video memory budget and usage of a memory segment.
+    ///
+    /// The budget changes over time, depending on the memory usage of other applications.
+    pub fn memory_budget(&self, segment: MemorySegment) -> MemoryBudget {
+        query_video_memory(self.adapter3, segment)
+    }
+
+    /// Set the highest priority class of queues created by `open`.
+    ///
+    /// All queues have normal priority by default, independent of the requested
+    /// `QueuePriority`. Realtime queues require elevated privileges, queues fall back
+    /// to high priority without them.
+    pub fn set_max_queue_priority(&mut self, priority: QueuePriorityClass) {
+        self.max_queue_priority = priority;
+    }
+
+    /// Check if the depth bias of pipelines can be set dynamically.
+    ///
+    /// Without support, pipelines with a dynamic depth bias fall back to no bias
+    /// and `set_depth_bias` only logs an error.
+    pub fn supports_dynamic_depth_bias(&self) -> bool {
+        self.private_caps.dynamic_depth_bias
+    }
+
+    /// Check if descriptor set layouts can have bindings with `DescriptorBindingFlags`.
+    ///
+    /// Requires resource binding tier 3, see `Device::create_descriptor_set_layout_with_flags`.
+    pub fn supports_bindless(&self) -> bool {
+        self.private_caps.bindless
+    }
+
+    /// Check if multisampled images of a format can be resolved.
+    pub fn supports_resolve(&self, format: f::Format) -> bool {
+        self.format_properties.supports_resolve(format as usize)
+    }
+
+    /// Feature level of devices created for this adapter.
+    pub fn feature_level(&self) -> d3dcommon::D3D_FEATURE_LEVEL {
+        self.private_caps.feature_level
+    }
+
+    /// Locally unique identifier of the adapter.
+    ///
+    /// The LUID is stable until the system restarts and matches the `AdapterLuid`
+    /// reported by DXGI, which allows correlating the adapter with other APIs.
+    pub fn luid(&self) -> [u8; 8] {
+        self.luid
+    }
+
+    /// Version of the user mode driver, if reported by the adapter.
+    ///
+    /// The four 16-bit parts are packed from the most significant one,
+    /// e.g. `0x001A_0014_000D_0C3F` for driver version `26.20.13.3135`.
+    pub fn driver_version(&self) -> Option<u64> {
+        self.driver_version
+    }
+}
+
+// Software adapters, like WARP, rasterize on the CPU. Hardware adapters sharing memory with
+// the CPU (UMA) are integrated into the host, all others are assumed to be discrete.
+fn device_type(adapter_flags: UINT, uma: bool) -> DeviceType {
+    if (adapter_flags & dxgi::DXGI_ADAPTER_FLAG_SOFTWARE) != 0 {
+        DeviceType::Cpu
+    } else if uma {
+        DeviceType::IntegratedGpu
+    } else {
+        DeviceType::DiscreteGpu
+    }
+}
+
+// Bytes of a LUID in memory order, low part followed by the high part.
+fn luid_bytes(luid: &winnt::LUID) -> [u8; 8] {
+    let low = luid.LowPart;
+    let high = luid.HighPart as u32;
+    [
+        low as u8,
+        (low >> 8) as u8,
+        (low >> 16) as u8,
+        (low >> 24) as u8,
+        high as u8,
+        (high >> 8) as u8,
+        (high >> 16) as u8,
+        (high >> 24) as u8,
+    ]
+}
+
+/// Video memory segment of an adapter.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MemorySegment {
+    /// Memory local to the adapter, i.e. video memory of discrete adapters.
+    Local,
+    /// System memory accessible by the adapter.
+    NonLocal,
+}
+
+/// Priority class of command queues, see `PhysicalDevice::set_max_queue_priority`.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum QueuePriorityClass {
+    /// Normal priority, used for all queues by default.
+    Normal,
+    /// High priority, used for queues with a priority above 0.5.
+    High,
+    /// Global realtime priority, used for queues with the highest priority.
+    Realtime,
+}
+
+/// Video memory budget and usage of a memory segment, in bytes.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct MemoryBudget {
+    /// Memory the application should stay below to avoid stuttering or eviction.
+    pub budget: u64,
+    /// Memory currently used by the application.
+    pub current_usage: u64,
+    /// Memory which can be reserved by the application.
+    pub available_for_reservation: u64,
+    /// Memory currently reserved by the application.
+    pub current_reservation: u64,
+}
+
+impl From<dxgi1_4::DXGI_QUERY_VIDEO_MEMORY_INFO> for MemoryBudget {
+    fn from(info: dxgi1_4::DXGI_QUERY_VIDEO_MEMORY_INFO) -> Self {
+        MemoryBudget {
+            budget: info.Budget,
+            current_usage: info.CurrentUsage,
+            available_for_reservation: info.AvailableForReservation,
+            current_reservation: info.CurrentReservation,
+        }
+    }
+}
+
+fn query_video_memory(
+    adapter: native::WeakPtr<dxgi1_4::IDXGIAdapter3>,
+    segment: MemorySegment,
+) -> MemoryBudget {
+    let segment = match segment {
+        MemorySegment::Local => dxgi1_4::DXGI_MEMORY_SEGMENT_GROUP_LOCAL,
+        MemorySegment::NonLocal => dxgi1_4::DXGI_MEMORY_SEGMENT_GROUP_NON_LOCAL,
+    };
+    unsafe {
+        let mut mem_info: dxgi1_4::DXGI_QUERY_VIDEO_MEMORY_INFO = mem::zeroed();
+        assert_eq!(
+            winerror::S_OK,
+            adapter.QueryVideoMemoryInfo(0, segment, &mut mem_info)
+        );
+        mem_info.into()
+    }
+}
+
 impl hal::PhysicalDevice<Backend> for PhysicalDevice {
     unsafe fn open(
         &self,
         families: &[(&QueueFamily, &[hal::QueuePriority])],
         requested_features: Features,
     ) -> Result<hal::Gpu<Backend>, error::DeviceCreationError> {
-        let lock = self.is_open.try_lock();
-        let mut open_guard = match lock {
-            Ok(inner) => inner,
-            Err(_) => return Err(error::DeviceCreationError::TooManyObjects),
+        // Serializes concurrent opens of the same adapter.
+        let mut open_count = match self.open_count.lock() {
+            Ok(count) => count,
+            Err(_) => return Err(error::DeviceCreationError::InitializationFailed),
         };
 
         if !self.features().contains(requested_features) {
             return Err(error::DeviceCreationError::MissingFeature);
         }
 
-        let (device_raw, hr_device) =
-            native::Device::create(self.adapter, native::FeatureLevel::L11_0);
-        if !winerror::SUCCEEDED(hr_device) {
-            error!("error on device creation: {:x}", hr_device);
-        }
+        let device_raw = match create_device(self.adapter) {
+            Some((device, _)) => device,
+            None => {
+                error!("error on device creation");
+                return Err(error::DeviceCreationError::InitializationFailed);
+            }
+        };
 
-        // Always create the presentation queue in case we want to build a swapchain.
-        let (present_queue, hr_queue) = device_raw.create_command_queue(
-            QueueFamily::Present.native_type(),
-            native::queue::Priority::Normal,
-            native::queue::CommandQueueFlags::empty(),
-            0,
-        );
-        if !winerror::SUCCEEDED(hr_queue) {
-            error!("error on queue creation: {:x}", hr_queue);
-        }
+        // Only create the presentation queue if the `Present` family has been requested,
+        // compute-only or headless devices don't need it.
+        let wants_present = families.iter().any(|&(family, _)| match *family {
+            QueueFamily::Present => true,
+            QueueFamily::Normal(_) => false,
+        });
+        let present_queue = if wants_present {
+            let (present_queue, hr_queue) = device_raw.create_command_queue(
+                QueueFamily::Present.native_type(),
+                native::queue::Priority::Normal,
+                native::queue::CommandQueueFlags::empty(),
+                0,
+            );
+            if !winerror::SUCCEEDED(hr_queue) {
+                error!("error on queue creation: {:x}", hr_queue);
+            }
+            Some(present_queue)
+        } else {
+            None
+        };
 
-        let mut device = Device::new(device_raw, &self, present_queue);
+        let mut device = Device::new(device_raw, &self, present_queue, requested_features);
+        let max_priority = self.max_queue_priority;
 
         let queue_groups = families
             .into_iter()
@@ -247,7 +449,7 @@ impl hal::PhysicalDevice<Backend> for PhysicalDevice {
                         // Number of queues need to be larger than 0 else it
                         // violates the specification.
                         let queue = CommandQueue {
-                            raw: device.present_queue.clone(),
+                            raw: device.present_queue.clone().unwrap(),
                             idle_fence: device.create_raw_fence(false),
                             idle_event: create_idle_event(),
                         };
@@ -256,13 +458,26 @@ impl hal::PhysicalDevice<Backend> for PhysicalDevice {
                     }
                     QueueFamily::Normal(_) => {
                         let list_type = family.native_type();
-                        for _ in 0 .. priorities.len() {
-                            let (queue, hr_queue) = device_raw.create_command_queue(
-                                list_type,
-                                native::queue::Priority::Normal,
-                                native::queue::CommandQueueFlags::empty(),
-                                0,
-                            );
+                        for &priority in priorities {
+                            let create_queue = |priority| {
+                                device_raw.create_command_queue(
+                                    list_type,
+                                    priority,
+                                    native::queue::CommandQueueFlags::empty(),
+                                    0,
+                                )
+                            };
+                            let (mut queue, mut hr_queue) =
+                                create_queue(conv::map_queue_priority(priority, max_priority));
+                            let realtime = max_priority == QueuePriorityClass::Realtime;
+                            if !winerror::SUCCEEDED(hr_queue) && realtime && priority >= 1.0 {
+                                // Realtime queues require privileges, degrade to high priority.
+                                info!("Unable to create a realtime queue, using high priority");
+                                let (high_queue, hr_high) =
+                                    create_queue(native::queue::Priority::High);
+                                queue = high_queue;
+                                hr_queue = hr_high;
+                            }
 
                             if winerror::SUCCEEDED(hr_queue) {
                                 let queue = CommandQueue {
@@ -283,7 +498,7 @@ impl hal::PhysicalDevice<Backend> for PhysicalDevice {
             })
             .collect();
 
-        *open_guard = true;
+        *open_count += 1;
 
         Ok(hal::Gpu {
             device,
@@ -341,6 +556,24 @@ impl hal::PhysicalDevice<Backend> for PhysicalDevice {
 
         let max_resource_size =
             (d3d12::D3D12_REQ_RESOURCE_SIZE_IN_MEGABYTES_EXPRESSION_A_TERM as usize) << 20;
+        if format.is_planar() {
+            // Planar images are single-level, single-sample 2D images. The layout
+            // of each plane is reported by `get_image_subresource_footprint`.
+            return match (tiling, dimensions) {
+                (image::Tiling::Optimal, 2) => Some(image::FormatProperties {
+                    max_extent: image::Extent {
+                        width: d3d12::D3D12_REQ_TEXTURE2D_U_OR_V_DIMENSION,
+                        height: d3d12::D3D12_REQ_TEXTURE2D_U_OR_V_DIMENSION,
+                        depth: 1,
+                    },
+                    max_levels: 1,
+                    max_layers: d3d12::D3D12_REQ_TEXTURE2D_ARRAY_AXIS_DIMENSION as _,
+                    sample_count_mask: 0x1,
+                    max_resource_size,
+                }),
+                _ => None,
+            };
+        }
         Some(match tiling {
             image::Tiling::Optimal => image::FormatProperties {
                 max_extent: match dimensions {
@@ -372,7 +605,7 @@ impl hal::PhysicalDevice<Backend> for PhysicalDevice {
                     && (usage.contains(image::Usage::COLOR_ATTACHMENT)
                         | usage.contains(image::Usage::DEPTH_STENCIL_ATTACHMENT))
                 {
-                    0x3F //TODO: use D3D12_FEATURE_DATA_FORMAT_SUPPORT
+                    self.format_properties.get_sample_count_mask(format as usize)
                 } else {
                     0x1
                 },
@@ -422,6 +655,207 @@ impl CommandQueue {
         self.idle_fence.destroy();
         self.raw.destroy();
     }
+
+    unsafe fn execute_lists(&self, lists: &mut Vec<*mut d3d12::ID3D12CommandList>) {
+        if !lists.is_empty() {
+            self.raw
+                .ExecuteCommandLists(lists.len() as _, lists.as_mut_ptr());
+            lists.clear();
+        }
+    }
+
+    /// Frequency of the GPU timestamp counter of this queue in ticks per second.
+    ///
+    /// Timestamp queries written on this queue need to be divided by this frequency.
+    /// Copy queues may have a different frequency than direct and compute queues.
+    pub fn timestamp_frequency(&self) -> u64 {
+        let mut frequency = 0;
+        let hr = unsafe { self.raw.GetTimestampFrequency(&mut frequency) };
+        if !winerror::SUCCEEDED(hr) {
+            error!("error on querying timestamp frequency: {:x}", hr);
+        }
+        frequency
+    }
+
+    /// Period of the GPU timestamp counter of this queue in nanoseconds per tick.
+    ///
+    /// Returns 0 if the queue doesn't support timestamps.
+    /// Timestamps of copy queues are only comparable to those of other queues after
+    /// calibration with `ID3D12CommandQueue::GetClockCalibration`.
+    pub fn timestamp_period(&self) -> f32 {
+        timestamp_period(self.timestamp_frequency())
+    }
+
+    /// Bind a region of tiles of a sparse image to memory, or unbind it if `memory` is `None`.
+    ///
+    /// `offset` and `extent` are given in tiles of the image subresource. The tiles are
+    /// mapped in row-major order to consecutive 64KB tiles of the memory, starting at the
+    /// memory offset. The memory can't be dedicated memory. See `Device::create_sparse_image`.
+    pub unsafe fn bind_sparse_image(
+        &mut self,
+        image: &resource::Image,
+        subresource: image::Subresource,
+        offset: image::Offset,
+        extent: image::Extent,
+        memory: Option<(&resource::Memory, u64)>,
+    ) {
+        let image = image.expect_bound();
+        match image.place {
+            resource::Place::Reserved => {}
+            _ => {
+                error!("Only sparse images can be bound per tile");
+                return;
+            }
+        }
+        let tile_size = d3d12::D3D12_TILED_RESOURCE_TILE_SIZE_IN_BYTES as u64;
+        let (heap, start_tile, range_flags) = match memory {
+            Some((memory, memory_offset)) => {
+                if memory.heap.is_null() || memory_offset % tile_size != 0 {
+                    error!(
+                        "Sparse images are bound to tiles of non-dedicated memory, given offset {}",
+                        memory_offset
+                    );
+                    return;
+                }
+                (
+                    memory.heap.as_mut_ptr(),
+                    (memory_offset / tile_size) as UINT,
+                    d3d12::D3D12_TILE_RANGE_FLAG_NONE,
+                )
+            }
+            None => (ptr::null_mut(), 0, d3d12::D3D12_TILE_RANGE_FLAG_NULL),
+        };
+
+        let coordinate = d3d12::D3D12_TILED_RESOURCE_COORDINATE {
+            X: offset.x as _,
+            Y: offset.y as _,
+            Z: offset.z as _,
+            Subresource: image.calc_subresource(
+                subresource.level as _,
+                subresource.layer as _,
+                0,
+            ),
+        };
+        let num_tiles = extent.width * extent.height * extent.depth;
+        let size = d3d12::D3D12_TILE_REGION_SIZE {
+            NumTiles: num_tiles,
+            UseBox: TRUE,
+            Width: extent.width,
+            Height: extent.height as _,
+            Depth: extent.depth as _,
+        };
+        self.raw.UpdateTileMappings(
+            image.resource.as_mut_ptr(),
+            1,
+            &coordinate,
+            &size,
+            heap,
+            1,
+            &range_flags,
+            &start_tile,
+            &num_tiles,
+            d3d12::D3D12_TILE_MAPPING_FLAG_NONE,
+        );
+    }
+
+    /// Submit a batch of submissions to multiple queues.
+    ///
+    /// Submissions are issued in dependency order, each one after all submissions
+    /// of the batch signalling one of its wait semaphores. The batch is validated
+    /// up front, nothing is submitted if it's invalid.
+    pub unsafe fn submit_batch(
+        queues: &mut [&mut CommandQueue],
+        batch: &[BatchSubmission],
+    ) -> Result<(), BatchError> {
+        if let Some(submission) = batch.iter().find(|s| s.queue >= queues.len()) {
+            error!(
+                "Batch submission to queue {} out of {} queues",
+                submission.queue,
+                queues.len()
+            );
+            return Err(BatchError::InvalidQueue(submission.queue));
+        }
+
+        let waits = batch
+            .iter()
+            .map(|submission| {
+                submission
+                    .wait_semaphores
+                    .iter()
+                    .map(|&(semaphore, _)| semaphore.raw.as_mut_ptr())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let signals = batch
+            .iter()
+            .map(|submission| {
+                submission
+                    .signal_semaphores
+                    .iter()
+                    .map(|semaphore| semaphore.raw.as_mut_ptr())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let order = submission_order(&waits, &signals).ok_or(BatchError::CyclicDependencies)?;
+
+        for index in order {
+            let submission = &batch[index];
+            hal::queue::RawCommandQueue::submit(
+                &mut *queues[submission.queue],
+                hal::queue::Submission {
+                    command_buffers: submission.command_buffers.iter().cloned(),
+                    wait_semaphores: submission
+                        .wait_semaphores
+                        .iter()
+                        .map(|&(semaphore, stages)| (semaphore, stages)),
+                    signal_semaphores: submission.signal_semaphores.iter().cloned(),
+                },
+                None,
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Submission to one of the queues of a batch, see `CommandQueue::submit_batch`.
+#[derive(Debug)]
+pub struct BatchSubmission<'a> {
+    /// Index of the queue to submit to.
+    pub queue: usize,
+    pub command_buffers: &'a [&'a command::CommandBuffer],
+    /// Semaphores to wait for, before the given pipeline stages.
+    pub wait_semaphores: &'a [(&'a resource::Semaphore, PipelineStage)],
+    pub signal_semaphores: &'a [&'a resource::Semaphore],
+}
+
+/// Error on submitting a batch with `CommandQueue::submit_batch`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BatchError {
+    /// Submission to a queue index outside of the given queues.
+    InvalidQueue(usize),
+    /// Semaphore dependencies between the submissions are cyclic.
+    CyclicDependencies,
+}
+
+// Order submissions such that every submission comes after all submissions
+// signalling one of its wait semaphores. Returns `None` for cyclic dependencies.
+fn submission_order<T: PartialEq>(waits: &[Vec<T>], signals: &[Vec<T>]) -> Option<Vec<usize>> {
+    let num = waits.len();
+    let mut issued = vec![false; num];
+    let mut order = Vec::with_capacity(num);
+
+    while order.len() < num {
+        let next = (0 .. num).find(|&i| {
+            !issued[i]
+                && waits[i]
+                    .iter()
+                    .all(|wait| (0 .. num).all(|j| issued[j] || !signals[j].contains(wait)))
+        })?;
+        issued[next] = true;
+        order.push(next);
+    }
+
+    Some(order)
 }
 
 unsafe impl Send for CommandQueue {}
@@ -444,17 +878,58 @@ impl hal::queue::RawCommandQueue<Backend> for CommandQueue {
         self.idle_fence.signal(0);
         synchapi::ResetEvent(self.idle_event.0);
 
-        // TODO: semaphores
-        let mut lists = submission
-            .command_buffers
-            .into_iter()
-            .map(|buf| buf.borrow().as_raw_list())
-            .collect::<Vec<_>>();
-        self.raw
-            .ExecuteCommandLists(lists.len() as _, lists.as_mut_ptr());
+        // Semaphores are waited for the value of their last signal operation,
+        // values never decrease so no reset is required for reuse.
+        for (semaphore, _) in submission.wait_semaphores {
+            let semaphore = semaphore.borrow();
+            let value = semaphore.wait_value();
+            assert_eq!(
+                winerror::S_OK,
+                self.raw.Wait(semaphore.raw.as_mut_ptr(), value)
+            );
+        }
+
+        let mut lists = Vec::new();
+        let mut query_signals = Vec::new();
+        for buf in submission.command_buffers {
+            let buf = buf.borrow();
+            buf.submit_query_resolves(&mut query_signals);
+            // Event waits and signals are issued on the queue around the command list
+            // which recorded them, splitting the batch if required.
+            if !buf.event_waits.is_empty() {
+                self.execute_lists(&mut lists);
+                for fence in &buf.event_waits {
+                    assert_eq!(winerror::S_OK, self.raw.Wait(fence.as_mut_ptr(), 1));
+                }
+            }
+            lists.push(buf.as_raw_list());
+            if !buf.event_signals.is_empty() {
+                self.execute_lists(&mut lists);
+                for &(fence, value) in &buf.event_signals {
+                    assert_eq!(winerror::S_OK, self.raw.Signal(fence.as_mut_ptr(), value));
+                }
+            }
+        }
+        self.execute_lists(&mut lists);
+
+        for (fence, value) in query_signals {
+            assert_eq!(winerror::S_OK, self.raw.Signal(fence.as_mut_ptr(), value));
+        }
+
+        for semaphore in submission.signal_semaphores {
+            let semaphore = semaphore.borrow();
+            let value = semaphore.next_signal_value();
+            assert_eq!(
+                winerror::S_OK,
+                self.raw.Signal(semaphore.raw.as_mut_ptr(), value)
+            );
+        }
 
         if let Some(fence) = fence {
-            assert_eq!(winerror::S_OK, self.raw.Signal(fence.raw.as_mut_ptr(), 1));
+            assert_eq!(
+                winerror::S_OK,
+                self.raw.Signal(fence.raw.as_mut_ptr(), fence.target.get())
+            );
         }
     }
 
@@ -470,8 +945,21 @@ impl hal::queue::RawCommandQueue<Backend> for CommandQueue {
         Iw: IntoIterator<Item = &'a S>,
     {
         // TODO: semaphores
-        for (swapchain, _) in swapchains {
-            swapchain.borrow().inner.Present(1, 0);
+        for (swapchain, index) in swapchains {
+            let swapchain = swapchain.borrow();
+            if let Some(ref msaa) = swapchain.msaa {
+                msaa.resolve(
+                    self.raw,
+                    index as _,
+                    swapchain.resources[index as usize],
+                    swapchain.buffer_format,
+                );
+            }
+            if swapchain.allow_tearing {
+                swapchain.inner.Present(0, dxgi::DXGI_PRESENT_ALLOW_TEARING);
+            } else {
+                swapchain.inner.Present(1, 0);
+            }
         }
 
         Ok(None)
@@ -503,6 +991,39 @@ enum MemoryArchitecture {
 pub struct Capabilities {
     heterogeneous_resource_heaps: bool,
     memory_architecture: MemoryArchitecture,
+    cross_adapter_row_major_texture: bool,
+    feature_level: d3dcommon::D3D_FEATURE_LEVEL,
+    // Depth bias can be set on the command list, see `ID3D12GraphicsCommandList9`.
+    dynamic_depth_bias: bool,
+    // Resource binding tier 3, descriptor tables may be unbounded and partially bound.
+    bindless: bool,
+    // Root signatures can be serialized as version 1.1 with descriptor range flags.
+    root_signature_1_1: bool,
+}
+
+// Feature levels requested on device creation, from highest to lowest.
+const FEATURE_LEVELS: [native::FeatureLevel; 4] = [
+    native::FeatureLevel::L12_1,
+    native::FeatureLevel::L12_0,
+    native::FeatureLevel::L11_1,
+    native::FeatureLevel::L11_0,
+];
+
+// Create a device with the highest feature level supported by the adapter.
+fn create_device(
+    adapter: native::WeakPtr<dxgi1_2::IDXGIAdapter2>,
+) -> Option<(native::Device, d3dcommon::D3D_FEATURE_LEVEL)> {
+    FEATURE_LEVELS
+        .iter()
+        .filter_map(|&level| {
+            let (device, hr) = native::Device::create(adapter, level);
+            if winerror::SUCCEEDED(hr) {
+                Some((device, level as _))
+            } else {
+                None
+            }
+        })
+        .next()
 }
 
 #[derive(Clone, Debug)]
@@ -525,6 +1046,8 @@ impl CmdSignatures {
 struct Shared {
     pub signatures: CmdSignatures,
     pub service_pipes: internal::ServicePipes,
+    // Enabled device features, required for validation of commands.
+    pub features: Features,
 }
 
 impl Shared {
@@ -541,27 +1064,35 @@ pub struct Device {
     private_caps: Capabilities,
     format_properties: Arc<FormatProperties>,
     heap_properties: &'static [HeapProperties],
+    // Memory types exposed by the physical device, for internal allocations.
+    memory_types: Vec<hal::MemoryType>,
     // CPU only pools
     rtv_pool: Mutex<DescriptorCpuPool>,
     dsv_pool: Mutex<DescriptorCpuPool>,
     srv_uav_pool: Mutex<DescriptorCpuPool>,
     sampler_pool: Mutex<DescriptorCpuPool>,
+    // Sampler descriptors shared between samplers with identical states.
+    sampler_cache: Mutex<descriptors_cpu::SamplerCache>,
     descriptor_update_pools: Mutex<Vec<descriptors_cpu::HeapLinear>>,
     // CPU/GPU descriptor heaps
-    heap_srv_cbv_uav: Mutex<resource::DescriptorHeap>,
-    heap_sampler: Mutex<resource::DescriptorHeap>,
+    heap_srv_cbv_uav: Mutex<resource::SharedDescriptorHeap>,
+    // Shader-visible sampler heap, shared by the sampler tables of descriptor sets.
+    sampler_tables: Arc<Mutex<resource::SamplerTables>>,
     #[derivative(Debug = "ignore")]
     events: Mutex<Vec<native::Event>>,
     #[derivative(Debug = "ignore")]
     shared: Arc<Shared>,
     // Present queue exposed by the `Present` queue family.
     // Required for swapchain creation. Only a single queue supports presentation.
-    present_queue: native::CommandQueue,
+    // `None` if the device has been opened without the `Present` family.
+    present_queue: Option<native::CommandQueue>,
+    // Features enabled on device creation.
+    features: Features,
     // List of all queues created from this device, including present queue.
     // Needed for `wait_idle`.
     queues: Vec<CommandQueue>,
-    // Indicates that there is currently an active device.
-    open: Arc<Mutex<bool>>,
+    // Number of active devices sharing the raw device.
+    open_count: Arc<Mutex<usize>>,
 }
 unsafe impl Send for Device {} //blocked by ComPtr
 unsafe impl Sync for Device {} //blocked by ComPtr
@@ -570,7 +1101,8 @@ impl Device {
     fn new(
         device: native::Device,
         physical_device: &PhysicalDevice,
-        present_queue: native::CommandQueue,
+        present_queue: Option<native::CommandQueue>,
+        features: Features,
     ) -> Self {
         // Allocate descriptor heaps
         let rtv_pool = DescriptorCpuPool::new(device, descriptor::HeapType::Rtv);
@@ -582,11 +1114,15 @@ impl Device {
             device,
             descriptor::HeapType::CbvSrvUav,
             true,
-            1_000_000, // maximum number of CBV/SRV/UAV descriptors in heap for Tier 1
+            MAX_SHADER_VISIBLE_SRV_CBV_UAV_HEAP_SIZE as _,
         );
 
-        let heap_sampler =
-            Self::create_descriptor_heap_impl(device, descriptor::HeapType::Sampler, true, 2_048);
+        let heap_sampler = Self::create_descriptor_heap_impl(
+            device,
+            descriptor::HeapType::Sampler,
+            true,
+            d3d12::D3D12_MAX_SHADER_VISIBLE_SAMPLER_HEAP_SIZE as _,
+        );
 
         let draw_signature = Self::create_command_signature(device, device::CommandSignature::Draw);
         let draw_indexed_signature =
@@ -603,6 +1139,7 @@ impl Device {
         let shared = Shared {
             signatures,
             service_pipes,
+            features,
         };
 
         Device {
@@ -610,18 +1147,21 @@ impl Device {
             private_caps: physical_device.private_caps,
             format_properties: physical_device.format_properties.clone(),
             heap_properties: physical_device.heap_properties,
+            memory_types: physical_device.memory_properties.memory_types.clone(),
             rtv_pool: Mutex::new(rtv_pool),
             dsv_pool: Mutex::new(dsv_pool),
             srv_uav_pool: Mutex::new(srv_uav_pool),
             sampler_pool: Mutex::new(sampler_pool),
+            sampler_cache: Mutex::new(descriptors_cpu::SamplerCache::default()),
             descriptor_update_pools: Mutex::new(Vec::new()),
-            heap_srv_cbv_uav: Mutex::new(heap_srv_cbv_uav),
-            heap_sampler: Mutex::new(heap_sampler),
+            heap_srv_cbv_uav: Mutex::new(resource::SharedDescriptorHeap::new(heap_srv_cbv_uav)),
+            sampler_tables: Arc::new(Mutex::new(resource::SamplerTables::new(heap_sampler))),
             events: Mutex::new(Vec::new()),
             shared: Arc::new(shared),
             present_queue,
+            features,
             queues: Vec::new(),
-            open: physical_device.is_open.clone(),
+            open_count: physical_device.open_count.clone(),
         }
     }
 
@@ -629,6 +1169,13 @@ impl Device {
         self.queues.push(queue);
     }
 
+    /// Features enabled when opening the device.
+    ///
+    /// Subset of the features supported by the physical device.
+    pub fn enabled_features(&self) -> Features {
+        self.features
+    }
+
     /// Get the native d3d12 device.
     ///
     /// Required for FFI with libraries like RenderDoc.
@@ -639,7 +1186,8 @@ impl Device {
 
 impl Drop for Device {
     fn drop(&mut self) {
-        *self.open.lock().unwrap() = false;
+        let mut open_count = self.open_count.lock().unwrap();
+        *open_count -= 1;
 
         unsafe {
             for queue in &mut self.queues {
@@ -647,8 +1195,9 @@ impl Drop for Device {
             }
 
             self.shared.destroy();
-            self.heap_srv_cbv_uav.lock().unwrap().destroy();
-            self.heap_sampler.lock().unwrap().destroy();
+            // Descriptor pools still alive keep the heap until they are dropped.
+            self.heap_srv_cbv_uav.lock().unwrap().owner = None;
+            self.sampler_tables.lock().unwrap().heap.destroy();
             self.rtv_pool.lock().unwrap().destroy();
             self.dsv_pool.lock().unwrap().destroy();
             self.srv_uav_pool.lock().unwrap().destroy();
@@ -658,11 +1207,14 @@ impl Drop for Device {
                 pool.destroy();
             }
 
-            // Debug tracking alive objects
-            let (debug_device, hr_debug) = self.raw.cast::<d3d12sdklayers::ID3D12DebugDevice>();
-            if winerror::SUCCEEDED(hr_debug) {
-                debug_device.ReportLiveDeviceObjects(d3d12sdklayers::D3D12_RLDO_DETAIL);
-                debug_device.destroy();
+            // Debug tracking alive objects, once the last device sharing the raw device is gone.
+            if *open_count == 0 {
+                let (debug_device, hr_debug) =
+                    self.raw.cast::<d3d12sdklayers::ID3D12DebugDevice>();
+                if winerror::SUCCEEDED(hr_debug) {
+                    debug_device.ReportLiveDeviceObjects(d3d12sdklayers::D3D12_RLDO_DETAIL);
+                    debug_device.destroy();
+                }
             }
 
             self.raw.destroy();
@@ -687,6 +1239,13 @@ unsafe impl Send for Instance {}
 unsafe impl Sync for Instance {}
 
 impl Instance {
+    /// Create a new instance.
+    ///
+    /// Debug builds enable the D3D12 debug layer. GPU-based validation, which additionally
+    /// catches invalid descriptors and resource states while executing on the GPU, can be
+    /// enabled by setting the `GFX_DX12_GPU_VALIDATION` environment variable.
+    /// GPU-based validation slows down rendering significantly and should only be
+    /// enabled while debugging.
     pub fn create(_: &str, _: u32) -> Instance {
         #[cfg(debug_assertions)]
         {
@@ -702,6 +1261,9 @@ impl Instance {
             if winerror::SUCCEEDED(hr) {
                 unsafe {
                     (*debug_controller).EnableDebugLayer();
+                    if std::env::var("GFX_DX12_GPU_VALIDATION").is_ok() {
+                        enable_gpu_based_validation(debug_controller);
+                    }
                     (*debug_controller).Release();
                 }
             }
@@ -747,15 +1309,43 @@ impl Instance {
             factory: dxgi_factory,
         }
     }
-}
 
-impl hal::Instance for Instance {
-    type Backend = Backend;
+    /// Enumerate the hardware adapters followed by the WARP software adapter.
+    ///
+    /// WARP is reported with `DeviceType::Cpu` and allows running on machines without a GPU.
+    pub fn enumerate_adapters_with_warp(&self) -> Vec<hal::Adapter<Backend>> {
+        let mut adapters = hal::Instance::enumerate_adapters(self);
+        // WARP may already be enumerated as a software adapter.
+        if adapters
+            .iter()
+            .any(|adapter| adapter.info.device_type == DeviceType::Cpu)
+        {
+            return adapters;
+        }
 
-    fn enumerate_adapters(&self) -> Vec<hal::Adapter<Backend>> {
-        use self::memory::Properties;
+        let mut adapter = native::WeakPtr::<dxgi1_2::IDXGIAdapter2>::null();
+        let hr = unsafe {
+            self.factory
+                .EnumWarpAdapter(&dxgi1_2::IDXGIAdapter2::uuidof(), adapter.mut_void())
+        };
+        if !winerror::SUCCEEDED(hr) {
+            error!("Failed to enumerate WARP adapter: {:x}", hr);
+            return adapters;
+        }
+
+        adapters.extend(self.create_adapter(adapter));
+        adapters
+    }
 
-        // Try to use high performance order by default (returns None on Windows < 1803)
+    /// Enumerate adapters ordered by the GPU preference.
+    ///
+    /// The preference is ignored on Windows versions before 1803, which enumerate
+    /// adapters in the default order.
+    pub fn enumerate_adapters_with_preference(
+        &self,
+        preference: GpuPreference,
+    ) -> Vec<hal::Adapter<Backend>> {
+        // Try to use the preferred order (returns None on Windows < 1803)
         let (use_f6, factory6) = unsafe {
             let (f6, hr) = self.factory.cast::<dxgi1_6::IDXGIFactory6>();
             if winerror::SUCCEEDED(hr) {
@@ -779,7 +1369,7 @@ impl hal::Instance for Instance {
                 let hr = unsafe {
                     factory6.EnumAdapterByGpuPreference(
                         cur_index,
-                        2, // HIGH_PERFORMANCE
+                        preference.to_dxgi(),
                         &dxgi1_2::IDXGIAdapter2::uuidof(),
                         adapter2.mut_void() as *mut *mut _,
                     )
@@ -815,314 +1405,388 @@ impl hal::Instance for Instance {
 
             cur_index += 1;
 
-            // Check for D3D12 support
-            // Create temporary device to get physical device information
-            let device = {
-                let (device, hr) = native::Device::create(adapter, native::FeatureLevel::L11_0);
-                if !winerror::SUCCEEDED(hr) {
-                    continue;
-                }
-                device
-            };
-
-            // We have found a possible adapter
-            // acquire the device information
-            let mut desc: dxgi1_2::DXGI_ADAPTER_DESC2 = unsafe { mem::zeroed() };
-            unsafe {
-                adapter.GetDesc2(&mut desc);
+            if let Some(adapter) = self.create_adapter(adapter) {
+                adapters.push(adapter);
             }
+        }
+        adapters
+    }
+
+    // Query the capabilities of a DXGI adapter, returns `None` if D3D12 isn't supported.
+    fn create_adapter(
+        &self,
+        adapter: native::WeakPtr<dxgi1_2::IDXGIAdapter2>,
+    ) -> Option<hal::Adapter<Backend>> {
+        use self::memory::Properties;
+
+        // Check for D3D12 support
+        // Create temporary device to get physical device information
+        let (device, feature_level) = create_device(adapter)?;
+
+        // We have found a possible adapter
+        // acquire the device information
+        let mut desc: dxgi1_2::DXGI_ADAPTER_DESC2 = unsafe { mem::zeroed() };
+        unsafe {
+            adapter.GetDesc2(&mut desc);
+        }
+
+        let device_name = {
+            let len = desc.Description.iter().take_while(|&&c| c != 0).count();
+            let name = <OsString as OsStringExt>::from_wide(&desc.Description[.. len]);
+            name.to_string_lossy().into_owned()
+        };
 
-            let device_name = {
-                let len = desc.Description.iter().take_while(|&&c| c != 0).count();
-                let name = <OsString as OsStringExt>::from_wide(&desc.Description[.. len]);
-                name.to_string_lossy().into_owned()
+        // The user mode driver version, only available through the legacy DXGI device interface.
+        let driver_version = {
+            let mut version: winnt::LARGE_INTEGER = unsafe { mem::zeroed() };
+            let hr = unsafe {
+                adapter.CheckInterfaceSupport(&dxgi::IDXGIDevice::uuidof(), &mut version)
             };
+            if winerror::SUCCEEDED(hr) {
+                Some(unsafe { *version.QuadPart() } as u64)
+            } else {
+                None
+            }
+        };
 
-            let info = hal::AdapterInfo {
-                name: device_name,
-                vendor: desc.VendorId as usize,
-                device: desc.DeviceId as usize,
-                device_type: if (desc.Flags & dxgi::DXGI_ADAPTER_FLAG_SOFTWARE) != 0 {
-                    DeviceType::VirtualGpu
-                } else {
-                    DeviceType::DiscreteGpu
-                },
+        let mut features: d3d12::D3D12_FEATURE_DATA_D3D12_OPTIONS = unsafe { mem::zeroed() };
+        assert_eq!(winerror::S_OK, unsafe {
+            device.CheckFeatureSupport(
+                d3d12::D3D12_FEATURE_D3D12_OPTIONS,
+                &mut features as *mut _ as *mut _,
+                mem::size_of::<d3d12::D3D12_FEATURE_DATA_D3D12_OPTIONS>() as _,
+            )
+        });
+
+        let mut features_architecture: d3d12::D3D12_FEATURE_DATA_ARCHITECTURE =
+            unsafe { mem::zeroed() };
+        assert_eq!(winerror::S_OK, unsafe {
+            device.CheckFeatureSupport(
+                d3d12::D3D12_FEATURE_ARCHITECTURE,
+                &mut features_architecture as *mut _ as *mut _,
+                mem::size_of::<d3d12::D3D12_FEATURE_DATA_ARCHITECTURE>() as _,
+            )
+        });
+
+        let depth_bounds_test_supported = {
+            let mut features2: d3d12::D3D12_FEATURE_DATA_D3D12_OPTIONS2 =
+                unsafe { mem::zeroed() };
+            let hr = unsafe {
+                device.CheckFeatureSupport(
+                    d3d12::D3D12_FEATURE_D3D12_OPTIONS2,
+                    &mut features2 as *mut _ as *mut _,
+                    mem::size_of::<d3d12::D3D12_FEATURE_DATA_D3D12_OPTIONS2>() as _,
+                )
             };
+            if hr == winerror::S_OK {
+                features2.DepthBoundsTestSupported != 0
+            } else {
+                false
+            }
+        };
 
-            let mut features: d3d12::D3D12_FEATURE_DATA_D3D12_OPTIONS = unsafe { mem::zeroed() };
-            assert_eq!(winerror::S_OK, unsafe {
+        let options3_features = {
+            let mut features3: FeatureDataOptions3 = unsafe { mem::zeroed() };
+            let hr = unsafe {
                 device.CheckFeatureSupport(
-                    d3d12::D3D12_FEATURE_D3D12_OPTIONS,
-                    &mut features as *mut _ as *mut _,
-                    mem::size_of::<d3d12::D3D12_FEATURE_DATA_D3D12_OPTIONS>() as _,
+                    D3D12_FEATURE_D3D12_OPTIONS3,
+                    &mut features3 as *mut _ as *mut _,
+                    mem::size_of::<FeatureDataOptions3>() as _,
                 )
-            });
+            };
+            if hr == winerror::S_OK {
+                features3.features()
+            } else {
+                Features::empty()
+            }
+        };
 
-            let mut features_architecture: d3d12::D3D12_FEATURE_DATA_ARCHITECTURE =
-                unsafe { mem::zeroed() };
-            assert_eq!(winerror::S_OK, unsafe {
+        let dynamic_depth_bias = {
+            let mut features16: FeatureDataOptions16 = unsafe { mem::zeroed() };
+            let hr = unsafe {
                 device.CheckFeatureSupport(
-                    d3d12::D3D12_FEATURE_ARCHITECTURE,
-                    &mut features_architecture as *mut _ as *mut _,
-                    mem::size_of::<d3d12::D3D12_FEATURE_DATA_ARCHITECTURE>() as _,
+                    D3D12_FEATURE_D3D12_OPTIONS16,
+                    &mut features16 as *mut _ as *mut _,
+                    mem::size_of::<FeatureDataOptions16>() as _,
                 )
-            });
+            };
+            hr == winerror::S_OK && features16.DynamicDepthBiasSupported != FALSE
+        };
 
-            let depth_bounds_test_supported = {
-                let mut features2: d3d12::D3D12_FEATURE_DATA_D3D12_OPTIONS2 =
-                    unsafe { mem::zeroed() };
-                let hr = unsafe {
-                    device.CheckFeatureSupport(
-                        d3d12::D3D12_FEATURE_D3D12_OPTIONS2,
-                        &mut features2 as *mut _ as *mut _,
-                        mem::size_of::<d3d12::D3D12_FEATURE_DATA_D3D12_OPTIONS2>() as _,
-                    )
-                };
-                if hr == winerror::S_OK {
-                    features2.DepthBoundsTestSupported != 0
-                } else {
-                    false
-                }
+        let root_signature_1_1 = {
+            let mut data = d3d12::D3D12_FEATURE_DATA_ROOT_SIGNATURE {
+                HighestVersion: d3d12::D3D_ROOT_SIGNATURE_VERSION_1_1,
             };
+            let hr = unsafe {
+                device.CheckFeatureSupport(
+                    d3d12::D3D12_FEATURE_ROOT_SIGNATURE,
+                    &mut data as *mut _ as *mut _,
+                    mem::size_of::<d3d12::D3D12_FEATURE_DATA_ROOT_SIGNATURE>() as _,
+                )
+            };
+            hr == winerror::S_OK
+                && data.HighestVersion >= d3d12::D3D_ROOT_SIGNATURE_VERSION_1_1
+        };
 
-            let heterogeneous_resource_heaps =
-                features.ResourceHeapTier != d3d12::D3D12_RESOURCE_HEAP_TIER_1;
+        let heterogeneous_resource_heaps =
+            features.ResourceHeapTier != d3d12::D3D12_RESOURCE_HEAP_TIER_1;
 
-            let uma = features_architecture.UMA == TRUE;
-            let cc_uma = features_architecture.CacheCoherentUMA == TRUE;
+        let uma = features_architecture.UMA == TRUE;
+        let cc_uma = features_architecture.CacheCoherentUMA == TRUE;
 
-            let (memory_architecture, heap_properties) = match (uma, cc_uma) {
-                (true, true) => (MemoryArchitecture::CacheCoherentUMA, &HEAPS_CCUMA),
-                (true, false) => (MemoryArchitecture::UMA, &HEAPS_UMA),
-                (false, _) => (MemoryArchitecture::NUMA, &HEAPS_NUMA),
-            };
+        let (memory_architecture, heap_properties) = match (uma, cc_uma) {
+            (true, true) => (MemoryArchitecture::CacheCoherentUMA, &HEAPS_CCUMA),
+            (true, false) => (MemoryArchitecture::UMA, &HEAPS_UMA),
+            (false, _) => (MemoryArchitecture::NUMA, &HEAPS_NUMA),
+        };
 
-            // https://msdn.microsoft.com/en-us/library/windows/desktop/dn788678(v=vs.85).aspx
-            let base_memory_types: [hal::MemoryType; NUM_HEAP_PROPERTIES] =
-                match memory_architecture {
-                    MemoryArchitecture::NUMA => [
-                        // DEFAULT
-                        hal::MemoryType {
-                            properties: Properties::DEVICE_LOCAL,
-                            heap_index: 0,
-                        },
-                        // UPLOAD
-                        hal::MemoryType {
-                            properties: Properties::CPU_VISIBLE | Properties::COHERENT,
-                            heap_index: 1,
-                        },
-                        // READBACK
-                        hal::MemoryType {
-                            properties: Properties::CPU_VISIBLE
-                                | Properties::COHERENT
-                                | Properties::CPU_CACHED,
-                            heap_index: 1,
-                        },
-                    ],
-                    MemoryArchitecture::UMA => [
-                        // DEFAULT
-                        hal::MemoryType {
-                            properties: Properties::DEVICE_LOCAL,
-                            heap_index: 0,
-                        },
-                        // UPLOAD
-                        hal::MemoryType {
-                            properties: Properties::DEVICE_LOCAL
-                                | Properties::CPU_VISIBLE
-                                | Properties::COHERENT,
-                            heap_index: 0,
-                        },
-                        // READBACK
-                        hal::MemoryType {
-                            properties: Properties::DEVICE_LOCAL
-                                | Properties::CPU_VISIBLE
-                                | Properties::COHERENT
-                                | Properties::CPU_CACHED,
-                            heap_index: 0,
-                        },
-                    ],
-                    MemoryArchitecture::CacheCoherentUMA => [
-                        // DEFAULT
-                        hal::MemoryType {
-                            properties: Properties::DEVICE_LOCAL,
-                            heap_index: 0,
-                        },
-                        // UPLOAD
-                        hal::MemoryType {
-                            properties: Properties::DEVICE_LOCAL
-                                | Properties::CPU_VISIBLE
-                                | Properties::COHERENT
-                                | Properties::CPU_CACHED,
-                            heap_index: 0,
-                        },
-                        // READBACK
-                        hal::MemoryType {
-                            properties: Properties::DEVICE_LOCAL
-                                | Properties::CPU_VISIBLE
-                                | Properties::COHERENT
-                                | Properties::CPU_CACHED,
-                            heap_index: 0,
-                        },
-                    ],
-                };
+        let info = hal::AdapterInfo {
+            name: device_name,
+            vendor: desc.VendorId as usize,
+            device: desc.DeviceId as usize,
+            device_type: device_type(desc.Flags, uma),
+        };
 
-            let memory_types = if heterogeneous_resource_heaps {
-                base_memory_types.to_vec()
-            } else {
-                // We multiplicate the base memory types depending on the resource usage:
-                //     0.. 3: Reserved for futures use
-                //     4.. 6: Buffers
-                //     7.. 9: Images
-                //    10..12: Targets
-                //
-                // The supported memory types for a resource can be requested by asking for
-                // the memory requirements. Memory type indices are encoded as bitflags.
-                // `device::MEM_TYPE_MASK` (0b111) defines the bitmask for one base memory type group.
-                // The corresponding shift masks (`device::MEM_TYPE_BUFFER_SHIFT`,
-                // `device::MEM_TYPE_IMAGE_SHIFT`, `device::MEM_TYPE_TARGET_SHIFT`)
-                // denote the usage group.
-                let mut types = Vec::new();
-                for i in 0 .. MemoryGroup::NumGroups as _ {
-                    types.extend(base_memory_types.iter().map(|mem_type| {
-                        let mut ty = mem_type.clone();
-
-                        // Images and Targets are not host visible as we can't create
-                        // a corresponding buffer for mapping.
-                        if i == MemoryGroup::ImageOnly as _ || i == MemoryGroup::TargetOnly as _ {
-                            ty.properties.remove(Properties::CPU_VISIBLE);
-                            // Coherent and cached can only be on memory types that are cpu visible
-                            ty.properties.remove(Properties::COHERENT);
-                            ty.properties.remove(Properties::CPU_CACHED);
-                        }
-                        ty
-                    }));
-                }
-                types
+        // https://msdn.microsoft.com/en-us/library/windows/desktop/dn788678(v=vs.85).aspx
+        let base_memory_types: [hal::MemoryType; NUM_HEAP_PROPERTIES] =
+            match memory_architecture {
+                MemoryArchitecture::NUMA => [
+                    // DEFAULT
+                    hal::MemoryType {
+                        properties: Properties::DEVICE_LOCAL,
+                        heap_index: 0,
+                    },
+                    // UPLOAD
+                    hal::MemoryType {
+                        properties: Properties::CPU_VISIBLE | Properties::COHERENT,
+                        heap_index: 1,
+                    },
+                    // READBACK
+                    hal::MemoryType {
+                        properties: Properties::CPU_VISIBLE
+                            | Properties::COHERENT
+                            | Properties::CPU_CACHED,
+                        heap_index: 1,
+                    },
+                ],
+                MemoryArchitecture::UMA => [
+                    // DEFAULT
+                    hal::MemoryType {
+                        properties: Properties::DEVICE_LOCAL,
+                        heap_index: 0,
+                    },
+                    // UPLOAD
+                    hal::MemoryType {
+                        properties: Properties::DEVICE_LOCAL
+                            | Properties::CPU_VISIBLE
+                            | Properties::COHERENT,
+                        heap_index: 0,
+                    },
+                    // READBACK
+                    hal::MemoryType {
+                        properties: Properties::DEVICE_LOCAL
+                            | Properties::CPU_VISIBLE
+                            | Properties::COHERENT
+                            | Properties::CPU_CACHED,
+                        heap_index: 0,
+                    },
+                ],
+                MemoryArchitecture::CacheCoherentUMA => [
+                    // DEFAULT
+                    hal::MemoryType {
+                        properties: Properties::DEVICE_LOCAL,
+                        heap_index: 0,
+                    },
+                    // UPLOAD
+                    hal::MemoryType {
+                        properties: Properties::DEVICE_LOCAL
+                            | Properties::CPU_VISIBLE
+                            | Properties::COHERENT
+                            | Properties::CPU_CACHED,
+                        heap_index: 0,
+                    },
+                    // READBACK
+                    hal::MemoryType {
+                        properties: Properties::DEVICE_LOCAL
+                            | Properties::CPU_VISIBLE
+                            | Properties::COHERENT
+                            | Properties::CPU_CACHED,
+                        heap_index: 0,
+                    },
+                ],
             };
 
-            let memory_heaps = {
-                // Get the IDXGIAdapter3 from the created device to query video memory information.
-                let adapter_id = unsafe { device.GetAdapterLuid() };
-                let adapter = {
-                    let mut adapter = native::WeakPtr::<dxgi1_4::IDXGIAdapter3>::null();
-                    unsafe {
-                        assert_eq!(
-                            winerror::S_OK,
-                            self.factory.EnumAdapterByLuid(
-                                adapter_id,
-                                &dxgi1_4::IDXGIAdapter3::uuidof(),
-                                adapter.mut_void(),
-                            )
-                        );
+        let memory_types = if heterogeneous_resource_heaps {
+            base_memory_types.to_vec()
+        } else {
+            // We multiplicate the base memory types depending on the resource usage:
+            //     0.. 3: Reserved for futures use
+            //     4.. 6: Buffers
+            //     7.. 9: Images
+            //    10..12: Targets
+            //
+            // The supported memory types for a resource can be requested by asking for
+            // the memory requirements. Memory type indices are encoded as bitflags.
+            // `device::MEM_TYPE_MASK` (0b111) defines the bitmask for one base memory type group.
+            // The corresponding shift masks (`device::MEM_TYPE_BUFFER_SHIFT`,
+            // `device::MEM_TYPE_IMAGE_SHIFT`, `device::MEM_TYPE_TARGET_SHIFT`)
+            // denote the usage group.
+            let mut types = Vec::new();
+            for i in 0 .. MemoryGroup::NumGroups as _ {
+                types.extend(base_memory_types.iter().map(|mem_type| {
+                    let mut ty = mem_type.clone();
+
+                    // Images and Targets are not host visible as we can't create
+                    // a corresponding buffer for mapping.
+                    if i == MemoryGroup::ImageOnly as _ || i == MemoryGroup::TargetOnly as _ {
+                        ty.properties.remove(Properties::CPU_VISIBLE);
+                        // Coherent and cached can only be on memory types that are cpu visible
+                        ty.properties.remove(Properties::COHERENT);
+                        ty.properties.remove(Properties::CPU_CACHED);
                     }
-                    adapter
-                };
+                    ty
+                }));
+            }
+            types
+        };
 
-                let query_memory = |segment: dxgi1_4::DXGI_MEMORY_SEGMENT_GROUP| unsafe {
-                    let mut mem_info: dxgi1_4::DXGI_QUERY_VIDEO_MEMORY_INFO = mem::uninitialized();
-                    assert_eq!(
-                        winerror::S_OK,
-                        adapter.QueryVideoMemoryInfo(0, segment, &mut mem_info,)
-                    );
-                    mem_info.Budget
-                };
+        // Get the IDXGIAdapter3 from the created device to query video memory information.
+        let adapter3 = {
+            let adapter_id = unsafe { device.GetAdapterLuid() };
+            let mut adapter = native::WeakPtr::<dxgi1_4::IDXGIAdapter3>::null();
+            unsafe {
+                assert_eq!(
+                    winerror::S_OK,
+                    self.factory.EnumAdapterByLuid(
+                        adapter_id,
+                        &dxgi1_4::IDXGIAdapter3::uuidof(),
+                        adapter.mut_void(),
+                    )
+                );
+            }
+            adapter
+        };
 
-                let local = query_memory(dxgi1_4::DXGI_MEMORY_SEGMENT_GROUP_LOCAL);
-                match memory_architecture {
-                    MemoryArchitecture::NUMA => {
-                        let non_local = query_memory(dxgi1_4::DXGI_MEMORY_SEGMENT_GROUP_NON_LOCAL);
-                        vec![local, non_local]
-                    }
-                    _ => vec![local],
+        let memory_heaps = {
+            let local = query_video_memory(adapter3, MemorySegment::Local).budget;
+            match memory_architecture {
+                MemoryArchitecture::NUMA => {
+                    let non_local = query_video_memory(adapter3, MemorySegment::NonLocal).budget;
+                    vec![local, non_local]
                 }
-            };
+                _ => vec![local],
+            }
+        };
 
-            let physical_device = PhysicalDevice {
-                adapter,
-                features:
-                    // TODO: add more features, based on
-                    // https://msdn.microsoft.com/de-de/library/windows/desktop/mt186615(v=vs.85).aspx
-                    Features::ROBUST_BUFFER_ACCESS |
-                    Features::IMAGE_CUBE_ARRAY |
-                    Features::GEOMETRY_SHADER |
-                    Features::TESSELLATION_SHADER |
-                    Features::NON_FILL_POLYGON_MODE |
-                    if depth_bounds_test_supported { Features::DEPTH_BOUNDS } else { Features::empty() } |
-                    //logic_op: false, // Optional on feature level 11_0
-                    Features::MULTI_DRAW_INDIRECT |
-                    Features::FORMAT_BC |
-                    Features::INSTANCE_RATE |
-                    Features::SAMPLER_MIP_LOD_BIAS |
-                    Features::SAMPLER_ANISOTROPY,
-                limits: Limits { // TODO
-                    max_image_1d_size: d3d12::D3D12_REQ_TEXTURE1D_U_DIMENSION as _,
-                    max_image_2d_size: d3d12::D3D12_REQ_TEXTURE2D_U_OR_V_DIMENSION as _,
-                    max_image_3d_size: d3d12::D3D12_REQ_TEXTURE3D_U_V_OR_W_DIMENSION as _,
-                    max_image_cube_size: d3d12::D3D12_REQ_TEXTURECUBE_DIMENSION as _,
-                    max_image_array_layers: d3d12::D3D12_REQ_TEXTURE2D_ARRAY_AXIS_DIMENSION as _,
-                    max_texel_elements: 0,
-                    max_patch_size: 0,
-                    max_viewports: d3d12::D3D12_VIEWPORT_AND_SCISSORRECT_OBJECT_COUNT_PER_PIPELINE as _,
-                    max_viewport_dimensions: [d3d12::D3D12_VIEWPORT_BOUNDS_MAX as _; 2],
-                    max_framebuffer_extent: hal::image::Extent { //TODO
-                        width: 4096,
-                        height: 4096,
-                        depth: 1,
-                    },
-                    max_compute_work_group_count: [
-                        d3d12::D3D12_CS_THREAD_GROUP_MAX_X,
-                        d3d12::D3D12_CS_THREAD_GROUP_MAX_Y,
-                        d3d12::D3D12_CS_THREAD_GROUP_MAX_Z,
-                    ],
-                    max_compute_work_group_size: [
-                        d3d12::D3D12_CS_THREAD_GROUP_MAX_THREADS_PER_GROUP,
-                        1, //TODO
-                        1, //TODO
-                    ],
-                    max_vertex_input_attributes: d3d12::D3D12_IA_VERTEX_INPUT_RESOURCE_SLOT_COUNT as _,
-                    max_vertex_input_bindings: 31, //TODO
-                    max_vertex_input_attribute_offset: 255, // TODO
-                    max_vertex_input_binding_stride: d3d12::D3D12_REQ_MULTI_ELEMENT_STRUCTURE_SIZE_IN_BYTES as _,
-                    max_vertex_output_components: 16, // TODO
-                    min_texel_buffer_offset_alignment: 1, // TODO
-                    min_uniform_buffer_offset_alignment: 256, // Required alignment for CBVs
-                    min_storage_buffer_offset_alignment: 1, // TODO
-                    // TODO: query supported sample count for all framebuffer formats and increase the limit
-                    //       if possible.
-                    framebuffer_color_sample_counts: 0b101,
-                    framebuffer_depth_sample_counts: 0b101,
-                    framebuffer_stencil_sample_counts: 0b101,
-                    max_color_attachments: d3d12::D3D12_SIMULTANEOUS_RENDER_TARGET_COUNT as _,
-                    buffer_image_granularity: 1,
-                    non_coherent_atom_size: 1, //TODO: confirm
-                    max_sampler_anisotropy: 16.,
-                    optimal_buffer_copy_offset_alignment: d3d12::D3D12_TEXTURE_DATA_PLACEMENT_ALIGNMENT as _,
-                    optimal_buffer_copy_pitch_alignment: d3d12::D3D12_TEXTURE_DATA_PITCH_ALIGNMENT as _,
-                    min_vertex_input_binding_stride_alignment: 1,
-                    .. Limits::default() //TODO
-                },
-                format_properties: Arc::new(FormatProperties::new(device)),
-                private_caps: Capabilities {
-                    heterogeneous_resource_heaps,
-                    memory_architecture,
-                },
-                heap_properties,
-                memory_properties: hal::MemoryProperties {
-                    memory_types,
-                    memory_heaps,
-                },
-                is_open: Arc::new(Mutex::new(false)),
-            };
+        let physical_device = PhysicalDevice {
+            adapter,
+            adapter3,
+            features:
+                // TODO: add more features, based on
+                // https://msdn.microsoft.com/de-de/library/windows/desktop/mt186615(v=vs.85).aspx
+                // `ROBUST_BUFFER_ACCESS` isn't advertised, the HLSL generated by spirv-cross
+                // doesn't guarantee bounds checked accesses for all buffer types.
+                Features::IMAGE_CUBE_ARRAY |
+                Features::GEOMETRY_SHADER |
+                Features::TESSELLATION_SHADER |
+                Features::NON_FILL_POLYGON_MODE |
+                // `DepthClipEnable` can always be toggled.
+                Features::DEPTH_CLAMP |
+                // Viewports can be selected from geometry shaders with `SV_ViewportArrayIndex`.
+                Features::MULTI_VIEWPORTS |
+                if depth_bounds_test_supported { Features::DEPTH_BOUNDS } else { Features::empty() } |
+                if features.OutputMergerLogicOp != 0 { Features::LOGIC_OP } else { Features::empty() } |
+                Features::MULTI_DRAW_INDIRECT |
+                // `ExecuteIndirect` always accepts a count buffer.
+                Features::DRAW_INDIRECT_COUNT |
+                Features::FORMAT_BC |
+                Features::INSTANCE_RATE |
+                Features::SAMPLER_MIP_LOD_BIAS |
+                Features::SAMPLER_ANISOTROPY |
+                if features.ConservativeRasterizationTier != d3d12::D3D12_CONSERVATIVE_RASTERIZATION_TIER_NOT_SUPPORTED {
+                    Features::CONSERVATIVE_RASTERIZATION
+                } else {
+                    Features::empty()
+                } |
+                tiled_resources_features(features.TiledResourcesTier) |
+                options3_features,
+            limits: device_limits(),
+            format_properties: Arc::new(FormatProperties::new(
+                device,
+                features.TypedUAVLoadAdditionalFormats == TRUE,
+            )),
+            private_caps: Capabilities {
+                heterogeneous_resource_heaps,
+                memory_architecture,
+                cross_adapter_row_major_texture: features.CrossAdapterRowMajorTextureSupported
+                    == TRUE,
+                feature_level,
+                dynamic_depth_bias,
+                bindless: features.ResourceBindingTier >= d3d12::D3D12_RESOURCE_BINDING_TIER_3,
+                root_signature_1_1,
+            },
+            heap_properties,
+            memory_properties: hal::MemoryProperties {
+                memory_types,
+                memory_heaps,
+            },
+            luid: luid_bytes(&desc.AdapterLuid),
+            driver_version,
+            open_count: Arc::new(Mutex::new(0)),
+            max_queue_priority: QueuePriorityClass::Normal,
+        };
+
+        let queue_families = QUEUE_FAMILIES.to_vec();
+
+        Some(hal::Adapter {
+            info,
+            physical_device,
+            queue_families,
+        })
+    }
+}
 
-            let queue_families = QUEUE_FAMILIES.to_vec();
+// Enable GPU-based validation, requires `ID3D12Debug1` which isn't available on older runtimes.
+#[cfg(debug_assertions)]
+unsafe fn enable_gpu_based_validation(debug_controller: *mut d3d12sdklayers::ID3D12Debug) {
+    let mut debug1: *mut d3d12sdklayers::ID3D12Debug1 = ptr::null_mut();
+    let hr = (*debug_controller).QueryInterface(
+        &d3d12sdklayers::ID3D12Debug1::uuidof(),
+        &mut debug1 as *mut *mut _ as *mut *mut _,
+    );
+    if winerror::SUCCEEDED(hr) {
+        (*debug1).SetEnableGPUBasedValidation(TRUE);
+        (*debug1).Release();
+        warn!("GPU-based validation enabled, expect a significant performance cost");
+    } else {
+        warn!("GPU-based validation isn't supported by the D3D12 runtime");
+    }
+}
 
-            adapters.push(hal::Adapter {
-                info,
-                physical_device,
-                queue_families,
-            });
+/// Preferred order of enumerated adapters.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum GpuPreference {
+    /// Prefer adapters with low power consumption, e.g. integrated GPUs.
+    MinimumPower,
+    /// Prefer adapters with high performance, e.g. discrete GPUs.
+    HighPerformance,
+}
+
+impl GpuPreference {
+    // Value of `DXGI_GPU_PREFERENCE`.
+    fn to_dxgi(&self) -> UINT {
+        match *self {
+            GpuPreference::MinimumPower => 1,
+            GpuPreference::HighPerformance => 2,
         }
-        adapters
+    }
+}
+
+impl hal::Instance for Instance {
+    type Backend = Backend;
+
+    fn enumerate_adapters(&self) -> Vec<hal::Adapter<Backend>> {
+        self.enumerate_adapters_with_preference(GpuPreference::HighPerformance)
     }
 }
 
@@ -1155,26 +1819,155 @@ impl hal::Backend for Backend {
     type ComputePipeline = resource::ComputePipeline;
     type GraphicsPipeline = resource::GraphicsPipeline;
     type PipelineLayout = resource::PipelineLayout;
-    type PipelineCache = ();
+    type PipelineCache = resource::PipelineCache;
     type DescriptorSetLayout = resource::DescriptorSetLayout;
     type DescriptorPool = resource::DescriptorPool;
     type DescriptorSet = resource::DescriptorSet;
 
     type Fence = resource::Fence;
     type Semaphore = resource::Semaphore;
-    type Event = ();
+    type Event = resource::Event;
     type QueryPool = resource::QueryPool;
 }
 
+/// Limits reported for every D3D12 adapter, taken from the
+// Sparse features supported by a tiled resources tier.
+//
+// Only 2D images are exposed as sparse images, 3D tiled resources require tier 3.
+// Min/max sampler reduction is part of tier 2.
+fn tiled_resources_features(tier: d3d12::D3D12_TILED_RESOURCES_TIER) -> Features {
+    let mut features = Features::empty();
+    if tier >= d3d12::D3D12_TILED_RESOURCES_TIER_1 {
+        features |= Features::SPARSE_BINDING | Features::SPARSE_RESIDENCY_IMAGE_2D;
+    }
+    if tier >= d3d12::D3D12_TILED_RESOURCES_TIER_2 {
+        features |= Features::SAMPLER_REDUCTION;
+    }
+    features
+}
+
+/// `D3D12_REQ_*` requirements of feature level 11_0.
+fn device_limits() -> Limits {
+    Limits { // TODO
+        max_image_1d_size: d3d12::D3D12_REQ_TEXTURE1D_U_DIMENSION as _,
+        max_image_2d_size: d3d12::D3D12_REQ_TEXTURE2D_U_OR_V_DIMENSION as _,
+        max_image_3d_size: d3d12::D3D12_REQ_TEXTURE3D_U_V_OR_W_DIMENSION as _,
+        max_image_cube_size: d3d12::D3D12_REQ_TEXTURECUBE_DIMENSION as _,
+        max_image_array_layers: d3d12::D3D12_REQ_TEXTURE2D_ARRAY_AXIS_DIMENSION as _,
+        max_texel_elements: 1 << d3d12::D3D12_REQ_BUFFER_RESOURCE_TEXEL_COUNT_2_TO_THE_POWER_OF,
+        max_patch_size: d3d12::D3D12_IA_PATCH_MAX_CONTROL_POINT_COUNT as _,
+        max_viewports: d3d12::D3D12_VIEWPORT_AND_SCISSORRECT_OBJECT_COUNT_PER_PIPELINE as _,
+        max_viewport_dimensions: [d3d12::D3D12_VIEWPORT_BOUNDS_MAX as _; 2],
+        max_framebuffer_extent: hal::image::Extent {
+            width: d3d12::D3D12_REQ_TEXTURE2D_U_OR_V_DIMENSION,
+            height: d3d12::D3D12_REQ_TEXTURE2D_U_OR_V_DIMENSION,
+            depth: 1,
+        },
+        max_compute_work_group_count: [
+            d3d12::D3D12_CS_THREAD_GROUP_MAX_X,
+            d3d12::D3D12_CS_THREAD_GROUP_MAX_Y,
+            d3d12::D3D12_CS_THREAD_GROUP_MAX_Z,
+        ],
+        max_compute_work_group_size: [
+            d3d12::D3D12_CS_THREAD_GROUP_MAX_THREADS_PER_GROUP,
+            1, //TODO
+            1, //TODO
+        ],
+        // Attributes may be rebased onto a binding of their own, see `create_graphics_pipeline`.
+        max_vertex_input_attributes: MAX_VERTEX_BUFFERS as _,
+        max_vertex_input_bindings: MAX_VERTEX_BUFFERS as _,
+        max_vertex_input_attribute_offset: 255, // TODO
+        max_vertex_input_binding_stride: d3d12::D3D12_REQ_MULTI_ELEMENT_STRUCTURE_SIZE_IN_BYTES as _,
+        max_vertex_output_components: 16, // TODO
+        min_texel_buffer_offset_alignment: 1, // TODO
+        min_uniform_buffer_offset_alignment: 256, // Required alignment for CBVs
+        min_storage_buffer_offset_alignment: 1, // TODO
+        // TODO: query supported sample count for all framebuffer formats and increase the limit
+        //       if possible.
+        framebuffer_color_sample_counts: 0b101,
+        framebuffer_depth_sample_counts: 0b101,
+        framebuffer_stencil_sample_counts: 0b101,
+        max_color_attachments: d3d12::D3D12_SIMULTANEOUS_RENDER_TARGET_COUNT as _,
+        buffer_image_granularity: 1,
+        non_coherent_atom_size: NON_COHERENT_ATOM_SIZE as _,
+        max_sampler_anisotropy: 16.,
+        optimal_buffer_copy_offset_alignment: d3d12::D3D12_TEXTURE_DATA_PLACEMENT_ALIGNMENT as _,
+        optimal_buffer_copy_pitch_alignment: d3d12::D3D12_TEXTURE_DATA_PITCH_ALIGNMENT as _,
+        min_buffer_copy_offset_alignment: MIN_BUFFER_COPY_OFFSET_ALIGNMENT,
+        min_buffer_copy_pitch_alignment: MIN_BUFFER_COPY_PITCH_ALIGNMENT,
+        min_vertex_input_binding_stride_alignment: 1,
+        .. Limits::default() //TODO
+    }
+}
+
+fn timestamp_period(frequency: u64) -> f32 {
+    if frequency == 0 {
+        0.0
+    } else {
+        (1_000_000_000.0 / frequency as f64) as f32
+    }
+}
+
+// `D3D12_FEATURE_DATA_D3D12_OPTIONS3`, not exposed by winapi yet.
+const D3D12_FEATURE_D3D12_OPTIONS3: d3d12::D3D12_FEATURE = 21;
+
+#[repr(C)]
+#[allow(non_snake_case)]
+#[derive(Debug, Default)]
+struct FeatureDataOptions3 {
+    CopyQueueTimestampQueriesSupported: BOOL,
+    CastingFullyTypedFormatSupported: BOOL,
+    WriteBufferImmediateSupportFlags: UINT,
+    ViewInstancingTier: UINT,
+    BarycentricsSupported: BOOL,
+}
+
+impl FeatureDataOptions3 {
+    fn features(&self) -> Features {
+        let mut features = Features::empty();
+        if self.BarycentricsSupported != FALSE {
+            features |= Features::SHADER_BARYCENTRICS;
+        }
+        // Tier 0 (`D3D12_VIEW_INSTANCING_TIER_NOT_SUPPORTED`) lacks support.
+        if self.ViewInstancingTier != 0 {
+            features |= Features::VIEW_INSTANCING;
+        }
+        features
+    }
+}
+
+// `D3D12_FEATURE_DATA_D3D12_OPTIONS16`, not exposed by winapi yet.
+const D3D12_FEATURE_D3D12_OPTIONS16: d3d12::D3D12_FEATURE = 45;
+
+#[repr(C)]
+#[allow(non_snake_case)]
+#[derive(Debug, Default)]
+struct FeatureDataOptions16 {
+    DynamicDepthBiasSupported: BOOL,
+    GPUUploadHeapSupported: BOOL,
+}
+
 fn validate_line_width(width: f32) {
     // Note from the Vulkan spec:
     // > If the wide lines feature is not enabled, lineWidth must be 1.0
-    // Simply assert and no-op because DX12 never exposes `Features::LINE_WIDTH`
-    assert_eq!(width, 1.0);
+    // DX12 never exposes `Features::LINE_WIDTH` and always rasterizes lines with a
+    // width of 1.0. Portable applications may still request wider lines, so only warn.
+    if width != 1.0 {
+        warn!("Line width {} is not supported, lines are drawn with a width of 1.0", width);
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct FormatInfo {
+    properties: f::Properties,
+    sample_count_mask: u8,
+    can_resolve: bool,
 }
 
+// Lazily queried format infos, the device used to query them and whether typed UAV loads
+// are supported for formats beyond R32_FLOAT, R32_UINT and R32_SINT.
 #[derive(Debug)]
-pub struct FormatProperties(Box<[Mutex<Option<f::Properties>>]>, native::Device);
+pub struct FormatProperties(Box<[Mutex<Option<FormatInfo>>]>, native::Device, bool);
 
 impl Drop for FormatProperties {
     fn drop(&mut self) {
@@ -1185,30 +1978,49 @@ impl Drop for FormatProperties {
 }
 
 impl FormatProperties {
-    fn new(device: native::Device) -> Self {
+    fn new(device: native::Device, typed_uav_load_additional_formats: bool) -> Self {
         let mut buf = Vec::with_capacity(f::NUM_FORMATS);
-        buf.push(Mutex::new(Some(f::Properties::default())));
+        buf.push(Mutex::new(Some(FormatInfo::default())));
         for _ in 1 .. f::NUM_FORMATS {
             buf.push(Mutex::new(None))
         }
-        FormatProperties(buf.into_boxed_slice(), device)
+        FormatProperties(
+            buf.into_boxed_slice(),
+            device,
+            typed_uav_load_additional_formats,
+        )
     }
 
     fn get(&self, idx: usize) -> f::Properties {
+        self.get_info(idx).properties
+    }
+
+    fn get_sample_count_mask(&self, idx: usize) -> u8 {
+        self.get_info(idx).sample_count_mask
+    }
+
+    fn supports_resolve(&self, idx: usize) -> bool {
+        self.get_info(idx).can_resolve
+    }
+
+    fn get_info(&self, idx: usize) -> FormatInfo {
         let mut guard = self.0[idx].lock().unwrap();
-        if let Some(props) = *guard {
-            return props;
+        if let Some(info) = *guard {
+            return info;
         }
-        let mut props = f::Properties::default();
+        let info = self.query_info(idx);
+        *guard = Some(info);
+        info
+    }
+
+    fn query_info(&self, idx: usize) -> FormatInfo {
         let format: f::Format = unsafe { mem::transmute(idx as u32) };
+        let dxgi_format = match conv::map_format(format) {
+            Some(format) => format,
+            None => return FormatInfo::default(),
+        };
         let mut data = d3d12::D3D12_FEATURE_DATA_FORMAT_SUPPORT {
-            Format: match conv::map_format(format) {
-                Some(format) => format,
-                None => {
-                    *guard = Some(props);
-                    return props;
-                }
-            },
+            Format: dxgi_format,
             Support1: unsafe { mem::zeroed() },
             Support2: unsafe { mem::zeroed() },
         };
@@ -1219,64 +2031,1221 @@ impl FormatProperties {
                 mem::size_of::<d3d12::D3D12_FEATURE_DATA_FORMAT_SUPPORT>() as _,
             )
         });
-        let can_buffer = 0 != data.Support1 & d3d12::D3D12_FORMAT_SUPPORT1_BUFFER;
-        let can_image = 0
-            != data.Support1
-                & (d3d12::D3D12_FORMAT_SUPPORT1_TEXTURE1D
-                    | d3d12::D3D12_FORMAT_SUPPORT1_TEXTURE2D
-                    | d3d12::D3D12_FORMAT_SUPPORT1_TEXTURE3D
-                    | d3d12::D3D12_FORMAT_SUPPORT1_TEXTURECUBE);
-        let can_linear = can_image && !format.surface_desc().is_compressed();
-        if can_image {
-            props.optimal_tiling |= f::ImageFeature::SAMPLED | f::ImageFeature::BLIT_SRC;
+        let props = format_properties(format, data.Support1, data.Support2, self.2);
+        let can_resolve = data.Support1 & d3d12::D3D12_FORMAT_SUPPORT1_MULTISAMPLE_RESOLVE != 0;
+
+        let sample_count_mask = sample_count_mask(|count| {
+            let mut data = d3d12::D3D12_FEATURE_DATA_MULTISAMPLE_QUALITY_LEVELS {
+                Format: dxgi_format,
+                SampleCount: count,
+                Flags: d3d12::D3D12_MULTISAMPLE_QUALITY_LEVELS_FLAG_NONE,
+                NumQualityLevels: 0,
+            };
+            let hr = unsafe {
+                self.1.CheckFeatureSupport(
+                    d3d12::D3D12_FEATURE_MULTISAMPLE_QUALITY_LEVELS,
+                    &mut data as *mut _ as *mut _,
+                    mem::size_of::<d3d12::D3D12_FEATURE_DATA_MULTISAMPLE_QUALITY_LEVELS>() as _,
+                )
+            };
+            if winerror::SUCCEEDED(hr) {
+                data.NumQualityLevels
+            } else {
+                0
+            }
+        });
+
+        FormatInfo {
+            properties: props,
+            sample_count_mask,
+            can_resolve,
         }
+    }
+}
+
+/// Derive the format properties from the D3D12 format support flags.
+///
+/// Typed UAV loads are always supported for R32_FLOAT, R32_UINT and R32_SINT, other formats
+/// require `TypedUAVLoadAdditionalFormats`.
+fn format_properties(
+    format: f::Format,
+    support1: d3d12::D3D12_FORMAT_SUPPORT1,
+    support2: d3d12::D3D12_FORMAT_SUPPORT2,
+    typed_uav_load_additional_formats: bool,
+) -> f::Properties {
+    let mut props = f::Properties::default();
+    let can_buffer = 0 != support1 & d3d12::D3D12_FORMAT_SUPPORT1_BUFFER;
+    let can_image = 0
+        != support1
+            & (d3d12::D3D12_FORMAT_SUPPORT1_TEXTURE1D
+                | d3d12::D3D12_FORMAT_SUPPORT1_TEXTURE2D
+                | d3d12::D3D12_FORMAT_SUPPORT1_TEXTURE3D
+                | d3d12::D3D12_FORMAT_SUPPORT1_TEXTURECUBE);
+    // Row-major textures can't be compressed or planar.
+    let can_linear =
+        can_image && !format.surface_desc().is_compressed() && !format.is_planar();
+    if can_image {
+        props.optimal_tiling |= f::ImageFeature::SAMPLED | f::ImageFeature::BLIT_SRC;
+    }
+    if can_linear {
+        props.linear_tiling |= f::ImageFeature::SAMPLED | f::ImageFeature::BLIT_SRC;
+    }
+    if support1 & d3d12::D3D12_FORMAT_SUPPORT1_IA_VERTEX_BUFFER != 0 {
+        props.buffer_features |= f::BufferFeature::VERTEX;
+    }
+    if support1 & d3d12::D3D12_FORMAT_SUPPORT1_SHADER_SAMPLE != 0 {
+        props.optimal_tiling |= f::ImageFeature::SAMPLED_LINEAR;
+    }
+    if support1 & d3d12::D3D12_FORMAT_SUPPORT1_RENDER_TARGET != 0 {
+        props.optimal_tiling |= f::ImageFeature::COLOR_ATTACHMENT | f::ImageFeature::BLIT_DST;
         if can_linear {
-            props.linear_tiling |= f::ImageFeature::SAMPLED | f::ImageFeature::BLIT_SRC;
+            props.linear_tiling |= f::ImageFeature::COLOR_ATTACHMENT | f::ImageFeature::BLIT_DST;
+        }
+    }
+    if support1 & d3d12::D3D12_FORMAT_SUPPORT1_BLENDABLE != 0 {
+        props.optimal_tiling |= f::ImageFeature::COLOR_ATTACHMENT_BLEND;
+    }
+    if support1 & d3d12::D3D12_FORMAT_SUPPORT1_DEPTH_STENCIL != 0 {
+        // Row-major textures can't be used as depth-stencil attachments.
+        props.optimal_tiling |= f::ImageFeature::DEPTH_STENCIL_ATTACHMENT;
+    }
+    if support1 & d3d12::D3D12_FORMAT_SUPPORT1_SHADER_LOAD != 0 {
+        if can_buffer {
+            props.buffer_features |= f::BufferFeature::UNIFORM_TEXEL;
+        }
+    }
+    if support2 & d3d12::D3D12_FORMAT_SUPPORT2_UAV_ATOMIC_ADD != 0 {
+        //TODO: other atomic flags?
+        if can_buffer {
+            props.buffer_features |= f::BufferFeature::STORAGE_TEXEL_ATOMIC;
+        }
+        if can_image {
+            props.optimal_tiling |= f::ImageFeature::STORAGE_ATOMIC;
+        }
+    }
+    if support2 & d3d12::D3D12_FORMAT_SUPPORT2_UAV_TYPED_STORE != 0 {
+        if can_buffer {
+            props.buffer_features |= f::BufferFeature::STORAGE_TEXEL;
+        }
+        if can_image {
+            props.optimal_tiling |= f::ImageFeature::STORAGE;
+        }
+    }
+    let typed_load = match format {
+        f::Format::R32Sfloat | f::Format::R32Uint | f::Format::R32Sint => true,
+        _ => {
+            typed_uav_load_additional_formats
+                && support2 & d3d12::D3D12_FORMAT_SUPPORT2_UAV_TYPED_LOAD != 0
+        }
+    };
+    if typed_load && props.optimal_tiling.contains(f::ImageFeature::STORAGE) {
+        props.optimal_tiling |= f::ImageFeature::STORAGE_READ;
+    }
+    props
+}
+
+/// Build a `sample_count_mask` from the number of quality levels reported
+/// for each of the sample counts 1, 2, 4, 8 and 16.
+fn sample_count_mask<F>(mut quality_levels: F) -> u8
+where
+    F: FnMut(u32) -> u32,
+{
+    (0 .. 5).fold(0, |mask, bit| {
+        if quality_levels(1 << bit) != 0 {
+            mask | (1 << bit)
+        } else {
+            mask
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        conv,
+        device_limits,
+        device_type,
+        format_properties,
+        luid_bytes,
+        sample_count_mask,
+        submission_order,
+        tiled_resources_features,
+        timestamp_period,
+        validate_line_width,
+        ColorSpace,
+        FeatureDataOptions3,
+        GpuPreference,
+        FEATURE_LEVELS,
+        QUEUE_FAMILIES,
+    };
+    use hal::format::{Aspects, Format, ImageFeature, SurfaceType};
+    use hal::{pso, Features, PresentMode, QueueFamily};
+    use window::present_modes;
+    use winapi::shared::minwindef::{FALSE, TRUE};
+    use winapi::um::d3d12::*;
+    use winapi::um::winnt::LUID;
+    use native::queue::Priority;
+
+    #[test]
+    fn test_queue_family_ids() {
+        for (i, family) in QUEUE_FAMILIES.iter().enumerate() {
+            assert_eq!(family.id().0, i);
+        }
+    }
+
+    #[test]
+    fn test_queue_priorities() {
+        use super::QueuePriorityClass::*;
+
+        let priority = |priority, max| conv::map_queue_priority(priority, max) as u32;
+        // Default priority of `Adapter::open_with`.
+        assert_eq!(priority(1.0, Normal), Priority::Normal as u32);
+        assert_eq!(priority(0.5, High), Priority::Normal as u32);
+        assert_eq!(priority(0.75, High), Priority::High as u32);
+        assert_eq!(priority(1.0, High), Priority::High as u32);
+        assert_eq!(priority(0.0, Realtime), Priority::Normal as u32);
+        assert_eq!(priority(0.75, Realtime), Priority::High as u32);
+        assert_eq!(priority(1.0, Realtime), Priority::GlobalRealtime as u32);
+    }
+
+    #[test]
+    fn test_sample_count_mask() {
+        // `Rgba8Srgb` is required to support 1, 2, 4 and 8 samples as a render target,
+        // 16 samples is optional.
+        assert_eq!(sample_count_mask(|count| (count <= 8) as u32), 0xF);
+        assert_eq!(sample_count_mask(|_| 1), 0x1F);
+        assert_eq!(sample_count_mask(|count| (count == 1) as u32), 0x1);
+        assert_eq!(sample_count_mask(|_| 0), 0x0);
+    }
+
+    #[test]
+    #[cfg(feature = "warp")]
+    fn test_warp_sample_count_mask() {
+        use hal::{image, PhysicalDevice};
+        use test_utils::warp_adapter;
+
+        let instance = super::Instance::create("test_warp_sample_count_mask", 1);
+        let adapter = warp_adapter(&instance);
+        let physical_device = &adapter.physical_device;
+        let mask = physical_device
+            .format_properties
+            .get_sample_count_mask(Format::Rgba8Srgb as usize);
+        assert_eq!(mask & 0xF, 0xF);
+        assert_eq!(mask & !0x1F, 0);
+
+        let properties = |usage| {
+            physical_device
+                .image_format_properties(
+                    Format::Rgba8Srgb,
+                    2,
+                    image::Tiling::Optimal,
+                    usage,
+                    image::ViewCapabilities::empty(),
+                )
+                .unwrap()
+        };
+        assert_eq!(
+            properties(image::Usage::COLOR_ATTACHMENT).sample_count_mask,
+            mask
+        );
+        assert_eq!(properties(image::Usage::SAMPLED).sample_count_mask, 0x1);
+    }
+
+    #[test]
+    fn test_device_limits() {
+        let limits = device_limits();
+        assert_eq!(
+            limits.max_texel_elements,
+            1usize << D3D12_REQ_BUFFER_RESOURCE_TEXEL_COUNT_2_TO_THE_POWER_OF
+        );
+        assert_eq!(
+            limits.max_patch_size,
+            D3D12_IA_PATCH_MAX_CONTROL_POINT_COUNT as hal::PatchSize
+        );
+        assert_ne!(limits.max_texel_elements, 0);
+        assert_ne!(limits.max_patch_size, 0);
+        // Required copy alignments are looser than the optimal ones.
+        assert_eq!(
+            limits.optimal_buffer_copy_offset_alignment % limits.min_buffer_copy_offset_alignment,
+            0
+        );
+        assert_eq!(
+            limits.optimal_buffer_copy_pitch_alignment % limits.min_buffer_copy_pitch_alignment,
+            0
+        );
+    }
+
+    #[test]
+    fn test_max_framebuffer_extent() {
+        let extent = device_limits().max_framebuffer_extent;
+        assert_eq!(extent.width, D3D12_REQ_TEXTURE2D_U_OR_V_DIMENSION);
+        assert_eq!(extent.height, D3D12_REQ_TEXTURE2D_U_OR_V_DIMENSION);
+        assert_eq!(extent.depth, 1);
+    }
+
+    #[test]
+    fn test_present_modes() {
+        assert_eq!(present_modes(false), vec![PresentMode::Fifo]);
+        let modes = present_modes(true);
+        assert!(modes.contains(&PresentMode::Fifo));
+        assert!(modes.contains(&PresentMode::Immediate));
+    }
+
+    #[test]
+    fn test_color_space_formats() {
+        assert!(ColorSpace::Srgb.supports_format(Format::Bgra8Srgb));
+        assert!(ColorSpace::Hdr10.supports_format(Format::A2b10g10r10Unorm));
+        assert!(!ColorSpace::Hdr10.supports_format(Format::Rgba8Unorm));
+        assert!(ColorSpace::ScRgb.supports_format(Format::Rgba16Sfloat));
+        assert!(!ColorSpace::ScRgb.supports_format(Format::A2b10g10r10Unorm));
+    }
+
+    #[test]
+    fn test_submission_order() {
+        // copy -> compute -> graphics, submitted in reverse order.
+        let (copy_done, compute_done) = (1, 2);
+        let waits = vec![vec![compute_done], vec![copy_done], vec![]];
+        let signals = vec![vec![], vec![compute_done], vec![copy_done]];
+        assert_eq!(submission_order(&waits, &signals), Some(vec![2, 1, 0]));
+
+        // External semaphores don't constrain the order.
+        let waits = vec![vec![3], vec![]];
+        let signals = vec![vec![], vec![]];
+        assert_eq!(submission_order(&waits, &signals), Some(vec![0, 1]));
+
+        let waits = vec![vec![1], vec![2]];
+        let signals = vec![vec![2], vec![1]];
+        assert_eq!(submission_order(&waits, &signals), None);
+    }
+
+    #[test]
+    fn test_typeless_families() {
+        let family = |a: Format, b: Format| {
+            conv::is_same_typeless_family(a.base_format().0, b.base_format().0)
+        };
+        assert!(family(Format::Rgba8Unorm, Format::Rgba8Uint));
+        assert!(family(Format::Rgba8Srgb, Format::Rgba8Sint));
+        assert!(family(Format::R32Float, Format::R32Uint));
+        assert!(family(Format::Bc1RgbaUnorm, Format::Bc1RgbaSrgb));
+        assert!(!family(Format::Bc1RgbaUnorm, Format::Bc3Unorm));
+        assert!(!family(Format::R32Uint, Format::Rgba8Unorm));
+        assert!(!family(Format::Rgba8Unorm, Format::Bgra8Unorm));
+    }
+
+    #[test]
+    fn test_timestamp_period() {
+        assert_eq!(timestamp_period(0), 0.0);
+        assert_eq!(timestamp_period(1_000_000_000), 1.0);
+        // Common frequency of desktop GPUs
+        let period = timestamp_period(10_000_000);
+        assert!((period - 100.0).abs() < 1e-3);
+    }
+
+    #[test]
+    #[cfg(feature = "warp")]
+    fn test_warp_timestamp_period() {
+        use test_utils::Warp;
+
+        let (_warp, queue_group) = Warp::open("test_warp_timestamp_period");
+        let queue = queue_group.queues[0].as_raw();
+        let period = queue.timestamp_period();
+        assert_eq!(period, timestamp_period(queue.timestamp_frequency()));
+        // Counters tick between 1 kHz and 10 GHz.
+        assert!(period >= 0.1 && period <= 1_000_000.0, "period {}", period);
+    }
+
+    #[test]
+    #[cfg(feature = "warp")]
+    fn test_warp_reopen_adapter() {
+        use hal::{buffer, Device, PhysicalDevice};
+        use test_utils::warp_adapter;
+
+        let instance = super::Instance::create("test_warp_reopen_adapter", 1);
+        let adapter = warp_adapter(&instance);
+        let family = &adapter.queue_families[0];
+        let open = || unsafe {
+            adapter
+                .physical_device
+                .open(&[(family, &[1.0])], Features::empty())
+                .unwrap()
+        };
+        let open_count = || *adapter.physical_device.open_count.lock().unwrap();
+
+        let first = open();
+        let second = open();
+        assert_eq!(open_count(), 2);
+        // Both logical devices share the raw device of the adapter.
+        assert_eq!(first.device.raw.as_mut_ptr(), second.device.raw.as_mut_ptr());
+
+        drop(second);
+        assert_eq!(open_count(), 1);
+        unsafe {
+            let buffer = first
+                .device
+                .create_buffer(256, buffer::Usage::TRANSFER_DST)
+                .unwrap();
+            first.device.destroy_buffer(buffer);
+        }
+        drop(first);
+        assert_eq!(open_count(), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "warp")]
+    fn test_warp_enabled_features() {
+        use hal::PhysicalDevice;
+        use test_utils::Warp;
+
+        let (warp, _queue_group) = Warp::open("test_warp_enabled_features");
+        assert_eq!(warp.device.enabled_features(), Features::empty());
+
+        const FEATURES: Features = Features::SAMPLER_ANISOTROPY;
+        assert!(warp.adapter.physical_device.features().contains(FEATURES));
+        let (warp, _queue_group) =
+            Warp::open_with_features("test_warp_enabled_features", FEATURES).unwrap();
+        assert_eq!(warp.device.enabled_features(), FEATURES);
+    }
+
+    #[test]
+    fn test_options3_features() {
+        let mut options = FeatureDataOptions3::default();
+        assert_eq!(options.features(), Features::empty());
+        options.BarycentricsSupported = 1;
+        assert_eq!(options.features(), Features::SHADER_BARYCENTRICS);
+        options.ViewInstancingTier = 2;
+        assert_eq!(
+            options.features(),
+            Features::SHADER_BARYCENTRICS | Features::VIEW_INSTANCING
+        );
+    }
+
+    #[test]
+    fn test_tiled_resources_features() {
+        assert_eq!(
+            tiled_resources_features(D3D12_TILED_RESOURCES_TIER_NOT_SUPPORTED),
+            Features::empty()
+        );
+        assert_eq!(
+            tiled_resources_features(D3D12_TILED_RESOURCES_TIER_1),
+            Features::SPARSE_BINDING | Features::SPARSE_RESIDENCY_IMAGE_2D
+        );
+        for &tier in &[D3D12_TILED_RESOURCES_TIER_2, D3D12_TILED_RESOURCES_TIER_3] {
+            assert_eq!(
+                tiled_resources_features(tier),
+                Features::SPARSE_BINDING
+                    | Features::SPARSE_RESIDENCY_IMAGE_2D
+                    | Features::SAMPLER_REDUCTION
+            );
+        }
+    }
+
+    #[test]
+    fn test_wide_lines() {
+        validate_line_width(1.0);
+        validate_line_width(4.0);
+        let rasterizer = pso::Rasterizer {
+            polygon_mode: pso::PolygonMode::Line(4.0),
+            ..pso::Rasterizer::FILL
+        };
+        assert_eq!(
+            conv::map_rasterizer(&rasterizer).FillMode,
+            D3D12_FILL_MODE_WIREFRAME
+        );
+    }
+
+    #[test]
+    fn test_primitive_restart() {
+        use hal::Primitive;
+
+        assert_eq!(
+            conv::map_primitive_restart(pso::PrimitiveRestart::Disabled),
+            D3D12_INDEX_BUFFER_STRIP_CUT_VALUE_DISABLED
+        );
+        assert_eq!(
+            conv::map_primitive_restart(pso::PrimitiveRestart::U16),
+            D3D12_INDEX_BUFFER_STRIP_CUT_VALUE_0xFFFF
+        );
+        assert_eq!(
+            conv::map_primitive_restart(pso::PrimitiveRestart::U32),
+            D3D12_INDEX_BUFFER_STRIP_CUT_VALUE_0xFFFFFFFF
+        );
+        assert!(conv::supports_primitive_restart(Primitive::TriangleStrip));
+        assert!(conv::supports_primitive_restart(Primitive::LineStrip));
+        assert!(!conv::supports_primitive_restart(Primitive::TriangleList));
+        assert!(!conv::supports_primitive_restart(Primitive::PointList));
+    }
+
+    #[test]
+    fn test_conservative_rasterizer() {
+        let mut rasterizer = pso::Rasterizer::FILL;
+        assert_eq!(
+            conv::map_rasterizer(&rasterizer).ConservativeRaster,
+            D3D12_CONSERVATIVE_RASTERIZATION_MODE_OFF
+        );
+        rasterizer.conservative = true;
+        assert_eq!(
+            conv::map_rasterizer(&rasterizer).ConservativeRaster,
+            D3D12_CONSERVATIVE_RASTERIZATION_MODE_ON
+        );
+    }
+
+    #[test]
+    fn test_depth_clip_rasterizer() {
+        let mut rasterizer = pso::Rasterizer::FILL;
+        assert_eq!(conv::map_rasterizer(&rasterizer).DepthClipEnable, TRUE);
+        rasterizer.depth_clamping = true;
+        assert_eq!(conv::map_rasterizer(&rasterizer).DepthClipEnable, FALSE);
+    }
+
+    const TEXTURE_SUPPORT: D3D12_FORMAT_SUPPORT1 = D3D12_FORMAT_SUPPORT1_TEXTURE1D
+        | D3D12_FORMAT_SUPPORT1_TEXTURE2D
+        | D3D12_FORMAT_SUPPORT1_TEXTURECUBE
+        | D3D12_FORMAT_SUPPORT1_MIP;
+
+    #[test]
+    fn test_format_properties_color() {
+        let support1 = TEXTURE_SUPPORT
+            | D3D12_FORMAT_SUPPORT1_BUFFER
+            | D3D12_FORMAT_SUPPORT1_SHADER_LOAD
+            | D3D12_FORMAT_SUPPORT1_SHADER_SAMPLE
+            | D3D12_FORMAT_SUPPORT1_RENDER_TARGET
+            | D3D12_FORMAT_SUPPORT1_BLENDABLE
+            | D3D12_FORMAT_SUPPORT1_MULTISAMPLE_RESOLVE;
+        let props = format_properties(Format::Rgba8Unorm, support1, 0, false);
+        assert!(props.optimal_tiling.contains(
+            ImageFeature::SAMPLED_LINEAR
+                | ImageFeature::COLOR_ATTACHMENT_BLEND
+                | ImageFeature::BLIT_DST
+        ));
+        assert!(props
+            .linear_tiling
+            .contains(ImageFeature::SAMPLED | ImageFeature::COLOR_ATTACHMENT));
+        assert!(!props
+            .linear_tiling
+            .contains(ImageFeature::DEPTH_STENCIL_ATTACHMENT));
+    }
+
+    #[test]
+    fn test_format_properties_typed_uav_load() {
+        let support2 = D3D12_FORMAT_SUPPORT2_UAV_TYPED_STORE | D3D12_FORMAT_SUPPORT2_UAV_TYPED_LOAD;
+        // Always supported, independent of the reported support.
+        for &additional in &[false, true] {
+            let props = format_properties(
+                Format::R32Sfloat,
+                TEXTURE_SUPPORT,
+                D3D12_FORMAT_SUPPORT2_UAV_TYPED_STORE,
+                additional,
+            );
+            assert!(props.optimal_tiling.contains(ImageFeature::STORAGE_READ));
         }
-        if data.Support1 & d3d12::D3D12_FORMAT_SUPPORT1_IA_VERTEX_BUFFER != 0 {
-            props.buffer_features |= f::BufferFeature::VERTEX;
+
+        let props = format_properties(Format::Rgba8Unorm, TEXTURE_SUPPORT, support2, false);
+        assert!(props.optimal_tiling.contains(ImageFeature::STORAGE));
+        assert!(!props.optimal_tiling.contains(ImageFeature::STORAGE_READ));
+        let props = format_properties(Format::Rgba8Unorm, TEXTURE_SUPPORT, support2, true);
+        assert!(props.optimal_tiling.contains(ImageFeature::STORAGE_READ));
+        let props = format_properties(
+            Format::Rgba8Unorm,
+            TEXTURE_SUPPORT,
+            D3D12_FORMAT_SUPPORT2_UAV_TYPED_STORE,
+            true,
+        );
+        assert!(!props.optimal_tiling.contains(ImageFeature::STORAGE_READ));
+    }
+
+    #[test]
+    fn test_format_properties_depth() {
+        let support1 = TEXTURE_SUPPORT | D3D12_FORMAT_SUPPORT1_DEPTH_STENCIL;
+        let props = format_properties(Format::D32Sfloat, support1, 0, false);
+        assert!(props
+            .optimal_tiling
+            .contains(ImageFeature::DEPTH_STENCIL_ATTACHMENT));
+        assert!(!props
+            .linear_tiling
+            .contains(ImageFeature::DEPTH_STENCIL_ATTACHMENT));
+        assert!(!props.optimal_tiling.contains(ImageFeature::COLOR_ATTACHMENT));
+    }
+
+    #[test]
+    fn test_depth_format_typeless_parents() {
+        use winapi::shared::dxgiformat::*;
+
+        let formats = [
+            (Format::D16Unorm, DXGI_FORMAT_R16_TYPELESS, DXGI_FORMAT_D16_UNORM),
+            (Format::X8D24Unorm, DXGI_FORMAT_R24G8_TYPELESS, DXGI_FORMAT_D24_UNORM_S8_UINT),
+            (Format::D24UnormS8Uint, DXGI_FORMAT_R24G8_TYPELESS, DXGI_FORMAT_D24_UNORM_S8_UINT),
+            (Format::D32Sfloat, DXGI_FORMAT_R32_TYPELESS, DXGI_FORMAT_D32_FLOAT),
+            (
+                Format::D32SfloatS8Uint,
+                DXGI_FORMAT_R32G8X24_TYPELESS,
+                DXGI_FORMAT_D32_FLOAT_S8X24_UINT,
+            ),
+        ];
+        for &(format, typeless, dsv) in &formats {
+            let surface = format.base_format().0;
+            assert_eq!(conv::map_surface_type(surface), Some(typeless));
+            assert_eq!(conv::map_format_dsv(surface), Some(dsv));
+            // Support is queried for the format the depth-stencil view is created with.
+            assert_eq!(conv::map_format(format), Some(dsv));
         }
-        if data.Support1 & d3d12::D3D12_FORMAT_SUPPORT1_SHADER_SAMPLE != 0 {
-            props.optimal_tiling |= f::ImageFeature::SAMPLED_LINEAR;
+    }
+
+    #[test]
+    fn test_format_properties_compressed() {
+        let support1 =
+            TEXTURE_SUPPORT | D3D12_FORMAT_SUPPORT1_SHADER_LOAD | D3D12_FORMAT_SUPPORT1_SHADER_SAMPLE;
+        let props = format_properties(Format::Bc1RgbaUnorm, support1, 0, false);
+        assert!(props
+            .optimal_tiling
+            .contains(ImageFeature::SAMPLED | ImageFeature::SAMPLED_LINEAR));
+        assert!(props.linear_tiling.is_empty());
+    }
+
+    #[test]
+    fn test_format_properties_planar() {
+        let support1 =
+            TEXTURE_SUPPORT | D3D12_FORMAT_SUPPORT1_SHADER_LOAD | D3D12_FORMAT_SUPPORT1_SHADER_SAMPLE;
+        let props = format_properties(Format::Nv12Unorm, support1, 0, false);
+        assert!(props.optimal_tiling.contains(ImageFeature::SAMPLED));
+        assert!(props.linear_tiling.is_empty());
+    }
+
+    #[test]
+    fn test_planar_format_planes() {
+        use winapi::shared::dxgiformat::*;
+
+        let formats = [
+            (
+                Format::Nv12Unorm,
+                DXGI_FORMAT_NV12,
+                (DXGI_FORMAT_R8_UNORM, 1),
+                (DXGI_FORMAT_R8G8_UNORM, 2),
+            ),
+            (
+                Format::P010Unorm,
+                DXGI_FORMAT_P010,
+                (DXGI_FORMAT_R16_UNORM, 2),
+                (DXGI_FORMAT_R16G16_UNORM, 4),
+            ),
+        ];
+        for &(format, dxgi_format, luma, chroma) in &formats {
+            let surface = format.base_format().0;
+            assert!(format.is_planar());
+            assert_eq!(conv::map_format(format), Some(dxgi_format));
+            assert_eq!(conv::map_surface_type(surface), Some(dxgi_format));
+
+            assert_eq!(
+                conv::map_format_shader_resource(dxgi_format, Aspects::PLANE_0),
+                luma.0
+            );
+            assert_eq!(conv::map_plane_slice(Aspects::PLANE_0), 0);
+            assert_eq!(
+                conv::map_planar_layout(surface, Aspects::PLANE_0),
+                Some((0, luma.0, luma.1))
+            );
+
+            assert_eq!(
+                conv::map_format_shader_resource(dxgi_format, Aspects::PLANE_1),
+                chroma.0
+            );
+            assert_eq!(conv::map_plane_slice(Aspects::PLANE_1), 1);
+            assert_eq!(
+                conv::map_planar_layout(surface, Aspects::PLANE_1),
+                Some((1, chroma.0, chroma.1))
+            );
         }
-        if data.Support1 & d3d12::D3D12_FORMAT_SUPPORT1_RENDER_TARGET != 0 {
-            props.optimal_tiling |= f::ImageFeature::COLOR_ATTACHMENT | f::ImageFeature::BLIT_DST;
-            if can_linear {
-                props.linear_tiling |=
-                    f::ImageFeature::COLOR_ATTACHMENT | f::ImageFeature::BLIT_DST;
+        assert_eq!(conv::map_planar_layout(SurfaceType::R8, Aspects::COLOR), None);
+    }
+
+    #[test]
+    fn test_depth_stencil_input_attachment_planes() {
+        use winapi::shared::dxgiformat::*;
+
+        // Reading the depth aspect of a depth-stencil attachment.
+        let format = conv::map_format(Format::D24UnormS8Uint).unwrap();
+        assert_eq!(
+            conv::map_format_shader_resource(format, Aspects::DEPTH),
+            DXGI_FORMAT_R24_UNORM_X8_TYPELESS
+        );
+        assert_eq!(conv::map_plane_slice(Aspects::DEPTH), 0);
+        assert_eq!(
+            conv::map_format_shader_resource(format, Aspects::STENCIL),
+            DXGI_FORMAT_X24_TYPELESS_G8_UINT
+        );
+        assert_eq!(conv::map_plane_slice(Aspects::STENCIL), 1);
+
+        let format = conv::map_format(Format::D32SfloatS8Uint).unwrap();
+        assert_eq!(
+            conv::map_format_shader_resource(format, Aspects::DEPTH | Aspects::STENCIL),
+            DXGI_FORMAT_R32_FLOAT_X8X24_TYPELESS
+        );
+        assert_eq!(conv::map_plane_slice(Aspects::DEPTH | Aspects::STENCIL), 0);
+    }
+
+    #[test]
+    fn test_feature_levels() {
+        use winapi::um::d3dcommon::D3D_FEATURE_LEVEL_11_0;
+
+        assert!(FEATURE_LEVELS
+            .iter()
+            .all(|&level| level as u32 >= D3D_FEATURE_LEVEL_11_0));
+        assert!(FEATURE_LEVELS
+            .windows(2)
+            .all(|levels| levels[0] as u32 > levels[1] as u32));
+        assert_eq!(
+            *FEATURE_LEVELS.last().unwrap() as u32,
+            D3D_FEATURE_LEVEL_11_0
+        );
+    }
+
+    #[cfg(feature = "warp")]
+    #[test]
+    fn test_warp_memory_budget() {
+        use super::MemorySegment;
+        use test_utils::warp_adapter;
+
+        let instance = super::Instance::create("test_warp_memory_budget", 1);
+        let adapter = warp_adapter(&instance);
+        let budget = adapter.physical_device.memory_budget(MemorySegment::Local);
+        assert!(budget.current_usage <= budget.budget);
+    }
+
+    #[cfg(feature = "warp")]
+    #[test]
+    fn test_warp_supports_resolve() {
+        use test_utils::warp_adapter;
+
+        let instance = super::Instance::create("test_warp_supports_resolve", 1);
+        let adapter = warp_adapter(&instance);
+        assert!(adapter.physical_device.supports_resolve(Format::Rgba8Unorm));
+        assert!(!adapter.physical_device.supports_resolve(Format::Bc1RgbaUnorm));
+    }
+
+    #[test]
+    fn test_device_type() {
+        use hal::adapter::DeviceType;
+        use winapi::shared::dxgi::DXGI_ADAPTER_FLAG_SOFTWARE;
+
+        assert_eq!(device_type(0, false), DeviceType::DiscreteGpu);
+        assert_eq!(device_type(0, true), DeviceType::IntegratedGpu);
+        assert_eq!(device_type(DXGI_ADAPTER_FLAG_SOFTWARE, false), DeviceType::Cpu);
+        assert_eq!(device_type(DXGI_ADAPTER_FLAG_SOFTWARE, true), DeviceType::Cpu);
+    }
+
+    #[cfg(feature = "warp")]
+    #[test]
+    fn test_enumerated_device_types() {
+        use hal::adapter::DeviceType;
+
+        let instance = super::Instance::create("test_enumerated_device_types", 1);
+        for adapter in instance.enumerate_adapters_with_warp() {
+            match adapter.info.device_type {
+                DeviceType::IntegratedGpu | DeviceType::DiscreteGpu | DeviceType::Cpu => {}
+                other => panic!("Unexpected device type {:?}", other),
+            }
+            // UMA adapters share memory with the host, these are never discrete.
+            match adapter.physical_device.private_caps.memory_architecture {
+                super::MemoryArchitecture::NUMA => {}
+                _ => assert_ne!(adapter.info.device_type, DeviceType::DiscreteGpu),
             }
         }
-        if data.Support1 & d3d12::D3D12_FORMAT_SUPPORT1_BLENDABLE != 0 {
-            props.optimal_tiling |= f::ImageFeature::COLOR_ATTACHMENT_BLEND;
+    }
+
+    #[cfg(feature = "warp")]
+    #[test]
+    fn test_warp_luid() {
+        use test_utils::warp_adapter;
+
+        let instance = super::Instance::create("test_warp_luid", 1);
+        let adapter = warp_adapter(&instance);
+        let luid = adapter.physical_device.luid();
+        assert_eq!(luid, adapter.physical_device.luid());
+    }
+
+    #[cfg(feature = "warp")]
+    #[test]
+    fn test_warp_robust_buffer_access_unsupported() {
+        use hal::error::DeviceCreationError;
+        use hal::PhysicalDevice;
+        use test_utils::warp_adapter;
+
+        let instance = super::Instance::create("test_warp_robust_buffer_access_unsupported", 1);
+        let adapter = warp_adapter(&instance);
+        const FEATURES: Features = Features::ROBUST_BUFFER_ACCESS;
+        let physical_device = &adapter.physical_device;
+        assert!(!physical_device.features().contains(FEATURES));
+        let family = &adapter.queue_families[0];
+        let result = unsafe { physical_device.open(&[(family, &[1.0])], FEATURES) };
+        match result {
+            Err(DeviceCreationError::MissingFeature) => {}
+            _ => panic!("Robust buffer access can't be enabled"),
         }
-        if data.Support1 & d3d12::D3D12_FORMAT_SUPPORT1_DEPTH_STENCIL != 0 {
-            props.optimal_tiling |= f::ImageFeature::DEPTH_STENCIL_ATTACHMENT;
+    }
+
+    #[test]
+    fn test_luid_bytes() {
+        let luid = LUID {
+            LowPart: 0x0403_0201,
+            HighPart: -2,
+        };
+        assert_eq!(luid_bytes(&luid), [1, 2, 3, 4, 0xFE, 0xFF, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn test_gpu_preference() {
+        assert_eq!(GpuPreference::MinimumPower.to_dxgi(), 1);
+        assert_eq!(GpuPreference::HighPerformance.to_dxgi(), 2);
+    }
+
+    #[cfg(feature = "warp")]
+    #[test]
+    fn test_enumerate_with_preference() {
+        let instance = super::Instance::create("test_enumerate_with_preference", 1);
+        for &preference in &[GpuPreference::MinimumPower, GpuPreference::HighPerformance] {
+            assert!(!instance
+                .enumerate_adapters_with_preference(preference)
+                .is_empty());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "warp")]
+    fn test_warp_sparse_image() {
+        use hal::command::{
+            BufferImageCopy,
+            ClearColorRaw,
+            ClearDepthStencilRaw,
+            CommandBufferFlags,
+            RawCommandBuffer,
+            RawLevel,
+        };
+        use hal::memory::{Barrier, Dependencies, Properties};
+        use hal::pool::{CommandPoolCreateFlags, RawCommandPool};
+        use hal::pso::PipelineStage;
+        use hal::queue::{RawCommandQueue, Submission};
+        use hal::{buffer, image, Device};
+        use std::iter;
+
+        use super::resource::Semaphore;
+        use test_utils::Warp;
+
+        // Sparse images are only available with `SPARSE_BINDING` enabled.
+        const FEATURES: Features = Features::SPARSE_BINDING;
+        let (warp, mut queue_group) =
+            match Warp::open_with_features("test_warp_sparse_image", FEATURES) {
+                Some(warp) => warp,
+                None => return,
+            };
+        let device = &warp.device;
+        unsafe {
+            // 256x256 RGBA8 image of 2x2 tiles, only the first tile is bound to memory.
+            let image = device
+                .create_sparse_image(
+                    image::Kind::D2(256, 256, 1, 1),
+                    1,
+                    Format::Rgba8Unorm,
+                    image::Usage::TRANSFER_DST | image::Usage::TRANSFER_SRC,
+                    image::ViewCapabilities::empty(),
+                )
+                .unwrap();
+            let requirements = device.get_image_requirements(&image);
+            let memory = warp.allocate(
+                requirements.type_mask,
+                D3D12_TILED_RESOURCE_TILE_SIZE_IN_BYTES as u64,
+                Properties::DEVICE_LOCAL,
+            );
+            let subresource = image::Subresource {
+                aspects: Aspects::COLOR,
+                level: 0,
+                layer: 0,
+            };
+            queue_group.queues[0].as_raw_mut().bind_sparse_image(
+                &image,
+                subresource,
+                image::Offset::ZERO,
+                image::Extent {
+                    width: 1,
+                    height: 1,
+                    depth: 1,
+                },
+                Some((&memory, 0)),
+            );
+
+            let mut readback = device
+                .create_buffer(256, buffer::Usage::TRANSFER_DST)
+                .unwrap();
+            let requirements = device.get_buffer_requirements(&readback);
+            let readback_memory = warp.allocate(
+                requirements.type_mask,
+                requirements.size,
+                Properties::CPU_VISIBLE | Properties::CPU_CACHED,
+            );
+            device
+                .bind_buffer_memory(&readback_memory, 0, &mut readback)
+                .unwrap();
+
+            let range = image::SubresourceRange {
+                aspects: Aspects::COLOR,
+                levels: 0 .. 1,
+                layers: 0 .. 1,
+            };
+            let mut command_pool = device
+                .create_command_pool(queue_group.family(), CommandPoolCreateFlags::empty())
+                .unwrap();
+            let mut cmd_buffer = command_pool.allocate_one(RawLevel::Primary);
+            cmd_buffer.begin(CommandBufferFlags::ONE_TIME_SUBMIT, Default::default());
+            cmd_buffer.pipeline_barrier(
+                PipelineStage::TOP_OF_PIPE .. PipelineStage::TRANSFER,
+                Dependencies::empty(),
+                iter::once(Barrier::Image {
+                    states: (image::Access::empty(), image::Layout::Undefined)
+                        .. (
+                            image::Access::TRANSFER_WRITE,
+                            image::Layout::TransferDstOptimal,
+                        ),
+                    target: &image,
+                    families: None,
+                    range: range.clone(),
+                }),
+            );
+            cmd_buffer.clear_image(
+                &image,
+                image::Layout::TransferDstOptimal,
+                ClearColorRaw {
+                    float32: [0.0, 1.0, 0.0, 1.0],
+                },
+                ClearDepthStencilRaw {
+                    depth: 0.0,
+                    stencil: 0,
+                },
+                iter::once(range.clone()),
+            );
+            cmd_buffer.pipeline_barrier(
+                PipelineStage::TRANSFER .. PipelineStage::TRANSFER,
+                Dependencies::empty(),
+                iter::once(Barrier::Image {
+                    states: (
+                        image::Access::TRANSFER_WRITE,
+                        image::Layout::TransferDstOptimal,
+                    )
+                        .. (
+                            image::Access::TRANSFER_READ,
+                            image::Layout::TransferSrcOptimal,
+                        ),
+                    target: &image,
+                    families: None,
+                    range,
+                }),
+            );
+            cmd_buffer.copy_image_to_buffer(
+                &image,
+                image::Layout::TransferSrcOptimal,
+                &readback,
+                iter::once(BufferImageCopy {
+                    buffer_offset: 0,
+                    buffer_width: 64,
+                    buffer_height: 1,
+                    image_layers: image::SubresourceLayers {
+                        aspects: Aspects::COLOR,
+                        level: 0,
+                        layers: 0 .. 1,
+                    },
+                    image_offset: image::Offset { x: 127, y: 127, z: 0 },
+                    image_extent: image::Extent {
+                        width: 1,
+                        height: 1,
+                        depth: 1,
+                    },
+                }),
+            );
+            cmd_buffer.finish();
+
+            let fence = device.create_fence(false).unwrap();
+            queue_group.queues[0].as_raw_mut().submit(
+                Submission {
+                    command_buffers: iter::once(&cmd_buffer),
+                    wait_semaphores: iter::empty::<(&Semaphore, _)>(),
+                    signal_semaphores: iter::empty::<&Semaphore>(),
+                },
+                Some(&fence),
+            );
+            assert_eq!(device.wait_for_fence(&fence, !0), Ok(true));
+
+            // Last texel of the bound tile.
+            let data = device.map_memory(&readback_memory, 0 .. 256).unwrap();
+            assert_eq!(*(data as *const u32), 0xFF00_FF00);
+            device.unmap_memory(&readback_memory);
+
+            device.destroy_fence(fence);
+            command_pool.free(iter::once(cmd_buffer));
+            device.destroy_command_pool(command_pool);
+            device.destroy_buffer(readback);
+            device.free_memory(readback_memory);
+            device.destroy_image(image);
+            device.free_memory(memory);
         }
-        if data.Support1 & d3d12::D3D12_FORMAT_SUPPORT1_SHADER_LOAD != 0 {
-            //TODO: check d3d12::D3D12_FORMAT_SUPPORT2_UAV_TYPED_LOAD ?
-            if can_buffer {
-                props.buffer_features |= f::BufferFeature::UNIFORM_TEXEL;
+    }
+
+    #[test]
+    #[cfg(feature = "warp")]
+    fn test_warp_realtime_queue_fallback() {
+        use test_utils::warp_adapter;
+
+        let instance = super::Instance::create("test_warp_realtime_queue_fallback", 1);
+        let mut adapter = warp_adapter(&instance);
+        let queue_priority = |adapter: &hal::Adapter<super::Backend>| {
+            let (_device, queue_group) = adapter.open_with::<_, hal::General>(1, |_| true).unwrap();
+            unsafe { queue_group.queues[0].as_raw().raw.GetDesc().Priority }
+        };
+
+        // `open_with` requests the highest priority, queues have normal priority by default.
+        assert_eq!(
+            queue_priority(&adapter),
+            D3D12_COMMAND_QUEUE_PRIORITY_NORMAL as i32
+        );
+        adapter.physical_device.set_max_queue_priority(super::QueuePriorityClass::High);
+        assert_eq!(
+            queue_priority(&adapter),
+            D3D12_COMMAND_QUEUE_PRIORITY_HIGH as i32
+        );
+        // Realtime queues fall back to high priority without the required privileges.
+        adapter.physical_device.set_max_queue_priority(super::QueuePriorityClass::Realtime);
+        let priority = queue_priority(&adapter);
+        assert!(
+            priority == D3D12_COMMAND_QUEUE_PRIORITY_GLOBAL_REALTIME as i32
+                || priority == D3D12_COMMAND_QUEUE_PRIORITY_HIGH as i32
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "warp")]
+    fn test_warp_semaphore_reuse() {
+        use hal::pso::PipelineStage;
+        use hal::queue::{RawCommandQueue, Submission};
+        use hal::Device;
+        use std::iter;
+
+        use super::command::CommandBuffer;
+        use test_utils::Warp;
+
+        let (warp, mut queue_group) = Warp::open("test_warp_semaphore_reuse");
+        let device = &warp.device;
+        unsafe {
+            let semaphore = device.create_semaphore().unwrap();
+            let fence = device.create_fence(false).unwrap();
+            let queue = queue_group.queues[0].as_raw_mut();
+            // Signal twice before waiting, then reuse the semaphore without a reset.
+            for &signals in &[2, 1, 1] {
+                for _ in 0 .. signals {
+                    queue.submit(
+                        Submission {
+                            command_buffers: iter::empty::<&CommandBuffer>(),
+                            wait_semaphores: iter::empty(),
+                            signal_semaphores: iter::once(&semaphore),
+                        },
+                        None,
+                    );
+                }
+                queue.submit(
+                    Submission {
+                        command_buffers: iter::empty::<&CommandBuffer>(),
+                        wait_semaphores: iter::once((&semaphore, PipelineStage::TOP_OF_PIPE)),
+                        signal_semaphores: iter::empty(),
+                    },
+                    Some(&fence),
+                );
+                assert_eq!(device.wait_for_fence(&fence, 1_000_000_000), Ok(true));
+                device.reset_fence(&fence).unwrap();
             }
+            assert_eq!(semaphore.raw.GetCompletedValue(), 4);
+
+            device.destroy_fence(fence);
+            device.destroy_semaphore(semaphore);
         }
-        if data.Support2 & d3d12::D3D12_FORMAT_SUPPORT2_UAV_ATOMIC_ADD != 0 {
-            //TODO: other atomic flags?
-            if can_buffer {
-                props.buffer_features |= f::BufferFeature::STORAGE_TEXEL_ATOMIC;
+    }
+
+    #[test]
+    #[cfg(feature = "warp")]
+    fn test_warp_submit_batch() {
+        use hal::buffer;
+        use hal::command::{BufferCopy, CommandBufferFlags, RawCommandBuffer, RawLevel};
+        use hal::memory::Properties;
+        use hal::pool::{CommandPoolCreateFlags, RawCommandPool};
+        use hal::pso::PipelineStage;
+        use hal::queue::RawCommandQueue;
+        use hal::Device;
+        use std::{iter, slice};
+
+        use super::{BatchError, BatchSubmission, CommandQueue};
+        use test_utils::{entry_point, Warp};
+
+        let (warp, mut general, mut compute, mut transfer) =
+            Warp::open_queue_families("test_warp_submit_batch");
+        let device = &warp.device;
+        unsafe {
+            let upload =
+                warp.create_buffer_with_data(&[1u32, 2, 3, 4], buffer::Usage::TRANSFER_SRC);
+            let data = warp.create_buffer(
+                16,
+                buffer::Usage::TRANSFER_SRC | buffer::Usage::TRANSFER_DST | buffer::Usage::STORAGE,
+                Properties::DEVICE_LOCAL,
+            );
+            let readback = warp.create_buffer(
+                16,
+                buffer::Usage::TRANSFER_DST,
+                Properties::CPU_VISIBLE | Properties::CPU_CACHED,
+            );
+
+            let set_layout = device
+                .create_descriptor_set_layout(
+                    &[pso::DescriptorSetLayoutBinding {
+                        binding: 0,
+                        ty: pso::DescriptorType::StorageBuffer,
+                        count: 1,
+                        stage_flags: pso::ShaderStageFlags::COMPUTE,
+                        immutable_samplers: false,
+                    }],
+                    &[],
+                )
+                .unwrap();
+            let layout = warp.create_pipeline_layout(&[&set_layout]);
+            let mut pool = device
+                .create_descriptor_pool(
+                    1,
+                    iter::once(pso::DescriptorRangeDesc {
+                        ty: pso::DescriptorType::StorageBuffer,
+                        count: 1,
+                    }),
+                    pso::DescriptorPoolCreateFlags::empty(),
+                )
+                .unwrap();
+            let set = pool.allocate_set(&set_layout).unwrap();
+            device.write_descriptor_sets(iter::once(pso::DescriptorSetWrite {
+                set: &set,
+                binding: 0,
+                array_offset: 0,
+                descriptors: iter::once(pso::Descriptor::Buffer(&data.0, None .. None)),
+            }));
+            let modules = warp.create_shader_modules(&[(
+                pso::Stage::Compute,
+                "cs_main",
+                b"RWByteAddressBuffer data : register(u0);
+                [numthreads(4, 1, 1)] void cs_main(uint i : SV_DispatchThreadID) {
+                    data.Store(i * 4, data.Load(i * 4) * 2);
+                }",
+            )];
+            let pipeline = device
+                .create_compute_pipeline(
+                    &pso::ComputePipelineDesc::new(entry_point(&modules[0]), &layout),
+                    None,
+                )
+                .unwrap();
+
+            // Upload on the copy queue, double on the compute queue and read back on the
+            // graphics queue.
+            let mut command_pools = vec![
+                device
+                    .create_command_pool(transfer.family(), CommandPoolCreateFlags::empty())
+                    .unwrap(),
+                device
+                    .create_command_pool(compute.family(), CommandPoolCreateFlags::empty())
+                    .unwrap(),
+                device
+                    .create_command_pool(general.family(), CommandPoolCreateFlags::empty())
+                    .unwrap(),
+            ];
+            let mut cmd_buffers = command_pools
+                .iter_mut()
+                .map(|command_pool| {
+                    let mut cmd_buffer = command_pool.allocate_one(RawLevel::Primary);
+                    cmd_buffer.begin(CommandBufferFlags::ONE_TIME_SUBMIT, Default::default());
+                    cmd_buffer
+                })
+                .collect::<Vec<_>>();
+            let copy = |cmd_buffer: &mut super::command::CommandBuffer, src, dst| {
+                cmd_buffer.copy_buffer(
+                    src,
+                    dst,
+                    iter::once(BufferCopy {
+                        src: 0,
+                        dst: 0,
+                        size: 16,
+                    }),
+                );
+            };
+            copy(&mut cmd_buffers[0], &upload.0, &data.0);
+            cmd_buffers[1].bind_compute_pipeline(&pipeline);
+            cmd_buffers[1].bind_compute_descriptor_sets(&layout, 0, iter::once(&set), &[]);
+            cmd_buffers[1].dispatch([1, 1, 1]);
+            copy(&mut cmd_buffers[2], &data.0, &readback.0);
+            for cmd_buffer in &mut cmd_buffers {
+                cmd_buffer.finish();
             }
-            if can_image {
-                props.optimal_tiling |= f::ImageFeature::STORAGE_ATOMIC;
+
+            let uploaded = device.create_semaphore().unwrap();
+            let doubled = device.create_semaphore().unwrap();
+            let mut queues: Vec<&mut CommandQueue> = vec![
+                transfer.queues[0].as_raw_mut(),
+                compute.queues[0].as_raw_mut(),
+                general.queues[0].as_raw_mut(),
+            ];
+
+            // Nothing is submitted when a queue index is out of range.
+            let invalid = [BatchSubmission {
+                queue: 3,
+                command_buffers: &[],
+                wait_semaphores: &[],
+                signal_semaphores: &[],
+            }];
+            assert_eq!(
+                CommandQueue::submit_batch(&mut queues, &invalid),
+                Err(BatchError::InvalidQueue(3))
+            );
+
+            // Submissions are listed in reverse dependency order.
+            let batch = [
+                BatchSubmission {
+                    queue: 2,
+                    command_buffers: &[&cmd_buffers[2]],
+                    wait_semaphores: &[(&doubled, PipelineStage::TRANSFER)],
+                    signal_semaphores: &[],
+                },
+                BatchSubmission {
+                    queue: 1,
+                    command_buffers: &[&cmd_buffers[1]],
+                    wait_semaphores: &[(&uploaded, PipelineStage::COMPUTE_SHADER)],
+                    signal_semaphores: &[&doubled],
+                },
+                BatchSubmission {
+                    queue: 0,
+                    command_buffers: &[&cmd_buffers[0]],
+                    wait_semaphores: &[],
+                    signal_semaphores: &[&uploaded],
+                },
+            ];
+            assert_eq!(CommandQueue::submit_batch(&mut queues, &batch), Ok(()));
+            for queue in &mut queues {
+                queue.wait_idle().unwrap();
             }
-        }
-        if data.Support2 & d3d12::D3D12_FORMAT_SUPPORT2_UAV_TYPED_STORE != 0 {
-            if can_buffer {
-                props.buffer_features |= f::BufferFeature::STORAGE_TEXEL;
+
+            let mapping = device.map_memory(&readback.1, 0 .. 16).unwrap();
+            assert_eq!(slice::from_raw_parts(mapping as *const u32, 4), &[2, 4, 6, 8]);
+            device.unmap_memory(&readback.1);
+
+            device.destroy_semaphore(uploaded);
+            device.destroy_semaphore(doubled);
+            for (mut command_pool, cmd_buffer) in command_pools.into_iter().zip(cmd_buffers) {
+                command_pool.free(iter::once(cmd_buffer));
+                device.destroy_command_pool(command_pool);
             }
-            if can_image {
-                props.optimal_tiling |= f::ImageFeature::STORAGE;
+            device.destroy_compute_pipeline(pipeline);
+            warp.destroy_shader_modules(modules);
+            device.destroy_descriptor_pool(pool);
+            device.destroy_pipeline_layout(layout);
+            device.destroy_descriptor_set_layout(set_layout);
+            for buffer in vec![upload, data, readback] {
+                warp.destroy_buffer(buffer);
             }
         }
-        //TODO: blits, linear tiling
-        *guard = Some(props);
-        props
     }
 }
@@ -23,14 +23,29 @@ pub struct RawCommandPool {
     pub(crate) list_type: CmdListType,
     pub(crate) shared: Arc<Shared>,
     pub(crate) create_flags: pool::CommandPoolCreateFlags,
+    // Allocators of secondary command buffers, created per bundle.
+    pub(crate) bundle_allocators: Vec<native::CommandAllocator>,
 }
 
 impl RawCommandPool {
-    fn create_command_list(&mut self) -> (native::GraphicsCommandList, native::CommandAllocator) {
-        let command_allocator = match self.allocator {
-            CommandPoolAllocator::Shared(ref allocator) => allocator.clone(),
-            CommandPoolAllocator::Individual(ref mut allocators) => {
-                let (command_allocator, hr) = self.device.create_command_allocator(self.list_type);
+    fn create_command_list(
+        &mut self,
+        level: command::RawLevel,
+    ) -> (native::GraphicsCommandList, native::CommandAllocator) {
+        let list_type = match level {
+            command::RawLevel::Primary => self.list_type,
+            command::RawLevel::Secondary => match self.list_type {
+                // Bundles can only be executed on direct command lists.
+                CmdListType::Direct => CmdListType::Bundle,
+                _ => panic!("Secondary command buffers require a graphics queue family"),
+            },
+        };
+        let command_allocator = match (level, &mut self.allocator) {
+            (command::RawLevel::Primary, &mut CommandPoolAllocator::Shared(ref allocator)) => {
+                allocator.clone()
+            }
+            (command::RawLevel::Primary, &mut CommandPoolAllocator::Individual(ref mut allocators)) => {
+                let (command_allocator, hr) = self.device.create_command_allocator(list_type);
 
                 // TODO: error handling
                 if !SUCCEEDED(hr) {
@@ -40,11 +55,22 @@ impl RawCommandPool {
                 allocators.push(command_allocator);
                 command_allocator
             }
+            (command::RawLevel::Secondary, _) => {
+                // Bundle allocators can't be shared with direct command lists.
+                let (command_allocator, hr) = self.device.create_command_allocator(list_type);
+
+                if !SUCCEEDED(hr) {
+                    error!("error on bundle allocator creation: {:x}", hr);
+                }
+
+                self.bundle_allocators.push(command_allocator);
+                command_allocator
+            }
         };
 
         // allocate command lists
         let (command_list, hr) = self.device.create_graphics_command_list(
-            self.list_type,
+            list_type,
             command_allocator,
             native::PipelineState::null(),
             0,
@@ -61,7 +87,23 @@ impl RawCommandPool {
         (command_list, command_allocator)
     }
 
+    // Destroy the allocator owned by a freed command buffer.
+    fn release_allocator(&mut self, allocator: native::CommandAllocator) {
+        if let CommandPoolAllocator::Individual(ref mut allocators) = self.allocator {
+            allocators.retain(|&a| a != allocator);
+        }
+        self.bundle_allocators.retain(|&a| a != allocator);
+        unsafe {
+            allocator.destroy();
+        }
+    }
+
     pub(crate) fn destroy(self) {
+        for allocator in &self.bundle_allocators {
+            unsafe {
+                allocator.destroy();
+            }
+        }
         match self.allocator {
             CommandPoolAllocator::Shared(ref allocator) => unsafe {
                 allocator.destroy();
@@ -92,17 +134,20 @@ impl pool::RawCommandPool<Backend> for RawCommandPool {
                 }
             }
         }
+        for allocator in &self.bundle_allocators {
+            allocator.Reset();
+        }
     }
 
     fn allocate_one(&mut self, level: command::RawLevel) -> CommandBuffer {
-        // TODO: Implement secondary buffers
-        assert_eq!(level, command::RawLevel::Primary);
-        let (command_list, command_allocator) = self.create_command_list();
+        // Secondary command buffers are recorded as bundles.
+        let (command_list, command_allocator) = self.create_command_list(level);
         CommandBuffer::new(
             command_list,
             command_allocator,
             self.shared.clone(),
             self.create_flags,
+            level,
         )
     }
 
@@ -111,7 +156,11 @@ impl pool::RawCommandPool<Backend> for RawCommandPool {
         I: IntoIterator<Item = CommandBuffer>,
     {
         for mut cbuf in cbufs {
+            let allocator = cbuf.owned_allocator();
             cbuf.destroy();
+            if let Some(allocator) = allocator {
+                self.release_allocator(allocator);
+            }
         }
     }
 }
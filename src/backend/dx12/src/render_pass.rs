@@ -0,0 +1,344 @@
+//! Render pass API of `ID3D12GraphicsCommandList4`, which isn't exposed by winapi.
+
+#![allow(non_snake_case)]
+
+use hal::pass;
+
+use std::mem;
+
+use winapi::shared::dxgiformat::DXGI_FORMAT;
+use winapi::shared::guiddef::GUID;
+use winapi::shared::minwindef::{BOOL, UINT};
+use winapi::um::d3d12;
+use winapi::Interface;
+
+pub(crate) type BeginningAccessType = UINT;
+pub(crate) const BEGINNING_ACCESS_TYPE_DISCARD: BeginningAccessType = 0;
+pub(crate) const BEGINNING_ACCESS_TYPE_PRESERVE: BeginningAccessType = 1;
+pub(crate) const BEGINNING_ACCESS_TYPE_CLEAR: BeginningAccessType = 2;
+pub(crate) const BEGINNING_ACCESS_TYPE_NO_ACCESS: BeginningAccessType = 3;
+
+pub(crate) type EndingAccessType = UINT;
+pub(crate) const ENDING_ACCESS_TYPE_DISCARD: EndingAccessType = 0;
+pub(crate) const ENDING_ACCESS_TYPE_PRESERVE: EndingAccessType = 1;
+pub(crate) const ENDING_ACCESS_TYPE_RESOLVE: EndingAccessType = 2;
+pub(crate) const ENDING_ACCESS_TYPE_NO_ACCESS: EndingAccessType = 3;
+
+pub(crate) type ResolveMode = UINT;
+pub(crate) const RESOLVE_MODE_AVERAGE: ResolveMode = 3;
+
+pub(crate) type RenderPassFlags = UINT;
+pub(crate) const RENDER_PASS_FLAG_NONE: RenderPassFlags = 0;
+pub(crate) const RENDER_PASS_FLAG_SUSPENDING_PASS: RenderPassFlags = 0x2;
+pub(crate) const RENDER_PASS_FLAG_RESUMING_PASS: RenderPassFlags = 0x4;
+
+bitflags! {
+    /// Split of a render pass across multiple command buffers.
+    ///
+    /// A suspended render pass must be resumed by the next command buffer
+    /// in the same submission.
+    pub struct RenderPassSplit: u32 {
+        /// The render pass is continued by a later command buffer.
+        const SUSPENDING = 0x1;
+        /// The render pass continues a pass suspended by an earlier command buffer.
+        const RESUMING = 0x2;
+    }
+}
+
+impl RenderPassSplit {
+    pub(crate) fn flags(&self) -> RenderPassFlags {
+        let mut flags = RENDER_PASS_FLAG_NONE;
+        if self.contains(RenderPassSplit::SUSPENDING) {
+            flags |= RENDER_PASS_FLAG_SUSPENDING_PASS;
+        }
+        if self.contains(RenderPassSplit::RESUMING) {
+            flags |= RENDER_PASS_FLAG_RESUMING_PASS;
+        }
+        flags
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct BeginningAccess {
+    pub Type: BeginningAccessType,
+    pub ClearValue: d3d12::D3D12_CLEAR_VALUE,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct ResolveSubresourceParameters {
+    pub SrcSubresource: UINT,
+    pub DstSubresource: UINT,
+    pub DstX: UINT,
+    pub DstY: UINT,
+    pub SrcRect: d3d12::D3D12_RECT,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct EndingAccessResolveParameters {
+    pub pSrcResource: *mut d3d12::ID3D12Resource,
+    pub pDstResource: *mut d3d12::ID3D12Resource,
+    pub SubresourceCount: UINT,
+    pub pSubresourceParameters: *const ResolveSubresourceParameters,
+    pub Format: DXGI_FORMAT,
+    pub ResolveMode: ResolveMode,
+    pub PreserveResolveSource: BOOL,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct EndingAccess {
+    pub Type: EndingAccessType,
+    pub Resolve: EndingAccessResolveParameters,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct RenderTargetDesc {
+    pub cpuDescriptor: d3d12::D3D12_CPU_DESCRIPTOR_HANDLE,
+    pub BeginningAccess: BeginningAccess,
+    pub EndingAccess: EndingAccess,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct DepthStencilDesc {
+    pub cpuDescriptor: d3d12::D3D12_CPU_DESCRIPTOR_HANDLE,
+    pub DepthBeginningAccess: BeginningAccess,
+    pub StencilBeginningAccess: BeginningAccess,
+    pub DepthEndingAccess: EndingAccess,
+    pub StencilEndingAccess: EndingAccess,
+}
+
+#[repr(C)]
+#[allow(dead_code)]
+pub(crate) struct GraphicsCommandList4Vtbl {
+    parent: d3d12::ID3D12GraphicsCommandList1Vtbl,
+    // `ID3D12GraphicsCommandList2::WriteBufferImmediate`
+    _write_buffer_immediate: usize,
+    // `ID3D12GraphicsCommandList3::SetProtectedResourceSession`
+    _set_protected_resource_session: usize,
+    BeginRenderPass: unsafe extern "system" fn(
+        This: *mut GraphicsCommandList4,
+        NumRenderTargets: UINT,
+        pRenderTargets: *const RenderTargetDesc,
+        pDepthStencil: *const DepthStencilDesc,
+        Flags: RenderPassFlags,
+    ),
+    EndRenderPass: unsafe extern "system" fn(This: *mut GraphicsCommandList4),
+}
+
+#[repr(C)]
+pub(crate) struct GraphicsCommandList4 {
+    lpVtbl: *const GraphicsCommandList4Vtbl,
+}
+
+impl Interface for GraphicsCommandList4 {
+    fn uuidof() -> GUID {
+        GUID {
+            Data1: 0x8754318e,
+            Data2: 0xd3a9,
+            Data3: 0x4541,
+            Data4: [0x98, 0xcf, 0x64, 0x5b, 0x50, 0xdc, 0x48, 0x74],
+        }
+    }
+}
+
+impl GraphicsCommandList4 {
+    pub(crate) unsafe fn BeginRenderPass(
+        &self,
+        render_targets: &[RenderTargetDesc],
+        depth_stencil: Option<&DepthStencilDesc>,
+        flags: RenderPassFlags,
+    ) {
+        ((*self.lpVtbl).BeginRenderPass)(
+            self as *const _ as *mut _,
+            render_targets.len() as _,
+            render_targets.as_ptr(),
+            depth_stencil.map_or(0 as *const _, |ds| ds as *const _),
+            flags,
+        )
+    }
+
+    pub(crate) unsafe fn EndRenderPass(&self) {
+        ((*self.lpVtbl).EndRenderPass)(self as *const _ as *mut _)
+    }
+}
+
+fn map_beginning_access(op: pass::AttachmentLoadOp) -> BeginningAccessType {
+    match op {
+        pass::AttachmentLoadOp::Load => BEGINNING_ACCESS_TYPE_PRESERVE,
+        pass::AttachmentLoadOp::Clear => BEGINNING_ACCESS_TYPE_CLEAR,
+        pass::AttachmentLoadOp::DontCare => BEGINNING_ACCESS_TYPE_DISCARD,
+    }
+}
+
+fn map_ending_access(op: pass::AttachmentStoreOp) -> EndingAccessType {
+    match op {
+        pass::AttachmentStoreOp::Store => ENDING_ACCESS_TYPE_PRESERVE,
+        pass::AttachmentStoreOp::DontCare => ENDING_ACCESS_TYPE_DISCARD,
+    }
+}
+
+/// Access of an attachment at the beginning of a subpass.
+///
+/// Only the first subpass using an attachment loads or clears it. Clears without a
+/// `clear_value` are recorded explicitly, e.g. if the render area doesn't cover
+/// the whole attachment.
+pub(crate) fn beginning_access(
+    first_use: bool,
+    op: pass::AttachmentLoadOp,
+    clear_value: Option<d3d12::D3D12_CLEAR_VALUE>,
+) -> BeginningAccess {
+    let ty = match (first_use, op, clear_value) {
+        (false, _, _) => BEGINNING_ACCESS_TYPE_PRESERVE,
+        (true, pass::AttachmentLoadOp::Clear, None) => BEGINNING_ACCESS_TYPE_PRESERVE,
+        (true, op, _) => map_beginning_access(op),
+    };
+    BeginningAccess {
+        Type: ty,
+        ClearValue: clear_value.unwrap_or_else(|| unsafe { mem::zeroed() }),
+    }
+}
+
+/// Access of an attachment at the end of a subpass.
+///
+/// Attachments required after the subpass, by later subpasses or resolves, are preserved
+/// regardless of the store operation.
+pub(crate) fn ending_access(preserve: bool, op: pass::AttachmentStoreOp) -> EndingAccess {
+    EndingAccess {
+        Type: if preserve {
+            ENDING_ACCESS_TYPE_PRESERVE
+        } else {
+            map_ending_access(op)
+        },
+        Resolve: unsafe { mem::zeroed() },
+    }
+}
+
+/// Access of a color attachment resolved at the end of a subpass.
+///
+/// `subresources` must outlive the `BeginRenderPass` call. The source stays valid
+/// afterwards only if `preserve` is set.
+pub(crate) fn resolve_access(
+    src: *mut d3d12::ID3D12Resource,
+    dst: *mut d3d12::ID3D12Resource,
+    format: DXGI_FORMAT,
+    subresources: &[ResolveSubresourceParameters],
+    preserve: bool,
+) -> EndingAccess {
+    EndingAccess {
+        Type: ENDING_ACCESS_TYPE_RESOLVE,
+        Resolve: EndingAccessResolveParameters {
+            pSrcResource: src,
+            pDstResource: dst,
+            SubresourceCount: subresources.len() as _,
+            pSubresourceParameters: subresources.as_ptr(),
+            Format: format,
+            ResolveMode: RESOLVE_MODE_AVERAGE,
+            PreserveResolveSource: preserve as BOOL,
+        },
+    }
+}
+
+/// Access of an aspect not present in the depth-stencil format.
+pub(crate) fn no_access() -> (BeginningAccess, EndingAccess) {
+    unsafe {
+        (
+            BeginningAccess {
+                Type: BEGINNING_ACCESS_TYPE_NO_ACCESS,
+                ClearValue: mem::zeroed(),
+            },
+            EndingAccess {
+                Type: ENDING_ACCESS_TYPE_NO_ACCESS,
+                Resolve: mem::zeroed(),
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_flags() {
+        assert_eq!(RenderPassSplit::empty().flags(), RENDER_PASS_FLAG_NONE);
+        assert_eq!(
+            RenderPassSplit::SUSPENDING.flags(),
+            RENDER_PASS_FLAG_SUSPENDING_PASS
+        );
+        assert_eq!(
+            (RenderPassSplit::SUSPENDING | RenderPassSplit::RESUMING).flags(),
+            RENDER_PASS_FLAG_SUSPENDING_PASS | RENDER_PASS_FLAG_RESUMING_PASS
+        );
+    }
+
+    #[test]
+    fn test_attachment_access() {
+        let clear_value: d3d12::D3D12_CLEAR_VALUE = unsafe { mem::zeroed() };
+        let access = beginning_access(true, pass::AttachmentLoadOp::Clear, Some(clear_value));
+        assert_eq!(access.Type, BEGINNING_ACCESS_TYPE_CLEAR);
+        // Partial clears are recorded explicitly.
+        let access = beginning_access(true, pass::AttachmentLoadOp::Clear, None);
+        assert_eq!(access.Type, BEGINNING_ACCESS_TYPE_PRESERVE);
+        let access = beginning_access(true, pass::AttachmentLoadOp::DontCare, None);
+        assert_eq!(access.Type, BEGINNING_ACCESS_TYPE_DISCARD);
+        // Later subpasses preserve the contents.
+        let access = beginning_access(false, pass::AttachmentLoadOp::Clear, Some(clear_value));
+        assert_eq!(access.Type, BEGINNING_ACCESS_TYPE_PRESERVE);
+
+        let access = ending_access(false, pass::AttachmentStoreOp::DontCare);
+        assert_eq!(access.Type, ENDING_ACCESS_TYPE_DISCARD);
+        let access = ending_access(true, pass::AttachmentStoreOp::DontCare);
+        assert_eq!(access.Type, ENDING_ACCESS_TYPE_PRESERVE);
+        let access = ending_access(false, pass::AttachmentStoreOp::Store);
+        assert_eq!(access.Type, ENDING_ACCESS_TYPE_PRESERVE);
+    }
+
+    #[test]
+    fn test_attachment_resolve() {
+        use winapi::shared::dxgiformat::DXGI_FORMAT_R8G8B8A8_UNORM;
+
+        let rect = d3d12::D3D12_RECT {
+            left: 0,
+            top: 0,
+            right: 64,
+            bottom: 32,
+        };
+        // 4x MSAA attachment with two layers resolved into a single sampled image.
+        let subresources = (0 .. 2)
+            .map(|layer| ResolveSubresourceParameters {
+                SrcSubresource: layer,
+                DstSubresource: layer,
+                DstX: rect.left as _,
+                DstY: rect.top as _,
+                SrcRect: rect,
+            })
+            .collect::<Vec<_>>();
+        let access = resolve_access(
+            0 as *mut _,
+            0 as *mut _,
+            DXGI_FORMAT_R8G8B8A8_UNORM,
+            &subresources,
+            false,
+        );
+        assert_eq!(access.Type, ENDING_ACCESS_TYPE_RESOLVE);
+        assert_eq!(access.Resolve.SubresourceCount, 2);
+        assert_eq!(access.Resolve.pSubresourceParameters, subresources.as_ptr());
+        assert_eq!(access.Resolve.Format, DXGI_FORMAT_R8G8B8A8_UNORM);
+        assert_eq!(access.Resolve.ResolveMode, RESOLVE_MODE_AVERAGE);
+        assert_eq!(access.Resolve.PreserveResolveSource, 0);
+
+        let access = resolve_access(
+            0 as *mut _,
+            0 as *mut _,
+            DXGI_FORMAT_R8G8B8A8_UNORM,
+            &subresources,
+            true,
+        );
+        assert_eq!(access.Resolve.PreserveResolveSource, 1);
+    }
+}
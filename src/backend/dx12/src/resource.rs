@@ -1,23 +1,35 @@
 use winapi::shared::dxgiformat::DXGI_FORMAT;
 use winapi::shared::minwindef::UINT;
-use winapi::um::d3d12;
+use winapi::shared::winerror;
+use winapi::um::{d3d12, handleapi, synchapi, winbase};
 
-use hal::{buffer, format, image, memory, pass, pso, DescriptorPool as HalDescriptorPool};
+use hal::{self, buffer, format, image, memory, pass, pso, DescriptorPool as HalDescriptorPool};
 use native::{self, query};
 use range_alloc::RangeAllocator;
 use root_constants::RootConstant;
-use {Backend, MAX_VERTEX_BUFFERS};
+use {conv, Backend, CommandQueue, MAX_VERTEX_BUFFERS, MIN_BUFFER_COPY_OFFSET_ALIGNMENT};
 
-use std::collections::BTreeMap;
+use std::cell::Cell;
+use std::cmp;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::hash::Hasher;
+use std::mem;
 use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 
 // ShaderModule is either a precompiled if the source comes from HLSL or
 // the SPIR-V module doesn't contain specialization constants or push constants
 // because they need to be adjusted on pipeline creation.
-#[derive(Debug, Hash)]
+#[derive(Debug)]
 pub enum ShaderModule {
     Compiled(BTreeMap<String, native::Blob>),
-    Spirv(Vec<u32>),
+    Spirv {
+        raw: Vec<u32>,
+        // Compiled entry points, keyed by a hash of the entry point, specialization and
+        // the pipeline layout state affecting the translation. Owned by the module.
+        translations: Mutex<HashMap<u64, native::Blob>>,
+    },
 }
 unsafe impl Send for ShaderModule {}
 unsafe impl Sync for ShaderModule {}
@@ -60,6 +72,8 @@ pub struct SubpassDesc {
     pub(crate) resolve_attachments: Vec<pass::AttachmentRef>,
     pub(crate) pre_barriers: Vec<BarrierDesc>,
     pub(crate) post_barriers: Vec<BarrierDesc>,
+    // Shader accesses of previous subpasses need a global UAV barrier before this subpass.
+    pub(crate) uav_barrier: bool,
 }
 
 impl SubpassDesc {
@@ -104,6 +118,10 @@ pub struct GraphicsPipeline {
     pub(crate) constants: Vec<RootConstant>,
     pub(crate) vertex_bindings: [Option<VertexBinding>; MAX_VERTEX_BUFFERS],
     pub(crate) baked_states: pso::BakedStates,
+    // Whether any color target blends with the blend constants.
+    pub(crate) uses_blend_constants: bool,
+    // Whether the depth bias is set on the command list.
+    pub(crate) dynamic_depth_bias: bool,
 }
 unsafe impl Send for GraphicsPipeline {}
 unsafe impl Sync for GraphicsPipeline {}
@@ -129,6 +147,26 @@ bitflags! {
 pub const SRV_CBV_UAV: SetTableTypes = SetTableTypes::SRV_CBV_UAV;
 pub const SAMPLERS: SetTableTypes = SetTableTypes::SAMPLERS;
 
+bitflags! {
+    /// Flags of descriptor set layout bindings.
+    ///
+    /// See `Device::create_descriptor_set_layout_with_flags`.
+    pub struct DescriptorBindingFlags: u8 {
+        /// Descriptors may be written after the set has been bound in a command buffer.
+        const UPDATE_AFTER_BIND = 0x1;
+        /// Descriptors which aren't accessed by shaders don't need to be valid.
+        const PARTIALLY_BOUND = 0x2;
+        /// Shaders see the binding as unbounded array, `count` is the number of
+        /// descriptors allocated for each set.
+        const VARIABLE_DESCRIPTOR_COUNT = 0x4;
+        /// Neither the descriptors nor the data they reference change once the set is bound,
+        /// until the command buffer completed execution.
+        const DATA_STATIC = 0x8;
+        /// The referenced data doesn't change while the set is bound during execution.
+        const DATA_STATIC_WHILE_SET_AT_EXECUTE = 0x10;
+    }
+}
+
 #[derive(Debug, Hash)]
 pub struct PipelineLayout {
     pub(crate) raw: native::RootSignature,
@@ -162,8 +200,12 @@ pub struct BufferUnbound {
 pub struct BufferBound {
     pub(crate) resource: native::Resource,
     pub(crate) requirements: memory::Requirements,
+    pub(crate) usage: buffer::Usage,
     #[derivative(Debug = "ignore")]
     pub(crate) clear_uav: Option<native::CpuDescriptor>,
+    // Memory occupied by placed buffers.
+    #[derivative(Debug = "ignore")]
+    pub(crate) region: Option<MemoryRegion>,
 }
 
 unsafe impl Send for BufferBound {}
@@ -195,6 +237,9 @@ impl Buffer {
 #[derive(Copy, Clone, Derivative)]
 #[derivative(Debug)]
 pub struct BufferView {
+    // Viewed buffer, required for validating its state when bound in a descriptor set.
+    #[derivative(Debug = "ignore")]
+    pub(crate) resource: native::Resource,
     // Descriptor handle for uniform texel buffers.
     #[derivative(Debug = "ignore")]
     pub(crate) handle_srv: native::CpuDescriptor,
@@ -209,6 +254,10 @@ unsafe impl Sync for BufferView {}
 pub enum Place {
     SwapChain,
     Heap { raw: native::Heap, offset: u64 },
+    // Committed resource in dedicated memory.
+    Committed,
+    // Reserved resource of a sparse image, bound to memory per tile.
+    Reserved,
 }
 
 #[derive(Clone, Derivative)]
@@ -217,7 +266,11 @@ pub struct ImageBound {
     pub(crate) resource: native::Resource,
     #[derivative(Debug = "ignore")]
     pub(crate) place: Place,
+    // Memory occupied by placed images.
+    #[derivative(Debug = "ignore")]
+    pub(crate) region: Option<MemoryRegion>,
     pub(crate) surface_type: format::SurfaceType,
+    pub(crate) format: format::Format,
     pub(crate) kind: image::Kind,
     pub(crate) usage: image::Usage,
     pub(crate) default_view_format: Option<DXGI_FORMAT>,
@@ -234,6 +287,8 @@ pub struct ImageBound {
     #[derivative(Debug = "ignore")]
     pub(crate) clear_sv: Vec<native::CpuDescriptor>,
     pub(crate) requirements: memory::Requirements,
+    // State of the resource before the first transition, assumed for `Layout::Undefined`.
+    pub(crate) initial_state: d3d12::D3D12_RESOURCE_STATES,
 }
 
 unsafe impl Send for ImageBound {}
@@ -254,6 +309,25 @@ impl ImageBound {
             + (layer * self.descriptor.MipLevels as UINT)
             + (plane * self.descriptor.MipLevels as UINT * self.kind.num_layers() as UINT)
     }
+
+    /// Get the plane slice, footprint format and texel block size of the surface
+    /// copied for `aspects`.
+    ///
+    /// Planes of planar images are copied as plain color surfaces of their own format.
+    pub fn copy_layout(&self, aspects: format::Aspects) -> (UINT, DXGI_FORMAT, u8) {
+        match conv::map_planar_layout(self.surface_type, aspects) {
+            Some(layout) => layout,
+            None => (0, self.descriptor.Format, self.bytes_per_block),
+        }
+    }
+
+    /// Required alignment of buffer offsets in copies between a buffer and `aspects`
+    /// of this image.
+    pub fn buffer_copy_alignment(&self, aspects: format::Aspects) -> buffer::Offset {
+        let (_, _, bytes_per_block) = self.copy_layout(aspects);
+        // Both alignments are powers of two.
+        cmp::max(MIN_BUFFER_COPY_OFFSET_ALIGNMENT, bytes_per_block as _)
+    }
 }
 
 #[derive(Copy, Clone, Derivative)]
@@ -319,6 +393,23 @@ impl Image {
             Image::Unbound(i) => i.calc_subresource(mip_level, layer, plane),
         }
     }
+
+    /// Required alignment of buffer offsets in copies between a buffer and `aspects`
+    /// of this image.
+    ///
+    /// This is `Limits::min_buffer_copy_offset_alignment` rounded up to the texel block
+    /// size of the copied surface. Copies not matching the optimal alignments of the
+    /// device limits are still valid but split into multiple copy operations.
+    pub fn buffer_copy_alignment(&self, aspects: format::Aspects) -> buffer::Offset {
+        match self {
+            Image::Bound(i) => i.buffer_copy_alignment(aspects),
+            Image::Unbound(i) => {
+                let bytes_per_block = conv::map_planar_layout(i.format.base_format().0, aspects)
+                    .map_or(i.bytes_per_block, |(_, _, bytes_per_block)| bytes_per_block);
+                cmp::max(MIN_BUFFER_COPY_OFFSET_ALIGNMENT, bytes_per_block as _)
+            }
+        }
+    }
 }
 
 #[derive(Copy, Derivative, Clone)]
@@ -333,9 +424,14 @@ pub struct ImageView {
     #[derivative(Debug = "ignore")]
     pub(crate) handle_dsv: Option<native::CpuDescriptor>,
     #[derivative(Debug = "ignore")]
+    pub(crate) handle_dsv_read_only: Option<native::CpuDescriptor>,
+    #[derivative(Debug = "ignore")]
     pub(crate) handle_uav: Option<native::CpuDescriptor>,
     // Required for attachment resolves.
     pub(crate) dxgi_format: DXGI_FORMAT,
+    // Format used for resolving into the view, typed resources can only be resolved
+    // with their own format.
+    pub(crate) resolve_format: DXGI_FORMAT,
     pub(crate) num_levels: image::Level,
     pub(crate) mip_levels: (image::Level, image::Level),
     pub(crate) layers: (image::Layer, image::Layer),
@@ -355,35 +451,181 @@ impl ImageView {
 pub struct Sampler {
     #[derivative(Debug = "ignore")]
     pub(crate) handle: native::CpuDescriptor,
+    // Sampler state, identifying the shared descriptor.
+    pub(crate) info: image::SamplerInfo,
 }
 
 #[derive(Debug)]
 pub struct DescriptorSetLayout {
     pub(crate) bindings: Vec<pso::DescriptorSetLayoutBinding>,
+    // Immutable samplers baked into the root signature, indexed by their register.
+    // The corresponding bindings are not part of `bindings`.
+    pub(crate) static_samplers: Vec<(u32, image::SamplerInfo)>,
+    // Flags of each of `bindings`, hints for the descriptor range flags of the root signature.
+    pub(crate) binding_flags: Vec<DescriptorBindingFlags>,
+    // The last binding is an unbounded descriptor range in the root signature.
+    pub(crate) variable_count: bool,
 }
 
+// D3D12 fence values can't decrease, fences are signalled once the completed value
+// reaches `target`. Resetting a fence advances the target instead.
 #[derive(Debug)]
 pub struct Fence {
     pub(crate) raw: native::Fence,
+    // Value signalled by the next submission, only changed on reset which requires
+    // external synchronization of the fence.
+    pub(crate) target: Cell<u64>,
 }
 unsafe impl Send for Fence {}
 unsafe impl Sync for Fence {}
 
+impl Fence {
+    // Completed value of fences on a removed device.
+    const LOST_VALUE: u64 = !0;
+
+    pub(crate) fn status(completed: u64, target: u64) -> Result<bool, hal::device::DeviceLost> {
+        match completed {
+            Fence::LOST_VALUE => Err(hal::device::DeviceLost),
+            value => Ok(value >= target),
+        }
+    }
+
+    // Target of a fence after a reset, fences which are still pending stay untouched.
+    pub(crate) fn reset_target(completed: u64, target: u64) -> u64 {
+        if completed >= target {
+            completed + 1
+        } else {
+            target
+        }
+    }
+
+    pub(crate) unsafe fn is_signalled(&self) -> Result<bool, hal::device::DeviceLost> {
+        Fence::status(self.raw.GetCompletedValue(), self.target.get())
+    }
+
+    pub(crate) unsafe fn reset(&self) {
+        let completed = self.raw.GetCompletedValue();
+        self.target.set(Fence::reset_target(completed, self.target.get()));
+    }
+}
+
+// Events are backed by a fence, where a value of 1 denotes the set state and 0 the
+// reset state. Fences can only be signaled or waited on by the queue itself, so
+// events recorded into a command buffer are resolved on submission, at the boundaries
+// of the command list which recorded them (see `CommandBuffer::event_signals`).
+//
+// Setting and resetting an event in the same command buffer only leaves the final
+// state visible, waits on other queues may not observe the intermediate set.
+#[derive(Debug)]
+pub struct Event {
+    pub(crate) raw: native::Fence,
+}
+unsafe impl Send for Event {}
+unsafe impl Sync for Event {}
+
+// Semaphores are backed by a fence, which is signalled with a monotonically
+// increasing value. Waits are issued for the last signalled value, so a semaphore
+// never needs to be reset and can be signalled again while waits are pending.
 #[derive(Debug)]
 pub struct Semaphore {
     pub(crate) raw: native::Fence,
+    // Last value signalled or scheduled to be signalled.
+    pub(crate) value: AtomicUsize,
+}
+
+impl Semaphore {
+    /// Reserve the value for the next signal operation.
+    pub(crate) fn next_signal_value(&self) -> u64 {
+        self.value.fetch_add(1, Ordering::AcqRel) as u64 + 1
+    }
+
+    /// Value waited for by the next wait operation.
+    pub(crate) fn wait_value(&self) -> u64 {
+        self.value.load(Ordering::Acquire) as u64
+    }
 }
 
 unsafe impl Send for Semaphore {}
 unsafe impl Sync for Semaphore {}
 
-#[derive(Debug)]
+#[derive(Derivative)]
+#[derivative(Debug)]
 pub struct Memory {
     pub(crate) heap: native::Heap,
     pub(crate) type_id: usize,
     pub(crate) size: u64,
     // Buffer containing the whole memory for mapping (only for host visible heaps)
     pub(crate) resource: Option<native::Resource>,
+    #[derivative(Debug = "ignore")]
+    pub(crate) placements: Arc<Placements>,
+    // Heap properties of committed resources bound to dedicated memory,
+    // which has no heap of its own.
+    #[derivative(Debug = "ignore")]
+    pub(crate) dedicated: Option<d3d12::D3D12_HEAP_PROPERTIES>,
+}
+
+impl Memory {
+    // Pageable object backing the memory, the heap or the committed resource
+    // bound to dedicated memory.
+    pub(crate) fn pageable(&self) -> Option<*mut d3d12::ID3D12Pageable> {
+        if !self.heap.is_null() {
+            return Some(self.heap.as_mut_ptr() as *mut _);
+        }
+        self.placements
+            .lock()
+            .unwrap()
+            .first()
+            .map(|&(_, resource)| resource.as_mut_ptr() as *mut _)
+    }
+}
+
+// Resources placed into a memory object with the occupied byte range.
+pub(crate) type Placements = Mutex<Vec<(Range<u64>, native::Resource)>>;
+
+/// Byte range of a memory object occupied by a placed resource.
+#[derive(Clone)]
+pub struct MemoryRegion {
+    pub(crate) heap: native::Heap,
+    pub(crate) range: Range<u64>,
+    pub(crate) placements: Arc<Placements>,
+}
+
+impl MemoryRegion {
+    pub(crate) fn new(memory: &Memory, offset: u64, size: u64, resource: native::Resource) -> Self {
+        let range = offset .. offset + size;
+        memory
+            .placements
+            .lock()
+            .unwrap()
+            .push((range.clone(), resource));
+        MemoryRegion {
+            heap: memory.heap,
+            range,
+            placements: Arc::clone(&memory.placements),
+        }
+    }
+
+    // Check if any other resource placed in the memory overlaps with `resource`.
+    pub(crate) fn is_aliased(&self, resource: native::Resource) -> bool {
+        self.placements
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|&(ref range, other)| {
+                other.as_mut_ptr() != resource.as_mut_ptr() && ranges_overlap(range, &self.range)
+            })
+    }
+
+    pub(crate) fn release(&self, resource: native::Resource) {
+        self.placements
+            .lock()
+            .unwrap()
+            .retain(|&(_, other)| other.as_mut_ptr() != resource.as_mut_ptr());
+    }
+}
+
+pub(crate) fn ranges_overlap(a: &Range<u64>, b: &Range<u64>) -> bool {
+    a.start < b.end && b.start < a.end
 }
 
 unsafe impl Send for Memory {}
@@ -409,7 +651,8 @@ impl DescriptorRange {
 pub struct DescriptorBindingInfo {
     pub(crate) count: u64,
     pub(crate) view_range: Option<DescriptorRange>,
-    pub(crate) sampler_range: Option<DescriptorRange>,
+    // Index of the first sampler of the binding in the sampler table of the set.
+    pub(crate) sampler_offset: Option<u64>,
     pub(crate) is_uav: bool,
 }
 
@@ -424,10 +667,21 @@ pub struct DescriptorSet {
 
     pub(crate) binding_infos: Vec<DescriptorBindingInfo>,
 
-    #[derivative(Debug = "ignore")]
-    pub(crate) first_gpu_sampler: Option<native::GpuDescriptor>,
+    // `None` if the layout doesn't have any sampler bindings.
+    pub(crate) sampler_table: Option<SamplerTable>,
     #[derivative(Debug = "ignore")]
     pub(crate) first_gpu_view: Option<native::GpuDescriptor>,
+
+    // Bumped on every write or copy into the set, required for skipping redundant rebinds
+    // of the set at command buffers.
+    pub(crate) generation: AtomicUsize,
+
+    // Buffers written into the set and the states required by their descriptors, keyed by
+    // binding and array index. Only tracked in debug builds for validating buffer states
+    // on binding the set.
+    #[derivative(Debug = "ignore")]
+    pub(crate) buffer_states:
+        Mutex<HashMap<(usize, u64), (*mut d3d12::ID3D12Resource, d3d12::D3D12_RESOURCE_STATES)>>,
 }
 
 // TODO: is this really safe?
@@ -462,11 +716,32 @@ pub struct DescriptorHeap {
     pub(crate) raw: native::DescriptorHeap,
     pub(crate) handle_size: u64,
     pub(crate) total_handles: u64,
+    // Number of handles handed out to descriptor pools.
+    pub(crate) allocated_handles: u64,
     pub(crate) start: DualHandle,
     pub(crate) range_allocator: RangeAllocator<u64>,
 }
 
 impl DescriptorHeap {
+    /// Allocate a range of `count` handles, fails if the heap can't fit them.
+    ///
+    /// The capacity of shader-visible heaps is limited, in particular sampler heaps
+    /// can hold at most 2048 samplers (`D3D12_MAX_SHADER_VISIBLE_SAMPLER_HEAP_SIZE`).
+    /// Static samplers declared in a root signature don't count against this limit.
+    pub(crate) fn allocate_range(&mut self, count: u64) -> Option<Range<u64>> {
+        if self.allocated_handles + count > self.total_handles {
+            return None;
+        }
+        let range = self.range_allocator.allocate_range(count).ok()?;
+        self.allocated_handles += count;
+        Some(range)
+    }
+
+    pub(crate) fn free_range(&mut self, range: Range<u64>) {
+        self.allocated_handles -= range.end - range.start;
+        self.range_allocator.free_range(range);
+    }
+
     pub(crate) fn at(&self, index: u64, size: u64) -> DualHandle {
         assert!(index < self.total_handles);
         DualHandle {
@@ -485,11 +760,262 @@ impl DescriptorHeap {
     }
 }
 
+/// Shader-visible sampler heap, holding the sampler tables of all descriptor sets.
+///
+/// Tables are deduplicated by the sampler states of their descriptors, so sets with
+/// identical samplers occupy the limited heap only once.
+#[derive(Debug)]
+pub struct SamplerTables {
+    pub(crate) heap: DescriptorHeap,
+    // Shared tables with the number of sets referencing them.
+    shared: HashMap<Vec<Option<SamplerKey>>, (Range<u64>, usize)>,
+}
+
+// Sampler state and CPU descriptor address of a table entry.
+//
+// The address is part of the key as freed CPU descriptors are reused by other sampler
+// states, which must not be shared with tables still referring to the old descriptor.
+type SamplerKey = (image::SamplerInfo, usize);
+
+impl SamplerTables {
+    pub(crate) fn new(heap: DescriptorHeap) -> Self {
+        SamplerTables {
+            heap,
+            shared: HashMap::new(),
+        }
+    }
+
+    // Get the shared table with the sampler states, `fill` writes the descriptors of
+    // newly allocated tables. Returns `None` if the heap is exhausted.
+    pub(crate) fn acquire<F>(
+        &mut self,
+        samplers: &[Option<SamplerKey>],
+        fill: F,
+    ) -> Option<Range<u64>>
+    where
+        F: FnOnce(&DescriptorHeap, &Range<u64>),
+    {
+        if let Some(&mut (ref range, ref mut count)) = self.shared.get_mut(samplers) {
+            *count += 1;
+            return Some(range.clone());
+        }
+        let range = self.heap.allocate_range(samplers.len() as u64)?;
+        fill(&self.heap, &range);
+        self.shared.insert(samplers.to_vec(), (range.clone(), 1));
+        Some(range)
+    }
+
+    // Release a reference to a shared table, freeing it once unused.
+    pub(crate) fn release(&mut self, samplers: &[Option<SamplerKey>]) {
+        let unused = match self.shared.get_mut(samplers) {
+            Some(&mut (_, ref mut count)) => {
+                *count -= 1;
+                *count == 0
+            }
+            None => false,
+        };
+        if unused {
+            let (range, _) = self.shared.remove(samplers).unwrap();
+            self.heap.free_range(range);
+        }
+    }
+
+    pub(crate) fn num_shared(&self) -> usize {
+        self.shared.len()
+    }
+}
+
+// Sampler descriptors of a descriptor set.
+//
+// Writes only update the sampler states, the set switches to the shared table matching
+// them once all writes are done. Sets with `UPDATE_AFTER_BIND` sampler bindings own
+// their table instead and are written in place, as they may be updated while bound.
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct SamplerTable {
+    #[derivative(Debug = "ignore")]
+    tables: Arc<Mutex<SamplerTables>>,
+    #[derivative(Debug = "ignore")]
+    start: DualHandle,
+    handle_size: u64,
+    owned: bool,
+    #[derivative(Debug = "ignore")]
+    state: Mutex<SamplerTableState>,
+}
+
+struct SamplerTableState {
+    samplers: Vec<Option<(image::SamplerInfo, native::CpuDescriptor)>>,
+    // Table in the heap, with the sampler states it has been acquired for if shared.
+    range: Option<Range<u64>>,
+    key: Vec<Option<SamplerKey>>,
+}
+
+impl SamplerTable {
+    fn new(tables: &Arc<Mutex<SamplerTables>>, count: u64, owned: bool) -> Option<Self> {
+        let mut guard = tables.lock().unwrap();
+        let range = if owned {
+            Some(guard.heap.allocate_range(count)?)
+        } else {
+            None
+        };
+        Some(SamplerTable {
+            tables: Arc::clone(tables),
+            start: guard.heap.start,
+            handle_size: guard.heap.handle_size,
+            owned,
+            state: Mutex::new(SamplerTableState {
+                samplers: vec![None; count as usize],
+                range,
+                key: Vec::new(),
+            }),
+        })
+    }
+
+    pub(crate) fn len(&self) -> u64 {
+        self.state.lock().unwrap().samplers.len() as u64
+    }
+
+    // Start of the table in the heap, unwritten shared tables point at the heap start.
+    pub(crate) fn gpu_start(&self) -> native::GpuDescriptor {
+        let state = self.state.lock().unwrap();
+        let index = state.range.as_ref().map_or(0, |range| range.start);
+        native::GpuDescriptor {
+            ptr: self.start.gpu.ptr + self.handle_size * index,
+        }
+    }
+
+    fn cpu_at(&self, index: u64) -> native::CpuDescriptor {
+        native::CpuDescriptor {
+            ptr: self.start.cpu.ptr + (self.handle_size * index) as usize,
+        }
+    }
+
+    pub(crate) unsafe fn write(
+        &self,
+        device: native::Device,
+        index: u64,
+        sampler: Option<(image::SamplerInfo, native::CpuDescriptor)>,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        if self.owned {
+            if let (Some(&(_, handle)), Some(range)) = (sampler.as_ref(), state.range.as_ref()) {
+                device.CopyDescriptorsSimple(
+                    1,
+                    self.cpu_at(range.start + index),
+                    handle,
+                    d3d12::D3D12_DESCRIPTOR_HEAP_TYPE_SAMPLER,
+                );
+            }
+        }
+        state.samplers[index as usize] = sampler;
+    }
+
+    pub(crate) fn get(&self, index: u64) -> Option<(image::SamplerInfo, native::CpuDescriptor)> {
+        self.state.lock().unwrap().samplers[index as usize].clone()
+    }
+
+    // Switch a shared table to the table matching the written sampler states.
+    pub(crate) unsafe fn update(&self, device: native::Device) {
+        if self.owned {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        let key = state
+            .samplers
+            .iter()
+            .map(|sampler| {
+                sampler
+                    .as_ref()
+                    .map(|&(ref info, handle)| (info.clone(), handle.ptr))
+            })
+            .collect::<Vec<_>>();
+        if state.range.is_some() && state.key == key {
+            return;
+        }
+
+        let mut tables = self.tables.lock().unwrap();
+        if state.range.take().is_some() {
+            tables.release(&state.key);
+        }
+        let range = {
+            let samplers = &state.samplers;
+            tables.acquire(&key, |heap, range| {
+                for (i, sampler) in samplers.iter().enumerate() {
+                    if let Some(&(_, handle)) = sampler.as_ref() {
+                        device.CopyDescriptorsSimple(
+                            1,
+                            heap.at(range.start + i as u64, 1).cpu,
+                            handle,
+                            d3d12::D3D12_DESCRIPTOR_HEAP_TYPE_SAMPLER,
+                        );
+                    }
+                }
+            })
+        };
+        if range.is_none() {
+            error!("Sampler heap exhausted, unable to allocate a table of {} samplers", key.len());
+        }
+        state.range = range;
+        state.key = key;
+    }
+}
+
+impl Drop for SamplerTable {
+    fn drop(&mut self) {
+        let state = self.state.lock().unwrap();
+        if let Some(ref range) = state.range {
+            let mut tables = self.tables.lock().unwrap();
+            if self.owned {
+                tables.heap.free_range(range.clone());
+            } else {
+                tables.release(&state.key);
+            }
+        }
+    }
+}
+
+// Destroys a shader-visible descriptor heap once the last reference is dropped.
+//
+// Referenced by the device while the heap is in use for new descriptor pools and by the
+// pools with a slice of it, so heaps replaced after exhaustion live as long as their pools.
+#[derive(Debug)]
+pub(crate) struct DescriptorHeapOwner(pub(crate) native::DescriptorHeap);
+
+unsafe impl Send for DescriptorHeapOwner {}
+unsafe impl Sync for DescriptorHeapOwner {}
+
+impl Drop for DescriptorHeapOwner {
+    fn drop(&mut self) {
+        unsafe {
+            self.0.destroy();
+        }
+    }
+}
+
+/// Shader-visible heap from which descriptor pools allocate their slices.
+#[derive(Debug)]
+pub(crate) struct SharedDescriptorHeap {
+    pub(crate) heap: DescriptorHeap,
+    // `None` once the device is dropped.
+    pub(crate) owner: Option<Arc<DescriptorHeapOwner>>,
+}
+
+impl SharedDescriptorHeap {
+    pub(crate) fn new(heap: DescriptorHeap) -> Self {
+        let owner = Arc::new(DescriptorHeapOwner(heap.raw));
+        SharedDescriptorHeap {
+            heap,
+            owner: Some(owner),
+        }
+    }
+}
+
 /// Slice of an descriptor heap, which is allocated for a pool.
 /// Pools will create descriptor sets inside this slice.
 #[derive(Debug)]
 pub struct DescriptorHeapSlice {
-    pub(crate) heap: native::DescriptorHeap, // Weak reference, owned by descriptor heap.
+    pub(crate) heap: native::DescriptorHeap, // Weak reference, kept alive by `owner`.
+    pub(crate) owner: Arc<DescriptorHeapOwner>,
     pub(crate) start: DualHandle,
     pub(crate) handle_size: u64,
     pub(crate) range_allocator: RangeAllocator<u64>,
@@ -523,10 +1049,15 @@ impl DescriptorHeapSlice {
     }
 }
 
-#[derive(Debug)]
+#[derive(Derivative)]
+#[derivative(Debug)]
 pub struct DescriptorPool {
     pub(crate) heap_srv_cbv_uav: DescriptorHeapSlice,
-    pub(crate) heap_sampler: DescriptorHeapSlice,
+    #[derivative(Debug = "ignore")]
+    pub(crate) sampler_tables: Arc<Mutex<SamplerTables>>,
+    // Samplers of the pool, which only occupy the sampler heap once written.
+    pub(crate) num_samplers: u64,
+    pub(crate) allocated_samplers: u64,
     pub(crate) pools: Vec<pso::DescriptorRangeDesc>,
     pub(crate) max_size: u64,
 }
@@ -539,10 +1070,11 @@ impl HalDescriptorPool<Backend> for DescriptorPool {
         layout: &DescriptorSetLayout,
     ) -> Result<DescriptorSet, pso::AllocationError> {
         let mut binding_infos = Vec::new();
-        let mut first_gpu_sampler = None;
+        let mut num_samplers = 0;
+        let mut owned_samplers = false;
         let mut first_gpu_view = None;
 
-        for binding in &layout.bindings {
+        for (i, binding) in layout.bindings.iter().enumerate() {
             let HeapProperties {
                 has_view,
                 has_sampler,
@@ -570,33 +1102,41 @@ impl HalDescriptorPool<Backend> for DescriptorPool {
                 } else {
                     None
                 },
-                sampler_range: if has_sampler {
-                    let handle = self
-                        .heap_sampler
-                        .alloc_handles(binding.count as u64)
-                        .ok_or(pso::AllocationError::OutOfPoolMemory)?;
-                    if first_gpu_sampler.is_none() {
-                        first_gpu_sampler = Some(handle.gpu);
-                    }
-                    Some(DescriptorRange {
-                        handle,
-                        ty: binding.ty,
-                        count: binding.count as _,
-                        handle_size: self.heap_sampler.handle_size,
-                    })
+                sampler_offset: if has_sampler {
+                    let offset = num_samplers;
+                    num_samplers += binding.count as u64;
+                    Some(offset)
                 } else {
                     None
                 },
                 is_uav,
             };
+            if has_sampler {
+                owned_samplers |= layout.binding_flags[i]
+                    .contains(DescriptorBindingFlags::UPDATE_AFTER_BIND);
+            }
         }
 
+        if self.allocated_samplers + num_samplers > self.num_samplers {
+            return Err(pso::AllocationError::OutOfPoolMemory);
+        }
+        let sampler_table = if num_samplers > 0 {
+            let table = SamplerTable::new(&self.sampler_tables, num_samplers, owned_samplers)
+                .ok_or(pso::AllocationError::OutOfDeviceMemory)?;
+            Some(table)
+        } else {
+            None
+        };
+        self.allocated_samplers += num_samplers;
+
         Ok(DescriptorSet {
             heap_srv_cbv_uav: self.heap_srv_cbv_uav.heap.clone(),
-            heap_samplers: self.heap_sampler.heap.clone(),
+            heap_samplers: self.sampler_tables.lock().unwrap().heap.raw.clone(),
             binding_infos,
-            first_gpu_sampler,
+            sampler_table,
             first_gpu_view,
+            generation: AtomicUsize::new(0),
+            buffer_states: Mutex::new(HashMap::new()),
         })
     }
 
@@ -611,18 +1151,17 @@ impl HalDescriptorPool<Backend> for DescriptorPool {
                         self.heap_srv_cbv_uav.free_handles(view_range.handle);
                     }
                 }
-                if let Some(ref sampler_range) = binding_info.sampler_range {
-                    if HeapProperties::from(sampler_range.ty).has_sampler {
-                        self.heap_sampler.free_handles(sampler_range.handle);
-                    }
-                }
+            }
+            // Dropping the set releases its sampler table.
+            if let Some(ref table) = descriptor_set.sampler_table {
+                self.allocated_samplers -= table.len();
             }
         }
     }
 
     unsafe fn reset(&mut self) {
         self.heap_srv_cbv_uav.clear();
-        self.heap_sampler.clear();
+        self.allocated_samplers = 0;
     }
 }
 
@@ -663,7 +1202,581 @@ impl HeapProperties {
 pub struct QueryPool {
     pub(crate) raw: native::QueryHeap,
     pub(crate) ty: query::HeapType,
+    pub(crate) statistics: hal::query::PipelineStatistic,
+    pub(crate) count: hal::query::Id,
+    // Resolved query results, followed by the availability of each query.
+    pub(crate) readback: native::Resource,
+    // Upload buffer with the availability values written into the readback buffer.
+    pub(crate) availability: native::Resource,
+    pub(crate) completion: Arc<QueryCompletion>,
+}
+
+// Tracks the completion of submissions resolving the queries of a pool, so that
+// waiting for query results can block on a fence instead of polling the readback.
+#[derive(Debug)]
+pub(crate) struct QueryCompletion {
+    pub(crate) fence: native::Fence,
+    // Last value scheduled to be signalled on the fence.
+    value: AtomicUsize,
+    // Fence value of the last submission resolving each query, 0 if never submitted.
+    queries: Vec<AtomicUsize>,
+}
+
+impl QueryCompletion {
+    pub(crate) fn new(fence: native::Fence, count: hal::query::Id) -> Self {
+        QueryCompletion {
+            fence,
+            value: AtomicUsize::new(0),
+            queries: (0 .. count).map(|_| AtomicUsize::new(0)).collect(),
+        }
+    }
+
+    /// Reserve the fence value signalled after a submission resolving the queries.
+    pub(crate) fn submit<I>(&self, queries: I) -> u64
+    where
+        I: IntoIterator<Item = hal::query::Id>,
+    {
+        let value = self.value.fetch_add(1, Ordering::AcqRel) + 1;
+        for id in queries {
+            self.queries[id as usize].store(value, Ordering::Release);
+        }
+        value as u64
+    }
+
+    /// Fence value to wait for until the last resolve of the query has been executed.
+    pub(crate) fn query_value(&self, id: hal::query::Id) -> u64 {
+        self.queries[id as usize].load(Ordering::Acquire) as u64
+    }
+}
+
+impl Drop for QueryCompletion {
+    fn drop(&mut self) {
+        unsafe {
+            self.fence.destroy();
+        }
+    }
+}
+
+unsafe impl Send for QueryCompletion {}
+unsafe impl Sync for QueryCompletion {}
+
+// Size of the availability value of a query in the readback buffer.
+pub(crate) const QUERY_AVAILABILITY_SIZE: u64 = 4;
+
+impl QueryPool {
+    // Size of a single resolved query result in the readback buffer.
+    pub(crate) fn result_size(&self) -> u64 {
+        match self.ty {
+            query::HeapType::PipelineStatistics => {
+                mem::size_of::<d3d12::D3D12_QUERY_DATA_PIPELINE_STATISTICS>() as u64
+            }
+            _ => mem::size_of::<u64>() as u64,
+        }
+    }
+
+    pub(crate) fn result_offset(&self, id: hal::query::Id) -> u64 {
+        id as u64 * self.result_size()
+    }
+
+    pub(crate) fn availability_offset(&self, id: hal::query::Id) -> u64 {
+        self.count as u64 * self.result_size() + id as u64 * QUERY_AVAILABILITY_SIZE
+    }
+
+    pub(crate) fn readback_size(&self) -> u64 {
+        self.availability_offset(self.count)
+    }
 }
 
 unsafe impl Send for QueryPool {}
 unsafe impl Sync for QueryPool {}
+
+const PIPELINE_CACHE_MAGIC: [u8; 4] = *b"GD12";
+const PIPELINE_CACHE_VERSION: u32 = 1;
+
+/// FNV-1a hasher used for pipeline cache keys.
+///
+/// Unlike `DefaultHasher` the result is stable across runs and compiler
+/// versions, which is required for serialized caches.
+pub(crate) struct CacheKeyHasher(u64);
+
+impl Default for CacheKeyHasher {
+    fn default() -> Self {
+        CacheKeyHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for CacheKeyHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x100_0000_01b3);
+        }
+    }
+}
+
+/// Compiled shader bytecode, keyed by a hash of the SPIR-V module, entry point,
+/// specialization and the pipeline layout state affecting the translation.
+#[derive(Debug, Default)]
+pub struct PipelineCache {
+    pub(crate) shaders: Mutex<HashMap<u64, Vec<u8>>>,
+}
+
+impl PipelineCache {
+    /// Load a cache from data returned by `get_pipeline_cache_data`.
+    ///
+    /// Returns `None` if the data is malformed or was written by an incompatible version.
+    pub(crate) fn from_data(mut data: &[u8]) -> Option<Self> {
+        fn take<'a>(data: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+            if data.len() < len {
+                return None;
+            }
+            let (head, tail) = data.split_at(len);
+            *data = tail;
+            Some(head)
+        }
+        fn take_u32(data: &mut &[u8]) -> Option<u32> {
+            take(data, 4).map(|b| {
+                (0 .. 4).fold(0, |value, i| value | (b[i] as u32) << (8 * i))
+            })
+        }
+        fn take_u64(data: &mut &[u8]) -> Option<u64> {
+            let low = take_u32(data)? as u64;
+            let high = take_u32(data)? as u64;
+            Some(low | high << 32)
+        }
+
+        if take(&mut data, 4)? != PIPELINE_CACHE_MAGIC
+            || take_u32(&mut data)? != PIPELINE_CACHE_VERSION
+        {
+            return None;
+        }
+
+        let count = take_u32(&mut data)?;
+        let mut shaders = HashMap::new();
+        for _ in 0 .. count {
+            let key = take_u64(&mut data)?;
+            let len = take_u32(&mut data)? as usize;
+            shaders.insert(key, take(&mut data, len)?.to_vec());
+        }
+
+        if !data.is_empty() {
+            return None;
+        }
+
+        Some(PipelineCache {
+            shaders: Mutex::new(shaders),
+        })
+    }
+
+    /// Serialize the cache, see `from_data` for the reverse.
+    pub(crate) fn to_data(&self) -> Vec<u8> {
+        fn put_u32(data: &mut Vec<u8>, value: u32) {
+            data.extend((0 .. 4).map(|i| (value >> (8 * i)) as u8));
+        }
+
+        let shaders = self.shaders.lock().unwrap();
+        let mut data = Vec::new();
+        data.extend_from_slice(&PIPELINE_CACHE_MAGIC);
+        put_u32(&mut data, PIPELINE_CACHE_VERSION);
+        put_u32(&mut data, shaders.len() as u32);
+        for (&key, bytecode) in shaders.iter() {
+            put_u32(&mut data, key as u32);
+            put_u32(&mut data, (key >> 32) as u32);
+            put_u32(&mut data, bytecode.len() as u32);
+            data.extend_from_slice(bytecode);
+        }
+        data
+    }
+
+    pub(crate) fn merge(&self, other: &PipelineCache) {
+        if self as *const _ == other as *const _ {
+            return;
+        }
+        let other = other.shaders.lock().unwrap();
+        let mut shaders = self.shaders.lock().unwrap();
+        for (&key, bytecode) in other.iter() {
+            shaders.entry(key).or_insert_with(|| bytecode.clone());
+        }
+    }
+}
+
+/// Allocator of sub-ranges in a ring buffer.
+///
+/// Allocations are released in order, once the fence value of the submission
+/// using them has been reached.
+#[derive(Debug)]
+pub(crate) struct RingAllocator {
+    size: u64,
+    // Offset of the next allocation.
+    head: u64,
+    // Number of bytes in use, including padding, ending at `head`.
+    used: u64,
+    // Number of bytes allocated since the last submission.
+    pending: u64,
+    // Fence values and number of bytes of submitted allocations.
+    in_flight: VecDeque<(u64, u64)>,
+}
+
+impl RingAllocator {
+    pub(crate) fn new(size: u64) -> Self {
+        RingAllocator {
+            size,
+            head: 0,
+            used: 0,
+            pending: 0,
+            in_flight: VecDeque::new(),
+        }
+    }
+
+    // Allocate `size` bytes, returns `None` if the range would overlap with
+    // allocations which are still in use.
+    pub(crate) fn allocate(&mut self, size: u64, alignment: u64) -> Option<u64> {
+        let aligned = (self.head + alignment - 1) / alignment * alignment;
+        let (offset, padding) = if aligned + size <= self.size {
+            (aligned, aligned - self.head)
+        } else {
+            // Wrap around, skipping the remainder of the buffer.
+            (0, self.size - self.head)
+        };
+        if self.used + padding + size > self.size {
+            return None;
+        }
+
+        self.used += padding + size;
+        self.pending += padding + size;
+        self.head = (offset + size) % self.size;
+        Some(offset)
+    }
+
+    // Tag all allocations since the last submission with the fence value.
+    pub(crate) fn submit(&mut self, fence_value: u64) {
+        if self.pending > 0 {
+            self.in_flight.push_back((fence_value, self.pending));
+            self.pending = 0;
+        }
+    }
+
+    // Release all allocations with a fence value up to `completed_value`.
+    pub(crate) fn reclaim(&mut self, completed_value: u64) {
+        while let Some(&(fence_value, bytes)) = self.in_flight.front() {
+            if fence_value > completed_value {
+                break;
+            }
+            self.used -= bytes;
+            self.in_flight.pop_front();
+        }
+    }
+
+    // Fence value of the oldest submission still in flight.
+    pub(crate) fn oldest_in_flight(&self) -> Option<u64> {
+        self.in_flight.front().map(|&(fence_value, _)| fence_value)
+    }
+
+    pub(crate) fn last_in_flight(&self) -> Option<u64> {
+        self.in_flight.back().map(|&(fence_value, _)| fence_value)
+    }
+}
+
+/// Persistently mapped upload buffer, sub-allocated as a ring.
+///
+/// Written ranges are tagged with a fence signalled after the submissions
+/// reading them and are reused once the GPU has passed this fence.
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct UploadRing {
+    pub(crate) buffer: Buffer,
+    #[derivative(Debug = "ignore")]
+    pub(crate) mapped: *mut u8,
+    #[derivative(Debug = "ignore")]
+    pub(crate) fence: native::Fence,
+    pub(crate) fence_value: u64,
+    pub(crate) allocator: RingAllocator,
+}
+
+unsafe impl Send for UploadRing {}
+unsafe impl Sync for UploadRing {}
+
+impl UploadRing {
+    /// Upload buffer backing the ring, used as source for copy commands.
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    /// Allocate `size` bytes, returning the offset into the buffer and the mapped memory.
+    ///
+    /// Blocks while the range would overlap with ranges still in use by the GPU.
+    /// Returns `None` if the ring can't fit the allocation, even once all
+    /// submitted ranges are released.
+    pub unsafe fn allocate(&mut self, size: u64, alignment: u64) -> Option<(u64, *mut u8)> {
+        loop {
+            self.allocator.reclaim(self.fence.GetCompletedValue());
+            if let Some(offset) = self.allocator.allocate(size, alignment) {
+                return Some((offset, self.mapped.offset(offset as isize)));
+            }
+            match self.allocator.oldest_in_flight() {
+                Some(fence_value) => self.wait(fence_value),
+                None => return None,
+            }
+        }
+    }
+
+    /// Signal the fence of the ring on the queue.
+    ///
+    /// Needs to be called after submitting the command buffers reading the ranges
+    /// allocated since the last call.
+    pub unsafe fn signal(&mut self, queue: &CommandQueue) {
+        self.fence_value += 1;
+        assert_eq!(
+            winerror::S_OK,
+            queue.raw.signal(self.fence, self.fence_value)
+        );
+        self.allocator.submit(self.fence_value);
+    }
+
+    pub(crate) unsafe fn wait(&self, fence_value: u64) {
+        wait_for_fence_value(self.fence, fence_value);
+    }
+}
+
+// Block until `fence` reached `fence_value`.
+unsafe fn wait_for_fence_value(fence: native::Fence, fence_value: u64) {
+    if fence.GetCompletedValue() < fence_value {
+        let event = native::Event::create(false, false);
+        let hr = fence.set_event_on_completion(event, fence_value);
+        // Waiting on an event which is never signaled would block forever.
+        if hr == winerror::S_OK {
+            synchapi::WaitForSingleObject(event.0, winbase::INFINITE);
+        }
+        handleapi::CloseHandle(event.0);
+        assert_eq!(winerror::S_OK, hr, "Unable to wait for the fence");
+    }
+}
+
+/// Fence values of frames submitted but not yet completed.
+#[derive(Debug)]
+pub(crate) struct FrameQueue {
+    max_frames: usize,
+    in_flight: VecDeque<u64>,
+}
+
+impl FrameQueue {
+    pub(crate) fn new(max_frames: usize) -> Self {
+        assert!(max_frames > 0);
+        FrameQueue {
+            max_frames,
+            in_flight: VecDeque::with_capacity(max_frames),
+        }
+    }
+
+    // Release all frames with a fence value up to `completed_value`.
+    pub(crate) fn reclaim(&mut self, completed_value: u64) {
+        while self.in_flight.front().map_or(false, |&value| value <= completed_value) {
+            self.in_flight.pop_front();
+        }
+    }
+
+    // Fence value to wait for before another frame can begin, if the limit is reached.
+    pub(crate) fn wait_value(&self) -> Option<u64> {
+        if self.in_flight.len() >= self.max_frames {
+            self.in_flight.get(self.in_flight.len() - self.max_frames).cloned()
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn push(&mut self, fence_value: u64) {
+        self.in_flight.push_back(fence_value);
+    }
+
+    pub(crate) fn last(&self) -> Option<u64> {
+        self.in_flight.back().cloned()
+    }
+}
+
+/// Limits the number of frames in flight on the GPU.
+///
+/// `begin_frame` blocks until fewer than the configured number of frames,
+/// marked by `end_frame`, are still executing.
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct FramePacer {
+    #[derivative(Debug = "ignore")]
+    pub(crate) fence: native::Fence,
+    pub(crate) fence_value: u64,
+    pub(crate) frames: FrameQueue,
+}
+
+unsafe impl Send for FramePacer {}
+unsafe impl Sync for FramePacer {}
+
+impl FramePacer {
+    /// Wait until a new frame can be recorded and submitted.
+    pub unsafe fn begin_frame(&mut self) {
+        self.frames.reclaim(self.fence.GetCompletedValue());
+        if let Some(fence_value) = self.frames.wait_value() {
+            self.wait(fence_value);
+            self.frames.reclaim(fence_value);
+        }
+    }
+
+    /// Mark the end of the frame after all its command buffers were submitted to `queue`.
+    pub unsafe fn end_frame(&mut self, queue: &CommandQueue) {
+        self.fence_value += 1;
+        assert_eq!(
+            winerror::S_OK,
+            queue.raw.signal(self.fence, self.fence_value)
+        );
+        self.frames.push(self.fence_value);
+    }
+
+    pub(crate) unsafe fn wait(&self, fence_value: u64) {
+        wait_for_fence_value(self.fence, fence_value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        DescriptorHeap,
+        DualHandle,
+        Fence,
+        FrameQueue,
+        HeapProperties,
+        PipelineCache,
+        RingAllocator,
+    };
+    use hal::pso;
+    use native;
+    use range_alloc::RangeAllocator;
+
+    #[test]
+    fn test_pipeline_cache_roundtrip() {
+        let cache = PipelineCache::default();
+        {
+            let mut shaders = cache.shaders.lock().unwrap();
+            shaders.insert(0x0123_4567_89ab_cdef, vec![1, 2, 3]);
+            shaders.insert(42, Vec::new());
+        }
+        let data = cache.to_data();
+
+        let loaded = PipelineCache::from_data(&data).unwrap();
+        assert_eq!(*loaded.shaders.lock().unwrap(), *cache.shaders.lock().unwrap());
+    }
+
+    #[test]
+    fn test_pipeline_cache_reject_invalid() {
+        let mut data = PipelineCache::default().to_data();
+        assert!(PipelineCache::from_data(&data).is_some());
+        assert!(PipelineCache::from_data(&data[.. data.len() - 1]).is_none());
+        assert!(PipelineCache::from_data(&[]).is_none());
+
+        // Unknown version
+        data[4] += 1;
+        assert!(PipelineCache::from_data(&data).is_none());
+    }
+
+    #[test]
+    fn test_ring_allocator_stall_and_reuse() {
+        let mut ring = RingAllocator::new(256);
+        assert_eq!(ring.allocate(128, 64), Some(0));
+        ring.submit(1);
+        assert_eq!(ring.allocate(100, 64), Some(128));
+        ring.submit(2);
+
+        // Full until the first submission completed.
+        assert_eq!(ring.allocate(64, 64), None);
+        ring.reclaim(0);
+        assert_eq!(ring.allocate(64, 64), None);
+        assert_eq!(ring.oldest_in_flight(), Some(1));
+
+        // Wraps around into the released range.
+        ring.reclaim(1);
+        assert_eq!(ring.allocate(64, 64), Some(0));
+        assert_eq!(ring.allocate(64, 64), Some(64));
+        ring.submit(3);
+        assert_eq!(ring.allocate(1, 1), None);
+
+        ring.reclaim(3);
+        assert_eq!(ring.oldest_in_flight(), None);
+        assert_eq!(ring.allocate(256, 1), Some(0));
+    }
+
+    #[test]
+    fn test_frame_queue_limit() {
+        let mut frames = FrameQueue::new(2);
+        assert_eq!(frames.wait_value(), None);
+        frames.push(1);
+        assert_eq!(frames.wait_value(), None);
+        frames.push(2);
+
+        // Third frame has to wait for the first one.
+        frames.reclaim(0);
+        assert_eq!(frames.wait_value(), Some(1));
+        frames.reclaim(1);
+        assert_eq!(frames.wait_value(), None);
+        frames.push(3);
+        assert_eq!(frames.wait_value(), Some(2));
+
+        frames.reclaim(3);
+        assert_eq!(frames.wait_value(), None);
+        assert_eq!(frames.last(), None);
+    }
+
+    #[test]
+    fn test_sampler_heap_capacity() {
+        let mut heap = DescriptorHeap {
+            raw: native::DescriptorHeap::null(),
+            handle_size: 32,
+            total_handles: 2048,
+            allocated_handles: 0,
+            start: DualHandle {
+                cpu: native::CpuDescriptor { ptr: 0 },
+                gpu: native::GpuDescriptor { ptr: 0 },
+                size: 0,
+            },
+            range_allocator: RangeAllocator::new(0 .. 2048),
+        };
+        assert_eq!(heap.allocate_range(2000), Some(0 .. 2000));
+        assert_eq!(heap.allocate_range(48), Some(2000 .. 2048));
+        assert_eq!(heap.allocated_handles, 2048);
+        assert_eq!(heap.allocate_range(1), None);
+        assert_eq!(heap.allocated_handles, 2048);
+    }
+
+    #[test]
+    fn test_combined_image_sampler_heaps() {
+        // Combined image-samplers are split into an SRV and a sampler descriptor.
+        let props = HeapProperties::from(pso::DescriptorType::CombinedImageSampler);
+        assert!(props.has_view);
+        assert!(props.has_sampler);
+        assert!(!props.is_uav);
+
+        let props = HeapProperties::from(pso::DescriptorType::SampledImage);
+        assert!(props.has_view);
+        assert!(!props.has_sampler);
+    }
+
+    #[test]
+    fn test_fence_reset() {
+        // Signalled on creation.
+        let target = 1;
+        assert_eq!(Fence::status(1, target), Ok(true));
+
+        // Reset while signalled, the next submission signals the new target.
+        let target = Fence::reset_target(1, target);
+        assert_eq!(target, 2);
+        assert_eq!(Fence::status(1, target), Ok(false));
+        assert_eq!(Fence::status(2, target), Ok(true));
+
+        // Resetting an unsignalled fence keeps the target.
+        let target = Fence::reset_target(2, target);
+        assert_eq!(Fence::reset_target(2, target), target);
+        assert_eq!(Fence::status(2, target), Ok(false));
+
+        assert!(Fence::status(!0, target).is_err());
+    }
+}
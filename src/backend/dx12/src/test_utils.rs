@@ -0,0 +1,600 @@
+//! Fixtures shared by the tests running on the WARP adapter.
+
+use hal::adapter::{DeviceType, MemoryType};
+use hal::command::{
+    BufferCopy,
+    BufferImageCopy,
+    ClearColorRaw,
+    ClearDepthStencilRaw,
+    ClearValueRaw,
+    CommandBufferFlags,
+    RawCommandBuffer,
+    RawLevel,
+    SubpassContents,
+};
+use hal::format::{Aspects, Format, Swizzle};
+use hal::memory::{Barrier, Properties};
+use hal::pass::{
+    Attachment,
+    AttachmentLoadOp,
+    AttachmentOps,
+    AttachmentStoreOp,
+    Subpass,
+    SubpassDependency,
+    SubpassDesc,
+};
+use hal::pool::{CommandPoolCreateFlags, RawCommandPool};
+use hal::queue::{RawCommandQueue, Submission};
+use hal::{
+    buffer,
+    image,
+    pso,
+    Compute,
+    Device as HalDevice,
+    Features,
+    General,
+    PhysicalDevice,
+    Primitive,
+    QueueFamily,
+    QueueGroup,
+    QueueType,
+    Transfer,
+};
+use std::borrow::Borrow;
+use std::ops::Range;
+use std::{iter, mem, ptr, slice};
+
+use command::CommandBuffer;
+use resource::{
+    Buffer,
+    DescriptorSetLayout,
+    Framebuffer,
+    Image,
+    ImageView,
+    Memory,
+    PipelineLayout,
+    RenderPass,
+    Semaphore,
+    ShaderModule,
+};
+use window::Surface;
+use {Backend, Device, Instance};
+
+/// Look up the WARP adapter of the instance.
+pub(crate) fn warp_adapter(instance: &Instance) -> hal::Adapter<Backend> {
+    instance
+        .enumerate_adapters_with_warp()
+        .into_iter()
+        .find(|adapter| adapter.info.device_type == DeviceType::Cpu)
+        .expect("WARP adapter not found")
+}
+
+/// Logical device opened on the WARP adapter.
+pub(crate) struct Warp {
+    pub device: Device,
+    pub adapter: hal::Adapter<Backend>,
+    pub memory_types: Vec<MemoryType>,
+    // Dropped last, the factory has to outlive the adapter and the device.
+    instance: Instance,
+}
+
+impl Warp {
+    /// Open a device with a single general queue and no features enabled.
+    pub fn open(name: &str) -> (Self, QueueGroup<Backend, General>) {
+        Self::open_with_features(name, Features::empty()).unwrap()
+    }
+
+    /// Open a device with a single general queue and the given features enabled.
+    ///
+    /// Returns `None` if the adapter doesn't support the features.
+    pub fn open_with_features(
+        name: &str,
+        features: Features,
+    ) -> Option<(Self, QueueGroup<Backend, General>)> {
+        let instance = Instance::create(name, 1);
+        let adapter = warp_adapter(&instance);
+        if !adapter.physical_device.features().contains(features) {
+            return None;
+        }
+        let family = adapter
+            .queue_families
+            .iter()
+            .find(|family| family.supports_graphics())
+            .unwrap();
+        let hal::Gpu { device, mut queues } = unsafe {
+            adapter
+                .physical_device
+                .open(&[(family, &[1.0])], features)
+                .unwrap()
+        };
+        let queue_group = queues.take::<General>(family.id()).unwrap();
+        let memory_types = adapter.physical_device.memory_properties().memory_types;
+        let warp = Warp {
+            device,
+            adapter,
+            memory_types,
+            instance,
+        };
+        Some((warp, queue_group))
+    }
+
+    /// Open a device with a queue of each of the general, compute and transfer families.
+    pub fn open_queue_families(
+        name: &str,
+    ) -> (
+        Self,
+        QueueGroup<Backend, General>,
+        QueueGroup<Backend, Compute>,
+        QueueGroup<Backend, Transfer>,
+    ) {
+        let instance = Instance::create(name, 1);
+        let adapter = warp_adapter(&instance);
+        let family = |ty| {
+            adapter
+                .queue_families
+                .iter()
+                .find(|family| family.queue_type() == ty)
+                .unwrap()
+        };
+        let families = [
+            family(QueueType::General),
+            family(QueueType::Compute),
+            family(QueueType::Transfer),
+        ];
+        let hal::Gpu { device, mut queues } = unsafe {
+            adapter
+                .physical_device
+                .open(
+                    &[
+                        (families[0], &[1.0]),
+                        (families[1], &[1.0]),
+                        (families[2], &[1.0]),
+                    ],
+                    Features::empty(),
+                )
+                .unwrap()
+        };
+        let general = queues.take::<General>(families[0].id()).unwrap();
+        let compute = queues.take::<Compute>(families[1].id()).unwrap();
+        let transfer = queues.take::<Transfer>(families[2].id()).unwrap();
+        let memory_types = adapter.physical_device.memory_properties().memory_types;
+        let warp = Warp {
+            device,
+            adapter,
+            memory_types,
+            instance,
+        };
+        (warp, general, compute, transfer)
+    }
+
+    /// Create a surface of `window`, which can be presented to from the opened queue.
+    #[cfg(feature = "winit")]
+    pub fn create_surface(&self, window: &winit::Window) -> Surface {
+        self.instance.create_surface(window)
+    }
+
+    /// First memory type of the mask with the given properties.
+    pub fn memory_type(&self, type_mask: u64, properties: Properties) -> hal::MemoryTypeId {
+        let ty = self
+            .memory_types
+            .iter()
+            .enumerate()
+            .position(|(id, ty)| type_mask & (1 << id) != 0 && ty.properties.contains(properties))
+            .unwrap();
+        hal::MemoryTypeId(ty)
+    }
+
+    /// Allocate memory from the first memory type of the mask with the given properties.
+    pub unsafe fn allocate(&self, type_mask: u64, size: u64, properties: Properties) -> Memory {
+        let ty = self.memory_type(type_mask, properties);
+        self.device.allocate_memory(ty, size).unwrap()
+    }
+
+    /// Create a buffer bound to a dedicated allocation.
+    pub unsafe fn create_buffer(
+        &self,
+        size: u64,
+        usage: buffer::Usage,
+        properties: Properties,
+    ) -> (Buffer, Memory) {
+        let mut buffer = self.device.create_buffer(size, usage).unwrap();
+        let requirements = self.device.get_buffer_requirements(&buffer);
+        let memory = self.allocate(requirements.type_mask, requirements.size, properties);
+        self.device
+            .bind_buffer_memory(&memory, 0, &mut buffer)
+            .unwrap();
+        (buffer, memory)
+    }
+
+    /// Create a 2D image with a single level and layer and a view of all its aspects.
+    ///
+    /// The image is bound to a dedicated device local allocation.
+    pub unsafe fn create_target(
+        &self,
+        format: Format,
+        (width, height): (image::Size, image::Size),
+        samples: image::NumSamples,
+        usage: image::Usage,
+    ) -> Target {
+        let mut image = self
+            .device
+            .create_image(
+                image::Kind::D2(width, height, 1, samples),
+                1,
+                format,
+                image::Tiling::Optimal,
+                usage,
+                image::ViewCapabilities::empty(),
+            )
+            .unwrap();
+        let requirements = self.device.get_image_requirements(&image);
+        let memory = self.allocate(
+            requirements.type_mask,
+            requirements.size,
+            Properties::DEVICE_LOCAL,
+        );
+        self.device
+            .bind_image_memory(&memory, 0, &mut image)
+            .unwrap();
+        let view = self
+            .device
+            .create_image_view(
+                &image,
+                image::ViewKind::D2,
+                format,
+                Swizzle::NO,
+                subresource_range(format.surface_desc().aspects),
+            )
+            .unwrap();
+        Target {
+            image,
+            memory,
+            view,
+        }
+    }
+
+    pub unsafe fn destroy_target(&self, target: Target) {
+        self.device.destroy_image_view(target.view);
+        self.device.destroy_image(target.image);
+        self.device.free_memory(target.memory);
+    }
+
+    /// Create a host visible buffer initialized with `data`.
+    pub unsafe fn create_buffer_with_data<T: Copy>(
+        &self,
+        data: &[T],
+        usage: buffer::Usage,
+    ) -> (Buffer, Memory) {
+        let size = (data.len() * mem::size_of::<T>()) as u64;
+        let (buffer, memory) =
+            self.create_buffer(size, usage, Properties::CPU_VISIBLE | Properties::COHERENT);
+        let mapping = self.device.map_memory(&memory, 0 .. size).unwrap();
+        ptr::copy_nonoverlapping(data.as_ptr(), mapping as *mut T, data.len());
+        self.device.unmap_memory(&memory);
+        (buffer, memory)
+    }
+
+    pub unsafe fn destroy_buffer(&self, (buffer, memory): (Buffer, Memory)) {
+        self.device.destroy_buffer(buffer);
+        self.device.free_memory(memory);
+    }
+
+    /// Compile HLSL shader modules, the entry point of each module is `main`.
+    pub unsafe fn create_shader_modules(&self, sources: &[ShaderSource]) -> Vec<ShaderModule> {
+        sources
+            .iter()
+            .map(|&(stage, entry, code)| {
+                self.device
+                    .create_shader_module_from_source(stage, entry, "main", code)
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    pub unsafe fn destroy_shader_modules(&self, modules: Vec<ShaderModule>) {
+        for module in modules {
+            self.device.destroy_shader_module(module);
+        }
+    }
+
+    /// Create a pipeline layout without push constants.
+    pub unsafe fn create_pipeline_layout(
+        &self,
+        set_layouts: &[&DescriptorSetLayout],
+    ) -> PipelineLayout {
+        self.device
+            .create_pipeline_layout(
+                set_layouts.iter().cloned(),
+                iter::empty::<(pso::ShaderStageFlags, Range<u32>)>(),
+            )
+            .unwrap()
+    }
+
+    /// Create a render pass with a single subpass drawing to a single color attachment.
+    ///
+    /// The attachment is cleared and left in the `TransferSrcOptimal` layout for readback.
+    pub unsafe fn create_color_pass(&self, format: Format) -> RenderPass {
+        self.device
+            .create_render_pass(
+                iter::once(Attachment {
+                    format: Some(format),
+                    samples: 1,
+                    ops: AttachmentOps::new(AttachmentLoadOp::Clear, AttachmentStoreOp::Store),
+                    stencil_ops: AttachmentOps::DONT_CARE,
+                    layouts: image::Layout::Undefined .. image::Layout::TransferSrcOptimal,
+                }),
+                iter::once(SubpassDesc {
+                    colors: &[(0, image::Layout::ColorAttachmentOptimal)],
+                    depth_stencil: None,
+                    inputs: &[],
+                    resolves: &[],
+                    preserves: &[],
+                }),
+                iter::empty::<SubpassDependency>(),
+            )
+            .unwrap()
+    }
+
+    pub unsafe fn create_framebuffer(
+        &self,
+        render_pass: &RenderPass,
+        views: &[&ImageView],
+        (width, height): (image::Size, image::Size),
+    ) -> Framebuffer {
+        self.device
+            .create_framebuffer(
+                render_pass,
+                views.iter().cloned(),
+                image::Extent {
+                    width,
+                    height,
+                    depth: 1,
+                },
+            )
+            .unwrap()
+    }
+
+    /// Record a primary command buffer, submit it to the first queue and wait for it.
+    pub unsafe fn submit<F>(&self, queue_group: &mut QueueGroup<Backend, General>, record: F)
+    where
+        F: FnOnce(&mut CommandBuffer),
+    {
+        let mut command_pool = self
+            .device
+            .create_command_pool(queue_group.family(), CommandPoolCreateFlags::empty())
+            .unwrap();
+        let mut cmd_buffer = command_pool.allocate_one(RawLevel::Primary);
+        cmd_buffer.begin(CommandBufferFlags::ONE_TIME_SUBMIT, Default::default());
+        record(&mut cmd_buffer);
+        cmd_buffer.finish();
+
+        let fence = self.device.create_fence(false).unwrap();
+        queue_group.queues[0].as_raw_mut().submit(
+            Submission {
+                command_buffers: iter::once(&cmd_buffer),
+                wait_semaphores: iter::empty::<(&Semaphore, _)>(),
+                signal_semaphores: iter::empty::<&Semaphore>(),
+            },
+            Some(&fence),
+        );
+        assert_eq!(self.device.wait_for_fence(&fence, !0), Ok(true));
+
+        self.device.destroy_fence(fence);
+        command_pool.free(iter::once(cmd_buffer));
+        self.device.destroy_command_pool(command_pool);
+    }
+
+    /// Read back the first `len` elements of a buffer with `TRANSFER_SRC` usage.
+    pub unsafe fn read_buffer<T: Copy>(
+        &self,
+        queue_group: &mut QueueGroup<Backend, General>,
+        buffer: &Buffer,
+        len: usize,
+    ) -> Vec<T> {
+        let size = (len * mem::size_of::<T>()) as u64;
+        let readback = self.create_buffer(
+            size,
+            buffer::Usage::TRANSFER_DST,
+            Properties::CPU_VISIBLE | Properties::CPU_CACHED,
+        );
+        self.submit(queue_group, |cmd_buffer| {
+            cmd_buffer.copy_buffer(
+                buffer,
+                &readback.0,
+                iter::once(BufferCopy {
+                    src: 0,
+                    dst: 0,
+                    size,
+                }),
+            );
+        });
+        let mapping = self.device.map_memory(&readback.1, 0 .. size).unwrap();
+        let data = slice::from_raw_parts(mapping as *const T, len).to_vec();
+        self.device.unmap_memory(&readback.1);
+        self.destroy_buffer(readback);
+        data
+    }
+
+    /// Read back the texels of an aspect of a single level and layer 2D image.
+    ///
+    /// The image has to be in the `TransferSrcOptimal` layout, texels are returned row by row.
+    pub unsafe fn read_image<T: Copy>(
+        &self,
+        queue_group: &mut QueueGroup<Backend, General>,
+        image: &Image,
+        aspects: Aspects,
+        (width, height): (image::Size, image::Size),
+    ) -> Vec<T> {
+        // Rows are aligned to 256 bytes to keep the copy aligned.
+        let texel_size = mem::size_of::<T>() as u64;
+        let row_pitch = (width as u64 * texel_size + 255) & !255;
+        let size = row_pitch * height as u64;
+        let readback = self.create_buffer(
+            size,
+            buffer::Usage::TRANSFER_DST,
+            Properties::CPU_VISIBLE | Properties::CPU_CACHED,
+        );
+        self.submit(queue_group, |cmd_buffer| {
+            cmd_buffer.copy_image_to_buffer(
+                image,
+                image::Layout::TransferSrcOptimal,
+                &readback.0,
+                iter::once(BufferImageCopy {
+                    buffer_offset: 0,
+                    buffer_width: (row_pitch / texel_size) as _,
+                    buffer_height: height,
+                    image_layers: image::SubresourceLayers {
+                        aspects,
+                        level: 0,
+                        layers: 0 .. 1,
+                    },
+                    image_offset: image::Offset::ZERO,
+                    image_extent: image::Extent {
+                        width,
+                        height,
+                        depth: 1,
+                    },
+                }),
+            );
+        });
+        let mapping = self.device.map_memory(&readback.1, 0 .. size).unwrap();
+        let texels = (0 .. height as isize)
+            .flat_map(|y| {
+                let row = mapping.offset(y * row_pitch as isize) as *const T;
+                slice::from_raw_parts(row, width as usize).to_vec()
+            })
+            .collect();
+        self.device.unmap_memory(&readback.1);
+        self.destroy_buffer(readback);
+        texels
+    }
+}
+
+/// Image created by `Warp::create_target`.
+pub(crate) struct Target {
+    pub image: Image,
+    pub memory: Memory,
+    pub view: ImageView,
+}
+
+/// Stage, entry point and HLSL code of a shader module.
+pub(crate) type ShaderSource = (pso::Stage, &'static str, &'static [u8]);
+
+/// Vertex shader of a triangle covering the whole viewport, drawn with 3 vertices.
+pub(crate) const FULLSCREEN_VS: ShaderSource = (
+    pso::Stage::Vertex,
+    "vs_main",
+    b"float4 vs_main(uint id : SV_VertexID) : SV_Position {
+        float2 uv = float2((id << 1) & 2, id & 2);
+        return float4(uv * float2(2.0, -2.0) + float2(-1.0, 1.0), 0.0, 1.0);
+    }",
+);
+
+/// Subresource range of the first level and layer.
+pub(crate) fn subresource_range(aspects: Aspects) -> image::SubresourceRange {
+    image::SubresourceRange {
+        aspects,
+        levels: 0 .. 1,
+        layers: 0 .. 1,
+    }
+}
+
+/// Barrier between two states of the first level and layer of an image.
+pub(crate) fn image_barrier<'a>(
+    target: &'a Image,
+    aspects: Aspects,
+    states: Range<image::State>,
+) -> Barrier<'a, Backend> {
+    Barrier::Image {
+        states,
+        target,
+        families: None,
+        range: subresource_range(aspects),
+    }
+}
+
+pub(crate) fn entry_point(module: &ShaderModule) -> pso::EntryPoint<Backend> {
+    pso::EntryPoint {
+        entry: "main",
+        module,
+        specialization: pso::Specialization::default(),
+    }
+}
+
+/// Describe a pipeline drawing triangle lists in a subpass.
+///
+/// Blending is disabled for all color attachments of the subpass.
+pub(crate) fn graphics_pipeline_desc<'a>(
+    vertex: &'a ShaderModule,
+    fragment: Option<&'a ShaderModule>,
+    layout: &'a PipelineLayout,
+    render_pass: &'a RenderPass,
+    subpass: usize,
+) -> pso::GraphicsPipelineDesc<'a, Backend> {
+    let mut desc = pso::GraphicsPipelineDesc::new(
+        pso::GraphicsShaderSet {
+            vertex: entry_point(vertex),
+            hull: None,
+            domain: None,
+            geometry: None,
+            fragment: fragment.map(entry_point),
+        },
+        Primitive::TriangleList,
+        pso::Rasterizer::FILL,
+        layout,
+        Subpass {
+            index: subpass,
+            main_pass: render_pass,
+        },
+    );
+    for _ in &render_pass.subpasses[subpass].color_attachments {
+        desc.blender.targets.push(pso::ColorBlendDesc::EMPTY);
+    }
+    desc
+}
+
+/// Begin an inline render pass, viewport and scissor cover the whole `extent`.
+pub(crate) unsafe fn begin_render_pass<T>(
+    cmd_buffer: &mut CommandBuffer,
+    render_pass: &RenderPass,
+    framebuffer: &Framebuffer,
+    (width, height): (image::Size, image::Size),
+    clear_values: T,
+) where
+    T: IntoIterator,
+    T::Item: Borrow<ClearValueRaw>,
+{
+    let rect = pso::Rect {
+        x: 0,
+        y: 0,
+        w: width as _,
+        h: height as _,
+    };
+    cmd_buffer.set_viewports(
+        0,
+        iter::once(pso::Viewport {
+            rect,
+            depth: 0.0 .. 1.0,
+        }),
+    );
+    cmd_buffer.set_scissors(0, iter::once(rect));
+    cmd_buffer.begin_render_pass(
+        render_pass,
+        framebuffer,
+        rect,
+        clear_values,
+        SubpassContents::Inline,
+    );
+}
+
+pub(crate) fn clear_color(float32: [f32; 4]) -> ClearValueRaw {
+    ClearValueRaw {
+        color: ClearColorRaw { float32 },
+    }
+}
+
+pub(crate) fn clear_depth_stencil(depth: f32, stencil: u32) -> ClearValueRaw {
+    ClearValueRaw {
+        depth_stencil: ClearDepthStencilRaw { depth, stencil },
+    }
+}
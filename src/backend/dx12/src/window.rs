@@ -1,20 +1,38 @@
-use std::collections::VecDeque;
-use std::mem;
+use std::ops::Range;
+use std::sync::Mutex;
+use std::{mem, ptr};
 
 #[cfg(feature = "winit")]
 use winit;
 
-use winapi::shared::dxgi1_4;
+use winapi::shared::minwindef::{BOOL, FALSE, TRUE};
+use winapi::shared::{dxgi, dxgi1_4, dxgi1_5};
+use winapi::shared::dxgiformat::DXGI_FORMAT;
 use winapi::shared::windef::{HWND, RECT};
+use winapi::shared::winerror;
 use winapi::um::winuser::GetClientRect;
+use winapi::um::{d3d12, handleapi, synchapi, winbase};
 
 use hal::{self, format as f, image as i, CompositeAlpha};
-use {native, resource as r, Backend, Instance, PhysicalDevice, QueueFamily};
+use {conv, native, resource as r, Backend, Instance, PhysicalDevice, QueueFamily};
 
 use std::os::raw::c_void;
 
 impl Instance {
-    pub fn create_surface_from_hwnd(&self, hwnd: *mut c_void) -> Surface {
+    /// Create a surface from a raw window handle, for windows not created by `winit`.
+    ///
+    /// Swapchains of the surface are created for the window with `CreateSwapChainForHwnd`.
+    ///
+    /// # Safety
+    ///
+    /// `hwnd` must be a valid window handle, and the window must outlive the surface
+    /// and all swapchains created from it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `hwnd` is null.
+    pub unsafe fn create_surface_from_hwnd(&self, hwnd: *mut c_void) -> Surface {
+        assert!(!hwnd.is_null(), "Unable to create a surface from a null window handle");
         Surface {
             factory: self.factory,
             wnd_handle: hwnd as *mut _,
@@ -24,7 +42,7 @@ impl Instance {
     #[cfg(feature = "winit")]
     pub fn create_surface(&self, window: &winit::Window) -> Surface {
         use winit::os::windows::WindowExt;
-        self.create_surface_from_hwnd(window.get_hwnd() as *mut _)
+        unsafe { self.create_surface_from_hwnd(window.get_hwnd() as *mut _) }
     }
 }
 
@@ -52,6 +70,37 @@ impl Surface {
             )
         }
     }
+
+    /// Check if the flip model swapchains of this surface can disable v-sync.
+    ///
+    /// Tearing requires `IDXGIFactory5`, which is only available from Windows 10 onwards.
+    pub(crate) fn supports_tearing(&self) -> bool {
+        unsafe {
+            let (factory5, hr) = self.factory.cast::<dxgi1_5::IDXGIFactory5>();
+            if !winerror::SUCCEEDED(hr) {
+                return false;
+            }
+
+            let mut allow_tearing: BOOL = FALSE;
+            let hr = factory5.CheckFeatureSupport(
+                dxgi1_5::DXGI_FEATURE_PRESENT_ALLOW_TEARING,
+                &mut allow_tearing as *mut _ as *mut _,
+                mem::size_of::<BOOL>() as _,
+            );
+            factory5.destroy();
+
+            winerror::SUCCEEDED(hr) && allow_tearing != FALSE
+        }
+    }
+}
+
+/// Present modes supported by flip model swapchains.
+pub(crate) fn present_modes(allow_tearing: bool) -> Vec<hal::PresentMode> {
+    let mut present_modes = vec![hal::PresentMode::Fifo];
+    if allow_tearing {
+        present_modes.push(hal::PresentMode::Immediate);
+    }
+    present_modes
 }
 
 impl hal::Surface<Backend> for Surface {
@@ -74,7 +123,7 @@ impl hal::Surface<Backend> for Surface {
         let extent = hal::window::Extent2D { width, height };
 
         let capabilities = hal::SurfaceCapabilities {
-            image_count: 2 .. 16, // we currently use a flip effect which supports 2..16 buffers
+            image_count: SWAPCHAIN_IMAGE_COUNT,
             current_extent: Some(extent),
             extents: extent .. extent,
             max_image_layers: 1,
@@ -94,48 +143,388 @@ impl hal::Surface<Backend> for Surface {
             f::Format::Rgba16Sfloat,
         ];
 
-        let present_modes = vec![
-            hal::PresentMode::Fifo, //TODO
-        ];
+        let present_modes = present_modes(self.supports_tearing());
 
         (capabilities, Some(formats), present_modes)
     }
 }
 
+// Multisampled color target, resolved into the current back buffer on present.
+#[derive(Debug)]
+pub(crate) struct MsaaTarget {
+    // Reference to the committed resource of the image handed out to the user.
+    pub(crate) resource: native::Resource,
+    // One allocator per back buffer, guarded by the fence values of the last resolve.
+    pub(crate) allocators: Vec<native::CommandAllocator>,
+    pub(crate) list: native::GraphicsCommandList,
+    pub(crate) fence: native::Fence,
+    pub(crate) fence_values: Mutex<Vec<u64>>,
+}
+
+impl MsaaTarget {
+    // Record and submit the resolve of the MSAA target into the back buffer.
+    //
+    // Both resources are expected to be in the `PRESENT` state and are returned to it.
+    pub(crate) unsafe fn resolve(
+        &self,
+        queue: native::CommandQueue,
+        index: usize,
+        back_buffer: native::Resource,
+        format: DXGI_FORMAT,
+    ) {
+        let mut fence_values = self.fence_values.lock().unwrap();
+
+        // Wait until the previous resolve into this back buffer has finished,
+        // to be able to reuse the allocator.
+        let pending = fence_values[index];
+        if self.fence.GetCompletedValue() < pending {
+            let event = native::Event::create(false, false);
+            assert_eq!(
+                winerror::S_OK,
+                self.fence.set_event_on_completion(event, pending)
+            );
+            synchapi::WaitForSingleObject(event.0, winbase::INFINITE);
+            handleapi::CloseHandle(event.0);
+        }
+
+        let allocator = self.allocators[index];
+        allocator.Reset();
+        self.list.reset(allocator, native::PipelineState::null());
+
+        let transition = |resource: native::Resource, states: (u32, u32)| {
+            let mut barrier = d3d12::D3D12_RESOURCE_BARRIER {
+                Type: d3d12::D3D12_RESOURCE_BARRIER_TYPE_TRANSITION,
+                Flags: d3d12::D3D12_RESOURCE_BARRIER_FLAG_NONE,
+                u: mem::zeroed(),
+            };
+            *barrier.u.Transition_mut() = d3d12::D3D12_RESOURCE_TRANSITION_BARRIER {
+                pResource: resource.as_mut_ptr(),
+                Subresource: d3d12::D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+                StateBefore: states.0,
+                StateAfter: states.1,
+            };
+            barrier
+        };
+
+        let pre_barriers = [
+            transition(
+                self.resource,
+                (
+                    d3d12::D3D12_RESOURCE_STATE_PRESENT,
+                    d3d12::D3D12_RESOURCE_STATE_RESOLVE_SOURCE,
+                ),
+            ),
+            transition(
+                back_buffer,
+                (
+                    d3d12::D3D12_RESOURCE_STATE_PRESENT,
+                    d3d12::D3D12_RESOURCE_STATE_RESOLVE_DEST,
+                ),
+            ),
+        ];
+        self.list
+            .ResourceBarrier(pre_barriers.len() as _, pre_barriers.as_ptr());
+
+        self.list.ResolveSubresource(
+            back_buffer.as_mut_ptr(),
+            0,
+            self.resource.as_mut_ptr(),
+            0,
+            format,
+        );
+
+        let post_barriers = [
+            transition(
+                self.resource,
+                (
+                    d3d12::D3D12_RESOURCE_STATE_RESOLVE_SOURCE,
+                    d3d12::D3D12_RESOURCE_STATE_PRESENT,
+                ),
+            ),
+            transition(
+                back_buffer,
+                (
+                    d3d12::D3D12_RESOURCE_STATE_RESOLVE_DEST,
+                    d3d12::D3D12_RESOURCE_STATE_PRESENT,
+                ),
+            ),
+        ];
+        self.list
+            .ResourceBarrier(post_barriers.len() as _, post_barriers.as_ptr());
+        assert_eq!(winerror::S_OK, self.list.close());
+
+        let mut list = self.list.as_mut_ptr() as *mut d3d12::ID3D12CommandList;
+        queue.ExecuteCommandLists(1, &mut list);
+
+        let value = fence_values.iter().cloned().max().unwrap_or(0) + 1;
+        assert_eq!(winerror::S_OK, queue.signal(self.fence, value));
+        fence_values[index] = value;
+    }
+
+    pub(crate) unsafe fn destroy(&self) {
+        // Make sure no resolve is in flight anymore.
+        let last = self.fence_values.lock().unwrap().iter().cloned().max().unwrap_or(0);
+        if self.fence.GetCompletedValue() < last {
+            let event = native::Event::create(false, false);
+            self.fence.set_event_on_completion(event, last);
+            synchapi::WaitForSingleObject(event.0, winbase::INFINITE);
+            handleapi::CloseHandle(event.0);
+        }
+        for allocator in &self.allocators {
+            allocator.destroy();
+        }
+        self.list.destroy();
+        self.fence.destroy();
+        self.resource.destroy();
+    }
+}
+
+// Flip model swapchains support 2 to `DXGI_MAX_SWAP_CHAIN_BUFFERS` (16) buffers.
+// Frame latency waitable objects aren't used, which would require an additional buffer.
+pub(crate) const SWAPCHAIN_IMAGE_COUNT: Range<hal::SwapImageIndex> =
+    2 .. dxgi::DXGI_MAX_SWAP_CHAIN_BUFFERS;
+
+/// Color space of the swapchain back buffers.
+///
+/// Only a subset of the swapchain formats is valid for each color space:
+///
+/// - `Srgb`: all formats, `Bgra8Srgb` and `Rgba8Srgb` are presented as their UNORM equivalent.
+/// - `Hdr10`: `A2b10g10r10Unorm`.
+/// - `ScRgb`: `Rgba16Sfloat`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// sRGB with BT.709 primaries, the default color space.
+    Srgb,
+    /// HDR10, ST.2084 (PQ) transfer function with BT.2020 primaries.
+    Hdr10,
+    /// Linear extended range sRGB with BT.709 primaries.
+    ScRgb,
+}
+
+impl ColorSpace {
+    /// Check if back buffers of the given format can be presented in this color space.
+    pub fn supports_format(&self, format: f::Format) -> bool {
+        match *self {
+            ColorSpace::Srgb => true,
+            ColorSpace::Hdr10 => format == f::Format::A2b10g10r10Unorm,
+            ColorSpace::ScRgb => format == f::Format::Rgba16Sfloat,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Swapchain {
     pub(crate) inner: native::WeakPtr<dxgi1_4::IDXGISwapChain3>,
-    pub(crate) next_frame: usize,
-    pub(crate) frame_queue: VecDeque<usize>,
     #[allow(dead_code)]
     pub(crate) rtv_heap: r::DescriptorHeap,
     // need to associate raw image pointers with the swapchain so they can be properly released
     // when the swapchain is destroyed
     pub(crate) resources: Vec<native::Resource>,
+    pub(crate) extent: hal::window::Extent2D,
+    pub(crate) format: f::Format,
+    // Format of the back buffers, sRGB formats are mapped to their UNORM equivalent.
+    pub(crate) buffer_format: DXGI_FORMAT,
+    pub(crate) msaa: Option<MsaaTarget>,
+    // Swapchain created with `PresentMode::Immediate`, presents without waiting for v-sync.
+    pub(crate) allow_tearing: bool,
+    pub(crate) color_space: ColorSpace,
+}
+
+/// Error on switching the fullscreen state of a swapchain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FullscreenError {
+    /// Fullscreen mode can't be entered right now, e.g. the window is not in the foreground.
+    NotCurrentlyAvailable,
+    /// Device is lost.
+    DeviceLost,
+    /// Surface is lost.
+    SurfaceLost,
+}
+
+impl Swapchain {
+    /// Check if the swapchain is in fullscreen exclusive mode.
+    ///
+    /// DXGI leaves fullscreen mode on its own, e.g. on ALT+TAB, so this may change
+    /// without calling `set_fullscreen`.
+    pub unsafe fn is_fullscreen(&self) -> bool {
+        let mut fullscreen: BOOL = FALSE;
+        let hr = self.inner.GetFullscreenState(&mut fullscreen, ptr::null_mut());
+        winerror::SUCCEEDED(hr) && fullscreen != FALSE
+    }
+
+    /// Enter or leave fullscreen exclusive mode on the output containing the window.
+    ///
+    /// After a successful transition the back buffers need to be resized with
+    /// `Device::recreate_swapchain`. Fullscreen mode is left when the window loses
+    /// focus (ALT+TAB), applications should check `is_fullscreen` on resize events and
+    /// re-enter fullscreen once the window is focused again. Entering fullscreen from
+    /// the background fails with `FullscreenError::NotCurrentlyAvailable`.
+    pub unsafe fn set_fullscreen(&mut self, fullscreen: bool) -> Result<(), FullscreenError> {
+        let hr = self.inner.SetFullscreenState(
+            if fullscreen { TRUE } else { FALSE },
+            ptr::null_mut(),
+        );
+        match hr {
+            winerror::S_OK => Ok(()),
+            winerror::DXGI_ERROR_NOT_CURRENTLY_AVAILABLE => {
+                Err(FullscreenError::NotCurrentlyAvailable)
+            }
+            winerror::DXGI_ERROR_DEVICE_REMOVED | winerror::DXGI_ERROR_DEVICE_RESET => {
+                Err(FullscreenError::DeviceLost)
+            }
+            _ => {
+                error!("error on setting fullscreen state 0x{:x}", hr);
+                Err(FullscreenError::SurfaceLost)
+            }
+        }
+    }
+
+    // Release the swapchain, it must not be in fullscreen mode while being destroyed.
+    pub(crate) unsafe fn release(&mut self) {
+        if self.inner.is_null() {
+            return;
+        }
+        if self.is_fullscreen() {
+            self.inner.SetFullscreenState(FALSE, ptr::null_mut());
+        }
+        self.inner.destroy();
+        self.inner = native::WeakPtr::null();
+    }
+
+    /// Current color space of the back buffers.
+    pub fn color_space(&self) -> ColorSpace {
+        self.color_space
+    }
+
+    /// Set the color space of the back buffers.
+    ///
+    /// Falls back to `ColorSpace::Srgb` if the requested color space is not supported for
+    /// presentation or not valid for the swapchain format, returning `Suboptimal` in this case.
+    pub unsafe fn set_color_space(
+        &mut self,
+        color_space: ColorSpace,
+    ) -> Option<hal::window::Suboptimal> {
+        let mut support = 0;
+        let hr = self
+            .inner
+            .CheckColorSpaceSupport(conv::map_color_space(color_space), &mut support);
+        let supported = winerror::SUCCEEDED(hr)
+            && support & dxgi1_4::DXGI_SWAP_CHAIN_COLOR_SPACE_SUPPORT_FLAG_PRESENT != 0
+            && color_space.supports_format(self.format);
+
+        let (color_space, suboptimal) = if supported {
+            (color_space, None)
+        } else {
+            warn!(
+                "Color space {:?} is not supported for {:?}, falling back to sRGB",
+                color_space, self.format
+            );
+            (ColorSpace::Srgb, Some(hal::window::Suboptimal))
+        };
+
+        let hr = self.inner.SetColorSpace1(conv::map_color_space(color_space));
+        if !winerror::SUCCEEDED(hr) {
+            error!("error on setting swapchain color space 0x{:x}", hr);
+        }
+        self.color_space = color_space;
+
+        suboptimal
+    }
 }
 
 impl hal::Swapchain<Backend> for Swapchain {
     unsafe fn acquire_image(
         &mut self,
         _timout_ns: u64,
-        _semaphore: Option<&r::Semaphore>,
-        _fence: Option<&r::Fence>,
+        semaphore: Option<&r::Semaphore>,
+        fence: Option<&r::Fence>,
     ) -> Result<(hal::SwapImageIndex, Option<hal::window::Suboptimal>), hal::AcquireError> {
-        // TODO: sync
-
-        if false {
-            // TODO: we need to block this at some point? (running out of backbuffers)
-            //let num_images = self.images.len();
-            let num_images = 1;
-            let index = self.next_frame;
-            self.frame_queue.push_back(index);
-            self.next_frame = (self.next_frame + 1) % num_images;
+        // The current back buffer is available for rendering once acquired, as
+        // `Present` blocks on the queue when running out of back buffers. Signal the
+        // synchronization primitives right away so that waits on them are satisfied.
+        if let Some(semaphore) = semaphore {
+            semaphore.raw.signal(semaphore.next_signal_value());
+        }
+        if let Some(fence) = fence {
+            fence.raw.signal(fence.target.get());
         }
 
-        // TODO:
         Ok((self.inner.GetCurrentBackBufferIndex(), None))
     }
 }
 
+impl Drop for Swapchain {
+    fn drop(&mut self) {
+        // Never leave the display in fullscreen mode of a lost swapchain.
+        unsafe {
+            if !self.inner.is_null() && self.is_fullscreen() {
+                self.inner.SetFullscreenState(FALSE, ptr::null_mut());
+            }
+        }
+    }
+}
+
 unsafe impl Send for Swapchain {}
 unsafe impl Sync for Swapchain {}
+
+#[cfg(all(test, feature = "warp"))]
+mod warp_tests {
+    use Instance;
+
+    #[test]
+    fn test_surface_from_hwnd() {
+        let instance = Instance::create("test_surface_from_hwnd", 1);
+        // The handle is only stored, it's used once a swapchain is created.
+        let hwnd = 0x1234 as *mut _;
+        let surface = unsafe { instance.create_surface_from_hwnd(hwnd) };
+        assert_eq!(surface.wnd_handle as *mut _, hwnd);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_surface_from_null_hwnd() {
+        let instance = Instance::create("test_surface_from_null_hwnd", 1);
+        unsafe { instance.create_surface_from_hwnd(std::ptr::null_mut()) };
+    }
+
+    #[cfg(feature = "winit")]
+    #[test]
+    fn test_warp_acquire_image_signals() {
+        use hal::format::Format;
+        use hal::{Device, Surface, Swapchain, SwapchainConfig};
+
+        use test_utils::warp_adapter;
+
+        let events_loop = winit::EventsLoop::new();
+        let window = winit::WindowBuilder::new()
+            .with_visibility(false)
+            .build(&events_loop)
+            .unwrap();
+        let instance = Instance::create("test_warp_acquire_image_signals", 1);
+        let mut surface = instance.create_surface(&window);
+        let adapter = warp_adapter(&instance);
+        let (device, _queue_group) = adapter
+            .open_with::<_, hal::General>(1, |family| surface.supports_queue_family(family))
+            .unwrap();
+        unsafe {
+            let config = SwapchainConfig::new(64, 64, Format::Bgra8Unorm, 2);
+            let (mut swapchain, _images) =
+                device.create_swapchain(&mut surface, config, None).unwrap();
+            let semaphore = device.create_semaphore().unwrap();
+            let fence = device.create_fence(false).unwrap();
+
+            for value in 1 .. 3 {
+                swapchain
+                    .acquire_image(!0, Some(&semaphore), Some(&fence))
+                    .unwrap();
+                assert_eq!(semaphore.raw.GetCompletedValue(), value);
+                assert_eq!(device.get_fence_status(&fence), Ok(true));
+                device.reset_fence(&fence).unwrap();
+            }
+
+            device.destroy_fence(fence);
+            device.destroy_semaphore(semaphore);
+            device.destroy_swapchain(swapchain);
+        }
+    }
+}
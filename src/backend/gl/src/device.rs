@@ -1043,6 +1043,12 @@ impl d::Device<B> for Device {
         info: i::SamplerInfo,
     ) -> Result<n::FatSampler, d::AllocationError> {
         assert!(info.normalized);
+        if info.reduction != i::ReductionMode::WeightedAverage {
+            warn!(
+                "Sampler reduction {:?} is not supported, using a weighted average",
+                info.reduction
+            );
+        }
 
         if !self
             .share
@@ -476,6 +476,8 @@ impl hal::PhysicalDevice<Backend> for PhysicalDevice {
 
             optimal_buffer_copy_offset_alignment: pc.buffer_alignment,
             optimal_buffer_copy_pitch_alignment: 4,
+            min_buffer_copy_offset_alignment: 1,
+            min_buffer_copy_pitch_alignment: 1,
             min_texel_buffer_offset_alignment: pc.buffer_alignment,
             min_uniform_buffer_offset_alignment: pc.buffer_alignment,
             min_storage_buffer_offset_alignment: pc.buffer_alignment,
@@ -1660,6 +1662,13 @@ impl hal::Device<Backend> for Device {
         &self,
         info: image::SamplerInfo,
     ) -> Result<n::Sampler, AllocationError> {
+        if info.reduction != image::ReductionMode::WeightedAverage {
+            warn!(
+                "Sampler reduction {:?} is not supported, using a weighted average",
+                info.reduction
+            );
+        }
+
         Ok(n::Sampler {
             raw: match self.make_sampler_descriptor(&info) {
                 Some(ref descriptor) => Some(self.shared.device.lock().new_sampler(descriptor)),
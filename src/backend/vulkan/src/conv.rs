@@ -10,14 +10,25 @@ use std::mem;
 use std::ptr;
 
 pub fn map_format(format: format::Format) -> vk::Format {
-    vk::Format::from_raw(format as i32)
+    match format {
+        format::Format::Nv12Unorm => vk::Format::G8_B8R8_2PLANE_420_UNORM,
+        format::Format::P010Unorm => vk::Format::G10X6_B10X6R10X6_2PLANE_420_UNORM_3PACK16,
+        _ => vk::Format::from_raw(format as i32),
+    }
 }
 
 pub fn map_vk_format(vk_format: vk::Format) -> Option<format::Format> {
-    if (vk_format.as_raw() as usize) < format::NUM_FORMATS && vk_format != vk::Format::UNDEFINED {
-        Some(unsafe { mem::transmute(vk_format) })
-    } else {
-        None
+    match vk_format {
+        vk::Format::G8_B8R8_2PLANE_420_UNORM => Some(format::Format::Nv12Unorm),
+        vk::Format::G10X6_B10X6R10X6_2PLANE_420_UNORM_3PACK16 => {
+            Some(format::Format::P010Unorm)
+        }
+        _ if (vk_format.as_raw() as usize) < format::Format::Nv12Unorm as usize
+            && vk_format != vk::Format::UNDEFINED =>
+        {
+            Some(unsafe { mem::transmute(vk_format) })
+        }
+        _ => None,
     }
 }
 
@@ -1054,6 +1054,13 @@ impl d::Device<B> for Device {
     ) -> Result<n::Sampler, d::AllocationError> {
         use crate::hal::pso::Comparison;
 
+        if sampler_info.reduction != image::ReductionMode::WeightedAverage {
+            warn!(
+                "Sampler reduction {:?} is not supported, using a weighted average",
+                sampler_info.reduction
+            );
+        }
+
         let (anisotropy_enable, max_anisotropy) = match sampler_info.anisotropic {
             image::Anisotropic::Off => (vk::FALSE, 1.0),
             image::Anisotropic::On(aniso) => {
@@ -875,6 +875,9 @@ impl hal::PhysicalDevice<Backend> for PhysicalDevice {
             optimal_buffer_copy_offset_alignment: limits.optimal_buffer_copy_offset_alignment as _,
             optimal_buffer_copy_pitch_alignment: limits.optimal_buffer_copy_row_pitch_alignment
                 as _,
+            // `bufferOffset` of buffer-image copies must be a multiple of 4.
+            min_buffer_copy_offset_alignment: 4,
+            min_buffer_copy_pitch_alignment: 1,
             min_texel_buffer_offset_alignment: limits.min_texel_buffer_offset_alignment as _,
             min_uniform_buffer_offset_alignment: limits.min_uniform_buffer_offset_alignment as _,
             min_storage_buffer_offset_alignment: limits.min_storage_buffer_offset_alignment as _,
@@ -23,6 +23,10 @@ bitflags!(
         const DEPTH = 0x2;
         /// Stencil aspect.
         const STENCIL = 0x4;
+        /// First plane of a multi-planar format, holding the luma samples.
+        const PLANE_0 = 0x10;
+        /// Second plane of a multi-planar format, holding the interleaved chroma samples.
+        const PLANE_1 = 0x20;
     }
 );
 
@@ -34,6 +38,8 @@ pub struct FormatDesc {
     /// * Depth/Stencil formats are opaque formats, where the total number of bits is unknown.
     ///   A dummy value is used for these formats instead (sum of depth and stencil bits).
     ///   For copy operations, the number of bits of the corresponding aspect should be used.
+    /// * Multi-planar formats use the number of bits of the first plane. The second plane
+    ///   stores two components per texel at half the resolution in each dimension.
     /// * The total number can be larger than the sum of individual format bits
     ///   (`color`, `alpha`, `depth` and `stencil`) for packed formats.
     /// * For compressed formats, this denotes the number of bits per block.
@@ -164,6 +170,9 @@ bitflags!(
         /// with linear sampling.
         /// Requires `SAMPLED` or `BLIT_SRC` flag.
         const SAMPLED_LINEAR = 0x1000;
+        /// Image view can be used as storage image with typed loads.
+        /// Requires `STORAGE` flag.
+        const STORAGE_READ = 0x8000_0000;
     }
 );
 
@@ -317,6 +326,8 @@ surface_types! {
     ASTC_10x10          { 128, COLOR, (10, 10) },
     ASTC_12x10          { 128, COLOR, (12, 10) },
     ASTC_12x12          { 128, COLOR, (12, 12) },
+    G8_B8R8_420         {   8, PLANE_0 | PLANE_1, (1, 1), color: 8 },
+    G10X6_B10X6R10X6_420 {  16, PLANE_0 | PLANE_1, (1, 1), color: 10 },
 }
 
 /// Generic run-time base format.
@@ -576,6 +587,10 @@ formats! {
     Astc12x10Srgb = (ASTC_12x10, Srgb),
     Astc12x12Unorm = (ASTC_12x12, Unorm),
     Astc12x12Srgb = (ASTC_12x12, Srgb),
+    // Multi-planar formats are not part of the core Vulkan format range above,
+    // backends have to map them explicitly.
+    Nv12Unorm = (G8_B8R8_420, Unorm),
+    P010Unorm = (G10X6_B10X6R10X6_420, Unorm),
 }
 
 impl Format {
@@ -606,6 +621,11 @@ impl Format {
     pub fn is_stencil(self) -> bool {
         self.surface_desc().aspects.contains(Aspects::STENCIL)
     }
+
+    /// Returns if the format stores its components in separate planes.
+    pub fn is_planar(self) -> bool {
+        self.surface_desc().aspects.contains(Aspects::PLANE_0 | Aspects::PLANE_1)
+    }
 }
 
 // Common vertex attribute formats
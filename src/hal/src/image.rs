@@ -229,6 +229,18 @@ pub enum Anisotropic {
     On(u8),
 }
 
+/// Reduction of the texels in the filter footprint of a sampler.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ReductionMode {
+    /// Weighted average of the texels, according to the filter.
+    WeightedAverage,
+    /// Component-wise minimum of the texels with a non-zero weight.
+    Minimum,
+    /// Component-wise maximum of the texels with a non-zero weight.
+    Maximum,
+}
+
 /// The face of a cube image to do an operation on.
 #[allow(missing_docs)]
 #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
@@ -522,6 +534,9 @@ pub struct SamplerInfo {
     pub normalized: bool,
     /// Anisotropic filtering.
     pub anisotropic: Anisotropic,
+    /// Reduction of the filtered texels, requires `Features::SAMPLER_REDUCTION`
+    /// for modes other than `WeightedAverage`.
+    pub reduction: ReductionMode,
 }
 
 impl SamplerInfo {
@@ -539,6 +554,7 @@ impl SamplerInfo {
             border: PackedColor(0),
             normalized: true,
             anisotropic: Anisotropic::Off,
+            reduction: ReductionMode::WeightedAverage,
         }
     }
 }
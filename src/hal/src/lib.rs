@@ -235,6 +235,16 @@ bitflags! {
         const VARIABLE_MULTISAMPLE_RATE = 0x020_0000_0000_0000;
         ///
         const INHERITED_QUERIES = 0x040_0000_0000_0000;
+        /// Support conservative rasterization.
+        const CONSERVATIVE_RASTERIZATION = 0x080_0000_0000_0000;
+        /// Support barycentric coordinates in fragment shaders.
+        const SHADER_BARYCENTRICS = 0x100_0000_0000_0000;
+        /// Support rendering multiple views in a single draw call.
+        const VIEW_INSTANCING = 0x200_0000_0000_0000;
+        /// Support indirect drawing with the draw count read from a buffer.
+        const DRAW_INDIRECT_COUNT = 0x400_0000_0000_0000;
+        /// Support minimum and maximum reduction modes on samplers.
+        const SAMPLER_REDUCTION = 0x800_0000_0000_0000;
 
         /// Support triangle fan primitive topology.
         const TRIANGLE_FAN = 0x1000_0000_0000_0000;
@@ -392,6 +402,14 @@ pub struct Limits {
     /// The alignment of the row pitch of the texture data stored in a buffer that is
     /// used in a GPU copy operation, in bytes, non-zero.
     pub optimal_buffer_copy_pitch_alignment: buffer::Offset,
+    /// The required alignment of the start of the buffer used in a copy between a buffer
+    /// and an image, in bytes, non-zero.
+    ///
+    /// Offsets also need to be a multiple of the texel block size of the copied image.
+    pub min_buffer_copy_offset_alignment: buffer::Offset,
+    /// The required alignment of the row pitch of the texture data stored in a buffer that is
+    /// used in a copy between a buffer and an image, in bytes, non-zero.
+    pub min_buffer_copy_pitch_alignment: buffer::Offset,
     /// Size and alignment in bytes that bounds concurrent access to host-mapped device memory.
     pub non_coherent_atom_size: usize,
 
@@ -78,6 +78,12 @@ pub enum CreationError {
     /// Window in use
     #[fail(display = "{}", _0)]
     WindowInUse(device::WindowInUse),
+    /// The number of images is outside of `SurfaceCapabilities::image_count`.
+    #[fail(display = "Unsupported number of swapchain images ({})", _0)]
+    ImageCount(SwapImageIndex),
+    /// The device has been opened without a queue family capable of presentation.
+    #[fail(display = "Device has no queue capable of presentation")]
+    NoPresentQueue,
 }
 
 impl From<device::OutOfMemory> for CreationError {